@@ -0,0 +1,34 @@
+//! Build script
+//!
+//! Records version metadata that the daemon reports about itself. Each value
+//! is optional: builds outside a git checkout simply omit the commit.
+
+use std::process::Command;
+
+fn main() {
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+
+    if let Some(commit) = git(&["rev-parse", "--short=12", "HEAD"]) {
+        println!("cargo:rustc-env=RCPDAEMON_GIT_COMMIT={}", commit);
+    }
+
+    if let Ok(target) = std::env::var("TARGET") {
+        println!("cargo:rustc-env=RCPDAEMON_BUILD_TARGET={}", target);
+    }
+
+    if let Ok(profile) = std::env::var("PROFILE") {
+        println!("cargo:rustc-env=RCPDAEMON_BUILD_PROFILE={}", profile);
+    }
+}
+
+/// Run a git command, returning its trimmed stdout on success
+fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    let value = String::from_utf8(output.stdout).ok()?.trim().to_string();
+    (!value.is_empty()).then_some(value)
+}
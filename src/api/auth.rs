@@ -0,0 +1,117 @@
+//! Authentication of API requests
+//!
+//! Protected endpoints take the credentials RCP clients use, sent as
+//! `Authorization: Bearer <credential>`: a token the daemon issued, or its
+//! pre-shared key, which acts as the operator. [`require_auth`] answers 401
+//! to requests without a valid one and hands the rest to the handler with an
+//! [`ApiCaller`], whose permissions the handler checks. With `auth.required`
+//! off, every request acts as the operator.
+
+use crate::api::config::ApiAuthConfig;
+use crate::error::ServiceError;
+use crate::protocol::handshake;
+use crate::server::config::AuthConfig;
+use crate::server::error::Error;
+use crate::server::tokens::{self, TokenRegistry};
+use axum::extract::State;
+use axum::http::{header, HeaderMap, Request};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+
+/// Who an API request acts as, attached to authenticated requests
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiCaller {
+    /// User or client the token was issued to, `None` for the operator
+    pub subject: Option<String>,
+
+    /// Permissions the request holds
+    pub permissions: Vec<String>,
+}
+
+impl ApiCaller {
+    /// The operator, who may do anything
+    pub fn operator() -> Self {
+        Self {
+            subject: None,
+            permissions: vec![tokens::OPERATOR_PERMISSION.to_string()],
+        }
+    }
+
+    /// Fail with 403 Forbidden unless the caller holds `permission`
+    pub fn require(&self, permission: &str) -> Result<(), ServiceError> {
+        if tokens::permission_granted(&self.permissions, permission) {
+            return Ok(());
+        }
+
+        Err(ServiceError::Forbidden(permission.to_string()))
+    }
+}
+
+/// What the API checks credentials against
+#[derive(Clone)]
+pub struct ApiAuth {
+    required: bool,
+    psk: Option<String>,
+    tokens: TokenRegistry,
+}
+
+impl ApiAuth {
+    /// Check requests as `config` says, against the RCP server's `auth`
+    /// settings and the `tokens` it issued
+    pub fn new(config: &ApiAuthConfig, auth: &AuthConfig, tokens: TokenRegistry) -> Self {
+        Self {
+            required: config.required,
+            psk: auth.psk.clone(),
+            tokens,
+        }
+    }
+
+    /// Who a request with `headers` acts as
+    ///
+    /// Fails with [`ServiceError::Unauthorized`] when the credential is
+    /// missing, malformed, expired, revoked or wrong.
+    pub fn authenticate(&self, headers: &HeaderMap) -> Result<ApiCaller, ServiceError> {
+        if !self.required {
+            return Ok(ApiCaller::operator());
+        }
+
+        let credential = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(str::trim)
+            .filter(|credential| !credential.is_empty())
+            .ok_or_else(|| ServiceError::Unauthorized("Missing bearer credential".to_string()))?;
+
+        if let Some(psk) = &self.psk {
+            if !tokens::looks_like_token(credential) && handshake::verify_plain_key(psk, credential)
+            {
+                return Ok(ApiCaller::operator());
+            }
+        }
+
+        let claims = self
+            .tokens
+            .verify_token(credential)
+            .map_err(|e| ServiceError::from(Error::from(e)))?;
+        Ok(ApiCaller {
+            subject: Some(claims.sub),
+            permissions: claims.permissions,
+        })
+    }
+}
+
+/// Middleware authenticating requests to the routes it wraps
+pub async fn require_auth<B>(
+    State(auth): State<ApiAuth>,
+    mut request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    match auth.authenticate(request.headers()) {
+        Ok(caller) => {
+            request.extensions_mut().insert(caller);
+            next.run(request).await
+        }
+        Err(e) => e.into_response(),
+    }
+}
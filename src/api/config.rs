@@ -1,6 +1,8 @@
 #[cfg(feature = "api")]
 /// API configuration module
 use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::PathBuf;
 
 /// Configuration for the API server component
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -13,6 +15,13 @@ pub struct ApiConfig {
     #[serde(default = "default_api_port")]
     pub port: u16,
 
+    /// Where to listen, overriding `address`/`port` when set
+    ///
+    /// A Unix socket keeps the API off the network entirely; access is then
+    /// governed by the permissions of the socket file and its directory.
+    #[serde(default)]
+    pub listen: Option<ApiListen>,
+
     /// Database connection string
     #[serde(default = "default_database_url")]
     pub database_url: String,
@@ -21,15 +30,97 @@ pub struct ApiConfig {
     #[serde(default)]
     pub cors_allowed_origins: Vec<String>,
 
+    /// Allow cross-origin requests from any origin, ignoring
+    /// `cors_allowed_origins`
+    ///
+    /// Off by default: with authentication disabled, any web page a user
+    /// visits could then drive the API.
+    #[serde(default)]
+    pub cors_allow_any: bool,
+
+    /// HTTP methods cross-origin requests may use
+    #[serde(default = "default_cors_allowed_methods")]
+    pub cors_allowed_methods: Vec<String>,
+
+    /// Request headers cross-origin requests may send
+    #[serde(default = "default_cors_allowed_headers")]
+    pub cors_allowed_headers: Vec<String>,
+
+    /// Whether cross-origin requests may carry credentials (cookies or
+    /// `Authorization`); cannot be combined with `cors_allow_any`
+    #[serde(default)]
+    pub cors_allow_credentials: bool,
+
+    /// Seconds browsers may cache a preflight response
+    #[serde(default)]
+    pub cors_max_age: Option<u64>,
+
     /// Authentication settings
     #[serde(default)]
     pub auth: ApiAuthConfig,
+
+    /// Whether responses are gzip-compressed for clients that accept it
+    #[serde(default = "default_compression_enabled")]
+    pub compression_enabled: bool,
+
+    /// Smallest response, in bytes, that is compressed
+    #[serde(default = "default_compression_min_bytes")]
+    pub compression_min_bytes: u16,
+
+    /// Seconds a request may take before it is answered 408 Request Timeout
+    #[serde(default = "default_request_timeout_secs")]
+    pub request_timeout_secs: u64,
+
+    /// Largest request body, in bytes; bigger ones get 413 Payload Too Large
+    #[serde(default = "default_max_body_bytes")]
+    pub max_body_bytes: usize,
+
+    /// Whether to serve a Swagger UI for the OpenAPI document at `/docs`
+    /// (requires the `swagger-ui` feature)
+    #[serde(default)]
+    pub docs_enabled: bool,
+
+    /// Seconds requests in flight get to finish when the API is stopped,
+    /// after which they are answered 503 Service Unavailable
+    #[serde(default = "default_shutdown_grace_secs")]
+    pub shutdown_grace_secs: u64,
+}
+
+/// Endpoint the API server listens on
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ApiListen {
+    /// A TCP address and port
+    Tcp { address: String, port: u16 },
+
+    /// A Unix domain socket at `path` (Unix only)
+    UnixSocket { path: PathBuf },
+}
+
+impl fmt::Display for ApiListen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiListen::Tcp { address, port } => write!(f, "{}:{}", address, port),
+            ApiListen::UnixSocket { path } => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+impl ApiConfig {
+    /// The endpoint to listen on: `listen` if set, otherwise `address`/`port`
+    pub fn listen_target(&self) -> ApiListen {
+        self.listen.clone().unwrap_or_else(|| ApiListen::Tcp {
+            address: self.address.clone(),
+            port: self.port,
+        })
+    }
 }
 
 /// Authentication configuration for the API
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ApiAuthConfig {
-    /// Whether authentication is required for API access
+    /// Whether the `/v1` endpoints other than `/v1/info` need a bearer
+    /// credential: a token the daemon issued, or its pre-shared key
     #[serde(default = "default_auth_required")]
     pub required: bool,
 
@@ -54,6 +145,37 @@ fn default_database_url() -> String {
     "sqlite:rcpdaemon.db".to_string()
 }
 
+fn default_cors_allowed_methods() -> Vec<String> {
+    ["GET", "POST", "PUT", "DELETE"]
+        .iter()
+        .map(|method| method.to_string())
+        .collect()
+}
+
+fn default_cors_allowed_headers() -> Vec<String> {
+    vec!["authorization".to_string(), "content-type".to_string()]
+}
+
+fn default_compression_enabled() -> bool {
+    true
+}
+
+fn default_compression_min_bytes() -> u16 {
+    1024
+}
+
+fn default_request_timeout_secs() -> u64 {
+    30
+}
+
+fn default_max_body_bytes() -> usize {
+    1024 * 1024
+}
+
+fn default_shutdown_grace_secs() -> u64 {
+    10
+}
+
 fn default_auth_required() -> bool {
     true
 }
@@ -67,9 +189,21 @@ impl Default for ApiConfig {
         Self {
             address: default_api_address(),
             port: default_api_port(),
+            listen: None,
             database_url: default_database_url(),
             cors_allowed_origins: vec!["http://localhost:3000".to_string()],
+            cors_allow_any: false,
+            cors_allowed_methods: default_cors_allowed_methods(),
+            cors_allowed_headers: default_cors_allowed_headers(),
+            cors_allow_credentials: false,
+            cors_max_age: None,
             auth: ApiAuthConfig::default(),
+            compression_enabled: default_compression_enabled(),
+            compression_min_bytes: default_compression_min_bytes(),
+            request_timeout_secs: default_request_timeout_secs(),
+            max_body_bytes: default_max_body_bytes(),
+            docs_enabled: false,
+            shutdown_grace_secs: default_shutdown_grace_secs(),
         }
     }
 }
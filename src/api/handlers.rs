@@ -1,4 +1,213 @@
-// This file previously contained the API handlers implementation
-// We've moved the implementation to inline handlers in server.rs
-// to avoid proc-macro issues with the Serialize derive macro.
-// The original implementation is saved in handlers.rs.bak files.
+//! Handlers for the API's fixed endpoints
+//!
+//! Each handler is annotated for the OpenAPI document served at
+//! `/openapi.json` (see [`openapi`](crate::api::openapi)), and each response
+//! type derives [`ToSchema`] so the document describes its body. The server
+//! side of most of these is not wired up yet, so they answer placeholders.
+//!
+//! The `/v1` handlers other than `/v1/info` sit behind
+//! [`require_auth`](crate::api::auth::require_auth) and check the caller's
+//! permissions, the same ones the matching RPC methods need.
+
+use crate::api::auth::ApiCaller;
+use crate::build_info;
+use crate::error::ServiceError;
+use crate::server::live_config::READ_CONFIG_PERMISSION;
+use crate::server::server::RESTART_PERMISSION;
+use crate::server::session::READ_SESSIONS_PERMISSION;
+use axum::{Extension, Json};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// Body of `/health`
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct HealthResponse {
+    /// Always `ok` when the API answers
+    pub status: String,
+
+    /// Daemon version
+    pub version: String,
+}
+
+/// Body of `/v1/status`
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct StatusResponse {
+    /// State of the service, `running` when the API answers
+    pub service: String,
+
+    /// State of the RCP server
+    pub server: ServerStatus,
+}
+
+/// State of the RCP server, as reported by `/v1/status`
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ServerStatus {
+    /// Whether the server is accepting connections
+    pub running: bool,
+
+    /// Number of active sessions, when the server is running
+    pub sessions: Option<usize>,
+}
+
+/// Body of `/v1/config`
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ConfigSummary {
+    /// Address the service listens on
+    pub service_address: String,
+
+    /// Port the service listens on
+    pub service_port: u16,
+
+    /// Whether the RCP server is enabled
+    pub server_enabled: bool,
+
+    /// Whether the API is enabled
+    pub api_enabled: bool,
+}
+
+/// Body of the `/v1/server/*` control endpoints
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ActionResponse {
+    /// Action that was asked for, e.g. `start`
+    pub action: String,
+
+    /// Outcome, `not_available` while server control is not wired up
+    pub result: String,
+}
+
+/// Body of `/v1/server/sessions`
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct SessionsResponse {
+    /// Number of active sessions
+    pub count: usize,
+
+    /// The active sessions
+    #[schema(value_type = Vec<Object>)]
+    pub sessions: Vec<serde_json::Value>,
+}
+
+/// Handler for `/`
+pub async fn root() -> &'static str {
+    "RCP API Server"
+}
+
+/// Handler for `/health`
+#[utoipa::path(
+    get,
+    path = "/health",
+    tag = "status",
+    responses((status = 200, description = "The API is up", body = HealthResponse))
+)]
+pub async fn health() -> Json<HealthResponse> {
+    Json(HealthResponse {
+        status: "ok".to_string(),
+        version: build_info::VERSION.to_string(),
+    })
+}
+
+/// Handler for `/v1/status`
+#[utoipa::path(
+    get,
+    path = "/v1/status",
+    tag = "status",
+    responses(
+        (status = 200, description = "State of the service and server", body = StatusResponse),
+        (status = 401, description = "Missing or invalid credentials")
+    )
+)]
+pub async fn status() -> Json<StatusResponse> {
+    Json(StatusResponse {
+        service: "running".to_string(),
+        server: ServerStatus {
+            running: false,
+            sessions: None,
+        },
+    })
+}
+
+/// Handler for `/v1/config`
+#[utoipa::path(
+    get,
+    path = "/v1/config",
+    tag = "config",
+    responses(
+        (status = 200, description = "Summary of the service configuration", body = ConfigSummary),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 403, description = "The caller lacks `read:config`")
+    )
+)]
+pub async fn config(
+    Extension(caller): Extension<ApiCaller>,
+) -> Result<Json<ConfigSummary>, ServiceError> {
+    caller.require(READ_CONFIG_PERMISSION)?;
+    Ok(Json(ConfigSummary {
+        service_address: "0.0.0.0".to_string(),
+        service_port: 55555,
+        server_enabled: true,
+        api_enabled: true,
+    }))
+}
+
+/// Handler for `/v1/server/start`
+#[utoipa::path(
+    post,
+    path = "/v1/server/start",
+    tag = "server",
+    responses(
+        (status = 200, description = "Outcome of starting the server", body = ActionResponse),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 403, description = "The caller lacks `admin:server`")
+    )
+)]
+pub async fn start_server(
+    Extension(caller): Extension<ApiCaller>,
+) -> Result<Json<ActionResponse>, ServiceError> {
+    caller.require(RESTART_PERMISSION)?;
+    Ok(Json(not_available("start")))
+}
+
+/// Handler for `/v1/server/stop`
+#[utoipa::path(
+    post,
+    path = "/v1/server/stop",
+    tag = "server",
+    responses(
+        (status = 200, description = "Outcome of stopping the server", body = ActionResponse),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 403, description = "The caller lacks `admin:server`")
+    )
+)]
+pub async fn stop_server(
+    Extension(caller): Extension<ApiCaller>,
+) -> Result<Json<ActionResponse>, ServiceError> {
+    caller.require(RESTART_PERMISSION)?;
+    Ok(Json(not_available("stop")))
+}
+
+/// Handler for `/v1/server/sessions`
+#[utoipa::path(
+    get,
+    path = "/v1/server/sessions",
+    tag = "sessions",
+    responses(
+        (status = 200, description = "Active sessions", body = SessionsResponse),
+        (status = 401, description = "Missing or invalid credentials"),
+        (status = 403, description = "The caller lacks `read:sessions`")
+    )
+)]
+pub async fn list_sessions(
+    Extension(caller): Extension<ApiCaller>,
+) -> Result<Json<SessionsResponse>, ServiceError> {
+    caller.require(READ_SESSIONS_PERMISSION)?;
+    Ok(Json(SessionsResponse {
+        count: 0,
+        sessions: Vec::new(),
+    }))
+}
+
+fn not_available(action: &str) -> ActionResponse {
+    ActionResponse {
+        action: action.to_string(),
+        result: "not_available".to_string(),
+    }
+}
@@ -1,12 +1,16 @@
 #[cfg(feature = "api")]
+pub mod auth;
+#[cfg(feature = "api")]
 pub mod config;
 #[cfg(feature = "api")]
 pub mod handlers;
 #[cfg(feature = "api")]
+pub mod openapi;
+#[cfg(feature = "api")]
 pub mod server;
 
 // Re-exports
 #[cfg(feature = "api")]
-pub use config::ApiConfig;
+pub use config::{ApiConfig, ApiListen};
 #[cfg(feature = "api")]
 pub use server::ApiServer;
@@ -0,0 +1,50 @@
+//! OpenAPI description of the API
+//!
+//! The document is always served at `/openapi.json`; with
+//! `docs_enabled` set, a Swagger UI reading it is served at `/docs`. The UI
+//! is only built with the `swagger-ui` feature.
+
+use crate::api::{handlers, server};
+use axum::Json;
+use utoipa::OpenApi;
+
+/// Where the OpenAPI document is served
+pub const OPENAPI_PATH: &str = "/openapi.json";
+
+/// Where the Swagger UI is served, when enabled
+pub const DOCS_PATH: &str = "/docs";
+
+/// OpenAPI document covering the status, config, server control and
+/// sessions endpoints
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        handlers::health,
+        server::info,
+        handlers::status,
+        handlers::config,
+        handlers::start_server,
+        handlers::stop_server,
+        handlers::list_sessions,
+    ),
+    components(schemas(
+        handlers::HealthResponse,
+        handlers::StatusResponse,
+        handlers::ServerStatus,
+        handlers::ConfigSummary,
+        handlers::ActionResponse,
+        handlers::SessionsResponse,
+    )),
+    tags(
+        (name = "status", description = "Health and state of the daemon"),
+        (name = "config", description = "Service configuration"),
+        (name = "server", description = "Starting and stopping the RCP server"),
+        (name = "sessions", description = "Sessions connected to the RCP server"),
+    )
+)]
+pub struct ApiDoc;
+
+/// Handler for `/openapi.json`
+pub async fn document() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
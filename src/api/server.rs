@@ -1,13 +1,27 @@
 #[cfg(feature = "api")]
 use crate::{
-    api::config::ApiConfig,
+    api::auth::{self, ApiAuth},
+    api::config::{ApiConfig, ApiListen},
+    api::handlers,
+    api::openapi::{self, DOCS_PATH, OPENAPI_PATH},
+    build_info::BuildInfo,
     config::ServiceConfig,
     error::ServiceError,
     manager::ServiceManager,
+    protocol::handshake::PROTOCOL_VERSION,
     // handlers module is not used directly anymore
+    server::ratelimit::{ConnectionLimit, ConnectionSlot},
+    server::tokens::TokenRegistry,
     server::Server,
 };
+use axum::body::Body;
+use axum::extract::State;
+use axum::http::{header, Request, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
 use axum::Json;
+use hyper::server::accept::Accept;
+use hyper::server::conn::{AddrIncoming, AddrStream};
 use serde_json;
 
 use axum::{
@@ -15,12 +29,28 @@ use axum::{
     routing::{get, post}, // Only using get and post routes
     Router,
 };
-use log::{error, info}; // debug is unused
+use log::{error, info, warn};
+use std::io;
 use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::sync::{oneshot, watch, Mutex};
+use tokio::task::JoinHandle;
+use tower_http::compression::predicate::SizeAbove;
+use tower_http::compression::CompressionLayer;
 use tower_http::cors::{Any, CorsLayer};
+use tower_http::limit::RequestBodyLimitLayer;
+use tower_http::timeout::TimeoutLayer;
 use tower_http::trace::TraceLayer;
+#[cfg(feature = "swagger-ui")]
+use utoipa_swagger_ui::SwaggerUi;
+
+#[cfg(unix)]
+use tokio::net::{UnixListener, UnixStream};
 
 /// API server component
 pub struct ApiServer {
@@ -32,10 +62,53 @@ pub struct ApiServer {
 
     /// Whether the API server is running
     running: Arc<Mutex<bool>>,
+
+    /// Routes served alongside the built-in endpoints
+    routes: Router<(), LimitedBody>,
+
+    /// Requests being handled, and the signal cutting them off
+    drain: Drain,
+
+    /// Set once requests in flight have had their grace period
+    cut_off: watch::Sender<bool>,
+
+    /// Tells the serving task to stop accepting connections
+    shutdown: Mutex<Option<oneshot::Sender<()>>>,
+
+    /// The serving task, finished once every connection has closed
+    serving: Mutex<Option<JoinHandle<()>>>,
+
+    /// Cap on connections open at once, shared with the server
+    connections: ConnectionLimit,
+}
+
+/// What the in-flight tracking middleware shares with [`ApiServer::stop`]
+#[derive(Clone)]
+struct Drain {
+    in_flight: Arc<AtomicUsize>,
+    cut_off: watch::Receiver<bool>,
+}
+
+/// Counts a request as in flight until dropped
+struct InFlightGuard(Arc<AtomicUsize>);
+
+impl InFlightGuard {
+    fn enter(in_flight: &Arc<AtomicUsize>) -> Self {
+        in_flight.fetch_add(1, Ordering::SeqCst);
+        Self(in_flight.clone())
+    }
 }
 
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Request body of routes served behind [`limit_requests`]
+pub type LimitedBody = http_body::Limited<Body>;
+
 /// API application state shared across handlers
-/// Currently not actively used in routes, but kept for future use
 #[derive(Clone)]
 #[allow(dead_code)]
 pub struct ApiState {
@@ -50,28 +123,63 @@ pub struct ApiState {
 
     /// Server reference (when available)
     pub server: Option<Arc<Mutex<Server>>>,
+
+    /// Whether the API server is running
+    pub api_running: Arc<Mutex<bool>>,
 }
 
 impl ApiServer {
     /// Create a new API server
     pub fn new(config: ApiConfig, service_manager: Arc<Mutex<ServiceManager>>) -> Self {
+        let (cut_off, cut_off_rx) = watch::channel(false);
         Self {
             config,
             service_manager,
             running: Arc::new(Mutex::new(false)),
+            routes: Router::new(),
+            drain: Drain {
+                in_flight: Arc::new(AtomicUsize::new(0)),
+                cut_off: cut_off_rx,
+            },
+            cut_off,
+            shutdown: Mutex::new(None),
+            serving: Mutex::new(None),
+            connections: ConnectionLimit::default(),
         }
     }
 
+    /// Count connections against `limit`, shared with other components such as the server
+    pub fn with_connection_limit(mut self, limit: ConnectionLimit) -> Self {
+        self.connections = limit;
+        self
+    }
+
+    /// Serve `routes` alongside the built-in endpoints, behind the same limits
+    pub fn with_routes(mut self, routes: Router<(), LimitedBody>) -> Self {
+        self.routes = self.routes.merge(routes);
+        self
+    }
+
+    /// Number of requests being handled
+    pub fn in_flight(&self) -> usize {
+        self.drain.in_flight.load(Ordering::SeqCst)
+    }
+
     /// Start the API server
     pub async fn start(&self) -> Result<(), ServiceError> {
-        let addr = format!("{}:{}", self.config.address, self.config.port);
-        info!("Starting API server on {}", addr);
+        let listen = self.config.listen_target();
+        info!("Starting API server on {}", listen);
+
+        // Configure CORS, refusing to start on invalid settings
+        let cors = cors_layer(&self.config)?;
 
         // Set running state
         {
             let mut running = self.running.lock().await;
             *running = true;
         }
+        // A previous stop cut requests off; let this run's requests through
+        self.cut_off.send_replace(false);
 
         // Get service manager reference and config
         let service_manager_lock = self.service_manager.lock().await;
@@ -85,148 +193,210 @@ impl ApiServer {
             service_manager.get_server().clone()
         };
 
+        // Check credentials against the tokens the running server issued
+        let tokens = match &server {
+            Some(server) => server.lock().await.tokens().clone(),
+            None => TokenRegistry::new(&service_config.server.auth),
+        };
+        let api_auth = ApiAuth::new(&self.config.auth, &service_config.server.auth, tokens);
+
         // Create API state
         let api_state = ApiState {
             config: Arc::new(self.config.clone()),
             service_config: Arc::new(service_config),
             service_manager: self.service_manager.clone(),
             server,
+            api_running: self.running.clone(),
         };
 
-        // Configure CORS
-        let cors = self.configure_cors();
+        // Endpoints that need credentials
+        let protected = Router::new()
+            // Service endpoints
+            .route("/v1/status", get(handlers::status))
+            .route("/v1/config", get(handlers::config))
+            // Server management endpoints
+            .route("/v1/server/start", post(handlers::start_server))
+            .route("/v1/server/stop", post(handlers::stop_server))
+            .route("/v1/server/sessions", get(handlers::list_sessions))
+            .route_layer(middleware::from_fn_with_state(api_auth, auth::require_auth));
 
         // Build the router with simple placeholder routes for now
         let app = Router::new()
             // Basic endpoints
-            .route("/", get(|| async { "RCP API Server" }))
-            .route(
-                "/health",
-                get(|| async {
-                    Json(serde_json::json!({
-                        "status": "ok",
-                        "version": env!("CARGO_PKG_VERSION")
-                    }))
-                }),
-            )
-            // Service endpoints
-            .route(
-                "/v1/status",
-                get(|| async {
-                    Json(serde_json::json!({
-                        "service": "running",
-                        "server": {
-                            "running": false,
-                            "sessions": null
-                        }
-                    }))
-                }),
-            )
-            .route(
-                "/v1/config",
-                get(|| async {
-                    Json(serde_json::json!({
-                        "service_address": "0.0.0.0",
-                        "service_port": 55555,
-                        "server_enabled": true,
-                        "api_enabled": true
-                    }))
-                }),
-            )
-            // Server management endpoints
-            .route(
-                "/v1/server/start",
-                post(|| async {
-                    Json(serde_json::json!({
-                        "action": "start",
-                        "result": "not_available"
-                    }))
-                }),
-            )
-            .route(
-                "/v1/server/stop",
-                post(|| async {
-                    Json(serde_json::json!({
-                        "action": "stop",
-                        "result": "not_available"
-                    }))
-                }),
-            )
-            .route(
-                "/v1/server/sessions",
-                get(|| async {
-                    Json(serde_json::json!({
-                        "count": 0,
-                        "sessions": []
-                    }))
-                }),
+            .route("/", get(handlers::root))
+            .route("/health", get(handlers::health))
+            .route("/v1/info", get(info))
+            .route(OPENAPI_PATH, get(openapi::document))
+            .merge(protected);
+
+        // Swagger UI reading the document served above
+        #[cfg(feature = "swagger-ui")]
+        let app = if self.config.docs_enabled {
+            app.merge(
+                SwaggerUi::new(DOCS_PATH).config(utoipa_swagger_ui::Config::from(OPENAPI_PATH)),
             )
+        } else {
+            app
+        };
+        #[cfg(not(feature = "swagger-ui"))]
+        if self.config.docs_enabled {
+            warn!(
+                "docs_enabled is set, but this build has no Swagger UI (feature `swagger-ui`); {} is not served",
+                DOCS_PATH
+            );
+        }
+
+        let app = app
+            .with_state(api_state)
+            .merge(self.routes.clone())
             // Add tracing and CORS
             .layer(TraceLayer::new_for_http())
-            .layer(cors)
-            .with_state(api_state);
+            .layer(cors);
+        let app = limit_requests(app, &self.config).layer(middleware::from_fn_with_state(
+            self.drain.clone(),
+            track_in_flight,
+        ));
 
-        // Parse the address
-        let addr: SocketAddr = addr
-            .parse()
-            .map_err(|e| ServiceError::Api(format!("Invalid API address: {}", e)))?;
+        // Compress large responses for clients sending `Accept-Encoding: gzip`
+        let app = if self.config.compression_enabled {
+            app.layer(
+                CompressionLayer::new()
+                    .compress_when(SizeAbove::new(self.config.compression_min_bytes)),
+            )
+        } else {
+            app
+        };
 
-        // Start the server in a separate task
+        // Start the server in a separate task, which finishes once told to
+        // shut down and every connection is done
         let running = self.running.clone();
-        tokio::spawn(async move {
-            info!("API server listening on {}", addr);
-            if let Err(e) = axum::Server::bind(&addr)
-                .serve(app.into_make_service())
-                .await
-            {
-                error!("API server error: {}", e);
-                // Update running state
-                let mut running_guard = running.lock().await;
-                *running_guard = false;
+        let connections = self.connections.clone();
+        let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+        let shutdown = async move {
+            let _ = shutdown_rx.await;
+        };
+        let serving = match listen {
+            ApiListen::Tcp { address, port } => {
+                // Parse the address
+                let addr: SocketAddr = format!("{}:{}", address, port)
+                    .parse()
+                    .map_err(|e| ServiceError::Api(format!("Invalid API address: {}", e)))?;
+                let incoming = match AddrIncoming::bind(&addr) {
+                    Ok(incoming) => incoming,
+                    Err(e) => {
+                        *running.lock().await = false;
+                        return Err(ServiceError::Api(format!(
+                            "Failed to bind API address {}: {}",
+                            addr, e
+                        )));
+                    }
+                };
+
+                tokio::spawn(async move {
+                    info!("API server listening on {}", addr);
+                    if let Err(e) = axum::Server::builder(LimitedAccept::new(incoming, connections))
+                        .serve(app.into_make_service())
+                        .with_graceful_shutdown(shutdown)
+                        .await
+                    {
+                        error!("API server error: {}", e);
+                        // Update running state
+                        let mut running_guard = running.lock().await;
+                        *running_guard = false;
+                    }
+                })
             }
-        });
+            #[cfg(unix)]
+            ApiListen::UnixSocket { path } => {
+                let listener = match bind_unix_socket(&path) {
+                    Ok(listener) => listener,
+                    Err(e) => {
+                        *running.lock().await = false;
+                        return Err(e);
+                    }
+                };
 
+                tokio::spawn(async move {
+                    info!("API server listening on unix:{}", path.display());
+                    let accept = LimitedAccept::new(UnixAccept { listener }, connections);
+                    if let Err(e) = axum::Server::builder(accept)
+                        .serve(app.into_make_service())
+                        .with_graceful_shutdown(shutdown)
+                        .await
+                    {
+                        error!("API server error: {}", e);
+                        // Update running state
+                        let mut running_guard = running.lock().await;
+                        *running_guard = false;
+                    }
+                })
+            }
+            #[cfg(not(unix))]
+            ApiListen::UnixSocket { .. } => {
+                *running.lock().await = false;
+                return Err(ServiceError::Api(
+                    "Unix socket listeners are not supported on this platform".to_string(),
+                ));
+            }
+        };
+
+        *self.shutdown.lock().await = Some(shutdown_tx);
+        *self.serving.lock().await = Some(serving);
         Ok(())
     }
 
-    /// Stop the API server
+    /// Stop the API server, draining requests in flight
+    ///
+    /// New connections are refused at once. Requests already being handled
+    /// get `shutdown_grace_secs` to finish; any still running then are
+    /// answered 503 Service Unavailable. Returns once every connection has
+    /// closed.
     pub async fn stop(&self) -> Result<(), ServiceError> {
-        info!("Stopping API server");
+        let grace = Duration::from_secs(self.config.shutdown_grace_secs);
+        info!(
+            "Stopping API server with {} requests in flight",
+            self.in_flight()
+        );
 
         // Update running state
         let mut running = self.running.lock().await;
         *running = false;
+        drop(running);
 
-        // Note: Axum doesn't provide a clean way to stop the server
-        // In a production environment, we would need a more robust solution
-        // For now, we just update the state and let the server continue running
-        // until the process terminates
-
-        Ok(())
-    }
+        if let Some(shutdown) = self.shutdown.lock().await.take() {
+            let _ = shutdown.send(());
+        }
 
-    /// Configure CORS for the API server
-    fn configure_cors(&self) -> CorsLayer {
-        let mut cors = CorsLayer::new()
-            .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
-            .allow_headers(Any);
+        // Remove the socket file so clients fail fast instead of hanging
+        #[cfg(unix)]
+        if let ApiListen::UnixSocket { path } = self.config.listen_target() {
+            let _ = std::fs::remove_file(path);
+        }
 
-        // Add allowed origins from configuration
-        if !self.config.cors_allowed_origins.is_empty() {
-            let origins = self
-                .config
-                .cors_allowed_origins
-                .iter()
-                .filter_map(|origin| origin.parse::<HeaderValue>().ok())
-                .collect::<Vec<_>>();
+        let Some(mut serving) = self.serving.lock().await.take() else {
+            return Ok(());
+        };
+        if tokio::time::timeout(grace, &mut serving).await.is_ok() {
+            info!("API server drained");
+            return Ok(());
+        }
 
-            cors = cors.allow_origin(origins);
-        } else {
-            // Default to allowing any origin if none configured
-            cors = cors.allow_origin(Any);
+        warn!(
+            "Cutting off {} API requests still in flight after {}s",
+            self.in_flight(),
+            grace.as_secs()
+        );
+        let _ = self.cut_off.send(true);
+        // Cut-off requests are answered at once, so this only waits for them
+        // to be written out
+        if tokio::time::timeout(Duration::from_secs(1), &mut serving)
+            .await
+            .is_err()
+        {
+            serving.abort();
         }
 
-        cors
+        Ok(())
     }
 
     /// Check if the API server is running
@@ -236,4 +406,325 @@ impl ApiServer {
     }
 }
 
+/// The CORS layer described by the `cors_*` settings of `config`
+///
+/// Only the listed origins are allowed unless `cors_allow_any` is set; with
+/// neither, cross-origin requests are refused. Fails on a method, header or
+/// origin that is not valid in HTTP, and on credentials allowed together
+/// with any origin, which browsers refuse anyway.
+pub fn cors_layer(config: &ApiConfig) -> Result<CorsLayer, ServiceError> {
+    let invalid = |setting: &str, value: &str| {
+        ServiceError::Config(format!("Invalid entry in {}: {}", setting, value))
+    };
+
+    let methods = config
+        .cors_allowed_methods
+        .iter()
+        .map(|method| {
+            Method::from_bytes(method.to_ascii_uppercase().as_bytes())
+                .map_err(|_| invalid("cors_allowed_methods", method))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let headers = config
+        .cors_allowed_headers
+        .iter()
+        .map(|name| {
+            name.parse::<header::HeaderName>()
+                .map_err(|_| invalid("cors_allowed_headers", name))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut cors = CorsLayer::new()
+        .allow_methods(methods)
+        .allow_headers(headers)
+        .allow_credentials(config.cors_allow_credentials);
+    if let Some(secs) = config.cors_max_age {
+        cors = cors.max_age(Duration::from_secs(secs));
+    }
+
+    if config.cors_allow_any {
+        if config.cors_allow_credentials {
+            return Err(ServiceError::Config(
+                "cors_allow_credentials cannot be combined with cors_allow_any".to_string(),
+            ));
+        }
+        warn!("API allows cross-origin requests from any origin");
+        return Ok(cors.allow_origin(Any));
+    }
+
+    let origins = config
+        .cors_allowed_origins
+        .iter()
+        .map(|origin| {
+            origin
+                .parse::<HeaderValue>()
+                .map_err(|_| invalid("cors_allowed_origins", origin))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(cors.allow_origin(origins))
+}
+
+/// Apply the request timeout and body size limit in `config` to `router`
+///
+/// A request still running after `request_timeout_secs` is answered 408. A
+/// body over `max_body_bytes` is answered 413, before the handler runs when
+/// its `Content-Length` already says so.
+pub fn limit_requests(router: Router<(), LimitedBody>, config: &ApiConfig) -> Router {
+    router
+        .layer(TimeoutLayer::new(Duration::from_secs(
+            config.request_timeout_secs,
+        )))
+        .layer(RequestBodyLimitLayer::new(config.max_body_bytes))
+}
+
+/// Capabilities of this daemon, served unauthenticated at `/v1/info`
+///
+/// Only reports which features are in use, never their settings, so that no
+/// keys, secrets or paths from `config` reach unauthenticated clients.
+pub fn server_info(
+    config: &ServiceConfig,
+    server_running: bool,
+    api_running: bool,
+) -> serde_json::Value {
+    serde_json::json!({
+        "protocol_version": PROTOCOL_VERSION.to_string(),
+        "build": BuildInfo::current(),
+        "features": {
+            "tls": config.server.tls.enabled,
+            "auth_provider": config.server.auth.provider,
+            "api": true,
+        },
+        "server": {
+            "running": server_running,
+        },
+        "api": {
+            "running": api_running,
+        },
+    })
+}
+
+/// HTTP status an API request failing with `error` is answered with
+///
+/// Bad or missing credentials get 401 and a missing permission 403, so
+/// clients can tell whether to log in again or to ask for more access.
+pub fn error_status(error: &ServiceError) -> StatusCode {
+    match error {
+        ServiceError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+        ServiceError::Forbidden(_) => StatusCode::FORBIDDEN,
+        ServiceError::Config(_) => StatusCode::BAD_REQUEST,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+impl IntoResponse for ServiceError {
+    fn into_response(self) -> Response {
+        let status = error_status(&self);
+        let mut body = serde_json::json!({ "error": self.to_string() });
+        if let ServiceError::Forbidden(permission) = &self {
+            body["required"] = serde_json::json!(permission);
+        }
+
+        let mut response = (status, Json(body)).into_response();
+        if status == StatusCode::UNAUTHORIZED {
+            response
+                .headers_mut()
+                .insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+        }
+        response
+    }
+}
+
+/// Count the request as in flight, answering 503 if it is cut off by
+/// [`ApiServer::stop`]
+async fn track_in_flight<B>(
+    State(mut drain): State<Drain>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let _guard = InFlightGuard::enter(&drain.in_flight);
+    let cut_off = async {
+        while !*drain.cut_off.borrow() {
+            if drain.cut_off.changed().await.is_err() {
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    tokio::select! {
+        response = next.run(request) => response,
+        _ = cut_off => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(serde_json::json!({ "error": "API server is shutting down" })),
+        )
+            .into_response(),
+    }
+}
+
+/// Handler for `/v1/info`
+#[utoipa::path(
+    get,
+    path = "/v1/info",
+    tag = "status",
+    responses((status = 200, description = "Features in use, build and running state"))
+)]
+async fn info(State(state): State<ApiState>) -> Json<serde_json::Value> {
+    let server_running = match &state.server {
+        Some(server) => server.lock().await.is_running().await,
+        None => false,
+    };
+    let api_running = *state.api_running.lock().await;
+
+    Json(server_info(
+        &state.service_config,
+        server_running,
+        api_running,
+    ))
+}
+
+/// Bind a Unix socket listener at `path`, replacing a stale socket file
+#[cfg(unix)]
+fn bind_unix_socket(path: &std::path::Path) -> Result<UnixListener, ServiceError> {
+    use std::os::unix::fs::FileTypeExt;
+
+    // A socket left behind by a previous run would make the bind fail, but
+    // any other kind of file at the path is left alone
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if metadata.file_type().is_socket() {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+
+    UnixListener::bind(path).map_err(|e| {
+        ServiceError::Api(format!(
+            "Failed to bind API socket {}: {}",
+            path.display(),
+            e
+        ))
+    })
+}
+
+/// Feeds connections from a Unix socket to hyper
+#[cfg(unix)]
+struct UnixAccept {
+    listener: UnixListener,
+}
+
+#[cfg(unix)]
+impl Accept for UnixAccept {
+    type Conn = UnixStream;
+    type Error = std::io::Error;
+
+    fn poll_accept(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Conn, Self::Error>>> {
+        let (stream, _addr) = ready!(self.listener.poll_accept(cx))?;
+        Poll::Ready(Some(Ok(stream)))
+    }
+}
+
+/// A connection the API accepted, described for the log
+trait Peer {
+    fn peer(&self) -> String;
+}
+
+impl Peer for AddrStream {
+    fn peer(&self) -> String {
+        self.remote_addr().to_string()
+    }
+}
+
+#[cfg(unix)]
+impl Peer for UnixStream {
+    fn peer(&self) -> String {
+        "the API socket".to_string()
+    }
+}
+
+/// Hands hyper the connections of `accept` that the connection limit admits
+///
+/// The rest are closed as soon as they are accepted.
+struct LimitedAccept<A> {
+    accept: A,
+    limit: ConnectionLimit,
+}
+
+impl<A> LimitedAccept<A> {
+    fn new(accept: A, limit: ConnectionLimit) -> Self {
+        Self { accept, limit }
+    }
+}
+
+impl<A> Accept for LimitedAccept<A>
+where
+    A: Accept<Error = io::Error> + Unpin,
+    A::Conn: Peer,
+{
+    type Conn = CountedConn<A::Conn>;
+    type Error = io::Error;
+
+    fn poll_accept(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Conn, Self::Error>>> {
+        let this = self.get_mut();
+        loop {
+            let conn = match ready!(Pin::new(&mut this.accept).poll_accept(cx)) {
+                Some(Ok(conn)) => conn,
+                Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+                None => return Poll::Ready(None),
+            };
+            if let Some(slot) = this.limit.admit("API", &conn.peer()) {
+                return Poll::Ready(Some(Ok(CountedConn { conn, _slot: slot })));
+            }
+        }
+    }
+}
+
+/// A connection holding its slot of the connection limit until it closes
+struct CountedConn<C> {
+    conn: C,
+    _slot: ConnectionSlot,
+}
+
+impl<C: AsyncRead + Unpin> AsyncRead for CountedConn<C> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().conn).poll_read(cx, buf)
+    }
+}
+
+impl<C: AsyncWrite + Unpin> AsyncWrite for CountedConn<C> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().conn).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().conn).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.conn.is_write_vectored()
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().conn).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().conn).poll_shutdown(cx)
+    }
+}
+
 // All API handler functionality is now in the handlers module
@@ -0,0 +1,146 @@
+//! Group membership cache shared by the native providers
+//!
+//! A single authentication checks the required group and then maps groups to
+//! permissions. Both go through [`GroupCache`], so the directory is queried
+//! once per user rather than once per check. Memberships are reused for
+//! [`GROUP_CACHE_TTL`], so a user removed from a group loses its permissions
+//! within that time without waiting for a reload.
+
+use crate::auth::command::CommandTimeout;
+use anyhow::Result;
+use log::debug;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+/// How long a user's groups are reused for
+pub const GROUP_CACHE_TTL: Duration = Duration::from_secs(300);
+
+/// Most users kept in the cache; the oldest entry makes room beyond it
+const MAX_CACHED_USERS: usize = 1024;
+
+/// Groups of one user and when they were fetched
+#[derive(Debug, Clone)]
+struct CachedGroups {
+    groups: Vec<String>,
+    fetched_at: Instant,
+}
+
+/// Cache of each user's OS groups
+#[derive(Debug)]
+pub struct GroupCache {
+    groups: RwLock<HashMap<String, CachedGroups>>,
+    ttl: Duration,
+}
+
+impl Default for GroupCache {
+    fn default() -> Self {
+        Self::with_ttl(GROUP_CACHE_TTL)
+    }
+}
+
+impl GroupCache {
+    /// Create an empty cache
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create an empty cache reusing groups for `ttl`
+    pub fn with_ttl(ttl: Duration) -> Self {
+        Self {
+            groups: RwLock::new(HashMap::new()),
+            ttl,
+        }
+    }
+
+    /// Cached groups of a user, if they were fetched within the TTL
+    pub fn get(&self, username: &str) -> Option<Vec<String>> {
+        self.groups
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(username)
+            .filter(|cached| cached.fetched_at.elapsed() < self.ttl)
+            .map(|cached| cached.groups.clone())
+    }
+
+    /// Store the groups of a user
+    pub fn insert(&self, username: &str, groups: Vec<String>) {
+        let mut cache = self.groups.write().unwrap_or_else(|e| e.into_inner());
+        if cache.len() >= MAX_CACHED_USERS && !cache.contains_key(username) {
+            cache.retain(|_, cached| cached.fetched_at.elapsed() < self.ttl);
+            if cache.len() >= MAX_CACHED_USERS {
+                let oldest = cache
+                    .iter()
+                    .min_by_key(|(_, cached)| cached.fetched_at)
+                    .map(|(username, _)| username.clone());
+                if let Some(oldest) = oldest {
+                    cache.remove(&oldest);
+                }
+            }
+        }
+        cache.insert(
+            username.to_string(),
+            CachedGroups {
+                groups,
+                fetched_at: Instant::now(),
+            },
+        );
+    }
+
+    /// Number of users cached, expired entries included until evicted
+    pub fn len(&self) -> usize {
+        self.groups.read().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    /// Whether nothing is cached
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Forget all cached memberships
+    pub fn clear(&self) {
+        self.groups
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+    }
+
+    /// Groups of a user, calling `fetch` only on a cache miss or once the
+    /// cached groups have expired
+    ///
+    /// Failed fetches are not cached, so a transient directory error is retried
+    /// on the next lookup.
+    pub async fn get_or_fetch<F, Fut>(&self, username: &str, fetch: F) -> Result<Vec<String>>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Vec<String>>>,
+    {
+        if let Some(groups) = self.get(username) {
+            return Ok(groups);
+        }
+
+        let groups = fetch().await?;
+        self.insert(username, groups.clone());
+        Ok(groups)
+    }
+
+    /// Whether a user belongs to a group, fetching their groups on a cache miss
+    ///
+    /// A user whose groups cannot be looked up (typically an unknown user) is
+    /// not a member; only timeouts are reported as errors.
+    pub async fn is_member<F, Fut>(&self, username: &str, group: &str, fetch: F) -> Result<bool>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Vec<String>>>,
+    {
+        match self.get_or_fetch(username, fetch).await {
+            Ok(groups) => Ok(groups.iter().any(|g| g == group)),
+            Err(e) if e.is::<CommandTimeout>() => Err(e),
+            Err(e) => {
+                debug!("Group lookup for {} failed: {}", username, e);
+                Ok(false)
+            }
+        }
+    }
+}
@@ -0,0 +1,197 @@
+//! Subprocess helpers for the native auth providers
+//!
+//! Directory lookups shell out to tools like `getent`, `dscl` or `net`, which
+//! can hang when the backing directory service is unreachable. Every call goes
+//! through [`output_with_timeout`] so a stuck lookup fails instead of blocking
+//! authentication forever.
+//!
+//! A burst of logins would otherwise spawn one lookup per request at once, so
+//! the commands also share a process-wide [`SubprocessLimiter`]: beyond
+//! `max_concurrent_auth_subprocesses` running commands, further lookups queue
+//! until one finishes.
+//!
+//! Usernames come from clients and end up as arguments to those tools, so
+//! providers check them with [`check_username`] first. Anything outside a
+//! conservative set of characters is refused, as is a leading `-`, so a name
+//! can never be taken for an option and no `--` terminator is needed.
+
+use anyhow::Result;
+use std::process::{Output, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::process::Command;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Default time allowed for a single directory lookup command
+pub const DEFAULT_COMMAND_TIMEOUT_SECS: u64 = 10;
+
+/// Default number of directory lookup commands allowed to run at once
+pub const DEFAULT_MAX_CONCURRENT_SUBPROCESSES: usize = 8;
+
+/// Limiter shared by every lookup, replaced when the auth config is applied
+static LIMITER: RwLock<Option<SubprocessLimiter>> = RwLock::new(None);
+
+/// Bounds the number of directory lookup commands running at once
+#[derive(Debug, Clone)]
+pub struct SubprocessLimiter {
+    /// Free slots, or `None` for no limit
+    slots: Option<Arc<Semaphore>>,
+
+    /// Total number of slots
+    capacity: usize,
+
+    /// Commands currently holding a slot
+    running: Arc<AtomicUsize>,
+
+    /// Most commands ever seen holding a slot at once
+    peak: Arc<AtomicUsize>,
+}
+
+/// A claimed subprocess slot, released when dropped
+#[derive(Debug)]
+pub struct SubprocessSlot {
+    _permit: Option<OwnedSemaphorePermit>,
+    running: Arc<AtomicUsize>,
+}
+
+impl Drop for SubprocessSlot {
+    fn drop(&mut self) {
+        self.running.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl SubprocessLimiter {
+    /// Allow `max_concurrent` commands at once
+    ///
+    /// Zero disables the limit.
+    pub fn new(max_concurrent: usize) -> Self {
+        Self {
+            slots: (max_concurrent > 0).then(|| Arc::new(Semaphore::new(max_concurrent))),
+            capacity: max_concurrent,
+            running: Arc::new(AtomicUsize::new(0)),
+            peak: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Maximum number of commands run at once, zero for no limit
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Claim a slot, waiting for one to free up if all are taken
+    pub async fn acquire(&self) -> SubprocessSlot {
+        let permit = match &self.slots {
+            // The semaphore is never closed, so acquiring only fails if it were
+            Some(slots) => slots.clone().acquire_owned().await.ok(),
+            None => None,
+        };
+
+        let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+        self.peak.fetch_max(running, Ordering::SeqCst);
+        SubprocessSlot {
+            _permit: permit,
+            running: self.running.clone(),
+        }
+    }
+
+    /// Number of commands currently running
+    pub fn running(&self) -> usize {
+        self.running.load(Ordering::SeqCst)
+    }
+
+    /// Most commands that have run at once since the limiter was created
+    pub fn peak(&self) -> usize {
+        self.peak.load(Ordering::SeqCst)
+    }
+}
+
+impl Default for SubprocessLimiter {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_CONCURRENT_SUBPROCESSES)
+    }
+}
+
+/// The limiter directory lookups currently share
+pub fn subprocess_limiter() -> SubprocessLimiter {
+    if let Some(limiter) = LIMITER.read().unwrap_or_else(|e| e.into_inner()).as_ref() {
+        return limiter.clone();
+    }
+
+    LIMITER
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .get_or_insert_with(SubprocessLimiter::default)
+        .clone()
+}
+
+/// Bound how many directory lookup commands run at once, zero for no limit
+///
+/// Commands already running keep their slots in the previous limiter.
+pub fn set_max_concurrent_subprocesses(max_concurrent: usize) {
+    *LIMITER.write().unwrap_or_else(|e| e.into_inner()) =
+        Some(SubprocessLimiter::new(max_concurrent));
+}
+
+/// Whether `username` is safe to pass to a directory lookup command
+///
+/// Allows ASCII letters and digits, `_`, `-`, `.` and `$` (Samba machine
+/// accounts), but not a leading `-` or a name made only of dots.
+pub fn is_valid_username(username: &str) -> bool {
+    !username.is_empty()
+        && !username.starts_with('-')
+        && !username.chars().all(|c| c == '.')
+        && username
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | '$'))
+}
+
+/// Refuse a username that [`is_valid_username`] does not allow
+pub fn check_username(username: &str) -> Result<()> {
+    if !is_valid_username(username) {
+        anyhow::bail!("Invalid username {:?}", username);
+    }
+    Ok(())
+}
+
+/// A directory lookup command did not finish in time and was killed
+#[derive(Debug, thiserror::Error)]
+#[error("Command `{program}` timed out after {timeout:?}")]
+pub struct CommandTimeout {
+    /// Program that timed out
+    pub program: String,
+
+    /// Timeout that was exceeded
+    pub timeout: Duration,
+}
+
+/// Run a command to completion, killing it if it exceeds `timeout`
+///
+/// Behaves like [`Command::output`], except that on expiry the child is killed
+/// and a [`CommandTimeout`] error is returned so callers can fall back. The
+/// command runs on the async runtime, so waiting for it never blocks a worker thread.
+///
+/// The command first waits for a slot in the [`subprocess_limiter`]; only the
+/// time it runs counts against `timeout`.
+pub async fn output_with_timeout(command: &mut Command, timeout: Duration) -> Result<Output> {
+    let program = command
+        .as_std()
+        .get_program()
+        .to_string_lossy()
+        .into_owned();
+
+    let _slot = subprocess_limiter().acquire().await;
+
+    let child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+
+    // Dropping the wait future on expiry drops the child, which kills it
+    match tokio::time::timeout(timeout, child.wait_with_output()).await {
+        Ok(output) => Ok(output?),
+        Err(_) => Err(CommandTimeout { program, timeout }.into()),
+    }
+}
@@ -1,5 +1,11 @@
+use crate::auth::command::{
+    set_max_concurrent_subprocesses, DEFAULT_COMMAND_TIMEOUT_SECS,
+    DEFAULT_MAX_CONCURRENT_SUBPROCESSES,
+};
 use crate::auth::mock_provider::MockAuthProvider;
 use crate::auth::provider::AuthProvider;
+use crate::auth::user_db::DEFAULT_MIN_USER_UID;
+use crate::server::user::UserRole;
 use anyhow::{anyhow, Result};
 use log::info;
 use serde::{Deserialize, Serialize};
@@ -42,6 +48,52 @@ impl Default for AuthProviderType {
     }
 }
 
+impl AuthProviderType {
+    /// Every provider type, whether or not this platform supports it
+    pub const ALL: [AuthProviderType; 5] = [
+        AuthProviderType::Internal,
+        AuthProviderType::Native,
+        AuthProviderType::Ldap,
+        AuthProviderType::OAuth,
+        AuthProviderType::Mock,
+    ];
+
+    /// Value of `provider` in the auth config that selects this type
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuthProviderType::Internal => "internal",
+            AuthProviderType::Native => "native",
+            AuthProviderType::Ldap => "ldap",
+            AuthProviderType::OAuth => "oauth",
+            AuthProviderType::Mock => "mock",
+        }
+    }
+
+    /// One-line description for provider listings
+    pub fn description(&self) -> &'static str {
+        match self {
+            AuthProviderType::Internal => "Internal user database",
+            AuthProviderType::Native => "Operating system accounts and groups",
+            AuthProviderType::Ldap => "LDAP directory",
+            AuthProviderType::OAuth => "OAuth identity provider",
+            AuthProviderType::Mock => "In-memory users, for testing",
+        }
+    }
+}
+
+/// Longest username, in bytes, checked by default
+pub const DEFAULT_MAX_USERNAME_LEN: usize = 256;
+
+/// Longest credential, in bytes, checked by default
+pub const DEFAULT_MAX_CREDENTIAL_LEN: usize = 4096;
+
+/// Times provider initialization is tried by default
+pub const DEFAULT_INIT_ATTEMPTS: u32 = 3;
+
+/// Milliseconds waited before the first retry of provider initialization by
+/// default; the wait doubles after each further failure
+pub const DEFAULT_INIT_RETRY_DELAY_MS: u64 = 500;
+
 /// Authentication configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AuthConfig {
@@ -71,12 +123,94 @@ pub struct AuthConfig {
     /// OAuth authentication configuration (not implemented in this example)
     #[serde(default)]
     pub oauth: HashMap<String, String>,
+
+    /// Authentication methods credentials may be checked with (empty for any
+    /// method the provider supports)
+    #[serde(default)]
+    pub allowed_methods: Vec<String>,
+
+    /// Longest username, in bytes, passed on to the provider (zero for no limit)
+    #[serde(default = "default_max_username_len")]
+    pub max_username_len: usize,
+
+    /// Longest credential, in bytes, passed on to the provider (zero for no limit)
+    #[serde(default = "default_max_credential_len")]
+    pub max_credential_len: usize,
+
+    /// Times provider initialization is tried before giving up (at least once)
+    #[serde(default = "default_init_attempts")]
+    pub init_attempts: u32,
+
+    /// Milliseconds before the first retry of provider initialization,
+    /// doubling after each further failure
+    #[serde(default = "default_init_retry_delay_ms")]
+    pub init_retry_delay_ms: u64,
+
+    /// Whether initialization failing on every attempt is an error
+    ///
+    /// When unset the manager starts degraded instead: authentication fails
+    /// until the provider initializes, which is tried again on each use.
+    #[serde(default = "default_true")]
+    pub init_strict: bool,
+}
+
+impl AuthConfig {
+    /// Refuse a username or credential longer than the configured limits
+    ///
+    /// Native providers hand both to system tools, so oversized input is
+    /// stopped before it gets that far.
+    pub fn check_lengths(&self, username: &str, credentials: &[u8]) -> anyhow::Result<()> {
+        if self.max_username_len > 0 && username.len() > self.max_username_len {
+            anyhow::bail!(
+                "Username is {} bytes, more than the limit of {}",
+                username.len(),
+                self.max_username_len
+            );
+        }
+        if self.max_credential_len > 0 && credentials.len() > self.max_credential_len {
+            anyhow::bail!(
+                "Credential is {} bytes, more than the limit of {}",
+                credentials.len(),
+                self.max_credential_len
+            );
+        }
+        Ok(())
+    }
+
+    /// Whether credentials may be checked with `method`
+    pub fn allows_method(&self, method: &str) -> bool {
+        method_allowed(&self.allowed_methods, method)
+    }
+}
+
+/// Whether `method` is in an `allowed_methods` list, where empty allows any
+pub fn method_allowed(allowed_methods: &[String], method: &str) -> bool {
+    allowed_methods.is_empty()
+        || allowed_methods
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(method))
 }
 
 fn default_true() -> bool {
     true
 }
 
+fn default_max_username_len() -> usize {
+    DEFAULT_MAX_USERNAME_LEN
+}
+
+fn default_max_credential_len() -> usize {
+    DEFAULT_MAX_CREDENTIAL_LEN
+}
+
+fn default_init_attempts() -> u32 {
+    DEFAULT_INIT_ATTEMPTS
+}
+
+fn default_init_retry_delay_ms() -> u64 {
+    DEFAULT_INIT_RETRY_DELAY_MS
+}
+
 impl Default for AuthConfig {
     fn default() -> Self {
         Self {
@@ -87,6 +221,12 @@ impl Default for AuthConfig {
             native: NativeAuthConfig::default(),
             ldap: HashMap::new(),
             oauth: HashMap::new(),
+            allowed_methods: Vec::new(),
+            max_username_len: DEFAULT_MAX_USERNAME_LEN,
+            max_credential_len: DEFAULT_MAX_CREDENTIAL_LEN,
+            init_attempts: DEFAULT_INIT_ATTEMPTS,
+            init_retry_delay_ms: DEFAULT_INIT_RETRY_DELAY_MS,
+            init_strict: true,
         }
     }
 }
@@ -109,9 +249,32 @@ pub struct NativeAuthConfig {
     #[serde(default = "default_admin_groups")]
     pub admin_groups: Vec<String>,
 
+    /// OS groups whose members are regular users, whatever `default_role` is
+    #[serde(default)]
+    pub user_groups: Vec<String>,
+
+    /// Role of users in neither an admin group nor a user group
+    ///
+    /// Set to `guest` on locked-down deployments so only listed groups get
+    /// regular user access.
+    #[serde(default = "default_role")]
+    pub default_role: UserRole,
+
     /// Custom permission mappings
     #[serde(default)]
     pub permission_mappings: HashMap<String, Vec<String>>,
+
+    /// Seconds allowed for each directory lookup command (getent, dscl, net, ...)
+    #[serde(default = "default_command_timeout_secs")]
+    pub command_timeout_secs: u64,
+
+    /// Directory lookup commands allowed to run at once; others queue (0 for no limit)
+    #[serde(default = "default_max_concurrent_auth_subprocesses")]
+    pub max_concurrent_auth_subprocesses: usize,
+
+    /// Lowest UID listed as a regular user; system accounts sit below it
+    #[serde(default = "default_min_user_uid")]
+    pub min_user_uid: u32,
 }
 
 fn default_admin_groups() -> Vec<String> {
@@ -123,6 +286,22 @@ fn default_admin_groups() -> Vec<String> {
     ]
 }
 
+fn default_role() -> UserRole {
+    UserRole::User
+}
+
+fn default_command_timeout_secs() -> u64 {
+    DEFAULT_COMMAND_TIMEOUT_SECS
+}
+
+fn default_max_concurrent_auth_subprocesses() -> usize {
+    DEFAULT_MAX_CONCURRENT_SUBPROCESSES
+}
+
+fn default_min_user_uid() -> u32 {
+    DEFAULT_MIN_USER_UID
+}
+
 impl Default for NativeAuthConfig {
     fn default() -> Self {
         Self {
@@ -130,7 +309,12 @@ impl Default for NativeAuthConfig {
             require_group: Some("rcp-users".to_string()),
             permission_mapping: true,
             admin_groups: default_admin_groups(),
+            user_groups: Vec::new(),
+            default_role: default_role(),
             permission_mappings: HashMap::new(),
+            command_timeout_secs: default_command_timeout_secs(),
+            max_concurrent_auth_subprocesses: default_max_concurrent_auth_subprocesses(),
+            min_user_uid: default_min_user_uid(),
         }
     }
 }
@@ -139,6 +323,32 @@ impl Default for NativeAuthConfig {
 pub struct AuthProviderFactory;
 
 impl AuthProviderFactory {
+    /// Provider types this platform supports, and whether each is implemented
+    ///
+    /// Types left out cannot be used here at all; types reported `false`
+    /// compile on every platform but [`create_provider`](Self::create_provider)
+    /// refuses them for now.
+    pub fn available_providers() -> Vec<(AuthProviderType, bool)> {
+        AuthProviderType::ALL
+            .into_iter()
+            .filter_map(|provider| match provider {
+                AuthProviderType::Native => {
+                    let supported = cfg!(any(
+                        target_os = "macos",
+                        target_os = "windows",
+                        target_os = "linux",
+                        unix
+                    ));
+                    supported.then_some((provider, true))
+                }
+                AuthProviderType::Mock => Some((provider, true)),
+                AuthProviderType::Internal | AuthProviderType::Ldap | AuthProviderType::OAuth => {
+                    Some((provider, false))
+                }
+            })
+            .collect()
+    }
+
     /// Create a new authentication provider based on configuration
     pub fn create_provider(config: &AuthConfig) -> Result<Box<dyn AuthProvider>> {
         match config.provider {
@@ -148,6 +358,7 @@ impl AuthProviderFactory {
             }
             AuthProviderType::Native => {
                 info!("Using native OS authentication provider");
+                set_max_concurrent_subprocesses(config.native.max_concurrent_auth_subprocesses);
 
                 #[cfg(target_os = "macos")]
                 {
@@ -158,7 +369,10 @@ impl AuthProviderFactory {
                         require_group: config.native.require_group.clone(),
                         permission_mapping: config.native.permission_mapping,
                         admin_groups: config.native.admin_groups.clone(),
+                        user_groups: config.native.user_groups.clone(),
+                        default_role: config.native.default_role.clone(),
                         permission_mappings: config.native.permission_mappings.clone(),
+                        command_timeout_secs: config.native.command_timeout_secs,
                     };
 
                     Ok(Box::new(MacOSAuthProvider::new(macos_config)))
@@ -173,7 +387,10 @@ impl AuthProviderFactory {
                         require_group: config.native.require_group.clone(),
                         permission_mapping: config.native.permission_mapping,
                         admin_groups: config.native.admin_groups.clone(),
+                        user_groups: config.native.user_groups.clone(),
+                        default_role: config.native.default_role.clone(),
                         permission_mappings: config.native.permission_mappings.clone(),
+                        command_timeout_secs: config.native.command_timeout_secs,
                     };
 
                     Ok(Box::new(WindowsAuthProvider::new(windows_config)))
@@ -188,7 +405,11 @@ impl AuthProviderFactory {
                         require_group: config.native.require_group.clone(),
                         permission_mapping: config.native.permission_mapping,
                         admin_groups: config.native.admin_groups.clone(),
+                        user_groups: config.native.user_groups.clone(),
+                        default_role: config.native.default_role.clone(),
                         permission_mappings: config.native.permission_mappings.clone(),
+                        command_timeout_secs: config.native.command_timeout_secs,
+                        min_user_uid: config.native.min_user_uid,
                     };
 
                     Ok(Box::new(LinuxAuthProvider::new(linux_config)))
@@ -204,7 +425,11 @@ impl AuthProviderFactory {
                         require_group: config.native.require_group.clone(),
                         permission_mapping: config.native.permission_mapping,
                         admin_groups: config.native.admin_groups.clone(),
+                        user_groups: config.native.user_groups.clone(),
+                        default_role: config.native.default_role.clone(),
                         permission_mappings: config.native.permission_mappings.clone(),
+                        command_timeout_secs: config.native.command_timeout_secs,
+                        min_user_uid: config.native.min_user_uid,
                     };
 
                     Ok(Box::new(crate::auth::native_unix::UnixAuthProvider::new(
@@ -254,7 +479,7 @@ impl AuthProviderFactory {
     /// Create a mock provider with pre-configured test data
     #[cfg(test)]
     pub fn create_mock_provider() -> Box<dyn AuthProvider> {
-        use crate::server::user::{User, UserRole};
+        use crate::server::user::User;
         use uuid::Uuid;
 
         let test_user_id = Uuid::new_v4();
@@ -2,17 +2,29 @@
 //!
 //! This module contains common utility functions and traits for improving
 //! the native authentication providers.
+//!
+//! Each group lookup checks the username with [`check_username`] before it
+//! reaches a command line. The commands run through [`output_with_timeout`],
+//! so a lookup never blocks a runtime worker thread.
 
+use crate::auth::command::{check_username, output_with_timeout, DEFAULT_COMMAND_TIMEOUT_SECS};
+use crate::server::user::UserRole;
 use anyhow::{anyhow, Result};
+use async_trait::async_trait;
 use log::{debug, warn};
 use std::collections::HashMap;
 use std::path::Path;
-use std::process::Command;
+use std::time::Duration;
+use tokio::process::Command;
+
+/// Time allowed for each lookup command
+const LOOKUP_TIMEOUT: Duration = Duration::from_secs(DEFAULT_COMMAND_TIMEOUT_SECS);
 
 /// Trait for enhanced group management
+#[async_trait]
 pub trait EnhancedGroupManagement {
     /// Get all groups a user belongs to with improved error handling
-    fn get_user_groups_enhanced(
+    async fn get_user_groups_enhanced(
         &self,
         username: &str,
         cache: &mut HashMap<String, Vec<String>>,
@@ -29,10 +41,12 @@ pub trait EnhancedGroupManagement {
 }
 
 /// Implementation for macOS group management
-pub fn get_macos_user_groups(
+pub async fn get_macos_user_groups(
     username: &str,
     cache: &mut HashMap<String, Vec<String>>,
 ) -> Result<Vec<String>> {
+    check_username(username)?;
+
     // Check cache first
     if let Some(groups) = cache.get(username) {
         debug!("Using cached groups for user: {}", username);
@@ -42,9 +56,11 @@ pub fn get_macos_user_groups(
     debug!("Getting groups for user: {}", username);
 
     // Use dscl to get all groups
-    let output = Command::new("dscl")
-        .args([".", "-list", "/Groups", "GroupMembership"])
-        .output()?;
+    let output = output_with_timeout(
+        Command::new("dscl").args([".", "-list", "/Groups", "GroupMembership"]),
+        LOOKUP_TIMEOUT,
+    )
+    .await?;
 
     if !output.status.success() {
         return Err(anyhow!("Failed to list groups"));
@@ -69,7 +85,8 @@ pub fn get_macos_user_groups(
     }
 
     // Get the user's primary group
-    let primary_output = Command::new("id").args(["-gn", username]).output()?;
+    let primary_output =
+        output_with_timeout(Command::new("id").args(["-gn", username]), LOOKUP_TIMEOUT).await?;
 
     if primary_output.status.success() {
         let primary_group = String::from_utf8_lossy(&primary_output.stdout)
@@ -96,10 +113,12 @@ pub fn get_macos_user_groups(
 }
 
 /// Implementation for Linux group management
-pub fn get_linux_user_groups(
+pub async fn get_linux_user_groups(
     username: &str,
     cache: &mut HashMap<String, Vec<String>>,
 ) -> Result<Vec<String>> {
+    check_username(username)?;
+
     // Check cache first
     if let Some(groups) = cache.get(username) {
         debug!("Using cached groups for user: {}", username);
@@ -109,7 +128,7 @@ pub fn get_linux_user_groups(
     debug!("Getting groups for user: {}", username);
 
     // Use the 'groups' command
-    let output = Command::new("groups").arg(username).output()?;
+    let output = output_with_timeout(Command::new("groups").arg(username), LOOKUP_TIMEOUT).await?;
 
     if !output.status.success() {
         return Err(anyhow!(
@@ -142,10 +161,12 @@ pub fn get_linux_user_groups(
 }
 
 /// Implementation for Windows group management
-pub fn get_windows_user_groups(
+pub async fn get_windows_user_groups(
     username: &str,
     cache: &mut HashMap<String, Vec<String>>,
 ) -> Result<Vec<String>> {
+    check_username(username)?;
+
     // Check cache first
     if let Some(groups) = cache.get(username) {
         debug!("Using cached groups for user: {}", username);
@@ -154,15 +175,19 @@ pub fn get_windows_user_groups(
 
     debug!("Getting groups for user: {}", username);
 
-    // Use PowerShell to get user groups
-    let ps_command = format!(
-        "Get-LocalGroupMember -Member {} | Select-Object -ExpandProperty Group | Select-Object -ExpandProperty Name",
-        username
-    );
+    // Use PowerShell to get user groups. The name is bound to a parameter
+    // from the environment, so it is never parsed as part of the script.
+    let ps_command = "& { param($Member) Get-LocalGroup | Where-Object { \
+        Get-LocalGroupMember -Group $_ | Where-Object { ($_.Name -split '\\\\')[-1] -eq $Member } \
+        } | Select-Object -ExpandProperty Name } $env:RCP_GROUPS_USERNAME";
 
-    let output = Command::new("powershell")
-        .args(["-Command", &ps_command])
-        .output()?;
+    let output = output_with_timeout(
+        Command::new("powershell")
+            .args(["-NoProfile", "-NonInteractive", "-Command", ps_command])
+            .env("RCP_GROUPS_USERNAME", username),
+        LOOKUP_TIMEOUT,
+    )
+    .await?;
 
     if !output.status.success() {
         return Err(anyhow!(
@@ -187,10 +212,12 @@ pub fn get_windows_user_groups(
 }
 
 /// Implementation for generic Unix group management (FreeBSD, OpenBSD, NetBSD, etc.)
-pub fn get_unix_user_groups(
+pub async fn get_unix_user_groups(
     username: &str,
     cache: &mut HashMap<String, Vec<String>>,
 ) -> Result<Vec<String>> {
+    check_username(username)?;
+
     // Check cache first
     if let Some(groups) = cache.get(username) {
         debug!("Using cached groups for user: {}", username);
@@ -202,7 +229,7 @@ pub fn get_unix_user_groups(
     let mut groups = Vec::new();
 
     // First approach: Use the 'groups' command which is available on most Unix systems
-    let output = Command::new("groups").arg(username).output();
+    let output = output_with_timeout(Command::new("groups").arg(username), LOOKUP_TIMEOUT).await;
 
     if let Ok(output) = output {
         if output.status.success() {
@@ -239,7 +266,8 @@ pub fn get_unix_user_groups(
         );
 
         // Fallback 1: Try using getent
-        let getent_output = Command::new("getent").args(["group"]).output();
+        let getent_output =
+            output_with_timeout(Command::new("getent").arg("group"), LOOKUP_TIMEOUT).await;
 
         if let Ok(output) = getent_output {
             if output.status.success() {
@@ -275,7 +303,11 @@ pub fn get_unix_user_groups(
         // Fallback 2: Try 'id -G -n' command (works on most BSD systems and some Unix variants)
         if groups.is_empty() {
             debug!("Trying 'id -G -n' command");
-            let id_output = Command::new("id").args(["-G", "-n", username]).output();
+            let id_output = output_with_timeout(
+                Command::new("id").args(["-G", "-n", username]),
+                LOOKUP_TIMEOUT,
+            )
+            .await;
 
             if let Ok(output) = id_output {
                 if output.status.success() {
@@ -299,7 +331,7 @@ pub fn get_unix_user_groups(
         // Fallback 3: Check /etc/group directly (works on most Unix systems)
         if groups.is_empty() {
             debug!("Trying to parse /etc/group file directly");
-            if let Ok(group_contents) = std::fs::read_to_string("/etc/group") {
+            if let Ok(group_contents) = tokio::fs::read_to_string("/etc/group").await {
                 for line in group_contents.lines() {
                     let group_parts: Vec<&str> = line.split(':').collect();
                     if group_parts.len() >= 4 {
@@ -335,9 +367,9 @@ pub fn get_unix_user_groups(
 }
 
 /// Detect the Unix variant for better platform-specific behavior
-pub fn detect_unix_platform() -> String {
+pub async fn detect_unix_platform() -> String {
     // First try uname -s
-    if let Ok(output) = Command::new("uname").arg("-s").output() {
+    if let Ok(output) = output_with_timeout(Command::new("uname").arg("-s"), LOOKUP_TIMEOUT).await {
         if output.status.success() {
             let os_name = String::from_utf8_lossy(&output.stdout).trim().to_string();
             match os_name.as_str() {
@@ -373,8 +405,8 @@ pub fn detect_unix_platform() -> String {
 }
 
 /// Get common admin groups for the detected platform
-pub fn get_platform_admin_groups() -> Vec<String> {
-    let platform = detect_unix_platform();
+pub async fn get_platform_admin_groups() -> Vec<String> {
+    let platform = detect_unix_platform().await;
     let mut admin_groups = vec![
         "wheel".to_string(), // Common across many Unix systems
     ];
@@ -407,6 +439,25 @@ pub fn get_platform_admin_groups() -> Vec<String> {
     admin_groups
 }
 
+/// Common implementation for determining a user's role from their groups
+///
+/// Members of an admin group are admins and members of a user group are
+/// regular users; everyone else gets `default_role`.
+pub fn determine_role_common(
+    groups: &[String],
+    admin_groups: &[String],
+    user_groups: &[String],
+    default_role: &UserRole,
+) -> UserRole {
+    if groups.iter().any(|g| admin_groups.contains(g)) {
+        UserRole::Admin
+    } else if groups.iter().any(|g| user_groups.contains(g)) {
+        UserRole::User
+    } else {
+        default_role.clone()
+    }
+}
+
 /// Common implementation for mapping permissions
 pub fn map_permissions_common(
     groups: &[String],
@@ -4,8 +4,10 @@ use crate::server::user::User;
 
 use anyhow::Result;
 use log::{error, info, warn};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::Duration;
+use tokio::sync::{RwLock, RwLockReadGuard};
 
 /// Authentication manager that uses the configured provider
 pub struct AuthManager {
@@ -17,8 +19,14 @@ pub struct AuthManager {
 
     /// Whether the provider has been initialized
     pub initialized: bool,
+
+    /// Whether initialization gave up and authentication is failing closed
+    degraded: AtomicBool,
 }
 
+/// Longest wait between two attempts at initializing the provider
+const MAX_INIT_RETRY_DELAY: Duration = Duration::from_secs(30);
+
 impl AuthManager {
     /// Create a new authentication manager with the specified configuration
     pub async fn new(config: AuthConfig) -> Result<Self> {
@@ -28,18 +36,65 @@ impl AuthManager {
             config,
             provider: Arc::new(RwLock::new(provider)),
             initialized: false,
+            degraded: AtomicBool::new(false),
         })
     }
 
     /// Initialize the authentication manager
+    ///
+    /// A provider backed by a remote directory may be briefly out of reach,
+    /// so initialization is tried up to `init_attempts` times, waiting
+    /// `init_retry_delay_ms` before the first retry and twice as long before
+    /// each one after. If every attempt fails, that is an error when
+    /// `init_strict` is set; otherwise the manager starts
+    /// [degraded](Self::is_degraded).
     pub async fn initialize(&mut self) -> Result<()> {
         if self.initialized {
             return Ok(());
         }
 
         let mut provider = self.provider.write().await;
-        provider.initialize().await?;
+        let attempts = self.config.init_attempts.max(1);
+        let mut delay = Duration::from_millis(self.config.init_retry_delay_ms);
+        let mut attempt = 1;
+        loop {
+            match provider.initialize().await {
+                Ok(()) => break,
+                Err(e) if attempt < attempts => {
+                    warn!(
+                        "Initializing authentication provider {} failed (attempt {} of {}), retrying in {:?}: {}",
+                        provider.name(),
+                        attempt,
+                        attempts,
+                        delay,
+                        e
+                    );
+                    tokio::time::sleep(delay).await;
+                    delay = (delay * 2).min(MAX_INIT_RETRY_DELAY);
+                    attempt += 1;
+                }
+                Err(e) if self.config.init_strict => {
+                    return Err(e.context(format!(
+                        "Authentication provider {} failed to initialize after {} attempts",
+                        provider.name(),
+                        attempts
+                    )));
+                }
+                Err(e) => {
+                    error!(
+                        "Authentication provider {} failed to initialize after {} attempts, \
+                         refusing authentication until it does: {}",
+                        provider.name(),
+                        attempts,
+                        e
+                    );
+                    self.degraded.store(true, Ordering::Relaxed);
+                    return Ok(());
+                }
+            }
+        }
         self.initialized = true;
+        self.degraded.store(false, Ordering::Relaxed);
 
         info!(
             "Authentication manager initialized with provider: {}",
@@ -48,14 +103,69 @@ impl AuthManager {
         Ok(())
     }
 
+    /// Whether the manager started without a working provider
+    ///
+    /// A degraded manager fails every authentication, trying to initialize
+    /// the provider again each time, until that succeeds.
+    pub fn is_degraded(&self) -> bool {
+        self.degraded.load(Ordering::Relaxed)
+    }
+
+    /// The provider, initializing it first if nobody has yet
+    ///
+    /// Lets a manager that was never explicitly initialized, or whose
+    /// provider was swapped, still authenticate against a ready provider.
+    async fn ready_provider(&self) -> Result<RwLockReadGuard<'_, Box<dyn AuthProvider>>> {
+        let provider = self.provider.read().await;
+        if provider.is_initialized() {
+            return Ok(provider);
+        }
+        drop(provider);
+
+        let mut provider = self.provider.write().await;
+        // Another caller may have got there while the lock was free
+        if !provider.is_initialized() {
+            provider.initialize().await?;
+            if self.degraded.swap(false, Ordering::Relaxed) {
+                info!(
+                    "Authentication provider {} initialized, no longer degraded",
+                    provider.name()
+                );
+            } else {
+                info!(
+                    "Initialized authentication provider {} on first use",
+                    provider.name()
+                );
+            }
+        }
+        Ok(provider.downgrade())
+    }
+
     /// Validate credentials for a user
+    ///
+    /// Methods left out of `allowed_methods`, and usernames or credentials
+    /// over the length limits, are refused before the provider is asked.
     pub async fn validate_credentials(
         &self,
         username: &str,
         credentials: &[u8],
         method: &str,
     ) -> Result<bool> {
-        let provider = self.provider.read().await;
+        if let Err(e) = self.config.check_lengths(username, credentials) {
+            // Not logging the username itself, as it may be huge
+            warn!("Refused authentication: {}", e);
+            return Err(e);
+        }
+
+        if !self.config.allows_method(method) {
+            warn!(
+                "Refused authentication of {} with disallowed method {}",
+                username, method
+            );
+            anyhow::bail!("Authentication method '{}' is not allowed", method);
+        }
+
+        let provider = self.ready_provider().await?;
 
         match provider
             .validate_credentials(username, credentials, method)
@@ -75,12 +185,15 @@ impl AuthManager {
                     fallback_config.provider = AuthProviderType::Internal;
 
                     match AuthProviderFactory::create_provider(&fallback_config) {
-                        Ok(fallback_provider) => {
+                        Ok(mut fallback_provider) => {
                             // Try validating with the fallback provider
-                            match fallback_provider
-                                .validate_credentials(username, credentials, method)
-                                .await
-                            {
+                            let validated = async {
+                                fallback_provider.initialize().await?;
+                                fallback_provider
+                                    .validate_credentials(username, credentials, method)
+                                    .await
+                            };
+                            match validated.await {
                                 Ok(valid) => Ok(valid),
                                 Err(fallback_err) => {
                                     warn!("Fallback authentication also failed: {}", fallback_err);
@@ -102,19 +215,19 @@ impl AuthManager {
 
     /// Get a user by their username
     pub async fn get_user_by_username(&self, username: &str) -> Result<Option<User>> {
-        let provider = self.provider.read().await;
+        let provider = self.ready_provider().await?;
         provider.get_user_by_username(username).await
     }
 
     /// Check if a user has the specified permission
     pub async fn has_permission(&self, user: &User, permission: &str) -> Result<bool> {
-        let provider = self.provider.read().await;
+        let provider = self.ready_provider().await?;
         provider.has_permission(user, permission).await
     }
 
     /// Get all permissions for a user
     pub async fn get_permissions(&self, user: &User) -> Result<Vec<String>> {
-        let provider = self.provider.read().await;
+        let provider = self.ready_provider().await?;
         provider.get_permissions(user).await
     }
 }
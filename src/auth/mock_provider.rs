@@ -18,6 +18,9 @@ pub struct MockAuthProvider {
 
     /// Whether initialization was called
     initialized: bool,
+
+    /// Initialization attempts still to fail
+    init_failures: u32,
 }
 
 impl Default for MockAuthProvider {
@@ -34,9 +37,17 @@ impl MockAuthProvider {
             credentials: HashMap::new(),
             permissions: HashMap::new(),
             initialized: false,
+            init_failures: 0,
         }
     }
 
+    /// Fail the first `failures` calls to initialize, as an unreachable
+    /// backend would
+    pub fn with_init_failures(mut self, failures: u32) -> Self {
+        self.init_failures = failures;
+        self
+    }
+
     /// Add a user to the mock provider
     pub fn with_user(mut self, user: User) -> Self {
         self.users.insert(user.username.clone(), user);
@@ -61,16 +72,26 @@ impl MockAuthProvider {
 #[async_trait]
 impl AuthProvider for MockAuthProvider {
     async fn initialize(&mut self) -> Result<()> {
+        if self.init_failures > 0 {
+            self.init_failures -= 1;
+            anyhow::bail!("Mock provider backend unavailable");
+        }
         self.initialized = true;
         Ok(())
     }
 
+    fn is_initialized(&self) -> bool {
+        self.initialized
+    }
+
     async fn validate_credentials(
         &self,
         username: &str,
         credentials: &[u8],
         method: &str,
     ) -> Result<bool> {
+        self.ensure_initialized()?;
+
         match method {
             "password" => {
                 if let Some(stored_creds) = self.credentials.get(username) {
@@ -117,6 +138,8 @@ impl AuthProvider for MockAuthProvider {
     }
 
     async fn has_permission(&self, user: &User, permission: &str) -> Result<bool> {
+        self.ensure_initialized()?;
+
         if let Some(perms) = self.permissions.get(&user.username) {
             // Check for exact permission match
             if perms.contains(&permission.to_string()) {
@@ -1,9 +1,12 @@
+pub mod cache;
+pub mod command;
 pub mod factory;
 pub mod improved_native;
 pub mod manager;
 pub mod mock_provider;
 pub mod native_macos;
 pub mod provider;
+pub mod user_db;
 
 #[cfg(target_os = "windows")]
 pub mod native_windows;
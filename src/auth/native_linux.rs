@@ -1,4 +1,10 @@
+use crate::auth::cache::GroupCache;
+use crate::auth::command::{
+    check_username, is_valid_username, output_with_timeout, DEFAULT_COMMAND_TIMEOUT_SECS,
+};
+use crate::auth::improved_native::determine_role_common;
 use crate::auth::provider::AuthProvider;
+use crate::auth::user_db::{parse_passwd, UserDatabase, DEFAULT_MIN_USER_UID};
 use crate::server::user::{User, UserRole};
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
@@ -6,9 +12,16 @@ use log::{debug, error, info, warn};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::Path;
-use std::process::Command;
+use std::time::Duration;
+use tokio::process::Command;
 use uuid::Uuid;
 
+/// passwd database read when `getent` is unavailable
+const PASSWD_PATH: &str = "/etc/passwd";
+
+/// group database read when `getent` is unavailable
+const GROUP_PATH: &str = "/etc/group";
+
 /// Configuration for the Linux native auth provider
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LinuxAuthConfig {
@@ -24,8 +37,20 @@ pub struct LinuxAuthConfig {
     /// Groups that have admin privileges
     pub admin_groups: Vec<String>,
 
+    /// Groups whose members are regular users even when `default_role` is lower
+    pub user_groups: Vec<String>,
+
+    /// Role of users in neither an admin group nor a user group
+    pub default_role: UserRole,
+
     /// Custom permission mappings (group -> permission)
     pub permission_mappings: HashMap<String, Vec<String>>,
+
+    /// Seconds allowed for each directory lookup command before it is killed
+    pub command_timeout_secs: u64,
+
+    /// Lowest UID listed as a regular user; system accounts sit below it
+    pub min_user_uid: u32,
 }
 
 impl Default for LinuxAuthConfig {
@@ -35,7 +60,11 @@ impl Default for LinuxAuthConfig {
             require_group: Some("rcp-users".to_string()),
             permission_mapping: true,
             admin_groups: vec!["sudo".to_string(), "wheel".to_string(), "admin".to_string()],
+            user_groups: Vec::new(),
+            default_role: UserRole::User,
             permission_mappings: HashMap::new(),
+            command_timeout_secs: DEFAULT_COMMAND_TIMEOUT_SECS,
+            min_user_uid: DEFAULT_MIN_USER_UID,
         }
     }
 }
@@ -49,7 +78,10 @@ pub struct LinuxAuthProvider {
     user_cache: HashMap<String, User>,
 
     /// Cache of group memberships
-    group_cache: HashMap<String, Vec<String>>,
+    group_cache: GroupCache,
+
+    /// Whether `initialize` has been called
+    initialized: bool,
 }
 
 impl LinuxAuthProvider {
@@ -58,50 +90,45 @@ impl LinuxAuthProvider {
         Self {
             config,
             user_cache: HashMap::new(),
-            group_cache: HashMap::new(),
+            group_cache: GroupCache::new(),
+            initialized: false,
         }
     }
 
-    /// Check if a user is a member of a group
-    fn is_member_of_group(&self, username: &str, group: &str) -> Result<bool> {
-        // Use getent to check group membership
-        let output = Command::new("getent").args(&["group", group]).output()?;
-
-        if !output.status.success() {
-            return Ok(false);
-        }
-
-        let output_str = String::from_utf8_lossy(&output.stdout);
-        let members = output_str.split(':').nth(3).unwrap_or("");
+    /// Time allowed for each directory lookup command
+    fn command_timeout(&self) -> Duration {
+        Duration::from_secs(self.config.command_timeout_secs)
+    }
 
-        Ok(members.split(',').any(|m| m.trim() == username))
+    /// Check if a user is a member of a group
+    async fn is_member_of_group(&self, username: &str, group: &str) -> Result<bool> {
+        self.group_cache
+            .is_member(username, group, || self.fetch_user_groups(username))
+            .await
     }
 
     /// Get all groups a user belongs to
-    fn get_user_groups(&self, username: &str) -> Result<Vec<String>> {
-        // Check if cached
-        if let Some(groups) = self.group_cache.get(username) {
-            return Ok(groups.clone());
-        }
+    async fn get_user_groups(&self, username: &str) -> Result<Vec<String>> {
+        self.group_cache
+            .get_or_fetch(username, || self.fetch_user_groups(username))
+            .await
+    }
+
+    /// Look up all groups a user belongs to, bypassing the cache
+    async fn fetch_user_groups(&self, username: &str) -> Result<Vec<String>> {
+        check_username(username)?;
 
         // Use groups command to get all groups
-        let output = Command::new("groups").arg(username).output()?;
+        let output =
+            output_with_timeout(Command::new("groups").arg(username), self.command_timeout())
+                .await?;
 
         if !output.status.success() {
             return Err(anyhow!("Failed to list groups for user: {}", username));
         }
 
         let output_str = String::from_utf8_lossy(&output.stdout);
-        let mut groups = Vec::new();
-
-        // Parse output: "username : group1 group2 group3"
-        if let Some(groups_part) = output_str.split(':').nth(1) {
-            for group in groups_part.split_whitespace() {
-                groups.push(group.to_string());
-            }
-        }
-
-        Ok(groups)
+        Ok(parse_groups_output(&output_str))
     }
 
     /// Map OS groups to RCP permissions
@@ -142,52 +169,171 @@ impl LinuxAuthProvider {
     }
 
     /// Validate credentials using PAM
-    fn validate_system_credentials(&self, username: &str, _password: &[u8]) -> Result<bool> {
+    async fn validate_system_credentials(&self, username: &str, _password: &[u8]) -> Result<bool> {
         // This is a simplified version for demonstration
         // In a real implementation, you would use PAM for authentication
 
         // For now, just check if the user exists
-        let output = Command::new("id").arg(username).output()?;
+        let output =
+            output_with_timeout(Command::new("id").arg(username), self.command_timeout()).await?;
 
         Ok(output.status.success())
     }
+
+    /// Read a whole NSS database such as `passwd` or `group`
+    ///
+    /// Prefers getent so NSS sources (LDAP, sssd, ...) are included; minimal
+    /// containers often lack it, so fall back to reading the local file directly.
+    async fn read_database(&self, database: &str, path: &str) -> Result<String> {
+        let getent =
+            output_with_timeout(Command::new("getent").arg(database), self.command_timeout()).await;
+
+        match getent {
+            Ok(output) if output.status.success() => {
+                return Ok(String::from_utf8_lossy(&output.stdout).into_owned());
+            }
+            Ok(output) => debug!(
+                "getent {} exited with {}, reading {}",
+                database, output.status, path
+            ),
+            Err(e) => debug!("getent unavailable ({}), reading {}", e, path),
+        }
+
+        tokio::fs::read_to_string(path)
+            .await
+            .map_err(|e| anyhow!("Failed to read {}: {}", path, e))
+    }
+}
+
+/// Usernames of regular (non-system) accounts in passwd-format data
+///
+/// Accepts both `getent passwd` output and `/etc/passwd` contents; blank lines,
+/// comments, malformed entries and accounts with a UID below `min_uid` are skipped.
+pub fn parse_passwd_usernames(contents: &str, min_uid: u32) -> Vec<String> {
+    parse_passwd(contents)
+        .into_iter()
+        .filter(|entry| entry.uid >= min_uid)
+        .map(|entry| entry.username)
+        .collect()
+}
+
+/// Build users for every regular account from passwd and group data
+///
+/// Group memberships come from the same snapshot, so no per-user lookups are needed.
+pub fn users_from_database(config: &LinuxAuthConfig, passwd: &str, group: &str) -> Vec<User> {
+    let database = UserDatabase::new(passwd, group);
+
+    database
+        .regular_users(config.min_user_uid)
+        .map(|entry| {
+            build_user(
+                config,
+                &entry.username,
+                entry.display_name(),
+                database.groups(&entry.username),
+            )
+        })
+        .collect()
+}
+
+/// Build a user object from its display name and groups
+fn build_user(
+    config: &LinuxAuthConfig,
+    username: &str,
+    display_name: String,
+    groups: &[String],
+) -> User {
+    // Determine role based on group membership
+    let role = determine_role_common(
+        groups,
+        &config.admin_groups,
+        &config.user_groups,
+        &config.default_role,
+    );
+
+    User {
+        // Derived from the username, so a user keeps its ID across lookups
+        id: Uuid::new_v5(&Uuid::NAMESPACE_DNS, username.as_bytes()),
+        username: username.to_string(),
+        full_name: Some(display_name),
+        email: None,
+        role,
+        password_hash: "".to_string(), // We don't store passwords
+        created_at: "1970-01-01T00:00:00Z".to_string(), // Not tracked, use epoch
+        updated_at: "1970-01-01T00:00:00Z".to_string(), // Not tracked, use epoch
+    }
+}
+
+/// Parse the member list of a `getent group` entry ("name:x:gid:member1,member2")
+pub fn parse_group_members(entry: &str) -> Vec<&str> {
+    let members = entry.split(':').nth(3).unwrap_or("");
+    members
+        .split(',')
+        .map(|m| m.trim())
+        .filter(|m| !m.is_empty())
+        .collect()
+}
+
+/// Parse the output of `groups <user>` ("username : group1 group2 group3")
+pub fn parse_groups_output(output: &str) -> Vec<String> {
+    match output.split(':').nth(1) {
+        Some(groups_part) => groups_part
+            .split_whitespace()
+            .map(|group| group.to_string())
+            .collect(),
+        None => Vec::new(),
+    }
 }
 
 #[async_trait]
 impl AuthProvider for LinuxAuthProvider {
     async fn initialize(&mut self) -> Result<()> {
+        if self.initialized {
+            return Ok(());
+        }
         info!("Initializing Linux native authentication provider");
 
         // Clear caches
         self.user_cache.clear();
         self.group_cache.clear();
+        self.initialized = true;
 
         Ok(())
     }
 
+    fn is_initialized(&self) -> bool {
+        self.initialized
+    }
+
     async fn validate_credentials(
         &self,
         username: &str,
         credentials: &[u8],
         method: &str,
     ) -> Result<bool> {
+        self.ensure_initialized()?;
+        check_username(username)?;
+
         match method {
             "psk" => {
                 // For PSK, we just check if the user exists and is allowed
                 if !self.config.allow_all_users {
                     if let Some(ref required_group) = self.config.require_group {
-                        return Ok(self.is_member_of_group(username, required_group)?);
+                        return Ok(self.is_member_of_group(username, required_group).await?);
                     }
                 }
 
                 // Check if user exists
-                let output = Command::new("id").arg(username).output()?;
+                let output =
+                    output_with_timeout(Command::new("id").arg(username), self.command_timeout())
+                        .await?;
 
                 Ok(output.status.success())
             }
             "password" => {
                 // Validate system credentials
                 self.validate_system_credentials(username, credentials)
+                    .await
             }
             "publickey" => {
                 // Public key auth could be implemented by checking ~/.ssh/authorized_keys
@@ -199,24 +345,34 @@ impl AuthProvider for LinuxAuthProvider {
     }
 
     async fn get_user_by_username(&self, username: &str) -> Result<Option<User>> {
+        // No account can have a name the lookup tools would misread
+        if !is_valid_username(username) {
+            return Ok(None);
+        }
+
         // Check if cached
         if let Some(user) = self.user_cache.get(username) {
             return Ok(Some(user.clone()));
         }
 
         // Check if user exists
-        let output = Command::new("id").arg(username).output()?;
+        let output =
+            output_with_timeout(Command::new("id").arg(username), self.command_timeout()).await?;
 
         if !output.status.success() {
             return Ok(None);
         }
 
-        // Get user information
-        let gecos_output = Command::new("getent")
-            .args(&["passwd", username])
-            .output()?;
+        // Get user information (getent may be missing on minimal systems)
+        let gecos_output = output_with_timeout(
+            Command::new("getent").args(&["passwd", username]),
+            self.command_timeout(),
+        )
+        .await
+        .ok()
+        .filter(|output| output.status.success());
 
-        let display_name = if gecos_output.status.success() {
+        let display_name = if let Some(gecos_output) = gecos_output {
             let output_str = String::from_utf8_lossy(&gecos_output.stdout);
             let parts: Vec<&str> = output_str.split(':').collect();
             if parts.len() >= 5 {
@@ -235,68 +391,41 @@ impl AuthProvider for LinuxAuthProvider {
         };
 
         // Get user's groups
-        let groups = self.get_user_groups(username)?;
+        let groups = self.get_user_groups(username).await?;
 
-        // Determine role based on group membership
-        let role = if groups.iter().any(|g| self.config.admin_groups.contains(g)) {
-            UserRole::Admin
-        } else {
-            UserRole::User
-        };
-
-        // Create user object
-        let user = User {
-            id: Uuid::new_v5(&Uuid::NAMESPACE_DNS, username.as_bytes()),
-            username: username.to_string(),
+        Ok(Some(build_user(
+            &self.config,
+            username,
             display_name,
-            email: None,
-            role,
-            password_hash: "".to_string(), // We don't store passwords
-            enabled: true,
-            created_at: 0, // Not tracked
-            last_login: 0, // Not tracked
-        };
-
-        Ok(Some(user))
+            &groups,
+        )))
     }
 
     async fn get_user(&self, id: &Uuid) -> Result<Option<User>> {
-        // Since we generate UUIDs based on usernames, we can't easily
-        // look up by UUID without listing all users. For efficiency,
-        // we'll return None and let the caller use get_user_by_username instead.
-        warn!("Looking up Linux users by UUID is not efficient");
-
-        // In a real implementation, maintain a reverse lookup cache
-        Ok(None)
+        // IDs are hashes of usernames and cannot be reversed, so find the
+        // user in one snapshot of the databases
+        Ok(self
+            .list_users()
+            .await?
+            .into_iter()
+            .find(|user| user.id == *id))
     }
 
     async fn list_users(&self) -> Result<Vec<User>> {
-        // Get all users from /etc/passwd
-        let output = Command::new("getent").arg("passwd").output()?;
-
-        if !output.status.success() {
-            return Err(anyhow!("Failed to list users"));
-        }
-
-        let output_str = String::from_utf8_lossy(&output.stdout);
-        let mut users = Vec::new();
-
-        for line in output_str.lines() {
-            let parts: Vec<&str> = line.split(':').collect();
-            if parts.len() >= 3 {
-                let username = parts[0];
-                let uid: i32 = parts[2].parse().unwrap_or(0);
-
-                // Skip system users (typically UID < 1000)
-                if uid >= 1000 {
-                    if let Ok(Some(user)) = self.get_user_by_username(username).await {
-                        users.push(user);
-                    }
-                }
+        // Read both databases once instead of spawning lookups for every user
+        let passwd = self.read_database("passwd", PASSWD_PATH).await?;
+        let group = match self.read_database("group", GROUP_PATH).await {
+            Ok(group) => group,
+            Err(e) => {
+                warn!(
+                    "Group database unavailable, listing users without groups: {}",
+                    e
+                );
+                String::new()
             }
-        }
+        };
 
-        Ok(users)
+        Ok(users_from_database(&self.config, &passwd, &group))
     }
 
     async fn create_user(&self, _user: User) -> Result<()> {
@@ -318,8 +447,10 @@ impl AuthProvider for LinuxAuthProvider {
     }
 
     async fn has_permission(&self, user: &User, permission: &str) -> Result<bool> {
+        self.ensure_initialized()?;
+
         // Get user's groups
-        let groups = self.get_user_groups(&user.username)?;
+        let groups = self.get_user_groups(&user.username).await?;
 
         // Map groups to permissions
         let permissions = self.map_permissions(&groups);
@@ -342,7 +473,7 @@ impl AuthProvider for LinuxAuthProvider {
     }
 
     async fn get_permissions(&self, user: &User) -> Result<Vec<String>> {
-        let groups = self.get_user_groups(&user.username)?;
+        let groups = self.get_user_groups(&user.username).await?;
         Ok(self.map_permissions(&groups))
     }
 
@@ -1,3 +1,8 @@
+use crate::auth::cache::GroupCache;
+use crate::auth::command::{
+    check_username, is_valid_username, output_with_timeout, DEFAULT_COMMAND_TIMEOUT_SECS,
+};
+use crate::auth::improved_native::determine_role_common;
 use crate::auth::provider::AuthProvider;
 use crate::server::user::{User, UserRole};
 use anyhow::{anyhow, Result};
@@ -5,7 +10,8 @@ use async_trait::async_trait;
 use log::{info, warn};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::process::Command;
+use std::time::Duration;
+use tokio::process::Command;
 use uuid::Uuid;
 
 /// Configuration for the macOS native auth provider
@@ -23,8 +29,17 @@ pub struct MacOSAuthConfig {
     /// Groups that have admin privileges
     pub admin_groups: Vec<String>,
 
+    /// Groups whose members are regular users even when `default_role` is lower
+    pub user_groups: Vec<String>,
+
+    /// Role of users in neither an admin group nor a user group
+    pub default_role: UserRole,
+
     /// Custom permission mappings (group -> permission)
     pub permission_mappings: HashMap<String, Vec<String>>,
+
+    /// Seconds allowed for each directory lookup command before it is killed
+    pub command_timeout_secs: u64,
 }
 
 impl Default for MacOSAuthConfig {
@@ -34,7 +49,10 @@ impl Default for MacOSAuthConfig {
             require_group: Some("rcp-users".to_string()),
             permission_mapping: true,
             admin_groups: vec!["admin".to_string(), "wheel".to_string()],
+            user_groups: Vec::new(),
+            default_role: UserRole::User,
             permission_mappings: HashMap::new(),
+            command_timeout_secs: DEFAULT_COMMAND_TIMEOUT_SECS,
         }
     }
 }
@@ -48,7 +66,10 @@ pub struct MacOSAuthProvider {
     user_cache: HashMap<String, User>,
 
     /// Cache of group memberships
-    group_cache: HashMap<String, Vec<String>>,
+    group_cache: GroupCache,
+
+    /// Whether `initialize` has been called
+    initialized: bool,
 }
 
 impl MacOSAuthProvider {
@@ -57,41 +78,38 @@ impl MacOSAuthProvider {
         Self {
             config,
             user_cache: HashMap::new(),
-            group_cache: HashMap::new(),
+            group_cache: GroupCache::new(),
+            initialized: false,
         }
     }
 
-    /// Check if a user is a member of a group
-    fn is_member_of_group(&self, username: &str, group: &str) -> Result<bool> {
-        // Use dscl to check group membership
-        let output = Command::new("dscl")
-            .args([
-                ".",
-                "-read",
-                &format!("/Groups/{}", group),
-                "GroupMembership",
-            ])
-            .output()?;
-
-        if !output.status.success() {
-            return Ok(false);
-        }
+    /// Time allowed for each directory lookup command
+    fn command_timeout(&self) -> Duration {
+        Duration::from_secs(self.config.command_timeout_secs)
+    }
 
-        let output_str = String::from_utf8_lossy(&output.stdout);
-        Ok(output_str.contains(username))
+    /// Check if a user is a member of a group
+    async fn is_member_of_group(&self, username: &str, group: &str) -> Result<bool> {
+        self.group_cache
+            .is_member(username, group, || self.fetch_user_groups(username))
+            .await
     }
 
     /// Get all groups a user belongs to
-    fn get_user_groups(&self, username: &str) -> Result<Vec<String>> {
-        // Check if cached
-        if let Some(groups) = self.group_cache.get(username) {
-            return Ok(groups.clone());
-        }
+    async fn get_user_groups(&self, username: &str) -> Result<Vec<String>> {
+        self.group_cache
+            .get_or_fetch(username, || self.fetch_user_groups(username))
+            .await
+    }
 
+    /// Look up all groups a user belongs to, bypassing the cache
+    async fn fetch_user_groups(&self, username: &str) -> Result<Vec<String>> {
         // Use dscl to get all groups
-        let output = Command::new("dscl")
-            .args([".", "-list", "/Groups", "GroupMembership"])
-            .output()?;
+        let output = output_with_timeout(
+            Command::new("dscl").args([".", "-list", "/Groups", "GroupMembership"]),
+            self.command_timeout(),
+        )
+        .await?;
 
         if !output.status.success() {
             return Err(anyhow!("Failed to list groups"));
@@ -153,7 +171,7 @@ impl MacOSAuthProvider {
     }
 
     /// Validate credentials using PAM
-    fn validate_system_credentials(&self, username: &str, password: &[u8]) -> Result<bool> {
+    async fn validate_system_credentials(&self, username: &str, password: &[u8]) -> Result<bool> {
         // This is a simplified version using the `pam` crate
         // In a real implementation, you would use the actual PAM APIs
 
@@ -165,9 +183,11 @@ impl MacOSAuthProvider {
         // or a proper PAM binding to validate credentials
 
         // For now, we'll just check if the user exists
-        let output = Command::new("dscl")
-            .args([".", "-read", &format!("/Users/{}", username)])
-            .output()?;
+        let output = output_with_timeout(
+            Command::new("dscl").args([".", "-read", &format!("/Users/{}", username)]),
+            self.command_timeout(),
+        )
+        .await?;
 
         Ok(output.status.success())
     }
@@ -176,40 +196,54 @@ impl MacOSAuthProvider {
 #[async_trait]
 impl AuthProvider for MacOSAuthProvider {
     async fn initialize(&mut self) -> Result<()> {
+        if self.initialized {
+            return Ok(());
+        }
         info!("Initializing macOS native authentication provider");
 
         // Clear caches
         self.user_cache.clear();
         self.group_cache.clear();
+        self.initialized = true;
 
         Ok(())
     }
 
+    fn is_initialized(&self) -> bool {
+        self.initialized
+    }
+
     async fn validate_credentials(
         &self,
         username: &str,
         credentials: &[u8],
         method: &str,
     ) -> Result<bool> {
+        self.ensure_initialized()?;
+        check_username(username)?;
+
         match method {
             "psk" => {
                 // For PSK, we just check if the user exists and is allowed
                 if !self.config.allow_all_users {
                     if let Some(required_group) = &self.config.require_group {
-                        return self.is_member_of_group(username, required_group);
+                        return self.is_member_of_group(username, required_group).await;
                     }
                 }
 
                 // Check if user exists
-                let output = Command::new("dscl")
-                    .args([".", "-read", &format!("/Users/{}", username)])
-                    .output()?;
+                let output = output_with_timeout(
+                    Command::new("dscl").args([".", "-read", &format!("/Users/{}", username)]),
+                    self.command_timeout(),
+                )
+                .await?;
 
                 Ok(output.status.success())
             }
             "password" => {
                 // Validate system credentials
                 self.validate_system_credentials(username, credentials)
+                    .await
             }
             "publickey" => {
                 // For public key auth, we'd check the user's authorized_keys
@@ -222,15 +256,22 @@ impl AuthProvider for MacOSAuthProvider {
     }
 
     async fn get_user_by_username(&self, username: &str) -> Result<Option<User>> {
+        // No account can have a name the lookup tools would misread
+        if !is_valid_username(username) {
+            return Ok(None);
+        }
+
         // Check if cached
         if let Some(user) = self.user_cache.get(username) {
             return Ok(Some(user.clone()));
         }
 
         // Check if user exists
-        let output = Command::new("dscl")
-            .args([".", "-read", &format!("/Users/{}", username)])
-            .output()?;
+        let output = output_with_timeout(
+            Command::new("dscl").args([".", "-read", &format!("/Users/{}", username)]),
+            self.command_timeout(),
+        )
+        .await?;
 
         if !output.status.success() {
             return Ok(None);
@@ -248,14 +289,15 @@ impl AuthProvider for MacOSAuthProvider {
         };
 
         // Get user's groups
-        let groups = self.get_user_groups(username)?;
+        let groups = self.get_user_groups(username).await?;
 
         // Determine role based on group membership
-        let role = if groups.iter().any(|g| self.config.admin_groups.contains(g)) {
-            UserRole::Admin
-        } else {
-            UserRole::User
-        };
+        let role = determine_role_common(
+            &groups,
+            &self.config.admin_groups,
+            &self.config.user_groups,
+            &self.config.default_role,
+        );
 
         // Create user object
         let user = User {
@@ -284,9 +326,11 @@ impl AuthProvider for MacOSAuthProvider {
 
     async fn list_users(&self) -> Result<Vec<User>> {
         // Get all users from directory services
-        let output = Command::new("dscl")
-            .args([".", "-list", "/Users"])
-            .output()?;
+        let output = output_with_timeout(
+            Command::new("dscl").args([".", "-list", "/Users"]),
+            self.command_timeout(),
+        )
+        .await?;
 
         if !output.status.success() {
             return Err(anyhow!("Failed to list users"));
@@ -328,8 +372,10 @@ impl AuthProvider for MacOSAuthProvider {
     }
 
     async fn has_permission(&self, user: &User, permission: &str) -> Result<bool> {
+        self.ensure_initialized()?;
+
         // Get user's groups
-        let groups = self.get_user_groups(&user.username)?;
+        let groups = self.get_user_groups(&user.username).await?;
 
         // Map groups to permissions
         let permissions = self.map_permissions(&groups);
@@ -352,7 +398,7 @@ impl AuthProvider for MacOSAuthProvider {
     }
 
     async fn get_permissions(&self, user: &User) -> Result<Vec<String>> {
-        let groups = self.get_user_groups(&user.username)?;
+        let groups = self.get_user_groups(&user.username).await?;
         Ok(self.map_permissions(&groups))
     }
 
@@ -1,4 +1,10 @@
+use crate::auth::cache::GroupCache;
+use crate::auth::command::{
+    check_username, is_valid_username, output_with_timeout, DEFAULT_COMMAND_TIMEOUT_SECS,
+};
+use crate::auth::improved_native::determine_role_common;
 use crate::auth::provider::AuthProvider;
+use crate::auth::user_db::{PasswdEntry, UserDatabase, DEFAULT_MIN_USER_UID};
 use crate::server::user::{User, UserRole};
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
@@ -6,9 +12,16 @@ use log::{debug, error, info, warn};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::Path;
-use std::process::Command;
+use std::time::Duration;
+use tokio::process::Command;
 use uuid::Uuid;
 
+/// passwd database read when `getent` is unavailable
+const PASSWD_PATH: &str = "/etc/passwd";
+
+/// group database read when `getent` is unavailable
+const GROUP_PATH: &str = "/etc/group";
+
 /// Configuration for the Unix native auth provider
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UnixAuthConfig {
@@ -24,8 +37,20 @@ pub struct UnixAuthConfig {
     /// Groups that have admin privileges
     pub admin_groups: Vec<String>,
 
+    /// Groups whose members are regular users even when `default_role` is lower
+    pub user_groups: Vec<String>,
+
+    /// Role of users in neither an admin group nor a user group
+    pub default_role: UserRole,
+
     /// Custom permission mappings (group -> permission)
     pub permission_mappings: HashMap<String, Vec<String>>,
+
+    /// Seconds allowed for each directory lookup command before it is killed
+    pub command_timeout_secs: u64,
+
+    /// Lowest UID listed as a regular user; system accounts sit below it
+    pub min_user_uid: u32,
 }
 
 impl Default for UnixAuthConfig {
@@ -42,7 +67,11 @@ impl Default for UnixAuthConfig {
                 "sys".to_string(),      // SunOS, Solaris
                 "staff".to_string(),    // Some Unix variants
             ],
+            user_groups: Vec::new(),
+            default_role: UserRole::User,
             permission_mappings: HashMap::new(),
+            command_timeout_secs: DEFAULT_COMMAND_TIMEOUT_SECS,
+            min_user_uid: DEFAULT_MIN_USER_UID,
         }
     }
 }
@@ -56,7 +85,10 @@ pub struct UnixAuthProvider {
     user_cache: HashMap<String, User>,
 
     /// Cache of group memberships
-    group_cache: HashMap<String, Vec<String>>,
+    group_cache: GroupCache,
+
+    /// Whether `initialize` has been called
+    initialized: bool,
 }
 
 impl UnixAuthProvider {
@@ -65,37 +97,38 @@ impl UnixAuthProvider {
         Self {
             config,
             user_cache: HashMap::new(),
-            group_cache: HashMap::new(),
+            group_cache: GroupCache::new(),
+            initialized: false,
         }
     }
 
-    /// Check if a user is a member of a group
-    fn is_member_of_group(&self, username: &str, group: &str) -> Result<bool> {
-        // Check if cached
-        if let Some(groups) = self.group_cache.get(username) {
-            return Ok(groups.contains(&group.to_string()));
-        }
-
-        // Use standard Unix commands that work across most Unix variants
-        let output = Command::new("groups").arg(username).output()?;
-
-        if !output.status.success() {
-            return Ok(false);
-        }
+    /// Time allowed for each directory lookup command
+    fn command_timeout(&self) -> Duration {
+        Duration::from_secs(self.config.command_timeout_secs)
+    }
 
-        let output_str = String::from_utf8_lossy(&output.stdout);
-        Ok(output_str.split_whitespace().any(|g| g == group))
+    /// Check if a user is a member of a group
+    async fn is_member_of_group(&self, username: &str, group: &str) -> Result<bool> {
+        self.group_cache
+            .is_member(username, group, || self.fetch_user_groups(username))
+            .await
     }
 
     /// Get all groups a user belongs to
-    fn get_user_groups(&self, username: &str) -> Result<Vec<String>> {
-        // Check if cached
-        if let Some(groups) = self.group_cache.get(username) {
-            return Ok(groups.clone());
-        }
+    async fn get_user_groups(&self, username: &str) -> Result<Vec<String>> {
+        self.group_cache
+            .get_or_fetch(username, || self.fetch_user_groups(username))
+            .await
+    }
+
+    /// Look up all groups a user belongs to, bypassing the cache
+    async fn fetch_user_groups(&self, username: &str) -> Result<Vec<String>> {
+        check_username(username)?;
 
         // Generic approach that works on most Unix systems
-        let output = Command::new("groups").arg(username).output()?;
+        let output =
+            output_with_timeout(Command::new("groups").arg(username), self.command_timeout())
+                .await?;
 
         if !output.status.success() {
             return Err(anyhow!("Failed to list groups"));
@@ -116,14 +149,6 @@ impl UnixAuthProvider {
             groups.push(group.to_string());
         }
 
-        // Save to cache
-        let cache_key = username.to_string();
-        let groups_clone = groups.clone();
-
-        // Update cache in a way that doesn't require mutable self
-        let mut cache = self.group_cache.clone();
-        cache.insert(cache_key, groups_clone);
-
         Ok(groups)
     }
 
@@ -168,52 +193,146 @@ impl UnixAuthProvider {
     }
 
     /// Validate credentials using basic Unix mechanisms
-    fn validate_system_credentials(&self, username: &str, _password: &[u8]) -> Result<bool> {
+    async fn validate_system_credentials(&self, username: &str, _password: &[u8]) -> Result<bool> {
         // This is a simplified version for demonstration
         // In a real implementation, you would use PAM or similar for authentication
 
         // For now, just check if the user exists
-        let output = Command::new("id").arg(username).output()?;
+        let output =
+            output_with_timeout(Command::new("id").arg(username), self.command_timeout()).await?;
 
         Ok(output.status.success())
     }
+
+    /// Read a whole NSS database such as `passwd` or `group`
+    ///
+    /// Not every Unix ships getent, so fall back to reading the local file.
+    async fn read_database(&self, database: &str, path: &str) -> Result<String> {
+        let getent =
+            output_with_timeout(Command::new("getent").arg(database), self.command_timeout()).await;
+
+        match getent {
+            Ok(output) if output.status.success() => {
+                return Ok(String::from_utf8_lossy(&output.stdout).into_owned());
+            }
+            Ok(output) => debug!(
+                "getent {} exited with {}, reading {}",
+                database, output.status, path
+            ),
+            Err(e) => debug!("getent unavailable ({}), reading {}", e, path),
+        }
+
+        tokio::fs::read_to_string(path)
+            .await
+            .map_err(|e| anyhow!("Failed to read {}: {}", path, e))
+    }
+}
+
+/// Whether a passwd entry belongs to a regular account rather than a system one
+fn is_regular_user(entry: &PasswdEntry, min_uid: u32) -> bool {
+    entry.uid >= min_uid
+        && !entry.username.starts_with('_')
+        && entry.username != "nobody"
+        && entry.username != "root"
+}
+
+/// Build users for every regular account from passwd and group data
+///
+/// Group memberships come from the same snapshot, so no per-user lookups are needed.
+pub fn users_from_database(config: &UnixAuthConfig, passwd: &str, group: &str) -> Vec<User> {
+    let database = UserDatabase::new(passwd, group);
+
+    database
+        .users()
+        .iter()
+        .filter(|entry| is_regular_user(entry, config.min_user_uid))
+        .map(|entry| {
+            build_user(
+                config,
+                &entry.username,
+                entry.display_name(),
+                database.groups(&entry.username),
+            )
+        })
+        .collect()
+}
+
+/// Build a user object from its display name and groups
+fn build_user(
+    config: &UnixAuthConfig,
+    username: &str,
+    display_name: String,
+    groups: &[String],
+) -> User {
+    // Determine role based on group membership
+    let role = determine_role_common(
+        groups,
+        &config.admin_groups,
+        &config.user_groups,
+        &config.default_role,
+    );
+
+    User {
+        id: Uuid::new_v4(),
+        username: username.to_string(),
+        full_name: Some(display_name),
+        email: None, // Unix systems don't have email in user DB by default
+        role,
+        password_hash: "".to_string(), // We don't store passwords
+        created_at: "1970-01-01T00:00:00Z".to_string(), // Not tracked, use epoch
+        updated_at: "1970-01-01T00:00:00Z".to_string(), // Not tracked, use epoch
+    }
 }
 
 #[async_trait]
 impl AuthProvider for UnixAuthProvider {
     async fn initialize(&mut self) -> Result<()> {
+        if self.initialized {
+            return Ok(());
+        }
         info!("Initializing Unix native authentication provider");
 
         // Clear caches
         self.user_cache.clear();
         self.group_cache.clear();
+        self.initialized = true;
 
         Ok(())
     }
 
+    fn is_initialized(&self) -> bool {
+        self.initialized
+    }
+
     async fn validate_credentials(
         &self,
         username: &str,
         credentials: &[u8],
         method: &str,
     ) -> Result<bool> {
+        self.ensure_initialized()?;
+        check_username(username)?;
+
         match method {
             "psk" => {
                 // For PSK, we just check if the user exists and is allowed
                 if !self.config.allow_all_users {
                     if let Some(ref required_group) = self.config.require_group {
-                        return Ok(self.is_member_of_group(username, required_group)?);
+                        return Ok(self.is_member_of_group(username, required_group).await?);
                     }
                 }
 
                 // Check if user exists
-                let output = Command::new("id").arg(username).output()?;
+                let output =
+                    output_with_timeout(Command::new("id").arg(username), self.command_timeout())
+                        .await?;
 
                 Ok(output.status.success())
             }
             "password" => {
                 // Validate system credentials
                 self.validate_system_credentials(username, credentials)
+                    .await
             }
             "publickey" => {
                 // For public key auth, we'd check the user's authorized_keys
@@ -226,22 +345,30 @@ impl AuthProvider for UnixAuthProvider {
     }
 
     async fn get_user_by_username(&self, username: &str) -> Result<Option<User>> {
+        // No account can have a name the lookup tools would misread
+        if !is_valid_username(username) {
+            return Ok(None);
+        }
+
         // Check if cached
         if let Some(user) = self.user_cache.get(username) {
             return Ok(Some(user.clone()));
         }
 
         // Check if user exists
-        let output = Command::new("id").arg(username).output()?;
+        let output =
+            output_with_timeout(Command::new("id").arg(username), self.command_timeout()).await?;
 
         if !output.status.success() {
             return Ok(None);
         }
 
         // Get user information (name from passwd)
-        let passwd_output = Command::new("getent")
-            .args(&["passwd", username])
-            .output()?;
+        let passwd_output = output_with_timeout(
+            Command::new("getent").args(&["passwd", username]),
+            self.command_timeout(),
+        )
+        .await?;
 
         let real_name = if passwd_output.status.success() {
             let passwd_str = String::from_utf8_lossy(&passwd_output.stdout);
@@ -256,28 +383,9 @@ impl AuthProvider for UnixAuthProvider {
         };
 
         // Get user's groups
-        let groups = self.get_user_groups(username)?;
+        let groups = self.get_user_groups(username).await?;
 
-        // Determine role based on group membership
-        let role = if groups.iter().any(|g| self.config.admin_groups.contains(g)) {
-            UserRole::Admin
-        } else {
-            UserRole::User
-        };
-
-        // Create user object
-        let user = User {
-            id: Uuid::new_v4(), // Using new_v4 instead of new_v5
-            username: username.to_string(),
-            full_name: Some(real_name),
-            email: None, // Unix systems don't have email in user DB by default
-            role,
-            password_hash: "".to_string(), // We don't store passwords
-            created_at: "1970-01-01T00:00:00Z".to_string(), // Not tracked, use epoch
-            updated_at: "1970-01-01T00:00:00Z".to_string(), // Not tracked, use epoch
-        };
-
-        Ok(Some(user))
+        Ok(Some(build_user(&self.config, username, real_name, &groups)))
     }
 
     async fn get_user(&self, id: &Uuid) -> Result<Option<User>> {
@@ -291,38 +399,20 @@ impl AuthProvider for UnixAuthProvider {
     }
 
     async fn list_users(&self) -> Result<Vec<User>> {
-        // Get all users from passwd database
-        let output = Command::new("getent").args(&["passwd"]).output()?;
-
-        if !output.status.success() {
-            return Err(anyhow!("Failed to list users"));
-        }
-
-        let output_str = String::from_utf8_lossy(&output.stdout);
-        let mut users = Vec::new();
-
-        for line in output_str.lines() {
-            let fields: Vec<&str> = line.split(':').collect();
-            if fields.len() >= 3 {
-                let username = fields[0];
-
-                // Skip system users (UID < 1000 typically)
-                let uid: u32 = fields[2].parse().unwrap_or(0);
-                if uid < 1000
-                    || username.starts_with('_')
-                    || username == "nobody"
-                    || username == "root"
-                {
-                    continue;
-                }
-
-                if let Ok(Some(user)) = self.get_user_by_username(username).await {
-                    users.push(user);
-                }
+        // Read both databases once instead of spawning lookups for every user
+        let passwd = self.read_database("passwd", PASSWD_PATH).await?;
+        let group = match self.read_database("group", GROUP_PATH).await {
+            Ok(group) => group,
+            Err(e) => {
+                warn!(
+                    "Group database unavailable, listing users without groups: {}",
+                    e
+                );
+                String::new()
             }
-        }
+        };
 
-        Ok(users)
+        Ok(users_from_database(&self.config, &passwd, &group))
     }
 
     async fn create_user(&self, _user: User) -> Result<()> {
@@ -344,8 +434,10 @@ impl AuthProvider for UnixAuthProvider {
     }
 
     async fn has_permission(&self, user: &User, permission: &str) -> Result<bool> {
+        self.ensure_initialized()?;
+
         // Get user's groups
-        let groups = self.get_user_groups(&user.username)?;
+        let groups = self.get_user_groups(&user.username).await?;
 
         // Map groups to permissions
         let permissions = self.map_permissions(&groups);
@@ -368,7 +460,7 @@ impl AuthProvider for UnixAuthProvider {
     }
 
     async fn get_permissions(&self, user: &User) -> Result<Vec<String>> {
-        let groups = self.get_user_groups(&user.username)?;
+        let groups = self.get_user_groups(&user.username).await?;
         Ok(self.map_permissions(&groups))
     }
 
@@ -1,12 +1,18 @@
+use crate::auth::cache::GroupCache;
+use crate::auth::command::{
+    check_username, is_valid_username, output_with_timeout, DEFAULT_COMMAND_TIMEOUT_SECS,
+};
+use crate::auth::improved_native::determine_role_common;
 use crate::auth::provider::AuthProvider;
 use crate::server::user::{User, UserRole};
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
+use chrono::Utc;
 use log::{info, warn};
 use serde::{Deserialize, Serialize};
-use chrono::Utc;
 use std::collections::HashMap;
-use std::process::Command;
+use std::time::Duration;
+use tokio::process::Command;
 use uuid::Uuid;
 
 /// Configuration for the Windows native auth provider
@@ -24,8 +30,17 @@ pub struct WindowsAuthConfig {
     /// Groups that have admin privileges
     pub admin_groups: Vec<String>,
 
+    /// Groups whose members are regular users even when `default_role` is lower
+    pub user_groups: Vec<String>,
+
+    /// Role of users in neither an admin group nor a user group
+    pub default_role: UserRole,
+
     /// Custom permission mappings (group -> permission)
     pub permission_mappings: HashMap<String, Vec<String>>,
+
+    /// Seconds allowed for each directory lookup command before it is killed
+    pub command_timeout_secs: u64,
 }
 
 impl Default for WindowsAuthConfig {
@@ -35,7 +50,10 @@ impl Default for WindowsAuthConfig {
             require_group: Some("RCP Users".to_string()),
             permission_mapping: true,
             admin_groups: vec!["Administrators".to_string()],
+            user_groups: Vec::new(),
+            default_role: UserRole::User,
             permission_mappings: HashMap::new(),
+            command_timeout_secs: DEFAULT_COMMAND_TIMEOUT_SECS,
         }
     }
 }
@@ -49,7 +67,10 @@ pub struct WindowsAuthProvider {
     user_cache: HashMap<String, User>,
 
     /// Cache of group memberships
-    group_cache: HashMap<String, Vec<String>>,
+    group_cache: GroupCache,
+
+    /// Whether `initialize` has been called
+    initialized: bool,
 }
 
 impl WindowsAuthProvider {
@@ -58,46 +79,40 @@ impl WindowsAuthProvider {
         Self {
             config,
             user_cache: HashMap::new(),
-            group_cache: HashMap::new(),
+            group_cache: GroupCache::new(),
+            initialized: false,
         }
     }
 
-    /// Check if a user is a member of a group using Windows commands
-    fn is_member_of_group(&self, username: &str, group: &str) -> Result<bool> {
-        // Use net user to check group membership
-        let output = Command::new("net").args(["user", username]).output()?;
-
-        if !output.status.success() {
-            return Ok(false);
-        }
-
-        // Parse the output to find group memberships
-        let output_str = String::from_utf8_lossy(&output.stdout);
-
-        // Check if the group name appears in the "Local Group Memberships" section
-        let group_section = output_str.find("Local Group Memberships");
-        if let Some(idx) = group_section {
-            let remaining = &output_str[idx..];
-            // Find the end of the group list
-            let end_idx = remaining.find("*").unwrap_or(remaining.len());
-            let groups_text = &remaining[..end_idx];
-
-            // Look for the group name
-            return Ok(groups_text.contains(group));
-        }
+    /// Time allowed for each directory lookup command
+    fn command_timeout(&self) -> Duration {
+        Duration::from_secs(self.config.command_timeout_secs)
+    }
 
-        Ok(false)
+    /// Check if a user is a member of a group
+    async fn is_member_of_group(&self, username: &str, group: &str) -> Result<bool> {
+        self.group_cache
+            .is_member(username, group, || self.fetch_user_groups(username))
+            .await
     }
 
     /// Get all groups a user belongs to
-    fn get_user_groups(&self, username: &str) -> Result<Vec<String>> {
-        // Check if cached
-        if let Some(groups) = self.group_cache.get(username) {
-            return Ok(groups.clone());
-        }
+    async fn get_user_groups(&self, username: &str) -> Result<Vec<String>> {
+        self.group_cache
+            .get_or_fetch(username, || self.fetch_user_groups(username))
+            .await
+    }
+
+    /// Look up all groups a user belongs to, bypassing the cache
+    async fn fetch_user_groups(&self, username: &str) -> Result<Vec<String>> {
+        check_username(username)?;
 
         // Use net user to get all groups
-        let output = Command::new("net").args(["user", username]).output()?;
+        let output = output_with_timeout(
+            Command::new("net").args(["user", username]),
+            self.command_timeout(),
+        )
+        .await?;
 
         if !output.status.success() {
             return Err(anyhow!("Failed to get groups for user: {}", username));
@@ -167,12 +182,16 @@ impl WindowsAuthProvider {
     }
 
     /// Validate credentials using Windows authentication
-    fn validate_system_credentials(&self, username: &str, _password: &[u8]) -> Result<bool> {
+    async fn validate_system_credentials(&self, username: &str, _password: &[u8]) -> Result<bool> {
         // This is a simplified version - in a real implementation,
         // you would use Windows authentication APIs (LogonUser, etc.)
 
         // For now, we'll just check if the user exists
-        let output = Command::new("net").args(["user", username]).output()?;
+        let output = output_with_timeout(
+            Command::new("net").args(["user", username]),
+            self.command_timeout(),
+        )
+        .await?;
 
         Ok(output.status.success())
     }
@@ -181,36 +200,48 @@ impl WindowsAuthProvider {
 #[async_trait]
 impl AuthProvider for WindowsAuthProvider {
     async fn initialize(&mut self) -> Result<()> {
+        if self.initialized {
+            return Ok(());
+        }
         info!("Initializing Windows native authentication provider");
 
         // Clear caches
         self.user_cache.clear();
         self.group_cache.clear();
+        self.initialized = true;
 
         Ok(())
     }
 
+    fn is_initialized(&self) -> bool {
+        self.initialized
+    }
+
     async fn validate_credentials(
         &self,
         username: &str,
         credentials: &[u8],
         method: &str,
     ) -> Result<bool> {
+        self.ensure_initialized()?;
+        check_username(username)?;
+
         match method {
             "psk" => {
                 // For PSK, we just check if the user exists and is allowed
                 if !self.config.allow_all_users {
                     if let Some(required_group) = &self.config.require_group {
-                        return self.is_member_of_group(username, required_group);
+                        return self.is_member_of_group(username, required_group).await;
                     }
                 }
 
                 // Check if user exists
-                self.validate_system_credentials(username, &[])
+                self.validate_system_credentials(username, &[]).await
             }
             "password" => {
                 // Validate system credentials
                 self.validate_system_credentials(username, credentials)
+                    .await
             }
             "publickey" => {
                 // Not implemented for Windows yet
@@ -222,13 +253,22 @@ impl AuthProvider for WindowsAuthProvider {
     }
 
     async fn get_user_by_username(&self, username: &str) -> Result<Option<User>> {
+        // No account can have a name the lookup tools would misread
+        if !is_valid_username(username) {
+            return Ok(None);
+        }
+
         // Check if cached
         if let Some(user) = self.user_cache.get(username) {
             return Ok(Some(user.clone()));
         }
 
         // Check if user exists
-        let output = Command::new("net").args(["user", username]).output()?;
+        let output = output_with_timeout(
+            Command::new("net").args(["user", username]),
+            self.command_timeout(),
+        )
+        .await?;
 
         if !output.status.success() {
             return Ok(None);
@@ -251,14 +291,15 @@ impl AuthProvider for WindowsAuthProvider {
         };
 
         // Get user's groups
-        let groups = self.get_user_groups(username)?;
+        let groups = self.get_user_groups(username).await?;
 
         // Determine role based on group membership
-        let role = if groups.iter().any(|g| self.config.admin_groups.contains(g)) {
-            UserRole::Admin
-        } else {
-            UserRole::User
-        };
+        let role = determine_role_common(
+            &groups,
+            &self.config.admin_groups,
+            &self.config.user_groups,
+            &self.config.default_role,
+        );
 
         // Create user object
         let user = User {
@@ -287,7 +328,8 @@ impl AuthProvider for WindowsAuthProvider {
 
     async fn list_users(&self) -> Result<Vec<User>> {
         // Get all users using Windows commands
-        let output = Command::new("net").args(["user"]).output()?;
+        let output =
+            output_with_timeout(Command::new("net").args(["user"]), self.command_timeout()).await?;
 
         if !output.status.success() {
             return Err(anyhow!("Failed to list users"));
@@ -335,8 +377,10 @@ impl AuthProvider for WindowsAuthProvider {
     }
 
     async fn has_permission(&self, user: &User, permission: &str) -> Result<bool> {
+        self.ensure_initialized()?;
+
         // Get user's groups
-        let groups = self.get_user_groups(&user.username)?;
+        let groups = self.get_user_groups(&user.username).await?;
 
         // Map groups to permissions
         let permissions = self.map_permissions(&groups);
@@ -359,7 +403,7 @@ impl AuthProvider for WindowsAuthProvider {
     }
 
     async fn get_permissions(&self, user: &User) -> Result<Vec<String>> {
-        let groups = self.get_user_groups(&user.username)?;
+        let groups = self.get_user_groups(&user.username).await?;
         Ok(self.map_permissions(&groups))
     }
 
@@ -3,16 +3,39 @@ use anyhow::Result;
 use async_trait::async_trait;
 use uuid::Uuid;
 
+/// A provider was asked to authenticate before it was initialized
+#[derive(Debug, thiserror::Error)]
+#[error("Authentication provider '{0}' used before it was initialized")]
+pub struct NotInitialized(pub String);
+
 /// Authentication provider interface for RCP
 ///
 /// This trait defines the contract that all authentication providers must fulfill.
 /// Implementations can use internal user databases, OS-native authentication,
 /// or external identity providers.
+///
+/// [`validate_credentials`](Self::validate_credentials) and
+/// [`has_permission`](Self::has_permission) fail with [`NotInitialized`]
+/// until [`initialize`](Self::initialize) has been called.
 #[async_trait]
 pub trait AuthProvider: Send + Sync {
     /// Initialize the authentication provider
+    ///
+    /// Calling it again once it has succeeded does nothing.
     async fn initialize(&mut self) -> Result<()>;
 
+    /// Whether [`initialize`](Self::initialize) has succeeded
+    fn is_initialized(&self) -> bool;
+
+    /// Fail with [`NotInitialized`] unless the provider has been initialized
+    fn ensure_initialized(&self) -> Result<()> {
+        if self.is_initialized() {
+            Ok(())
+        } else {
+            Err(NotInitialized(self.name().to_string()).into())
+        }
+    }
+
     /// Validate credentials for a user
     async fn validate_credentials(
         &self,
@@ -0,0 +1,168 @@
+//! Parsed passwd and group databases
+//!
+//! Listing users one `getent`/`groups` call at a time spawns several processes
+//! per account. The native providers instead read both databases once and
+//! resolve every user's groups from this in-memory snapshot.
+
+use std::collections::HashMap;
+
+/// Default lowest UID of regular (non-system) accounts
+pub const DEFAULT_MIN_USER_UID: u32 = 1000;
+
+/// A single passwd entry ("name:x:uid:gid:gecos:home:shell")
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PasswdEntry {
+    /// Login name
+    pub username: String,
+
+    /// Numeric user ID
+    pub uid: u32,
+
+    /// Primary group ID
+    pub gid: u32,
+
+    /// GECOS field (full name, room, phone, ...)
+    pub gecos: String,
+}
+
+impl PasswdEntry {
+    /// Full name from the GECOS field, falling back to the username
+    pub fn display_name(&self) -> String {
+        match self.gecos.split(',').next() {
+            Some(name) if !name.trim().is_empty() => name.trim().to_string(),
+            _ => self.username.clone(),
+        }
+    }
+}
+
+/// A single group entry ("name:x:gid:member1,member2")
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GroupEntry {
+    /// Group name
+    pub name: String,
+
+    /// Numeric group ID
+    pub gid: u32,
+
+    /// Supplementary members
+    pub members: Vec<String>,
+}
+
+/// Lines of a passwd/group database that carry an entry
+fn entry_lines(contents: &str) -> impl Iterator<Item = &str> {
+    contents
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+}
+
+/// Parse passwd-format data (`getent passwd` output or `/etc/passwd`)
+///
+/// Malformed entries are skipped.
+pub fn parse_passwd(contents: &str) -> Vec<PasswdEntry> {
+    entry_lines(contents)
+        .filter_map(|line| {
+            let parts: Vec<&str> = line.split(':').collect();
+            if parts.len() < 4 {
+                return None;
+            }
+
+            Some(PasswdEntry {
+                username: parts[0].to_string(),
+                uid: parts[2].parse().ok()?,
+                gid: parts[3].parse().ok()?,
+                gecos: parts.get(4).unwrap_or(&"").to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Parse group-format data (`getent group` output or `/etc/group`)
+///
+/// Malformed entries are skipped.
+pub fn parse_group(contents: &str) -> Vec<GroupEntry> {
+    entry_lines(contents)
+        .filter_map(|line| {
+            let parts: Vec<&str> = line.split(':').collect();
+            if parts.len() < 3 {
+                return None;
+            }
+
+            let members = parts
+                .get(3)
+                .unwrap_or(&"")
+                .split(',')
+                .map(|m| m.trim())
+                .filter(|m| !m.is_empty())
+                .map(|m| m.to_string())
+                .collect();
+
+            Some(GroupEntry {
+                name: parts[0].to_string(),
+                gid: parts[2].parse().ok()?,
+                members,
+            })
+        })
+        .collect()
+}
+
+/// Snapshot of the passwd and group databases with group memberships resolved
+#[derive(Debug, Clone, Default)]
+pub struct UserDatabase {
+    users: Vec<PasswdEntry>,
+    groups: HashMap<String, Vec<String>>,
+}
+
+impl UserDatabase {
+    /// Build a snapshot from passwd and group data
+    ///
+    /// Each user's groups are their primary group followed by every group
+    /// listing them as a member, matching the order `groups <user>` prints.
+    pub fn new(passwd: &str, group: &str) -> Self {
+        let users = parse_passwd(passwd);
+        let group_entries = parse_group(group);
+
+        let group_names: HashMap<u32, &str> = group_entries
+            .iter()
+            .map(|g| (g.gid, g.name.as_str()))
+            .collect();
+
+        let mut groups: HashMap<String, Vec<String>> = HashMap::with_capacity(users.len());
+        for user in &users {
+            let primary = group_names.get(&user.gid).map(|name| name.to_string());
+            groups
+                .entry(user.username.clone())
+                .or_default()
+                .extend(primary);
+        }
+
+        for entry in &group_entries {
+            for member in &entry.members {
+                let member_groups = groups.entry(member.clone()).or_default();
+                if !member_groups.contains(&entry.name) {
+                    member_groups.push(entry.name.clone());
+                }
+            }
+        }
+
+        Self { users, groups }
+    }
+
+    /// All passwd entries
+    pub fn users(&self) -> &[PasswdEntry] {
+        &self.users
+    }
+
+    /// Entries of regular accounts, i.e. with a UID of at least `min_uid`
+    pub fn regular_users(&self, min_uid: u32) -> impl Iterator<Item = &PasswdEntry> {
+        self.users.iter().filter(move |user| user.uid >= min_uid)
+    }
+
+    /// Groups a user belongs to, empty if the user is unknown
+    pub fn groups(&self, username: &str) -> &[String] {
+        self.groups
+            .get(username)
+            .map(|groups| groups.as_slice())
+            .unwrap_or(&[])
+    }
+}
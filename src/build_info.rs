@@ -0,0 +1,37 @@
+//! Build metadata
+//!
+//! Version information recorded at compile time by `build.rs`, shared by
+//! everything that reports which build of the daemon is running.
+
+use serde::{Deserialize, Serialize};
+
+/// Crate version of this build
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Metadata describing this build of the daemon
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BuildInfo {
+    /// Crate version
+    pub version: String,
+
+    /// Abbreviated git commit the build was made from, if known
+    pub git_commit: Option<String>,
+
+    /// Target triple the build was compiled for, if known
+    pub target: Option<String>,
+
+    /// Cargo profile (`debug` or `release`), if known
+    pub profile: Option<String>,
+}
+
+impl BuildInfo {
+    /// Metadata for the running binary
+    pub fn current() -> Self {
+        Self {
+            version: VERSION.to_string(),
+            git_commit: option_env!("RCPDAEMON_GIT_COMMIT").map(str::to_string),
+            target: option_env!("RCPDAEMON_BUILD_TARGET").map(str::to_string),
+            profile: option_env!("RCPDAEMON_BUILD_PROFILE").map(str::to_string),
+        }
+    }
+}
@@ -11,13 +11,20 @@ use clap::Parser;
 use colored::Colorize;
 #[cfg(feature = "cli")]
 use std::fmt::Display;
+#[cfg(feature = "cli")]
+use std::time::Duration;
 
 #[cfg(feature = "cli")]
 use crate::cli::{
+    service::{AppLogLine, ServiceClient},
     types::{AppCommand, Cli},
     utils::OutputFormatter,
 };
 
+/// How often `app logs --follow` polls for new output
+#[cfg(feature = "cli")]
+const LOG_FOLLOW_INTERVAL: Duration = Duration::from_millis(500);
+
 /// Application representation
 #[cfg(feature = "cli")]
 #[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
@@ -60,91 +67,244 @@ impl Display for Application {
 
 /// Handle the app command
 #[cfg(feature = "cli")]
-pub async fn handle_app_command(cli: &mut Cli, command: &AppCommand) -> Result<()> {
+pub async fn handle_app_command(
+    cli: &mut Cli,
+    command: &AppCommand,
+    client: &ServiceClient,
+) -> Result<()> {
     match command {
-        AppCommand::List => list_applications(cli, None).await,
-        AppCommand::Info { app_id } => show_application(cli, app_id).await,
+        AppCommand::List => list_applications(cli, client).await,
+        AppCommand::Info { app_id } => show_application(cli, client, app_id).await,
         AppCommand::Launch {
             app_id,
             user_id,
             args,
-        } => {
-            // TODO: Implement app launch functionality
-            let formatter = OutputFormatter::new(cli.json, true, false);
-            formatter.output_success(&format!("Launching app '{}' with args: {:?}", app_id, args));
-            Ok(())
+        } => launch_application(cli, client, app_id, user_id.as_deref(), args).await,
+        AppCommand::Instances => list_instances(cli, client).await,
+        AppCommand::Stop { instance_id } => stop_instance(cli, client, instance_id).await,
+        AppCommand::Logs {
+            instance_id,
+            lines,
+            follow,
+        } => show_instance_logs(cli, client, instance_id, *lines, *follow).await,
+    }
+}
+
+/// Show recent output of an application instance, optionally following it
+#[cfg(feature = "cli")]
+async fn show_instance_logs(
+    cli: &Cli,
+    client: &ServiceClient,
+    instance_id: &str,
+    lines: usize,
+    follow: bool,
+) -> Result<()> {
+    let formatter = crate::cli::output_formatter(cli);
+
+    let logs = client.get_app_logs(instance_id, lines, None).await?;
+    print_log_lines(&formatter, &logs.lines);
+
+    if !follow {
+        return Ok(());
+    }
+
+    // Poll for lines newer than the last one seen until the instance goes away
+    let mut next_seq = logs.next_seq;
+    loop {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => return Ok(()),
+            _ = tokio::time::sleep(LOG_FOLLOW_INTERVAL) => {}
         }
-        AppCommand::Instances => {
-            // TODO: Implement list instances functionality
-            let formatter = OutputFormatter::new(cli.json, true, false);
-            formatter.info("Listing application instances...");
-            Ok(())
+
+        match client
+            .get_app_logs(instance_id, usize::MAX, Some(next_seq))
+            .await
+        {
+            Ok(logs) => {
+                print_log_lines(&formatter, &logs.lines);
+                next_seq = logs.next_seq;
+            }
+            Err(e) => {
+                formatter.info(&format!(
+                    "Stopped following instance '{}': {}",
+                    instance_id, e
+                ));
+                return Ok(());
+            }
         }
-        AppCommand::Stop { instance_id } => {
-            // TODO: Implement stop instance functionality
-            let formatter = OutputFormatter::new(cli.json, true, false);
-            formatter.output_success(&format!("Stopped instance '{}'", instance_id));
-            Ok(())
+    }
+}
+
+/// Print captured output lines, one JSON object per line in JSON mode
+#[cfg(feature = "cli")]
+fn print_log_lines(formatter: &OutputFormatter, lines: &[AppLogLine]) {
+    for line in lines {
+        if formatter.json_output {
+            if let Ok(json) = serde_json::to_string(line) {
+                println!("{}", json);
+            }
+        } else {
+            let timestamp = formatter.paint(&line.timestamp, |s| s.dimmed());
+            if line.stream == "stderr" {
+                println!("{} {}", timestamp, formatter.paint(&line.line, |s| s.red()));
+            } else {
+                println!("{} {}", timestamp, line.line);
+            }
         }
     }
 }
 
 /// List available applications
 #[cfg(feature = "cli")]
-async fn list_applications(cli: &mut Cli, filter: Option<&str>) -> Result<()> {
-    let formatter = OutputFormatter::new(cli.json, true, false);
-
-    // TODO: Implement service client request to get applications
-    let applications = vec![
-        // This is just sample data - replace with actual service client call
-        Application {
-            id: "app1".to_string(),
-            name: "Sample App 1".to_string(),
-            path: "/usr/bin/sample1".to_string(),
-            arguments: Some(vec!["-v".to_string()]),
-            working_dir: None,
-            enabled: true,
-        },
-        Application {
-            id: "app2".to_string(),
-            name: "Sample App 2".to_string(),
-            path: "/usr/bin/sample2".to_string(),
-            arguments: None,
-            working_dir: Some("/tmp".to_string()),
-            enabled: false,
-        },
-    ];
-
-    let filtered = if let Some(filter_text) = filter {
-        applications
-            .into_iter()
-            .filter(|app| app.name.contains(filter_text) || app.id.contains(filter_text))
-            .collect::<Vec<_>>()
-    } else {
-        applications
-    };
+async fn list_applications(cli: &Cli, client: &ServiceClient) -> Result<()> {
+    let formatter = crate::cli::output_formatter(cli);
+    let apps = client.list_apps().await?;
 
-    let _ = formatter.output_list(&filtered, "Applications", "No applications found");
+    if formatter.json_output {
+        formatter
+            .json(&apps)
+            .unwrap_or_else(|e| formatter.error(&format!("Failed to format applications: {}", e)));
+        return Ok(());
+    }
+
+    if apps.is_empty() {
+        formatter.info("No applications found");
+        return Ok(());
+    }
+
+    formatter.table(
+        vec!["ID", "Name", "Category", "Permission", "Arguments"],
+        |table| {
+            for app in &apps {
+                table.add_row(vec![
+                    app.id.as_str(),
+                    app.name.as_str(),
+                    app.category.as_deref().unwrap_or("-"),
+                    app.required_permission.as_deref().unwrap_or("-"),
+                    if app.accepts_args { "yes" } else { "no" },
+                ]);
+            }
+        },
+    );
     Ok(())
 }
 
 /// Show application details
 #[cfg(feature = "cli")]
-async fn show_application(cli: &mut Cli, id: &str) -> Result<()> {
-    let formatter = OutputFormatter::new(cli.json, true, false);
+async fn show_application(cli: &Cli, client: &ServiceClient, id: &str) -> Result<()> {
+    let formatter = crate::cli::output_formatter(cli);
+    let app = client.get_app_info(id).await?;
 
-    // TODO: Implement service client request to get application by ID
-    // This is just sample data - replace with actual service client call
-    let application = Application {
-        id: id.to_string(),
-        name: "Sample App".to_string(),
-        path: "/usr/bin/sample".to_string(),
-        arguments: Some(vec!["-v".to_string()]),
-        working_dir: None,
-        enabled: true,
-    };
+    if formatter.json_output {
+        formatter
+            .json(&app)
+            .unwrap_or_else(|e| formatter.error(&format!("Failed to format application: {}", e)));
+        return Ok(());
+    }
 
-    let _ = formatter.output_item(&application, "Application Details");
+    formatter.info(&format!("ID:         {}", app.id));
+    formatter.info(&format!("Name:       {}", app.name));
+    formatter.info(&format!("Executable: {}", app.executable_path));
+    formatter.info(&format!(
+        "Category:   {}",
+        app.category.as_deref().unwrap_or("none")
+    ));
+    formatter.info(&format!(
+        "Permission: {}",
+        app.required_permission.as_deref().unwrap_or("none")
+    ));
+    formatter.info(&format!(
+        "Arguments:  {}",
+        if app.accepts_args {
+            "accepted"
+        } else {
+            "not accepted"
+        }
+    ));
+    Ok(())
+}
+
+/// Launch an application for the CLI's session
+#[cfg(feature = "cli")]
+async fn launch_application(
+    cli: &Cli,
+    client: &ServiceClient,
+    app_id: &str,
+    user_id: Option<&str>,
+    args: &[String],
+) -> Result<()> {
+    let formatter = crate::cli::output_formatter(cli);
+    let args = (!args.is_empty()).then(|| args.to_vec());
+    let instance = client.launch_app(app_id, user_id, args).await?;
+
+    if formatter.json_output {
+        formatter
+            .json(&instance)
+            .unwrap_or_else(|e| formatter.error(&format!("Failed to format instance: {}", e)));
+        return Ok(());
+    }
+
+    formatter.success(&format!(
+        "Launched {} as instance {} for {}",
+        instance.app_id, instance.id, instance.user_id
+    ));
+    Ok(())
+}
+
+/// List running application instances
+#[cfg(feature = "cli")]
+async fn list_instances(cli: &Cli, client: &ServiceClient) -> Result<()> {
+    let formatter = crate::cli::output_formatter(cli);
+    let instances = client.list_app_instances().await?;
+
+    if formatter.json_output {
+        formatter
+            .json(&instances)
+            .unwrap_or_else(|e| formatter.error(&format!("Failed to format instances: {}", e)));
+        return Ok(());
+    }
+
+    if instances.is_empty() {
+        formatter.info("No running application instances");
+        return Ok(());
+    }
+
+    formatter.table(
+        vec!["ID", "Application", "User", "Status", "Started", "PID"],
+        |table| {
+            for instance in &instances {
+                let pid = instance
+                    .pid
+                    .map(|pid| pid.to_string())
+                    .unwrap_or_else(|| "-".to_string());
+                table.add_row(vec![
+                    instance.id.as_str(),
+                    instance.app_id.as_str(),
+                    instance.user_id.as_str(),
+                    instance.status.as_str(),
+                    instance.created_at.as_str(),
+                    pid.as_str(),
+                ]);
+            }
+        },
+    );
+    Ok(())
+}
+
+/// Stop a running application instance
+#[cfg(feature = "cli")]
+async fn stop_instance(cli: &Cli, client: &ServiceClient, instance_id: &str) -> Result<()> {
+    if uuid::Uuid::parse_str(instance_id).is_err() {
+        return Err(crate::cli::error::CliError::ValidationError(format!(
+            "Invalid instance ID: {} (expected a UUID)",
+            instance_id
+        ))
+        .into());
+    }
+
+    let formatter = crate::cli::output_formatter(cli);
+    client.stop_app(instance_id).await?;
+    formatter.success(&format!("Stopped instance {}", instance_id));
     Ok(())
 }
 
@@ -158,7 +318,7 @@ async fn create_application(
     working_dir: Option<&str>,
     enabled: bool,
 ) -> Result<()> {
-    let formatter = OutputFormatter::new(cli.json, true, false);
+    let formatter = crate::cli::output_formatter(cli);
 
     // TODO: Implement service client request to create application
     // This is just sample code - replace with actual service client call
@@ -187,7 +347,7 @@ async fn update_application(
     working_dir: Option<&str>,
     enabled: Option<bool>,
 ) -> Result<()> {
-    let formatter = OutputFormatter::new(cli.json, true, false);
+    let formatter = crate::cli::output_formatter(cli);
 
     // TODO: Implement service client request to update application
     // This is just sample code - replace with actual service client call
@@ -224,7 +384,7 @@ async fn update_application(
 /// Delete an application
 #[cfg(feature = "cli")]
 async fn delete_application(cli: &mut Cli, id: &str) -> Result<()> {
-    let formatter = OutputFormatter::new(cli.json, true, false);
+    let formatter = crate::cli::output_formatter(cli);
 
     // TODO: Implement service client request to delete application
     // This is just sample code - replace with actual service client call
@@ -236,7 +396,7 @@ async fn delete_application(cli: &mut Cli, id: &str) -> Result<()> {
 /// Enable or disable an application
 #[cfg(feature = "cli")]
 async fn set_application_status(cli: &mut Cli, id: &str, enabled: bool) -> Result<()> {
-    let formatter = OutputFormatter::new(cli.json, true, false);
+    let formatter = crate::cli::output_formatter(cli);
 
     // TODO: Implement service client request to enable/disable application
     // This is just sample code - replace with actual service client call
@@ -0,0 +1,225 @@
+//! Command module for authentication tokens
+//!
+//! This module contains the command handlers for inspecting the CLI's own
+//! auth token and for issuing, listing and revoking the auth tokens of a
+//! daemon. Managing tokens requires admin permission. Listing the auth
+//! providers this platform supports needs no daemon.
+
+#[cfg(feature = "cli")]
+use anyhow::Result;
+
+#[cfg(feature = "cli")]
+use crate::cli::service::ServiceClient;
+#[cfg(feature = "cli")]
+use crate::cli::utils::OutputFormatter;
+
+/// Format used for token timestamps in tables
+#[cfg(feature = "cli")]
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S UTC";
+
+/// Handle the whoami command
+#[cfg(feature = "cli")]
+pub async fn handle_whoami(client: &ServiceClient, formatter: &OutputFormatter) -> Result<()> {
+    if client.client().auth_token.is_none() {
+        formatter.warning(&format!(
+            "No auth token configured; set --token, {} or service.token in the CLI config",
+            crate::cli::service::TOKEN_ENV_VAR
+        ));
+        return Ok(());
+    }
+
+    let identity = client.whoami().await?;
+
+    if formatter.json_output {
+        formatter
+            .json(&identity)
+            .unwrap_or_else(|e| formatter.error(&format!("Failed to format identity: {}", e)));
+        return Ok(());
+    }
+
+    let role = identity
+        .role
+        .as_ref()
+        .map(|role| format!("{:?}", role).to_lowercase())
+        .unwrap_or_else(|| "unknown".to_string());
+    formatter.info(&format!(
+        "User:        {}",
+        identity.user.as_deref().unwrap_or("operator")
+    ));
+    formatter.info(&format!("Role:        {}", role));
+    formatter.info(&format!("Permissions: {}", identity.permissions.join(", ")));
+    match (identity.token_id, identity.expires_at) {
+        (Some(jti), Some(expires_at)) => {
+            formatter.info(&format!("Token:       {}", jti));
+            formatter.info(&format!("Expires:     {}", expires_at.format(TIME_FORMAT)));
+        }
+        _ => formatter.info("Token:       none (authenticated as the operator)"),
+    }
+
+    Ok(())
+}
+
+/// Handle the token list command
+#[cfg(feature = "cli")]
+pub async fn handle_list_tokens(client: &ServiceClient, formatter: &OutputFormatter) -> Result<()> {
+    let tokens = client.list_tokens().await?;
+
+    if formatter.json_output {
+        formatter
+            .json(&tokens)
+            .unwrap_or_else(|e| formatter.error(&format!("Failed to format tokens: {}", e)));
+        return Ok(());
+    }
+
+    if tokens.is_empty() {
+        formatter.info("No issued tokens");
+        return Ok(());
+    }
+
+    formatter.table(
+        vec!["ID", "Subject", "Issued", "Expires", "Status"],
+        |table| {
+            for token in &tokens {
+                let jti = token.jti.to_string();
+                let issued = token.issued_at.format(TIME_FORMAT).to_string();
+                let expires = token.expires_at.format(TIME_FORMAT).to_string();
+                table.add_row(vec![
+                    jti.as_str(),
+                    token.subject.as_str(),
+                    issued.as_str(),
+                    expires.as_str(),
+                    if token.revoked { "revoked" } else { "active" },
+                ]);
+            }
+        },
+    );
+
+    Ok(())
+}
+
+/// Handle the token issue command
+#[cfg(feature = "cli")]
+pub async fn handle_issue_token(
+    subject: &str,
+    permissions: Vec<String>,
+    ttl_secs: u64,
+    client: &ServiceClient,
+    formatter: &OutputFormatter,
+) -> Result<()> {
+    let issued = client
+        .issue_token(
+            subject,
+            permissions,
+            std::time::Duration::from_secs(ttl_secs),
+        )
+        .await?;
+
+    if formatter.json_output {
+        formatter
+            .json(&issued)
+            .unwrap_or_else(|e| formatter.error(&format!("Failed to format token: {}", e)));
+        return Ok(());
+    }
+
+    formatter.success(&format!(
+        "Issued token {} to {} (expires {})",
+        issued.info.jti,
+        issued.info.subject,
+        issued.info.expires_at.format(TIME_FORMAT)
+    ));
+    formatter.info(&issued.token);
+    Ok(())
+}
+
+/// Handle the token revoke command
+#[cfg(feature = "cli")]
+pub async fn handle_revoke_token(
+    jti: &str,
+    client: &ServiceClient,
+    formatter: &OutputFormatter,
+) -> Result<()> {
+    if uuid::Uuid::parse_str(jti).is_err() {
+        return Err(crate::cli::error::CliError::ValidationError(format!(
+            "Invalid token ID: {} (expected a UUID)",
+            jti
+        ))
+        .into());
+    }
+
+    let token = client.revoke_token(jti).await?;
+
+    if formatter.json_output {
+        formatter
+            .json(&token)
+            .unwrap_or_else(|e| formatter.error(&format!("Failed to format token: {}", e)));
+        return Ok(());
+    }
+
+    formatter.success(&format!(
+        "Revoked token {} of {} (it would have expired {})",
+        token.jti,
+        token.subject,
+        token.expires_at.format(TIME_FORMAT)
+    ));
+    Ok(())
+}
+
+/// A provider type as shown by `auth providers`
+#[cfg(feature = "cli")]
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ProviderSupport {
+    /// Value of the `provider` setting
+    pub provider: &'static str,
+
+    /// What the provider authenticates against
+    pub description: &'static str,
+
+    /// Whether the provider can be used today rather than being a stub
+    pub implemented: bool,
+}
+
+/// Provider types this platform supports, as listed by `auth providers`
+#[cfg(feature = "cli")]
+pub fn provider_support() -> Vec<ProviderSupport> {
+    crate::auth::factory::AuthProviderFactory::available_providers()
+        .into_iter()
+        .map(|(provider, implemented)| ProviderSupport {
+            provider: provider.as_str(),
+            description: provider.description(),
+            implemented,
+        })
+        .collect()
+}
+
+/// Handle the providers command
+#[cfg(feature = "cli")]
+pub fn handle_list_providers(formatter: &OutputFormatter) -> Result<()> {
+    let providers = provider_support();
+
+    if formatter.json_output {
+        formatter
+            .json(&providers)
+            .unwrap_or_else(|e| formatter.error(&format!("Failed to format providers: {}", e)));
+        return Ok(());
+    }
+
+    formatter.table(vec!["Provider", "Status", "Description"], |table| {
+        for support in &providers {
+            table.add_row(vec![
+                support.provider,
+                if support.implemented {
+                    "available"
+                } else {
+                    "not implemented"
+                },
+                support.description,
+            ]);
+        }
+    });
+    formatter.info(&format!(
+        "Providers for {}; set one as auth.provider in the daemon config",
+        std::env::consts::OS
+    ));
+
+    Ok(())
+}
@@ -7,55 +7,74 @@ use anyhow::Result;
 #[cfg(feature = "cli")]
 use clap::CommandFactory;
 #[cfg(feature = "cli")]
-use std::path::Path;
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "cli")]
+use crate::cli::error::CliError;
+#[cfg(feature = "cli")]
+use crate::cli::utils::OutputFormatter;
+
+/// Where `completions` wrote a script, as reported in JSON mode
+#[cfg(feature = "cli")]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct CompletionsWritten {
+    pub shell: String,
+    pub path: PathBuf,
+    pub bytes: usize,
+}
 
 /// Handle completions command
+///
+/// Without `dir` the script is printed as is, whatever the output format, so
+/// it can be piped or sourced. With it the script is written to a file named
+/// after the shell and the formatter reports where. Returns the length of the
+/// script, so callers can check something was generated.
 #[cfg(feature = "cli")]
-pub fn handle_completions_command(shell: clap_complete::Shell, dir: Option<&Path>) -> Result<()> {
+pub fn handle_completions_command(
+    shell: clap_complete::Shell,
+    dir: Option<&Path>,
+    formatter: &OutputFormatter,
+) -> Result<usize> {
     // Get command from clap
     let _cmd = crate::cli::types::Cli::command();
 
     // Due to version mismatch between clap 3.x and clap_complete 4.x,
     // we use a simpler approach to generate completions
-    if let Some(dir) = dir {
-        match shell {
-            clap_complete::Shell::Bash => {
-                let path = dir.join("rcpdaemon.bash");
-                std::fs::write(&path, generate_bash_completions())?;
-                println!("Bash completions written to: {}", path.display());
-            }
-            clap_complete::Shell::Zsh => {
-                let path = dir.join("rcpdaemon.zsh");
-                std::fs::write(&path, generate_zsh_completions())?;
-                println!("Zsh completions written to: {}", path.display());
-            }
-            clap_complete::Shell::Fish => {
-                let path = dir.join("rcpdaemon.fish");
-                std::fs::write(&path, generate_fish_completions())?;
-                println!("Fish completions written to: {}", path.display());
-            }
-            _ => {
-                println!("Completions for {:?} are not currently supported", shell);
-            }
+    let (name, extension, script) = match shell {
+        clap_complete::Shell::Bash => ("Bash", "bash", generate_bash_completions()),
+        clap_complete::Shell::Zsh => ("Zsh", "zsh", generate_zsh_completions()),
+        clap_complete::Shell::Fish => ("Fish", "fish", generate_fish_completions()),
+        _ => {
+            return Err(CliError::ValidationError(format!(
+                "Completions for {} are not currently supported",
+                shell
+            ))
+            .into())
         }
+    };
+
+    let Some(dir) = dir else {
+        println!("{}", script);
+        return Ok(script.len());
+    };
+
+    let path = dir.join(format!("rcpdaemon.{}", extension));
+    std::fs::write(&path, &script)?;
+    if formatter.json_output {
+        formatter.json(CompletionsWritten {
+            shell: shell.to_string(),
+            path,
+            bytes: script.len(),
+        })?;
     } else {
-        match shell {
-            clap_complete::Shell::Bash => {
-                println!("{}", generate_bash_completions());
-            }
-            clap_complete::Shell::Zsh => {
-                println!("{}", generate_zsh_completions());
-            }
-            clap_complete::Shell::Fish => {
-                println!("{}", generate_fish_completions());
-            }
-            _ => {
-                println!("Completions for {:?} are not currently supported", shell);
-            }
-        }
+        formatter.success(&format!(
+            "{} completions written to: {}",
+            name,
+            path.display()
+        ));
     }
 
-    Ok(())
+    Ok(script.len())
 }
 
 /// Generate bash completions
@@ -290,7 +309,7 @@ complete -c rcpdaemon -n "__fish_seen_subcommand_from app" -a stop -d "Stop an a
 
 /// Auto-detect current shell and generate completions
 #[cfg(feature = "cli")]
-pub fn handle_auto_completions(dir: Option<&Path>) -> Result<()> {
+pub fn handle_auto_completions(dir: Option<&Path>, formatter: &OutputFormatter) -> Result<usize> {
     // Try to detect shell from environment
     let shell = if let Ok(shell_env) = std::env::var("SHELL") {
         if shell_env.contains("bash") {
@@ -308,5 +327,5 @@ pub fn handle_auto_completions(dir: Option<&Path>) -> Result<()> {
         clap_complete::Shell::Bash
     };
 
-    handle_completions_command(shell, dir)
+    handle_completions_command(shell, dir, formatter)
 }
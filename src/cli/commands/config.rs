@@ -9,30 +9,82 @@ use anyhow::Result;
 #[cfg(feature = "cli")]
 use std::path::PathBuf;
 
+/// Configuration keys understood by `config get/set/remove`
+#[cfg(feature = "cli")]
+pub const CONFIG_KEYS: &[&str] = &[
+    "host",
+    "port",
+    "use_tls",
+    "verify_cert",
+    "format",
+    "color",
+    "json",
+    "quiet",
+    "timeout",
+];
+
+/// Build the error returned for an unknown configuration key
+///
+/// Includes a "did you mean?" hint when the key is a close match for a known one.
+#[cfg(feature = "cli")]
+pub fn unknown_key_error(key: &str) -> CliError {
+    match suggest_config_key(key) {
+        Some(suggestion) => CliError::ConfigurationError(format!(
+            "Unknown config key: {} (did you mean `{}`?)",
+            key, suggestion
+        )),
+        None => CliError::ConfigurationError(format!(
+            "Unknown config key: {} (valid keys: {})",
+            key,
+            CONFIG_KEYS.join(", ")
+        )),
+    }
+}
+
+/// Suggest the closest known configuration key for a mistyped one
+#[cfg(feature = "cli")]
+pub fn suggest_config_key(key: &str) -> Option<&'static str> {
+    crate::cli::utils::closest_match(key, CONFIG_KEYS)
+}
+
 /// Configure command implementation
+///
+/// `config_path` is the CLI's own configuration; `daemon_config_path` is the
+/// daemon configuration edited by `server.*` and `auth.*` keys.
 #[cfg(feature = "cli")]
 pub async fn handle_config_command(
     command: &crate::cli::types::ConfigCommand,
     config_path: Option<PathBuf>,
+    daemon_config_path: &str,
     formatter: &crate::cli::utils::OutputFormatter,
 ) -> Result<(), CliError> {
     match command {
-        crate::cli::types::ConfigCommand::Get { key } => get_config(Some(key), config_path).await,
+        crate::cli::types::ConfigCommand::Get { key } => {
+            get_config(Some(key), config_path, formatter).await
+        }
+        crate::cli::types::ConfigCommand::Set { key, value } if is_daemon_config_key(key) => {
+            set_daemon_config(key, value, daemon_config_path, formatter)
+        }
         crate::cli::types::ConfigCommand::Set { key, value } => {
-            set_config(key, value, config_path).await
+            set_config(key, value, config_path, formatter).await
+        }
+        crate::cli::types::ConfigCommand::Show => list_config(config_path, formatter).await,
+        crate::cli::types::ConfigCommand::Remove { key } => {
+            remove_config(key, config_path, formatter).await
         }
-        crate::cli::types::ConfigCommand::Show => list_config(config_path).await,
-        crate::cli::types::ConfigCommand::Remove { key } => remove_config(key, config_path).await,
     }
 }
 
 /// Get configuration value
 #[cfg(feature = "cli")]
-async fn get_config(key: Option<&str>, config_path: Option<PathBuf>) -> Result<(), CliError> {
-    use crate::cli::utils::{load_config, OutputFormatter};
+async fn get_config(
+    key: Option<&str>,
+    config_path: Option<PathBuf>,
+    formatter: &crate::cli::utils::OutputFormatter,
+) -> Result<(), CliError> {
+    use crate::cli::utils::load_config;
 
     let config = load_config(config_path)?;
-    let formatter = OutputFormatter::new(true, false, false);
 
     if let Some(key) = key {
         // Get specific config value
@@ -41,7 +93,7 @@ async fn get_config(key: Option<&str>, config_path: Option<PathBuf>) -> Result<(
             "port" => formatter.info(&format!("port = {}", config.service.port)),
             "use_tls" => formatter.info(&format!("use_tls = {}", config.service.use_tls)),
             "verify_cert" => {
-                formatter.info(&format!("verify_cert = {}", config.service.skip_verify))
+                formatter.info(&format!("verify_cert = {}", !config.service.skip_verify))
             }
             "format" => formatter.info(&format!("format = {:?}", config.global.format)),
             "color" => formatter.info(&format!("color = {}", config.global.color)),
@@ -49,10 +101,7 @@ async fn get_config(key: Option<&str>, config_path: Option<PathBuf>) -> Result<(
             "quiet" => formatter.info(&format!("quiet = {}", config.global.quiet)),
             "timeout" => formatter.info(&format!("timeout = {}", config.service.timeout)),
             _ => {
-                return Err(CliError::ConfigurationError(format!(
-                    "Unknown config key: {}",
-                    key
-                )));
+                return Err(unknown_key_error(key));
             }
         }
     } else {
@@ -67,13 +116,35 @@ async fn get_config(key: Option<&str>, config_path: Option<PathBuf>) -> Result<(
 
 /// Set configuration value
 #[cfg(feature = "cli")]
-async fn set_config(key: &str, value: &str, config_path: Option<PathBuf>) -> Result<(), CliError> {
-    use crate::cli::utils::{load_config, save_config, OutputFormatter};
+async fn set_config(
+    key: &str,
+    value: &str,
+    config_path: Option<PathBuf>,
+    formatter: &crate::cli::utils::OutputFormatter,
+) -> Result<(), CliError> {
+    use crate::cli::utils::{load_config, save_config};
 
     let mut config = load_config(config_path.clone())?;
-    let formatter = OutputFormatter::new(true, false, false);
 
     // Update config based on key
+    apply_config_value(&mut config, key, value)?;
+
+    // Save updated config
+    save_config(&config, config_path.expect("Config path required to save"))?;
+    formatter.success(&format!("Updated {} = {}", key, value));
+
+    Ok(())
+}
+
+/// Apply a single `key = value` setting to a CLI configuration
+///
+/// Values are validated per key; unknown keys produce a suggestion for the closest known key.
+#[cfg(feature = "cli")]
+pub fn apply_config_value(
+    config: &mut crate::cli::config::CliConfig,
+    key: &str,
+    value: &str,
+) -> Result<(), CliError> {
     match key {
         "host" => config.service.host = value.to_string(),
         "port" => {
@@ -94,7 +165,8 @@ async fn set_config(key: &str, value: &str, config_path: Option<PathBuf>) -> Res
             let verify_cert = value.parse::<bool>().map_err(|_| {
                 CliError::ConfigurationError("verify_cert must be true or false".to_string())
             })?;
-            config.service.skip_verify = verify_cert;
+            // verify_cert is the user-facing inverse of skip_verify
+            config.service.skip_verify = !verify_cert;
         }
         "format" => match value.to_lowercase().as_str() {
             "text" | "json" | "yaml" => {
@@ -136,28 +208,218 @@ async fn set_config(key: &str, value: &str, config_path: Option<PathBuf>) -> Res
             })?;
             config.service.timeout = timeout;
         }
-        _ => {
-            return Err(CliError::ConfigurationError(format!(
-                "Unknown config key: {}",
-                key
-            )))
+        _ => return Err(unknown_key_error(key)),
+    }
+
+    Ok(())
+}
+
+/// Whether `key` names a daemon setting (`server.*` or `auth.*`) rather than a CLI one
+#[cfg(feature = "cli")]
+pub fn is_daemon_config_key(key: &str) -> bool {
+    key.starts_with("server.") || key.starts_with("auth.")
+}
+
+/// JSON pointer to a daemon key in the serialized `ServiceConfig`
+///
+/// `auth.*` is short for `server.auth.*`.
+#[cfg(feature = "cli")]
+fn daemon_key_pointer(key: &str) -> String {
+    match key.strip_prefix("auth.") {
+        Some(rest) => format!("/server/auth/{}", rest.replace('.', "/")),
+        None => format!("/{}", key.replace('.', "/")),
+    }
+}
+
+/// Daemon keys that `config set` accepts, with `server.auth.*` shortened to `auth.*`
+#[cfg(feature = "cli")]
+pub fn daemon_config_keys() -> Vec<String> {
+    fn collect(value: &serde_json::Value, prefix: &str, keys: &mut Vec<String>) {
+        match value.as_object() {
+            Some(table) => {
+                for (name, child) in table {
+                    collect(child, &format!("{}.{}", prefix, name), keys);
+                }
+            }
+            None => keys.push(prefix.to_string()),
         }
     }
 
-    // Save updated config
-    save_config(&config, config_path.expect("Config path required to save"))?;
-    formatter.success(&format!("Updated {} = {}", key, value));
+    let defaults =
+        serde_json::to_value(crate::config::ServiceConfig::default()).unwrap_or_default();
+    let mut keys = Vec::new();
+    collect(&defaults["server"], "server", &mut keys);
+
+    keys.into_iter()
+        .map(|key| match key.strip_prefix("server.auth.") {
+            Some(rest) => format!("auth.{}", rest),
+            None => key,
+        })
+        .collect()
+}
+
+/// Build the error returned for an unknown daemon configuration key
+#[cfg(feature = "cli")]
+fn unknown_daemon_key_error(key: &str) -> CliError {
+    let keys = daemon_config_keys();
+    let candidates: Vec<&str> = keys.iter().map(|k| k.as_str()).collect();
+    match crate::cli::utils::closest_match(key, &candidates) {
+        Some(suggestion) => CliError::ConfigurationError(format!(
+            "Unknown config key: {} (did you mean `{}`?)",
+            key, suggestion
+        )),
+        None => CliError::ConfigurationError(format!(
+            "Unknown config key: {} (daemon keys look like server.port or auth.required)",
+            key
+        )),
+    }
+}
+
+/// Apply a dotted `key = value` setting to a daemon configuration
+///
+/// The value is checked against the type of the key: numbers, booleans and
+/// enums must parse as such, and list keys take comma-separated items.
+#[cfg(feature = "cli")]
+pub fn apply_daemon_config_value(
+    config: &mut crate::config::ServiceConfig,
+    key: &str,
+    value: &str,
+) -> Result<(), CliError> {
+    use crate::server::live_config::{apply_setting_at, SettingError};
+
+    match apply_setting_at(&*config, &daemon_key_pointer(key), value) {
+        Ok(updated) => {
+            *config = updated;
+            Ok(())
+        }
+        Err(SettingError::UnknownKey) => Err(unknown_daemon_key_error(key)),
+        Err(SettingError::Section) => Err(CliError::ConfigurationError(format!(
+            "{} is a section; set one of its keys instead",
+            key
+        ))),
+        Err(SettingError::InvalidValue(e)) => Err(CliError::ConfigurationError(format!(
+            "Invalid value for {}: {}",
+            key, e
+        ))),
+    }
+}
+
+/// Set a daemon configuration value in the daemon's config file
+#[cfg(feature = "cli")]
+fn set_daemon_config(
+    key: &str,
+    value: &str,
+    path: &str,
+    formatter: &crate::cli::utils::OutputFormatter,
+) -> Result<(), CliError> {
+    let stored = set_daemon_config_value(key, value, path)?;
+    formatter.success(&format!("Updated {} = {} in {}", key, stored, path));
 
     Ok(())
 }
 
+/// Set a daemon configuration value in the daemon's config file, returning
+/// the value as stored with secrets redacted
+///
+/// Only the key is rewritten, so comments and layout elsewhere in the file
+/// are kept. A missing file is created from the defaults.
+#[cfg(feature = "cli")]
+pub fn set_daemon_config_value(key: &str, value: &str, path: &str) -> Result<String, CliError> {
+    use crate::config::ServiceConfig;
+
+    let exists = std::path::Path::new(path).exists();
+    let mut config = if exists {
+        ServiceConfig::from_file(path)
+            .map_err(|e| CliError::ConfigurationError(format!("Failed to load {}: {}", path, e)))?
+    } else {
+        ServiceConfig::default()
+    };
+
+    apply_daemon_config_value(&mut config, key, value)?;
+
+    let pointer = daemon_key_pointer(key);
+    if exists {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| CliError::FileSystemError(format!("Failed to read {}: {}", path, e)))?;
+        let mut document: toml_edit::DocumentMut = text
+            .parse()
+            .map_err(|e| CliError::ConfigurationError(format!("Failed to load {}: {}", path, e)))?;
+        let updated = toml::Value::try_from(&config)
+            .map_err(|e| CliError::SerializationError(e.to_string()))
+            .and_then(|config| {
+                toml_edit::ser::to_document(&config)
+                    .map_err(|e| CliError::SerializationError(e.to_string()))
+            })?;
+        copy_toml_item(&updated, &mut document, &pointer);
+        crate::files::write_with_mode(path, document.to_string(), crate::files::PRIVATE_FILE_MODE)
+            .map_err(|e| CliError::FileSystemError(format!("Failed to write {}: {}", path, e)))?;
+    } else {
+        config
+            .to_file(path)
+            .map_err(|e| CliError::FileSystemError(format!("Failed to write {}: {}", path, e)))?;
+    }
+
+    let mut stored = serde_json::to_value(&config)?;
+    crate::server::config::redact_secrets(&mut stored);
+    Ok(match stored.pointer(&pointer) {
+        Some(serde_json::Value::String(text)) => text.clone(),
+        Some(other) => other.to_string(),
+        None => serde_json::Value::Null.to_string(),
+    })
+}
+
+/// Copy the item at `pointer` from `from` into `to`, keeping the comments
+/// around the key in `to`
+///
+/// Missing tables are created; a key `from` does not have is removed.
+#[cfg(feature = "cli")]
+fn copy_toml_item(from: &toml_edit::DocumentMut, to: &mut toml_edit::DocumentMut, pointer: &str) {
+    let path: Vec<&str> = pointer.trim_start_matches('/').split('/').collect();
+    let Some((last, parents)) = path.split_last() else {
+        return;
+    };
+    let item = path
+        .iter()
+        .try_fold(from.as_item(), |item, key| item.get(key))
+        .cloned();
+
+    let mut table: &mut dyn toml_edit::TableLike = to.as_table_mut();
+    for key in parents {
+        table = match table
+            .entry(key)
+            .or_insert(toml_edit::table())
+            .as_table_like_mut()
+        {
+            Some(next) => next,
+            None => return,
+        };
+    }
+
+    match item {
+        Some(mut item) => {
+            if let (Some(toml_edit::Item::Value(old)), toml_edit::Item::Value(new)) =
+                (table.get(last), &mut item)
+            {
+                *new.decor_mut() = old.decor().clone();
+            }
+            table.insert(last, item);
+        }
+        None => {
+            table.remove(last);
+        }
+    }
+}
+
 /// Remove configuration value
 #[cfg(feature = "cli")]
-async fn remove_config(key: &str, config_path: Option<PathBuf>) -> Result<(), CliError> {
-    use crate::cli::utils::{load_config, save_config, OutputFormatter};
+async fn remove_config(
+    key: &str,
+    config_path: Option<PathBuf>,
+    formatter: &crate::cli::utils::OutputFormatter,
+) -> Result<(), CliError> {
+    use crate::cli::utils::{load_config, save_config};
 
     let mut config = load_config(config_path.clone())?;
-    let formatter = OutputFormatter::new(true, false, false);
 
     // Reset config to default based on key
     match key {
@@ -170,12 +432,7 @@ async fn remove_config(key: &str, config_path: Option<PathBuf>) -> Result<(), Cl
         "json" => config.global.json = false,
         "quiet" => config.global.quiet = false,
         "timeout" => config.service.timeout = 30,
-        _ => {
-            return Err(CliError::ConfigurationError(format!(
-                "Unknown config key: {}",
-                key
-            )))
-        }
+        _ => return Err(unknown_key_error(key)),
     }
 
     // Save updated config
@@ -187,18 +444,20 @@ async fn remove_config(key: &str, config_path: Option<PathBuf>) -> Result<(), Cl
 
 /// List all configuration values
 #[cfg(feature = "cli")]
-async fn list_config(config_path: Option<PathBuf>) -> Result<(), CliError> {
-    use crate::cli::utils::{load_config, OutputFormatter};
+async fn list_config(
+    config_path: Option<PathBuf>,
+    formatter: &crate::cli::utils::OutputFormatter,
+) -> Result<(), CliError> {
+    use crate::cli::utils::load_config;
 
     let config = load_config(config_path)?;
-    let formatter = OutputFormatter::new(true, false, false);
 
     // Display connection settings
     formatter.info("Connection settings:");
     formatter.info(&format!("  host = {}", config.service.host));
     formatter.info(&format!("  port = {}", config.service.port));
     formatter.info(&format!("  use_tls = {}", config.service.use_tls));
-    formatter.info(&format!("  verify_cert = {}", config.service.skip_verify));
+    formatter.info(&format!("  verify_cert = {}", !config.service.skip_verify));
 
     // Display output settings
     formatter.info("Output settings:");
@@ -6,273 +6,1070 @@
 #[cfg(feature = "cli")]
 use anyhow::Result;
 #[cfg(feature = "cli")]
-use colored::Colorize;
+use std::collections::BTreeMap;
 #[cfg(feature = "cli")]
-use std::collections::HashMap;
-#[cfg(feature = "cli")]
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::time::Duration;
 
 #[cfg(feature = "cli")]
-use crate::cli::service::ServiceClient;
+use crate::cli::service::{MetricsSnapshot, ServerEventType, ServiceClient};
 #[cfg(feature = "cli")]
 use crate::cli::utils::OutputFormatter;
+#[cfg(feature = "cli")]
+use crate::platform::sysinfo;
 
-/// Handle system diagnostics command
 #[cfg(feature = "cli")]
-pub async fn handle_system_diag(formatter: &OutputFormatter) -> Result<()> {
-    // Collect system information
-    let os_info = os_info();
-    let memory_info = memory_info();
-    let disk_info = disk_info();
+const GIB: u64 = 1024 * 1024 * 1024;
 
-    // Output system information
-    // Format system diagnostics as tables
-    if formatter.json_output {
-        let mut data = std::collections::HashMap::new();
-        data.insert("operating_system".to_string(), os_info);
-        data.insert("memory".to_string(), memory_info);
-        data.insert("disk".to_string(), disk_info);
-        formatter.json(&data).unwrap_or_else(|e| {
-            formatter.error(&format!("Failed to format diagnostics data: {}", e))
-        });
-    } else {
-        formatter.info("System Diagnostics");
-        formatter.info("=================");
-        formatter.info("\nOperating System:");
-        for (key, value) in os_info {
-            formatter.info(&format!("  {}: {}", key, value));
+/// How large a byte count is, in gigabytes with one decimal
+#[cfg(feature = "cli")]
+fn format_size(bytes: u64) -> String {
+    format!("{:.1} GB", bytes as f64 / GIB as f64)
+}
+
+/// Operating system of the host
+#[cfg(feature = "cli")]
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct SystemSection {
+    pub os: String,
+    pub architecture: String,
+    pub hostname: String,
+    pub kernel_version: Option<String>,
+    /// Seconds since the host booted
+    pub uptime_secs: Option<u64>,
+}
+
+#[cfg(feature = "cli")]
+impl SystemSection {
+    /// Describe the host this runs on
+    pub fn collect() -> Self {
+        Self {
+            os: std::env::consts::OS.to_string(),
+            architecture: std::env::consts::ARCH.to_string(),
+            hostname: sysinfo::hostname(),
+            kernel_version: sysinfo::kernel_version(),
+            uptime_secs: sysinfo::boot_uptime().map(|uptime| uptime.as_secs()),
         }
+    }
+}
+
+/// Memory and swap of the host, in bytes
+#[cfg(feature = "cli")]
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct MemorySection {
+    pub total_bytes: u64,
+    pub used_bytes: u64,
+    pub free_bytes: u64,
+    pub swap_total_bytes: u64,
+    pub swap_used_bytes: u64,
+}
+
+#[cfg(feature = "cli")]
+impl MemorySection {
+    /// Describe the host's memory, or `None` where the platform does not report it
+    pub fn collect() -> Option<Self> {
+        let memory = sysinfo::memory()?;
+        Some(Self {
+            total_bytes: memory.total,
+            used_bytes: memory.total.saturating_sub(memory.available),
+            free_bytes: memory.available,
+            swap_total_bytes: memory.swap_total,
+            swap_used_bytes: memory.swap_total.saturating_sub(memory.swap_free),
+        })
+    }
+}
 
-        formatter.info("\nMemory:");
-        for (key, value) in memory_info {
-            formatter.info(&format!("  {}: {}", key, value));
+/// Disk space of the file system holding `path`, in bytes
+#[cfg(feature = "cli")]
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct DiskSection {
+    pub path: String,
+    pub total_bytes: u64,
+    pub used_bytes: u64,
+    pub free_bytes: u64,
+}
+
+#[cfg(feature = "cli")]
+impl DiskSection {
+    /// Describe the root file system, or `None` where the platform does not report it
+    pub fn collect() -> Option<Self> {
+        let path = "/";
+        let disk = sysinfo::disk_space(std::path::Path::new(path))?;
+        Some(Self {
+            path: path.to_string(),
+            total_bytes: disk.total,
+            used_bytes: disk.total.saturating_sub(disk.available),
+            free_bytes: disk.available,
+        })
+    }
+}
+
+/// Network interfaces of the host
+#[cfg(feature = "cli")]
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct NetworkSection {
+    /// Addresses of each interface with their prefix length, by interface name
+    pub interfaces: BTreeMap<String, Vec<String>>,
+}
+
+#[cfg(feature = "cli")]
+impl NetworkSection {
+    /// Describe the host's network interfaces, or `None` where the platform
+    /// does not list them
+    pub fn collect() -> Option<Self> {
+        let mut interfaces: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for address in sysinfo::interface_addresses()? {
+            interfaces
+                .entry(address.interface)
+                .or_default()
+                .push(format!("{}/{}", address.address, address.prefix_len));
         }
+        Some(Self { interfaces })
+    }
+}
+
+/// Whether the daemon answers
+#[cfg(feature = "cli")]
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct ConnectivitySection {
+    pub service_reachable: bool,
+    /// Why the daemon could not be reached
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
 
-        formatter.info("\nDisk:");
-        for (key, value) in disk_info {
-            formatter.info(&format!("  {}: {}", key, value));
+#[cfg(feature = "cli")]
+impl ConnectivitySection {
+    /// Check that the daemon `client` talks to answers a status request
+    pub async fn collect(client: &ServiceClient) -> Self {
+        match client.get_status().await {
+            Ok(_) => Self {
+                service_reachable: true,
+                error: None,
+            },
+            Err(e) => Self {
+                service_reachable: false,
+                error: Some(e.to_string()),
+            },
         }
     }
+}
 
-    Ok(())
+/// What the diag commands report, printed alike in every output format
+///
+/// Each command fills the sections it collects; the others, and those the
+/// platform does not report, are left out.
+#[cfg(feature = "cli")]
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize)]
+pub struct DiagReport {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system: Option<SystemSection>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory: Option<MemorySection>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disk: Option<DiskSection>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub network: Option<NetworkSection>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connectivity: Option<ConnectivitySection>,
+}
+
+#[cfg(feature = "cli")]
+impl std::fmt::Display for DiagReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut sections: Vec<(&str, Vec<(String, String)>)> = Vec::new();
+        let unknown = || "unknown".to_string();
+
+        if let Some(system) = &self.system {
+            sections.push((
+                "Operating System",
+                vec![
+                    ("OS Type".to_string(), system.os.clone()),
+                    ("Architecture".to_string(), system.architecture.clone()),
+                    ("Hostname".to_string(), system.hostname.clone()),
+                    (
+                        "Kernel Version".to_string(),
+                        system.kernel_version.clone().unwrap_or_else(unknown),
+                    ),
+                    (
+                        "Uptime".to_string(),
+                        system
+                            .uptime_secs
+                            .map(crate::cli::utils::format_duration)
+                            .unwrap_or_else(unknown),
+                    ),
+                ],
+            ));
+        }
+        if let Some(memory) = &self.memory {
+            sections.push((
+                "Memory",
+                vec![
+                    ("Total Memory".to_string(), format_size(memory.total_bytes)),
+                    ("Used Memory".to_string(), format_size(memory.used_bytes)),
+                    ("Free Memory".to_string(), format_size(memory.free_bytes)),
+                    (
+                        "Swap Total".to_string(),
+                        format_size(memory.swap_total_bytes),
+                    ),
+                    ("Swap Used".to_string(), format_size(memory.swap_used_bytes)),
+                ],
+            ));
+        }
+        if let Some(disk) = &self.disk {
+            sections.push((
+                "Disk",
+                vec![
+                    ("Path".to_string(), disk.path.clone()),
+                    ("Total Space".to_string(), format_size(disk.total_bytes)),
+                    ("Used Space".to_string(), format_size(disk.used_bytes)),
+                    ("Free Space".to_string(), format_size(disk.free_bytes)),
+                ],
+            ));
+        }
+        if let Some(network) = &self.network {
+            sections.push((
+                "Network Interfaces",
+                network
+                    .interfaces
+                    .iter()
+                    .map(|(name, addresses)| (name.clone(), addresses.join(", ")))
+                    .collect(),
+            ));
+        }
+        if let Some(connectivity) = &self.connectivity {
+            let reachable = match (&connectivity.error, connectivity.service_reachable) {
+                (_, true) => "Yes".to_string(),
+                (Some(error), false) => format!("No ({})", error),
+                (None, false) => "No".to_string(),
+            };
+            sections.push((
+                "Service Connectivity",
+                vec![("Service Reachable".to_string(), reachable)],
+            ));
+        }
+
+        for (i, (title, rows)) in sections.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}:", title)?;
+            for (key, value) in rows {
+                write!(f, "\n  {}: {}", key, value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Handle system diagnostics command
+#[cfg(feature = "cli")]
+pub async fn handle_system_diag(formatter: &OutputFormatter) -> Result<()> {
+    let report = DiagReport {
+        system: Some(SystemSection::collect()),
+        memory: MemorySection::collect(),
+        disk: DiskSection::collect(),
+        ..Default::default()
+    };
+    formatter.output_item(&report, "System Diagnostics")
 }
 
 /// Handle network diagnostics command
 #[cfg(feature = "cli")]
 pub async fn handle_network_diag(
-    _client: &ServiceClient,
+    client: &ServiceClient,
     formatter: &OutputFormatter,
 ) -> Result<()> {
-    // Check network connectivity to service
-    let service_check = ping_service(_client).await;
+    let report = DiagReport {
+        network: NetworkSection::collect(),
+        connectivity: Some(ConnectivitySection::collect(client).await),
+        ..Default::default()
+    };
+    formatter.output_item(&report, "Network Diagnostics")
+}
+
+/// Handle the runtime log level command
+#[cfg(feature = "cli")]
+pub async fn handle_log_level(
+    level: &str,
+    client: &ServiceClient,
+    formatter: &OutputFormatter,
+) -> Result<()> {
+    let level = match crate::logging::parse_level(level) {
+        Some(level) => level.to_string().to_lowercase(),
+        None => {
+            return Err(crate::cli::error::CliError::ValidationError(format!(
+                "Invalid log level: {} (expected off, error, warn, info, debug or trace)",
+                level
+            ))
+            .into())
+        }
+    };
+
+    let previous = client.set_log_level(&level).await?;
+    formatter.success(&format!("Log level changed from {} to {}", previous, level));
+    Ok(())
+}
+
+/// Handle the recent events command
+#[cfg(feature = "cli")]
+pub async fn handle_events(
+    count: usize,
+    types: &[String],
+    client: &ServiceClient,
+    formatter: &OutputFormatter,
+) -> Result<()> {
+    let types = types
+        .iter()
+        .map(|t| t.parse::<ServerEventType>())
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .map_err(crate::cli::error::CliError::ValidationError)?;
+
+    let events = client.recent_events(count, &types).await?;
 
-    // Format network diagnostics
     if formatter.json_output {
-        let mut data = std::collections::HashMap::new();
-        data.insert("interfaces".to_string(), network_interfaces());
-        let mut connectivity = std::collections::HashMap::new();
-        connectivity.insert("service_reachable".to_string(), service_check);
-        data.insert("connectivity".to_string(), connectivity);
         formatter
-            .json(&data)
-            .unwrap_or_else(|e| formatter.error(&format!("Failed to format network data: {}", e)));
-    } else {
-        formatter.info("Network Diagnostics");
-        formatter.info("=================");
-        formatter.info("\nNetwork Interfaces:");
-
-        // Get network interfaces
-        let interfaces = network_interfaces();
-        for (name, details) in interfaces {
-            formatter.info(&format!("  {}: {}", name, details));
-        }
+            .json(&events)
+            .unwrap_or_else(|e| formatter.error(&format!("Failed to format events: {}", e)));
+        return Ok(());
+    }
+
+    if events.is_empty() {
+        formatter.info("No recent events");
+        return Ok(());
+    }
+
+    formatter.table(
+        vec!["Time", "Type", "Session", "User", "Client IP"],
+        |table| {
+            for event in &events {
+                let session = event
+                    .session_id
+                    .map(|id| id.to_string())
+                    .unwrap_or_else(|| "-".to_string());
+                table.add_row(vec![
+                    event.timestamp.as_str(),
+                    event.event_type.as_str(),
+                    session.as_str(),
+                    event.user.as_deref().unwrap_or("-"),
+                    event.client_ip.as_deref().unwrap_or("-"),
+                ]);
+            }
+        },
+    );
+
+    Ok(())
+}
+
+/// Handle the cluster command: the daemon's view of its peers
+#[cfg(feature = "cli")]
+pub async fn handle_cluster(client: &ServiceClient, formatter: &OutputFormatter) -> Result<()> {
+    let peers = client.cluster_peers().await?;
 
-        formatter.info("\nService Connectivity:");
-        formatter.info(&format!("  Service Reachable: {}", service_check));
+    if formatter.json_output {
+        formatter
+            .json(&peers)
+            .unwrap_or_else(|e| formatter.error(&format!("Failed to format peers: {}", e)));
+        return Ok(());
+    }
+
+    if peers.is_empty() {
+        formatter.info("No cluster peers (set cluster.peers in the daemon config)");
+        return Ok(());
     }
 
+    formatter.table(
+        vec![
+            "Peer",
+            "Status",
+            "Sessions",
+            "Latency",
+            "Last Seen",
+            "Error",
+        ],
+        |table| {
+            for peer in &peers {
+                let status = match (peer.reachable, peer.configured) {
+                    (true, _) => "reachable",
+                    (false, true) => "unreachable",
+                    (false, false) => "announced",
+                };
+                let sessions = peer
+                    .health
+                    .as_ref()
+                    .map(|health| health.active_sessions.to_string())
+                    .unwrap_or_else(|| "-".to_string());
+                let latency = peer
+                    .latency_ms
+                    .map(|ms| format!("{} ms", ms))
+                    .unwrap_or_else(|| "-".to_string());
+                table.add_row(vec![
+                    peer.address.as_str(),
+                    status,
+                    sessions.as_str(),
+                    latency.as_str(),
+                    peer.last_seen.as_deref().unwrap_or("never"),
+                    peer.last_error.as_deref().unwrap_or("-"),
+                ]);
+            }
+        },
+    );
+
+    let reachable = peers.iter().filter(|peer| peer.reachable).count();
+    let sessions: usize = peers
+        .iter()
+        .filter(|peer| peer.reachable)
+        .filter_map(|peer| peer.health.as_ref())
+        .map(|health| health.active_sessions)
+        .sum();
+    formatter.info(&format!(
+        "{} of {} peers reachable, {} sessions on reachable peers",
+        reachable,
+        peers.len(),
+        sessions
+    ));
+
     Ok(())
 }
 
-/// Handle log viewing command
+/// Rows of the `diag metrics` table: each counter's label and value
 #[cfg(feature = "cli")]
-pub async fn handle_logs(lines: usize, follow: bool, formatter: &OutputFormatter) -> Result<()> {
-    // This is just a placeholder - replace with actual log retrieval
-    let logs = get_logs(lines).await?;
+pub fn metrics_rows(metrics: &MetricsSnapshot) -> Vec<(&'static str, String)> {
+    let error_rate = match metrics.requests_total {
+        0 => "-".to_string(),
+        total => format!(
+            "{:.1}%",
+            metrics.request_errors as f64 * 100.0 / total as f64
+        ),
+    };
+    vec![
+        (
+            "Connections accepted",
+            metrics.connections_accepted.to_string(),
+        ),
+        (
+            "Connections rejected",
+            metrics.connections_rejected.to_string(),
+        ),
+        ("Active sessions", metrics.sessions_active.to_string()),
+        ("Requests", metrics.requests_total.to_string()),
+        ("Request errors", metrics.request_errors.to_string()),
+        ("Error rate", error_rate),
+    ]
+}
+
+/// Handle the metrics command: the daemon's counters as a table, JSON or
+/// Prometheus text
+#[cfg(feature = "cli")]
+pub async fn handle_metrics(
+    prometheus: bool,
+    client: &ServiceClient,
+    formatter: &OutputFormatter,
+) -> Result<()> {
+    let metrics = client.get_metrics().await?;
+
+    if prometheus {
+        print!("{}", metrics.to_prometheus());
+        return Ok(());
+    }
 
-    // Format logs
     if formatter.json_output {
         formatter
-            .json(&logs)
-            .unwrap_or_else(|e| formatter.error(&format!("Failed to format logs: {}", e)));
-    } else {
-        formatter.info("Service Logs");
-        formatter.info("=================");
+            .json(&metrics)
+            .unwrap_or_else(|e| formatter.error(&format!("Failed to format metrics: {}", e)));
+        return Ok(());
+    }
 
-        for log in &logs {
-            formatter.info(log);
+    formatter.table(vec!["Metric", "Value"], |table| {
+        for (label, value) in metrics_rows(&metrics) {
+            table.add_row(vec![label, value.as_str()]);
         }
+    });
+    if !metrics.collected_at.is_empty() {
+        formatter.info(&format!("Collected at {}", metrics.collected_at));
+    }
 
-        if follow {
-            formatter.info("Log following enabled (press Ctrl+C to exit)");
-
-            // In a real implementation, this would continue to stream logs
-            // For this placeholder, we'll just wait a bit and show a few more logs
-            tokio::time::sleep(Duration::from_secs(2)).await;
-
-            let follow_logs = vec![
-                format!(
-                    "{} INFO  rcpdaemon: New client connection from 192.168.1.105",
-                    timestamp()
-                ),
-                format!(
-                    "{} DEBUG rcpdaemon: Authentication successful for user 'test'",
-                    timestamp()
-                ),
-                format!(
-                    "{} INFO  rcpdaemon: Session started for user 'test'",
-                    timestamp()
-                ),
-            ];
-
-            for log in follow_logs {
-                formatter.info(&log);
-                tokio::time::sleep(Duration::from_millis(500)).await;
+    Ok(())
+}
+
+/// Handle the auth benchmark command: timings measured inside the daemon
+#[cfg(feature = "cli")]
+pub async fn handle_bench_auth(
+    iterations: usize,
+    user: Option<&str>,
+    password: Option<&str>,
+    client: &ServiceClient,
+    formatter: &OutputFormatter,
+) -> Result<()> {
+    let max = crate::server::bench::MAX_BENCH_ITERATIONS;
+    if iterations == 0 || iterations > max {
+        return Err(crate::cli::error::CliError::ValidationError(format!(
+            "Iterations must be between 1 and {}",
+            max
+        ))
+        .into());
+    }
+
+    let report = client.bench_auth(iterations, user, password).await?;
+
+    if formatter.json_output {
+        formatter
+            .json(&report)
+            .unwrap_or_else(|e| formatter.error(&format!("Failed to format report: {}", e)));
+        return Ok(());
+    }
+
+    let ms = |us: u64| format!("{:.2} ms", us as f64 / 1000.0);
+    let rows = [
+        ("Throughput", format!("{:.1} /s", report.per_second)),
+        ("Total", format!("{} ms", report.total_ms)),
+        ("p50", ms(report.p50_us)),
+        ("p90", ms(report.p90_us)),
+        ("p99", ms(report.p99_us)),
+        ("Max", ms(report.max_us)),
+        ("Failures", report.failures.to_string()),
+    ];
+
+    formatter.info(&format!(
+        "Auth benchmark: {} iterations against {} as {}",
+        report.iterations, report.provider, report.username
+    ));
+    formatter.table(vec!["Metric", "Value"], |table| {
+        for (metric, value) in &rows {
+            table.add_row(vec![*metric, value.as_str()]);
+        }
+    });
+
+    if report.failures > 0 {
+        formatter.warning(&format!(
+            "{} of {} iterations failed; check the test user's credentials",
+            report.failures, report.iterations
+        ));
+    }
+
+    Ok(())
+}
+
+/// A configuration key whose running and on-disk values differ
+#[cfg(feature = "cli")]
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct ConfigDifference {
+    /// Dotted path of the key, e.g. `auth.psk`
+    pub key: String,
+
+    /// Value in the running daemon, if the key is present there
+    pub running: Option<serde_json::Value>,
+
+    /// Value in the config file, if the key is present there
+    pub on_disk: Option<serde_json::Value>,
+}
+
+/// Flatten a JSON configuration tree into dotted keys and leaf values
+#[cfg(feature = "cli")]
+pub(crate) fn flatten_config(
+    prefix: &str,
+    value: &serde_json::Value,
+    out: &mut std::collections::BTreeMap<String, serde_json::Value>,
+) {
+    match value {
+        serde_json::Value::Object(map) if !map.is_empty() => {
+            for (key, value) in map {
+                let key = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                flatten_config(&key, value, out);
             }
         }
+        _ => {
+            out.insert(prefix.to_string(), value.clone());
+        }
+    }
+}
+
+/// Keys that differ between the running and on-disk configurations, sorted by key
+///
+/// Pass trees from [`ServerConfig::to_fingerprinted_json`] with the same salt
+/// so changed secrets are listed; their fingerprints are shown as
+/// [`REDACTED`].
+///
+/// [`ServerConfig::to_fingerprinted_json`]: crate::server::config::ServerConfig::to_fingerprinted_json
+/// [`REDACTED`]: crate::server::config::REDACTED
+#[cfg(feature = "cli")]
+pub fn diff_configs(
+    running: &serde_json::Value,
+    on_disk: &serde_json::Value,
+) -> Vec<ConfigDifference> {
+    let mut running_keys = std::collections::BTreeMap::new();
+    let mut on_disk_keys = std::collections::BTreeMap::new();
+    flatten_config("", running, &mut running_keys);
+    flatten_config("", on_disk, &mut on_disk_keys);
+
+    let keys: std::collections::BTreeSet<&String> =
+        running_keys.keys().chain(on_disk_keys.keys()).collect();
+
+    keys.into_iter()
+        .filter_map(|key| {
+            let running = running_keys.get(key);
+            let on_disk = on_disk_keys.get(key);
+            let hide = crate::server::config::hide_fingerprints;
+            (running != on_disk).then(|| ConfigDifference {
+                key: key.clone(),
+                running: running.map(hide),
+                on_disk: on_disk.map(hide),
+            })
+        })
+        .collect()
+}
+
+/// Handle the config diff command: running daemon configuration vs the config file
+#[cfg(feature = "cli")]
+pub async fn handle_config_diff(
+    config_path: &str,
+    client: &ServiceClient,
+    formatter: &OutputFormatter,
+) -> Result<()> {
+    let on_disk = crate::config::ServiceConfig::from_file(config_path).map_err(|e| {
+        crate::cli::error::CliError::ConfigurationError(format!(
+            "Failed to load {}: {}",
+            config_path, e
+        ))
+    })?;
+    // A fresh salt keeps the fingerprints from being compared across runs
+    let salt = uuid::Uuid::new_v4().to_string();
+    let running = client.get_fingerprinted_config(&salt).await?;
+    let differences = diff_configs(&running, &on_disk.server.to_fingerprinted_json(&salt));
+
+    if formatter.json_output {
+        formatter
+            .json(&differences)
+            .unwrap_or_else(|e| formatter.error(&format!("Failed to format config diff: {}", e)));
+        return Ok(());
+    }
+
+    if differences.is_empty() {
+        formatter.success(&format!("Running configuration matches {}", config_path));
+        return Ok(());
+    }
+
+    let show = |value: &Option<serde_json::Value>| match value {
+        Some(value) => value.to_string(),
+        None => "(unset)".to_string(),
+    };
+
+    formatter.warning(&format!(
+        "{} key(s) differ from {}; reload or restart the daemon to apply them",
+        differences.len(),
+        config_path
+    ));
+    for difference in &differences {
+        formatter.info(&format!("  {}", difference.key));
+        formatter.info(&format!("    running: {}", show(&difference.running)));
+        formatter.info(&format!("    on disk: {}", show(&difference.on_disk)));
     }
 
     Ok(())
 }
 
-// Helper Functions
+/// One section of a [`DiagBundle`], or why it could not be collected
+///
+/// A daemon that is down still gets a bundle, with its sections failed.
+#[cfg(feature = "cli")]
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+#[serde(untagged)]
+pub enum Collected<T> {
+    Ok(T),
+    Failed { error: String },
+}
 
 #[cfg(feature = "cli")]
-fn timestamp() -> String {
-    let now = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_secs();
-    let datetime = chrono::DateTime::<chrono::Utc>::from_timestamp(now as i64, 0);
-    match datetime {
-        Some(dt) => dt.format("%Y-%m-%d %H:%M:%S").to_string(),
-        None => "Unknown time".to_string(),
+impl<T, E: std::fmt::Display> From<std::result::Result<T, E>> for Collected<T> {
+    fn from(result: std::result::Result<T, E>) -> Self {
+        match result {
+            Ok(value) => Collected::Ok(value),
+            Err(e) => Collected::Failed {
+                error: e.to_string(),
+            },
+        }
     }
 }
 
+/// Configuration in a [`DiagBundle`], with secrets redacted
 #[cfg(feature = "cli")]
-fn os_info() -> HashMap<String, String> {
-    let mut info = HashMap::new();
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct BundleConfig {
+    /// Config file the bundle was made against
+    pub path: String,
+    /// Contents of the config file
+    pub file: Collected<serde_json::Value>,
+    /// Server configuration the daemon is running with
+    pub running: Collected<serde_json::Value>,
+}
 
-    // These are placeholders - in a real implementation, we'd use actual system calls
-    info.insert("OS Type".to_string(), std::env::consts::OS.to_string());
-    info.insert(
-        "Architecture".to_string(),
-        std::env::consts::ARCH.to_string(),
-    );
-    info.insert("Hostname".to_string(), hostname());
-    info.insert("Kernel Version".to_string(), kernel_version());
-    info.insert("Uptime".to_string(), uptime());
+/// Daemon log lines in a [`DiagBundle`]
+#[cfg(feature = "cli")]
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct BundleLogs {
+    pub path: std::path::PathBuf,
+    /// The last lines of the log file, oldest first
+    pub lines: Vec<String>,
+}
 
-    info
+/// Everything `diag dump` collects, for attaching to a bug report
+#[cfg(feature = "cli")]
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct DiagBundle {
+    /// When the bundle was made (RFC 3339)
+    pub generated_at: String,
+    pub build: crate::build_info::BuildInfo,
+    pub platform: DiagReport,
+    pub config: BundleConfig,
+    pub status: Collected<crate::cli::service::ServiceStatus>,
+    pub events: Collected<Vec<crate::cli::service::ServerEvent>>,
+    pub logs: Collected<BundleLogs>,
 }
 
+/// Number of recent events a [`DiagBundle`] holds
 #[cfg(feature = "cli")]
-fn memory_info() -> HashMap<String, String> {
-    let mut info = HashMap::new();
+const BUNDLE_EVENTS: usize = 100;
 
-    // These are placeholders - in a real implementation, we'd use actual system calls
-    info.insert("Total Memory".to_string(), "16.0 GB".to_string());
-    info.insert("Used Memory".to_string(), "8.2 GB".to_string());
-    info.insert("Free Memory".to_string(), "7.8 GB".to_string());
-    info.insert("Swap Total".to_string(), "4.0 GB".to_string());
-    info.insert("Swap Used".to_string(), "0.5 GB".to_string());
+/// Collect a [`DiagBundle`] for the daemon configured by `config_path`
+///
+/// Secret configuration values are redacted everywhere, including in log
+/// lines that repeat them.
+#[cfg(feature = "cli")]
+pub async fn collect_bundle(config_path: &str, lines: usize, client: &ServiceClient) -> DiagBundle {
+    use crate::server::config::{redact_secrets, secret_values, REDACTED};
+
+    let file = crate::config::ServiceConfig::from_file(config_path)
+        .map_err(|e| format!("Failed to load {}: {}", config_path, e))
+        .and_then(|config| serde_json::to_value(config).map_err(|e| e.to_string()));
+    let status = client.get_status().await;
+    let running = client.get_running_config().await;
+    let events = client.recent_events(BUNDLE_EVENTS, &[]).await;
+
+    // The daemon redacts its running config, but the secrets in the file are
+    // what may show up in its logs
+    let mut secrets = file.as_ref().map(secret_values).unwrap_or_default();
+    if let Ok(running) = &running {
+        secrets.extend(secret_values(running));
+    }
+    let log_path = crate::cli::commands::service::daemon_log_path(config_path);
+    let logs = crate::cli::commands::service::tail_log_file(&log_path, lines)
+        .map(|(lines, _)| BundleLogs {
+            path: log_path.clone(),
+            lines: lines
+                .into_iter()
+                .map(|line| {
+                    secrets
+                        .iter()
+                        .fold(line, |line, secret| line.replace(secret.as_str(), REDACTED))
+                })
+                .collect(),
+        })
+        .map_err(|e| format!("Failed to read {}: {}", log_path.display(), e));
 
-    info
+    let redacted = |value: serde_json::Value| {
+        let mut value = value;
+        redact_secrets(&mut value);
+        value
+    };
+
+    DiagBundle {
+        generated_at: chrono::Utc::now().to_rfc3339(),
+        build: crate::build_info::BuildInfo::current(),
+        platform: DiagReport {
+            system: Some(SystemSection::collect()),
+            memory: MemorySection::collect(),
+            disk: DiskSection::collect(),
+            network: NetworkSection::collect(),
+            ..Default::default()
+        },
+        config: BundleConfig {
+            path: config_path.to_string(),
+            file: file.map(redacted).into(),
+            running: running.map(redacted).into(),
+        },
+        status: status.into(),
+        events: events.into(),
+        logs: logs.into(),
+    }
 }
 
+/// Handle the dump command: write a [`DiagBundle`] to `output` as JSON
 #[cfg(feature = "cli")]
-fn disk_info() -> HashMap<String, String> {
-    let mut info = HashMap::new();
+pub async fn handle_dump(
+    output: &std::path::Path,
+    lines: usize,
+    config_path: &str,
+    client: &ServiceClient,
+    formatter: &OutputFormatter,
+) -> Result<()> {
+    let bundle = collect_bundle(config_path, lines, client).await;
+    let json = serde_json::to_string_pretty(&bundle)?;
 
-    // These are placeholders - in a real implementation, we'd use actual system calls
-    info.insert("Total Space".to_string(), "512.0 GB".to_string());
-    info.insert("Used Space".to_string(), "256.3 GB".to_string());
-    info.insert("Free Space".to_string(), "255.7 GB".to_string());
+    // Redacted, but still more than other users of the host need to see
+    crate::files::write_with_mode(output, json, crate::files::PRIVATE_FILE_MODE).map_err(|e| {
+        crate::cli::error::CliError::FileSystemError(format!(
+            "Failed to write {}: {}",
+            output.display(),
+            e
+        ))
+    })?;
 
-    info
+    if formatter.json_output {
+        formatter
+            .json(&serde_json::json!({ "output": output }))
+            .unwrap_or_else(|e| formatter.error(&format!("Failed to format result: {}", e)));
+    } else {
+        formatter.success(&format!(
+            "Diagnostic bundle written to {}",
+            output.display()
+        ));
+        if let Collected::Failed { error } = &bundle.status {
+            formatter.warning(&format!("Daemon status not included: {}", error));
+        }
+    }
+
+    Ok(())
 }
 
+/// User the self-test authenticates as
+#[cfg(feature = "cli")]
+const SELFTEST_USER: &str = "selftest";
+
+/// Password of [`SELFTEST_USER`] in the self-test's mock provider
 #[cfg(feature = "cli")]
-fn hostname() -> String {
-    // This is a placeholder - in a real implementation, we'd use actual system calls
-    "example-host.local".to_string()
+const SELFTEST_PASSWORD: &[u8] = b"selftest-password";
+
+/// Outcome of one self-test step
+#[cfg(feature = "cli")]
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct SelftestStep {
+    /// Step name
+    pub name: String,
+
+    /// Whether the step succeeded
+    pub passed: bool,
+
+    /// What the step observed, or why it failed
+    pub detail: String,
+
+    /// Time the step took, in milliseconds
+    pub duration_ms: u64,
 }
 
+/// Records step outcomes, running each step only while all before it passed
 #[cfg(feature = "cli")]
-fn kernel_version() -> String {
-    // This is a placeholder - in a real implementation, we'd use actual system calls
-    "5.10.0-generic".to_string()
+#[derive(Default)]
+struct SelftestRun {
+    steps: Vec<SelftestStep>,
 }
 
 #[cfg(feature = "cli")]
-fn uptime() -> String {
-    // This is a placeholder - in a real implementation, we'd use actual system calls
-    "3 days, 7 hours, 15 minutes".to_string()
+impl SelftestRun {
+    /// Run a step yielding a value and a detail line, if no earlier step failed
+    async fn step<T, F>(&mut self, name: &str, step: F) -> Option<T>
+    where
+        F: std::future::Future<Output = anyhow::Result<(T, String)>>,
+    {
+        if self.steps.iter().any(|s| !s.passed) {
+            return None;
+        }
+
+        let started = std::time::Instant::now();
+        let result = step.await;
+        let duration_ms = started.elapsed().as_millis() as u64;
+
+        let (value, passed, detail) = match result {
+            Ok((value, detail)) => (Some(value), true, detail),
+            Err(e) => (None, false, format!("{:#}", e)),
+        };
+        self.steps.push(SelftestStep {
+            name: name.to_string(),
+            passed,
+            detail,
+            duration_ms,
+        });
+        value
+    }
 }
 
+/// Start a loopback server whose auth manager uses a mock provider
 #[cfg(feature = "cli")]
-fn network_interfaces() -> HashMap<String, String> {
-    let mut interfaces = HashMap::new();
+async fn start_selftest_server() -> anyhow::Result<(crate::server::Server, std::net::SocketAddr)> {
+    use crate::auth::factory::{AuthConfig, AuthProviderType};
+    use crate::auth::mock_provider::MockAuthProvider;
+    use crate::auth::AuthManager;
+    use crate::server::user::{User, UserRole};
+
+    let now = chrono::Utc::now().to_rfc3339();
+    let provider = MockAuthProvider::new()
+        .with_user(User {
+            id: uuid::Uuid::new_v4(),
+            username: SELFTEST_USER.to_string(),
+            full_name: None,
+            email: None,
+            password_hash: String::new(),
+            role: UserRole::User,
+            created_at: now.clone(),
+            updated_at: now,
+        })
+        .with_credential(SELFTEST_USER, SELFTEST_PASSWORD)
+        .with_permission(SELFTEST_USER, "connect:*");
+
+    let mut auth_manager = AuthManager::new(AuthConfig {
+        provider: AuthProviderType::Mock,
+        ..Default::default()
+    })
+    .await?;
+    auth_manager.provider = std::sync::Arc::new(tokio::sync::RwLock::new(Box::new(provider)));
+    auth_manager.initialize().await?;
 
-    // These are placeholders - in a real implementation, we'd use actual system calls
-    interfaces.insert("en0".to_string(), "192.168.1.100/24".to_string());
-    interfaces.insert("lo0".to_string(), "127.0.0.1/8".to_string());
+    // A key nobody knows, so the session can only authenticate with its token
+    let mut config = crate::server::config::ServerConfig::default();
+    config.auth.psk = Some(uuid::Uuid::new_v4().to_string());
 
-    interfaces
+    let server = crate::server::Server::builder()
+        .config(config)
+        .auth_manager(std::sync::Arc::new(auth_manager))
+        .build();
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(server.clone().serve(listener));
+
+    Ok((server, addr))
 }
 
+/// Check the self-test user's credentials and issue it a session token
 #[cfg(feature = "cli")]
-#[allow(clippy::needless_borrow)]
-async fn ping_service(client: &ServiceClient) -> String {
-    // This is a placeholder - in a real implementation, we'd actually ping the service
-    match client.get_status().await {
-        Ok(_) => "Yes".green().to_string(),
-        Err(_) => "No (Service unreachable)".red().to_string(),
+async fn selftest_login(server: &crate::server::Server) -> anyhow::Result<String> {
+    let auth_manager = server
+        .auth_manager()
+        .ok_or_else(|| anyhow::anyhow!("Server has no auth manager"))?;
+
+    if auth_manager
+        .validate_credentials(SELFTEST_USER, b"wrong-password", "password")
+        .await?
+    {
+        anyhow::bail!("Mock provider accepted a wrong password");
+    }
+    if !auth_manager
+        .validate_credentials(SELFTEST_USER, SELFTEST_PASSWORD, "password")
+        .await?
+    {
+        anyhow::bail!("Mock provider rejected valid credentials");
     }
+
+    let user = auth_manager
+        .get_user_by_username(SELFTEST_USER)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Mock provider has no user {}", SELFTEST_USER))?;
+    let permissions = auth_manager.get_permissions(&user).await?;
+
+    Ok(server
+        .tokens()
+        .issue(&user.username, permissions, Duration::from_secs(60)))
 }
 
+/// Exercise handshake, authentication and requests against an in-process server
+///
+/// Steps stop at the first failure, since each one depends on the previous.
 #[cfg(feature = "cli")]
-async fn get_logs(lines: usize) -> Result<Vec<String>> {
-    // This is a placeholder - in a real implementation, we'd retrieve actual logs
-    let mut logs = Vec::new();
+pub async fn run_selftest() -> Vec<SelftestStep> {
+    let mut run = SelftestRun::default();
 
-    for i in 0..lines.min(10) {
-        let timestamp = chrono::Utc::now() - chrono::TimeDelta::minutes(i as i64);
-        let formatted_time = timestamp.format("%Y-%m-%d %H:%M:%S").to_string();
+    let Some((server, addr)) = run
+        .step("start server", async {
+            let (server, addr) = start_selftest_server().await?;
+            Ok(((server, addr), format!("listening on {}", addr)))
+        })
+        .await
+    else {
+        return run.steps;
+    };
 
-        let level = match i % 4 {
-            0 => "INFO ",
-            1 => "DEBUG",
-            2 => "WARN ",
-            _ => "ERROR",
-        };
+    let Some(token) = run
+        .step("authenticate", async {
+            let token = selftest_login(&server).await?;
+            Ok((
+                token,
+                format!("{} logged in with the mock provider", SELFTEST_USER),
+            ))
+        })
+        .await
+    else {
+        return run.steps;
+    };
 
-        let message = match i % 4 {
-            0 => "Service started successfully",
-            1 => "Processing client request",
-            2 => "Connection timeout, retrying",
-            _ => "Failed to connect to database",
-        };
+    let client =
+        crate::client::Client::new(addr.ip().to_string(), addr.port(), 5).with_auth(Some(token));
 
-        logs.push(format!(
-            "{} {} rcpdaemon: {}",
-            formatted_time, level, message
-        ));
+    run.step("ping", async {
+        let rtt = client.ping().await?;
+        Ok(((), format!("pong in {} ms", rtt.as_millis())))
+    })
+    .await;
+
+    run.step("session info", async {
+        let session = client.session_info().await?;
+        if session.user.as_deref() != Some(SELFTEST_USER) {
+            anyhow::bail!(
+                "Session belongs to {:?}, not {}",
+                session.user,
+                SELFTEST_USER
+            );
+        }
+        Ok((
+            (),
+            format!(
+                "session {} as {} with {}",
+                session.session_id,
+                SELFTEST_USER,
+                session.permissions.join(", ")
+            ),
+        ))
+    })
+    .await;
+
+    let _ = server.stop().await;
+    run.steps
+}
+
+/// Handle the self-test command
+#[cfg(feature = "cli")]
+pub async fn handle_selftest(dev: bool, formatter: &OutputFormatter) -> Result<()> {
+    if !dev {
+        return Err(crate::cli::error::CliError::ValidationError(
+            "diag selftest is a developer command; run it with --dev".to_string(),
+        )
+        .into());
     }
 
-    // Reverse to show newest logs last (chronological order)
-    logs.reverse();
+    let steps = run_selftest().await;
+    let passed = steps.iter().all(|step| step.passed);
 
-    Ok(logs)
+    if formatter.json_output {
+        formatter
+            .json(&steps)
+            .unwrap_or_else(|e| formatter.error(&format!("Failed to format results: {}", e)));
+    } else {
+        formatter.table(vec!["Step", "Result", "Time", "Detail"], |table| {
+            for step in &steps {
+                let time = format!("{} ms", step.duration_ms);
+                table.add_row(vec![
+                    step.name.as_str(),
+                    if step.passed { "pass" } else { "FAIL" },
+                    time.as_str(),
+                    step.detail.as_str(),
+                ]);
+            }
+        });
+    }
+
+    if !passed {
+        return Err(crate::cli::error::CliError::Other("Self-test failed".to_string()).into());
+    }
+    formatter.success("Self-test passed");
+    Ok(())
 }
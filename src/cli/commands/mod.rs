@@ -26,14 +26,14 @@ pub mod completions;
 #[cfg(feature = "cli")]
 pub mod diag;
 
+#[cfg(feature = "cli")]
+pub mod auth;
+
 // Future modules to implement:
 // #[cfg(feature = "cli")]
 // pub mod logs;
 //
 // #[cfg(feature = "cli")]
-// pub mod auth;
-//
-// #[cfg(feature = "cli")]
 // pub mod batch;
 //
 // #[cfg(feature = "cli")]
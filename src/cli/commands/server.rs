@@ -11,11 +11,39 @@ use crate::cli::utils::OutputFormatter;
 #[cfg(feature = "cli")]
 use anyhow::Result;
 
-/// Handle server status command
+/// Handle server status command: whether the daemon runs, and since when
 #[cfg(feature = "cli")]
 pub async fn handle_status(
     client: &ServiceClient,
     formatter: &OutputFormatter,
+) -> Result<(), CliError> {
+    let status = client.get_status().await?;
+
+    if formatter.json_output {
+        formatter.json(&status)?;
+        return Ok(());
+    }
+
+    formatter.info(&format!(
+        "Server: {}",
+        if status.running { "running" } else { "stopped" }
+    ));
+    formatter.info(&format!("Version: {}", status.version));
+    if let Some(pid) = status.pid {
+        formatter.info(&format!("PID: {}", pid));
+    }
+    if let Some(uptime) = &status.uptime {
+        formatter.info(&format!("Uptime: {}", uptime));
+    }
+
+    Ok(())
+}
+
+/// Handle server info command: everything `server/info` reports
+#[cfg(feature = "cli")]
+pub async fn handle_info(
+    client: &ServiceClient,
+    formatter: &OutputFormatter,
 ) -> Result<(), CliError> {
     let info = client.get_server_info().await?;
 
@@ -45,25 +73,28 @@ pub async fn handle_status(
 /// Handle server restart command
 #[cfg(feature = "cli")]
 pub async fn handle_restart(
-    _client: &ServiceClient,
+    client: &ServiceClient,
     formatter: &OutputFormatter,
+    yes: bool,
 ) -> Result<(), CliError> {
     let request = crate::cli::utils::confirmation::ConfirmationRequest::new()
         .with_prompt("Are you sure you want to restart the RCP server?")
-        .with_default(false);
+        .with_default(false)
+        .with_assume_yes(yes);
 
     if !request.ask() {
         formatter.info("Server restart cancelled");
         return Ok(());
     }
 
-    // This is a placeholder - in real implementation, it would call a specific API endpoint
     formatter.info("Restarting server...");
+    let status = client.restart_server().await?;
 
-    // Here we would make an actual API call to restart the server
-    // client.restart_server().await?;
-
-    formatter.success("Server restarting...");
+    if formatter.json_output {
+        formatter.json(&status)?;
+        return Ok(());
+    }
+    formatter.success(&format!("Server restarted (version {})", status.version));
     Ok(())
 }
 
@@ -72,40 +103,61 @@ pub async fn handle_restart(
 pub mod config {
     use super::*;
 
-    /// Handle server config display command
+    /// Handle server config display command: the running configuration,
+    /// secrets redacted
     #[cfg(feature = "cli")]
     pub async fn handle_display(
-        _client: &ServiceClient,
+        client: &ServiceClient,
         formatter: &OutputFormatter,
     ) -> Result<(), CliError> {
-        // This is a placeholder - in real implementation, it would fetch actual server config
-        formatter.info("Server configuration:");
-        formatter.info("TLS: disabled");
-        formatter.info("Address: 0.0.0.0");
-        formatter.info("Port: 8716");
+        let config = client.get_running_config().await?;
 
-        // Here we would make an actual API call to get the server configuration
-        // let config = client.get_server_config().await?;
-        // formatter.json(&config)?;
+        if formatter.json_output {
+            formatter.json(&config)?;
+            return Ok(());
+        }
 
+        if !formatter.quiet {
+            for line in config_lines(&config) {
+                println!("{}", line);
+            }
+        }
         Ok(())
     }
 
+    /// A configuration as `key = value` lines, one per setting, sorted by key
+    pub fn config_lines(config: &serde_json::Value) -> Vec<String> {
+        let mut keys = std::collections::BTreeMap::new();
+        crate::cli::commands::diag::flatten_config("", config, &mut keys);
+        keys.into_iter()
+            .map(|(key, value)| format!("{} = {}", key, value))
+            .collect()
+    }
+
     /// Handle server config update command
+    ///
+    /// The daemon saves the change to its config file and applies it without
+    /// a restart; if saving fails the running configuration is left as it was.
     #[cfg(feature = "cli")]
     pub async fn handle_update(
         key: &str,
         value: &str,
-        _client: &ServiceClient,
+        client: &ServiceClient,
         formatter: &OutputFormatter,
     ) -> Result<(), CliError> {
-        // This is a placeholder - in real implementation, it would update server config
-        formatter.info(&format!("Updating server config {} to {}", key, value));
+        let config = client.update_server_config(key, value).await?;
 
-        // Here we would make an actual API call to update the server configuration
-        // client.update_server_config(key, value).await?;
+        if formatter.json_output {
+            formatter.json(&config)?;
+            return Ok(());
+        }
 
-        formatter.success(&format!("Updated {} = {}", key, value));
+        // Show the value as the daemon stored it, which hides secrets
+        let pointer = format!("/{}", key.replace('.', "/"));
+        match config.pointer(&pointer) {
+            Some(stored) => formatter.success(&format!("Updated {} = {}", key, stored)),
+            None => formatter.success(&format!("Updated {}", key)),
+        }
         Ok(())
     }
 }
@@ -52,3 +52,112 @@ pub async fn handle_status(
         }
     }
 }
+
+/// Interval at which `service logs --follow` checks the log file for new output
+#[cfg(feature = "cli")]
+const FOLLOW_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// Log file of the daemon described by `config_path`
+///
+/// Uses the config file's `log_file` if set, otherwise the file a daemonized
+/// process writes its console output to.
+#[cfg(feature = "cli")]
+pub fn daemon_log_path(config_path: &str) -> std::path::PathBuf {
+    crate::config::ServiceConfig::from_file(config_path)
+        .ok()
+        .and_then(|config| config.log_file)
+        .unwrap_or_else(crate::logging::default_daemon_log_path)
+}
+
+/// The last `lines` lines of a log file, and the file length they were read at
+#[cfg(feature = "cli")]
+pub fn tail_log_file(path: &std::path::Path, lines: usize) -> std::io::Result<(Vec<String>, u64)> {
+    let contents = std::fs::read(path)?;
+    let text = String::from_utf8_lossy(&contents);
+    let all: Vec<&str> = text.lines().collect();
+    let skip = all.len().saturating_sub(lines);
+
+    Ok((
+        all[skip..].iter().map(|line| line.to_string()).collect(),
+        contents.len() as u64,
+    ))
+}
+
+/// Complete lines appended to a log file since `offset`, and the new offset
+///
+/// A file shorter than `offset` was truncated or rotated and is read from the start.
+#[cfg(feature = "cli")]
+pub fn read_new_log_lines(
+    path: &std::path::Path,
+    offset: u64,
+) -> std::io::Result<(Vec<String>, u64)> {
+    use std::io::{Read, Seek, SeekFrom};
+
+    let mut file = std::fs::File::open(path)?;
+    let len = file.metadata()?.len();
+    let offset = if len < offset { 0 } else { offset };
+
+    file.seek(SeekFrom::Start(offset))?;
+    let mut buf = Vec::new();
+    file.read_to_end(&mut buf)?;
+
+    // Leave a partially written last line for the next poll
+    let complete = buf.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+    let lines = String::from_utf8_lossy(&buf[..complete])
+        .lines()
+        .map(|line| line.to_string())
+        .collect();
+
+    Ok((lines, offset + complete as u64))
+}
+
+/// Print one log line, as a JSON object per line when `--json` is set
+#[cfg(feature = "cli")]
+fn print_log_line(line: &str, formatter: &OutputFormatter) {
+    if formatter.json_output {
+        println!("{}", serde_json::json!({ "line": line }));
+    } else {
+        println!("{}", line);
+    }
+}
+
+/// Handle service logs command: print the tail of the daemon log file
+///
+/// With `follow`, keeps printing appended lines until interrupted.
+#[cfg(feature = "cli")]
+pub async fn handle_logs(
+    lines: usize,
+    follow: bool,
+    log_path: &std::path::Path,
+    formatter: &OutputFormatter,
+) -> Result<(), CliError> {
+    let (tail, mut offset) = tail_log_file(log_path, lines).map_err(|e| {
+        CliError::FileSystemError(format!("Failed to read {}: {}", log_path.display(), e))
+    })?;
+
+    if formatter.json_output && !follow {
+        formatter.json(&tail)?;
+        return Ok(());
+    }
+
+    for line in &tail {
+        print_log_line(line, formatter);
+    }
+
+    if !follow {
+        return Ok(());
+    }
+
+    loop {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => return Ok(()),
+            _ = tokio::time::sleep(FOLLOW_POLL_INTERVAL) => {}
+        }
+
+        let (new_lines, new_offset) = read_new_log_lines(log_path, offset)?;
+        for line in &new_lines {
+            print_log_line(line, formatter);
+        }
+        offset = new_offset;
+    }
+}
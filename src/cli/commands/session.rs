@@ -7,11 +7,17 @@
 use anyhow::Result;
 #[cfg(feature = "cli")]
 use std::fmt::{Display, Formatter};
+#[cfg(feature = "cli")]
+use std::net::IpAddr;
 
 #[cfg(feature = "cli")]
 use crate::cli::service::ServiceClient;
 #[cfg(feature = "cli")]
+use crate::cli::utils::confirmation::ConfirmationRequest;
+#[cfg(feature = "cli")]
 use crate::cli::utils::OutputFormatter;
+#[cfg(feature = "cli")]
+use crate::client::types::{format_client_address, SessionInfo};
 
 /// Session representation
 #[cfg(feature = "cli")]
@@ -20,10 +26,46 @@ pub struct Session {
     pub id: String,
     pub user_id: String,
     pub username: String,
-    pub client_ip: String,
+    pub client_ip: IpAddr,
+    #[serde(default)]
+    pub client_port: Option<u16>,
     pub connected_at: String,
     pub idle_time: u64,
-    pub active_apps: Vec<String>,
+    /// Names of the applications running in the session, if they were asked for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub active_apps: Option<Vec<String>>,
+}
+
+#[cfg(feature = "cli")]
+impl Session {
+    /// A session as the daemon reports it, idle since its last request
+    pub fn from_info(info: SessionInfo, now: chrono::DateTime<chrono::Utc>) -> Self {
+        let idle_time = chrono::DateTime::parse_from_rfc3339(&info.last_active)
+            .map(|last| {
+                (now - last.with_timezone(&chrono::Utc))
+                    .num_seconds()
+                    .max(0) as u64
+            })
+            .unwrap_or(0);
+
+        Self {
+            id: info.id,
+            user_id: info.user_id,
+            username: info.username,
+            client_ip: info.client_ip,
+            client_port: info.client_port,
+            connected_at: info.created_at,
+            idle_time,
+            active_apps: info
+                .apps
+                .map(|apps| apps.into_iter().map(|app| app.name).collect()),
+        }
+    }
+
+    /// Client address for display, e.g. `192.168.1.101:50123` or `[::1]:50123`
+    pub fn client_address(&self) -> String {
+        format_client_address(self.client_ip, self.client_port)
+    }
 }
 
 #[cfg(feature = "cli")]
@@ -31,103 +73,101 @@ impl Display for Session {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "Session ID: {}\nUser: {} ({})\nClient IP: {}\nConnected At: {}\nIdle Time: {} seconds\nActive Apps: {}",
+            "Session ID: {}\nUser: {} ({})\nClient Address: {}\nConnected At: {}\nIdle Time: {} seconds",
             self.id,
             self.username,
             self.user_id,
-            self.client_ip,
+            self.client_address(),
             self.connected_at,
             self.idle_time,
-            if self.active_apps.is_empty() {
-                "None".to_string()
-            } else {
-                self.active_apps.join(", ")
-            }
-        )
+        )?;
+        match &self.active_apps {
+            Some(apps) if apps.is_empty() => write!(f, "\nActive Apps: None"),
+            Some(apps) => write!(f, "\nActive Apps: {}", apps.join(", ")),
+            None => Ok(()),
+        }
     }
 }
 
 /// Handle listing sessions
+///
+/// With `--json` (or another structured format) the sessions are written as
+/// the daemon reports them, one
+/// [`SessionInfo`](crate::client::types::SessionInfo) object per session, so
+/// scripts do not depend on the table's columns.
 #[cfg(feature = "cli")]
-pub async fn handle_list(_client: &ServiceClient, formatter: &OutputFormatter) -> Result<()> {
-    // This is a placeholder implementation - replace with actual client call
-    // Format: client.list_sessions().await
-
-    // Sample sessions for demonstration
-    let sessions = vec![
-        Session {
-            id: "sess_12345".to_string(),
-            user_id: "user_1".to_string(),
-            username: "admin".to_string(),
-            client_ip: "192.168.1.101".to_string(),
-            connected_at: "2024-05-14T09:30:00Z".to_string(),
-            idle_time: 120,
-            active_apps: vec!["notepad".to_string(), "calculator".to_string()],
-        },
-        Session {
-            id: "sess_67890".to_string(),
-            user_id: "user_2".to_string(),
-            username: "user1".to_string(),
-            client_ip: "192.168.1.102".to_string(),
-            connected_at: "2024-05-14T10:15:00Z".to_string(),
-            idle_time: 45,
-            active_apps: vec!["browser".to_string()],
-        },
-    ];
+pub async fn handle_list(client: &ServiceClient, formatter: &OutputFormatter) -> Result<()> {
+    let sessions = client.list_sessions().await?;
+
+    if formatter.json_output {
+        formatter
+            .json(&sessions)
+            .unwrap_or_else(|e| formatter.error(&format!("Failed to format sessions: {}", e)));
+        return Ok(());
+    }
 
     if sessions.is_empty() {
         formatter.info("No active sessions found");
-    } else {
-        formatter.table(
-            vec!["ID", "User", "IP Address", "Connected", "Idle (s)", "Apps"],
-            |table| {
-                for s in &sessions {
-                    table.add_row(vec![
-                        &s.id,
-                        &s.username,
-                        &s.client_ip,
-                        &s.connected_at,
-                        &s.idle_time.to_string(),
-                        if s.active_apps.is_empty() {
-                            "None"
-                        } else {
-                            "Multiple"
-                        },
-                    ]);
-                }
-            },
-        );
+        return Ok(());
     }
 
+    formatter.table(
+        vec![
+            "ID",
+            "User",
+            "Address",
+            "Connected",
+            "Last Active",
+            "Status",
+        ],
+        |table| {
+            for s in &sessions {
+                table.add_row(vec![
+                    &s.id,
+                    &s.username,
+                    &s.client_address(),
+                    &s.created_at,
+                    &s.last_active,
+                    if s.active { "active" } else { "inactive" },
+                ]);
+            }
+        },
+    );
+
     Ok(())
 }
 
 /// Handle showing session details
+///
+/// With `include_apps` the applications running in the session are listed
+/// too. Structured output is the daemon's
+/// [`SessionInfo`](crate::client::types::SessionInfo), as for `session list`.
 #[cfg(feature = "cli")]
 pub async fn handle_info(
     session_id: &str,
-    _client: &ServiceClient,
+    include_apps: bool,
+    client: &ServiceClient,
     formatter: &OutputFormatter,
 ) -> Result<()> {
-    // This is a placeholder implementation - replace with actual client call
-    // Format: client.get_session(session_id).await
-
-    // Sample session for demonstration
-    let session = Session {
-        id: session_id.to_string(),
-        user_id: "user_1".to_string(),
-        username: "admin".to_string(),
-        client_ip: "192.168.1.101".to_string(),
-        connected_at: "2024-05-14T09:30:00Z".to_string(),
-        idle_time: 120,
-        active_apps: vec!["notepad".to_string(), "calculator".to_string()],
-    };
-
-    formatter
-        .json(&session)
-        .unwrap_or_else(|e| formatter.error(&format!("Failed to format session data: {}", e)));
+    if uuid::Uuid::parse_str(session_id).is_err() {
+        return Err(crate::cli::error::CliError::ValidationError(format!(
+            "Invalid session ID: {} (expected a UUID)",
+            session_id
+        ))
+        .into());
+    }
 
-    Ok(())
+    let info = client.get_session(session_id, include_apps).await?;
+
+    if formatter.json_output {
+        formatter
+            .json(&info)
+            .unwrap_or_else(|e| formatter.error(&format!("Failed to format session data: {}", e)));
+        return Ok(());
+    }
+
+    let session = Session::from_info(info, chrono::Utc::now());
+    formatter.output_item(&session, "Session Information")
 }
 
 /// Handle disconnecting a session
@@ -136,7 +176,17 @@ pub async fn handle_disconnect(
     session_id: &str,
     _client: &ServiceClient,
     formatter: &OutputFormatter,
+    yes: bool,
 ) -> Result<()> {
+    let request = ConfirmationRequest::new()
+        .with_prompt(&format!("Close session '{}'?", session_id))
+        .with_default(false)
+        .with_assume_yes(yes);
+    if !request.ask() {
+        formatter.info("Session close cancelled");
+        return Ok(());
+    }
+
     // This is a placeholder implementation - replace with actual client call
     // Format: client.disconnect_session(session_id).await
 
@@ -147,3 +197,35 @@ pub async fn handle_disconnect(
 
     Ok(())
 }
+
+/// Handle kicking a session with a reason
+#[cfg(feature = "cli")]
+pub async fn handle_kick(
+    session_id: &str,
+    reason: Option<&str>,
+    client: &ServiceClient,
+    formatter: &OutputFormatter,
+    yes: bool,
+) -> Result<()> {
+    if uuid::Uuid::parse_str(session_id).is_err() {
+        return Err(crate::cli::error::CliError::ValidationError(format!(
+            "Invalid session ID: {} (expected a UUID)",
+            session_id
+        ))
+        .into());
+    }
+
+    let request = ConfirmationRequest::new()
+        .with_prompt(&format!("Kick session '{}'?", session_id))
+        .with_default(false)
+        .with_assume_yes(yes);
+    if !request.ask() {
+        formatter.info("Session kick cancelled");
+        return Ok(());
+    }
+
+    client.kick_session(session_id, reason).await?;
+    formatter.success(&format!("Session '{}' kicked", session_id));
+
+    Ok(())
+}
@@ -7,7 +7,9 @@
 use anyhow::Result;
 
 #[cfg(feature = "cli")]
-use crate::cli::service::ServiceClient;
+use crate::cli::service::{ImportMode, ServiceClient, UserExport, UserRole, UserUpdate};
+#[cfg(feature = "cli")]
+use crate::cli::utils::confirmation::ConfirmationRequest;
 #[cfg(feature = "cli")]
 use crate::cli::utils::OutputFormatter;
 
@@ -136,7 +138,17 @@ pub async fn handle_delete(
     user_id: &str,
     _client: &ServiceClient,
     formatter: &OutputFormatter,
+    yes: bool,
 ) -> Result<()> {
+    let request = ConfirmationRequest::new()
+        .with_prompt(&format!("Delete user '{}'?", user_id))
+        .with_default(false)
+        .with_assume_yes(yes);
+    if !request.ask() {
+        formatter.info("User deletion cancelled");
+        return Ok(());
+    }
+
     // This is a placeholder implementation - replace with actual client call
     // Format: client.delete_user(user_id).await
 
@@ -169,30 +181,119 @@ pub async fn handle_info(
     Ok(())
 }
 
-/// Handle updating a user
+/// Handle updating a user's full name, email or role
+///
+/// Fields left as `None` are not changed; an empty string clears the full
+/// name or email.
 #[cfg(feature = "cli")]
 pub async fn handle_update(
-    user_id: &str,
-    password: Option<&str>,
-    is_admin: Option<bool>,
-    _client: &ServiceClient,
+    username: &str,
+    full_name: Option<&str>,
+    email: Option<&str>,
+    role: Option<&str>,
+    client: &ServiceClient,
     formatter: &OutputFormatter,
 ) -> Result<()> {
-    // This is a placeholder implementation - replace with actual client call
-    // Format: client.update_user(user_id, password, is_admin).await
+    let role = role
+        .map(|role| {
+            role.parse::<UserRole>()
+                .map_err(|e| crate::cli::error::CliError::ValidationError(e.to_string()))
+        })
+        .transpose()?;
+    let update = UserUpdate {
+        full_name: full_name.map(str::to_string),
+        email: email.map(str::to_string),
+        role,
+    };
+    if update.is_empty() {
+        return Err(crate::cli::error::CliError::ValidationError(
+            "Nothing to update: give --full-name, --email or --role".to_string(),
+        )
+        .into());
+    }
 
-    formatter.output_success(&format!("User '{}' updated successfully", user_id));
+    let user = client.update_user(username, &update).await?;
 
-    if let Some(is_admin) = is_admin {
-        formatter.info(&format!(
-            "Admin privileges {}",
-            if is_admin { "granted" } else { "revoked" }
-        ));
+    if formatter.json_output {
+        formatter
+            .json(&user)
+            .unwrap_or_else(|e| formatter.error(&format!("Failed to format user: {}", e)));
+        return Ok(());
     }
 
-    if password.is_some() {
-        formatter.info("Password changed");
+    formatter.success(&format!("User '{}' updated", user.username));
+    formatter.info(&format!(
+        "Full name: {}",
+        user.full_name.as_deref().unwrap_or("-")
+    ));
+    formatter.info(&format!("Email: {}", user.email.as_deref().unwrap_or("-")));
+    formatter.info(&format!("Role: {}", user.role.as_str()));
+
+    Ok(())
+}
+
+/// Handle exporting the internal provider's users to a file
+///
+/// The file holds password hashes, so it is created readable only by its owner.
+#[cfg(feature = "cli")]
+pub async fn handle_export(
+    output: &str,
+    client: &ServiceClient,
+    formatter: &OutputFormatter,
+) -> Result<()> {
+    let export = client.export_users().await?;
+    let data = serde_json::to_string_pretty(&export)?;
+    crate::files::write_with_mode(output, data, crate::files::PRIVATE_FILE_MODE).map_err(|e| {
+        crate::cli::error::CliError::Other(format!("Failed to write {}: {}", output, e))
+    })?;
+
+    formatter.success(&format!(
+        "Exported {} users to {}",
+        export.users.len(),
+        output
+    ));
+    Ok(())
+}
+
+/// Handle importing users from a file written by `user export`
+#[cfg(feature = "cli")]
+pub async fn handle_import(
+    file: &str,
+    mode: ImportMode,
+    client: &ServiceClient,
+    formatter: &OutputFormatter,
+) -> Result<()> {
+    let data = std::fs::read_to_string(file).map_err(|e| {
+        crate::cli::error::CliError::Other(format!("Failed to read {}: {}", file, e))
+    })?;
+    let export: UserExport = serde_json::from_str(&data).map_err(|e| {
+        crate::cli::error::CliError::ValidationError(format!(
+            "{} is not a user export: {}",
+            file, e
+        ))
+    })?;
+
+    let report = client.import_users(&export, mode).await?;
+
+    if formatter.json_output {
+        formatter
+            .json(&report)
+            .unwrap_or_else(|e| formatter.error(&format!("Failed to format report: {}", e)));
+        return Ok(());
     }
 
+    formatter.success(&format!(
+        "Imported users from {}: {} added, {} replaced, {} skipped",
+        file,
+        report.added.len(),
+        report.replaced.len(),
+        report.skipped.len()
+    ));
+    if !report.skipped.is_empty() {
+        formatter.warning(&format!(
+            "Kept existing users {}; import with --replace to overwrite them",
+            report.skipped.join(", ")
+        ));
+    }
     Ok(())
 }
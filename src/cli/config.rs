@@ -48,10 +48,14 @@ pub struct ServiceConfig {
 
     /// Skip TLS verification
     pub skip_verify: bool,
+
+    /// Auth token presented to the daemon
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
 }
 
 /// Output format options
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, clap::ValueEnum)]
 pub enum OutputFormat {
     /// Text output
     Text,
@@ -79,11 +83,12 @@ impl Default for GlobalConfig {
 impl Default for ServiceConfig {
     fn default() -> Self {
         Self {
-            host: "localhost".to_string(),
-            port: 5000,
+            host: "127.0.0.1".to_string(),
+            port: 8716,
             timeout: 30,
             use_tls: false,
             skip_verify: false,
+            token: None,
         }
     }
 }
@@ -13,6 +13,10 @@ pub enum CliError {
     #[error("Communication error: {0}")]
     CommunicationError(String),
 
+    /// The daemon refused the connection and its PID file says it is not running
+    #[error("rcpdaemon is not running; start it with `rcpdaemon daemon start`")]
+    DaemonNotRunning,
+
     /// Authentication error
     #[error("Authentication error: {0}")]
     AuthenticationError(String),
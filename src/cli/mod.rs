@@ -21,71 +21,198 @@ pub mod types;
 pub mod service;
 
 #[cfg(feature = "cli")]
-use anyhow::Result;
+use anyhow::{Context, Result};
 #[cfg(feature = "cli")]
-use service::ServiceClient;
+use service::{DaemonTarget, ServiceClient};
+#[cfg(feature = "cli")]
+use std::time::Duration;
 #[cfg(feature = "cli")]
 use types::{Cli, RcpdaemonCommand};
 #[cfg(feature = "cli")]
 use utils::OutputFormatter;
 
+/// Exit code of a command that needs the daemon when it is not running, the
+/// code LSB init scripts use for the same situation
+#[cfg(feature = "cli")]
+pub const NOT_RUNNING_EXIT_CODE: i32 = 3;
+
 /// Main CLI handler function
 #[cfg(feature = "cli")]
 pub async fn handle_cli(cli: Cli) -> Result<()> {
     // Create output formatter
-    let formatter = OutputFormatter::new(cli.json, true, false);
+    let formatter = output_formatter(&cli);
+
+    if cli.config_check {
+        return check_config(&cli, &formatter);
+    }
 
     // Create service client for commands that need it
-    let client = ServiceClient::new("127.0.0.1".to_string(), 8716, 30);
+    let client = ServiceClient::for_target(&daemon_target(&cli)).with_pid_file(pid_file(&cli));
 
     match cli.command {
-        Some(RcpdaemonCommand::Daemon { command }) => {
-            match command {
-                Some(daemon_cmd) => {
-                    // Handle daemon commands (start, stop, restart, status)
-                    formatter.info(&format!("Daemon command: {:?}", daemon_cmd));
+        Some(RcpdaemonCommand::Daemon { ref command }) => match command {
+            Some(types::DaemonCommand::Start(wait)) => {
+                start_daemon(&cli, load_service_config(&cli)?, wait, &client, &formatter).await?;
+            }
+            Some(types::DaemonCommand::Stop) => {
+                crate::daemon::stop(&pid_file(&cli))?;
+                formatter.success("Daemon stopped");
+            }
+            Some(types::DaemonCommand::Restart(wait)) => {
+                let config = load_service_config(&cli)?;
+                let pid_file = crate::daemon::pid_file_path(&config);
+                let old_pid = crate::daemon::daemon_status(&pid_file)
+                    .ok()
+                    .and_then(|s| s.pid);
+                crate::daemon::stop(&pid_file)?;
+                // Until the old daemon is gone it would answer the readiness check
+                if let (true, Some(pid)) = (wait.wait, old_pid) {
+                    crate::daemon::wait_for_exit(pid, Duration::from_secs(wait.timeout)).await?;
                 }
-                None => {
-                    formatter.info("No daemon subcommand specified");
+                start_daemon(&cli, config, wait, &client, &formatter).await?;
+            }
+            Some(types::DaemonCommand::Status) => {
+                let status = crate::daemon::daemon_status(&pid_file(&cli))?;
+                if formatter.json_output {
+                    formatter.json(&status)?;
+                } else {
+                    formatter.info(&format!("RCP Service Status: {}", status));
                 }
             }
-        }
-        Some(RcpdaemonCommand::Server { command }) => {
-            commands::server::handle_status(&client, &formatter).await?;
-        }
-        Some(RcpdaemonCommand::Service { command }) => {
-            commands::service::handle_status(&client, &formatter).await?;
-        }
+            None => {
+                formatter.info("No daemon subcommand specified");
+            }
+        },
+        Some(RcpdaemonCommand::Server { command }) => match command {
+            types::ServerCommand::Status => {
+                commands::server::handle_status(&client, &formatter).await?;
+            }
+            types::ServerCommand::Info => {
+                commands::server::handle_info(&client, &formatter).await?;
+            }
+            types::ServerCommand::Restart => {
+                commands::server::handle_restart(&client, &formatter, cli.yes).await?;
+            }
+            types::ServerCommand::Config { action } => match action {
+                types::ServerConfigAction::Display => {
+                    commands::server::config::handle_display(&client, &formatter).await?;
+                }
+                types::ServerConfigAction::Update { key, value } => {
+                    commands::server::config::handle_update(&key, &value, &client, &formatter)
+                        .await?;
+                }
+            },
+        },
+        Some(RcpdaemonCommand::Service { command }) => match command {
+            types::ServiceCommand::Logs { lines, follow } => {
+                let log_path = daemon_log_path(cli.no_config, &cli.config);
+                commands::service::handle_logs(lines, follow, &log_path, &formatter).await?;
+            }
+            types::ServiceCommand::LogLevel { level } => {
+                commands::diag::handle_log_level(&level, &client, &formatter).await?;
+            }
+            _ => {
+                commands::service::handle_status(&client, &formatter).await?;
+            }
+        },
         Some(RcpdaemonCommand::App { ref command }) => {
             let mut cli_mut = cli.clone();
-            commands::app::handle_app_command(&mut cli_mut, command)
+            commands::app::handle_app_command(&mut cli_mut, command, &client)
                 .await
-                .map_err(|e| anyhow::anyhow!("App command error: {}", e))?;
+                .context("App command error")?;
         }
         Some(RcpdaemonCommand::Session { command }) => match command {
             types::SessionCommand::List => {
                 commands::session::handle_list(&client, &formatter).await?;
             }
-            types::SessionCommand::Info { session_id } => {
-                commands::session::handle_info(&session_id, &client, &formatter).await?;
+            types::SessionCommand::Info {
+                session_id,
+                include_apps,
+            } => {
+                commands::session::handle_info(&session_id, include_apps, &client, &formatter)
+                    .await?;
             }
             types::SessionCommand::Close { session_id } => {
-                commands::session::handle_disconnect(&session_id, &client, &formatter).await?;
+                commands::session::handle_disconnect(&session_id, &client, &formatter, cli.yes)
+                    .await?;
+            }
+            types::SessionCommand::Kick { session_id, reason } => {
+                commands::session::handle_kick(
+                    &session_id,
+                    reason.as_deref(),
+                    &client,
+                    &formatter,
+                    cli.yes,
+                )
+                .await?;
             }
         },
         Some(RcpdaemonCommand::User { command }) => match command {
             types::UserCommand::List => {
                 commands::user::handle_list(&client, &formatter).await?;
             }
+            types::UserCommand::Update {
+                username,
+                full_name,
+                email,
+                role,
+            } => {
+                commands::user::handle_update(
+                    &username,
+                    full_name.as_deref(),
+                    email.as_deref(),
+                    role.as_deref(),
+                    &client,
+                    &formatter,
+                )
+                .await?;
+            }
+            types::UserCommand::Export { output } => {
+                commands::user::handle_export(&output, &client, &formatter).await?;
+            }
+            types::UserCommand::Import {
+                file,
+                merge: _,
+                replace,
+            } => {
+                let mode = if replace {
+                    service::ImportMode::Replace
+                } else {
+                    service::ImportMode::Merge
+                };
+                commands::user::handle_import(&file, mode, &client, &formatter).await?;
+            }
             _ => {
                 formatter.info("User command handling not fully implemented");
             }
         },
         Some(RcpdaemonCommand::Config { command }) => {
-            commands::config::handle_config_command(&command, None, &formatter)
+            commands::config::handle_config_command(&command, None, &cli.config, &formatter)
                 .await
                 .map_err(|e| anyhow::anyhow!("Config command error: {}", e))?;
         }
+        Some(RcpdaemonCommand::Auth { command }) => match command {
+            types::AuthCommand::Whoami => {
+                commands::auth::handle_whoami(&client, &formatter).await?;
+            }
+            types::AuthCommand::Tokens => {
+                commands::auth::handle_list_tokens(&client, &formatter).await?;
+            }
+            types::AuthCommand::Issue {
+                subject,
+                permissions,
+                ttl,
+            } => {
+                commands::auth::handle_issue_token(&subject, permissions, ttl, &client, &formatter)
+                    .await?;
+            }
+            types::AuthCommand::Revoke { jti } => {
+                commands::auth::handle_revoke_token(&jti, &client, &formatter).await?;
+            }
+            types::AuthCommand::Providers => {
+                commands::auth::handle_list_providers(&formatter)?;
+            }
+        },
         Some(RcpdaemonCommand::Diag { command }) => match command {
             types::DiagCommand::System => {
                 commands::diag::handle_system_diag(&formatter).await?;
@@ -94,11 +221,52 @@ pub async fn handle_cli(cli: Cli) -> Result<()> {
                 commands::diag::handle_network_diag(&client, &formatter).await?;
             }
             types::DiagCommand::Logs { lines, follow } => {
-                commands::diag::handle_logs(lines, follow, &formatter).await?;
+                let log_path = daemon_log_path(cli.no_config, &cli.config);
+                commands::service::handle_logs(lines, follow, &log_path, &formatter).await?;
+            }
+            types::DiagCommand::LogLevel { level } => {
+                commands::diag::handle_log_level(&level, &client, &formatter).await?;
+            }
+            types::DiagCommand::Events { count, types } => {
+                commands::diag::handle_events(count, &types, &client, &formatter).await?;
+            }
+            types::DiagCommand::Cluster => {
+                commands::diag::handle_cluster(&client, &formatter).await?;
+            }
+            types::DiagCommand::Metrics { prometheus } => {
+                commands::diag::handle_metrics(prometheus, &client, &formatter).await?;
+            }
+            types::DiagCommand::Bench {
+                target:
+                    types::BenchTarget::Auth {
+                        iterations,
+                        user,
+                        password,
+                    },
+            } => {
+                commands::diag::handle_bench_auth(
+                    iterations,
+                    user.as_deref(),
+                    password.as_deref(),
+                    &client,
+                    &formatter,
+                )
+                .await?;
+            }
+            types::DiagCommand::ConfigDiff { file } => {
+                let path = file.unwrap_or_else(|| cli.config.clone());
+                commands::diag::handle_config_diff(&path, &client, &formatter).await?;
+            }
+            types::DiagCommand::Dump { output, lines } => {
+                commands::diag::handle_dump(&output, lines, &cli.config, &client, &formatter)
+                    .await?;
+            }
+            types::DiagCommand::Selftest => {
+                commands::diag::handle_selftest(cli.dev, &formatter).await?;
             }
         },
         Some(RcpdaemonCommand::Completions { shell }) => {
-            commands::completions::handle_completions_command(shell, None)?;
+            commands::completions::handle_completions_command(shell, None, &formatter)?;
         }
         None => {
             // No command specified, run daemon mode
@@ -110,47 +278,210 @@ pub async fn handle_cli(cli: Cli) -> Result<()> {
     Ok(())
 }
 
-/// Run daemon mode when no command is specified
+/// Validate the service config file for `--config-check`, failing if it has problems
+///
+/// With `--no-config` the built-in defaults are checked.
 #[cfg(feature = "cli")]
-async fn run_daemon_mode(cli: &Cli) -> Result<()> {
-    use crate::config;
-    use log::info;
-    use std::path::PathBuf;
-
-    // Load configuration
-    let config_file = &cli.config;
-    let config = match config::ServiceConfig::from_file(config_file) {
-        Ok(cfg) => {
-            info!("Configuration loaded from {}", config_file);
-            cfg
+pub fn check_config(cli: &Cli, formatter: &OutputFormatter) -> Result<()> {
+    let problems = if cli.no_config {
+        crate::config::ServiceConfig::default().validate()
+    } else {
+        crate::config::ServiceConfig::check_file(&cli.config, cli.strict_config)
+    };
+
+    if formatter.json_output {
+        formatter.json(serde_json::json!({
+            "path": cli.config,
+            "valid": problems.is_empty(),
+            "problems": problems,
+        }))?;
+    } else if problems.is_empty() {
+        formatter.success(&format!("Configuration {} is valid", cli.config));
+    } else {
+        for problem in &problems {
+            formatter.error(problem);
         }
-        Err(e) => {
-            info!(
-                "Failed to load config from {}: {}. Using defaults.",
-                config_file, e
-            );
-            config::ServiceConfig::default()
+    }
+
+    match problems.len() {
+        0 => Ok(()),
+        n => Err(anyhow::anyhow!(
+            "Configuration {} has {} problem(s)",
+            cli.config,
+            n
+        )),
+    }
+}
+
+/// Whether the command runs the daemon in this process
+#[cfg(feature = "cli")]
+pub fn runs_daemon(cli: &Cli) -> bool {
+    !cli.config_check
+        && matches!(
+            cli.command,
+            None | Some(RcpdaemonCommand::Daemon {
+                command: Some(types::DaemonCommand::Start(_) | types::DaemonCommand::Restart(_))
+            })
+        )
+}
+
+/// Let SIGPIPE end the process, as it does for other command-line tools
+///
+/// Rust ignores SIGPIPE, so once the reader of a pipe such as `| head` exits,
+/// every write fails and the print macros panic. With the default action
+/// restored the CLI is terminated quietly instead, and the shell reports the
+/// conventional status 141. Only call this for client commands: the daemon
+/// must survive clients and child processes hanging up.
+#[cfg(feature = "cli")]
+pub fn exit_on_sigpipe() {
+    // SAFETY: restoring a signal's default disposition has no preconditions
+    #[cfg(unix)]
+    unsafe {
+        libc::signal(libc::SIGPIPE, libc::SIG_DFL);
+    }
+}
+
+/// Start the daemon and print its PID; with `--wait`, return only once it answers
+///
+/// Fails, and so exits non-zero, if the daemon is not ready within the
+/// timeout. There is nothing to wait for in the foreground, where this
+/// process is the daemon.
+#[cfg(feature = "cli")]
+async fn start_daemon(
+    cli: &Cli,
+    config: crate::config::ServiceConfig,
+    wait: &types::WaitArgs,
+    client: &ServiceClient,
+    formatter: &OutputFormatter,
+) -> Result<()> {
+    if cli.foreground {
+        return crate::daemon::run(config, true).await;
+    }
+
+    let pid_file = crate::daemon::pid_file_path(&config);
+    let launched = std::time::SystemTime::now();
+    if crate::daemon::launch(config, false).await? == crate::daemon::Detached::Launcher {
+        let pid =
+            crate::daemon::wait_for_pid_file(&pid_file, launched, crate::daemon::PID_FILE_TIMEOUT)
+                .await?;
+        let started = crate::daemon::DaemonStarted { pid, pid_file };
+
+        if wait.wait {
+            let timeout = Duration::from_secs(wait.timeout);
+            let elapsed = crate::daemon::wait_until_ready(client.client(), timeout).await?;
+            if !formatter.json_output {
+                formatter.success(&format!("Daemon ready after {:.1}s", elapsed.as_secs_f64()));
+            }
+        }
+
+        if formatter.json_output {
+            formatter.json(&started)?;
+        } else {
+            formatter.success(&started.to_string());
         }
+    }
+    Ok(())
+}
+
+/// Run daemon mode when no command is specified
+#[cfg(feature = "cli")]
+async fn run_daemon_mode(cli: &Cli) -> Result<()> {
+    crate::daemon::run(load_service_config(cli)?, cli.foreground).await
+}
+
+/// PID file of the daemon: `--pid-file`, then the config's `pid_file`, then
+/// the default
+///
+/// A config file that fails to load falls back to the default, as
+/// [`load_service_config`] does without `--strict-config`.
+#[cfg(feature = "cli")]
+pub fn pid_file(cli: &Cli) -> std::path::PathBuf {
+    if let Some(pid_file) = &cli.pid_file {
+        return pid_file.clone();
+    }
+    if cli.no_config {
+        return crate::daemon::default_pid_file();
+    }
+    crate::config::ServiceConfig::from_file(&cli.config)
+        .map(|config| crate::daemon::pid_file_path(&config))
+        .unwrap_or_else(|_| crate::daemon::default_pid_file())
+}
+
+/// The daemon log file `service logs` and `diag logs` read, given the
+/// `--no-config` and `--config` flags
+#[cfg(feature = "cli")]
+fn daemon_log_path(no_config: bool, config: &str) -> std::path::PathBuf {
+    if no_config {
+        return crate::logging::default_daemon_log_path();
+    }
+    commands::service::daemon_log_path(config)
+}
+
+/// Create the output formatter for the format resolved from `cli`
+///
+/// The CLI config file's format applies unless `--no-config` is given.
+#[cfg(feature = "cli")]
+pub fn output_formatter(cli: &Cli) -> OutputFormatter {
+    let cli_config = if cli.no_config {
+        config::CliConfig::default()
+    } else {
+        utils::load_config(None).unwrap_or_default()
     };
+    OutputFormatter::with_format(cli.output_format(&cli_config.global), true, false)
+}
 
-    #[cfg(feature = "api")]
-    info!("Starting rcpdaemon (with API)...");
+/// Resolve the daemon the CLI talks to
+///
+/// With `--no-config` the CLI config file and environment are skipped, so
+/// only `--host`/`--port`/`--token` override the built-in defaults.
+#[cfg(feature = "cli")]
+pub fn daemon_target(cli: &Cli) -> DaemonTarget {
+    if cli.no_config {
+        let defaults = config::CliConfig::default();
+        return DaemonTarget::resolve_with_env(
+            cli.host.as_deref(),
+            cli.port,
+            &defaults.service,
+            |_| None,
+        )
+        .with_auth_token(cli.token.as_deref());
+    }
+
+    let cli_config = utils::load_config(None).unwrap_or_default();
+    DaemonTarget::resolve(cli.host.as_deref(), cli.port, &cli_config.service)
+        .with_auth_token(cli.token.as_deref())
+}
+
+/// Load the daemon configuration named by `--config`, falling back to defaults
+///
+/// Also applies the config's `log_file` unless `--log-file` was given, and
+/// `--pid-file` over the config's `pid_file`. With `--no-config` no file is
+/// read and the defaults are returned as they are; with `--strict-config` a
+/// file that fails to load is an error instead.
+#[cfg(feature = "cli")]
+pub fn load_service_config(cli: &Cli) -> Result<crate::config::ServiceConfig> {
+    use crate::config;
+    use log::info;
 
-    #[cfg(not(feature = "api"))]
-    info!("Starting rcpdaemon...");
+    if cli.no_config {
+        info!("Ignoring configuration files (--no-config)");
+        return Ok(config::ServiceConfig {
+            pid_file: cli.pid_file.clone(),
+            ..config::ServiceConfig::default()
+        });
+    }
 
-    // Check if we should daemonize
-    if !cli.foreground {
-        let work_dir = std::env::current_dir()?;
-        info!("Daemonizing process in {}", work_dir.display());
-        // Note: daemon functionality will be implemented separately
-        // daemon::daemonize(&work_dir)?;
+    let mut config = config::ServiceConfig::load_or_default(&cli.config, cli.strict_config)?;
+    if cli.pid_file.is_some() {
+        config.pid_file = cli.pid_file.clone();
     }
 
-    // Start the daemon
-    // Note: daemon start functionality will be implemented separately
-    // daemon::start(config, std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")))
+    // The --log-file flag, applied at startup, wins over the config setting
+    if cli.log_file.is_none() {
+        if let Some(log_file) = &config.log_file {
+            crate::logging::set_log_file(Some(log_file))?;
+        }
+    }
 
-    info!("Daemon mode started successfully");
-    Ok(())
+    Ok(config)
 }
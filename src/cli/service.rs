@@ -1,91 +1,151 @@
 //! CLI service client
 //!
 //! This module provides functionality for CLI commands to communicate with the daemon.
+//! The protocol itself lives in [`crate::client`]; this wrapper maps its errors to `CliError`.
 
 #[cfg(feature = "cli")]
-use crate::cli::error::CliError;
-#[cfg(feature = "cli")]
-use anyhow::Result;
-#[cfg(feature = "cli")]
-use serde::{Deserialize, Serialize};
+use crate::cli::config::ServiceConfig as CliServiceConfig;
 #[cfg(feature = "cli")]
-use std::time::Duration;
+use crate::cli::error::CliError;
 #[cfg(feature = "cli")]
-use tokio::net::TcpStream;
+use crate::client::{Client, ClientError, RpcTransport};
 #[cfg(feature = "cli")]
-use tokio::time::timeout;
+use anyhow::Result;
+
 #[cfg(feature = "cli")]
-use uuid::Uuid;
+pub use crate::client::types::{
+    AppInfo, AppInstanceInfo, AppLogLine, AppLogs, AuthBenchReport, Identity, ImportMode,
+    ImportReport, IssuedToken, MetricsSnapshot, PeerStatus, ServerEvent, ServerEventType,
+    ServerInfo, ServiceStatus, SessionInfo, TokenInfo, User, UserExport, UserRole, UserUpdate,
+};
 
-/// Service status information
 #[cfg(feature = "cli")]
-#[derive(Debug, Deserialize, Serialize, Clone)]
-pub struct ServiceStatus {
-    pub running: bool,
-    pub pid: Option<u32>,
-    pub uptime: Option<String>,
-    pub version: String,
+impl From<ClientError> for CliError {
+    fn from(err: ClientError) -> Self {
+        cli_error(err, &crate::daemon::default_pid_file())
+    }
 }
 
-/// Application instance information
+/// `err` as a CLI error, with a refused connection reported as the daemon
+/// not running unless `pid_file` names a live daemon process
 #[cfg(feature = "cli")]
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct AppInstanceInfo {
-    pub id: String,
-    pub app_id: String,
-    pub name: String,
-    pub user_id: String,
-    pub status: String,
-    pub created_at: String,
+fn cli_error(err: ClientError, pid_file: &std::path::Path) -> CliError {
+    match err {
+        ClientError::ConnectionRefused(_) if !local_daemon_running(pid_file) => {
+            CliError::DaemonNotRunning
+        }
+        ClientError::Serialization(msg) => CliError::SerializationError(msg),
+        ClientError::Rpc { message, .. } => CliError::CommunicationError(message),
+        ClientError::PermissionDenied(e) => CliError::AuthorizationError(e.to_string()),
+        other => CliError::CommunicationError(other.to_string()),
+    }
 }
 
-/// Application information
+/// Whether `pid_file` names a live daemon process
 #[cfg(feature = "cli")]
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct AppInfo {
-    pub id: String,
-    pub name: String,
-    pub description: Option<String>,
-    pub version: Option<String>,
-    pub publisher: Option<String>,
-    pub icon_path: Option<String>,
-    pub executable_path: String,
+fn local_daemon_running(pid_file: &std::path::Path) -> bool {
+    crate::daemon::daemon_status(pid_file)
+        .map(|status| status.running)
+        .unwrap_or(false)
 }
 
-/// Server information
+/// Environment variable overriding the daemon host
+#[cfg(feature = "cli")]
+pub const HOST_ENV_VAR: &str = "RCPDAEMON_HOST";
+
+/// Environment variable overriding the daemon port
+#[cfg(feature = "cli")]
+pub const PORT_ENV_VAR: &str = "RCPDAEMON_PORT";
+
+/// Environment variable holding the auth token presented to the daemon
 #[cfg(feature = "cli")]
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct ServerInfo {
-    pub version: String,
-    pub uptime: String,
-    pub address: String,
+pub const TOKEN_ENV_VAR: &str = "RCPDAEMON_TOKEN";
+
+/// Address of the daemon the CLI talks to
+#[cfg(feature = "cli")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DaemonTarget {
+    /// Daemon host
+    pub host: String,
+
+    /// Daemon port
     pub port: u16,
-    pub tls_enabled: bool,
-    pub active_sessions: usize,
-    pub total_sessions: usize,
+
+    /// Request timeout in seconds
+    pub timeout_seconds: u64,
+
+    /// Auth token presented in the handshake, if any
+    pub auth_token: Option<String>,
 }
 
-/// Session information
 #[cfg(feature = "cli")]
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct SessionInfo {
-    pub id: String,
-    pub user_id: String,
-    pub username: String,
-    pub client_ip: String,
-    pub created_at: String,
-    pub expires_at: String,
-    pub last_active: String,
-    pub active: bool,
+impl DaemonTarget {
+    /// Resolve the daemon address from the process environment
+    ///
+    /// Precedence, highest first: the `--host`/`--port` flags, the
+    /// `RCPDAEMON_HOST`/`RCPDAEMON_PORT` environment variables, the CLI config
+    /// file, then the built-in default (which the config carries when unset).
+    /// The auth token comes from `RCPDAEMON_TOKEN` or the config file; the
+    /// `--token` flag is applied with [`with_auth_token`](Self::with_auth_token).
+    pub fn resolve(host: Option<&str>, port: Option<u16>, config: &CliServiceConfig) -> Self {
+        Self::resolve_with_env(host, port, config, |name| std::env::var(name).ok())
+    }
+
+    /// Resolve the daemon address, reading variables through `env`
+    pub fn resolve_with_env<F>(
+        host: Option<&str>,
+        port: Option<u16>,
+        config: &CliServiceConfig,
+        env: F,
+    ) -> Self
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let host = host
+            .map(|host| host.to_string())
+            .or_else(|| env(HOST_ENV_VAR).filter(|host| !host.is_empty()))
+            .unwrap_or_else(|| config.host.clone());
+
+        let port = port
+            .or_else(|| {
+                env(PORT_ENV_VAR).and_then(|value| match value.parse() {
+                    Ok(port) => Some(port),
+                    Err(_) => {
+                        log::warn!("Ignoring invalid {}: {}", PORT_ENV_VAR, value);
+                        None
+                    }
+                })
+            })
+            .unwrap_or(config.port);
+
+        let auth_token = env(TOKEN_ENV_VAR)
+            .filter(|token| !token.is_empty())
+            .or_else(|| config.token.clone());
+
+        Self {
+            host,
+            port,
+            timeout_seconds: config.timeout,
+            auth_token,
+        }
+    }
+
+    /// Override the auth token, keeping the resolved one when `token` is `None`
+    pub fn with_auth_token(mut self, token: Option<&str>) -> Self {
+        if let Some(token) = token {
+            self.auth_token = Some(token.to_string());
+        }
+        self
+    }
 }
 
 /// Service client for CLI to communicate with the daemon
 #[cfg(feature = "cli")]
 pub struct ServiceClient {
-    pub host: String,
-    pub port: u16,
-    pub timeout_seconds: u64,
-    pub auth_token: Option<String>,
+    inner: Client,
+
+    /// PID file of the local daemon, checked when a connection is refused
+    pid_file: std::path::PathBuf,
 }
 
 #[cfg(feature = "cli")]
@@ -93,61 +153,72 @@ impl ServiceClient {
     /// Create a new service client
     pub fn new(host: String, port: u16, timeout_seconds: u64) -> Self {
         Self {
-            host,
-            port,
-            timeout_seconds,
-            auth_token: None,
+            inner: Client::new(host, port, timeout_seconds),
+            pid_file: crate::daemon::default_pid_file(),
         }
     }
 
+    /// Create a service client for a resolved daemon address
+    pub fn for_target(target: &DaemonTarget) -> Self {
+        Self::new(target.host.clone(), target.port, target.timeout_seconds)
+            .with_auth(target.auth_token.clone())
+    }
+
     /// Set authentication token
     pub fn with_auth(mut self, token: Option<String>) -> Self {
-        self.auth_token = token;
+        self.inner = self.inner.with_auth(token);
         self
     }
 
-    /// Get service status
-    pub async fn get_status(&self) -> Result<ServiceStatus, CliError> {
-        let request = self.build_request("status", serde_json::Value::Null)?;
-        let response = self.send_request(request).await?;
+    /// Check `pid_file` for a local daemon when the connection is refused,
+    /// instead of the default PID file
+    pub fn with_pid_file(mut self, pid_file: std::path::PathBuf) -> Self {
+        self.pid_file = pid_file;
+        self
+    }
 
-        let status: ServiceStatus = serde_json::from_value(response)
-            .map_err(|e| CliError::SerializationError(e.to_string()))?;
+    /// Send requests through `transport` instead of a TCP connection
+    pub fn with_transport(mut self, transport: impl RpcTransport + 'static) -> Self {
+        self.inner = self.inner.with_transport(transport);
+        self
+    }
 
-        Ok(status)
+    /// Get the underlying protocol client
+    pub fn client(&self) -> &Client {
+        &self.inner
     }
 
-    /// Get server information
-    pub async fn get_server_info(&self) -> Result<ServerInfo, CliError> {
-        let request = self.build_request("server/info", serde_json::Value::Null)?;
-        let response = self.send_request(request).await?;
+    /// Await a call of the protocol client, mapping its error
+    async fn request<T>(
+        &self,
+        call: impl std::future::Future<Output = crate::client::Result<T>>,
+    ) -> Result<T, CliError> {
+        call.await.map_err(|e| cli_error(e, &self.pid_file))
+    }
 
-        let info: ServerInfo = serde_json::from_value(response)
-            .map_err(|e| CliError::SerializationError(e.to_string()))?;
+    /// Get service status
+    pub async fn get_status(&self) -> Result<ServiceStatus, CliError> {
+        self.request(self.inner.get_status()).await
+    }
 
-        Ok(info)
+    /// Get server information
+    pub async fn get_server_info(&self) -> Result<ServerInfo, CliError> {
+        self.request(self.inner.get_server_info()).await
     }
 
-    /// Get list of applications
+    /// Get list of available applications
     pub async fn list_apps(&self) -> Result<Vec<AppInfo>, CliError> {
-        let request = self.build_request("apps/list", serde_json::Value::Null)?;
-        let response = self.send_request(request).await?;
-
-        let apps: Vec<AppInfo> = serde_json::from_value(response)
-            .map_err(|e| CliError::SerializationError(e.to_string()))?;
+        self.request(self.inner.list_apps()).await
+    }
 
-        Ok(apps)
+    /// Get the definition of an application
+    pub async fn get_app_info(&self, app_id: &str) -> Result<AppInfo, CliError> {
+        self.request(self.inner.get_app_info(app_id)).await
     }
 
     /// Get list of application instances
     pub async fn list_app_instances(&self) -> Result<Vec<AppInstanceInfo>, CliError> {
-        let request = self.build_request("apps/instances", serde_json::Value::Null)?;
-        let response = self.send_request(request).await?;
-
-        let instances: Vec<AppInstanceInfo> = serde_json::from_value(response)
-            .map_err(|e| CliError::SerializationError(e.to_string()))?;
-
-        Ok(instances)
+        self.request(self.inner.list_app_instances()).await
     }
 
     /// Launch an application
@@ -157,144 +228,180 @@ impl ServiceClient {
         user_id: Option<&str>,
         args: Option<Vec<String>>,
     ) -> Result<AppInstanceInfo, CliError> {
-        let params = serde_json::json!({
-            "app_id": app_id,
-            "user_id": user_id,
-            "arguments": args
-        });
+        self.request(self.inner.launch_app(app_id, user_id, args))
+            .await
+    }
 
-        let request = self.build_request("apps/launch", params)?;
-        let response = self.send_request(request).await?;
+    /// Stop an application instance
+    pub async fn stop_app(&self, instance_id: &str) -> Result<(), CliError> {
+        self.request(self.inner.stop_app(instance_id)).await
+    }
 
-        let instance: AppInstanceInfo = serde_json::from_value(response)
-            .map_err(|e| CliError::SerializationError(e.to_string()))?;
+    /// Get recent output of an application instance
+    ///
+    /// With `since`, only lines from that sequence number onwards are returned.
+    pub async fn get_app_logs(
+        &self,
+        instance_id: &str,
+        lines: usize,
+        since: Option<u64>,
+    ) -> Result<AppLogs, CliError> {
+        self.request(self.inner.get_app_logs(instance_id, lines, since))
+            .await
+    }
 
-        Ok(instance)
+    /// Change the daemon log level, returning the previous level
+    pub async fn set_log_level(&self, level: &str) -> Result<String, CliError> {
+        self.request(self.inner.set_log_level(level)).await
     }
 
-    /// Stop an application instance
-    pub async fn stop_app(&self, instance_id: &str) -> Result<(), CliError> {
-        let params = serde_json::json!({
-            "instance_id": instance_id
-        });
+    /// Get the daemon's running server configuration, with secrets redacted
+    pub async fn get_running_config(&self) -> Result<serde_json::Value, CliError> {
+        self.request(self.inner.get_running_config()).await
+    }
+
+    /// Get the daemon's running server configuration, with secrets replaced
+    /// by fingerprints keyed with `salt`
+    pub async fn get_fingerprinted_config(
+        &self,
+        salt: &str,
+    ) -> Result<serde_json::Value, CliError> {
+        self.request(self.inner.get_fingerprinted_config(salt))
+            .await
+    }
+
+    /// Get the daemon's connection, session and request counters
+    pub async fn get_metrics(&self) -> Result<MetricsSnapshot, CliError> {
+        self.request(self.inner.get_metrics()).await
+    }
+
+    /// Change and save one setting of the daemon's server configuration
+    pub async fn update_server_config(
+        &self,
+        key: &str,
+        value: &str,
+    ) -> Result<serde_json::Value, CliError> {
+        self.request(self.inner.update_server_config(key, value))
+            .await
+    }
+
+    /// Restart the daemon's server, returning its status once it is back
+    pub async fn restart_server(&self) -> Result<ServiceStatus, CliError> {
+        self.request(self.inner.restart_server()).await
+    }
+
+    /// Get the most recent server events, optionally limited to some types
+    pub async fn recent_events(
+        &self,
+        count: usize,
+        types: &[ServerEventType],
+    ) -> Result<Vec<ServerEvent>, CliError> {
+        self.request(self.inner.recent_events(count, types)).await
+    }
 
-        let request = self.build_request("apps/stop", params)?;
-        let _response = self.send_request(request).await?;
+    /// What the daemon knows about its cluster peers
+    pub async fn cluster_peers(&self) -> Result<Vec<PeerStatus>, CliError> {
+        self.request(self.inner.cluster_peers()).await
+    }
+
+    /// Benchmark auth validation inside the daemon
+    pub async fn bench_auth(
+        &self,
+        iterations: usize,
+        username: Option<&str>,
+        password: Option<&str>,
+    ) -> Result<AuthBenchReport, CliError> {
+        Ok(self
+            .inner
+            .bench_auth(iterations, username, password)
+            .await?)
+    }
+
+    /// Identity of the calling session, as established by its auth token
+    pub async fn whoami(&self) -> Result<Identity, CliError> {
+        self.request(self.inner.whoami()).await
+    }
+
+    /// List issued auth tokens that have not yet expired
+    pub async fn list_tokens(&self) -> Result<Vec<TokenInfo>, CliError> {
+        self.request(self.inner.list_tokens()).await
+    }
+
+    /// Issue an auth token for `subject` with some of the caller's own permissions
+    pub async fn issue_token(
+        &self,
+        subject: &str,
+        permissions: Vec<String>,
+        ttl: std::time::Duration,
+    ) -> Result<IssuedToken, CliError> {
+        self.request(self.inner.issue_token(subject, permissions, ttl))
+            .await
+    }
+
+    /// Revoke an issued auth token by its ID
+    pub async fn revoke_token(&self, jti: &str) -> Result<TokenInfo, CliError> {
+        self.request(self.inner.revoke_token(jti)).await
+    }
+
+    /// Export the internal provider's users, password hashes included
+    pub async fn export_users(&self) -> Result<UserExport, CliError> {
+        self.request(self.inner.export_users()).await
+    }
+
+    /// Restore users from an export
+    pub async fn import_users(
+        &self,
+        export: &UserExport,
+        mode: ImportMode,
+    ) -> Result<ImportReport, CliError> {
+        self.request(self.inner.import_users(export, mode)).await
+    }
 
-        Ok(())
+    /// Change a user's full name, email or role
+    pub async fn update_user(&self, username: &str, update: &UserUpdate) -> Result<User, CliError> {
+        self.request(self.inner.update_user(username, update)).await
+    }
+
+    /// Call several methods in a single round trip, returning per-call results in order
+    pub async fn call_batch(
+        &self,
+        calls: Vec<(&str, serde_json::Value)>,
+    ) -> Result<Vec<Result<serde_json::Value, CliError>>, CliError> {
+        let results = self.request(self.inner.call_batch(calls)).await?;
+        Ok(results
+            .into_iter()
+            .map(|result| result.map_err(|e| cli_error(e, &self.pid_file)))
+            .collect())
     }
 
     /// Get list of active sessions
     pub async fn list_sessions(&self) -> Result<Vec<SessionInfo>, CliError> {
-        let request = self.build_request("sessions/list", serde_json::Value::Null)?;
-        let response = self.send_request(request).await?;
-
-        let sessions: Vec<SessionInfo> = serde_json::from_value(response)
-            .map_err(|e| CliError::SerializationError(e.to_string()))?;
+        self.request(self.inner.list_sessions()).await
+    }
 
-        Ok(sessions)
+    /// Get one active session, with its running applications if `include_apps`
+    pub async fn get_session(
+        &self,
+        session_id: &str,
+        include_apps: bool,
+    ) -> Result<SessionInfo, CliError> {
+        self.request(self.inner.get_session(session_id, include_apps))
+            .await
     }
 
     /// Disconnect a session
     pub async fn disconnect_session(&self, session_id: &str) -> Result<(), CliError> {
-        let params = serde_json::json!({
-            "session_id": session_id
-        });
-
-        let request = self.build_request("sessions/disconnect", params)?;
-        let _response = self.send_request(request).await?;
-
-        Ok(())
-    }
-
-    /// Build a request to the service
-    fn build_request(&self, method: &str, params: serde_json::Value) -> Result<String, CliError> {
-        let request = serde_json::json!({
-            "jsonrpc": "2.0",
-            "id": Uuid::new_v4().to_string(),
-            "method": method,
-            "params": params,
-            "auth": self.auth_token
-        });
-
-        serde_json::to_string(&request).map_err(|e| CliError::SerializationError(e.to_string()))
-    }
-
-    /// Send a request to the service
-    async fn send_request(&self, request: String) -> Result<serde_json::Value, CliError> {
-        // Connect to the service
-        let address = format!("{}:{}", self.host, self.port);
-        let stream_result = timeout(
-            Duration::from_secs(self.timeout_seconds),
-            TcpStream::connect(&address),
-        )
-        .await;
-
-        let mut stream = match stream_result {
-            Ok(Ok(stream)) => stream,
-            Ok(Err(e)) => return Err(CliError::CommunicationError(e.to_string())),
-            Err(_) => {
-                return Err(CliError::CommunicationError(format!(
-                    "Operation timed out after {} seconds",
-                    self.timeout_seconds
-                )))
-            }
-        };
-
-        // Send the request
-        let result = timeout(Duration::from_secs(self.timeout_seconds), async {
-            use tokio::io::{AsyncReadExt, AsyncWriteExt};
-
-            // Write request with length prefix
-            let bytes = request.as_bytes();
-            let len = bytes.len() as u32;
-            stream.write_all(&len.to_be_bytes()).await?;
-            stream.write_all(bytes).await?;
-
-            // Read length prefix
-            let mut len_buf = [0u8; 4];
-            stream.read_exact(&mut len_buf).await?;
-            let len = u32::from_be_bytes(len_buf) as usize;
-
-            // Read response
-            let mut response = vec![0u8; len];
-            stream.read_exact(&mut response).await?;
-
-            let response_str = String::from_utf8(response)
-                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
-
-            Ok::<String, std::io::Error>(response_str)
-        })
-        .await;
-
-        let response_str = match result {
-            Ok(Ok(response)) => response,
-            Ok(Err(e)) => return Err(CliError::FileSystemError(e.to_string())),
-            Err(_) => {
-                return Err(CliError::CommunicationError(format!(
-                    "Operation timed out after {} seconds",
-                    self.timeout_seconds
-                )))
-            }
-        };
-
-        // Parse the response
-        let response: serde_json::Value = serde_json::from_str(&response_str)
-            .map_err(|e| CliError::SerializationError(e.to_string()))?;
-
-        // Check for errors
-        if let Some(error) = response.get("error") {
-            let error_msg = error["message"].as_str().unwrap_or("Unknown error");
-            return Err(CliError::CommunicationError(error_msg.to_string()));
-        }
+        self.request(self.inner.disconnect_session(session_id))
+            .await
+    }
 
-        // Extract result
-        if let Some(result) = response.get("result") {
-            Ok(result.clone())
-        } else {
-            Err(CliError::CommunicationError(
-                "Invalid response format".to_string(),
-            ))
-        }
+    /// Disconnect a session, showing it the reason
+    pub async fn kick_session(
+        &self,
+        session_id: &str,
+        reason: Option<&str>,
+    ) -> Result<(), CliError> {
+        self.request(self.inner.kick_session(session_id, reason))
+            .await
     }
 }
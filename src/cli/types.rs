@@ -3,9 +3,15 @@
 //! This module defines types for CLI commands.
 
 #[cfg(feature = "cli")]
-use clap::Parser;
+use crate::cli::config::{GlobalConfig, OutputFormat};
+#[cfg(feature = "cli")]
+use clap::{Args, CommandFactory, FromArgMatches, Parser};
 #[cfg(feature = "cli")]
 use clap_complete::Shell;
+#[cfg(feature = "cli")]
+use std::ffi::OsString;
+#[cfg(feature = "cli")]
+use std::path::PathBuf;
 
 /// Main CLI struct for rcpdaemon
 #[cfg(feature = "cli")]
@@ -16,6 +22,24 @@ pub struct Cli {
     #[clap(short, long, default_value = "rcpdaemon.toml")]
     pub config: String,
 
+    /// Ignore all config files and environment overrides, using built-in defaults
+    /// (`--host`/`--port`/`--token` still apply)
+    #[clap(long, global = true)]
+    pub no_config: bool,
+
+    /// Abort if the config file cannot be loaded instead of using defaults
+    #[clap(long, global = true)]
+    pub strict_config: bool,
+
+    /// Enable developer commands such as `diag selftest`
+    #[clap(long, global = true)]
+    pub dev: bool,
+
+    /// Validate the config file and exit, non-zero if it has problems,
+    /// without starting anything (e.g. for systemd `ExecStartPre`)
+    #[clap(long)]
+    pub config_check: bool,
+
     /// Run in foreground (no daemon)
     #[clap(short, long)]
     pub foreground: bool,
@@ -24,15 +48,175 @@ pub struct Cli {
     #[clap(short, long)]
     pub verbose: bool,
 
-    /// Output in JSON format
+    /// Also write logs to this file, in any run mode
+    #[clap(long, value_name = "PATH")]
+    pub log_file: Option<PathBuf>,
+
+    /// Log levels by component, e.g. `warn,rcpdaemon::auth=debug` (overrides RUST_LOG)
+    #[clap(long, value_name = "FILTER")]
+    pub log_filter: Option<String>,
+
+    /// PID file of the daemon, overriding the config's `pid_file`
+    #[clap(long, global = true, value_name = "PATH")]
+    pub pid_file: Option<PathBuf>,
+
+    /// Output in JSON format (shorthand for `--format json`)
     #[clap(long)]
     pub json: bool,
 
+    /// Output format, overriding `--json` and the CLI config file
+    #[clap(long, global = true, value_enum, value_name = "FORMAT")]
+    pub format: Option<OutputFormat>,
+
+    /// Daemon host to connect to (overrides RCPDAEMON_HOST and the config file)
+    #[clap(long, global = true, value_name = "HOST")]
+    pub host: Option<String>,
+
+    /// Daemon port to connect to (overrides RCPDAEMON_PORT and the config file)
+    #[clap(long, global = true, value_name = "PORT")]
+    pub port: Option<u16>,
+
+    /// Auth token to present to the daemon (overrides RCPDAEMON_TOKEN and the config file)
+    #[clap(long, global = true, value_name = "TOKEN")]
+    pub token: Option<String>,
+
+    /// Answer yes to confirmation prompts, for scripts
+    #[clap(short = 'y', long, alias = "assume-yes", global = true)]
+    pub yes: bool,
+
     /// Command to execute
     #[clap(subcommand)]
     pub command: Option<RcpdaemonCommand>,
 }
 
+/// Developer subcommands of `diag`, listed in help only with `--dev`
+#[cfg(feature = "cli")]
+const DEV_DIAG_COMMANDS: &[&str] = &["selftest"];
+
+#[cfg(feature = "cli")]
+impl Cli {
+    /// Parse the process arguments, exiting with usage on error
+    pub fn parse_args() -> Self {
+        Self::try_parse_args(std::env::args_os()).unwrap_or_else(|e| e.exit())
+    }
+
+    /// Parse arguments, showing developer commands in help when `--dev` is given
+    pub fn try_parse_args<I, T>(args: I) -> Result<Self, clap::Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        let args: Vec<OsString> = args.into_iter().map(Into::into).collect();
+        let mut command = Self::command();
+        if args.iter().any(|arg| arg == "--dev") {
+            command = command.mut_subcommand("diag", |mut diag| {
+                for name in DEV_DIAG_COMMANDS {
+                    diag = diag.mut_subcommand(name, |sub| sub.hide(false));
+                }
+                diag
+            });
+        }
+
+        let matches = command.try_get_matches_from(args)?;
+        Self::from_arg_matches(&matches)
+    }
+
+    /// Resolve the output format: `--format`, then `--json`, then the CLI config
+    pub fn output_format(&self, config: &GlobalConfig) -> OutputFormat {
+        if let Some(format) = self.format {
+            return format;
+        }
+        if self.json || config.json {
+            return OutputFormat::Json;
+        }
+        config.format
+    }
+}
+
+/// Usage examples shown by `rcpdaemon server --help`
+#[cfg(feature = "cli")]
+const SERVER_EXAMPLES: &str = "\
+Examples:
+  rcpdaemon server status
+  rcpdaemon server info --format yaml
+  rcpdaemon server config display
+  rcpdaemon server config update port 8717
+  rcpdaemon --host 10.0.0.5 --port 8716 server status";
+
+/// Usage examples shown by `rcpdaemon app --help`
+#[cfg(feature = "cli")]
+const APP_EXAMPLES: &str = "\
+Examples:
+  rcpdaemon app list
+  rcpdaemon app info notepad
+  rcpdaemon app launch notepad -- --new-window notes.txt
+  rcpdaemon app instances
+  rcpdaemon app logs 3f2b0c1e-5d4a-4c8e-9a57-2f0d6b1e7c44 -n 50 --follow
+  rcpdaemon app stop 3f2b0c1e-5d4a-4c8e-9a57-2f0d6b1e7c44";
+
+/// Usage examples shown by `rcpdaemon app launch --help`
+#[cfg(feature = "cli")]
+const APP_LAUNCH_EXAMPLES: &str = "\
+Examples:
+  rcpdaemon app launch calculator
+  rcpdaemon app launch notepad --user-id alice notes.txt
+  rcpdaemon app launch browser -- --incognito https://example.com
+
+Everything after the first application argument is passed to the application
+as is. Put `--` ahead of a first argument that starts with `-`.";
+
+/// Usage examples shown by `rcpdaemon session --help`
+#[cfg(feature = "cli")]
+const SESSION_EXAMPLES: &str = "\
+Examples:
+  rcpdaemon session list
+  rcpdaemon --json session list
+  rcpdaemon session info 0d9c6a52-8f3e-4b71-a0c2-5e1f7d3b9a60
+  rcpdaemon session kick 0d9c6a52-8f3e-4b71-a0c2-5e1f7d3b9a60 --reason \"maintenance window\"";
+
+/// Usage examples shown by `rcpdaemon user --help`
+#[cfg(feature = "cli")]
+const USER_EXAMPLES: &str = "\
+Examples:
+  rcpdaemon user list
+  rcpdaemon user create alice 's3cret-passw0rd' --admin
+  rcpdaemon user info user_1
+  rcpdaemon user set-password user_1 'n3w-passw0rd'
+  rcpdaemon user update alice --role guest --email alice@example.com
+  rcpdaemon user delete user_1
+  rcpdaemon user export --output users.json
+  rcpdaemon user import users.json --replace";
+
+/// Usage examples shown by `rcpdaemon config --help`
+#[cfg(feature = "cli")]
+const CONFIG_EXAMPLES: &str = "\
+Examples:
+  rcpdaemon config show
+  rcpdaemon config get port
+  rcpdaemon config set host 10.0.0.5
+  rcpdaemon config set format yaml
+  rcpdaemon config remove timeout
+  rcpdaemon config set server.port 8717
+  rcpdaemon --config /etc/rcpdaemon/rcpdaemon.toml config set auth.required true";
+
+/// Key reference shown by `rcpdaemon config set --help`
+#[cfg(feature = "cli")]
+const CONFIG_SET_EXAMPLES: &str = "\
+CLI keys:
+  host, port, timeout      daemon to connect to
+  use_tls, verify_cert     connection security (true/false)
+  format                   text, json or yaml
+  color, json, quiet       output settings (true/false)
+
+Keys starting with `server.` or `auth.` (short for `server.auth.`) edit the
+daemon config file named by --config instead.
+
+Examples:
+  rcpdaemon config set port 8717
+  rcpdaemon config set verify_cert false
+  rcpdaemon config set server.session.timeout 600
+  rcpdaemon config set auth.psk 'change-me'";
+
 /// Top-level rcpdaemon commands
 #[cfg(feature = "cli")]
 #[derive(Parser, Debug, Clone)]
@@ -45,6 +229,12 @@ pub enum RcpdaemonCommand {
     },
 
     /// Server management commands
+    #[clap(
+        long_about = "Inspect and reconfigure the RCP server of a running daemon.\n\n\
+                      Commands connect to the daemon given by --host/--port, \
+                      RCPDAEMON_HOST/RCPDAEMON_PORT or the CLI config, in that order.",
+        after_help = SERVER_EXAMPLES
+    )]
     Server {
         /// Server subcommand
         #[clap(subcommand)]
@@ -59,6 +249,11 @@ pub enum RcpdaemonCommand {
     },
 
     /// Application management commands
+    #[clap(
+        long_about = "List the applications a daemon can launch, start them for a user, \
+                      and watch or stop the running instances.",
+        after_help = APP_EXAMPLES
+    )]
     App {
         /// Application subcommand
         #[clap(subcommand)]
@@ -66,6 +261,12 @@ pub enum RcpdaemonCommand {
     },
 
     /// Session management commands
+    #[clap(
+        long_about = "List the client sessions connected to a daemon, show their details, \
+                      and disconnect them. Kicking a session requires the admin:sessions \
+                      permission.",
+        after_help = SESSION_EXAMPLES
+    )]
     Session {
         /// Session subcommand
         #[clap(subcommand)]
@@ -73,6 +274,11 @@ pub enum RcpdaemonCommand {
     },
 
     /// User management commands
+    #[clap(
+        long_about = "Manage the users known to the daemon's authentication provider. \
+                      Commands other than `create` take the user's ID.",
+        after_help = USER_EXAMPLES
+    )]
     User {
         /// User subcommand
         #[clap(subcommand)]
@@ -80,12 +286,25 @@ pub enum RcpdaemonCommand {
     },
 
     /// Configuration management commands
+    #[clap(
+        long_about = "Read and change configuration. Plain keys such as `host` or `format` \
+                      belong to the CLI's own config file; keys starting with `server.` or \
+                      `auth.` edit the daemon config file named by --config.",
+        after_help = CONFIG_EXAMPLES
+    )]
     Config {
         /// Config subcommand
         #[clap(subcommand)]
         command: ConfigCommand,
     },
 
+    /// Authentication token commands
+    Auth {
+        /// Auth subcommand
+        #[clap(subcommand)]
+        command: AuthCommand,
+    },
+
     /// Diagnostics commands
     Diag {
         /// Diagnostics subcommand
@@ -106,18 +325,51 @@ pub enum RcpdaemonCommand {
 #[derive(Parser, Debug, Clone)]
 pub enum DaemonCommand {
     /// Start the daemon
-    Start,
+    Start(WaitArgs),
 
     /// Stop the daemon
     Stop,
 
     /// Restart the daemon
-    Restart,
+    Restart(WaitArgs),
 
     /// Show daemon status
     Status,
 }
 
+/// Seconds `--wait` waits for the daemon by default
+#[cfg(feature = "cli")]
+pub const DEFAULT_WAIT_TIMEOUT_SECS: u64 = 30;
+
+/// Options of the commands that start the daemon
+#[cfg(feature = "cli")]
+#[derive(Args, Debug, Clone, PartialEq, Eq)]
+pub struct WaitArgs {
+    /// Return only once the daemon answers, failing if it does not in time
+    /// (ignored with --foreground)
+    #[clap(long)]
+    pub wait: bool,
+
+    /// Seconds to wait for the daemon with --wait
+    #[clap(
+        long,
+        value_name = "SECS",
+        default_value_t = DEFAULT_WAIT_TIMEOUT_SECS,
+        requires = "wait"
+    )]
+    pub timeout: u64,
+}
+
+#[cfg(feature = "cli")]
+impl Default for WaitArgs {
+    fn default() -> Self {
+        Self {
+            wait: false,
+            timeout: DEFAULT_WAIT_TIMEOUT_SECS,
+        }
+    }
+}
+
 /// Service commands
 #[cfg(feature = "cli")]
 #[derive(Parser, Debug, Clone)]
@@ -150,6 +402,12 @@ pub enum ServiceCommand {
         #[clap(short, long)]
         follow: bool,
     },
+
+    /// Change the daemon log level at runtime
+    LogLevel {
+        /// New level (off, error, warn, info, debug, trace)
+        level: String,
+    },
 }
 
 /// Server commands
@@ -159,6 +417,9 @@ pub enum ServerCommand {
     /// Display server status
     Status,
 
+    /// Display server details: version, uptime, listen address, TLS and sessions
+    Info,
+
     /// Restart the server
     Restart,
 
@@ -176,9 +437,10 @@ pub enum ServerConfigAction {
     /// Display server configuration
     Display,
 
-    /// Update server configuration
+    /// Change a setting of the running server and save it to its config file
+    #[clap(visible_alias = "set")]
     Update {
-        /// Configuration key
+        /// Configuration key, e.g. session.timeout
         key: String,
 
         /// Configuration value
@@ -200,6 +462,7 @@ pub enum AppCommand {
     },
 
     /// Launch an application
+    #[clap(after_help = APP_LAUNCH_EXAMPLES)]
     Launch {
         /// Application ID
         app_id: String,
@@ -209,6 +472,10 @@ pub enum AppCommand {
         user_id: Option<String>,
 
         /// Additional arguments to pass to the application
+        ///
+        /// Everything from the first of them on is passed through untouched,
+        /// so options for rcpdaemon itself must come before it.
+        #[clap(trailing_var_arg = true, allow_hyphen_values = true)]
         args: Vec<String>,
     },
 
@@ -220,6 +487,20 @@ pub enum AppCommand {
         /// Instance ID
         instance_id: String,
     },
+
+    /// Show recent stdout/stderr output of a running instance
+    Logs {
+        /// Instance ID
+        instance_id: String,
+
+        /// Number of lines to show
+        #[clap(short = 'n', long, default_value = "100")]
+        lines: usize,
+
+        /// Keep printing new output as it arrives
+        #[clap(short, long)]
+        follow: bool,
+    },
 }
 
 /// Session commands
@@ -233,6 +514,10 @@ pub enum SessionCommand {
     Info {
         /// Session ID
         session_id: String,
+
+        /// Also list the applications running in the session
+        #[clap(long)]
+        include_apps: bool,
     },
 
     /// Close a session
@@ -240,6 +525,50 @@ pub enum SessionCommand {
         /// Session ID
         session_id: String,
     },
+
+    /// Disconnect a session and tell its user why
+    Kick {
+        /// Session ID
+        session_id: String,
+
+        /// Reason shown to the kicked user
+        #[clap(long)]
+        reason: Option<String>,
+    },
+}
+
+/// Authentication commands
+#[cfg(feature = "cli")]
+#[derive(Parser, Debug, Clone)]
+pub enum AuthCommand {
+    /// Show the user, role and permissions of the configured auth token
+    Whoami,
+
+    /// List issued tokens that have not yet expired
+    Tokens,
+
+    /// Issue a token for a user or client, printing it once
+    Issue {
+        /// User or client the token is for
+        subject: String,
+
+        /// Permission to grant, e.g. `read:*`; repeat for several
+        #[clap(long = "permission")]
+        permissions: Vec<String>,
+
+        /// Seconds until the token expires
+        #[clap(long, default_value_t = crate::server::tokens::DEFAULT_TOKEN_TTL_SECS)]
+        ttl: u64,
+    },
+
+    /// Revoke an issued token so the daemon rejects it
+    Revoke {
+        /// Token ID (jti)
+        jti: String,
+    },
+
+    /// List the auth providers this platform supports, for the `provider` setting
+    Providers,
 }
 
 /// Configuration commands
@@ -250,6 +579,7 @@ pub enum ConfigCommand {
     Show,
 
     /// Set a configuration value
+    #[clap(after_help = CONFIG_SET_EXAMPLES)]
     Set {
         /// Configuration key
         key: String,
@@ -311,6 +641,45 @@ pub enum UserCommand {
         /// New password
         password: String,
     },
+
+    /// Change a user's full name, email or role
+    Update {
+        /// Username
+        username: String,
+
+        /// New full name (empty to clear it)
+        #[clap(long, value_name = "NAME")]
+        full_name: Option<String>,
+
+        /// New email address (empty to clear it)
+        #[clap(long)]
+        email: Option<String>,
+
+        /// New role: admin, user or guest
+        #[clap(long)]
+        role: Option<String>,
+    },
+
+    /// Export the internal provider's users, password hashes included, to a file
+    Export {
+        /// File to write the export to, readable only by its owner
+        #[clap(short, long)]
+        output: String,
+    },
+
+    /// Import users from a file written by `user export`
+    Import {
+        /// Export file to read
+        file: String,
+
+        /// Keep users that already exist, skipping their imported copies (the default)
+        #[clap(long, conflicts_with = "replace")]
+        merge: bool,
+
+        /// Replace users that already exist with their imported copies
+        #[clap(long)]
+        replace: bool,
+    },
 }
 
 /// Diagnostic commands
@@ -333,4 +702,86 @@ pub enum DiagCommand {
         #[clap(short, long)]
         follow: bool,
     },
+
+    /// Change the daemon log level at runtime
+    LogLevel {
+        /// New level (off, error, warn, info, debug, trace)
+        level: String,
+    },
+
+    /// Show recent connection, authentication and disconnect events
+    Events {
+        /// Maximum number of events to show
+        #[clap(long, default_value = "50")]
+        count: usize,
+
+        /// Only show events of this type (connected, rejected, authenticated,
+        /// auth_failed, disconnected); may be repeated
+        #[clap(long = "type", value_name = "TYPE")]
+        types: Vec<String>,
+    },
+
+    /// Show the daemon's cluster peers, whether they are reachable and their sessions
+    Cluster,
+
+    /// Show the daemon's connection, session and request counters
+    Metrics {
+        /// Print the counters in the Prometheus text format
+        #[clap(long)]
+        prometheus: bool,
+    },
+
+    /// Measure throughput and latency inside the daemon
+    Bench {
+        #[clap(subcommand)]
+        target: BenchTarget,
+    },
+
+    /// Compare the daemon's running configuration with the config file
+    ConfigDiff {
+        /// Config file to compare against (defaults to --config)
+        #[clap(long, value_name = "PATH")]
+        file: Option<String>,
+    },
+
+    /// Write the config, status, recent events and logs to one JSON file,
+    /// with secrets redacted, for attaching to a bug report
+    Dump {
+        /// File to write the bundle to
+        #[clap(long, short, value_name = "PATH")]
+        output: PathBuf,
+
+        /// Number of log lines to include
+        #[clap(long, default_value = "200")]
+        lines: usize,
+    },
+
+    /// Check the handshake, auth and request path against an in-process server
+    /// (developer command, requires --dev)
+    #[clap(hide = true)]
+    Selftest,
+}
+
+/// What `diag bench` measures
+#[cfg(feature = "cli")]
+#[derive(Parser, Debug, Clone)]
+pub enum BenchTarget {
+    /// Time credential validation and user lookup against the auth provider
+    ///
+    /// Without --user the daemon's in-process mock provider is timed, as a
+    /// baseline; with it the configured provider is, which shows whether
+    /// subprocess-spawning native auth is the bottleneck.
+    Auth {
+        /// Number of validations to run
+        #[clap(long, default_value = "100")]
+        iterations: usize,
+
+        /// Test user to validate against the configured provider
+        #[clap(long, value_name = "NAME")]
+        user: Option<String>,
+
+        /// Password of the test user
+        #[clap(long, requires = "user")]
+        password: Option<String>,
+    },
 }
@@ -1,6 +1,11 @@
 //! Confirmation request utilities for CLI
 //!
 //! This module contains utilities for handling confirmation prompts.
+//!
+//! A prompt is only shown when stdin is a terminal. With `--yes` every
+//! confirmation is granted without asking; otherwise, when there is no one
+//! to ask, as in scripts and cron jobs, the prompt's default is taken.
+//! Destructive commands default to "no", so they fail safe.
 
 use std::io::{self, Write};
 
@@ -9,6 +14,8 @@ use std::io::{self, Write};
 pub struct ConfirmationRequest {
     pub prompt: String,
     pub default: bool,
+    /// Confirm without asking, as with `--yes`
+    pub assume_yes: bool,
 }
 
 #[cfg(feature = "cli")]
@@ -24,6 +31,7 @@ impl ConfirmationRequest {
         Self {
             prompt: "Are you sure?".to_string(),
             default: true,
+            assume_yes: false,
         }
     }
 
@@ -39,8 +47,44 @@ impl ConfirmationRequest {
         self
     }
 
+    /// Confirm without asking when `assume_yes` is set, as by `--yes`
+    pub fn with_assume_yes(mut self, assume_yes: bool) -> Self {
+        self.assume_yes = assume_yes;
+        self
+    }
+
+    /// The answer given without prompting, or `None` if the user must be asked
+    ///
+    /// `--yes` confirms; without it, a non-`interactive` stdin gets the default.
+    pub fn answer_without_prompt(&self, interactive: bool) -> Option<bool> {
+        if self.assume_yes {
+            return Some(true);
+        }
+        if !interactive {
+            return Some(self.default);
+        }
+        None
+    }
+
+    /// Settle the request without reading stdin if it can or must be
+    fn settle(&self) -> Option<bool> {
+        let answer = self.answer_without_prompt(atty::is(atty::Stream::Stdin))?;
+        if !self.assume_yes {
+            eprintln!(
+                "{} [{}] (stdin is not a terminal; pass --yes to confirm)",
+                self.prompt,
+                if answer { "yes" } else { "no" }
+            );
+        }
+        Some(answer)
+    }
+
     /// Ask for confirmation and return the result
     pub fn ask(&self) -> bool {
+        if let Some(answer) = self.settle() {
+            return answer;
+        }
+
         let default_text = if self.default { "Y/n" } else { "y/N" };
         let prompt = format!("{} [{}]: ", self.prompt, default_text);
 
@@ -70,6 +114,10 @@ impl ConfirmationRequest {
 
     /// Ask for confirmation and return the result, with custom yes/no values
     pub fn ask_with_values(&self, yes_values: &[&str], no_values: &[&str]) -> bool {
+        if let Some(answer) = self.settle() {
+            return answer;
+        }
+
         let default_text = if self.default {
             format!("{}/{}", yes_values[0], no_values[0].to_lowercase())
         } else {
@@ -2,12 +2,14 @@
 //!
 //! This module provides utility functions for CLI operations.
 
+#[cfg(feature = "cli")]
+use crate::cli::config::OutputFormat;
 #[cfg(feature = "cli")]
 use crate::cli::error::CliError;
 #[cfg(feature = "cli")]
 use anyhow::Result;
 #[cfg(feature = "cli")]
-use colored::Colorize;
+use colored::{ColoredString, Colorize};
 
 // Submodules
 #[cfg(feature = "cli")]
@@ -17,21 +19,154 @@ pub mod confirmation;
 #[cfg(feature = "cli")]
 pub struct OutputFormatter {
     pub color_enabled: bool,
+    /// Whether output is structured (JSON or YAML) rather than text
     pub json_output: bool,
+    pub format: OutputFormat,
     pub quiet: bool,
 }
 
+/// Decide whether to colorize output
+///
+/// Color must be `requested`, then the usual conventions apply, in order:
+/// `CLICOLOR_FORCE` (other than `0`) forces it on, `NO_COLOR` (any value),
+/// `CLICOLOR=0` or `TERM=dumb` turn it off, and otherwise it is only used
+/// when stdout `is_tty`.
+#[cfg(feature = "cli")]
+pub fn should_colorize<F>(requested: bool, is_tty: bool, env: F) -> bool
+where
+    F: Fn(&str) -> Option<String>,
+{
+    if !requested {
+        return false;
+    }
+
+    let set = |name: &str| env(name).filter(|value| !value.is_empty());
+    if set("CLICOLOR_FORCE").is_some_and(|value| value != "0") {
+        return true;
+    }
+    if set("NO_COLOR").is_some()
+        || set("CLICOLOR").as_deref() == Some("0")
+        || set("TERM").as_deref() == Some("dumb")
+    {
+        return false;
+    }
+
+    is_tty
+}
+
+/// Decide whether to colorize output written to a console
+///
+/// As [`should_colorize`], but color that would be shown on the terminal is
+/// only used if `enable_ansi` can make the console interpret ANSI escapes.
+/// Output that is not going to a terminal never needs it, so `enable_ansi` is
+/// only called when color would otherwise be used on one.
+#[cfg(feature = "cli")]
+pub fn should_colorize_console<F, A>(requested: bool, is_tty: bool, env: F, enable_ansi: A) -> bool
+where
+    F: Fn(&str) -> Option<String>,
+    A: FnOnce() -> bool,
+{
+    should_colorize(requested, is_tty, env) && (!is_tty || enable_ansi())
+}
+
+/// Make the console interpret ANSI escapes, returning whether it does
+///
+/// Consoles on Windows 10 and later do once virtual terminal processing is
+/// turned on; legacy consoles cannot, and get plain output instead of raw
+/// escape sequences around the `INFO:`/`SUCCESS:` prefixes. Terminals
+/// elsewhere always do.
+#[cfg(feature = "cli")]
+pub fn enable_ansi_support() -> bool {
+    #[cfg(windows)]
+    {
+        colored::control::set_virtual_terminal(true).is_ok()
+    }
+    #[cfg(not(windows))]
+    {
+        true
+    }
+}
+
+/// Apply `style` to `text` when `enabled`, or return it as plain text
+#[cfg(feature = "cli")]
+pub fn paint<F>(enabled: bool, text: &str, style: F) -> String
+where
+    F: FnOnce(&str) -> ColoredString,
+{
+    if !enabled {
+        return text.to_string();
+    }
+
+    style(text).to_string()
+}
+
 #[cfg(feature = "cli")]
 impl OutputFormatter {
     /// Create a new formatter with default settings
+    ///
+    /// `color_enabled` requests color; it is only used if the environment and
+    /// terminal allow it (see [`should_colorize_console`]).
     pub fn new(json_output: bool, color_enabled: bool, quiet: bool) -> Self {
+        let format = if json_output {
+            OutputFormat::Json
+        } else {
+            OutputFormat::Text
+        };
+        Self::with_format(format, color_enabled, quiet)
+    }
+
+    /// Create a formatter for the given output format
+    pub fn with_format(format: OutputFormat, color_enabled: bool, quiet: bool) -> Self {
+        let is_tty = atty::is(atty::Stream::Stdout);
         Self {
-            color_enabled,
-            json_output,
+            color_enabled: should_colorize_console(
+                color_enabled,
+                is_tty,
+                |name| std::env::var(name).ok(),
+                enable_ansi_support,
+            ),
+            json_output: format != OutputFormat::Text,
+            format,
             quiet,
         }
     }
 
+    /// Render data in the structured output format, pretty-printed JSON or YAML
+    pub fn render<T: serde::Serialize + ?Sized>(&self, data: &T) -> Result<String, CliError> {
+        match self.format {
+            OutputFormat::Yaml => serde_yaml::to_string(data)
+                .map(|yaml| yaml.trim_end().to_string())
+                .map_err(|e| CliError::SerializationError(e.to_string())),
+            OutputFormat::Json | OutputFormat::Text => Ok(serde_json::to_string_pretty(data)?),
+        }
+    }
+
+    /// Print a status message in the structured output format
+    fn status(&self, status: &str, message: &str) {
+        if self.format == OutputFormat::Yaml {
+            let mut fields = serde_yaml::Mapping::new();
+            fields.insert("status".into(), status.into());
+            fields.insert("message".into(), message.into());
+            if let Ok(yaml) = self.render(&fields) {
+                println!("{}", yaml);
+            }
+            return;
+        }
+
+        println!(
+            "{}",
+            serde_json::json!({ "status": status, "message": message })
+        );
+    }
+
+    /// Apply `style` to `text` if this formatter uses color
+    pub fn paint<F>(&self, text: &str, style: F) -> String
+    where
+        F: FnOnce(&str) -> ColoredString,
+    {
+        paint(self.color_enabled, text, style)
+    }
+
     /// Print success message
     pub fn success(&self, message: &str) {
         if self.quiet {
@@ -39,15 +174,15 @@ impl OutputFormatter {
         }
 
         if self.json_output {
-            println!("{{\"status\":\"success\",\"message\":\"{}\"}}", message);
+            self.status("success", message);
             return;
         }
 
-        if self.color_enabled {
-            println!("{} {}", "SUCCESS:".green().bold(), message);
-        } else {
-            println!("SUCCESS: {}", message);
-        }
+        println!(
+            "{} {}",
+            self.paint("SUCCESS:", |s| s.green().bold()),
+            message
+        );
     }
 
     /// Print error message
@@ -57,15 +192,11 @@ impl OutputFormatter {
         }
 
         if self.json_output {
-            println!("{{\"status\":\"error\",\"message\":\"{}\"}}", message);
+            self.status("error", message);
             return;
         }
 
-        if self.color_enabled {
-            println!("{} {}", "ERROR:".red().bold(), message);
-        } else {
-            println!("ERROR: {}", message);
-        }
+        println!("{} {}", self.paint("ERROR:", |s| s.red().bold()), message);
     }
 
     /// Print warning message
@@ -75,15 +206,15 @@ impl OutputFormatter {
         }
 
         if self.json_output {
-            println!("{{\"status\":\"warning\",\"message\":\"{}\"}}", message);
+            self.status("warning", message);
             return;
         }
 
-        if self.color_enabled {
-            println!("{} {}", "WARNING:".yellow().bold(), message);
-        } else {
-            println!("WARNING: {}", message);
-        }
+        println!(
+            "{} {}",
+            self.paint("WARNING:", |s| s.yellow().bold()),
+            message
+        );
     }
 
     /// Print info message
@@ -93,15 +224,11 @@ impl OutputFormatter {
         }
 
         if self.json_output {
-            println!("{{\"status\":\"info\",\"message\":\"{}\"}}", message);
+            self.status("info", message);
             return;
         }
 
-        if self.color_enabled {
-            println!("{} {}", "INFO:".blue().bold(), message);
-        } else {
-            println!("INFO: {}", message);
-        }
+        println!("{} {}", self.paint("INFO:", |s| s.blue().bold()), message);
     }
 
     /// Print output success message
@@ -120,19 +247,13 @@ impl OutputFormatter {
         }
 
         if self.json_output {
-            let json = serde_json::to_string_pretty(item)?;
-            println!("{}", json);
+            println!("{}", self.render(item)?);
             return Ok(());
         }
 
         if !header.is_empty() {
-            if self.color_enabled {
-                println!("{}", header.blue().bold());
-                println!("{}", "=".repeat(header.len()).blue());
-            } else {
-                println!("{}", header);
-                println!("{}", "=".repeat(header.len()));
-            }
+            println!("{}", self.paint(header, |s| s.blue().bold()));
+            println!("{}", self.paint(&"=".repeat(header.len()), |s| s.blue()));
         }
 
         println!("{}", item);
@@ -151,30 +272,20 @@ impl OutputFormatter {
         }
 
         if self.json_output {
-            let json = serde_json::to_string_pretty(items)?;
-            println!("{}", json);
+            println!("{}", self.render(items)?);
             return Ok(());
         }
 
         if items.is_empty() {
             if !empty_message.is_empty() {
-                if self.color_enabled {
-                    println!("{}", empty_message.yellow());
-                } else {
-                    println!("{}", empty_message);
-                }
+                println!("{}", self.paint(empty_message, |s| s.yellow()));
             }
             return Ok(());
         }
 
         if !header.is_empty() {
-            if self.color_enabled {
-                println!("{}", header.blue().bold());
-                println!("{}", "=".repeat(header.len()).blue());
-            } else {
-                println!("{}", header);
-                println!("{}", "=".repeat(header.len()));
-            }
+            println!("{}", self.paint(header, |s| s.blue().bold()));
+            println!("{}", self.paint(&"=".repeat(header.len()), |s| s.blue()));
         }
 
         for (i, item) in items.iter().enumerate() {
@@ -187,16 +298,13 @@ impl OutputFormatter {
         Ok(())
     }
 
-    /// Print data as JSON
+    /// Print data as JSON, or as YAML when that format was chosen
     pub fn json<T: serde::Serialize>(&self, data: T) -> Result<(), CliError> {
         if self.quiet {
             return Ok(());
         }
 
-        let json = serde_json::to_string_pretty(&data)
-            .map_err(|e| CliError::SerializationError(e.to_string()))?;
-
-        println!("{}", json);
+        println!("{}", self.render(&data)?);
 
         Ok(())
     }
@@ -214,8 +322,8 @@ impl OutputFormatter {
         row_fn(&mut builder);
 
         if self.json_output {
-            if let Ok(json) = serde_json::to_string_pretty(&builder.to_json()) {
-                println!("{}", json);
+            if let Ok(output) = self.render(&builder.to_json()) {
+                println!("{}", output);
             }
             return;
         }
@@ -297,11 +405,7 @@ impl TableBuilder {
             .collect::<Vec<_>>()
             .join(" | ");
 
-        if color_enabled {
-            println!("{}", header_row.bold());
-        } else {
-            println!("{}", header_row);
-        }
+        println!("{}", paint(color_enabled, &header_row, |s| s.bold()));
 
         // Print separator
         let separator = widths
@@ -373,15 +477,12 @@ pub fn load_config(
     }
 }
 
-/// Save configuration to file
+/// Save configuration to file, readable by its owner only as it may hold an auth token
 #[cfg(feature = "cli")]
 pub fn save_config(
     config: &crate::cli::config::CliConfig,
     config_path: std::path::PathBuf,
 ) -> Result<()> {
-    use std::fs;
-    use std::io::Write;
-
     // Ensure the parent directory exists
     if let Some(parent) = config_path.parent() {
         if !parent.exists() {
@@ -395,10 +496,7 @@ pub fn save_config(
         .map_err(|e| anyhow::anyhow!("Failed to serialize config: {}", e))?;
 
     // Write config to file
-    let mut file = fs::File::create(&config_path)
-        .map_err(|e| anyhow::anyhow!("Failed to create config file: {}", e))?;
-
-    file.write_all(content.as_bytes())
+    crate::files::write_with_mode(&config_path, content, crate::files::PRIVATE_FILE_MODE)
         .map_err(|e| anyhow::anyhow!("Failed to write config file: {}", e))?;
 
     Ok(())
@@ -424,3 +522,39 @@ pub fn format_duration(seconds: u64) -> String {
     let days = hours / 24;
     format!("{} days", days)
 }
+
+/// Compute the Levenshtein edit distance between two strings
+#[cfg(feature = "cli")]
+pub fn levenshtein(a: &str, b: &str) -> usize {
+    let b_chars: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b_chars.len()).collect();
+    let mut curr = vec![0; b_chars.len() + 1];
+
+    for (i, ca) in a.chars().enumerate() {
+        curr[0] = i + 1;
+        for (j, cb) in b_chars.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            curr[j + 1] = (prev[j + 1] + 1).min(curr[j] + 1).min(prev[j] + cost);
+        }
+        std::mem::swap(&mut prev, &mut curr);
+    }
+
+    prev[b_chars.len()]
+}
+
+/// Find the closest candidate to `input`, if any is reasonably close
+///
+/// A candidate is considered close when its edit distance is at most a third
+/// of the input length (and never more than 3), which catches common typos
+/// without suggesting unrelated keys.
+#[cfg(feature = "cli")]
+pub fn closest_match<'a>(input: &str, candidates: &[&'a str]) -> Option<&'a str> {
+    let max_distance = (input.chars().count() / 3).clamp(1, 3);
+
+    candidates
+        .iter()
+        .map(|candidate| (levenshtein(input, candidate), *candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
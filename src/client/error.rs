@@ -0,0 +1,80 @@
+//! Client error types
+
+use crate::protocol::goodbye::Goodbye;
+use crate::protocol::handshake::ProtocolVersion;
+use crate::protocol::FrameError;
+use crate::server::error::PermissionError;
+use thiserror::Error;
+
+/// Result type for daemon client operations
+pub type Result<T> = std::result::Result<T, ClientError>;
+
+/// Errors returned by the daemon client
+#[derive(Debug, Error)]
+pub enum ClientError {
+    /// The daemon could not be reached
+    #[error("Connection error: {0}")]
+    Connection(String),
+
+    /// Nothing is listening at the daemon's address, as when it is not running
+    #[error("Connection error: {0}")]
+    ConnectionRefused(String),
+
+    /// The operation did not complete in time
+    #[error("Operation timed out after {0} seconds")]
+    Timeout(u64),
+
+    /// The connection failed at the framing layer
+    #[error("Frame error: {0}")]
+    Frame(#[from] FrameError),
+
+    /// The daemon speaks a protocol version this client does not understand
+    #[error(
+        "Daemon speaks protocol {daemon} but this CLI speaks {client}; {}",
+        upgrade_hint(.client, .daemon)
+    )]
+    IncompatibleProtocol {
+        client: ProtocolVersion,
+        daemon: ProtocolVersion,
+    },
+
+    /// A request or response could not be (de)serialized
+    #[error("Serialization error: {0}")]
+    Serialization(String),
+
+    /// The daemon returned a JSON-RPC error
+    #[error("{message}")]
+    Rpc { code: i64, message: String },
+
+    /// The daemon refused the request because a permission is missing
+    #[error("{0}")]
+    PermissionDenied(PermissionError),
+
+    /// The daemon closed the session and said why
+    #[error("{0}")]
+    Disconnected(Goodbye),
+
+    /// A method's result does not have the shape this client expects, as
+    /// when the daemon runs a different version
+    #[error("Failed to parse {method} response: {message}")]
+    UnexpectedResult { method: String, message: String },
+
+    /// The daemon returned a response that is not valid JSON-RPC
+    #[error("Invalid response: {0}")]
+    InvalidResponse(String),
+}
+
+/// Which side the user has to upgrade to resolve a version mismatch
+fn upgrade_hint(client: &ProtocolVersion, daemon: &ProtocolVersion) -> &'static str {
+    if client > daemon {
+        "upgrade the daemon or use an older CLI"
+    } else {
+        "upgrade the CLI to match the daemon"
+    }
+}
+
+impl From<serde_json::Error> for ClientError {
+    fn from(err: serde_json::Error) -> Self {
+        ClientError::Serialization(err.to_string())
+    }
+}
@@ -0,0 +1,484 @@
+//! Daemon client
+//!
+//! A typed client for the daemon's JSON-RPC protocol that does not depend on
+//! the `cli` feature, so other Rust programs can talk to a running daemon.
+//! The CLI's `ServiceClient` is a thin wrapper around [`Client`].
+
+pub mod error;
+pub mod transport;
+pub mod types;
+
+pub use self::error::{ClientError, Result};
+pub use self::transport::{MockRequest, MockTransport, RpcTransport, TcpTransport};
+pub use self::types::*;
+
+use crate::server::error::PermissionError;
+use crate::server::rpc;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::timeout;
+use uuid::Uuid;
+
+/// Seconds a `diag/bench` call may take, as benchmarks outlast ordinary requests
+pub const BENCH_TIMEOUT_SECS: u64 = 300;
+
+/// Client for a running daemon
+#[derive(Clone)]
+pub struct Client {
+    host: String,
+    port: u16,
+    pub timeout_seconds: u64,
+    pub auth_token: Option<String>,
+    transport: Arc<dyn RpcTransport>,
+}
+
+impl std::fmt::Debug for Client {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Client")
+            .field("host", &self.host)
+            .field("port", &self.port)
+            .field("timeout_seconds", &self.timeout_seconds)
+            .field("auth_token", &self.auth_token)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Client {
+    /// Create a new client
+    pub fn new(host: String, port: u16, timeout_seconds: u64) -> Self {
+        Self {
+            transport: Arc::new(TcpTransport::new(host.clone(), port)),
+            host,
+            port,
+            timeout_seconds,
+            auth_token: None,
+        }
+    }
+
+    /// Host the client connects to
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    /// Port the client connects to
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Set authentication token
+    pub fn with_auth(mut self, token: Option<String>) -> Self {
+        self.auth_token = token;
+        self
+    }
+
+    /// Send requests through `transport` instead of a TCP connection to
+    /// `host` and `port`, e.g. a [`MockTransport`] in tests
+    pub fn with_transport(mut self, transport: impl RpcTransport + 'static) -> Self {
+        self.transport = Arc::new(transport);
+        self
+    }
+
+    /// Get service status
+    pub async fn get_status(&self) -> Result<ServiceStatus> {
+        self.call("status", Value::Null).await
+    }
+
+    /// Check that the daemon answers, returning the round-trip time
+    pub async fn ping(&self) -> Result<Duration> {
+        let started = std::time::Instant::now();
+        let reply: String = self.call("ping", Value::Null).await?;
+        if reply != "pong" {
+            return Err(ClientError::InvalidResponse(format!(
+                "Unexpected ping reply: {}",
+                reply
+            )));
+        }
+        Ok(started.elapsed())
+    }
+
+    /// Get the session serving this request, as the daemon sees it
+    pub async fn session_info(&self) -> Result<CurrentSession> {
+        self.call("session/info", Value::Null).await
+    }
+
+    /// Get server information
+    pub async fn get_server_info(&self) -> Result<ServerInfo> {
+        self.call("server/info", Value::Null).await
+    }
+
+    /// Get list of available applications
+    pub async fn list_apps(&self) -> Result<Vec<AppInfo>> {
+        self.call("apps/list", Value::Null).await
+    }
+
+    /// Get the definition of an application
+    pub async fn get_app_info(&self, app_id: &str) -> Result<AppInfo> {
+        let params = serde_json::json!({ "app_id": app_id });
+        self.call("apps/info", params).await
+    }
+
+    /// Get list of application instances
+    pub async fn list_app_instances(&self) -> Result<Vec<AppInstanceInfo>> {
+        self.call("apps/instances", Value::Null).await
+    }
+
+    /// Launch an application
+    pub async fn launch_app(
+        &self,
+        app_id: &str,
+        user_id: Option<&str>,
+        args: Option<Vec<String>>,
+    ) -> Result<AppInstanceInfo> {
+        let params = serde_json::json!({
+            "app_id": app_id,
+            "user_id": user_id,
+            "arguments": args
+        });
+
+        self.call("apps/launch", params).await
+    }
+
+    /// Stop an application instance
+    pub async fn stop_app(&self, instance_id: &str) -> Result<()> {
+        let params = serde_json::json!({
+            "instance_id": instance_id
+        });
+
+        self.call_raw("apps/stop", params).await?;
+        Ok(())
+    }
+
+    /// Get recent output of an application instance
+    ///
+    /// With `since`, only lines from that sequence number onwards are returned.
+    pub async fn get_app_logs(
+        &self,
+        instance_id: &str,
+        lines: usize,
+        since: Option<u64>,
+    ) -> Result<AppLogs> {
+        let params = serde_json::json!({
+            "instance_id": instance_id,
+            "lines": lines,
+            "since": since
+        });
+
+        self.call("apps/logs", params).await
+    }
+
+    /// Change the daemon log level, returning the previous level
+    pub async fn set_log_level(&self, level: &str) -> Result<String> {
+        #[derive(serde::Deserialize)]
+        struct Reply {
+            previous: String,
+        }
+
+        let params = serde_json::json!({ "level": level });
+        let reply: Reply = self.call("diag/set_log_level", params).await?;
+        Ok(reply.previous)
+    }
+
+    /// Get the daemon's running server configuration, with secrets redacted
+    pub async fn get_running_config(&self) -> Result<Value> {
+        self.call_raw("server/config", Value::Null).await
+    }
+
+    /// Get the daemon's running server configuration, with secrets replaced
+    /// by fingerprints keyed with `salt` (admin only)
+    pub async fn get_fingerprinted_config(&self, salt: &str) -> Result<Value> {
+        let params = serde_json::json!({ "secret_salt": salt });
+        self.call_raw("server/config", params).await
+    }
+
+    /// Get the daemon's connection, session and request counters
+    pub async fn get_metrics(&self) -> Result<MetricsSnapshot> {
+        self.call("metrics", Value::Null).await
+    }
+
+    /// Change one setting of the daemon's server configuration (admin only)
+    ///
+    /// The daemon saves the change to its config file before applying it, and
+    /// replies with the updated configuration, with secrets redacted.
+    pub async fn update_server_config(&self, key: &str, value: &str) -> Result<Value> {
+        let params = serde_json::json!({ "key": key, "value": value });
+        self.call_raw("server/config/update", params).await
+    }
+
+    /// Restart the daemon's server, returning its status once it is back
+    ///
+    /// The daemon disconnects every other session first; this one stays open.
+    pub async fn restart_server(&self) -> Result<ServiceStatus> {
+        self.call("server/restart", Value::Null).await
+    }
+
+    /// Get the most recent server events, optionally limited to some types
+    pub async fn recent_events(
+        &self,
+        count: usize,
+        types: &[ServerEventType],
+    ) -> Result<Vec<ServerEvent>> {
+        let params = serde_json::json!({ "count": count, "type": types });
+        self.call("events/recent", params).await
+    }
+
+    /// What the daemon knows about its cluster peers
+    pub async fn cluster_peers(&self) -> Result<Vec<PeerStatus>> {
+        self.call("cluster/peers", Value::Null).await
+    }
+
+    /// Benchmark auth validation inside the daemon (admin only)
+    ///
+    /// With a `username` the daemon's configured provider is benchmarked with
+    /// that user's `password`, otherwise its in-process mock provider. The
+    /// call may run for up to [`BENCH_TIMEOUT_SECS`] whatever the client timeout.
+    pub async fn bench_auth(
+        &self,
+        iterations: usize,
+        username: Option<&str>,
+        password: Option<&str>,
+    ) -> Result<AuthBenchReport> {
+        let params = serde_json::json!({
+            "target": "auth",
+            "iterations": iterations,
+            "username": username,
+            "password": password,
+        });
+        let client = Self {
+            timeout_seconds: self.timeout_seconds.max(BENCH_TIMEOUT_SECS),
+            ..self.clone()
+        };
+        client.call("diag/bench", params).await
+    }
+
+    /// Identity of the calling session, as established by its auth token
+    pub async fn whoami(&self) -> Result<Identity> {
+        self.call("auth/whoami", Value::Null).await
+    }
+
+    /// List issued auth tokens that have not yet expired (admin only)
+    pub async fn list_tokens(&self) -> Result<Vec<TokenInfo>> {
+        self.call("auth/tokens/list", Value::Null).await
+    }
+
+    /// Issue an auth token for `subject`, valid for `ttl` (admin only)
+    ///
+    /// The daemon only grants `permissions` the calling session holds itself.
+    pub async fn issue_token(
+        &self,
+        subject: &str,
+        permissions: Vec<String>,
+        ttl: Duration,
+    ) -> Result<IssuedToken> {
+        let params = serde_json::json!({
+            "subject": subject,
+            "permissions": permissions,
+            "ttl_secs": ttl.as_secs(),
+        });
+        self.call("auth/tokens/issue", params).await
+    }
+
+    /// Revoke an issued auth token by its ID (admin only)
+    pub async fn revoke_token(&self, jti: &str) -> Result<TokenInfo> {
+        let params = serde_json::json!({ "jti": jti });
+        self.call("auth/tokens/revoke", params).await
+    }
+
+    /// Export the internal provider's users, password hashes included
+    pub async fn export_users(&self) -> Result<UserExport> {
+        self.call("users/export", Value::Null).await
+    }
+
+    /// Restore users from an export, `mode` deciding what happens to existing ones
+    pub async fn import_users(
+        &self,
+        export: &UserExport,
+        mode: ImportMode,
+    ) -> Result<ImportReport> {
+        let params = serde_json::json!({ "export": export, "mode": mode });
+        self.call("users/import", params).await
+    }
+
+    /// Change a user's full name, email or role (admin only), returning the updated user
+    pub async fn update_user(&self, username: &str, update: &UserUpdate) -> Result<User> {
+        let mut params = serde_json::to_value(update)?;
+        params["username"] = Value::String(username.to_string());
+        self.call("users/update", params).await
+    }
+
+    /// Get list of active sessions
+    pub async fn list_sessions(&self) -> Result<Vec<SessionInfo>> {
+        self.call("sessions/list", Value::Null).await
+    }
+
+    /// Get one active session, with its running applications if `include_apps`
+    pub async fn get_session(&self, session_id: &str, include_apps: bool) -> Result<SessionInfo> {
+        let params = serde_json::json!({
+            "session_id": session_id,
+            "include_apps": include_apps
+        });
+
+        self.call("sessions/info", params).await
+    }
+
+    /// Disconnect a session
+    pub async fn disconnect_session(&self, session_id: &str) -> Result<()> {
+        let params = serde_json::json!({
+            "session_id": session_id
+        });
+
+        self.call_raw("sessions/disconnect", params).await?;
+        Ok(())
+    }
+
+    /// Disconnect a session, showing it `reason` (admin only)
+    pub async fn kick_session(&self, session_id: &str, reason: Option<&str>) -> Result<()> {
+        let params = serde_json::json!({
+            "session_id": session_id,
+            "reason": reason
+        });
+
+        self.call_raw("sessions/kick", params).await?;
+        Ok(())
+    }
+
+    /// Call a method and deserialize its result
+    pub async fn call<T: DeserializeOwned>(&self, method: &str, params: Value) -> Result<T> {
+        let response = self.call_raw(method, params).await?;
+        parse_result(method, response)
+    }
+
+    /// Call a method and return its raw JSON result
+    pub async fn call_raw(&self, method: &str, params: Value) -> Result<Value> {
+        let request = self.build_request(method, params)?;
+        let response = self.send_request(request).await?;
+        parse_response(&response)
+    }
+
+    /// Call several methods in a single round trip
+    ///
+    /// Results are returned in call order. A failing call yields an error in
+    /// its own slot without affecting the others; the outer error is only for
+    /// failures of the batch as a whole.
+    pub async fn call_batch(&self, calls: Vec<(&str, Value)>) -> Result<Vec<Result<Value>>> {
+        let ids: Vec<String> = calls.iter().map(|_| Uuid::new_v4().to_string()).collect();
+        let batch: Vec<Value> = calls
+            .into_iter()
+            .zip(&ids)
+            .map(|((method, params), id)| self.request_value(id, method, params))
+            .collect();
+
+        let response = self.send_request(serde_json::to_string(&batch)?).await?;
+        parse_batch_response(&response, &ids)
+    }
+
+    /// Build a request to the service
+    fn build_request(&self, method: &str, params: Value) -> Result<String> {
+        let request = self.request_value(&Uuid::new_v4().to_string(), method, params);
+        Ok(serde_json::to_string(&request)?)
+    }
+
+    /// Build a request object with the given ID
+    fn request_value(&self, id: &str, method: &str, params: Value) -> Value {
+        serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": method,
+            "params": params,
+            "auth": self.auth_token
+        })
+    }
+
+    /// Send a request to the service and return the raw response
+    async fn send_request(&self, request: String) -> Result<String> {
+        let send = self.transport.send(request, self.auth_token.as_deref());
+        match timeout(Duration::from_secs(self.timeout_seconds), send).await {
+            Ok(response) => response,
+            Err(_) => Err(ClientError::Timeout(self.timeout_seconds)),
+        }
+    }
+}
+
+/// Extract the result from a JSON-RPC response, or its error
+pub fn parse_response(response: &str) -> Result<Value> {
+    response_result(serde_json::from_str(response)?)
+}
+
+/// Deserialize the result of `method`, naming the method and the field that
+/// did not match, e.g. "Failed to parse server/info response: missing field
+/// `tls_enabled`"
+pub fn parse_result<T: DeserializeOwned>(method: &str, result: Value) -> Result<T> {
+    serde_path_to_error::deserialize(result).map_err(|e| {
+        // Missing fields are reported at the root, whose path is just "."
+        let path = e.path().to_string();
+        let error = e.into_inner();
+        ClientError::UnexpectedResult {
+            method: method.to_string(),
+            message: if path == "." {
+                error.to_string()
+            } else {
+                format!("{}: {}", path, error)
+            },
+        }
+    })
+}
+
+/// Match the responses of a batch to its request IDs
+///
+/// Results are returned in the order of `ids`, whatever order the server
+/// answered in. A single (non-array) response means the batch as a whole failed.
+pub fn parse_batch_response(response: &str, ids: &[String]) -> Result<Vec<Result<Value>>> {
+    let items = match serde_json::from_str(response)? {
+        Value::Array(items) => items,
+        other => {
+            return Err(response_result(other).err().unwrap_or_else(|| {
+                ClientError::InvalidResponse("Expected a batch response".to_string())
+            }))
+        }
+    };
+
+    let mut responses: HashMap<String, Value> = items
+        .into_iter()
+        .filter_map(|item| Some((item.get("id")?.as_str()?.to_string(), item)))
+        .collect();
+
+    Ok(ids
+        .iter()
+        .map(|id| match responses.remove(id) {
+            Some(response) => response_result(response),
+            None => Err(ClientError::InvalidResponse(format!(
+                "Missing response for request {}",
+                id
+            ))),
+        })
+        .collect())
+}
+
+/// Extract the result from a parsed response, or its error
+fn response_result(mut response: Value) -> Result<Value> {
+    if let Some(error) = response.get("error").filter(|e| !e.is_null()) {
+        if error["code"].as_i64() == Some(rpc::PERMISSION_DENIED) {
+            if let Ok(permission) = serde_json::from_value::<PermissionError>(error["data"].clone())
+            {
+                return Err(ClientError::PermissionDenied(permission));
+            }
+        }
+
+        return Err(ClientError::Rpc {
+            code: error["code"].as_i64().unwrap_or(0),
+            message: error["message"]
+                .as_str()
+                .unwrap_or("Unknown error")
+                .to_string(),
+        });
+    }
+
+    match response.get_mut("result") {
+        Some(result) => Ok(result.take()),
+        None => Err(ClientError::InvalidResponse(
+            "Invalid response format".to_string(),
+        )),
+    }
+}
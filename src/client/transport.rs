@@ -0,0 +1,206 @@
+//! How requests reach the daemon
+//!
+//! [`Client`](super::Client) builds requests and parses responses; getting a
+//! request to the daemon and its response back is up to an [`RpcTransport`].
+//! [`TcpTransport`] is the real one. [`MockTransport`] answers from a queue
+//! of canned replies and records what it was sent, so the client's logic can
+//! be tested without a socket.
+
+use super::error::{ClientError, Result};
+use crate::protocol::codec::{self, FrameError};
+use crate::protocol::goodbye::Goodbye;
+use crate::protocol::handshake::{self, ClientHello};
+use crate::protocol::keepalive::Keepalive;
+use crate::server::tokens;
+use async_trait::async_trait;
+use bytes::Bytes;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard};
+use tokio::net::TcpStream;
+
+/// Carries one serialized request to the daemon and returns the raw response
+///
+/// The client puts its own timeout around `send`, so a transport need not.
+#[async_trait]
+pub trait RpcTransport: Send + Sync {
+    /// Send `request`, authenticating the connection with `auth` if set
+    async fn send(&self, request: String, auth: Option<&str>) -> Result<String>;
+}
+
+/// Transport opening a TCP connection to the daemon for each request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TcpTransport {
+    pub host: String,
+    pub port: u16,
+}
+
+impl TcpTransport {
+    /// Connect to the daemon at `host` and `port`
+    pub fn new(host: String, port: u16) -> Self {
+        Self { host, port }
+    }
+}
+
+#[async_trait]
+impl RpcTransport for TcpTransport {
+    async fn send(&self, request: String, auth: Option<&str>) -> Result<String> {
+        // A (host, port) pair resolves IPv6 literals, which "host:port" would not
+        let stream = TcpStream::connect((self.host.as_str(), self.port))
+            .await
+            .map_err(|e| match e.kind() {
+                std::io::ErrorKind::ConnectionRefused => {
+                    ClientError::ConnectionRefused(e.to_string())
+                }
+                _ => ClientError::Connection(e.to_string()),
+            })?;
+
+        // A token is sent as-is; a key is only proven against the server's challenge
+        let (auth, key) = match auth {
+            Some(credential) if !tokens::looks_like_token(credential) => (None, Some(credential)),
+            auth => (auth, None),
+        };
+
+        // Send the request and wait for the response frame
+        let mut framed = codec::framed(stream, codec::DEFAULT_MAX_FRAME_LENGTH);
+        let result = async {
+            let hello = ClientHello {
+                auth: auth.map(str::to_string),
+                ..Default::default()
+            };
+            let compressor = handshake::client_handshake_with_key(&mut framed, &hello, key).await?;
+
+            let frame = compressor.encode(Bytes::from(request.into_bytes()))?;
+            codec::write_frame(&mut framed, frame).await?;
+
+            // A keep-alive ping may arrive ahead of the response
+            loop {
+                let payload = compressor.decode(codec::read_frame(&mut framed).await?)?;
+                match Keepalive::ping_from_payload(&payload) {
+                    Some(ping) => {
+                        let pong = compressor.encode(ping.pong_payload())?;
+                        codec::write_frame(&mut framed, pong).await?;
+                    }
+                    None => break Ok(payload),
+                }
+            }
+        }
+        .await;
+
+        match result {
+            // The server closed the session instead of answering
+            Ok(response) => match Goodbye::from_payload(&response) {
+                Some(goodbye) => Err(ClientError::Disconnected(goodbye)),
+                None => String::from_utf8(response.to_vec())
+                    .map_err(|e| FrameError::InvalidPayload(e.to_string()).into()),
+            },
+            Err(FrameError::IncompatibleProtocol { local, remote }) => {
+                Err(ClientError::IncompatibleProtocol {
+                    client: local,
+                    daemon: remote,
+                })
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// A reply queued on a [`MockTransport`]
+#[derive(Debug)]
+enum MockReply {
+    Response(String),
+    Error(ClientError),
+    Hang,
+}
+
+/// A request a [`MockTransport`] was sent
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MockRequest {
+    /// The serialized request
+    pub body: String,
+
+    /// Auth token the connection would have been opened with
+    pub auth: Option<String>,
+}
+
+/// Transport answering requests from a queue of canned replies
+///
+/// Clones share the queue and the record of requests, so a test can keep one
+/// clone to inspect after handing the other to a client. Once the queue runs
+/// out, requests fail with a connection error.
+#[derive(Debug, Clone, Default)]
+pub struct MockTransport {
+    replies: Arc<Mutex<VecDeque<MockReply>>>,
+    requests: Arc<Mutex<Vec<MockRequest>>>,
+}
+
+impl MockTransport {
+    /// Create a mock transport with no replies queued
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answer the next request with the raw `response`
+    pub fn with_response(self, response: impl Into<String>) -> Self {
+        self.push(MockReply::Response(response.into()))
+    }
+
+    /// Answer the next request with a response carrying `result`
+    pub fn with_result(self, result: serde_json::Value) -> Self {
+        let response = serde_json::json!({ "jsonrpc": "2.0", "id": null, "result": result });
+        self.with_response(response.to_string())
+    }
+
+    /// Answer the next request with a JSON-RPC error
+    pub fn with_rpc_error(self, code: i64, message: &str) -> Self {
+        let response = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": null,
+            "error": { "code": code, "message": message }
+        });
+        self.with_response(response.to_string())
+    }
+
+    /// Fail the next request with `error`, as a broken connection would
+    pub fn with_error(self, error: ClientError) -> Self {
+        self.push(MockReply::Error(error))
+    }
+
+    /// Never answer the next request, so the client's timeout fires
+    pub fn with_hang(self) -> Self {
+        self.push(MockReply::Hang)
+    }
+
+    /// Requests sent so far, oldest first
+    pub fn requests(&self) -> Vec<MockRequest> {
+        lock(&self.requests).clone()
+    }
+
+    fn push(self, reply: MockReply) -> Self {
+        lock(&self.replies).push_back(reply);
+        self
+    }
+}
+
+#[async_trait]
+impl RpcTransport for MockTransport {
+    async fn send(&self, request: String, auth: Option<&str>) -> Result<String> {
+        lock(&self.requests).push(MockRequest {
+            body: request,
+            auth: auth.map(str::to_string),
+        });
+
+        let reply = lock(&self.replies).pop_front();
+        match reply {
+            Some(MockReply::Response(response)) => Ok(response),
+            Some(MockReply::Error(error)) => Err(error),
+            Some(MockReply::Hang) => std::future::pending().await,
+            None => Err(ClientError::Connection(
+                "mock transport has no replies left".to_string(),
+            )),
+        }
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
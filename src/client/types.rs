@@ -0,0 +1,143 @@
+//! Data types exchanged with the daemon
+
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, SocketAddr};
+
+pub use crate::server::bench::AuthBenchReport;
+pub use crate::server::cluster::{PeerHealth, PeerStatus};
+pub use crate::server::events::{ServerEvent, ServerEventType};
+pub use crate::server::metrics::MetricsSnapshot;
+pub use crate::server::tokens::{Identity, IssuedToken, TokenInfo};
+pub use crate::server::user::{
+    ExportedUser, ImportMode, ImportReport, User, UserExport, UserRole, UserUpdate,
+};
+
+/// Service status information
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ServiceStatus {
+    pub running: bool,
+    pub pid: Option<u32>,
+    pub uptime: Option<String>,
+    pub version: String,
+}
+
+/// Application instance information
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AppInstanceInfo {
+    pub id: String,
+    pub app_id: String,
+    pub name: String,
+    pub user_id: String,
+    pub status: String,
+    pub created_at: String,
+    #[serde(default)]
+    pub pid: Option<u32>,
+    #[serde(default)]
+    pub session_id: Option<String>,
+}
+
+/// A captured line of application output
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AppLogLine {
+    pub seq: u64,
+    pub timestamp: String,
+    pub stream: String,
+    pub line: String,
+}
+
+/// Recent output of an application instance
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AppLogs {
+    pub instance_id: String,
+    pub lines: Vec<AppLogLine>,
+    pub next_seq: u64,
+}
+
+/// Application information
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AppInfo {
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub version: Option<String>,
+    pub publisher: Option<String>,
+    pub icon_path: Option<String>,
+    pub executable_path: String,
+    #[serde(default)]
+    pub required_permission: Option<String>,
+    #[serde(default)]
+    pub category: Option<String>,
+    #[serde(default = "default_accepts_args")]
+    pub accepts_args: bool,
+}
+
+fn default_accepts_args() -> bool {
+    true
+}
+
+/// Server information
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ServerInfo {
+    pub version: String,
+    pub uptime: String,
+    pub address: String,
+    pub port: u16,
+    pub tls_enabled: bool,
+    pub active_sessions: usize,
+    pub total_sessions: usize,
+}
+
+/// The calling client's own session, as returned by `session/info`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CurrentSession {
+    pub session_id: String,
+    pub user: Option<String>,
+    pub client_ip: Option<IpAddr>,
+    #[serde(default)]
+    pub client_port: Option<u16>,
+    pub permissions: Vec<String>,
+    pub token_id: Option<String>,
+    #[serde(default)]
+    pub resume_token: Option<String>,
+}
+
+impl CurrentSession {
+    /// Client address as the server sees it, if both parts are known
+    pub fn client_addr(&self) -> Option<SocketAddr> {
+        Some(SocketAddr::new(self.client_ip?, self.client_port?))
+    }
+}
+
+/// An active session, as listed by `sessions/list`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SessionInfo {
+    pub id: String,
+    pub user_id: String,
+    pub username: String,
+    pub client_ip: IpAddr,
+    #[serde(default)]
+    pub client_port: Option<u16>,
+    pub created_at: String,
+    pub expires_at: String,
+    pub last_active: String,
+    pub active: bool,
+    /// Application instances running in the session, from `sessions/info`
+    /// with `include_apps`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub apps: Option<Vec<AppInstanceInfo>>,
+}
+
+impl SessionInfo {
+    /// Client address for display, bracketing IPv6 addresses when a port is known
+    pub fn client_address(&self) -> String {
+        format_client_address(self.client_ip, self.client_port)
+    }
+}
+
+/// Format a client IP and optional port, e.g. `10.0.0.5:50123` or `[::1]:50123`
+pub fn format_client_address(ip: IpAddr, port: Option<u16>) -> String {
+    match port {
+        Some(port) => SocketAddr::new(ip, port).to_string(),
+        None => ip.to_string(),
+    }
+}
@@ -2,8 +2,10 @@
 use crate::api::ApiConfig;
 use crate::server::config::ServerConfig;
 use anyhow::Result;
+use log::{info, warn};
 use serde::{Deserialize, Serialize};
-use std::path::Path;
+use std::fmt;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServiceConfig {
@@ -11,6 +13,20 @@ pub struct ServiceConfig {
     pub port: u16,
     pub tls: TlsConfig,
 
+    /// File log output is copied to in addition to the console
+    /// (`--log-file` takes precedence)
+    #[serde(default)]
+    pub log_file: Option<PathBuf>,
+
+    /// File the daemon writes its PID to (`--pid-file` takes precedence)
+    #[serde(default)]
+    pub pid_file: Option<PathBuf>,
+
+    /// Connections the server and the API may have open at once in all
+    /// (0 for no limit)
+    #[serde(default)]
+    pub max_total_connections: usize,
+
     /// Integrated server configuration
     #[serde(default)]
     pub server: ServerConfig,
@@ -18,6 +34,11 @@ pub struct ServiceConfig {
     /// Integrated API configuration (when api feature is enabled)
     #[cfg(feature = "api")]
     pub api: Option<ApiConfig>,
+
+    /// File the configuration was loaded from, which server configuration
+    /// changes made at runtime are saved back to
+    #[serde(skip)]
+    pub loaded_from: Option<PathBuf>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -39,8 +60,12 @@ impl Default for ServiceConfig {
                     cert_path: "cert.pem".to_string(),
                     key_path: "key.pem".to_string(),
                 },
+                log_file: None,
+                pid_file: None,
+                max_total_connections: 0,
                 server: ServerConfig::default(),
                 api: Some(ApiConfig::default()),
+                loaded_from: None,
             }
         }
 
@@ -53,23 +78,218 @@ impl Default for ServiceConfig {
                 cert_path: "cert.pem".to_string(),
                 key_path: "key.pem".to_string(),
             },
+            log_file: None,
+            pid_file: None,
+            max_total_connections: 0,
             server: ServerConfig::default(),
+            loaded_from: None,
+        }
+    }
+}
+
+/// A configuration file could not be loaded
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigFileError {
+    /// The file could not be read
+    #[error("{}: {source}", .path.display())]
+    Read {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// The file is not valid configuration
+    #[error("{location}: {message}")]
+    Parse {
+        location: ConfigErrorLocation,
+        message: String,
+    },
+}
+
+/// Where in a configuration file an error was found
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigErrorLocation {
+    /// Path of the file
+    pub path: PathBuf,
+
+    /// Line of the error, starting at 1, when the parser reports one
+    pub line: Option<usize>,
+
+    /// Column of the error, starting at 1, when the parser reports one
+    pub column: Option<usize>,
+}
+
+impl fmt::Display for ConfigErrorLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.path.display())?;
+        if let Some(line) = self.line {
+            write!(f, ":{}", line)?;
+            if let Some(column) = self.column {
+                write!(f, ":{}", column)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ConfigFileError {
+    /// Whether the file simply does not exist
+    pub fn is_not_found(&self) -> bool {
+        matches!(
+            self,
+            ConfigFileError::Read { source, .. } if source.kind() == std::io::ErrorKind::NotFound
+        )
+    }
+
+    /// Describe a TOML error in `path`, moving its position into the location
+    fn parse(path: &Path, error: toml::de::Error) -> Self {
+        let mut message = error.to_string();
+        let position = error
+            .line_col()
+            .map(|(line, column)| (line + 1, column + 1));
+
+        // The TOML message ends with the position, which the location already gives
+        if let Some((line, column)) = position {
+            let suffix = format!(" at line {} column {}", line, column);
+            if let Some(stripped) = message.strip_suffix(&suffix) {
+                message = stripped.to_string();
+            }
+        }
+
+        ConfigFileError::Parse {
+            location: ConfigErrorLocation {
+                path: path.to_path_buf(),
+                line: position.map(|(line, _)| line),
+                column: position.map(|(_, column)| column),
+            },
+            message,
         }
     }
 }
 
 impl ServiceConfig {
     /// Load configuration from a file
+    ///
+    /// Errors are [`ConfigFileError`]s naming the file and, for parse errors,
+    /// the line and column.
     pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let config_str = std::fs::read_to_string(path)?;
-        let config: ServiceConfig = toml::from_str(&config_str)?;
-        Ok(config)
+        let path = path.as_ref();
+        let config_str = std::fs::read_to_string(path).map_err(|source| ConfigFileError::Read {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let config: ServiceConfig =
+            toml::from_str(&config_str).map_err(|e| ConfigFileError::parse(path, e))?;
+        Ok(Self {
+            loaded_from: Some(path.to_path_buf()),
+            ..config
+        })
+    }
+
+    /// Load configuration from a file, falling back to defaults unless `strict`
+    ///
+    /// In strict mode any failure to load the file is returned, so startup
+    /// aborts instead of running with settings the operator did not write.
+    pub fn load_or_default<P: AsRef<Path>>(path: P, strict: bool) -> Result<Self> {
+        let path = path.as_ref();
+        match Self::from_file(path) {
+            Ok(config) => {
+                info!("Configuration loaded from {}", path.display());
+                Ok(config)
+            }
+            Err(e) if strict => Err(e.context("Invalid configuration (--strict-config)")),
+            Err(e) => {
+                let missing = e
+                    .downcast_ref::<ConfigFileError>()
+                    .is_some_and(ConfigFileError::is_not_found);
+                if missing {
+                    info!("No config file at {}, using defaults", path.display());
+                    // Runtime changes create the file
+                    return Ok(Self {
+                        loaded_from: Some(path.to_path_buf()),
+                        ..Self::default()
+                    });
+                }
+                warn!("Failed to load config: {}. Using defaults.", e);
+                Ok(Self::default())
+            }
+        }
+    }
+
+    /// Problems with the configuration, each as `key: message`
+    ///
+    /// Covers the integrated server's settings too, under `server.`. Empty
+    /// means the daemon can start with this configuration.
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        if self.port == 0 {
+            problems.push("port: must not be 0".to_string());
+        }
+        if self.tls.enabled {
+            for (key, path) in [
+                ("tls.cert_path", &self.tls.cert_path),
+                ("tls.key_path", &self.tls.key_path),
+            ] {
+                if !Path::new(path).is_file() {
+                    problems.push(format!("{}: {} does not exist", key, path));
+                }
+            }
+        }
+        for (key, path) in [("log_file", &self.log_file), ("pid_file", &self.pid_file)] {
+            if let Some(dir) = path.as_deref().and_then(Path::parent) {
+                if !dir.as_os_str().is_empty() && !dir.is_dir() {
+                    problems.push(format!(
+                        "{}: directory {} does not exist",
+                        key,
+                        dir.display()
+                    ));
+                }
+            }
+        }
+
+        #[cfg(feature = "api")]
+        if let Some(api) = &self.api {
+            if api.listen.is_none() && api.port == self.server.port {
+                problems.push(format!("api.port: {} is also the server port", api.port));
+            }
+        }
+
+        problems.extend(
+            self.server
+                .validate()
+                .into_iter()
+                .map(|problem| format!("server.{}", problem)),
+        );
+        problems
+    }
+
+    /// Load the configuration at `path` and list everything wrong with it
+    ///
+    /// A missing file is checked as the defaults, as
+    /// [`load_or_default`](Self::load_or_default) starts the daemon with
+    /// them, unless `strict`. A file that does not parse is always a problem.
+    pub fn check_file<P: AsRef<Path>>(path: P, strict: bool) -> Vec<String> {
+        match Self::from_file(path) {
+            Ok(config) => config.validate(),
+            Err(e) => {
+                let missing = e
+                    .downcast_ref::<ConfigFileError>()
+                    .is_some_and(ConfigFileError::is_not_found);
+                if missing && !strict {
+                    return Self::default().validate();
+                }
+                vec![e.to_string()]
+            }
+        }
     }
 
     /// Save configuration to a file
     pub fn to_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
-        let toml = toml::to_string(self)?;
-        std::fs::write(path, toml)?;
+        // Going through a toml::Value writes plain keys ahead of tables, which
+        // serializing the struct directly cannot do for `log_file`
+        let toml = toml::to_string(&toml::Value::try_from(self)?)?;
+        crate::files::write_with_mode(path, toml, crate::files::PRIVATE_FILE_MODE)?;
         Ok(())
     }
 }
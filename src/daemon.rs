@@ -1,9 +1,39 @@
+use crate::client::{Client, ClientError};
 use crate::{config::ServiceConfig, error::ServiceError, manager::ServiceManager};
 use anyhow::Result;
 use log::{error, info};
-use std::path::PathBuf;
+use serde::Serialize;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
 use tokio::sync::mpsc;
 
+/// How often `start --wait` checks whether the daemon is up
+pub const READY_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long the launching process waits for the daemon to write its PID file
+pub const PID_FILE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// PID file used unless the config or `--pid-file` names another
+pub fn default_pid_file() -> PathBuf {
+    std::env::temp_dir().join("rcpdaemon.pid")
+}
+
+/// PID file of the daemon run with `config`
+pub fn pid_file_path(config: &ServiceConfig) -> PathBuf {
+    config.pid_file.clone().unwrap_or_else(default_pid_file)
+}
+
+/// Which process carries on after the daemon detached from the terminal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Detached {
+    /// The daemon itself, which goes on to serve
+    Daemon,
+
+    /// The process that launched the daemon
+    Launcher,
+}
+
 /// Service daemon that runs in the background
 pub struct ServiceDaemon {
     /// Configuration
@@ -53,14 +83,16 @@ impl ServiceDaemon {
 }
 
 /// Daemonize the current process (Unix only)
+///
+/// Returns in both the daemon and the launching process, which is told apart
+/// by the result.
 #[cfg(unix)]
-pub fn daemonize(work_dir: &PathBuf) -> Result<()> {
+pub fn daemonize(work_dir: &PathBuf, pid_file: &Path) -> Result<Detached> {
     use std::fs::File;
 
-    info!("Daemonizing process");
+    info!("Daemonizing process, PID file {}", pid_file.display());
 
-    let pid_file = std::env::temp_dir().join("rcpdaemon.pid");
-    let log_file = std::env::temp_dir().join("rcpdaemon.log");
+    let log_file = crate::logging::default_daemon_log_path();
 
     let daemonize = daemonize::Daemonize::new()
         .pid_file(pid_file)
@@ -69,19 +101,37 @@ pub fn daemonize(work_dir: &PathBuf) -> Result<()> {
         .stdout(File::create(&log_file).unwrap())
         .stderr(File::create(&log_file).unwrap());
 
-    daemonize.start().map_err(|e| {
+    let failed = |e: daemonize::Error| {
         error!("Error starting daemon: {}", e);
         anyhow::anyhow!("Failed to start daemon: {}", e)
-    })?;
+    };
+    match daemonize.execute() {
+        daemonize::Outcome::Parent(parent) => {
+            let parent = parent.map_err(failed)?;
+            if parent.first_child_exit_code != 0 {
+                return Err(anyhow::anyhow!(
+                    "Failed to start daemon: exited with status {}",
+                    parent.first_child_exit_code
+                ));
+            }
+            return Ok(Detached::Launcher);
+        }
+        daemonize::Outcome::Child(child) => {
+            child.map_err(failed)?;
+        }
+    }
 
-    Ok(())
+    // stderr now lands in the daemon log; don't tee into it a second time
+    crate::logging::set_console_redirect(Some(&log_file));
+
+    Ok(Detached::Daemon)
 }
 
 /// Windows service implementation (placeholder)
 #[cfg(windows)]
-pub fn daemonize(_work_dir: &PathBuf) -> Result<()> {
+pub fn daemonize(_work_dir: &PathBuf, _pid_file: &Path) -> Result<Detached> {
     info!("Windows service mode - daemonize not needed");
-    Ok(())
+    Ok(Detached::Daemon)
 }
 
 /// Start the daemon service
@@ -128,6 +178,9 @@ async fn setup_signal_handlers(shutdown_tx: mpsc::Sender<()>) -> Result<()> {
         }
     });
 
+    // SIGUSR1/SIGUSR2 adjust the log level at runtime
+    crate::logging::spawn_signal_handler()?;
+
     Ok(())
 }
 
@@ -149,24 +202,198 @@ async fn setup_signal_handlers(shutdown_tx: mpsc::Sender<()>) -> Result<()> {
     Ok(())
 }
 
-/// Get daemon status
-pub fn status() -> Result<String> {
-    let pid_file = std::env::temp_dir().join("rcpdaemon.pid");
+/// Run the daemon, detaching from the terminal unless `foreground` is set
+///
+/// Shared by every entry point so `rcpdaemon`, `rcpdaemon start` and
+/// `rcpdaemon daemon start` behave the same.
+pub async fn run(config: ServiceConfig, foreground: bool) -> Result<()> {
+    launch(config, foreground).await.map(|_| ())
+}
+
+/// Like [`run`], but telling the caller which process it returned in
+///
+/// The launching process returns [`Detached::Launcher`] as soon as the daemon
+/// has detached, which may be before it accepts connections; see
+/// [`wait_until_ready`]. The daemon, or a `foreground` run, returns
+/// [`Detached::Daemon`] once it has shut down.
+pub async fn launch(config: ServiceConfig, foreground: bool) -> Result<Detached> {
+    #[cfg(feature = "api")]
+    info!("Starting rcpdaemon (with API)...");
+
+    #[cfg(not(feature = "api"))]
+    info!("Starting rcpdaemon...");
+
+    let work_dir = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    if !foreground {
+        info!("Daemonizing process in {}", work_dir.display());
+        if daemonize(&work_dir, &pid_file_path(&config))? == Detached::Launcher {
+            return Ok(Detached::Launcher);
+        }
+    }
+
+    // `start` drives its own runtime, which cannot be nested in the caller's
+    tokio::task::spawn_blocking(move || start(config, work_dir)).await??;
+    Ok(Detached::Daemon)
+}
+
+/// Poll the daemon behind `client` until it answers, for up to `timeout`
+///
+/// Any reply counts, including an error such as a missing permission: the
+/// daemon has bound its port and speaks the protocol. Only failures to reach
+/// it are retried. Returns how long the daemon took to become ready.
+pub async fn wait_until_ready(client: &Client, timeout: Duration) -> Result<Duration> {
+    let started = Instant::now();
+    loop {
+        let remaining = timeout.saturating_sub(started.elapsed());
+        let error = match tokio::time::timeout(remaining, client.ping()).await {
+            Ok(Ok(_)) => return Ok(started.elapsed()),
+            Ok(Err(
+                e @ (ClientError::Connection(_)
+                | ClientError::ConnectionRefused(_)
+                | ClientError::Timeout(_)
+                | ClientError::Frame(_)),
+            )) => e.to_string(),
+            Ok(Err(_)) => return Ok(started.elapsed()),
+            Err(_) => "no reply to ping".to_string(),
+        };
+
+        if started.elapsed() + READY_POLL_INTERVAL >= timeout {
+            return Err(anyhow::anyhow!(
+                "Daemon did not become ready within {}s: {}",
+                timeout.as_secs_f64(),
+                error
+            ));
+        }
+        tokio::time::sleep(READY_POLL_INTERVAL).await;
+    }
+}
+
+/// Wait for up to `timeout` for the daemon to write `pid_file`, returning its PID
+///
+/// Only a PID file written at or after `since` counts, so one left behind by
+/// an earlier daemon is not mistaken for the one just launched.
+pub async fn wait_for_pid_file(
+    pid_file: &Path,
+    since: SystemTime,
+    timeout: Duration,
+) -> Result<u32> {
+    let started = Instant::now();
+    loop {
+        let written = std::fs::metadata(pid_file)
+            .and_then(|metadata| metadata.modified())
+            .is_ok_and(|modified| modified >= since);
+        if written {
+            if let Some(pid) = read_pid(pid_file)? {
+                return Ok(pid);
+            }
+        }
+
+        if started.elapsed() >= timeout {
+            return Err(anyhow::anyhow!(
+                "Daemon did not write its PID file {} within {}s",
+                pid_file.display(),
+                timeout.as_secs_f64()
+            ));
+        }
+        tokio::time::sleep(READY_POLL_INTERVAL).await;
+    }
+}
+
+/// Read the PID recorded in `pid_file`, `None` if there is no PID file
+///
+/// A PID file that exists but is still empty, as while the daemon is writing
+/// it, also reads as `None`.
+pub fn read_pid(pid_file: &Path) -> Result<Option<u32>> {
+    let pid_data = match std::fs::read_to_string(pid_file) {
+        Ok(pid_data) => pid_data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    match pid_data.trim() {
+        "" => Ok(None),
+        pid => Ok(Some(pid.parse()?)),
+    }
+}
+
+/// Wait for up to `timeout` for the process `pid` to exit
+pub async fn wait_for_exit(pid: u32, timeout: Duration) -> Result<()> {
+    let started = Instant::now();
+    while is_process_running(pid) {
+        if started.elapsed() >= timeout {
+            return Err(anyhow::anyhow!(
+                "Daemon (PID: {}) did not exit within {}s",
+                pid,
+                timeout.as_secs_f64()
+            ));
+        }
+        tokio::time::sleep(READY_POLL_INTERVAL).await;
+    }
+    Ok(())
+}
+
+/// State of the daemon according to its PID file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DaemonStatus {
+    /// Whether the daemon process is alive
+    pub running: bool,
 
-    if !pid_file.exists() {
-        return Ok("Not running".to_string());
+    /// PID recorded in the PID file, if any
+    pub pid: Option<u32>,
+
+    /// Whether a PID file exists for a process that is no longer running
+    pub stale_pid_file: bool,
+}
+
+impl fmt::Display for DaemonStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.running, self.pid) {
+            (true, Some(pid)) => write!(f, "Running (PID: {})", pid),
+            _ if self.stale_pid_file => write!(f, "Not running (stale PID file)"),
+            _ => write!(f, "Not running"),
+        }
     }
+}
+
+/// A daemon that was just launched
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DaemonStarted {
+    /// PID of the daemon process
+    pub pid: u32,
 
-    let pid_data = std::fs::read_to_string(&pid_file)?;
-    let pid: u32 = pid_data.trim().parse()?;
+    /// PID file the daemon wrote
+    pub pid_file: PathBuf,
+}
 
-    if is_process_running(pid) {
-        Ok(format!("Running (PID: {})", pid))
-    } else {
-        Ok("Not running (stale PID file)".to_string())
+impl fmt::Display for DaemonStarted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Daemon started (PID: {})", self.pid)
     }
 }
 
+/// Get the status of the daemon with the PID file `pid_file`
+pub fn daemon_status(pid_file: &Path) -> Result<DaemonStatus> {
+    let Some(pid) = read_pid(pid_file)? else {
+        return Ok(DaemonStatus {
+            running: false,
+            pid: None,
+            stale_pid_file: false,
+        });
+    };
+
+    let running = is_process_running(pid);
+
+    Ok(DaemonStatus {
+        running,
+        pid: Some(pid),
+        stale_pid_file: !running,
+    })
+}
+
+/// Get daemon status as a human-readable string
+pub fn status(pid_file: &Path) -> Result<String> {
+    Ok(daemon_status(pid_file)?.to_string())
+}
+
 /// Check if a process is running (Unix)
 #[cfg(unix)]
 fn is_process_running(pid: u32) -> bool {
@@ -192,22 +419,20 @@ fn is_process_running(pid: u32) -> bool {
     }
 }
 
-/// Stop the daemon
-pub fn stop() -> Result<()> {
+/// Stop the daemon with the PID file `pid_file`
+pub fn stop(pid_file: &Path) -> Result<()> {
     info!("Stopping daemon");
 
-    let pid_file = std::env::temp_dir().join("rcpdaemon.pid");
-
-    if !pid_file.exists() {
-        return Err(anyhow::anyhow!("Daemon not running (no PID file)"));
-    }
-
-    let pid_data = std::fs::read_to_string(&pid_file)?;
-    let pid: u32 = pid_data.trim().parse()?;
+    let Some(pid) = read_pid(pid_file)? else {
+        return Err(anyhow::anyhow!(
+            "Daemon not running (no PID file at {})",
+            pid_file.display()
+        ));
+    };
 
     terminate_process(pid)?;
 
-    std::fs::remove_file(&pid_file)?;
+    std::fs::remove_file(pid_file)?;
 
     info!("Daemon stopped");
     Ok(())
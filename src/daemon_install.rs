@@ -58,6 +58,7 @@ Description=RCP Daemon
 After=network.target
 
 [Service]
+ExecStartPre={exec} --config {config} --config-check
 ExecStart={exec} --config {config}
 Restart=on-failure
 RestartSec=5s
@@ -69,7 +70,11 @@ WantedBy=default.target
         config = config
     );
 
-    std::fs::write(service_file, service_content)?;
+    crate::files::write_with_mode(
+        service_file,
+        service_content,
+        crate::files::PUBLIC_FILE_MODE,
+    )?;
 
     // Enable service
     let status = std::process::Command::new("systemctl")
@@ -124,7 +129,7 @@ fn install_macos(config: &str) -> Result<()> {
         config = config
     );
 
-    std::fs::write(&plist_file, plist_content)?;
+    crate::files::write_with_mode(&plist_file, plist_content, crate::files::PUBLIC_FILE_MODE)?;
 
     // Load service
     let status = std::process::Command::new("launchctl")
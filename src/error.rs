@@ -23,4 +23,12 @@ pub enum ServiceError {
 
     #[error("Database error: {0}")]
     Database(String),
+
+    /// The caller's credentials are missing or invalid
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
+
+    /// The caller is authenticated but lacks the named permission
+    #[error("Forbidden: operation requires permission {0}")]
+    Forbidden(String),
 }
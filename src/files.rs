@@ -0,0 +1,51 @@
+//! Writing files with explicit permissions
+//!
+//! Configs may hold a pre-shared key, token secret or auth token, so they are
+//! written readable by their owner only. Service units and plists hold nothing
+//! secret but are read by the service manager, so they stay world-readable.
+//!
+//! Modes only apply on Unix. On Windows a new file inherits the ACL of its
+//! directory: configs under the user's profile are private to that user, but
+//! ones placed under `ProgramData` are readable by all local users unless the
+//! ACL is tightened, e.g. with `icacls <file> /inheritance:r /grant:r <user>:F`.
+
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Mode of files that may contain secrets
+pub const PRIVATE_FILE_MODE: u32 = 0o600;
+
+/// Mode of files other users and the service manager need to read
+pub const PUBLIC_FILE_MODE: u32 = 0o644;
+
+/// Write `contents` to `path`, creating or truncating it, with Unix permissions `mode`
+///
+/// The mode is set even when the file already exists, and before anything
+/// is written, so secrets never sit in a file with looser permissions.
+pub fn write_with_mode(
+    path: impl AsRef<Path>,
+    contents: impl AsRef<[u8]>,
+    mode: u32,
+) -> io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+
+    #[cfg(unix)]
+    let mut file = {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+        let file = options.mode(mode).open(path)?;
+        file.set_permissions(std::fs::Permissions::from_mode(mode))?;
+        file
+    };
+
+    #[cfg(not(unix))]
+    let mut file = {
+        let _ = mode;
+        options.open(path)?
+    };
+
+    file.write_all(contents.as_ref())?;
+    file.flush()
+}
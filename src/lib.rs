@@ -3,11 +3,17 @@
 
 // Public modules
 pub mod auth;
+pub mod build_info;
+pub mod client;
 pub mod config;
+pub mod daemon;
 pub mod error;
+pub mod files;
 pub mod instance;
 pub mod lifecycle;
+pub mod logging;
 pub mod manager;
+pub mod protocol;
 pub mod server;
 pub mod service;
 pub mod user;
@@ -23,7 +29,9 @@ pub mod cli;
 mod platform;
 
 // Re-export common types for external usage
+pub use client::{Client, ClientError};
 pub use config::ServiceConfig;
 pub use error::{Result, ServiceError};
 pub use manager::ServiceManager;
+pub use platform::sysinfo;
 pub use service::Service;
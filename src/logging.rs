@@ -0,0 +1,375 @@
+//! Logging setup and runtime log level control
+//!
+//! The logger itself is installed with every level enabled; the effective
+//! verbosity is the global `log` max level, which can be changed at runtime
+//! through [`set_level`], the `diag/set_log_level` RPC or (on Unix) the
+//! SIGUSR1/SIGUSR2 signals.
+//!
+//! Records always go to stderr and can additionally be teed into a log file
+//! with [`set_log_file`], in any run mode.
+//!
+//! Each record's target is the module it was logged from, such as
+//! `rcpdaemon::auth::manager`, so a [`LogFilter`] given with `--log-filter`
+//! or `RUST_LOG` can set the level of one component, e.g.
+//! `warn,rcpdaemon::auth=debug`. Specs are parsed by `env_filter`, the
+//! parser behind `env_logger`. The runtime level then applies to the targets
+//! the filter does not name.
+
+use log::{info, warn, Level, LevelFilter, Log, Metadata, Record};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+/// Levels in order of increasing verbosity, as stepped through by the signals
+const LEVELS: [LevelFilter; 5] = [
+    LevelFilter::Error,
+    LevelFilter::Warn,
+    LevelFilter::Info,
+    LevelFilter::Debug,
+    LevelFilter::Trace,
+];
+
+/// Every level, indexed by `LevelFilter as usize`
+const ALL_LEVELS: [LevelFilter; 6] = [
+    LevelFilter::Off,
+    LevelFilter::Error,
+    LevelFilter::Warn,
+    LevelFilter::Info,
+    LevelFilter::Debug,
+    LevelFilter::Trace,
+];
+
+/// Log file records are teed into, if any
+static LOG_FILE: Mutex<Option<LogFile>> = Mutex::new(None);
+
+/// File stderr itself is redirected to, as when daemonized
+static CONSOLE_REDIRECT: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// Per-target filter, set once at startup if at all
+static FILTER: OnceLock<LogFilter> = OnceLock::new();
+
+/// Level of the targets the filter does not name, as `LevelFilter as usize`
+static FILTER_DEFAULT_LEVEL: AtomicUsize = AtomicUsize::new(LevelFilter::Info as usize);
+
+/// Environment variable read for a log filter when `--log-filter` is not given
+pub const FILTER_ENV_VAR: &str = "RUST_LOG";
+
+/// Log levels by target, in the `RUST_LOG` syntax of `env_logger`
+///
+/// A spec is a comma-separated list of `target=level` directives and a bare
+/// `level` for the targets no directive names. A directive covers its
+/// target's submodules too, and the most specific one wins.
+pub struct LogFilter {
+    /// Level of the targets no directive names, if the spec sets one
+    pub default: Option<LevelFilter>,
+
+    /// The parsed spec, once for each level of the unnamed targets, indexed
+    /// by `LevelFilter as usize`
+    compiled: Vec<env_filter::Filter>,
+}
+
+impl LogFilter {
+    /// Parse a spec such as `warn,rcpdaemon::auth=debug`
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let compile = |level: LevelFilter| {
+            let mut builder = env_filter::Builder::new();
+            builder.try_parse(spec).map_err(|e| e.to_string())?;
+            // Replaces the spec's default level, which is kept separately
+            Ok::<_, String>(builder.filter_level(level).build())
+        };
+        let compiled = ALL_LEVELS
+            .into_iter()
+            .map(compile)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // Everything after a `/` is a message pattern, not directives
+        let directives = spec.split('/').next().unwrap_or_default();
+        let default = directives
+            .split(',')
+            .map(str::trim)
+            .filter(|directive| !directive.contains('='))
+            .filter_map(parse_level)
+            .last();
+
+        Ok(Self { default, compiled })
+    }
+
+    /// Whether records of `level` from `target` pass, with the targets no
+    /// directive names logged at `default_level`
+    pub fn enabled(&self, target: &str, level: Level, default_level: LevelFilter) -> bool {
+        let metadata = Metadata::builder().target(target).level(level).build();
+        self.compiled[default_level as usize].enabled(&metadata)
+    }
+
+    /// Most verbose level of any directive
+    fn most_verbose(&self) -> LevelFilter {
+        self.compiled[LevelFilter::Off as usize].filter()
+    }
+}
+
+/// The filter to start with: `flag` (`--log-filter`) if given, else `env`
+/// ([`FILTER_ENV_VAR`])
+///
+/// A bad flag is an error, a bad environment filter only a warning. With
+/// `explicit_level`, the level was set on the command line (`-v`) and wins
+/// over the default level of an environment filter.
+pub fn startup_filter(
+    flag: Option<&str>,
+    env: Option<&str>,
+    explicit_level: bool,
+) -> Result<Option<LogFilter>, String> {
+    if let Some(spec) = flag {
+        return LogFilter::parse(spec)
+            .map(Some)
+            .map_err(|e| format!("Invalid --log-filter `{}`: {}", spec, e));
+    }
+    let Some(spec) = env else {
+        return Ok(None);
+    };
+    match LogFilter::parse(spec) {
+        Ok(mut filter) => {
+            if explicit_level {
+                filter.default = None;
+            }
+            Ok(Some(filter))
+        }
+        Err(e) => {
+            warn!("Ignoring {}: {}", FILTER_ENV_VAR, e);
+            Ok(None)
+        }
+    }
+}
+
+/// Logger dropping the records the [`LogFilter`] in effect leaves out
+struct FilteredLogger {
+    inner: env_logger::Logger,
+}
+
+impl Log for FilteredLogger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        target_enabled(metadata.target(), metadata.level())
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        if self.enabled(record.metadata()) {
+            self.inner.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// Whether records of `level` from `target` are logged
+///
+/// The global max level has already been checked by the time a record gets
+/// here; only a [`LogFilter`] can still leave it out.
+pub fn target_enabled(target: &str, level: Level) -> bool {
+    match FILTER.get() {
+        Some(filter) => filter.enabled(target, level, filter_default_level()),
+        None => true,
+    }
+}
+
+fn filter_default_level() -> LevelFilter {
+    ALL_LEVELS[FILTER_DEFAULT_LEVEL.load(Ordering::Relaxed)]
+}
+
+/// An open log file and its canonical path
+struct LogFile {
+    path: PathBuf,
+    file: File,
+}
+
+/// Resolve a path for comparison, falling back to the path as given
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Writer sending each formatted record to stderr and the log file
+///
+/// The file is skipped when stderr is already redirected to it, so a
+/// daemonized process does not write every record twice.
+#[derive(Debug, Default)]
+pub struct LogWriter;
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Logging must never fail the caller, so sink errors are ignored
+        let _ = io::stderr().write_all(buf);
+
+        let redirect = CONSOLE_REDIRECT.lock().unwrap_or_else(|e| e.into_inner());
+        let mut log_file = LOG_FILE.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(log_file) = log_file.as_mut() {
+            if redirect.as_ref() != Some(&log_file.path) {
+                let _ = log_file.file.write_all(buf);
+            }
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let _ = io::stderr().flush();
+        if let Some(log_file) = LOG_FILE.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+            let _ = log_file.file.flush();
+        }
+        Ok(())
+    }
+}
+
+/// Install the process logger with the given initial level
+pub fn init(level: LevelFilter) {
+    let inner = env_logger::Builder::new()
+        .filter_level(LevelFilter::Trace)
+        .format_timestamp_millis()
+        .target(env_logger::Target::Pipe(Box::new(LogWriter)))
+        .build();
+    log::set_boxed_logger(Box::new(FilteredLogger { inner })).expect("logger initialized twice");
+
+    log::set_max_level(level);
+}
+
+/// Filter records by target with `filter` for the rest of the process
+///
+/// Targets the filter does not name are logged at its default level, or at
+/// the current level if it has none. Only one filter can be set; a second
+/// is handed back.
+pub fn set_filter(filter: LogFilter) -> Result<(), LogFilter> {
+    let default_level = filter.default.unwrap_or_else(level);
+    let max_level = filter.most_verbose().max(default_level);
+
+    FILTER_DEFAULT_LEVEL.store(default_level as usize, Ordering::Relaxed);
+    FILTER.set(filter)?;
+    log::set_max_level(max_level);
+    Ok(())
+}
+
+/// Tee log records into `path` (appending), or stop teeing with `None`
+pub fn set_log_file(path: Option<&Path>) -> io::Result<()> {
+    let log_file = match path {
+        Some(path) => {
+            let file = OpenOptions::new().create(true).append(true).open(path)?;
+            Some(LogFile {
+                path: canonical(path),
+                file,
+            })
+        }
+        None => None,
+    };
+
+    *LOG_FILE.lock().unwrap_or_else(|e| e.into_inner()) = log_file;
+    Ok(())
+}
+
+/// File a daemonized process writes its console output (and so its log) to
+pub fn default_daemon_log_path() -> PathBuf {
+    std::env::temp_dir().join("rcpdaemon.log")
+}
+
+/// Path of the file log records are teed into, if any
+pub fn log_file() -> Option<PathBuf> {
+    LOG_FILE
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .as_ref()
+        .map(|log_file| log_file.path.clone())
+}
+
+/// Record that stderr now points at `path`, e.g. after daemonizing
+///
+/// If that is also the log file, records are written to it only once.
+pub fn set_console_redirect(path: Option<&Path>) {
+    *CONSOLE_REDIRECT.lock().unwrap_or_else(|e| e.into_inner()) = path.map(canonical);
+}
+
+/// Current log level, of the targets a filter does not name
+pub fn level() -> LevelFilter {
+    match FILTER.get() {
+        Some(_) => filter_default_level(),
+        None => log::max_level(),
+    }
+}
+
+/// Make `level` the current level, keeping the filter's directives in effect
+fn apply_level(level: LevelFilter) {
+    match FILTER.get() {
+        Some(filter) => {
+            FILTER_DEFAULT_LEVEL.store(level as usize, Ordering::Relaxed);
+            log::set_max_level(filter.most_verbose().max(level));
+        }
+        None => log::set_max_level(level),
+    }
+}
+
+/// Parse a level name such as `debug` or `WARN`
+pub fn parse_level(level: &str) -> Option<LevelFilter> {
+    level.trim().parse().ok()
+}
+
+/// Change the log level, returning the previous one
+///
+/// The change is logged at info level while whichever of the old and new
+/// levels is more verbose is still in effect, so it shows up either way.
+pub fn set_level(level: LevelFilter) -> LevelFilter {
+    let previous = self::level();
+    if previous == level {
+        return previous;
+    }
+
+    if level > previous {
+        apply_level(level);
+        info!("Log level changed from {} to {}", previous, level);
+    } else {
+        info!("Log level changed from {} to {}", previous, level);
+        apply_level(level);
+    }
+
+    previous
+}
+
+/// Step one level towards `trace`, returning the new level
+pub fn increase_verbosity() -> LevelFilter {
+    step(1)
+}
+
+/// Step one level towards `error`, returning the new level
+pub fn decrease_verbosity() -> LevelFilter {
+    step(-1)
+}
+
+fn step(delta: isize) -> LevelFilter {
+    let current = LEVELS.iter().position(|l| *l == level()).unwrap_or(2) as isize;
+    let next = (current + delta).clamp(0, LEVELS.len() as isize - 1) as usize;
+
+    set_level(LEVELS[next]);
+    LEVELS[next]
+}
+
+/// Spawn a task adjusting the log level on SIGUSR1 (more verbose) and SIGUSR2 (less verbose)
+#[cfg(unix)]
+pub fn spawn_signal_handler() -> std::io::Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut sigusr1 = signal(SignalKind::user_defined1())?;
+    let mut sigusr2 = signal(SignalKind::user_defined2())?;
+
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                Some(_) = sigusr1.recv() => {
+                    increase_verbosity();
+                }
+                Some(_) = sigusr2.recv() => {
+                    decrease_verbosity();
+                }
+                else => break,
+            }
+        }
+    });
+
+    Ok(())
+}
@@ -1,12 +1,18 @@
 // Main entry point for rcpdaemon
+mod auth;
+mod build_info;
+mod client;
 mod config;
 mod daemon;
 mod daemon_install;
 mod error;
+mod files;
 mod instance;
 mod lifecycle;
+mod logging;
 mod manager;
 mod platform;
+mod protocol;
 mod server;
 mod service;
 mod user;
@@ -21,10 +27,9 @@ mod cli;
 
 use anyhow::Result;
 use log::{info, LevelFilter};
+#[cfg(not(feature = "cli"))]
 use std::path::PathBuf;
 
-#[cfg(feature = "cli")]
-use clap::Parser;
 #[cfg(feature = "cli")]
 use cli::types::Cli;
 
@@ -40,6 +45,18 @@ struct Cli {
     #[clap(short, long, default_value = "service.toml")]
     config: String,
 
+    /// Ignore the config file, using built-in defaults
+    #[clap(long)]
+    no_config: bool,
+
+    /// Abort if the config file cannot be loaded instead of using defaults
+    #[clap(long)]
+    strict_config: bool,
+
+    /// Validate the config file and exit, non-zero if it has problems
+    #[clap(long)]
+    config_check: bool,
+
     /// Run in foreground (no daemon)
     #[clap(short, long)]
     foreground: bool,
@@ -48,6 +65,18 @@ struct Cli {
     #[clap(short, long)]
     verbose: bool,
 
+    /// Also write logs to this file, in any run mode
+    #[clap(long, value_name = "PATH")]
+    log_file: Option<PathBuf>,
+
+    /// Log levels by component, e.g. `warn,rcpdaemon::auth=debug` (overrides RUST_LOG)
+    #[clap(long, value_name = "FILTER")]
+    log_filter: Option<String>,
+
+    /// PID file of the daemon, overriding the config's `pid_file`
+    #[clap(long, value_name = "PATH")]
+    pid_file: Option<PathBuf>,
+
     /// Output in JSON format
     #[clap(long)]
     json: bool,
@@ -78,6 +107,9 @@ pub enum ServiceCommand {
 #[tokio::main]
 async fn main() -> Result<()> {
     // Parse command line arguments
+    #[cfg(feature = "cli")]
+    let cli = Cli::parse_args();
+    #[cfg(not(feature = "cli"))]
     let cli = Cli::parse();
 
     // Set log level
@@ -87,17 +119,37 @@ async fn main() -> Result<()> {
         LevelFilter::Info
     };
 
-    env_logger::Builder::new()
-        .filter_level(log_level)
-        .format_timestamp_millis()
-        .init();
+    logging::init(log_level);
+    let env_filter = std::env::var(logging::FILTER_ENV_VAR).ok();
+    let filter = logging::startup_filter(
+        cli.log_filter.as_deref(),
+        env_filter.as_deref(),
+        cli.verbose,
+    )
+    .map_err(|e| anyhow::anyhow!(e))?;
+    if let Some(filter) = filter {
+        let _ = logging::set_filter(filter);
+    }
+    if let Some(log_file) = &cli.log_file {
+        logging::set_log_file(Some(log_file))?;
+    }
 
-    info!("rcpdaemon v{} initializing...", env!("CARGO_PKG_VERSION"));
+    info!("rcpdaemon v{} initializing...", build_info::VERSION);
 
     #[cfg(feature = "cli")]
     {
+        if !cli::runs_daemon(&cli) {
+            cli::exit_on_sigpipe();
+        }
+
         // Use the full CLI module when available
-        cli::handle_cli(cli).await?;
+        if let Err(e) = cli::handle_cli(cli).await {
+            if let Some(not_running @ cli::error::CliError::DaemonNotRunning) = e.downcast_ref() {
+                eprintln!("{}", not_running);
+                std::process::exit(cli::NOT_RUNNING_EXIT_CODE);
+            }
+            return Err(e);
+        }
     }
 
     #[cfg(not(feature = "cli"))]
@@ -111,39 +163,80 @@ async fn main() -> Result<()> {
 
 #[cfg(not(feature = "cli"))]
 async fn handle_basic_commands(cli: Cli) -> Result<()> {
-    // Load configuration
-    let config_file = &cli.config;
-    let config = match config::ServiceConfig::from_file(config_file) {
-        Ok(cfg) => {
-            info!("Configuration loaded from {}", config_file);
-            cfg
+    if cli.config_check {
+        let problems = if cli.no_config {
+            config::ServiceConfig::default().validate()
+        } else {
+            config::ServiceConfig::check_file(&cli.config, cli.strict_config)
+        };
+        for problem in &problems {
+            eprintln!("{}", problem);
         }
-        Err(e) => {
-            info!(
-                "Failed to load config from {}: {}. Using defaults.",
-                config_file, e
+        if !problems.is_empty() {
+            anyhow::bail!(
+                "Configuration {} has {} problem(s)",
+                cli.config,
+                problems.len()
             );
-            config::ServiceConfig::default()
         }
+        println!("Configuration {} is valid", cli.config);
+        return Ok(());
+    }
+
+    // Load configuration
+    let config_file = &cli.config;
+    let mut config = if cli.no_config {
+        info!("Ignoring configuration files (--no-config)");
+        config::ServiceConfig::default()
+    } else {
+        config::ServiceConfig::load_or_default(config_file, cli.strict_config)?
     };
+    if cli.pid_file.is_some() {
+        config.pid_file = cli.pid_file.clone();
+    }
+    let pid_file = daemon::pid_file_path(&config);
+
+    // The --log-file flag, applied at startup, wins over the config setting
+    if cli.log_file.is_none() {
+        if let Some(log_file) = &config.log_file {
+            logging::set_log_file(Some(log_file))?;
+        }
+    }
 
     // Handle command or run daemon by default
     match cli.command {
+        Some(ServiceCommand::Start) if !cli.foreground => {
+            let launched = std::time::SystemTime::now();
+            if daemon::launch(config, false).await? == daemon::Detached::Launcher {
+                let pid = daemon::wait_for_pid_file(&pid_file, launched, daemon::PID_FILE_TIMEOUT)
+                    .await?;
+                let started = daemon::DaemonStarted { pid, pid_file };
+                if cli.json {
+                    println!("{}", serde_json::to_string_pretty(&started)?);
+                } else {
+                    println!("{}", started);
+                }
+            }
+        }
         Some(ServiceCommand::Start) => {
-            run_daemon(&cli, config).await?;
+            daemon::run(config, true).await?;
         }
         Some(ServiceCommand::Stop) => {
             info!("Stopping RCP service...");
-            daemon::stop()?;
+            daemon::stop(&pid_file)?;
         }
         Some(ServiceCommand::Restart) => {
             info!("Restarting RCP service...");
-            daemon::stop()?;
-            run_daemon(&cli, config).await?;
+            daemon::stop(&pid_file)?;
+            daemon::run(config, cli.foreground).await?;
         }
         Some(ServiceCommand::Status) => {
-            let status = daemon::status()?;
-            println!("RCP Service Status: {}", status);
+            let status = daemon::daemon_status(&pid_file)?;
+            if cli.json {
+                println!("{}", serde_json::to_string_pretty(&status)?);
+            } else {
+                println!("RCP Service Status: {}", status);
+            }
         }
         Some(ServiceCommand::Install) => {
             info!("Installing RCP service...");
@@ -155,31 +248,9 @@ async fn handle_basic_commands(cli: Cli) -> Result<()> {
         }
         None => {
             // No command specified, run daemon
-            run_daemon(&cli, config).await?;
+            daemon::run(config, cli.foreground).await?;
         }
     }
 
     Ok(())
 }
-
-#[cfg(not(feature = "cli"))]
-async fn run_daemon(cli: &Cli, config: config::ServiceConfig) -> Result<()> {
-    #[cfg(feature = "api")]
-    info!("Starting rcpdaemon (with API)...");
-
-    #[cfg(not(feature = "api"))]
-    info!("Starting rcpdaemon...");
-
-    // Check if we should daemonize
-    if !cli.foreground {
-        let work_dir = std::env::current_dir()?;
-        info!("Daemonizing process in {}", work_dir.display());
-        daemon::daemonize(&work_dir)?;
-    }
-
-    // Start the daemon
-    daemon::start(
-        config,
-        std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")),
-    )
-}
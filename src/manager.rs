@@ -1,9 +1,17 @@
-use crate::{config::ServiceConfig, error::ServiceError, server::Server};
+use crate::{
+    auth::manager::AuthManager,
+    config::ServiceConfig,
+    error::ServiceError,
+    platform::sysinfo,
+    server::{live_config::ConfigFile, ratelimit::ConnectionLimit, Server},
+};
 // Conditionally import API types
 #[cfg(feature = "api")]
 use crate::api::ApiServer;
 
 use log::{debug, error, info};
+use serde::Serialize;
+use std::fmt;
 use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::sync::{mpsc, Mutex};
@@ -22,6 +30,9 @@ pub struct ServiceManager {
     /// Integrated server instance
     server: Option<Arc<Mutex<Server>>>,
 
+    /// Cap on connections open at once, shared by the server and the API
+    connections: ConnectionLimit,
+
     /// Integrated API instance (when api feature is enabled)
     #[cfg(feature = "api")]
     api: Option<ApiServer>,
@@ -30,6 +41,8 @@ pub struct ServiceManager {
 impl ServiceManager {
     /// Create a new service manager
     pub fn new(work_dir: PathBuf, config: ServiceConfig, shutdown_tx: mpsc::Sender<()>) -> Self {
+        let connections = ConnectionLimit::new(config.max_total_connections);
+
         #[cfg(feature = "api")]
         {
             Self {
@@ -37,6 +50,7 @@ impl ServiceManager {
                 config,
                 shutdown_tx,
                 server: None,
+                connections,
                 api: None,
             }
         }
@@ -47,16 +61,35 @@ impl ServiceManager {
             config,
             shutdown_tx,
             server: None,
+            connections,
         }
     }
 
     /// Start the service and the integrated components
     pub async fn start(&mut self) -> Result<(), ServiceError> {
         info!("Starting RCP service");
+        info!(
+            "Host {}, kernel {}, up {}",
+            sysinfo::hostname(),
+            sysinfo::kernel_version().as_deref().unwrap_or("unknown"),
+            sysinfo::boot_uptime().map_or_else(
+                || "unknown".to_string(),
+                |uptime| format!("{}s", uptime.as_secs())
+            )
+        );
 
         // Initialize and start the integrated server
         info!("Initializing integrated RCP server");
-        let server = Server::new(self.config.server.clone());
+        let mut builder = Server::builder()
+            .config(self.config.server.clone())
+            .connection_limit(self.connections.clone());
+        if let Some(path) = &self.config.loaded_from {
+            builder = builder.config_file(ConfigFile::nested(path, "server"));
+        }
+        if let Some(auth_manager) = self.auth_manager().await? {
+            builder = builder.auth_manager(Arc::new(auth_manager));
+        }
+        let server = builder.build();
         let server_arc = Arc::new(Mutex::new(server));
 
         // Clone for the server task
@@ -82,7 +115,8 @@ impl ServiceManager {
 
                 // Create API server with access to service manager
                 let api_server =
-                    ApiServer::new(api_config.clone(), Arc::new(Mutex::new(self.clone())));
+                    ApiServer::new(api_config.clone(), Arc::new(Mutex::new(self.clone())))
+                        .with_connection_limit(self.connections.clone());
 
                 // Start the API server
                 if let Err(e) = api_server.start().await {
@@ -97,10 +131,50 @@ impl ServiceManager {
             }
         }
 
-        info!("RCP service started successfully");
+        let summary = StartupSummary::new(&self.config, self.api_endpoint(), worker_threads());
+        info!("RCP service started: {}", summary);
         Ok(())
     }
 
+    /// Auth manager for the configured provider, or `None` for the internal one
+    async fn auth_manager(&self) -> Result<Option<AuthManager>, ServiceError> {
+        let auth_config = self
+            .config
+            .server
+            .auth
+            .provider_config()
+            .map_err(|e| ServiceError::Config(e.to_string()))?;
+        let Some(auth_config) = auth_config else {
+            return Ok(None);
+        };
+
+        info!(
+            "Initializing authentication provider {}",
+            auth_config.provider.as_str()
+        );
+        let mut auth_manager = AuthManager::new(auth_config)
+            .await
+            .map_err(|e| ServiceError::Config(e.to_string()))?;
+        auth_manager
+            .initialize()
+            .await
+            .map_err(|e| ServiceError::Config(e.to_string()))?;
+        Ok(Some(auth_manager))
+    }
+
+    /// Endpoint of the API, if it was started
+    fn api_endpoint(&self) -> Option<String> {
+        #[cfg(feature = "api")]
+        {
+            self.api
+                .as_ref()
+                .map(|api| api.config.listen_target().to_string())
+        }
+
+        #[cfg(not(feature = "api"))]
+        None
+    }
+
     /// Stop the service and all integrated components
     pub async fn stop(&mut self) -> Result<(), ServiceError> {
         info!("Stopping RCP service");
@@ -109,7 +183,10 @@ impl ServiceManager {
         #[cfg(feature = "api")]
         if let Some(api) = &self.api {
             if api.is_running().await {
-                info!("Stopping integrated API server");
+                info!(
+                    "Stopping integrated API server, waiting up to {}s for requests in flight",
+                    api.config.shutdown_grace_secs
+                );
                 if let Err(e) = api.stop().await {
                     error!("Error stopping API server: {}", e);
                 }
@@ -121,7 +198,10 @@ impl ServiceManager {
             let server = server_arc.lock().await;
             match server.is_running().await {
                 true => {
-                    info!("Stopping integrated RCP server");
+                    info!(
+                        "Stopping integrated RCP server, waiting up to {}s for requests in flight",
+                        server.config().shutdown_grace_secs
+                    );
                     if let Err(e) = server.stop().await {
                         error!("Error stopping server: {}", e);
                     }
@@ -157,6 +237,7 @@ impl ServiceManager {
             };
 
             Some(ServerStatus {
+                hostname: sysinfo::hostname(),
                 running,
                 uptime,
                 sessions,
@@ -176,6 +257,11 @@ impl ServiceManager {
         &self.config
     }
 
+    /// Get the cap on connections open at once across the server and the API
+    pub fn connection_limit(&self) -> &ConnectionLimit {
+        &self.connections
+    }
+
     /// Get the working directory path
     pub fn get_work_dir(&self) -> &PathBuf {
         &self.work_dir
@@ -189,7 +275,7 @@ impl ServiceManager {
 
             Some(ApiStatus {
                 running,
-                address: format!("{}:{}", api.config.address, api.config.port),
+                address: api.config.listen_target().to_string(),
             })
         } else {
             None
@@ -203,8 +289,86 @@ impl ServiceManager {
     }
 }
 
+/// Key settings the service started with, logged on one line
+///
+/// Only reports where the service listens and which features are in use,
+/// never keys, secrets or credentials. Displays as `key=value` pairs, and
+/// serializes with the same keys for log pipelines that want JSON.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StartupSummary {
+    /// Address the RCP server listens on
+    pub address: String,
+
+    /// Whether the RCP server uses TLS
+    pub tls: bool,
+
+    /// Authentication provider (empty for the default)
+    pub auth_provider: String,
+
+    /// Whether clients must authenticate
+    pub auth_required: bool,
+
+    /// Endpoint of the API, if it is running
+    pub api: Option<String>,
+
+    /// Worker threads of the async runtime
+    pub worker_threads: usize,
+
+    /// File the configuration was loaded from
+    pub config_file: Option<PathBuf>,
+}
+
+impl StartupSummary {
+    /// Summary of `config`, with the API at `api` if it was started
+    pub fn new(config: &ServiceConfig, api: Option<String>, worker_threads: usize) -> Self {
+        Self {
+            address: format!("{}:{}", config.server.address, config.server.port),
+            tls: config.server.tls.enabled,
+            auth_provider: config.server.auth.provider.clone(),
+            auth_required: config.server.auth.required,
+            api,
+            worker_threads,
+            config_file: config.loaded_from.clone(),
+        }
+    }
+}
+
+impl fmt::Display for StartupSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let on_off = |enabled: bool| if enabled { "on" } else { "off" };
+        let provider = match self.auth_provider.as_str() {
+            "" => "default",
+            provider => provider,
+        };
+
+        write!(
+            f,
+            "address={} tls={} auth_provider={} auth_required={} api={} worker_threads={} config_file={}",
+            self.address,
+            on_off(self.tls),
+            provider,
+            self.auth_required,
+            self.api.as_deref().unwrap_or("off"),
+            self.worker_threads,
+            self.config_file
+                .as_ref()
+                .map_or("none".into(), |path| path.display().to_string()),
+        )
+    }
+}
+
+/// Worker threads of the current async runtime, or zero outside of one
+fn worker_threads() -> usize {
+    tokio::runtime::Handle::try_current()
+        .map(|handle| handle.metrics().num_workers())
+        .unwrap_or(0)
+}
+
 /// Server status information
 pub struct ServerStatus {
+    /// Host the server runs on
+    pub hostname: String,
+
     /// Whether the server is running
     pub running: bool,
 
@@ -225,6 +389,7 @@ impl Clone for ServiceManager {
                 config: self.config.clone(),
                 shutdown_tx: self.shutdown_tx.clone(),
                 server: self.server.clone(),
+                connections: self.connections.clone(),
                 api: None, // API is not clonable and not needed in clones
             }
         }
@@ -235,6 +400,7 @@ impl Clone for ServiceManager {
             config: self.config.clone(),
             shutdown_tx: self.shutdown_tx.clone(),
             server: self.server.clone(),
+            connections: self.connections.clone(),
         }
     }
 }
@@ -245,6 +411,6 @@ pub struct ApiStatus {
     /// Whether the API server is running
     pub running: bool,
 
-    /// API server address and port
+    /// API server endpoint (`address:port` or `unix:path`)
     pub address: String,
 }
@@ -7,6 +7,8 @@ pub mod unix;
 #[cfg(target_family = "windows")]
 pub mod windows;
 
+pub mod sysinfo;
+
 #[cfg(target_family = "unix")]
 #[allow(unused_imports)]
 pub use unix::UnixPlatform;
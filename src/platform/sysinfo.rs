@@ -0,0 +1,359 @@
+//! Host introspection
+//!
+//! Hostname, kernel version, time since boot, memory, disk space and network
+//! interfaces, read through the platform's own APIs (uname, sysctl, statvfs,
+//! getifaddrs, /proc, Win32). Every query degrades gracefully: when the
+//! platform cannot answer, a fallback or `None` is returned instead of an
+//! error, since callers only use these values for reporting.
+
+use std::net::IpAddr;
+use std::path::Path;
+use std::time::Duration;
+
+/// Placeholder reported when the hostname cannot be determined
+pub const UNKNOWN_HOSTNAME: &str = "unknown";
+
+/// Name of this host
+///
+/// Falls back to the `HOSTNAME`/`COMPUTERNAME` environment variables, then to
+/// [`UNKNOWN_HOSTNAME`].
+pub fn hostname() -> String {
+    platform_hostname()
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .or_else(|| std::env::var("COMPUTERNAME").ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| UNKNOWN_HOSTNAME.to_string())
+}
+
+/// Version of the running kernel (e.g. `6.1.0-18-amd64`, `23.4.0`)
+pub fn kernel_version() -> Option<String> {
+    platform_kernel_version()
+        .map(|version| version.trim().to_string())
+        .filter(|version| !version.is_empty())
+}
+
+/// Time since the host booted
+pub fn boot_uptime() -> Option<Duration> {
+    platform_boot_uptime()
+}
+
+/// Memory and swap of the host, in bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryInfo {
+    pub total: u64,
+    /// Memory that new work can use without swapping
+    pub available: u64,
+    pub swap_total: u64,
+    pub swap_free: u64,
+}
+
+/// Memory and swap of the host, where the platform reports them (Linux)
+pub fn memory() -> Option<MemoryInfo> {
+    platform_memory()
+}
+
+/// Size of a file system, in bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiskSpace {
+    pub total: u64,
+    /// Space available to unprivileged users
+    pub available: u64,
+}
+
+/// Size of the file system holding `path`, where the platform reports it (Unix)
+pub fn disk_space(path: &Path) -> Option<DiskSpace> {
+    platform_disk_space(path)
+}
+
+/// One address of a network interface
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InterfaceAddress {
+    pub interface: String,
+    pub address: IpAddr,
+    pub prefix_len: u32,
+}
+
+/// IPv4 and IPv6 addresses of the host's network interfaces, where the
+/// platform lists them (Unix)
+pub fn interface_addresses() -> Option<Vec<InterfaceAddress>> {
+    platform_interface_addresses()
+}
+
+#[cfg(unix)]
+fn platform_hostname() -> Option<String> {
+    let mut buf = [0u8; 256];
+    // SAFETY: the buffer is valid for `buf.len()` bytes
+    let rc = unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) };
+    if rc != 0 {
+        return None;
+    }
+
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    Some(String::from_utf8_lossy(&buf[..len]).into_owned())
+}
+
+#[cfg(unix)]
+fn platform_kernel_version() -> Option<String> {
+    // SAFETY: utsname is plain data and is fully written by a successful uname
+    let mut uts: libc::utsname = unsafe { std::mem::zeroed() };
+    if unsafe { libc::uname(&mut uts) } != 0 {
+        return None;
+    }
+
+    // SAFETY: uname NUL-terminates every field
+    let release = unsafe { std::ffi::CStr::from_ptr(uts.release.as_ptr()) };
+    Some(release.to_string_lossy().into_owned())
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn platform_boot_uptime() -> Option<Duration> {
+    // "<seconds since boot> <idle seconds>"
+    let contents = std::fs::read_to_string("/proc/uptime").ok()?;
+    parse_proc_uptime(&contents)
+}
+
+#[cfg(any(
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "openbsd",
+    target_os = "netbsd",
+    target_os = "dragonfly"
+))]
+fn platform_boot_uptime() -> Option<Duration> {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    let mut mib = [libc::CTL_KERN, libc::KERN_BOOTTIME];
+    let mut boot = libc::timeval {
+        tv_sec: 0,
+        tv_usec: 0,
+    };
+    let mut size = std::mem::size_of::<libc::timeval>();
+
+    // SAFETY: `boot` is valid for `size` bytes and the MIB names kern.boottime
+    let rc = unsafe {
+        libc::sysctl(
+            mib.as_mut_ptr(),
+            mib.len() as libc::c_uint,
+            &mut boot as *mut libc::timeval as *mut libc::c_void,
+            &mut size,
+            std::ptr::null_mut(),
+            0,
+        )
+    };
+    if rc != 0 || boot.tv_sec <= 0 {
+        return None;
+    }
+
+    let booted_at = UNIX_EPOCH + Duration::new(boot.tv_sec as u64, boot.tv_usec as u32 * 1000);
+    SystemTime::now().duration_since(booted_at).ok()
+}
+
+#[cfg(windows)]
+fn platform_hostname() -> Option<String> {
+    std::env::var("COMPUTERNAME").ok()
+}
+
+#[cfg(windows)]
+fn platform_kernel_version() -> Option<String> {
+    // "Microsoft Windows [Version 10.0.19045.3803]"
+    let output = std::process::Command::new("cmd")
+        .args(["/C", "ver"])
+        .output()
+        .ok()?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    let start = text.find("Version ")? + "Version ".len();
+    let end = text[start..].find(']').map_or(text.len(), |i| start + i);
+    Some(text[start..end].to_string())
+}
+
+#[cfg(windows)]
+fn platform_boot_uptime() -> Option<Duration> {
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetTickCount64() -> u64;
+    }
+
+    // SAFETY: GetTickCount64 takes no arguments and cannot fail
+    Some(Duration::from_millis(unsafe { GetTickCount64() }))
+}
+
+#[cfg(not(any(unix, windows)))]
+fn platform_hostname() -> Option<String> {
+    None
+}
+
+#[cfg(not(any(unix, windows)))]
+fn platform_kernel_version() -> Option<String> {
+    None
+}
+
+#[cfg(not(any(
+    windows,
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "openbsd",
+    target_os = "netbsd",
+    target_os = "dragonfly"
+)))]
+fn platform_boot_uptime() -> Option<Duration> {
+    None
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn platform_memory() -> Option<MemoryInfo> {
+    let contents = std::fs::read_to_string("/proc/meminfo").ok()?;
+    parse_proc_meminfo(&contents)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn platform_memory() -> Option<MemoryInfo> {
+    None
+}
+
+#[cfg(unix)]
+// The statvfs field types differ between platforms
+#[allow(clippy::unnecessary_cast)]
+fn platform_disk_space(path: &Path) -> Option<DiskSpace> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    // SAFETY: statvfs is plain data and is fully written by a successful call
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+
+    let block = if stat.f_frsize > 0 {
+        stat.f_frsize as u64
+    } else {
+        stat.f_bsize as u64
+    };
+    Some(DiskSpace {
+        total: stat.f_blocks as u64 * block,
+        available: stat.f_bavail as u64 * block,
+    })
+}
+
+#[cfg(not(unix))]
+fn platform_disk_space(_path: &Path) -> Option<DiskSpace> {
+    None
+}
+
+#[cfg(unix)]
+fn platform_interface_addresses() -> Option<Vec<InterfaceAddress>> {
+    let mut head: *mut libc::ifaddrs = std::ptr::null_mut();
+    // SAFETY: on success `head` points to a list that is freed below
+    if unsafe { libc::getifaddrs(&mut head) } != 0 {
+        return None;
+    }
+
+    let mut addresses = Vec::new();
+    let mut entry = head;
+    while !entry.is_null() {
+        // SAFETY: every entry stays valid until freeifaddrs, and the name
+        // and sockaddrs it points to are either null or valid
+        let (name, address, netmask) = unsafe {
+            let ifa = &*entry;
+            entry = ifa.ifa_next;
+            let Some(address) = sockaddr_ip(ifa.ifa_addr, None) else {
+                continue;
+            };
+            let netmask = sockaddr_ip(ifa.ifa_netmask, Some(&address));
+            let name = std::ffi::CStr::from_ptr(ifa.ifa_name);
+            (name.to_string_lossy().into_owned(), address, netmask)
+        };
+
+        let prefix_len = match netmask {
+            Some(IpAddr::V4(mask)) => u32::from(mask).count_ones(),
+            Some(IpAddr::V6(mask)) => u128::from(mask).count_ones(),
+            None if address.is_ipv4() => 32,
+            None => 128,
+        };
+        addresses.push(InterfaceAddress {
+            interface: name,
+            address,
+            prefix_len,
+        });
+    }
+
+    // SAFETY: `head` came from getifaddrs and is not used after this
+    unsafe { libc::freeifaddrs(head) };
+    Some(addresses)
+}
+
+/// The IP address in `addr`, read as the family of `like` if given
+///
+/// Some platforms leave the family of an interface's netmask unset, so it is
+/// read as the family of the address it belongs to.
+///
+/// # Safety
+///
+/// `addr` must be null or point to a valid sockaddr of that family.
+#[cfg(unix)]
+unsafe fn sockaddr_ip(addr: *const libc::sockaddr, like: Option<&IpAddr>) -> Option<IpAddr> {
+    if addr.is_null() {
+        return None;
+    }
+
+    let family = match like {
+        Some(IpAddr::V4(_)) => libc::AF_INET,
+        Some(IpAddr::V6(_)) => libc::AF_INET6,
+        None => (*addr).sa_family as libc::c_int,
+    };
+    match family {
+        libc::AF_INET => {
+            let addr = &*(addr as *const libc::sockaddr_in);
+            // `s_addr` is held in network byte order
+            Some(IpAddr::from(addr.sin_addr.s_addr.to_ne_bytes()))
+        }
+        libc::AF_INET6 => {
+            let addr = &*(addr as *const libc::sockaddr_in6);
+            Some(IpAddr::from(addr.sin6_addr.s6_addr))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(not(unix))]
+fn platform_interface_addresses() -> Option<Vec<InterfaceAddress>> {
+    None
+}
+
+/// Parse the contents of `/proc/meminfo`
+///
+/// Lines look like `MemTotal:       16318480 kB`. Kernels before 3.14 have no
+/// `MemAvailable`, so free memory stands in for it there.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn parse_proc_meminfo(contents: &str) -> Option<MemoryInfo> {
+    let field = |name: &str| {
+        contents.lines().find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            if key.trim() != name {
+                return None;
+            }
+            let mut parts = value.split_whitespace();
+            let amount: u64 = parts.next()?.parse().ok()?;
+            let scale = if parts.next() == Some("kB") { 1024 } else { 1 };
+            Some(amount * scale)
+        })
+    };
+
+    Some(MemoryInfo {
+        total: field("MemTotal")?,
+        available: field("MemAvailable").or_else(|| field("MemFree"))?,
+        swap_total: field("SwapTotal").unwrap_or(0),
+        swap_free: field("SwapFree").unwrap_or(0),
+    })
+}
+
+/// Parse the contents of `/proc/uptime`
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn parse_proc_uptime(contents: &str) -> Option<Duration> {
+    let seconds: f64 = contents.split_whitespace().next()?.parse().ok()?;
+    (seconds.is_finite() && seconds >= 0.0).then(|| Duration::from_secs_f64(seconds))
+}
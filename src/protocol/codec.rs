@@ -0,0 +1,134 @@
+//! Length-delimited framing for the RCP wire protocol
+//!
+//! Every message is sent as a big-endian `u32` length prefix followed by the
+//! payload. Framing, the maximum frame size, and error mapping live here so the
+//! server session loop and the client share a single implementation.
+
+use crate::protocol::handshake::ProtocolVersion;
+use bytes::Bytes;
+use futures_util::{SinkExt, StreamExt};
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_util::codec::{Framed, LengthDelimitedCodec, LengthDelimitedCodecError};
+
+/// Default maximum frame size (16 MiB)
+pub const DEFAULT_MAX_FRAME_LENGTH: usize = 16 * 1024 * 1024;
+
+/// A byte stream wrapped in the RCP length-delimited codec
+pub type FramedStream<T> = Framed<T, LengthDelimitedCodec>;
+
+/// Errors produced while reading or writing frames
+#[derive(Debug, Error)]
+pub enum FrameError {
+    /// The peer closed the connection
+    #[error("Connection closed by peer")]
+    Closed,
+
+    /// A frame exceeded the configured maximum length
+    #[error("Frame exceeds maximum length of {0} bytes")]
+    TooLarge(usize),
+
+    /// The payload was not valid UTF-8
+    #[error("Invalid frame payload: {0}")]
+    InvalidPayload(String),
+
+    /// A frame could not be compressed or decompressed
+    #[error("Compression error: {0}")]
+    Compression(String),
+
+    /// The peer speaks an incompatible protocol version
+    #[error("Incompatible protocol version: peer speaks {remote}, this side speaks {local}")]
+    IncompatibleProtocol {
+        local: ProtocolVersion,
+        remote: ProtocolVersion,
+    },
+
+    /// The peer did not open the connection with the RCP protocol magic
+    #[error("Not an RCP client: connection did not start with the protocol magic")]
+    BadMagic,
+
+    /// The client holds a pre-shared key but the server issued no challenge
+    /// to prove it against, as servers before protocol 1.1 do
+    #[error("Server does not support PSK challenge (protocol {0}); upgrade the daemon to 1.1 or use a token")]
+    ChallengeUnsupported(ProtocolVersion),
+
+    /// A frame did not fully arrive within the allowed time
+    #[error("Frame not received within {0:?}")]
+    ReadTimeout(std::time::Duration),
+
+    /// An underlying I/O error
+    #[error("IO error: {0}")]
+    Io(std::io::Error),
+}
+
+/// Create the length-delimited codec used by the RCP protocol
+pub fn codec(max_frame_length: usize) -> LengthDelimitedCodec {
+    LengthDelimitedCodec::builder()
+        .length_field_length(4)
+        .big_endian()
+        .max_frame_length(max_frame_length)
+        .new_codec()
+}
+
+/// Wrap a byte stream in the RCP framing
+pub fn framed<T>(io: T, max_frame_length: usize) -> FramedStream<T>
+where
+    T: AsyncRead + AsyncWrite,
+{
+    Framed::new(io, codec(max_frame_length))
+}
+
+/// Read the next frame from the stream
+pub async fn read_frame<T>(framed: &mut FramedStream<T>) -> Result<Bytes, FrameError>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    let max_frame_length = framed.codec().max_frame_length();
+
+    match framed.next().await {
+        Some(Ok(frame)) => Ok(frame.freeze()),
+        Some(Err(e)) => Err(map_io_error(e, max_frame_length)),
+        None => Err(FrameError::Closed),
+    }
+}
+
+/// Read the next frame and decode it as UTF-8 text
+pub async fn read_text_frame<T>(framed: &mut FramedStream<T>) -> Result<String, FrameError>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    let frame = read_frame(framed).await?;
+    String::from_utf8(frame.to_vec()).map_err(|e| FrameError::InvalidPayload(e.to_string()))
+}
+
+/// Write a single frame to the stream
+pub async fn write_frame<T>(framed: &mut FramedStream<T>, payload: Bytes) -> Result<(), FrameError>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    let max_frame_length = framed.codec().max_frame_length();
+
+    framed
+        .send(payload)
+        .await
+        .map_err(|e| map_io_error(e, max_frame_length))
+}
+
+/// Map codec I/O errors onto frame errors
+fn map_io_error(err: std::io::Error, max_frame_length: usize) -> FrameError {
+    let is_length_error = err
+        .get_ref()
+        .map(|inner| inner.is::<LengthDelimitedCodecError>())
+        .unwrap_or(false);
+
+    if is_length_error {
+        return FrameError::TooLarge(max_frame_length);
+    }
+
+    match err.kind() {
+        std::io::ErrorKind::UnexpectedEof
+        | std::io::ErrorKind::ConnectionReset
+        | std::io::ErrorKind::BrokenPipe => FrameError::Closed,
+        _ => FrameError::Io(err),
+    }
+}
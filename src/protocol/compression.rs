@@ -0,0 +1,191 @@
+//! Optional per-connection frame compression
+//!
+//! Compression is negotiated during the handshake: the client advertises the
+//! algorithms it supports and the server picks the first of its own allowed
+//! algorithms that the client also supports. Once an algorithm is agreed, every
+//! frame carries a one-byte header saying whether the payload is compressed; frames
+//! smaller than the threshold are sent as-is to avoid wasting CPU on tiny messages.
+//! Without an agreed algorithm, frames are sent unchanged with no header.
+
+use crate::protocol::codec::FrameError;
+use bytes::{BufMut, Bytes, BytesMut};
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+
+/// Header byte for a frame sent uncompressed
+const FLAG_RAW: u8 = 0;
+
+/// Header byte for a frame compressed with the negotiated algorithm
+const FLAG_COMPRESSED: u8 = 1;
+
+/// Default size in bytes below which frames are not compressed
+pub const DEFAULT_COMPRESSION_THRESHOLD: usize = 1024;
+
+/// Supported compression algorithms
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CompressionAlgorithm {
+    /// gzip (deflate) via flate2
+    Gzip,
+
+    /// Zstandard
+    Zstd,
+}
+
+impl CompressionAlgorithm {
+    /// All algorithms this build supports, in order of preference
+    pub fn supported() -> Vec<CompressionAlgorithm> {
+        vec![CompressionAlgorithm::Zstd, CompressionAlgorithm::Gzip]
+    }
+
+    /// Compress a payload
+    pub fn compress(&self, data: &[u8]) -> std::io::Result<Vec<u8>> {
+        match self {
+            CompressionAlgorithm::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+            CompressionAlgorithm::Zstd => zstd::stream::encode_all(data, 0),
+        }
+    }
+
+    /// Decompress a payload, refusing to produce more than `max_len` bytes
+    pub fn decompress(&self, data: &[u8], max_len: usize) -> std::io::Result<Vec<u8>> {
+        let mut output = Vec::new();
+        let limit = max_len as u64 + 1;
+
+        match self {
+            CompressionAlgorithm::Gzip => {
+                flate2::read::GzDecoder::new(data)
+                    .take(limit)
+                    .read_to_end(&mut output)?;
+            }
+            CompressionAlgorithm::Zstd => {
+                zstd::stream::read::Decoder::new(data)?
+                    .take(limit)
+                    .read_to_end(&mut output)?;
+            }
+        }
+
+        if output.len() > max_len {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Decompressed frame exceeds {} bytes", max_len),
+            ));
+        }
+
+        Ok(output)
+    }
+}
+
+impl std::fmt::Display for CompressionAlgorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CompressionAlgorithm::Gzip => write!(f, "gzip"),
+            CompressionAlgorithm::Zstd => write!(f, "zstd"),
+        }
+    }
+}
+
+/// Pick the algorithm to use for a connection
+///
+/// The server's allowed list is ordered by preference; the first entry the client
+/// also supports wins. Returns `None` when there is no overlap.
+pub fn negotiate(
+    client_supported: &[CompressionAlgorithm],
+    server_allowed: &[CompressionAlgorithm],
+) -> Option<CompressionAlgorithm> {
+    server_allowed
+        .iter()
+        .find(|algorithm| client_supported.contains(algorithm))
+        .copied()
+}
+
+/// Applies the negotiated compression to outgoing and incoming frames
+#[derive(Debug, Clone)]
+pub struct FrameCompressor {
+    /// Negotiated algorithm, if any
+    algorithm: Option<CompressionAlgorithm>,
+
+    /// Frames smaller than this are sent uncompressed
+    threshold: usize,
+
+    /// Maximum size of a decompressed frame
+    max_frame_length: usize,
+}
+
+impl FrameCompressor {
+    /// Create a compressor for the negotiated algorithm
+    pub fn new(
+        algorithm: Option<CompressionAlgorithm>,
+        threshold: usize,
+        max_frame_length: usize,
+    ) -> Self {
+        Self {
+            algorithm,
+            threshold,
+            max_frame_length,
+        }
+    }
+
+    /// Create a compressor that leaves frames untouched
+    pub fn disabled(max_frame_length: usize) -> Self {
+        Self::new(None, DEFAULT_COMPRESSION_THRESHOLD, max_frame_length)
+    }
+
+    /// Get the negotiated algorithm
+    pub fn algorithm(&self) -> Option<CompressionAlgorithm> {
+        self.algorithm
+    }
+
+    /// Prepare a payload for sending
+    pub fn encode(&self, payload: Bytes) -> Result<Bytes, FrameError> {
+        let algorithm = match self.algorithm {
+            Some(algorithm) => algorithm,
+            None => return Ok(payload),
+        };
+
+        let mut frame = BytesMut::with_capacity(payload.len() + 1);
+
+        if payload.len() < self.threshold {
+            frame.put_u8(FLAG_RAW);
+            frame.extend_from_slice(&payload);
+        } else {
+            let compressed = algorithm
+                .compress(&payload)
+                .map_err(|e| FrameError::Compression(e.to_string()))?;
+            frame.put_u8(FLAG_COMPRESSED);
+            frame.extend_from_slice(&compressed);
+        }
+
+        Ok(frame.freeze())
+    }
+
+    /// Recover the payload from a received frame
+    pub fn decode(&self, frame: Bytes) -> Result<Bytes, FrameError> {
+        let algorithm = match self.algorithm {
+            Some(algorithm) => algorithm,
+            None => return Ok(frame),
+        };
+
+        if frame.is_empty() {
+            return Err(FrameError::Compression(
+                "Missing compression header".to_string(),
+            ));
+        }
+
+        match frame[0] {
+            FLAG_RAW => Ok(frame.slice(1..)),
+            FLAG_COMPRESSED => algorithm
+                .decompress(&frame[1..], self.max_frame_length)
+                .map(Bytes::from)
+                .map_err(|e| FrameError::Compression(e.to_string())),
+            flag => Err(FrameError::Compression(format!(
+                "Unknown compression header: {}",
+                flag
+            ))),
+        }
+    }
+}
@@ -0,0 +1,116 @@
+//! Server-initiated disconnects
+//!
+//! Before the server closes a session on its own initiative it sends a
+//! goodbye frame: a JSON-RPC notification with method [`GOODBYE_METHOD`]
+//! carrying a [`Goodbye`]. Clients that recognize it can tell the user why
+//! the connection ended instead of reporting a broken read.
+
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+
+/// Method name of the goodbye notification
+pub const GOODBYE_METHOD: &str = "session/goodbye";
+
+/// Why the server ended a session
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DisconnectReason {
+    /// The session sent nothing for longer than the session timeout
+    IdleTimeout,
+
+    /// The server is shutting down
+    Shutdown,
+
+    /// An administrator disconnected the session
+    Kicked,
+
+    /// The client did not answer a keep-alive ping in time
+    KeepaliveTimeout,
+
+    /// The session reached its maximum lifetime and must authenticate again
+    LifetimeExceeded,
+
+    /// A reason this build does not know about
+    #[serde(other)]
+    Unknown,
+}
+
+impl DisconnectReason {
+    /// Human-readable description of the reason
+    pub fn description(&self) -> &'static str {
+        match self {
+            DisconnectReason::IdleTimeout => "idle timeout",
+            DisconnectReason::Shutdown => "server shutting down",
+            DisconnectReason::Kicked => "kicked by an administrator",
+            DisconnectReason::KeepaliveTimeout => "no reply to keep-alive ping",
+            DisconnectReason::LifetimeExceeded => "session lifetime exceeded",
+            DisconnectReason::Unknown => "unknown reason",
+        }
+    }
+}
+
+impl fmt::Display for DisconnectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.description())
+    }
+}
+
+/// Parameters of a goodbye notification
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Goodbye {
+    /// Reason code
+    pub reason: DisconnectReason,
+
+    /// Free-form detail for the user, e.g. why an administrator kicked them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+impl Goodbye {
+    /// A goodbye with no further detail
+    pub fn new(reason: DisconnectReason) -> Self {
+        Self {
+            reason,
+            message: None,
+        }
+    }
+
+    /// Attach a message for the user
+    pub fn with_message(mut self, message: impl Into<String>) -> Self {
+        self.message = Some(message.into());
+        self
+    }
+
+    /// Encode the notification as a frame payload
+    pub fn to_payload(&self) -> Bytes {
+        let notification = serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": GOODBYE_METHOD,
+            "params": self,
+        });
+        Bytes::from(notification.to_string())
+    }
+
+    /// Recognize a goodbye notification in a frame payload
+    ///
+    /// Returns `None` for anything else, including ordinary responses.
+    pub fn from_payload(payload: &[u8]) -> Option<Self> {
+        let mut message: Value = serde_json::from_slice(payload).ok()?;
+        if message.get("method")?.as_str()? != GOODBYE_METHOD {
+            return None;
+        }
+        serde_json::from_value(message.get_mut("params")?.take()).ok()
+    }
+}
+
+impl fmt::Display for Goodbye {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "disconnected by server: {}", self.reason)?;
+        if let Some(message) = &self.message {
+            write!(f, " ({})", message)?;
+        }
+        Ok(())
+    }
+}
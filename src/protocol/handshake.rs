@@ -0,0 +1,377 @@
+//! Connection handshake
+//!
+//! The first frame on every connection is a `ClientHello` sent by the client,
+//! answered by a `ServerHello`. The handshake negotiates per-connection options
+//! such as frame compression before any requests are exchanged.
+//!
+//! Both hellos carry the sender's [`ProtocolVersion`]. Peers with different
+//! major versions refuse each other; a minor mismatch is only logged.
+//!
+//! Before its hello the client sends [`PROTOCOL_MAGIC`], so the server can
+//! drop anything that is not an RCP client (HTTP scanners, browsers) as soon
+//! as its first bytes arrive.
+//!
+//! The server's hello carries a fresh random challenge for the connection. A
+//! client holding a pre-shared key does not send the key itself: it marks its
+//! hello with `challenge` and, once it has the server's hello, answers with a
+//! [`ChallengeResponse`] proving it holds the key for this challenge. A proof
+//! recorded on one connection is worthless on the next.
+
+use crate::protocol::codec::{self, FrameError, FramedStream};
+use crate::protocol::compression::{self, CompressionAlgorithm, FrameCompressor};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use bytes::Bytes;
+use hmac::{Hmac, Mac};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::fmt;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use uuid::Uuid;
+
+/// Protocol version spoken by this build
+///
+/// Bump the minor version for backwards-compatible additions and the major
+/// version for changes older peers would misinterpret.
+///
+/// - 1.0: magic, hellos and compression negotiation
+/// - 1.1: the server hello carries a [`ServerHello::challenge`], and a
+///   client with a pre-shared key answers with a [`ChallengeResponse`]
+pub const PROTOCOL_VERSION: ProtocolVersion = ProtocolVersion::new(1, 1);
+
+/// Bytes every connection starts with, ahead of the framed `ClientHello`
+pub const PROTOCOL_MAGIC: [u8; 4] = *b"RCP\x01";
+
+/// Version of the wire protocol
+///
+/// Peers that predate versioning send none and are treated as version 1.0,
+/// the protocol they speak.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct ProtocolVersion {
+    /// Incremented for incompatible changes
+    pub major: u16,
+
+    /// Incremented for compatible additions
+    pub minor: u16,
+}
+
+impl ProtocolVersion {
+    /// Create a protocol version
+    pub const fn new(major: u16, minor: u16) -> Self {
+        Self { major, minor }
+    }
+
+    /// Version assumed for a hello that carries none
+    pub const fn unversioned() -> Self {
+        Self::new(1, 0)
+    }
+
+    /// Whether a peer speaking `other` can be talked to
+    pub fn is_compatible_with(&self, other: &ProtocolVersion) -> bool {
+        self.major == other.major
+    }
+}
+
+impl fmt::Display for ProtocolVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+/// First message sent by a client
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClientHello {
+    /// Protocol version the client speaks
+    #[serde(default = "ProtocolVersion::unversioned")]
+    pub protocol_version: ProtocolVersion,
+
+    /// Compression algorithms the client supports
+    #[serde(default)]
+    pub compression: Vec<CompressionAlgorithm>,
+
+    /// Credential presented to the server, e.g. its pre-shared key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth: Option<String>,
+
+    /// Resume token of a dropped session to reattach to, instead of starting a new one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resume: Option<String>,
+
+    /// Whether the client answers the server's challenge with a
+    /// [`ChallengeResponse`] proving it holds the pre-shared key
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub challenge: bool,
+}
+
+/// Server response to a `ClientHello`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerHello {
+    /// Protocol version the server speaks
+    #[serde(default = "ProtocolVersion::unversioned")]
+    pub protocol_version: ProtocolVersion,
+
+    /// Compression algorithm selected for this connection, if any
+    pub compression: Option<CompressionAlgorithm>,
+
+    /// Frames smaller than this many bytes are sent uncompressed
+    pub compression_threshold: usize,
+
+    /// Random challenge issued for this connection, which a pre-shared key
+    /// is proven against
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub challenge: Option<String>,
+}
+
+/// Client's answer to the server's challenge, sent right after its hello
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChallengeResponse {
+    /// [`challenge_proof`] of the client's key for the challenge
+    pub proof: String,
+}
+
+impl Default for ClientHello {
+    fn default() -> Self {
+        Self {
+            protocol_version: PROTOCOL_VERSION,
+            compression: CompressionAlgorithm::supported(),
+            auth: None,
+            resume: None,
+            challenge: false,
+        }
+    }
+}
+
+/// A fresh random challenge for one connection
+pub fn new_challenge() -> String {
+    URL_SAFE_NO_PAD.encode([*Uuid::new_v4().as_bytes(), *Uuid::new_v4().as_bytes()].concat())
+}
+
+fn challenge_mac(key: &str, challenge: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key.as_bytes()).expect("HMAC key of any length");
+    mac.update(challenge.as_bytes());
+    mac
+}
+
+/// Proof of holding `key` for `challenge`: HMAC-SHA256 of the challenge
+/// under the key, base64url-encoded
+pub fn challenge_proof(key: &str, challenge: &str) -> String {
+    URL_SAFE_NO_PAD.encode(challenge_mac(key, challenge).finalize().into_bytes())
+}
+
+/// Whether `proof` proves holding `key` for `challenge`, compared in constant time
+pub fn verify_challenge_proof(key: &str, challenge: &str, proof: &str) -> bool {
+    URL_SAFE_NO_PAD
+        .decode(proof)
+        .is_ok_and(|proof| challenge_mac(key, challenge).verify_slice(&proof).is_ok())
+}
+
+/// Whether `presented` is `key` itself, compared in constant time
+pub fn verify_plain_key(key: &str, presented: &str) -> bool {
+    let expected = challenge_mac(key, key).finalize().into_bytes();
+    challenge_mac(key, presented)
+        .verify_slice(&expected)
+        .is_ok()
+}
+
+/// Check a peer's version, logging a warning for a minor mismatch
+fn check_version(local: ProtocolVersion, remote: ProtocolVersion) -> Result<(), FrameError> {
+    if !local.is_compatible_with(&remote) {
+        return Err(FrameError::IncompatibleProtocol { local, remote });
+    }
+
+    if local != remote {
+        warn!(
+            "Peer speaks protocol version {} (this side speaks {}); upgrade the older side",
+            remote, local
+        );
+    }
+
+    Ok(())
+}
+
+/// Perform the client side of the handshake
+///
+/// Returns the compressor to use for the rest of the connection, or
+/// [`FrameError::IncompatibleProtocol`] if the server speaks an incompatible version.
+pub async fn client_handshake<T>(
+    framed: &mut FramedStream<T>,
+    hello: &ClientHello,
+) -> Result<FrameCompressor, FrameError>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    client_handshake_with_key(framed, hello, None).await
+}
+
+/// Perform the client side of the handshake, proving `key` against the
+/// server's challenge if set
+///
+/// The key itself is never sent. A server that issues no challenge predates
+/// protocol 1.1, so with a key this fails with
+/// [`FrameError::ChallengeUnsupported`] rather than connecting without one.
+pub async fn client_handshake_with_key<T>(
+    framed: &mut FramedStream<T>,
+    hello: &ClientHello,
+    key: Option<&str>,
+) -> Result<FrameCompressor, FrameError>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    let hello = ClientHello {
+        challenge: key.is_some(),
+        ..hello.clone()
+    };
+
+    framed
+        .get_mut()
+        .write_all(&PROTOCOL_MAGIC)
+        .await
+        .map_err(FrameError::Io)?;
+
+    let payload =
+        serde_json::to_vec(&hello).map_err(|e| FrameError::InvalidPayload(e.to_string()))?;
+    codec::write_frame(framed, Bytes::from(payload)).await?;
+
+    let response = codec::read_frame(framed).await?;
+    let server_hello: ServerHello = serde_json::from_slice(&response)
+        .map_err(|e| FrameError::InvalidPayload(format!("Invalid server hello: {}", e)))?;
+    check_version(hello.protocol_version, server_hello.protocol_version)?;
+
+    if let Some(key) = key {
+        let Some(challenge) = server_hello.challenge.as_deref() else {
+            return Err(FrameError::ChallengeUnsupported(
+                server_hello.protocol_version,
+            ));
+        };
+        let response = ChallengeResponse {
+            proof: challenge_proof(key, challenge),
+        };
+        let payload =
+            serde_json::to_vec(&response).map_err(|e| FrameError::InvalidPayload(e.to_string()))?;
+        codec::write_frame(framed, Bytes::from(payload)).await?;
+    }
+
+    Ok(FrameCompressor::new(
+        server_hello.compression,
+        server_hello.compression_threshold,
+        framed.codec().max_frame_length(),
+    ))
+}
+
+/// Read and check the protocol magic a client starts the connection with
+///
+/// Fails with [`FrameError::BadMagic`] on the first byte that does not match,
+/// without waiting for the rest.
+async fn read_magic<T>(framed: &mut FramedStream<T>) -> Result<(), FrameError>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    // Nothing has been read through the codec yet, so the magic is still unbuffered
+    let io = framed.get_mut();
+    let mut received = [0u8; PROTOCOL_MAGIC.len()];
+    let mut filled = 0;
+
+    while filled < received.len() {
+        let n = io
+            .read(&mut received[filled..])
+            .await
+            .map_err(FrameError::Io)?;
+        if n == 0 {
+            return Err(FrameError::Closed);
+        }
+        if received[filled..filled + n] != PROTOCOL_MAGIC[filled..filled + n] {
+            return Err(FrameError::BadMagic);
+        }
+        filled += n;
+    }
+
+    Ok(())
+}
+
+/// Perform the server side of the handshake
+///
+/// Checks the protocol magic, reads the client's hello, negotiates options against the server's allowed
+/// settings, and replies with the result. The reply always carries the server's
+/// version so an incompatible client can explain the failure to its user.
+pub async fn server_handshake<T>(
+    framed: &mut FramedStream<T>,
+    allowed_compression: &[CompressionAlgorithm],
+    compression_threshold: usize,
+) -> Result<(ClientHello, FrameCompressor), FrameError>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    let (hello, compressor, _) =
+        handshake_server(framed, allowed_compression, compression_threshold, None).await?;
+    Ok((hello, compressor))
+}
+
+/// Perform the server side of the handshake, issuing `challenge`
+///
+/// Also returns the client's proof for the challenge, if it sent one.
+pub async fn server_handshake_with_challenge<T>(
+    framed: &mut FramedStream<T>,
+    allowed_compression: &[CompressionAlgorithm],
+    compression_threshold: usize,
+    challenge: &str,
+) -> Result<(ClientHello, FrameCompressor, Option<String>), FrameError>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    handshake_server(
+        framed,
+        allowed_compression,
+        compression_threshold,
+        Some(challenge),
+    )
+    .await
+}
+
+async fn handshake_server<T>(
+    framed: &mut FramedStream<T>,
+    allowed_compression: &[CompressionAlgorithm],
+    compression_threshold: usize,
+    challenge: Option<&str>,
+) -> Result<(ClientHello, FrameCompressor, Option<String>), FrameError>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    read_magic(framed).await?;
+
+    let request = codec::read_frame(framed).await?;
+    let client_hello: ClientHello = serde_json::from_slice(&request)
+        .map_err(|e| FrameError::InvalidPayload(format!("Invalid client hello: {}", e)))?;
+
+    let selected = compression::negotiate(&client_hello.compression, allowed_compression);
+    let server_hello = ServerHello {
+        protocol_version: PROTOCOL_VERSION,
+        compression: selected,
+        compression_threshold,
+        challenge: challenge.map(str::to_string),
+    };
+
+    let payload =
+        serde_json::to_vec(&server_hello).map_err(|e| FrameError::InvalidPayload(e.to_string()))?;
+    codec::write_frame(framed, Bytes::from(payload)).await?;
+    check_version(PROTOCOL_VERSION, client_hello.protocol_version)?;
+
+    let proof = match challenge {
+        Some(_) if client_hello.challenge => {
+            let response = codec::read_frame(framed).await?;
+            let response: ChallengeResponse = serde_json::from_slice(&response).map_err(|e| {
+                FrameError::InvalidPayload(format!("Invalid challenge response: {}", e))
+            })?;
+            Some(response.proof)
+        }
+        _ => None,
+    };
+
+    let compressor = FrameCompressor::new(
+        selected,
+        compression_threshold,
+        framed.codec().max_frame_length(),
+    );
+
+    Ok((client_hello, compressor, proof))
+}
@@ -0,0 +1,77 @@
+//! Keep-alive pings on quiet sessions
+//!
+//! NAT gateways and firewalls drop connections that carry no traffic for a
+//! while, without telling either end. With `session.keepalive_secs` set, the
+//! server sends a session that has been quiet that long a notification with
+//! method [`PING_METHOD`] carrying a [`Keepalive`]. The client answers with a
+//! [`PONG_METHOD`] notification echoing it; if nothing at all arrives within
+//! `session.keepalive_timeout_secs` the server closes the session.
+//!
+//! Pongs are not requests: they get no response and, unlike requests, do not
+//! reset the idle timeout, so a client that only answers pings is still
+//! disconnected once it has been idle for `session.timeout`.
+
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Method name of the server's keep-alive notification
+pub const PING_METHOD: &str = "session/ping";
+
+/// Method name of the client's answer to a keep-alive notification
+pub const PONG_METHOD: &str = "session/pong";
+
+/// Parameters of a keep-alive ping or pong
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Keepalive {
+    /// Number of the ping, counting from 1 in each session
+    pub seq: u64,
+}
+
+impl Keepalive {
+    /// Keep-alive number `seq`
+    pub fn new(seq: u64) -> Self {
+        Self { seq }
+    }
+
+    /// Encode a ping carrying this keep-alive as a frame payload
+    pub fn ping_payload(&self) -> Bytes {
+        self.to_payload(PING_METHOD)
+    }
+
+    /// Encode the pong answering this keep-alive as a frame payload
+    pub fn pong_payload(&self) -> Bytes {
+        self.to_payload(PONG_METHOD)
+    }
+
+    /// Recognize a ping in a frame payload
+    ///
+    /// Returns `None` for anything else, including ordinary responses.
+    pub fn ping_from_payload(payload: &[u8]) -> Option<Self> {
+        Self::from_payload(PING_METHOD, payload)
+    }
+
+    /// Recognize a pong in a frame payload
+    ///
+    /// Returns `None` for anything else, including ordinary requests.
+    pub fn pong_from_payload(payload: &[u8]) -> Option<Self> {
+        Self::from_payload(PONG_METHOD, payload)
+    }
+
+    fn to_payload(&self, method: &str) -> Bytes {
+        let notification = serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": self,
+        });
+        Bytes::from(notification.to_string())
+    }
+
+    fn from_payload(method: &str, payload: &[u8]) -> Option<Self> {
+        let mut message: Value = serde_json::from_slice(payload).ok()?;
+        if message.get("method")?.as_str()? != method || message.get("id").is_some() {
+            return None;
+        }
+        serde_json::from_value(message.get_mut("params")?.take()).ok()
+    }
+}
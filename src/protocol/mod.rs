@@ -0,0 +1,14 @@
+// Module for the wire protocol shared by the daemon and its clients
+// Both the integrated server and the CLI client speak this protocol
+
+pub mod codec;
+pub mod compression;
+pub mod goodbye;
+pub mod handshake;
+pub mod keepalive;
+
+// Re-export important items
+pub use self::codec::{FrameError, FramedStream};
+pub use self::compression::{CompressionAlgorithm, FrameCompressor};
+pub use self::goodbye::{DisconnectReason, Goodbye};
+pub use self::keepalive::Keepalive;
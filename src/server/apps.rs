@@ -0,0 +1,666 @@
+//! Application launching and instance tracking
+//!
+//! The [`AppRegistry`] holds the definitions of the applications that may be
+//! launched, as served by `apps/list` and `apps/info`. The [`AppLauncher`]
+//! spawns application processes, keeps a registry of the running instances
+//! and captures each instance's stdout/stderr into a bounded in-memory buffer
+//! so recent output can be fetched over `apps/logs`.
+//!
+//! Each instance is owned by a watcher task that waits for the process to
+//! terminate, whether on its own or through [`AppLauncher::stop`]. The first
+//! of the two to take the instance out of the registry removes it, so an
+//! instance is never removed twice, and the watcher records an `app_exited`
+//! event with the exit code.
+
+use crate::server::config::ApplicationConfig;
+use crate::server::error::{Error, PermissionError, Result};
+use crate::server::events::EventLog;
+use crate::server::tokens::permission_granted;
+use chrono::Utc;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::process::{Child, Command};
+use tokio::sync::{oneshot, Mutex, RwLock};
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+/// Default number of output lines retained per instance
+pub const DEFAULT_OUTPUT_BUFFER_LINES: usize = 1000;
+
+/// Permission needed to list applications and instances, and to read the
+/// output of instances other sessions launched
+pub const READ_APPS_PERMISSION: &str = "read:apps";
+
+/// Permission needed to launch and stop applications
+pub const MANAGE_APPS_PERMISSION: &str = "admin:apps";
+
+/// Definition of a launchable application
+///
+/// Definitions written before `required_permission`, `category` and
+/// `accepts_args` existed still load: anyone may launch them, uncategorized,
+/// with extra arguments.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AppDefinition {
+    /// Application ID
+    pub id: String,
+
+    /// Display name
+    pub name: String,
+
+    /// Path to the executable
+    pub executable_path: String,
+
+    /// Arguments always passed to the executable
+    #[serde(default)]
+    pub arguments: Vec<String>,
+
+    /// Working directory for the process
+    #[serde(default)]
+    pub working_dir: Option<String>,
+
+    /// Permission needed to launch the application, e.g. `app:editor`
+    #[serde(default)]
+    pub required_permission: Option<String>,
+
+    /// Category clients may group the application under
+    #[serde(default)]
+    pub category: Option<String>,
+
+    /// Whether arguments may be appended when launching
+    #[serde(default = "default_accepts_args")]
+    pub accepts_args: bool,
+}
+
+fn default_accepts_args() -> bool {
+    true
+}
+
+impl Default for AppDefinition {
+    fn default() -> Self {
+        Self {
+            id: String::new(),
+            name: String::new(),
+            executable_path: String::new(),
+            arguments: Vec::new(),
+            working_dir: None,
+            required_permission: None,
+            category: None,
+            accepts_args: default_accepts_args(),
+        }
+    }
+}
+
+impl AppDefinition {
+    /// Check that a caller holding `permissions` may launch the application
+    /// with `args` appended
+    pub fn check_launch(&self, permissions: &[String], args: &[String]) -> Result<()> {
+        if let Some(required) = &self.required_permission {
+            if !permission_granted(permissions, required) {
+                return Err(PermissionError::new(required.as_str(), permissions.to_vec()).into());
+            }
+        }
+        if !self.accepts_args && !args.is_empty() {
+            return Err(Error::InvalidArgument(format!(
+                "{} does not accept arguments",
+                self.id
+            )));
+        }
+        Ok(())
+    }
+
+    /// Read a definition from a `.toml` or `.json` file
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)?;
+        let app: Self = if is_json(path) {
+            serde_json::from_str(&contents).map_err(|e| invalid_definition(path, e))?
+        } else {
+            toml::from_str(&contents).map_err(|e| invalid_definition(path, e))?
+        };
+
+        if app.id.trim().is_empty() {
+            return Err(invalid_definition(path, "id must not be empty"));
+        }
+        Ok(app)
+    }
+
+    /// Write the definition to a `.toml` or `.json` file, all fields included
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let contents = if is_json(path) {
+            serde_json::to_string_pretty(self).map_err(|e| invalid_definition(path, e))?
+        } else {
+            toml::to_string(self).map_err(|e| invalid_definition(path, e))?
+        };
+        std::fs::write(path, contents)?;
+        Ok(())
+    }
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "json")
+}
+
+fn invalid_definition(path: &Path, error: impl std::fmt::Display) -> Error {
+    Error::InvalidArgument(format!(
+        "Invalid application definition {}: {}",
+        path.display(),
+        error
+    ))
+}
+
+/// Definitions of the applications that may be launched, by ID
+///
+/// With application management enabled, the registry is loaded from
+/// `app_dir`, which holds one `.toml` or `.json` definition per application.
+/// Files that do not parse are skipped with a warning rather than keeping
+/// the server from starting.
+#[derive(Debug, Clone, Default)]
+pub struct AppRegistry {
+    apps: Arc<std::sync::RwLock<BTreeMap<String, AppDefinition>>>,
+}
+
+impl AppRegistry {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The registry `config` describes, empty unless application management is enabled
+    pub fn from_config(config: &ApplicationConfig) -> Self {
+        let registry = Self::new();
+        if config.enabled {
+            match registry.load_dir(Path::new(&config.app_dir)) {
+                Ok(count) => info!(
+                    "Loaded {} application definitions from {}",
+                    count, config.app_dir
+                ),
+                Err(e) => warn!(
+                    "Failed to read application directory {}: {}",
+                    config.app_dir, e
+                ),
+            }
+        }
+        registry
+    }
+
+    /// Add the definitions in `dir`, returning how many were loaded
+    ///
+    /// A definition replaces any already registered under its ID.
+    pub fn load_dir(&self, dir: &Path) -> std::io::Result<usize> {
+        let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|ext| ext == "toml" || ext == "json")
+            })
+            .collect();
+        paths.sort();
+
+        let mut loaded = 0;
+        for path in paths {
+            match AppDefinition::load(&path) {
+                Ok(app) => {
+                    self.insert(app);
+                    loaded += 1;
+                }
+                Err(e) => warn!("Skipping {}", e),
+            }
+        }
+        Ok(loaded)
+    }
+
+    /// Register `app`, returning the definition it replaced
+    pub fn insert(&self, app: AppDefinition) -> Option<AppDefinition> {
+        self.write().insert(app.id.clone(), app)
+    }
+
+    /// The definition registered under `id`
+    pub fn get(&self, id: &str) -> Option<AppDefinition> {
+        self.read().get(id).cloned()
+    }
+
+    /// Every registered definition, by ID
+    pub fn list(&self) -> Vec<AppDefinition> {
+        self.read().values().cloned().collect()
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, BTreeMap<String, AppDefinition>> {
+        self.apps.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write(&self) -> std::sync::RwLockWriteGuard<'_, BTreeMap<String, AppDefinition>> {
+        self.apps.write().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Stream an output line was captured from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+impl std::fmt::Display for OutputStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OutputStream::Stdout => write!(f, "stdout"),
+            OutputStream::Stderr => write!(f, "stderr"),
+        }
+    }
+}
+
+/// A captured line of application output
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OutputLine {
+    /// Sequence number, increasing across both streams
+    pub seq: u64,
+
+    /// Capture time (RFC 3339)
+    pub timestamp: String,
+
+    /// Stream the line came from
+    pub stream: OutputStream,
+
+    /// Line contents without the trailing newline
+    pub line: String,
+}
+
+/// Ring buffer holding the most recent output lines of an instance
+#[derive(Debug)]
+pub struct OutputBuffer {
+    lines: VecDeque<OutputLine>,
+    capacity: usize,
+    next_seq: u64,
+}
+
+impl OutputBuffer {
+    /// Create a buffer retaining at most `capacity` lines
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            lines: VecDeque::with_capacity(capacity.min(DEFAULT_OUTPUT_BUFFER_LINES)),
+            capacity: capacity.max(1),
+            next_seq: 0,
+        }
+    }
+
+    /// Append a line, evicting the oldest one when the buffer is full
+    pub fn push(&mut self, stream: OutputStream, line: String) {
+        if self.lines.len() == self.capacity {
+            self.lines.pop_front();
+        }
+
+        self.lines.push_back(OutputLine {
+            seq: self.next_seq,
+            timestamp: Utc::now().to_rfc3339(),
+            stream,
+            line,
+        });
+        self.next_seq += 1;
+    }
+
+    /// The last `count` lines still in the buffer
+    pub fn tail(&self, count: usize) -> Vec<OutputLine> {
+        let skip = self.lines.len().saturating_sub(count);
+        self.lines.iter().skip(skip).cloned().collect()
+    }
+
+    /// Lines with a sequence number of at least `seq`, capped to the last `count`
+    pub fn since(&self, seq: u64, count: usize) -> Vec<OutputLine> {
+        let lines: Vec<OutputLine> = self
+            .lines
+            .iter()
+            .filter(|line| line.seq >= seq)
+            .cloned()
+            .collect();
+        let skip = lines.len().saturating_sub(count);
+        lines.into_iter().skip(skip).collect()
+    }
+
+    /// Sequence number the next captured line will get
+    pub fn next_seq(&self) -> u64 {
+        self.next_seq
+    }
+
+    /// Number of lines currently held
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// Whether the buffer holds no lines
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+}
+
+/// Public information about a running instance
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppInstanceInfo {
+    /// Instance ID
+    pub id: String,
+
+    /// ID of the launched application
+    pub app_id: String,
+
+    /// Application display name
+    pub name: String,
+
+    /// User the instance was launched for
+    pub user_id: String,
+
+    /// Instance status
+    pub status: String,
+
+    /// Launch time (RFC 3339)
+    pub created_at: String,
+
+    /// OS process ID
+    pub pid: Option<u32>,
+
+    /// Session the instance was launched from, if any
+    #[serde(default)]
+    pub session_id: Option<Uuid>,
+}
+
+/// Recent output of an instance, as returned by `apps/logs`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppLogs {
+    /// Instance ID
+    pub instance_id: String,
+
+    /// Output lines, oldest first
+    pub lines: Vec<OutputLine>,
+
+    /// Sequence number to pass as `since` to fetch only newer lines
+    pub next_seq: u64,
+}
+
+/// A running application instance
+struct AppInstance {
+    info: AppInstanceInfo,
+    output: Arc<Mutex<OutputBuffer>>,
+
+    /// Asks the watcher to kill the process
+    stop_tx: oneshot::Sender<()>,
+
+    /// Watcher task owning the process, finished once it has terminated
+    watcher: JoinHandle<()>,
+}
+
+type InstanceRegistry = Arc<RwLock<HashMap<Uuid, AppInstance>>>;
+
+/// Launches applications and tracks their running instances
+#[derive(Clone)]
+pub struct AppLauncher {
+    /// Running instances by ID
+    instances: InstanceRegistry,
+
+    /// Maximum number of output lines kept per instance
+    output_buffer_lines: usize,
+
+    /// Log receiving an `app_exited` event per terminated instance
+    events: EventLog,
+}
+
+impl AppLauncher {
+    /// Create a launcher keeping up to `output_buffer_lines` lines of output per instance
+    pub fn new(output_buffer_lines: usize) -> Self {
+        Self {
+            instances: Arc::new(RwLock::new(HashMap::new())),
+            output_buffer_lines,
+            events: EventLog::default(),
+        }
+    }
+
+    /// Record instance exits in `events` instead of a private log
+    pub fn with_events(mut self, events: EventLog) -> Self {
+        self.events = events;
+        self
+    }
+
+    /// Log that instance exits are recorded in
+    pub fn events(&self) -> &EventLog {
+        &self.events
+    }
+
+    /// Launch an application for a user, with extra arguments appended
+    ///
+    /// Fails without starting anything unless
+    /// [`AppDefinition::check_launch`] allows the user's `permissions`.
+    pub async fn launch(
+        &self,
+        app: &AppDefinition,
+        user_id: &str,
+        permissions: &[String],
+        args: &[String],
+    ) -> Result<AppInstanceInfo> {
+        self.start(app, user_id, permissions, args, None).await
+    }
+
+    /// Launch an application as [`launch`](Self::launch) does, owned by a session
+    ///
+    /// The instance is listed by [`list_in_session`](Self::list_in_session)
+    /// for `session_id` until it exits or is stopped.
+    pub async fn launch_in_session(
+        &self,
+        session_id: Uuid,
+        app: &AppDefinition,
+        user_id: &str,
+        permissions: &[String],
+        args: &[String],
+    ) -> Result<AppInstanceInfo> {
+        self.start(app, user_id, permissions, args, Some(session_id))
+            .await
+    }
+
+    async fn start(
+        &self,
+        app: &AppDefinition,
+        user_id: &str,
+        permissions: &[String],
+        args: &[String],
+        session_id: Option<Uuid>,
+    ) -> Result<AppInstanceInfo> {
+        app.check_launch(permissions, args)?;
+
+        let mut command = Command::new(&app.executable_path);
+        command
+            .args(&app.arguments)
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        if let Some(dir) = &app.working_dir {
+            command.current_dir(dir);
+        }
+
+        let mut child = command
+            .spawn()
+            .map_err(|e| Error::Application(format!("Failed to launch {}: {}", app.id, e)))?;
+
+        let output = Arc::new(Mutex::new(OutputBuffer::new(self.output_buffer_lines)));
+        if let Some(stdout) = child.stdout.take() {
+            capture_output(stdout, OutputStream::Stdout, output.clone());
+        }
+        if let Some(stderr) = child.stderr.take() {
+            capture_output(stderr, OutputStream::Stderr, output.clone());
+        }
+
+        let id = Uuid::new_v4();
+        let info = AppInstanceInfo {
+            id: id.to_string(),
+            app_id: app.id.clone(),
+            name: app.name.clone(),
+            user_id: user_id.to_string(),
+            status: "running".to_string(),
+            created_at: Utc::now().to_rfc3339(),
+            pid: child.id(),
+            session_id,
+        };
+
+        info!(
+            "Launched {} as instance {} (pid {:?})",
+            app.id, id, info.pid
+        );
+
+        // The watcher cannot remove the instance before it is registered,
+        // since it needs this write lock to do so
+        let mut instances = self.instances.write().await;
+        let (stop_tx, stop_rx) = oneshot::channel();
+        let watcher = watch_instance(
+            id,
+            user_id.to_string(),
+            child,
+            stop_rx,
+            self.instances.clone(),
+            self.events.clone(),
+        );
+        instances.insert(
+            id,
+            AppInstance {
+                info: info.clone(),
+                output,
+                stop_tx,
+                watcher,
+            },
+        );
+
+        Ok(info)
+    }
+
+    /// Stop a running instance and remove it from the registry
+    ///
+    /// Returns once the process has been killed and reaped.
+    pub async fn stop(&self, instance_id: &Uuid) -> Result<()> {
+        let instance = self
+            .instances
+            .write()
+            .await
+            .remove(instance_id)
+            .ok_or_else(|| Error::NotFound(format!("App instance not found: {}", instance_id)))?;
+
+        // Fails only if the process already exited, and the watcher is done
+        let _ = instance.stop_tx.send(());
+        if let Err(e) = instance.watcher.await {
+            warn!("Watcher for app instance {} failed: {}", instance_id, e);
+        }
+
+        debug!("Stopped app instance {}", instance_id);
+        Ok(())
+    }
+
+    /// List running instances
+    pub async fn list(&self) -> Vec<AppInstanceInfo> {
+        let instances = self.instances.read().await;
+        instances.values().map(|i| i.info.clone()).collect()
+    }
+
+    /// Running instances launched from `session_id`, oldest first
+    pub async fn list_in_session(&self, session_id: &Uuid) -> Vec<AppInstanceInfo> {
+        let instances = self.instances.read().await;
+        let mut owned: Vec<AppInstanceInfo> = instances
+            .values()
+            .filter(|i| i.info.session_id.as_ref() == Some(session_id))
+            .map(|i| i.info.clone())
+            .collect();
+        owned.sort_by(|a, b| (&a.created_at, &a.id).cmp(&(&b.created_at, &b.id)));
+        owned
+    }
+
+    /// Recent output of an instance
+    ///
+    /// Returns the last `lines` lines, or only lines from sequence number `since`
+    /// onwards when given, so callers can follow the output by polling.
+    pub async fn logs(
+        &self,
+        instance_id: &Uuid,
+        lines: usize,
+        since: Option<u64>,
+    ) -> Result<AppLogs> {
+        let output = {
+            let instances = self.instances.read().await;
+            let instance = instances.get(instance_id).ok_or_else(|| {
+                Error::NotFound(format!("App instance not found: {}", instance_id))
+            })?;
+            instance.output.clone()
+        };
+
+        let buffer = output.lock().await;
+        let lines = match since {
+            Some(seq) => buffer.since(seq, lines),
+            None => buffer.tail(lines),
+        };
+
+        Ok(AppLogs {
+            instance_id: instance_id.to_string(),
+            lines,
+            next_seq: buffer.next_seq(),
+        })
+    }
+}
+
+impl Default for AppLauncher {
+    fn default() -> Self {
+        Self::new(DEFAULT_OUTPUT_BUFFER_LINES)
+    }
+}
+
+/// Wait for an instance's process to terminate, then reap it from the registry
+///
+/// The process is killed when `stop_rx` fires or its sender is dropped. An
+/// instance that [`AppLauncher::stop`] already removed is not removed again.
+fn watch_instance(
+    id: Uuid,
+    user_id: String,
+    mut child: Child,
+    stop_rx: oneshot::Receiver<()>,
+    instances: InstanceRegistry,
+    events: EventLog,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let exited = tokio::select! {
+            status = child.wait() => Some(status),
+            _ = stop_rx => None,
+        };
+        let status = match exited {
+            Some(status) => status,
+            None => {
+                if let Err(e) = child.kill().await {
+                    warn!("Failed to kill app instance {}: {}", id, e);
+                }
+                child.wait().await
+            }
+        };
+
+        let exit_code = match status {
+            Ok(status) => status.code(),
+            Err(e) => {
+                warn!("Failed to wait for app instance {}: {}", id, e);
+                None
+            }
+        };
+
+        if instances.write().await.remove(&id).is_some() {
+            info!("App instance {} exited with code {:?}", id, exit_code);
+        }
+        events.record_app_exit(id, Some(user_id), exit_code);
+    })
+}
+
+/// Copy lines from a child's output stream into its buffer until the stream closes
+fn capture_output<R>(reader: R, stream: OutputStream, buffer: Arc<Mutex<OutputBuffer>>)
+where
+    R: AsyncRead + Unpin + Send + 'static,
+{
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(reader).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            buffer.lock().await.push(stream, line);
+        }
+    });
+}
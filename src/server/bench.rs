@@ -0,0 +1,167 @@
+//! Server-side auth benchmarks for `diag/bench`
+//!
+//! The loop runs inside the daemon so the numbers reflect the auth provider,
+//! not the network between the daemon and `rcpdaemon diag bench auth`. Each
+//! iteration validates a password and then looks up the user and their
+//! permissions, which is where subprocess-spawning native providers spend
+//! their time.
+
+use crate::auth::factory::{AuthConfig, AuthProviderType};
+use crate::auth::manager::AuthManager;
+use crate::auth::mock_provider::MockAuthProvider;
+use crate::server::user::{User, UserRole};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+
+/// Permission needed to run benchmarks
+pub const BENCH_PERMISSION: &str = "admin:diag";
+
+/// Iterations run when the caller does not say
+pub const DEFAULT_BENCH_ITERATIONS: usize = 100;
+
+/// Most iterations a single benchmark may run
+pub const MAX_BENCH_ITERATIONS: usize = 10_000;
+
+/// User the mock provider is benchmarked with
+const MOCK_BENCH_USER: &str = "bench";
+
+/// Password of [`MOCK_BENCH_USER`]
+const MOCK_BENCH_PASSWORD: &[u8] = b"bench-password";
+
+/// Throughput and latency of an auth benchmark
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuthBenchReport {
+    /// Name of the provider that was benchmarked
+    pub provider: String,
+
+    /// User the credentials were checked for
+    pub username: String,
+
+    /// Number of iterations run
+    pub iterations: usize,
+
+    /// Iterations whose credentials were refused or whose lookup failed
+    pub failures: usize,
+
+    /// Wall-clock time of the whole run, in milliseconds
+    pub total_ms: u64,
+
+    /// Iterations completed per second
+    pub per_second: f64,
+
+    /// Median iteration latency, in microseconds
+    pub p50_us: u64,
+
+    /// 90th percentile iteration latency, in microseconds
+    pub p90_us: u64,
+
+    /// 99th percentile iteration latency, in microseconds
+    pub p99_us: u64,
+
+    /// Slowest iteration, in microseconds
+    pub max_us: u64,
+}
+
+/// Benchmark `iterations` validations of `username`'s `password` against `manager`
+pub async fn bench_auth(
+    manager: &AuthManager,
+    username: &str,
+    password: &[u8],
+    iterations: usize,
+) -> AuthBenchReport {
+    let provider = manager.provider.read().await.name().to_string();
+    let mut latencies = Vec::with_capacity(iterations);
+    let mut failures = 0;
+
+    let started = Instant::now();
+    for _ in 0..iterations {
+        let iteration = Instant::now();
+        if !auth_round(manager, username, password).await {
+            failures += 1;
+        }
+        latencies.push(iteration.elapsed());
+    }
+    let total = started.elapsed();
+    latencies.sort();
+
+    AuthBenchReport {
+        provider,
+        username: username.to_string(),
+        iterations,
+        failures,
+        total_ms: millis(total),
+        per_second: match total.as_secs_f64() {
+            secs if secs > 0.0 => iterations as f64 / secs,
+            _ => 0.0,
+        },
+        p50_us: micros(percentile(&latencies, 50)),
+        p90_us: micros(percentile(&latencies, 90)),
+        p99_us: micros(percentile(&latencies, 99)),
+        max_us: micros(latencies.last().copied().unwrap_or_default()),
+    }
+}
+
+/// One benchmark iteration, returning whether it succeeded
+async fn auth_round(manager: &AuthManager, username: &str, password: &[u8]) -> bool {
+    match manager
+        .validate_credentials(username, password, "password")
+        .await
+    {
+        Ok(true) => {}
+        _ => return false,
+    }
+
+    match manager.get_user_by_username(username).await {
+        Ok(Some(user)) => manager.get_permissions(&user).await.is_ok(),
+        _ => false,
+    }
+}
+
+/// Benchmark the in-process mock provider, as a baseline for the configured one
+pub async fn bench_mock_auth(iterations: usize) -> Result<AuthBenchReport> {
+    let now = chrono::Utc::now().to_rfc3339();
+    let provider = MockAuthProvider::new()
+        .with_user(User {
+            id: uuid::Uuid::new_v4(),
+            username: MOCK_BENCH_USER.to_string(),
+            full_name: None,
+            email: None,
+            password_hash: String::new(),
+            role: UserRole::User,
+            created_at: now.clone(),
+            updated_at: now,
+        })
+        .with_credential(MOCK_BENCH_USER, MOCK_BENCH_PASSWORD)
+        .with_permission(MOCK_BENCH_USER, "connect:*");
+
+    let mut manager = AuthManager::new(AuthConfig {
+        provider: AuthProviderType::Mock,
+        ..Default::default()
+    })
+    .await?;
+    manager.provider = Arc::new(RwLock::new(Box::new(provider)));
+    manager.initialize().await?;
+
+    Ok(bench_auth(&manager, MOCK_BENCH_USER, MOCK_BENCH_PASSWORD, iterations).await)
+}
+
+/// The `pct`th percentile of sorted latencies, by the nearest-rank method
+pub fn percentile(sorted: &[Duration], pct: usize) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+
+    let rank = (pct * sorted.len()).div_ceil(100).max(1);
+    sorted[rank.min(sorted.len()) - 1]
+}
+
+fn millis(duration: Duration) -> u64 {
+    duration.as_millis().try_into().unwrap_or(u64::MAX)
+}
+
+fn micros(duration: Duration) -> u64 {
+    duration.as_micros().try_into().unwrap_or(u64::MAX)
+}
@@ -0,0 +1,243 @@
+//! Peer awareness for multi-daemon deployments
+//!
+//! A daemon configured with `cluster.peers` probes each peer every
+//! `cluster.probe_interval_secs` by calling its `cluster/announce` RPC. The
+//! call tells the peer how to reach this daemon and returns the peer's
+//! [`PeerHealth`], so both sides learn about each other from one round trip.
+//! What a daemon knows is kept in a [`PeerTable`] and served over
+//! `cluster/peers`, which `rcpdaemon diag cluster` summarizes.
+//!
+//! The feature is opt-in: with no peers configured nothing is probed, though
+//! peers that announce themselves are still listed. Announcing takes
+//! [`ANNOUNCE_PERMISSION`], so `cluster.peer_token` must grant it, and at
+//! most [`MAX_ANNOUNCED_PEERS`] announced peers are kept, each for
+//! [`ANNOUNCED_PEER_TTL`] after it was last heard from.
+
+use crate::client::Client;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Permission needed to list the cluster peers
+pub const READ_CLUSTER_PERMISSION: &str = "read:cluster";
+
+/// Permission needed to announce a daemon as a peer
+pub const ANNOUNCE_PERMISSION: &str = "admin:cluster";
+
+/// Most peers kept that announced themselves without being configured
+pub const MAX_ANNOUNCED_PEERS: usize = 64;
+
+/// How long an announced peer is listed after it was last heard from
+pub const ANNOUNCED_PEER_TTL: Duration = Duration::from_secs(600);
+
+/// Health a daemon reports to a peer that announces itself
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerHealth {
+    /// Daemon version
+    pub version: String,
+
+    /// Number of active sessions
+    pub active_sessions: usize,
+
+    /// Seconds since the server started accepting connections
+    pub uptime_secs: u64,
+}
+
+/// Parameters of a `cluster/announce` call
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Announcement {
+    /// Address the announcing daemon can be reached at, as `host:port`
+    pub address: String,
+
+    /// Health of the announcing daemon
+    pub health: PeerHealth,
+}
+
+/// What a daemon knows about one peer, as listed by `cluster/peers`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerStatus {
+    /// Peer address, as `host:port`
+    pub address: String,
+
+    /// Whether the peer is listed in `cluster.peers` rather than only having
+    /// announced itself
+    pub configured: bool,
+
+    /// Whether the last probe or announcement succeeded
+    pub reachable: bool,
+
+    /// Health the peer last reported
+    pub health: Option<PeerHealth>,
+
+    /// Round-trip time of the last successful probe, in milliseconds
+    pub latency_ms: Option<u64>,
+
+    /// When the peer was last heard from (RFC 3339)
+    pub last_seen: Option<String>,
+
+    /// Why the last probe failed, if it did
+    pub last_error: Option<String>,
+}
+
+impl PeerStatus {
+    fn new(address: &str, configured: bool) -> Self {
+        Self {
+            address: address.to_string(),
+            configured,
+            reachable: false,
+            health: None,
+            latency_ms: None,
+            last_seen: None,
+            last_error: None,
+        }
+    }
+}
+
+/// Known peers by address, shared by all sessions
+#[derive(Debug, Clone, Default)]
+pub struct PeerTable {
+    peers: Arc<Mutex<BTreeMap<String, PeerStatus>>>,
+}
+
+impl PeerTable {
+    /// Create a table listing the configured peers, none of them probed yet
+    pub fn new(configured: &[String]) -> Self {
+        let peers = configured
+            .iter()
+            .map(|address| (address.clone(), PeerStatus::new(address, true)))
+            .collect();
+
+        Self {
+            peers: Arc::new(Mutex::new(peers)),
+        }
+    }
+
+    /// Addresses of the peers listed in `cluster.peers`
+    pub fn configured(&self) -> Vec<String> {
+        self.lock()
+            .values()
+            .filter(|peer| peer.configured)
+            .map(|peer| peer.address.clone())
+            .collect()
+    }
+
+    /// Record the outcome of probing a peer
+    pub fn record_probe(
+        &self,
+        address: &str,
+        outcome: std::result::Result<(PeerHealth, Duration), String>,
+    ) {
+        let mut peers = self.lock();
+        let peer = peers
+            .entry(address.to_string())
+            .or_insert_with(|| PeerStatus::new(address, false));
+
+        match outcome {
+            Ok((health, latency)) => {
+                peer.reachable = true;
+                peer.health = Some(health);
+                peer.latency_ms = Some(latency.as_millis().try_into().unwrap_or(u64::MAX));
+                peer.last_seen = Some(Utc::now().to_rfc3339());
+                peer.last_error = None;
+            }
+            Err(error) => {
+                peer.reachable = false;
+                peer.latency_ms = None;
+                peer.last_error = Some(error);
+            }
+        }
+    }
+
+    /// Record a peer announcing itself
+    ///
+    /// Announced peers not heard from within [`ANNOUNCED_PEER_TTL`] are
+    /// dropped first, and the one heard from longest ago makes room for a
+    /// new one once [`MAX_ANNOUNCED_PEERS`] are listed.
+    pub fn record_announcement(&self, announcement: Announcement) {
+        let mut peers = self.lock();
+        let now = Utc::now();
+        peers.retain(|_, peer| {
+            peer.configured
+                || last_seen(peer).is_some_and(|seen| {
+                    (now - seen).to_std().unwrap_or_default() < ANNOUNCED_PEER_TTL
+                })
+        });
+
+        let announced = peers.values().filter(|peer| !peer.configured).count();
+        if announced >= MAX_ANNOUNCED_PEERS && !peers.contains_key(&announcement.address) {
+            let oldest = peers
+                .values()
+                .filter(|peer| !peer.configured)
+                .min_by_key(|peer| last_seen(peer))
+                .map(|peer| peer.address.clone());
+            if let Some(address) = oldest {
+                peers.remove(&address);
+            }
+        }
+
+        let peer = peers
+            .entry(announcement.address.clone())
+            .or_insert_with(|| PeerStatus::new(&announcement.address, false));
+
+        peer.reachable = true;
+        peer.health = Some(announcement.health);
+        peer.last_seen = Some(now.to_rfc3339());
+        peer.last_error = None;
+    }
+
+    /// All known peers, by address
+    pub fn list(&self) -> Vec<PeerStatus> {
+        self.lock().values().cloned().collect()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<String, PeerStatus>> {
+        self.peers.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// When `peer` was last heard from
+fn last_seen(peer: &PeerStatus) -> Option<DateTime<Utc>> {
+    let seen = DateTime::parse_from_rfc3339(peer.last_seen.as_deref()?).ok()?;
+    Some(seen.with_timezone(&Utc))
+}
+
+/// Split a `host:port` peer address, accepting bracketed IPv6 hosts
+pub fn parse_peer_address(address: &str) -> Option<(String, u16)> {
+    let (host, port) = address.rsplit_once(':')?;
+    let host = host
+        .strip_prefix('[')
+        .and_then(|host| host.strip_suffix(']'))
+        .unwrap_or(host);
+    if host.is_empty() {
+        return None;
+    }
+
+    Some((host.to_string(), port.parse().ok()?))
+}
+
+/// Announce this daemon to a peer, returning the peer's health and the round-trip time
+pub async fn probe_peer(
+    address: &str,
+    credential: Option<String>,
+    timeout: Duration,
+    announcement: &Announcement,
+) -> std::result::Result<(PeerHealth, Duration), String> {
+    let (host, port) = parse_peer_address(address)
+        .ok_or_else(|| format!("Invalid peer address: {} (expected host:port)", address))?;
+    let client = Client::new(host, port, timeout.as_secs().max(1)).with_auth(credential);
+    let params = serde_json::to_value(announcement).map_err(|e| e.to_string())?;
+
+    let started = Instant::now();
+    let health = client
+        .call::<PeerHealth>("cluster/announce", params)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok((health, started.elapsed()))
+}
+
+/// Version reported to peers
+pub fn daemon_version() -> &'static str {
+    env!("CARGO_PKG_VERSION")
+}
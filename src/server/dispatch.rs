@@ -0,0 +1,505 @@
+//! Registry of JSON-RPC methods served to every session
+//!
+//! Methods that only read require a `read:` permission, which observers hold
+//! through [`OBSERVER_PERMISSION`](tokens::OBSERVER_PERMISSION); ones that
+//! change anything require an `admin:` permission. `ping`, `status` and
+//! `server/info` are open to every authenticated session.
+//!
+//! An [`RpcDispatcher`] maps method names to async handlers that get the
+//! request params and an [`RpcContext`] describing the calling session.
+//! Sessions consult it for every method they do not handle themselves, which
+//! are the few that need the session's own connection state (`session/info`,
+//! `auth/whoami` and the like). Embedders add endpoints with
+//! [`ServerBuilder::rpc_method`](crate::server::ServerBuilder::rpc_method).
+
+use crate::client::types::{ServerInfo, ServiceStatus};
+use crate::server::apps::{MANAGE_APPS_PERMISSION, READ_APPS_PERMISSION};
+use crate::server::config::RpcConfig;
+use crate::server::error::{Error, PermissionError};
+use crate::server::live_config::{CONFIG_PERMISSION, READ_CONFIG_PERMISSION};
+use crate::server::metrics::READ_METRICS_PERMISSION;
+use crate::server::rpc::{self, RpcError};
+use crate::server::server::RESTART_PERMISSION;
+use crate::server::session::{SessionDetails, READ_SESSIONS_PERMISSION};
+use crate::server::user::{ImportMode, UserExport, UserRole, UserUpdate, MANAGE_USERS_PERMISSION};
+use crate::server::{cluster, tokens, Server};
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Future returned by a registered handler
+pub type HandlerFuture = Pin<Box<dyn Future<Output = Result<Value, RpcError>> + Send>>;
+
+/// A registered method handler
+pub type RpcHandler = Arc<dyn Fn(Value, RpcContext) -> HandlerFuture + Send + Sync>;
+
+/// The session a request arrived on, as seen by handlers
+#[derive(Clone)]
+pub struct RpcContext {
+    /// Server the session belongs to
+    pub server: Server,
+
+    /// ID of the calling session
+    pub session_id: Uuid,
+
+    /// Name the session authenticated as, if any
+    pub client_name: Option<String>,
+
+    /// Permissions the session holds
+    pub permissions: Vec<String>,
+}
+
+impl RpcContext {
+    /// Fail with a permission error unless the session holds `permission`
+    pub fn require_permission(&self, permission: &str) -> Result<(), RpcError> {
+        require_permission(&self.permissions, permission)
+    }
+}
+
+/// Fail with a permission error unless `permissions` grant `permission`
+pub fn require_permission(permissions: &[String], permission: &str) -> Result<(), RpcError> {
+    if tokens::permission_granted(permissions, permission) {
+        return Ok(());
+    }
+
+    Err(Error::PermissionDenied(PermissionError::new(permission, permissions.to_vec())).into())
+}
+
+/// Refuse `method` if `config` disables it or requires a permission the
+/// session's `permissions` do not grant
+///
+/// A disabled method is reported as not found, so it cannot be told apart
+/// from one the daemon does not have.
+pub fn authorize(config: &RpcConfig, method: &str, permissions: &[String]) -> Result<(), RpcError> {
+    if config.is_disabled(method) {
+        return Err(RpcError::method_not_found(method));
+    }
+
+    match config.required_permission(method) {
+        Some(required) => require_permission(permissions, required),
+        None => Ok(()),
+    }
+}
+
+/// Method name to handler registry
+#[derive(Clone, Default)]
+pub struct RpcDispatcher {
+    handlers: HashMap<String, RpcHandler>,
+}
+
+impl RpcDispatcher {
+    /// Create a dispatcher with no methods
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a dispatcher serving the core methods: `ping`, `status`,
+    /// `server/info`, `server/config`, `server/config/update`,
+    /// `server/restart`, `metrics`, `sessions/list`, `sessions/info`,
+    /// `apps/list`, `apps/info`, `apps/instances`, `apps/launch`, `apps/stop`,
+    /// `users/export`, `users/import` and `users/update`
+    pub fn with_core_handlers() -> Self {
+        let mut dispatcher = Self::new();
+        dispatcher.register("ping", |_, _| async {
+            Ok(Value::String("pong".to_string()))
+        });
+        dispatcher.register("status", |_, ctx| handle_status(ctx));
+        dispatcher.register("server/info", |_, ctx| handle_server_info(ctx));
+        dispatcher.register("server/config", handle_running_config);
+        dispatcher.register("server/config/update", handle_config_update);
+        dispatcher.register("server/restart", |_, ctx| handle_restart(ctx));
+        dispatcher.register("metrics", |_, ctx| handle_metrics(ctx));
+        dispatcher.register("sessions/list", |_, ctx| handle_list_sessions(ctx));
+        dispatcher.register("sessions/info", handle_session_info);
+        dispatcher.register("apps/list", |_, ctx| handle_list_apps(ctx));
+        dispatcher.register("apps/info", handle_app_info);
+        dispatcher.register("apps/instances", |_, ctx| handle_list_app_instances(ctx));
+        dispatcher.register("apps/launch", handle_launch_app);
+        dispatcher.register("apps/stop", handle_stop_app);
+        dispatcher.register("users/export", |_, ctx| handle_export_users(ctx));
+        dispatcher.register("users/import", handle_import_users);
+        dispatcher.register("users/update", handle_update_user);
+        dispatcher
+    }
+
+    /// Register `handler` for `method`, replacing any handler it already had
+    pub fn register<F, Fut>(&mut self, method: &str, handler: F)
+    where
+        F: Fn(Value, RpcContext) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Value, RpcError>> + Send + 'static,
+    {
+        let handler: RpcHandler = Arc::new(move |params, ctx| Box::pin(handler(params, ctx)));
+        self.handlers.insert(method.to_string(), handler);
+    }
+
+    /// Add the methods of `other`, its handlers replacing any already registered
+    pub fn extend(&mut self, other: RpcDispatcher) {
+        self.handlers.extend(other.handlers);
+    }
+
+    /// Whether `method` has a handler
+    pub fn contains(&self, method: &str) -> bool {
+        self.handlers.contains_key(method)
+    }
+
+    /// Names of the registered methods, sorted
+    pub fn methods(&self) -> Vec<&str> {
+        let mut methods: Vec<&str> = self.handlers.keys().map(String::as_str).collect();
+        methods.sort_unstable();
+        methods
+    }
+
+    /// Run the handler for `method`, failing with "method not found" if there is none
+    ///
+    /// The server's `rpc` configuration is checked first, see [`authorize`].
+    pub async fn dispatch(
+        &self,
+        method: &str,
+        params: Value,
+        ctx: RpcContext,
+    ) -> Result<Value, RpcError> {
+        authorize(&ctx.server.config().rpc, method, &ctx.permissions)?;
+        self.call(method, params, ctx).await
+    }
+
+    /// Start the handler for `method`, without borrowing the dispatcher while it runs
+    ///
+    /// Unlike [`dispatch`](Self::dispatch) this does not check the `rpc`
+    /// configuration; sessions have done so before they call it.
+    pub fn call(&self, method: &str, params: Value, ctx: RpcContext) -> HandlerFuture {
+        match self.handlers.get(method) {
+            Some(handler) => handler(params, ctx),
+            None => Box::pin(std::future::ready(Err(RpcError::method_not_found(method)))),
+        }
+    }
+}
+
+/// Serialize a handler's result
+fn to_value<T: Serialize>(value: T) -> Result<Value, RpcError> {
+    serde_json::to_value(value).map_err(|e| RpcError::new(rpc::INTERNAL_ERROR, e.to_string()))
+}
+
+/// Handle `status`: whether the daemon is running, its PID and uptime
+async fn handle_status(ctx: RpcContext) -> Result<Value, RpcError> {
+    let uptime = ctx.server.uptime().await;
+    to_value(ServiceStatus {
+        running: ctx.server.is_running().await,
+        pid: Some(std::process::id()),
+        uptime: uptime.map(format_uptime),
+        version: cluster::daemon_version().to_string(),
+    })
+}
+
+/// Handle `server/info`: version, listen address and session counts
+async fn handle_server_info(ctx: RpcContext) -> Result<Value, RpcError> {
+    let server = &ctx.server;
+    let config = server.config();
+    let metrics = server.metrics().snapshot();
+    let uptime = server.uptime().await.unwrap_or_default();
+
+    to_value(ServerInfo {
+        version: cluster::daemon_version().to_string(),
+        uptime: format_uptime(uptime),
+        address: config.address.clone(),
+        port: config.port,
+        tls_enabled: config.tls.enabled,
+        active_sessions: server.session_summaries().len(),
+        total_sessions: metrics
+            .connections_accepted
+            .try_into()
+            .unwrap_or(usize::MAX),
+    })
+}
+
+/// Handle `server/config`: the running configuration, with secrets redacted
+///
+/// With a `secret_salt`, secrets are fingerprinted rather than redacted so a
+/// caller can tell which ones changed. A fingerprint lets its holder test
+/// guesses offline, so that takes the admin permission.
+async fn handle_running_config(params: Value, ctx: RpcContext) -> Result<Value, RpcError> {
+    #[derive(Deserialize)]
+    struct Params {
+        #[serde(default)]
+        secret_salt: Option<String>,
+    }
+
+    ctx.require_permission(READ_CONFIG_PERMISSION)?;
+    let params: Params = rpc::parse_params(params)?;
+    match params.secret_salt {
+        Some(salt) => {
+            ctx.require_permission(CONFIG_PERMISSION)?;
+            Ok(ctx.server.config().to_fingerprinted_json(&salt))
+        }
+        None => Ok(ctx.server.config().to_redacted_json()),
+    }
+}
+
+/// Handle `server/config/update`: change one setting, save it and apply it
+///
+/// Replies with the updated configuration, with secrets redacted.
+async fn handle_config_update(params: Value, ctx: RpcContext) -> Result<Value, RpcError> {
+    #[derive(Deserialize)]
+    struct Params {
+        key: String,
+        value: String,
+    }
+
+    ctx.require_permission(CONFIG_PERMISSION)?;
+    let params: Params = rpc::parse_params(params)?;
+    let config = ctx
+        .server
+        .live_config()
+        .update(&params.key, &params.value)?;
+
+    info!(
+        "Session {} changed server setting {}",
+        ctx.session_id, params.key
+    );
+    Ok(config.to_redacted_json())
+}
+
+/// Handle `server/restart`: drain the other sessions and restart the server
+/// with the current configuration
+///
+/// Replies with the status of the restarted server.
+async fn handle_restart(ctx: RpcContext) -> Result<Value, RpcError> {
+    ctx.require_permission(RESTART_PERMISSION)?;
+    info!("Session {} restarted the server", ctx.session_id);
+    ctx.server.restart(Some(ctx.session_id)).await?;
+    handle_status(ctx).await
+}
+
+/// Handle `metrics`: connection, session and request counters
+async fn handle_metrics(ctx: RpcContext) -> Result<Value, RpcError> {
+    ctx.require_permission(READ_METRICS_PERMISSION)?;
+    to_value(ctx.server.metrics().snapshot())
+}
+
+/// Handle `sessions/list`: every active session, including the caller's
+async fn handle_list_sessions(ctx: RpcContext) -> Result<Value, RpcError> {
+    ctx.require_permission(READ_SESSIONS_PERMISSION)?;
+    to_value(ctx.server.session_summaries())
+}
+
+/// Handle `sessions/info`: one active session, with its running application
+/// instances if `include_apps` is set
+async fn handle_session_info(params: Value, ctx: RpcContext) -> Result<Value, RpcError> {
+    #[derive(Deserialize)]
+    struct Params {
+        session_id: Uuid,
+        #[serde(default)]
+        include_apps: bool,
+    }
+
+    ctx.require_permission(READ_SESSIONS_PERMISSION)?;
+    let params: Params = rpc::parse_params(params)?;
+    let summary = ctx
+        .server
+        .session_summary(&params.session_id)
+        .ok_or_else(|| Error::NotFound(format!("Session not found: {}", params.session_id)))?;
+
+    let apps = if params.include_apps {
+        Some(ctx.server.apps().list_in_session(&params.session_id).await)
+    } else {
+        None
+    };
+    to_value(SessionDetails { summary, apps })
+}
+
+/// Handle `apps/list`: the applications that may be launched
+async fn handle_list_apps(ctx: RpcContext) -> Result<Value, RpcError> {
+    ctx.require_permission(READ_APPS_PERMISSION)?;
+    to_value(ctx.server.app_registry().list())
+}
+
+/// Handle `apps/info`: one application's definition
+async fn handle_app_info(params: Value, ctx: RpcContext) -> Result<Value, RpcError> {
+    #[derive(Deserialize)]
+    struct Params {
+        app_id: String,
+    }
+
+    ctx.require_permission(READ_APPS_PERMISSION)?;
+    let params: Params = rpc::parse_params(params)?;
+    let app = ctx
+        .server
+        .app_registry()
+        .get(&params.app_id)
+        .ok_or_else(|| Error::NotFound(format!("Application not found: {}", params.app_id)))?;
+    to_value(app)
+}
+
+/// Handle `apps/instances`: every running application instance
+async fn handle_list_app_instances(ctx: RpcContext) -> Result<Value, RpcError> {
+    ctx.require_permission(READ_APPS_PERMISSION)?;
+    to_value(ctx.server.apps().list().await)
+}
+
+/// Handle `apps/launch`: start an application, owned by the calling session
+///
+/// The instance runs for `user_id`, by default the user the session
+/// authenticated as. The application's own `required_permission` is checked
+/// against the session's permissions on top of the admin one.
+async fn handle_launch_app(params: Value, ctx: RpcContext) -> Result<Value, RpcError> {
+    #[derive(Deserialize)]
+    struct Params {
+        app_id: String,
+        #[serde(default)]
+        user_id: Option<String>,
+        #[serde(default)]
+        arguments: Option<Vec<String>>,
+    }
+
+    ctx.require_permission(MANAGE_APPS_PERMISSION)?;
+    let params: Params = rpc::parse_params(params)?;
+    let app = ctx
+        .server
+        .app_registry()
+        .get(&params.app_id)
+        .ok_or_else(|| Error::NotFound(format!("Application not found: {}", params.app_id)))?;
+    let user_id = params
+        .user_id
+        .or_else(|| ctx.client_name.clone())
+        .unwrap_or_else(|| "operator".to_string());
+
+    let instance = ctx
+        .server
+        .apps()
+        .launch_in_session(
+            ctx.session_id,
+            &app,
+            &user_id,
+            &ctx.permissions,
+            &params.arguments.unwrap_or_default(),
+        )
+        .await?;
+    info!(
+        "Session {} launched {} as instance {} for {}",
+        ctx.session_id, app.id, instance.id, user_id
+    );
+    to_value(instance)
+}
+
+/// Handle `apps/stop`: kill a running application instance
+async fn handle_stop_app(params: Value, ctx: RpcContext) -> Result<Value, RpcError> {
+    #[derive(Deserialize)]
+    struct Params {
+        instance_id: Uuid,
+    }
+
+    ctx.require_permission(MANAGE_APPS_PERMISSION)?;
+    let params: Params = rpc::parse_params(params)?;
+    ctx.server.apps().stop(&params.instance_id).await?;
+    info!(
+        "Session {} stopped app instance {}",
+        ctx.session_id, params.instance_id
+    );
+    Ok(serde_json::json!({ "instance_id": params.instance_id }))
+}
+
+/// Handle `users/export`: the internal provider's users, password hashes included
+async fn handle_export_users(ctx: RpcContext) -> Result<Value, RpcError> {
+    ctx.require_permission(MANAGE_USERS_PERMISSION)?;
+    let export = ctx.server.users().export().await;
+    info!(
+        "Session {} exported {} users",
+        ctx.session_id,
+        export.users.len()
+    );
+    to_value(export)
+}
+
+/// Handle `users/import`: restore users from an export
+async fn handle_import_users(params: Value, ctx: RpcContext) -> Result<Value, RpcError> {
+    #[derive(Deserialize)]
+    struct Params {
+        export: UserExport,
+        #[serde(default)]
+        mode: ImportMode,
+    }
+
+    ctx.require_permission(MANAGE_USERS_PERMISSION)?;
+    let params: Params = rpc::parse_params(params)?;
+    let report = ctx
+        .server
+        .users()
+        .import(params.export, params.mode)
+        .await?;
+    info!(
+        "Session {} imported users: {} added, {} replaced, {} skipped",
+        ctx.session_id,
+        report.added.len(),
+        report.replaced.len(),
+        report.skipped.len()
+    );
+    to_value(report)
+}
+
+/// Handle `users/update`: change a user's full name, email or role
+async fn handle_update_user(params: Value, ctx: RpcContext) -> Result<Value, RpcError> {
+    #[derive(Deserialize)]
+    struct Params {
+        username: String,
+        #[serde(default)]
+        full_name: Option<String>,
+        #[serde(default)]
+        email: Option<String>,
+        #[serde(default)]
+        role: Option<String>,
+    }
+
+    ctx.require_permission(MANAGE_USERS_PERMISSION)?;
+    let params: Params = rpc::parse_params(params)?;
+    let update = UserUpdate {
+        full_name: params.full_name,
+        email: params.email,
+        role: params
+            .role
+            .as_deref()
+            .map(str::parse::<UserRole>)
+            .transpose()?,
+    };
+    if update.is_empty() {
+        return Err(Error::InvalidArgument(
+            "Nothing to update: give a full name, email or role".to_string(),
+        )
+        .into());
+    }
+
+    let users = ctx.server.users();
+    let mut user = users
+        .get_user_by_username(&params.username)
+        .await
+        .ok_or_else(|| Error::NotFound(format!("User not found: {}", params.username)))?;
+    update.apply(&mut user);
+    users.update_user(user.clone()).await?;
+    info!(
+        "Session {} updated user {}",
+        ctx.session_id, params.username
+    );
+    to_value(user)
+}
+
+/// Format an uptime as e.g. `2d 3h 4m 5s`, leaving out leading zero units
+pub fn format_uptime(uptime: Duration) -> String {
+    let secs = uptime.as_secs();
+    let units = [
+        (secs / 86_400, "d"),
+        (secs / 3_600 % 24, "h"),
+        (secs / 60 % 60, "m"),
+        (secs % 60, "s"),
+    ];
+
+    let parts: Vec<String> = units
+        .iter()
+        .skip_while(|(value, unit)| *value == 0 && *unit != "s")
+        .map(|(value, unit)| format!("{}{}", value, unit))
+        .collect();
+    parts.join(" ")
+}
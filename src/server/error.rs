@@ -1,3 +1,5 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
 use std::io;
 use thiserror::Error;
 
@@ -20,14 +22,16 @@ pub enum Error {
     #[error("TLS error: {0}")]
     Tls(String),
 
+    /// The client's credentials are missing, malformed, expired or wrong
     #[error("Authentication error: {0}")]
     Authentication(String),
 
     #[error("Session error: {0}")]
     Session(String),
 
+    /// The client is authenticated but lacks a permission the operation requires
     #[error("Operation not permitted: {0}")]
-    PermissionDenied(String),
+    PermissionDenied(#[from] PermissionError),
 
     #[error("Resource not found: {0}")]
     NotFound(String),
@@ -53,10 +57,62 @@ pub enum Error {
     #[error("Protocol error: {0}")]
     Protocol(String),
 
+    #[error("Frame error: {0}")]
+    Frame(#[from] crate::protocol::FrameError),
+
     #[error("{0}")]
     Other(String),
 }
 
+/// An operation was denied because the user lacks a permission
+///
+/// Sent to clients as the `data` of a permission-denied RPC error, so they
+/// can say what was missing instead of only that the operation failed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PermissionError {
+    /// Permission the operation requires, e.g. `app:word`
+    pub required: String,
+
+    /// Permissions the user holds; empty when hidden from the viewer
+    #[serde(default)]
+    pub granted: Vec<String>,
+}
+
+impl PermissionError {
+    /// Create an error for `required`, listing the user's `granted` permissions
+    pub fn new(required: impl Into<String>, granted: Vec<String>) -> Self {
+        Self {
+            required: required.into(),
+            granted,
+        }
+    }
+}
+
+impl fmt::Display for PermissionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "operation requires permission {}", self.required)?;
+        if !self.granted.is_empty() {
+            write!(f, " (you have: {})", self.granted.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for PermissionError {}
+
+/// Auth failures keep their kind, so the API can answer 401 or 403
+impl From<Error> for crate::error::ServiceError {
+    fn from(error: Error) -> Self {
+        use crate::error::ServiceError;
+
+        match error {
+            Error::Authentication(reason) => ServiceError::Unauthorized(reason),
+            Error::PermissionDenied(permission) => ServiceError::Forbidden(permission.required),
+            error => ServiceError::Server(error.to_string()),
+        }
+    }
+}
+
 // Type aliases for backward compatibility with the service integration
 pub type ServerError = Error;
 pub type ServerResult<T> = Result<T>;
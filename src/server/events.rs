@@ -0,0 +1,238 @@
+//! Recent server events
+//!
+//! Connection, authentication, disconnect and application exit events are kept in a bounded
+//! in-memory [`EventLog`] so operators can query recent history over
+//! `events/recent` instead of tailing the daemon log.
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+/// Default number of events retained by the server
+pub const DEFAULT_EVENT_LOG_SIZE: usize = 1000;
+
+/// Kind of a recorded server event
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ServerEventType {
+    /// A connection was accepted and a session created
+    Connected,
+
+    /// A connection was closed before a session was created
+    Rejected,
+
+    /// A session authenticated successfully
+    Authenticated,
+
+    /// A session failed to authenticate
+    AuthFailed,
+
+    /// A session ended
+    Disconnected,
+
+    /// A launched application instance terminated
+    AppExited,
+}
+
+impl ServerEventType {
+    /// All event types, in lifecycle order
+    pub const ALL: &'static [ServerEventType] = &[
+        ServerEventType::Connected,
+        ServerEventType::Rejected,
+        ServerEventType::Authenticated,
+        ServerEventType::AuthFailed,
+        ServerEventType::Disconnected,
+        ServerEventType::AppExited,
+    ];
+
+    /// Name used on the wire and on the command line
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ServerEventType::Connected => "connected",
+            ServerEventType::Rejected => "rejected",
+            ServerEventType::Authenticated => "authenticated",
+            ServerEventType::AuthFailed => "auth_failed",
+            ServerEventType::Disconnected => "disconnected",
+            ServerEventType::AppExited => "app_exited",
+        }
+    }
+}
+
+impl fmt::Display for ServerEventType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ServerEventType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ServerEventType::ALL
+            .iter()
+            .copied()
+            .find(|t| t.as_str().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                let names: Vec<&str> = ServerEventType::ALL.iter().map(|t| t.as_str()).collect();
+                format!(
+                    "Unknown event type: {} (expected one of {})",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+/// A recorded server event
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServerEvent {
+    /// Sequence number, increasing for the lifetime of the server
+    pub seq: u64,
+
+    /// Time the event was recorded (RFC 3339)
+    pub timestamp: String,
+
+    /// Kind of event
+    #[serde(rename = "type")]
+    pub event_type: ServerEventType,
+
+    /// Session the event belongs to, if one was created
+    pub session_id: Option<Uuid>,
+
+    /// Authenticated user or client name, if known
+    pub user: Option<String>,
+
+    /// IP address of the client
+    pub client_ip: Option<String>,
+
+    /// Application instance the event belongs to, for `app_exited`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instance_id: Option<Uuid>,
+
+    /// Exit code of the instance, if it exited normally
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+}
+
+#[derive(Debug)]
+struct EventBuffer {
+    events: VecDeque<ServerEvent>,
+    capacity: usize,
+    next_seq: u64,
+}
+
+/// Bounded ring buffer of recent server events, shared by all sessions
+#[derive(Debug, Clone)]
+pub struct EventLog {
+    inner: Arc<Mutex<EventBuffer>>,
+}
+
+impl Default for EventLog {
+    fn default() -> Self {
+        Self::new(DEFAULT_EVENT_LOG_SIZE)
+    }
+}
+
+impl EventLog {
+    /// Create a log retaining at most `capacity` events
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(EventBuffer {
+                events: VecDeque::with_capacity(capacity.min(DEFAULT_EVENT_LOG_SIZE)),
+                capacity: capacity.max(1),
+                next_seq: 0,
+            })),
+        }
+    }
+
+    /// Record an event, evicting the oldest one when the log is full
+    pub fn record(
+        &self,
+        event_type: ServerEventType,
+        session_id: Option<Uuid>,
+        user: Option<String>,
+        client_ip: Option<String>,
+    ) {
+        self.push(|seq| ServerEvent {
+            seq,
+            timestamp: Utc::now().to_rfc3339(),
+            event_type,
+            session_id,
+            user,
+            client_ip,
+            instance_id: None,
+            exit_code: None,
+        });
+    }
+
+    /// Record that an application instance terminated
+    ///
+    /// `exit_code` is `None` when the process was killed by a signal.
+    pub fn record_app_exit(&self, instance_id: Uuid, user: Option<String>, exit_code: Option<i32>) {
+        self.push(|seq| ServerEvent {
+            seq,
+            timestamp: Utc::now().to_rfc3339(),
+            event_type: ServerEventType::AppExited,
+            session_id: None,
+            user,
+            client_ip: None,
+            instance_id: Some(instance_id),
+            exit_code,
+        });
+    }
+
+    /// Append the event built for the next sequence number
+    fn push(&self, event: impl FnOnce(u64) -> ServerEvent) {
+        let mut buffer = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        if buffer.events.len() == buffer.capacity {
+            buffer.events.pop_front();
+        }
+
+        let seq = buffer.next_seq;
+        buffer.events.push_back(event(seq));
+        buffer.next_seq += 1;
+    }
+
+    /// The last `count` events, oldest first, optionally limited to some types
+    ///
+    /// An empty `types` slice matches every event.
+    pub fn recent(&self, count: usize, types: &[ServerEventType]) -> Vec<ServerEvent> {
+        let buffer = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        let mut events: Vec<ServerEvent> = buffer
+            .events
+            .iter()
+            .rev()
+            .filter(|event| types.is_empty() || types.contains(&event.event_type))
+            .take(count)
+            .cloned()
+            .collect();
+        events.reverse();
+        events
+    }
+
+    /// Maximum number of events retained
+    pub fn capacity(&self) -> usize {
+        self.inner
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .capacity
+    }
+
+    /// Number of events currently held
+    pub fn len(&self) -> usize {
+        self.inner
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .events
+            .len()
+    }
+
+    /// Whether no events are held
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
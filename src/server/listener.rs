@@ -0,0 +1,126 @@
+use crate::server::config::{BindRetryConfig, ServerConfig};
+use log::{info, warn};
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpSocket};
+
+/// Connections the kernel queues for the listener before they are accepted
+const LISTEN_BACKLOG: u32 = 1024;
+
+/// Options set on the listening socket before it is bound
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SocketOptions {
+    /// Set `SO_REUSEADDR`, so a restarted server can rebind a port whose old
+    /// connections are still in `TIME_WAIT`
+    pub reuse_addr: bool,
+
+    /// Set `SO_REUSEPORT`, so several processes can listen on the port (Unix only)
+    pub reuse_port: bool,
+}
+
+impl SocketOptions {
+    /// Options the configuration asks for
+    pub fn from_config(config: &ServerConfig) -> Self {
+        Self {
+            reuse_addr: config.reuse_addr,
+            reuse_port: config.reuse_port,
+        }
+    }
+}
+
+/// Bind a listening socket on `addr`, trying each address it resolves to
+pub async fn bind_listener(addr: &str, options: SocketOptions) -> io::Result<TcpListener> {
+    let mut last_error = None;
+    for addr in tokio::net::lookup_host(addr).await? {
+        match bind_socket(addr, options) {
+            Ok(listener) => return Ok(listener),
+            Err(e) => last_error = Some(e),
+        }
+    }
+
+    Err(last_error.unwrap_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "could not resolve to any address",
+        )
+    }))
+}
+
+/// Bind a listening socket on one resolved address
+fn bind_socket(addr: SocketAddr, options: SocketOptions) -> io::Result<TcpListener> {
+    let socket = match addr {
+        SocketAddr::V4(_) => TcpSocket::new_v4()?,
+        SocketAddr::V6(_) => TcpSocket::new_v6()?,
+    };
+
+    // On Windows SO_REUSEADDR would let another socket bind over a live
+    // listener, and a closed one holds no port anyway, so it is left unset there
+    #[cfg(unix)]
+    socket.set_reuseaddr(options.reuse_addr)?;
+    #[cfg(all(unix, not(target_os = "solaris"), not(target_os = "illumos")))]
+    socket.set_reuseport(options.reuse_port)?;
+    #[cfg(not(unix))]
+    let _ = options;
+
+    socket.bind(addr)?;
+    socket.listen(LISTEN_BACKLOG)
+}
+
+/// Bind the server's listening socket, retrying while the address is unavailable
+///
+/// At boot the daemon may start before the network interface carrying its
+/// address is up, in which case binding fails with "cannot assign requested
+/// address". Rather than exiting, the bind is retried according to the config.
+pub async fn bind_with_retry(
+    addr: &str,
+    retry: &BindRetryConfig,
+    options: SocketOptions,
+) -> io::Result<TcpListener> {
+    retry_bind(addr, retry, |addr| async move {
+        bind_listener(&addr, options).await
+    })
+    .await
+}
+
+/// Retry an arbitrary bind operation according to the retry configuration
+pub async fn retry_bind<T, F, Fut>(
+    addr: &str,
+    retry: &BindRetryConfig,
+    mut bind: F,
+) -> io::Result<T>
+where
+    F: FnMut(String) -> Fut,
+    Fut: Future<Output = io::Result<T>>,
+{
+    let mut attempt = 0;
+
+    loop {
+        match bind(addr.to_string()).await {
+            Ok(listener) => {
+                if attempt > 0 {
+                    info!("Bound {} after {} retries", addr, attempt);
+                }
+                return Ok(listener);
+            }
+            Err(e) if attempt < retry.attempts && is_retryable(&e) => {
+                attempt += 1;
+                warn!(
+                    "Failed to bind {}: {}. Retrying in {}ms (attempt {}/{})",
+                    addr, e, retry.delay_ms, attempt, retry.attempts
+                );
+                tokio::time::sleep(Duration::from_millis(retry.delay_ms)).await;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Whether a bind error may go away on its own
+fn is_retryable(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::AddrNotAvailable | io::ErrorKind::AddrInUse
+    )
+}
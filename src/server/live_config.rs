@@ -0,0 +1,265 @@
+//! Running server configuration that can be changed without a restart
+//!
+//! `server/config/update` changes one setting in two phases. The new
+//! configuration is first built and validated, then written
+//! to a temporary file next to the config file and renamed over it, so the
+//! file on disk is always either the old or the new configuration. The
+//! in-memory configuration is swapped under the same lock: if the write fails
+//! the previous configuration is put back, so the running server and the file
+//! never disagree.
+//!
+//! Settings read when the server starts are saved but only take effect on the
+//! next start; the listen address also on
+//! [`Server::restart`](crate::server::Server::restart).
+
+use crate::files::{self, PRIVATE_FILE_MODE};
+use crate::server::config::ServerConfig;
+use crate::server::error::{Error, Result};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock, RwLockWriteGuard};
+
+/// Permission needed to change the running configuration
+pub const CONFIG_PERMISSION: &str = "admin:config";
+
+/// Permission needed to read the running configuration
+pub const READ_CONFIG_PERMISSION: &str = "read:config";
+
+/// File a running configuration is saved to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigFile {
+    /// Path of the file
+    pub path: PathBuf,
+
+    /// Table of the file holding the server configuration, or `None` when
+    /// the whole file is the server configuration
+    pub table: Option<String>,
+}
+
+impl ConfigFile {
+    /// A file holding only the server configuration
+    pub fn standalone(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            table: None,
+        }
+    }
+
+    /// A file holding the server configuration in `table`, e.g. the daemon's
+    /// config with its `[server]` section
+    pub fn nested(path: impl Into<PathBuf>, table: &str) -> Self {
+        Self {
+            path: path.into(),
+            table: Some(table.to_string()),
+        }
+    }
+
+    /// Write `config` to the file through a temporary file and a rename
+    ///
+    /// The rest of a nested file is kept, though comments are not.
+    pub fn save(&self, config: &ServerConfig) -> Result<()> {
+        let server = toml::Value::try_from(config).map_err(|e| Error::Other(e.to_string()))?;
+        let document = match &self.table {
+            None => server,
+            Some(table) => {
+                let mut document = match std::fs::read_to_string(&self.path) {
+                    Ok(text) => toml::from_str(&text)
+                        .map_err(|e| Error::Other(format!("{}: {}", self.path.display(), e)))?,
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                        toml::Value::Table(Default::default())
+                    }
+                    Err(e) => return Err(e.into()),
+                };
+                match document.as_table_mut() {
+                    Some(document) => document.insert(table.clone(), server),
+                    None => {
+                        return Err(Error::Other(format!(
+                            "{}: not a TOML table",
+                            self.path.display()
+                        )))
+                    }
+                };
+                document
+            }
+        };
+        let text = toml::to_string(&document).map_err(|e| Error::Other(e.to_string()))?;
+
+        let temp = temp_path(&self.path);
+        let written = files::write_with_mode(&temp, text, PRIVATE_FILE_MODE)
+            .and_then(|()| std::fs::rename(&temp, &self.path));
+        if written.is_err() {
+            let _ = std::fs::remove_file(&temp);
+        }
+        written.map_err(Error::from)
+    }
+}
+
+/// Temporary file `path` is written through, in the same directory so the
+/// rename cannot cross filesystems
+fn temp_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.tmp", name))
+}
+
+/// The server configuration currently in effect
+#[derive(Clone)]
+pub struct LiveConfig {
+    current: Arc<RwLock<Arc<ServerConfig>>>,
+    file: Option<Arc<ConfigFile>>,
+}
+
+impl LiveConfig {
+    /// Start from `config`, saving updates to `file` if there is one
+    pub fn new(config: ServerConfig, file: Option<ConfigFile>) -> Self {
+        Self {
+            current: Arc::new(RwLock::new(Arc::new(config))),
+            file: file.map(Arc::new),
+        }
+    }
+
+    /// The configuration in effect now
+    pub fn current(&self) -> Arc<ServerConfig> {
+        self.current
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// File updates are saved to, if any
+    pub fn file(&self) -> Option<&ConfigFile> {
+        self.file.as_deref()
+    }
+
+    /// Set the dotted `key` to `value`, save the result and put it in effect
+    ///
+    /// The update is refused if [`ServerConfig::validate`] finds a problem
+    /// the current configuration does not already have. Nothing changes, in
+    /// memory or on disk, unless both the save and the swap succeed.
+    pub fn update(&self, key: &str, value: &str) -> Result<Arc<ServerConfig>> {
+        // Held throughout, so concurrent updates cannot undo one another
+        let mut current = self.lock_current();
+        let updated = apply_setting(&current, key, value)?;
+        let existing = current.validate();
+        let problems: Vec<String> = updated
+            .validate()
+            .into_iter()
+            .filter(|problem| !existing.contains(problem))
+            .collect();
+        if !problems.is_empty() {
+            return Err(Error::InvalidArgument(format!(
+                "Invalid value for '{}': {}",
+                key,
+                problems.join("; ")
+            )));
+        }
+        let updated = Arc::new(updated);
+        let previous = std::mem::replace(&mut *current, updated.clone());
+        if let Some(file) = &self.file {
+            if let Err(e) = file.save(&updated) {
+                *current = previous;
+                return Err(e);
+            }
+        }
+        Ok(updated)
+    }
+
+    fn lock_current(&self) -> RwLockWriteGuard<'_, Arc<ServerConfig>> {
+        self.current.write().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Why a setting could not be applied
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum SettingError {
+    /// The configuration has no such key
+    #[error("unknown configuration key")]
+    UnknownKey,
+
+    /// The key names a whole section rather than one setting
+    #[error("a section, not a single setting")]
+    Section,
+
+    /// The value cannot be held by the key
+    #[error("{0}")]
+    InvalidValue(String),
+}
+
+/// `config` with the setting at the JSON `pointer` replaced by `value`
+///
+/// The value is checked against the type of the key: numbers, booleans and
+/// enums must parse as such, and list keys take comma-separated items or a
+/// JSON array. Shared by `server/config/update` and `rcpdaemon config set`.
+pub fn apply_setting_at<T>(
+    config: &T,
+    pointer: &str,
+    value: &str,
+) -> std::result::Result<T, SettingError>
+where
+    T: serde::Serialize + serde::de::DeserializeOwned,
+{
+    let mut root =
+        serde_json::to_value(config).map_err(|e| SettingError::InvalidValue(e.to_string()))?;
+    let current = root.pointer(pointer).ok_or(SettingError::UnknownKey)?;
+    if current.is_object() {
+        return Err(SettingError::Section);
+    }
+
+    let parsed = match current {
+        Value::String(_) => Value::String(value.to_string()),
+        Value::Array(_) if !value.trim_start().starts_with('[') => Value::Array(
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(|item| Value::String(item.to_string()))
+                .collect(),
+        ),
+        // Numbers, booleans and unset options take a JSON literal
+        _ => serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.to_string())),
+    };
+
+    // An unset option may be a string that happens to look like a number
+    let mut candidates = vec![parsed];
+    if current.is_null() && !candidates[0].is_string() {
+        candidates.push(Value::String(value.to_string()));
+    }
+
+    let mut first_error = None;
+    for candidate in candidates {
+        if let Some(slot) = root.pointer_mut(pointer) {
+            *slot = candidate;
+        }
+        match serde_json::from_value(root.clone()) {
+            Ok(updated) => return Ok(updated),
+            Err(e) => {
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+
+    Err(SettingError::InvalidValue(
+        first_error.map(|e| e.to_string()).unwrap_or_default(),
+    ))
+}
+
+/// `config` with the dotted `key`, e.g. `session.timeout`, set to `value`
+///
+/// Fails with `InvalidArgument` for unknown keys and values the key cannot hold.
+pub fn apply_setting(config: &ServerConfig, key: &str, value: &str) -> Result<ServerConfig> {
+    let pointer = format!("/{}", key.replace('.', "/"));
+    apply_setting_at(config, &pointer, value).map_err(|e| match e {
+        SettingError::UnknownKey => {
+            Error::InvalidArgument(format!("Unknown configuration key '{}'", key))
+        }
+        SettingError::Section => Error::InvalidArgument(format!(
+            "'{}' is a section; set one of its keys instead",
+            key
+        )),
+        SettingError::InvalidValue(e) => {
+            Error::InvalidArgument(format!("Invalid value for '{}': {}", key, e))
+        }
+    })
+}
@@ -0,0 +1,147 @@
+//! Server metrics
+//!
+//! Counters are plain atomics so they can be updated from any session task
+//! without locking; [`ServerMetrics::snapshot`] gives a consistent-enough view
+//! for reporting, as JSON over the `metrics` RPC or as Prometheus text.
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Permission needed to read the metrics over the `metrics` RPC
+pub const READ_METRICS_PERMISSION: &str = "read:metrics";
+
+/// Shared handle to a server's metrics
+pub type Metrics = Arc<ServerMetrics>;
+
+/// Counters describing server activity
+#[derive(Debug, Default)]
+pub struct ServerMetrics {
+    connections_accepted: AtomicU64,
+    connections_rejected: AtomicU64,
+    sessions_active: AtomicU64,
+    requests_total: AtomicU64,
+    request_errors: AtomicU64,
+}
+
+/// Point-in-time copy of the server metrics
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MetricsSnapshot {
+    /// Connections accepted since startup
+    pub connections_accepted: u64,
+
+    /// Connections rejected before a session was created
+    pub connections_rejected: u64,
+
+    /// Sessions currently open
+    pub sessions_active: u64,
+
+    /// RPC requests handled
+    pub requests_total: u64,
+
+    /// RPC requests that returned an error
+    pub request_errors: u64,
+
+    /// When the snapshot was taken (RFC 3339); empty from older daemons
+    #[serde(default)]
+    pub collected_at: String,
+}
+
+impl MetricsSnapshot {
+    /// Render the snapshot in the Prometheus text exposition format
+    pub fn to_prometheus(&self) -> String {
+        let samples = [
+            (
+                "rcpdaemon_connections_accepted_total",
+                "counter",
+                "Connections accepted since startup",
+                self.connections_accepted,
+            ),
+            (
+                "rcpdaemon_connections_rejected_total",
+                "counter",
+                "Connections rejected before a session was created",
+                self.connections_rejected,
+            ),
+            (
+                "rcpdaemon_sessions_active",
+                "gauge",
+                "Sessions currently open",
+                self.sessions_active,
+            ),
+            (
+                "rcpdaemon_requests_total",
+                "counter",
+                "RPC requests handled",
+                self.requests_total,
+            ),
+            (
+                "rcpdaemon_request_errors_total",
+                "counter",
+                "RPC requests that returned an error",
+                self.request_errors,
+            ),
+        ];
+
+        let mut text = String::new();
+        if !self.collected_at.is_empty() {
+            text.push_str(&format!("# Collected at {}\n", self.collected_at));
+        }
+        for (name, kind, help, value) in samples {
+            text.push_str(&format!(
+                "# HELP {name} {help}\n# TYPE {name} {kind}\n{name} {value}\n"
+            ));
+        }
+        text
+    }
+}
+
+impl ServerMetrics {
+    /// Create a new, zeroed metrics handle
+    pub fn new() -> Metrics {
+        Arc::new(Self::default())
+    }
+
+    /// Record an accepted connection
+    pub fn connection_accepted(&self) {
+        self.connections_accepted.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a rejected connection
+    pub fn connection_rejected(&self) {
+        self.connections_rejected.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a session being opened
+    pub fn session_opened(&self) {
+        self.sessions_active.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a session being closed
+    pub fn session_closed(&self) {
+        let _ = self
+            .sessions_active
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1));
+    }
+
+    /// Record a handled request and whether it failed
+    pub fn request_handled(&self, failed: bool) {
+        self.requests_total.fetch_add(1, Ordering::Relaxed);
+        if failed {
+            self.request_errors.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Take a snapshot of the current counters
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            connections_accepted: self.connections_accepted.load(Ordering::Relaxed),
+            connections_rejected: self.connections_rejected.load(Ordering::Relaxed),
+            sessions_active: self.sessions_active.load(Ordering::Relaxed),
+            requests_total: self.requests_total.load(Ordering::Relaxed),
+            request_errors: self.request_errors.load(Ordering::Relaxed),
+            collected_at: Utc::now().to_rfc3339(),
+        }
+    }
+}
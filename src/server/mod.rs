@@ -1,13 +1,26 @@
 // Module for integrated server functionality
 // This module contains the server components migrated from the separate rcp-server crate
 
+pub mod apps;
+pub mod bench;
+pub mod cluster;
 pub mod config;
+pub mod dispatch;
 pub mod error;
+pub mod events;
+pub mod listener;
+pub mod live_config;
+pub mod metrics;
+pub mod ratelimit;
+pub mod rdns;
+pub mod resume;
+pub mod rpc;
 // Apply clippy allow to avoid module inception warning
 #[allow(clippy::module_inception)]
 pub mod server;
 pub mod session;
+pub mod tokens;
 pub mod user;
 
 // Re-export important items
-pub use self::server::Server;
+pub use self::server::{ConnectDecision, ConnectHook, Server, ServerBuilder, ServiceConstructor};
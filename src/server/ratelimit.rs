@@ -0,0 +1,315 @@
+//! Per-source-IP authentication rate limiting
+//!
+//! The per-username lockout does not stop an attacker who rotates usernames,
+//! so failed authentication attempts are also counted per client IP over a
+//! sliding one-minute window. Once an IP reaches the limit, its new sessions
+//! are closed after a tarpit delay without their credentials being checked.
+//!
+//! Connections turned away by the connect hook never reach authentication,
+//! so a connection-level rate limiter installed as that hook composes with
+//! this one: it bounds how often an IP may connect, and this bounds how often
+//! the connections it lets through may fail to authenticate.
+//!
+//! Separately, [`HandshakeLimiter`] bounds how many connections may be in the
+//! handshake at once, whatever their source. A client that connects and then
+//! stalls keeps its slot until the handshake timeout, so a flood of them only
+//! ever occupies that many slots; the rest wait briefly and are turned away.
+//!
+//! [`ConnectionLimit`] bounds the connections open at once across the server
+//! and the API together, so neither can take the host's share of the other.
+
+use log::warn;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::net::{IpAddr, Ipv6Addr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Window over which failed attempts are counted
+pub const AUTH_RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Most sources tracked at once; the one with the oldest failure makes room beyond it
+pub const MAX_TRACKED_SOURCES: usize = 4096;
+
+/// Counts failed authentication attempts per client IP
+///
+/// IPv6 clients are counted per /64 prefix, the smallest block a site is
+/// normally given, so rotating addresses within it does not reset the count.
+#[derive(Debug, Clone)]
+pub struct AuthRateLimiter {
+    /// Failures allowed per source within `window`, or `None` for no limit
+    limit: Option<u32>,
+
+    /// Length of the sliding window
+    window: Duration,
+
+    /// Recent failures
+    failures: Arc<Mutex<Failures>>,
+}
+
+/// Failures within the window, by source and in the order they happened
+#[derive(Debug, Default)]
+struct Failures {
+    /// Times of recent failures by source, oldest first
+    by_source: HashMap<IpAddr, VecDeque<Instant>>,
+
+    /// Every recorded failure, oldest first; entries of evicted sources are skipped
+    order: VecDeque<(Instant, IpAddr)>,
+}
+
+impl Failures {
+    /// Drop failures older than `window`
+    fn expire(&mut self, now: Instant, window: Duration) {
+        while let Some(&(time, source)) = self.order.front() {
+            if now.duration_since(time) < window {
+                break;
+            }
+            self.order.pop_front();
+            self.forget(time, source);
+        }
+    }
+
+    /// Drop the failure of `source` at `time`, if it is still the source's oldest
+    fn forget(&mut self, time: Instant, source: IpAddr) {
+        let Some(times) = self.by_source.get_mut(&source) else {
+            return;
+        };
+        if times.front() == Some(&time) {
+            times.pop_front();
+            if times.is_empty() {
+                self.by_source.remove(&source);
+            }
+        }
+    }
+
+    /// Stop tracking the source with the oldest failure
+    fn evict_oldest(&mut self) {
+        while let Some((time, source)) = self.order.pop_front() {
+            if self
+                .by_source
+                .get(&source)
+                .is_some_and(|times| times.front() == Some(&time))
+            {
+                self.by_source.remove(&source);
+                return;
+            }
+        }
+    }
+}
+
+impl AuthRateLimiter {
+    /// Limit each IP to `max_per_minute` failed attempts per minute
+    ///
+    /// `None` or zero disables the limit.
+    pub fn new(max_per_minute: Option<u32>) -> Self {
+        Self::with_window(max_per_minute, AUTH_RATE_WINDOW)
+    }
+
+    /// Limit each IP to `limit` failed attempts within `window`
+    pub fn with_window(limit: Option<u32>, window: Duration) -> Self {
+        Self {
+            limit: limit.filter(|&limit| limit > 0),
+            window,
+            failures: Arc::new(Mutex::new(Failures::default())),
+        }
+    }
+
+    /// Whether any limit is configured
+    pub fn is_enabled(&self) -> bool {
+        self.limit.is_some()
+    }
+
+    /// Whether `ip` has used up its failed attempts for the current window
+    pub fn is_limited(&self, ip: IpAddr) -> bool {
+        let Some(limit) = self.limit else {
+            return false;
+        };
+
+        let mut failures = self.lock_failures();
+        failures.expire(Instant::now(), self.window);
+        failures
+            .by_source
+            .get(&source_key(ip))
+            .is_some_and(|times| times.len() >= limit as usize)
+    }
+
+    /// Count a failed authentication attempt from `ip`
+    pub fn record_failure(&self, ip: IpAddr) {
+        if self.limit.is_none() {
+            return;
+        }
+
+        let now = Instant::now();
+        let source = source_key(ip);
+        let mut failures = self.lock_failures();
+        failures.expire(now, self.window);
+        if failures.by_source.len() >= MAX_TRACKED_SOURCES
+            && !failures.by_source.contains_key(&source)
+        {
+            failures.evict_oldest();
+        }
+
+        failures.by_source.entry(source).or_default().push_back(now);
+        failures.order.push_back((now, source));
+    }
+
+    /// Number of sources with failures in the current window
+    ///
+    /// A source is an IPv4 address or an IPv6 /64 prefix.
+    pub fn tracked_ips(&self) -> usize {
+        let mut failures = self.lock_failures();
+        failures.expire(Instant::now(), self.window);
+        failures.by_source.len()
+    }
+
+    fn lock_failures(&self) -> std::sync::MutexGuard<'_, Failures> {
+        self.failures.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Default for AuthRateLimiter {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+/// The source `ip` is counted under: the address itself for IPv4, its /64 for IPv6
+pub fn source_key(ip: IpAddr) -> IpAddr {
+    match ip.to_canonical() {
+        IpAddr::V4(v4) => IpAddr::V4(v4),
+        IpAddr::V6(v6) => IpAddr::V6(Ipv6Addr::from(u128::from(v6) & !(u64::MAX as u128))),
+    }
+}
+
+/// Bounds the number of connections in the handshake at once
+#[derive(Debug, Clone)]
+pub struct HandshakeLimiter {
+    /// Free handshake slots, or `None` for no limit
+    slots: Option<Arc<Semaphore>>,
+
+    /// Total number of slots
+    capacity: usize,
+
+    /// How long a connection waits for a free slot before it is rejected
+    wait: Duration,
+}
+
+/// A claimed handshake slot, released when dropped
+#[derive(Debug)]
+pub struct HandshakeSlot {
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+impl HandshakeLimiter {
+    /// Allow `max_concurrent` handshakes at once, each new connection waiting
+    /// up to `wait` for a slot
+    ///
+    /// Zero disables the limit.
+    pub fn new(max_concurrent: usize, wait: Duration) -> Self {
+        Self {
+            slots: (max_concurrent > 0).then(|| Arc::new(Semaphore::new(max_concurrent))),
+            capacity: max_concurrent,
+            wait,
+        }
+    }
+
+    /// Whether any limit is configured
+    pub fn is_enabled(&self) -> bool {
+        self.slots.is_some()
+    }
+
+    /// Claim a slot, waiting for one to free up if all are taken
+    ///
+    /// Returns `None` if no slot became free within the wait time.
+    pub async fn acquire(&self) -> Option<HandshakeSlot> {
+        let Some(slots) = &self.slots else {
+            return Some(HandshakeSlot { _permit: None });
+        };
+
+        match tokio::time::timeout(self.wait, slots.clone().acquire_owned()).await {
+            Ok(Ok(permit)) => Some(HandshakeSlot {
+                _permit: Some(permit),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Number of handshakes currently holding a slot
+    pub fn in_progress(&self) -> usize {
+        self.slots
+            .as_ref()
+            .map_or(0, |slots| self.capacity - slots.available_permits())
+    }
+}
+
+impl Default for HandshakeLimiter {
+    fn default() -> Self {
+        Self::new(0, Duration::ZERO)
+    }
+}
+
+/// Bounds the connections open at once, across every component sharing it
+///
+/// Clones share the limit. A connection counts against it from when it is
+/// accepted until it closes, and one over the limit is refused at once.
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionLimit {
+    /// Free connection slots, or `None` for no limit
+    slots: Option<Arc<Semaphore>>,
+
+    /// Total number of slots
+    capacity: usize,
+}
+
+/// A connection counted against a [`ConnectionLimit`], released when dropped
+#[derive(Debug)]
+pub struct ConnectionSlot {
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+impl ConnectionLimit {
+    /// Allow `max_total` connections open at once
+    ///
+    /// Zero disables the limit.
+    pub fn new(max_total: usize) -> Self {
+        Self {
+            slots: (max_total > 0).then(|| Arc::new(Semaphore::new(max_total))),
+            capacity: max_total,
+        }
+    }
+
+    /// Whether any limit is configured
+    pub fn is_enabled(&self) -> bool {
+        self.slots.is_some()
+    }
+
+    /// Count a connection `component` accepted from `peer`, unless the limit is reached
+    ///
+    /// A refused connection is logged with the component that refused it.
+    pub fn admit(&self, component: &str, peer: &dyn fmt::Display) -> Option<ConnectionSlot> {
+        let Some(slots) = &self.slots else {
+            return Some(ConnectionSlot { _permit: None });
+        };
+
+        match slots.clone().try_acquire_owned() {
+            Ok(permit) => Some(ConnectionSlot {
+                _permit: Some(permit),
+            }),
+            Err(_) => {
+                warn!(
+                    "Connection from {} refused by the {}: {} connections already open",
+                    peer, component, self.capacity
+                );
+                None
+            }
+        }
+    }
+
+    /// Number of connections currently counted
+    pub fn open(&self) -> usize {
+        self.slots
+            .as_ref()
+            .map_or(0, |slots| self.capacity - slots.available_permits())
+    }
+}
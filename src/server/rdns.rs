@@ -0,0 +1,207 @@
+//! Reverse DNS names for connection logs
+//!
+//! With `log_reverse_dns` set, accepted connections are logged as
+//! `host (ip)` instead of just the IP. Lookups run on the blocking pool under
+//! `reverse_dns_timeout_ms` and never on the accept loop, which logs the
+//! connection once the name is known or the lookup gave up. Results, names
+//! and failures alike, are cached for [`REVERSE_DNS_CACHE_TTL`] so a busy
+//! client costs one lookup.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How long a lookup result is reused for
+pub const REVERSE_DNS_CACHE_TTL: Duration = Duration::from_secs(600);
+
+/// Most addresses kept in the cache; the oldest entry makes room beyond it
+pub const MAX_CACHED_ADDRESSES: usize = 4096;
+
+/// Something that maps an IP address to a host name
+///
+/// `resolve` may block; it is only ever called on the blocking pool.
+pub trait ReverseResolver: Send + Sync {
+    /// Name of `ip`, or `None` if it has none
+    fn resolve(&self, ip: IpAddr) -> Option<String>;
+}
+
+/// Resolver asking the system (`getnameinfo`), so `/etc/hosts` and the
+/// configured name servers are consulted
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemResolver;
+
+impl ReverseResolver for SystemResolver {
+    fn resolve(&self, ip: IpAddr) -> Option<String> {
+        system_lookup(ip)
+    }
+}
+
+#[cfg(unix)]
+fn system_lookup(ip: IpAddr) -> Option<String> {
+    use std::mem::size_of;
+
+    // NI_MAXHOST
+    let mut host = [0 as libc::c_char; 1025];
+
+    // SAFETY: the sockaddrs are plain data, fully initialized before use and
+    // valid for the length passed; `host` is valid for `host.len()` bytes
+    let rc = unsafe {
+        match ip {
+            IpAddr::V4(v4) => {
+                let mut addr: libc::sockaddr_in = std::mem::zeroed();
+                addr.sin_family = libc::AF_INET as libc::sa_family_t;
+                addr.sin_addr = libc::in_addr {
+                    s_addr: u32::from_ne_bytes(v4.octets()),
+                };
+                #[cfg(any(
+                    target_os = "macos",
+                    target_os = "ios",
+                    target_os = "freebsd",
+                    target_os = "openbsd",
+                    target_os = "netbsd",
+                    target_os = "dragonfly"
+                ))]
+                {
+                    addr.sin_len = size_of::<libc::sockaddr_in>() as u8;
+                }
+                libc::getnameinfo(
+                    &addr as *const libc::sockaddr_in as *const libc::sockaddr,
+                    size_of::<libc::sockaddr_in>() as libc::socklen_t,
+                    host.as_mut_ptr(),
+                    host.len() as libc::socklen_t,
+                    std::ptr::null_mut(),
+                    0,
+                    libc::NI_NAMEREQD,
+                )
+            }
+            IpAddr::V6(v6) => {
+                let mut addr: libc::sockaddr_in6 = std::mem::zeroed();
+                addr.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+                addr.sin6_addr = libc::in6_addr {
+                    s6_addr: v6.octets(),
+                };
+                #[cfg(any(
+                    target_os = "macos",
+                    target_os = "ios",
+                    target_os = "freebsd",
+                    target_os = "openbsd",
+                    target_os = "netbsd",
+                    target_os = "dragonfly"
+                ))]
+                {
+                    addr.sin6_len = size_of::<libc::sockaddr_in6>() as u8;
+                }
+                libc::getnameinfo(
+                    &addr as *const libc::sockaddr_in6 as *const libc::sockaddr,
+                    size_of::<libc::sockaddr_in6>() as libc::socklen_t,
+                    host.as_mut_ptr(),
+                    host.len() as libc::socklen_t,
+                    std::ptr::null_mut(),
+                    0,
+                    libc::NI_NAMEREQD,
+                )
+            }
+        }
+    };
+    if rc != 0 {
+        return None;
+    }
+
+    // SAFETY: a successful getnameinfo NUL-terminates `host`
+    let name = unsafe { std::ffi::CStr::from_ptr(host.as_ptr()) };
+    Some(name.to_string_lossy().into_owned()).filter(|name| !name.is_empty())
+}
+
+#[cfg(not(unix))]
+fn system_lookup(_ip: IpAddr) -> Option<String> {
+    None
+}
+
+/// A cached lookup result
+#[derive(Debug, Clone)]
+struct CachedName {
+    name: Option<String>,
+    resolved_at: Instant,
+}
+
+/// Reverse DNS with a timeout and a cache, shared by every connection
+#[derive(Clone)]
+pub struct ReverseDns {
+    resolver: Arc<dyn ReverseResolver>,
+    timeout: Duration,
+    cache: Arc<Mutex<HashMap<IpAddr, CachedName>>>,
+}
+
+impl ReverseDns {
+    /// Look names up with `resolver`, giving up on a lookup after `timeout`
+    pub fn new(resolver: Arc<dyn ReverseResolver>, timeout: Duration) -> Self {
+        Self {
+            resolver,
+            timeout,
+            cache: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Name of `ip`, from the cache when a recent lookup found one (or none)
+    ///
+    /// A lookup that times out is not cached, so the next connection tries again.
+    pub async fn lookup(&self, ip: IpAddr) -> Option<String> {
+        if let Some(cached) = self.lock_cache().get(&ip) {
+            if cached.resolved_at.elapsed() < REVERSE_DNS_CACHE_TTL {
+                return cached.name.clone();
+            }
+        }
+
+        let resolver = self.resolver.clone();
+        let lookup = tokio::task::spawn_blocking(move || resolver.resolve(ip));
+        let name = match tokio::time::timeout(self.timeout, lookup).await {
+            Ok(Ok(name)) => name,
+            Ok(Err(_)) | Err(_) => return None,
+        };
+
+        let mut cache = self.lock_cache();
+        if cache.len() >= MAX_CACHED_ADDRESSES && !cache.contains_key(&ip) {
+            cache.retain(|_, cached| cached.resolved_at.elapsed() < REVERSE_DNS_CACHE_TTL);
+            if cache.len() >= MAX_CACHED_ADDRESSES {
+                let oldest = cache
+                    .iter()
+                    .min_by_key(|(_, cached)| cached.resolved_at)
+                    .map(|(ip, _)| *ip);
+                if let Some(oldest) = oldest {
+                    cache.remove(&oldest);
+                }
+            }
+        }
+        cache.insert(
+            ip,
+            CachedName {
+                name: name.clone(),
+                resolved_at: Instant::now(),
+            },
+        );
+        name
+    }
+
+    /// `ip` as it should appear in logs: `host (ip)`, or just the IP
+    pub async fn describe(&self, ip: IpAddr) -> String {
+        describe_source(self.lookup(ip).await.as_deref(), ip)
+    }
+
+    /// Number of addresses cached, expired entries included until evicted
+    pub fn cached_addresses(&self) -> usize {
+        self.lock_cache().len()
+    }
+
+    fn lock_cache(&self) -> std::sync::MutexGuard<'_, HashMap<IpAddr, CachedName>> {
+        self.cache.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Format a connection source as `host (ip)`, or just the IP without a host
+pub fn describe_source(host: Option<&str>, ip: IpAddr) -> String {
+    match host {
+        Some(host) => format!("{} ({})", host, ip),
+        None => ip.to_string(),
+    }
+}
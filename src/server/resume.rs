@@ -0,0 +1,108 @@
+//! Resuming sessions after a dropped connection
+//!
+//! With `session.resume_grace_secs` set, every session is given a resume
+//! token once it has authenticated, reported by `session/info`. When the
+//! connection drops without the server closing it (the client went away, the
+//! read failed or a keep-alive went unanswered), the session's identity is
+//! held for the grace period. A client reconnecting within it and presenting
+//! the token in its hello gets the same session ID and identity back, without
+//! authenticating again, along with a fresh token for the next time.
+//!
+//! Sessions the server closed itself, by kick, idle timeout or shutdown, are
+//! not held. A token can be used once; an unknown or expired one is refused
+//! like a bad credential.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// What a held session is restored with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeldSession {
+    /// ID the session had, and keeps on resume
+    pub session_id: Uuid,
+
+    /// When the session's first connection was accepted, RFC 3339
+    pub created_at: String,
+
+    /// Client name
+    pub client_name: Option<String>,
+
+    /// Session permissions
+    pub permissions: Vec<String>,
+
+    /// ID of the token the session authenticated with, if any
+    pub token_id: Option<Uuid>,
+
+    /// Expiry of that token, in seconds since the Unix epoch
+    pub token_exp: Option<i64>,
+}
+
+/// Why a resume token was refused
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ResumeError {
+    /// No session is held for the token
+    #[error("Unknown resume token")]
+    Unknown,
+
+    /// The session's grace period is over
+    #[error("Resume token has expired")]
+    Expired,
+}
+
+/// Disconnected sessions held for resumption, by resume token
+#[derive(Debug, Clone, Default)]
+pub struct ResumeRegistry {
+    held: Arc<Mutex<HashMap<String, (HeldSession, Instant)>>>,
+}
+
+impl ResumeRegistry {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Hold `session` for `grace`, to be resumed with `token`
+    ///
+    /// Sessions whose grace period is already over are reaped.
+    pub fn hold(&self, token: String, session: HeldSession, grace: Duration) {
+        let now = Instant::now();
+        let mut held = self.lock();
+        held.retain(|_, (_, deadline)| *deadline > now);
+        held.insert(token, (session, now + grace));
+    }
+
+    /// Take the session held for `token`
+    ///
+    /// The token is used up, whether or not its grace period is over.
+    pub fn take(&self, token: &str) -> Result<HeldSession, ResumeError> {
+        match self.lock().remove(token) {
+            Some((_, deadline)) if deadline <= Instant::now() => Err(ResumeError::Expired),
+            Some((session, _)) => Ok(session),
+            None => Err(ResumeError::Unknown),
+        }
+    }
+
+    /// Number of sessions still within their grace period
+    pub fn len(&self) -> usize {
+        let now = Instant::now();
+        let mut held = self.lock();
+        held.retain(|_, (_, deadline)| *deadline > now);
+        held.len()
+    }
+
+    /// Whether no session is within its grace period
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, (HeldSession, Instant)>> {
+        self.held.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// A new random resume token
+pub fn issue_token() -> String {
+    format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
@@ -0,0 +1,186 @@
+//! JSON-RPC 2.0 message types used on client connections
+//!
+//! Each request frame carries one [`RpcRequest`], or a batch (JSON array) of
+//! them; the session answers with one [`RpcResponse`] per request, carrying
+//! either a result or an [`RpcError`], wrapped in an [`RpcReply`].
+
+use crate::server::error::Error;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// JSON-RPC protocol version string
+pub const JSONRPC_VERSION: &str = "2.0";
+
+/// The request was not valid JSON
+pub const PARSE_ERROR: i64 = -32700;
+
+/// The request was not a valid JSON-RPC request
+pub const INVALID_REQUEST: i64 = -32600;
+
+/// The requested method does not exist
+pub const METHOD_NOT_FOUND: i64 = -32601;
+
+/// The method parameters were invalid
+pub const INVALID_PARAMS: i64 = -32602;
+
+/// The server failed while handling the request
+pub const INTERNAL_ERROR: i64 = -32603;
+
+/// A resource referenced by the request does not exist
+pub const NOT_FOUND: i64 = -32004;
+
+/// The caller is not allowed to perform the request
+pub const PERMISSION_DENIED: i64 = -32003;
+
+/// The client failed to authenticate
+pub const UNAUTHENTICATED: i64 = -32001;
+
+/// A JSON-RPC request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RpcRequest {
+    /// Protocol version, always "2.0"
+    pub jsonrpc: String,
+
+    /// Request ID echoed back in the response
+    #[serde(default)]
+    pub id: Value,
+
+    /// Method name, e.g. `apps/logs`
+    pub method: String,
+
+    /// Method parameters
+    #[serde(default)]
+    pub params: Value,
+
+    /// Authentication token
+    #[serde(default)]
+    pub auth: Option<String>,
+}
+
+/// A JSON-RPC error object
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RpcError {
+    /// Error code
+    pub code: i64,
+
+    /// Human-readable message
+    pub message: String,
+
+    /// Additional error data
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl RpcError {
+    /// Create an error with the given code and message
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+
+    /// Error for an unknown method
+    pub fn method_not_found(method: &str) -> Self {
+        Self::new(METHOD_NOT_FOUND, format!("Method not found: {}", method))
+    }
+
+    /// Error for invalid method parameters
+    pub fn invalid_params(message: impl Into<String>) -> Self {
+        Self::new(INVALID_PARAMS, message)
+    }
+}
+
+impl From<Error> for RpcError {
+    fn from(error: Error) -> Self {
+        if let Error::PermissionDenied(permission) = &error {
+            return Self {
+                code: PERMISSION_DENIED,
+                message: error.to_string(),
+                data: serde_json::to_value(permission).ok(),
+            };
+        }
+
+        let code = match &error {
+            Error::NotFound(_) => NOT_FOUND,
+            Error::Authentication(_) => UNAUTHENTICATED,
+            Error::InvalidArgument(_) => INVALID_PARAMS,
+            _ => INTERNAL_ERROR,
+        };
+        Self::new(code, error.to_string())
+    }
+}
+
+/// A JSON-RPC response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RpcResponse {
+    /// Protocol version, always "2.0"
+    pub jsonrpc: String,
+
+    /// ID of the request this responds to
+    pub id: Value,
+
+    /// Result on success
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+
+    /// Error on failure
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<RpcError>,
+}
+
+impl RpcResponse {
+    /// Successful response
+    pub fn success(id: Value, result: Value) -> Self {
+        Self {
+            jsonrpc: JSONRPC_VERSION.to_string(),
+            id,
+            result: Some(result),
+            error: None,
+        }
+    }
+
+    /// Error response
+    pub fn failure(id: Value, error: RpcError) -> Self {
+        Self {
+            jsonrpc: JSONRPC_VERSION.to_string(),
+            id,
+            result: None,
+            error: Some(error),
+        }
+    }
+
+    /// Build a response from a handler result
+    pub fn from_result(id: Value, result: std::result::Result<Value, RpcError>) -> Self {
+        match result {
+            Ok(value) => Self::success(id, value),
+            Err(error) => Self::failure(id, error),
+        }
+    }
+}
+
+/// Reply to one request frame
+///
+/// A batch request is answered with an array holding one response per item,
+/// in request order.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum RpcReply {
+    /// Response to a single request
+    Single(RpcResponse),
+
+    /// Responses to a batch request
+    Batch(Vec<RpcResponse>),
+}
+
+/// Deserialize method parameters, mapping failures to an invalid-params error
+///
+/// Missing (`null`) params are treated as an empty object.
+pub fn parse_params<T: serde::de::DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    let params = match params {
+        Value::Null => Value::Object(serde_json::Map::new()),
+        other => other,
+    };
+    serde_json::from_value(params).map_err(|e| RpcError::invalid_params(e.to_string()))
+}
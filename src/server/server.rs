@@ -1,83 +1,650 @@
-use crate::server::{config::ServerConfig, error::Result, session::Session};
-use log::{debug, error, info};
+use crate::auth::AuthManager;
+use crate::protocol::goodbye::{DisconnectReason, Goodbye};
+use crate::server::{
+    apps::{AppLauncher, AppRegistry},
+    cluster::{self, Announcement, PeerHealth, PeerTable},
+    config::ServerConfig,
+    dispatch::{RpcContext, RpcDispatcher},
+    error::{Error, Result},
+    events::{EventLog, ServerEventType},
+    listener::{bind_listener, bind_with_retry, SocketOptions},
+    live_config::{ConfigFile, LiveConfig},
+    metrics::{Metrics, ServerMetrics},
+    ratelimit::{AuthRateLimiter, ConnectionLimit, HandshakeLimiter, HandshakeSlot},
+    rdns::{ReverseDns, ReverseResolver, SystemResolver},
+    resume::ResumeRegistry,
+    rpc,
+    session::{self, ServiceTrait, Session, SessionSummary},
+    tokens::TokenRegistry,
+    user::UserManager,
+};
+use log::{debug, error, info, warn};
 use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::net::TcpListener;
-use tokio::sync::Mutex;
+use tokio::sync::{mpsc, oneshot, watch, Mutex};
 use uuid::Uuid;
 
+/// Permission needed to restart the server
+pub const RESTART_PERMISSION: &str = "admin:server";
+
+/// Longest a restart waits for the sessions it closed to go away
+pub const RESTART_DRAIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What the accept loop is asked to do
+enum LoopRequest {
+    /// Drain the sessions and start over
+    Restart(RestartRequest),
+
+    /// Stop accepting connections, reporting once the listener is closed
+    Stop(oneshot::Sender<()>),
+}
+
+/// A restart for the accept loop to carry out
+struct RestartRequest {
+    /// Session left open, normally the one asking for the restart
+    keep: Option<Uuid>,
+
+    /// Where the outcome is reported once the server accepts connections again
+    done: oneshot::Sender<Result<()>>,
+}
+
+/// Outcome of the connect hook for an accepted connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectDecision {
+    /// Create a session for the connection
+    Allow,
+
+    /// Drop the connection without creating a session
+    Reject,
+}
+
+/// Callback invoked for every accepted connection, before a session is created
+///
+/// Embedders can use it for auditing, metrics or custom admission rules.
+pub type ConnectHook = Arc<dyn Fn(SocketAddr) -> ConnectDecision + Send + Sync>;
+
+/// Constructor for a service instantiated in every new session
+pub type ServiceConstructor = Arc<dyn Fn() -> Box<dyn ServiceTrait + Send> + Send + Sync>;
+
+/// Builder for [`Server`]
+///
+/// Lets embedders supply an auth manager, metrics handle, connect hook,
+/// session services and extra RPC methods without routing everything through
+/// [`ServerConfig`].
+#[derive(Default)]
+pub struct ServerBuilder {
+    config: ServerConfig,
+    config_file: Option<ConfigFile>,
+    auth_manager: Option<Arc<AuthManager>>,
+    users: Option<Arc<UserManager>>,
+    app_registry: Option<AppRegistry>,
+    metrics: Option<Metrics>,
+    on_connect: Option<ConnectHook>,
+    services: HashMap<String, ServiceConstructor>,
+    rpc_methods: RpcDispatcher,
+    reverse_resolver: Option<Arc<dyn ReverseResolver>>,
+    connections: ConnectionLimit,
+}
+
+impl ServerBuilder {
+    /// Create a builder with the default configuration
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the server configuration
+    pub fn config(mut self, config: ServerConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Save configuration changes made through `server/config/update` to `file`
+    ///
+    /// Without a file such changes only last until the server stops.
+    pub fn config_file(mut self, file: ConfigFile) -> Self {
+        self.config_file = Some(file);
+        self
+    }
+
+    /// Set the authentication manager used by sessions
+    pub fn auth_manager(mut self, auth_manager: Arc<AuthManager>) -> Self {
+        self.auth_manager = Some(auth_manager);
+        self
+    }
+
+    /// Set the internal provider's users, served by `users/export` and `users/import`
+    pub fn user_manager(mut self, users: Arc<UserManager>) -> Self {
+        self.users = Some(users);
+        self
+    }
+
+    /// Serve the applications in `registry` instead of loading `application.app_dir`
+    pub fn app_registry(mut self, registry: AppRegistry) -> Self {
+        self.app_registry = Some(registry);
+        self
+    }
+
+    /// Share an existing metrics handle instead of creating a new one
+    pub fn metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Set a hook that is called for each accepted connection
+    ///
+    /// A hook returning [`ConnectDecision::Reject`] causes the connection to be
+    /// closed before any session is created. Without a hook all connections are allowed.
+    pub fn connect_hook<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(SocketAddr) -> ConnectDecision + Send + Sync + 'static,
+    {
+        self.on_connect = Some(Arc::new(hook));
+        self
+    }
+
+    /// Register a service that is created in every new session
+    pub fn service<F>(mut self, name: &str, constructor: F) -> Self
+    where
+        F: Fn() -> Box<dyn ServiceTrait + Send> + Send + Sync + 'static,
+    {
+        self.services
+            .insert(name.to_string(), Arc::new(constructor));
+        self
+    }
+
+    /// Serve an RPC method in every session, replacing a core method of the same name
+    pub fn rpc_method<F, Fut>(mut self, method: &str, handler: F) -> Self
+    where
+        F: Fn(serde_json::Value, RpcContext) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = std::result::Result<serde_json::Value, rpc::RpcError>>
+            + Send
+            + 'static,
+    {
+        self.rpc_methods.register(method, handler);
+        self
+    }
+
+    /// Look up connection host names with `resolver` instead of the system
+    ///
+    /// Only used when `log_reverse_dns` is set.
+    pub fn reverse_resolver<R: ReverseResolver + 'static>(mut self, resolver: R) -> Self {
+        self.reverse_resolver = Some(Arc::new(resolver));
+        self
+    }
+
+    /// Count connections against `limit`, shared with other components such as the API
+    ///
+    /// Without one the server accepts any number of connections.
+    pub fn connection_limit(mut self, limit: ConnectionLimit) -> Self {
+        self.connections = limit;
+        self
+    }
+
+    /// Build the server
+    pub fn build(self) -> Server {
+        let events = EventLog::new(self.config.event_log_size);
+        let mut dispatcher = RpcDispatcher::with_core_handlers();
+        dispatcher.extend(self.rpc_methods);
+        let reverse_dns = self.config.log_reverse_dns.then(|| {
+            ReverseDns::new(
+                self.reverse_resolver
+                    .unwrap_or_else(|| Arc::new(SystemResolver)),
+                Duration::from_millis(self.config.reverse_dns_timeout_ms),
+            )
+        });
+        Server {
+            sessions: Arc::new(Mutex::new(HashMap::new())),
+            goodbyes: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            summaries: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            running: Arc::new(Mutex::new(false)),
+            start_time: Arc::new(Mutex::new(None)),
+            app_registry: self
+                .app_registry
+                .unwrap_or_else(|| AppRegistry::from_config(&self.config.application)),
+            apps: AppLauncher::new(self.config.application.output_buffer_lines)
+                .with_events(events.clone()),
+            on_connect: self.on_connect,
+            auth_manager: self.auth_manager,
+            users: self
+                .users
+                .unwrap_or_else(|| Arc::new(UserManager::from_config(&self.config.auth))),
+            metrics: self.metrics.unwrap_or_else(ServerMetrics::new),
+            services: Arc::new(self.services),
+            dispatcher: Arc::new(dispatcher),
+            reverse_dns,
+            events,
+            auth_limiter: AuthRateLimiter::new(self.config.auth.max_auth_attempts_per_ip_per_min),
+            tokens: TokenRegistry::new(&self.config.auth),
+            resumption: ResumeRegistry::new(),
+            peers: PeerTable::new(&self.config.cluster.peers),
+            handshakes: HandshakeLimiter::new(
+                self.config.session.max_concurrent_handshakes,
+                Duration::from_millis(self.config.session.handshake_slot_wait_ms),
+            ),
+            connections: self.connections,
+            config: LiveConfig::new(self.config, self.config_file),
+            control: Arc::new(std::sync::Mutex::new(None)),
+            force_close: Arc::new(watch::channel(false).0),
+        }
+    }
+}
+
 /// The main RCP server that accepts connections and manages sessions
 #[derive(Clone)]
 pub struct Server {
-    /// Server configuration
-    config: ServerConfig,
+    /// Server configuration, which `server/config/update` may change
+    config: LiveConfig,
 
     /// Active sessions
     sessions: Arc<Mutex<HashMap<Uuid, Arc<Mutex<Session>>>>>,
 
+    /// Channels telling each active session to say goodbye and close
+    goodbyes: Arc<std::sync::Mutex<HashMap<Uuid, oneshot::Sender<Goodbye>>>>,
+
+    /// What `sessions/list` reports about each active session
+    ///
+    /// Kept apart from the sessions themselves, which stay locked while they run.
+    summaries: Arc<std::sync::Mutex<HashMap<Uuid, SessionSummary>>>,
+
     /// Server state
     running: Arc<Mutex<bool>>,
 
     /// Server start time
     start_time: Arc<Mutex<Option<Instant>>>,
+
+    /// Applications that may be launched
+    app_registry: AppRegistry,
+
+    /// Application launcher and running instances
+    apps: AppLauncher,
+
+    /// Optional hook deciding whether to accept each connection
+    on_connect: Option<ConnectHook>,
+
+    /// Authentication manager, if one was provided
+    auth_manager: Option<Arc<AuthManager>>,
+
+    /// Users of the internal provider
+    users: Arc<UserManager>,
+
+    /// Server metrics
+    metrics: Metrics,
+
+    /// Services created for every new session
+    services: Arc<HashMap<String, ServiceConstructor>>,
+
+    /// RPC methods served to every session
+    dispatcher: Arc<RpcDispatcher>,
+
+    /// Host name lookups for the connection log, when `log_reverse_dns` is set
+    reverse_dns: Option<ReverseDns>,
+
+    /// Recent connection, authentication and disconnect events
+    events: EventLog,
+
+    /// Failed authentication attempts per client IP, shared by all sessions
+    auth_limiter: AuthRateLimiter,
+
+    /// Issued auth tokens and the revocation set
+    tokens: TokenRegistry,
+
+    /// Dropped sessions held for their clients to resume
+    resumption: ResumeRegistry,
+
+    /// Slots for connections still in the handshake
+    handshakes: HandshakeLimiter,
+
+    /// Cap on connections open at once, shared with the API
+    connections: ConnectionLimit,
+
+    /// Other daemons of the deployment and what they last reported
+    peers: PeerTable,
+
+    /// Where the accept loop takes restart and stop requests, while there is one
+    control: Arc<std::sync::Mutex<Option<mpsc::Sender<LoopRequest>>>>,
+
+    /// Set once a stop's grace period is over, closing the sessions still open
+    force_close: Arc<watch::Sender<bool>>,
 }
 
 impl Server {
     /// Create a new server with the given configuration
     pub fn new(config: ServerConfig) -> Self {
-        Self {
-            config,
-            sessions: Arc::new(Mutex::new(HashMap::new())),
-            running: Arc::new(Mutex::new(false)),
-            start_time: Arc::new(Mutex::new(None)),
-        }
+        ServerBuilder::new().config(config).build()
+    }
+
+    /// Start building a server
+    pub fn builder() -> ServerBuilder {
+        ServerBuilder::new()
+    }
+
+    /// Set a hook that is called for each accepted connection
+    ///
+    /// A hook returning [`ConnectDecision::Reject`] causes the connection to be
+    /// closed before any session is created. Without a hook all connections are allowed.
+    pub fn with_connect_hook<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(SocketAddr) -> ConnectDecision + Send + Sync + 'static,
+    {
+        self.on_connect = Some(Arc::new(hook));
+        self
     }
 
     /// Run the server and start accepting connections
+    ///
+    /// On [`restart`](Self::restart) the listener is rebound if the configured
+    /// address has changed since it was bound.
     pub async fn run(self) -> Result<()> {
-        let addr = format!("{}:{}", self.config.address, self.config.port);
+        let mut addr = self.listen_addr();
         info!("Starting RCP server on {}", addr);
 
-        let listener = TcpListener::bind(&addr).await?;
+        let config = self.config();
+        if config.auth.required && config.auth.psk.is_none() {
+            warn!("Authentication is required but no pre-shared key is set; only token holders can connect");
+        }
+        let mut listener = bind_with_retry(
+            &addr,
+            &config.bind_retry,
+            SocketOptions::from_config(&config),
+        )
+        .await?;
+        self.spawn_background_tasks();
+        self.mark_started().await;
+
+        let stopped = loop {
+            let request = match self.accept_until_told(&listener).await {
+                Some(LoopRequest::Restart(request)) => request,
+                Some(LoopRequest::Stop(done)) => break Some(done),
+                None => break None,
+            };
+            self.drain_for_restart(request.keep).await;
 
-        // Mark server as running and set start time
-        {
-            let mut running_guard = self.running.lock().await;
-            *running_guard = true;
+            // If the new address cannot be bound the old one is kept
+            let new_addr = self.listen_addr();
+            let outcome = if new_addr == addr {
+                Ok(())
+            } else {
+                let options = SocketOptions::from_config(&self.config());
+                match bind_listener(&new_addr, options).await {
+                    Ok(rebound) => {
+                        info!("RCP server now listening on {}", new_addr);
+                        listener = rebound;
+                        addr = new_addr;
+                        Ok(())
+                    }
+                    Err(e) => {
+                        error!(
+                            "Failed to bind {}, still listening on {}: {}",
+                            new_addr, addr, e
+                        );
+                        Err(e.into())
+                    }
+                }
+            };
+
+            self.mark_started().await;
+            let _ = request.done.send(outcome);
+        };
+
+        drop(listener);
+        if let Some(done) = stopped {
+            let _ = done.send(());
+        }
+        Ok(())
+    }
+
+    /// Accept connections on an already bound listener
+    ///
+    /// On [`restart`](Self::restart) the same listener is kept.
+    pub async fn serve(self, listener: TcpListener) -> Result<()> {
+        self.spawn_background_tasks();
+        self.mark_started().await;
+
+        let stopped = loop {
+            let request = match self.accept_until_told(&listener).await {
+                Some(LoopRequest::Restart(request)) => request,
+                Some(LoopRequest::Stop(done)) => break Some(done),
+                None => break None,
+            };
+            self.drain_for_restart(request.keep).await;
+            self.mark_started().await;
+            let _ = request.done.send(Ok(()));
+        };
+
+        drop(listener);
+        if let Some(done) = stopped {
+            let _ = done.send(());
+        }
+        Ok(())
+    }
+
+    /// Address the configuration says to listen on
+    fn listen_addr(&self) -> String {
+        let config = self.config();
+
+        // IPv6 literals need brackets once a port is appended
+        match config.address.parse::<IpAddr>() {
+            Ok(ip) => SocketAddr::new(ip, config.port).to_string(),
+            Err(_) => format!("{}:{}", config.address, config.port),
+        }
+    }
+
+    /// Start the background tasks that live as long as the server
+    fn spawn_background_tasks(&self) {
+        if !self.config().cluster.peers.is_empty() {
+            tokio::spawn(self.clone().probe_peers_periodically());
+        }
+    }
+
+    /// Mark the server as running, its uptime counted from now
+    async fn mark_started(&self) {
+        self.force_close.send_replace(false);
+        *self.running.lock().await = true;
+        *self.start_time.lock().await = Some(Instant::now());
+    }
+
+    /// Accept connections until the listener fails or a restart or stop is requested
+    async fn accept_until_told(&self, listener: &TcpListener) -> Option<LoopRequest> {
+        let (tx, mut rx) = mpsc::channel(1);
+        *self.lock_control() = Some(tx);
+
+        let request = loop {
+            tokio::select! {
+                accepted = listener.accept() => match accepted {
+                    Ok((socket, peer_addr)) => self.admit(socket, peer_addr),
+                    Err(_) => break None,
+                },
+                Some(request) = rx.recv() => break Some(request),
+            }
+        };
+
+        *self.lock_control() = None;
+        request
+    }
+
+    /// Start a session for an accepted connection, unless it is rejected
+    fn admit(&self, socket: tokio::net::TcpStream, peer_addr: SocketAddr) {
+        let peer_addr_str = peer_addr.to_string();
+        let client_ip = Some(session::client_ip(&peer_addr).to_string());
+        self.log_accepted(peer_addr);
 
-            let mut start_time_guard = self.start_time.lock().await;
-            *start_time_guard = Some(Instant::now());
+        if let Some(hook) = &self.on_connect {
+            if hook(peer_addr) == ConnectDecision::Reject {
+                info!("Connection from {} rejected by connect hook", peer_addr_str);
+                self.metrics.connection_rejected();
+                self.events
+                    .record(ServerEventType::Rejected, None, None, client_ip);
+                return;
+            }
         }
 
-        // Accept connections
-        while let Ok((socket, peer_addr)) = listener.accept().await {
-            let peer_addr_str = peer_addr.to_string();
-            info!("Accepted connection from: {}", peer_addr_str);
+        let Some(connection) = self.connections.admit("server", &peer_addr_str) else {
+            self.metrics.connection_rejected();
+            self.events
+                .record(ServerEventType::Rejected, None, None, client_ip);
+            return;
+        };
+
+        // Wait for a handshake slot off the accept loop, so connections
+        // queued behind stalled handshakes don't hold up new ones
+        let server = self.clone();
+        tokio::spawn(async move {
+            let Some(slot) = server.handshakes.acquire().await else {
+                info!(
+                    "Connection from {} rejected: too many handshakes in progress",
+                    peer_addr_str
+                );
+                server.metrics.connection_rejected();
+                server
+                    .events
+                    .record(ServerEventType::Rejected, None, None, client_ip);
+                return;
+            };
 
-            // Create a new session
-            let session_id = Uuid::new_v4();
-            let session = Session::new(session_id, socket, self.config.clone(), peer_addr_str);
+            let session_config = (*server.config()).clone();
+            server
+                .run_session(socket, peer_addr, session_config, slot)
+                .await;
+            drop(connection);
+        });
+    }
+
+    /// Drain the sessions and start over with the current configuration
+    ///
+    /// Every session but `keep` is told the server is shutting down, and the
+    /// restart waits up to [`RESTART_DRAIN_TIMEOUT`] for them to close. The
+    /// process, and the server's tokens, metrics and event log, carry on.
+    /// Fails if the server is not accepting connections, or if a changed
+    /// listen address cannot be bound, in which case the old one is kept.
+    pub async fn restart(&self, keep: Option<Uuid>) -> Result<()> {
+        let not_running = || Error::Other("Server is not running".to_string());
+        let requests = self.lock_control().clone().ok_or_else(not_running)?;
+
+        info!("Restarting RCP server");
+        let (done, outcome) = oneshot::channel();
+        requests
+            .send(LoopRequest::Restart(RestartRequest { keep, done }))
+            .await
+            .map_err(|_| not_running())?;
+        outcome.await.map_err(|_| not_running())?
+    }
+
+    /// Close every session but `keep` ahead of a restart
+    async fn drain_for_restart(&self, keep: Option<Uuid>) {
+        let open = self.drain_sessions(keep, RESTART_DRAIN_TIMEOUT).await;
+        if open > 0 {
+            warn!(
+                "Restarting with {} sessions still open after {:?}",
+                open, RESTART_DRAIN_TIMEOUT
+            );
+        }
+    }
 
-            // Store the session
+    /// Tell every session but `keep` to close, waiting up to `timeout` for
+    /// them to go, and return how many are still open
+    ///
+    /// A session busy with a request answers it before it closes.
+    async fn drain_sessions(&self, keep: Option<Uuid>, timeout: Duration) -> usize {
+        let others = |id: &Uuid| Some(*id) != keep;
+
+        let session_ids: Vec<Uuid> = self
+            .lock_goodbyes()
+            .keys()
+            .copied()
+            .filter(others)
+            .collect();
+        for session_id in session_ids {
+            debug!("Disconnecting session: {}", session_id);
+            if let Err(e) = self.send_goodbye(&session_id, Goodbye::new(DisconnectReason::Shutdown))
             {
-                let mut sessions = self.sessions.lock().await;
-                sessions.insert(session_id, Arc::new(Mutex::new(session)));
+                debug!("Session {} already closed: {}", session_id, e);
             }
+        }
+
+        let started = Instant::now();
+        loop {
+            let open = self
+                .sessions
+                .lock()
+                .await
+                .keys()
+                .filter(|&id| others(id))
+                .count();
+            if open == 0 || started.elapsed() >= timeout {
+                return open;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
 
-            // Spawn a task to handle the session
-            let server_clone = self.clone();
-            tokio::spawn(async move {
-                if let Err(e) = server_clone.handle_session(session_id).await {
+    /// Log an accepted connection, by host name too when reverse DNS is on
+    ///
+    /// The lookup runs in its own task so the accept loop never waits on it.
+    fn log_accepted(&self, peer_addr: SocketAddr) {
+        let Some(reverse_dns) = self.reverse_dns.clone() else {
+            info!("Accepted connection from: {}", peer_addr);
+            return;
+        };
+
+        tokio::spawn(async move {
+            let source = reverse_dns.describe(session::client_ip(&peer_addr)).await;
+            info!(
+                "Accepted connection from: {}, port {}",
+                source,
+                peer_addr.port()
+            );
+        });
+    }
+
+    /// Create a session for an admitted connection and run it to completion
+    async fn run_session(
+        &self,
+        socket: tokio::net::TcpStream,
+        peer_addr: SocketAddr,
+        config: ServerConfig,
+        handshake_slot: HandshakeSlot,
+    ) {
+        self.metrics.connection_accepted();
+
+        // Create a new session
+        let session_id = Uuid::new_v4();
+        let mut session = Session::new(session_id, socket, config, peer_addr, self.clone());
+        session.hold_handshake_slot(handshake_slot);
+        let client_ip = Some(session.client_ip().to_string());
+
+        // Store the session
+        let session = Arc::new(Mutex::new(session));
+        {
+            let mut sessions = self.sessions.lock().await;
+            sessions.insert(session_id, session.clone());
+        }
+        self.metrics.session_opened();
+        self.events.record(
+            ServerEventType::Connected,
+            Some(session_id),
+            None,
+            client_ip,
+        );
+
+        // Past a stop's grace period the session is closed even mid-request
+        let mut force_close = self.force_close.subscribe();
+        tokio::select! {
+            result = self.handle_session(session_id) => {
+                if let Err(e) = result {
                     error!("Session error: {}", e);
                 }
-
-                // Always clean up the session
-                let _ = server_clone.remove_session(session_id).await;
-            });
+            }
+            _ = force_close.wait_for(|forced| *forced) => {
+                info!("Session {} disconnected at the end of the shutdown grace period", session_id);
+            }
         }
 
-        Ok(())
+        // Always clean up the session, by the ID it ended with if it resumed another
+        let session_id = session.lock().await.id();
+        let _ = self.remove_session(session_id).await;
     }
 
     /// Handle a client session
@@ -103,23 +670,267 @@ impl Server {
     async fn remove_session(&self, session_id: Uuid) -> Result<()> {
         let mut sessions = self.sessions.lock().await;
 
-        if let Some(session_arc) = sessions.get(&session_id) {
+        self.lock_goodbyes().remove(&session_id);
+        self.lock_summaries().remove(&session_id);
+        if let Some(session_arc) = sessions.remove(&session_id) {
             // Try to disconnect the session properly
             let mut session = session_arc.lock().await;
             let _ = session.disconnect().await;
-        }
 
-        sessions.remove(&session_id);
+            let grace = self.config().session.resume_grace_secs;
+            if let Some((token, held)) = session.held_session().filter(|_| grace > 0) {
+                debug!("Holding session {} for {} seconds", session_id, grace);
+                self.resumption
+                    .hold(token, held, Duration::from_secs(grace));
+            }
+
+            self.metrics.session_closed();
+            self.events.record(
+                ServerEventType::Disconnected,
+                Some(session_id),
+                session.client_name().map(|name| name.to_string()),
+                Some(session.client_ip().to_string()),
+            );
+        }
         debug!("Session removed: {}", session_id);
         Ok(())
     }
 
+    /// Move the running session `from` to the ID `to` of the session it resumed
+    pub(crate) async fn adopt_session_id(&self, from: &Uuid, to: Uuid, created_at: &str) {
+        let mut sessions = self.sessions.lock().await;
+        if let Some(session) = sessions.remove(from) {
+            sessions.insert(to, session);
+        }
+
+        let mut goodbyes = self.lock_goodbyes();
+        if let Some(goodbye) = goodbyes.remove(from) {
+            goodbyes.insert(to, goodbye);
+        }
+        drop(goodbyes);
+
+        let mut summaries = self.lock_summaries();
+        if let Some(mut summary) = summaries.remove(from) {
+            summary.id = to;
+            summary.created_at = created_at.to_string();
+            summaries.insert(to, summary);
+        }
+    }
+
+    /// Create the channel a new session is told to close on
+    pub(crate) fn register_goodbye(&self, session_id: Uuid) -> oneshot::Receiver<Goodbye> {
+        let (tx, rx) = oneshot::channel();
+        self.lock_goodbyes().insert(session_id, tx);
+        rx
+    }
+
+    /// Disconnect a session, sending it `goodbye` first
+    ///
+    /// The session closes once the goodbye frame is written. Fails with
+    /// `NotFound` if there is no such session or it is already closing.
+    pub fn send_goodbye(&self, session_id: &Uuid, goodbye: Goodbye) -> Result<()> {
+        let tx = self
+            .lock_goodbyes()
+            .remove(session_id)
+            .ok_or_else(|| Error::NotFound(format!("Session not found: {}", session_id)))?;
+
+        // The session may have ended on its own since the lookup
+        tx.send(goodbye)
+            .map_err(|_| Error::NotFound(format!("Session not found: {}", session_id)))
+    }
+
+    /// Start reporting a new session in `sessions/list`
+    pub(crate) fn register_summary(&self, summary: SessionSummary) {
+        self.lock_summaries().insert(summary.id, summary);
+    }
+
+    /// Update what `sessions/list` reports about a session
+    pub(crate) fn update_summary(
+        &self,
+        session_id: &Uuid,
+        update: impl FnOnce(&mut SessionSummary),
+    ) {
+        if let Some(summary) = self.lock_summaries().get_mut(session_id) {
+            update(summary);
+        }
+    }
+
+    /// Summary of one active session
+    pub fn session_summary(&self, session_id: &Uuid) -> Option<SessionSummary> {
+        self.lock_summaries().get(session_id).cloned()
+    }
+
+    /// Summaries of all active sessions, oldest first
+    pub fn session_summaries(&self) -> Vec<SessionSummary> {
+        let mut summaries: Vec<SessionSummary> = self.lock_summaries().values().cloned().collect();
+        summaries.sort_by(|a, b| (&a.created_at, a.id).cmp(&(&b.created_at, b.id)));
+        summaries
+    }
+
+    fn lock_summaries(&self) -> std::sync::MutexGuard<'_, HashMap<Uuid, SessionSummary>> {
+        self.summaries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn lock_goodbyes(&self) -> std::sync::MutexGuard<'_, HashMap<Uuid, oneshot::Sender<Goodbye>>> {
+        self.goodbyes.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn lock_control(&self) -> std::sync::MutexGuard<'_, Option<mpsc::Sender<LoopRequest>>> {
+        self.control.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
     /// Get all active sessions
     pub async fn get_sessions(&self) -> Vec<Uuid> {
         let sessions = self.sessions.lock().await;
         sessions.keys().cloned().collect()
     }
 
+    /// Get the application launcher
+    pub fn apps(&self) -> &AppLauncher {
+        &self.apps
+    }
+
+    /// Get the definitions of the applications that may be launched
+    pub fn app_registry(&self) -> &AppRegistry {
+        &self.app_registry
+    }
+
+    /// Get the authentication manager, if one was configured
+    pub fn auth_manager(&self) -> Option<&Arc<AuthManager>> {
+        self.auth_manager.as_ref()
+    }
+
+    /// Get the internal provider's users
+    pub fn users(&self) -> &Arc<UserManager> {
+        &self.users
+    }
+
+    /// Get the configuration currently in effect
+    pub fn config(&self) -> Arc<ServerConfig> {
+        self.config.current()
+    }
+
+    /// Get the running configuration, to change it in place
+    pub fn live_config(&self) -> &LiveConfig {
+        &self.config
+    }
+
+    /// Get the log of recent server events
+    pub fn events(&self) -> &EventLog {
+        &self.events
+    }
+
+    /// Get the per-IP authentication rate limiter
+    pub fn auth_rate_limiter(&self) -> &AuthRateLimiter {
+        &self.auth_limiter
+    }
+
+    /// Get the registry of issued auth tokens
+    pub fn tokens(&self) -> &TokenRegistry {
+        &self.tokens
+    }
+
+    /// Get the dropped sessions held for resumption
+    pub fn resumption(&self) -> &ResumeRegistry {
+        &self.resumption
+    }
+
+    /// Get the limiter on concurrent handshakes
+    pub fn handshake_limiter(&self) -> &HandshakeLimiter {
+        &self.handshakes
+    }
+
+    /// Get the cap on connections open at once
+    pub fn connection_limit(&self) -> &ConnectionLimit {
+        &self.connections
+    }
+
+    /// Get the RPC methods served to every session
+    pub fn dispatcher(&self) -> &RpcDispatcher {
+        &self.dispatcher
+    }
+
+    /// Get the table of cluster peers
+    pub fn peers(&self) -> &PeerTable {
+        &self.peers
+    }
+
+    /// Health reported to peers
+    pub async fn health(&self) -> PeerHealth {
+        // Released before awaiting the uptime
+        let active_sessions = self.lock_summaries().len();
+        PeerHealth {
+            version: cluster::daemon_version().to_string(),
+            active_sessions,
+            uptime_secs: self.uptime().await.map_or(0, |uptime| uptime.as_secs()),
+        }
+    }
+
+    /// Announce this daemon to every configured peer once, recording their health
+    pub async fn probe_peers(&self) {
+        let config = self.config();
+        let cluster = &config.cluster;
+        let announcement = Announcement {
+            address: cluster
+                .advertise_address
+                .clone()
+                .unwrap_or_else(|| format!("{}:{}", config.address, config.port)),
+            health: self.health().await,
+        };
+        let timeout = Duration::from_secs(cluster.probe_timeout_secs);
+
+        let probes = self.peers.configured().into_iter().map(|address| {
+            let announcement = &announcement;
+            async move {
+                let outcome = cluster::probe_peer(
+                    &address,
+                    cluster.peer_token.clone(),
+                    timeout,
+                    announcement,
+                )
+                .await;
+                if let Err(e) = &outcome {
+                    debug!("Cluster peer {} unreachable: {}", address, e);
+                }
+                self.peers.record_probe(&address, outcome);
+            }
+        });
+        futures_util::future::join_all(probes).await;
+    }
+
+    /// Probe the configured peers every `cluster.probe_interval_secs` until the server stops
+    async fn probe_peers_periodically(self) {
+        let config = self.config();
+        let interval = Duration::from_secs(config.cluster.probe_interval_secs.max(1));
+        info!(
+            "Probing {} cluster peers every {:?}",
+            config.cluster.peers.len(),
+            interval
+        );
+
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            if !self.is_running().await {
+                break;
+            }
+            self.probe_peers().await;
+        }
+    }
+
+    /// Get the server metrics handle
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Instantiate the registered services for a new session
+    pub(crate) fn create_services(&self) -> HashMap<String, Box<dyn ServiceTrait + Send>> {
+        self.services
+            .iter()
+            .map(|(name, constructor)| (name.clone(), constructor()))
+            .collect()
+    }
+
     /// Get the server uptime
     pub async fn uptime(&self) -> Option<Duration> {
         let start_time = self.start_time.lock().await;
@@ -133,23 +944,40 @@ impl Server {
     }
 
     /// Stop the server
+    ///
+    /// Sessions get up to `shutdown_grace_secs` to finish the request they
+    /// are serving, see [`stop_within`](Self::stop_within).
     pub async fn stop(&self) -> Result<()> {
+        let grace = Duration::from_secs(self.config().shutdown_grace_secs);
+        self.stop_within(grace).await
+    }
+
+    /// Stop the server, giving sessions up to `grace` to finish their requests
+    ///
+    /// New connections are refused from the start. Every session is told the
+    /// server is shutting down; an idle one closes at once and a busy one once
+    /// it has answered its current request. Sessions still open when `grace`
+    /// runs out are disconnected, wherever they are.
+    pub async fn stop_within(&self, grace: Duration) -> Result<()> {
         info!("Stopping RCP server");
+        *self.running.lock().await = false;
 
-        // Set running to false
-        {
-            let mut running = self.running.lock().await;
-            *running = false;
+        // Close the listener first, so nothing new arrives while draining
+        let control = self.lock_control().clone();
+        if let Some(control) = control {
+            let (done, stopped) = oneshot::channel();
+            if control.send(LoopRequest::Stop(done)).await.is_ok() {
+                let _ = stopped.await;
+            }
         }
 
-        // Disconnect all sessions
-        let sessions = self.sessions.lock().await;
-        for (session_id, session) in sessions.iter() {
-            debug!("Disconnecting session: {}", session_id);
-            let mut session = session.lock().await;
-            if let Err(e) = session.disconnect().await {
-                error!("Error disconnecting session {}: {}", session_id, e);
-            }
+        let open = self.drain_sessions(None, grace).await;
+        if open > 0 {
+            warn!(
+                "Disconnecting {} sessions still busy after the {:?} shutdown grace period",
+                open, grace
+            );
+            self.force_close.send_replace(true);
         }
 
         // Reset start time
@@ -1,28 +1,144 @@
+use crate::logging;
+use crate::protocol::codec::{self, FrameError, FramedStream};
+use crate::protocol::goodbye::{DisconnectReason, Goodbye};
+use crate::protocol::{handshake, FrameCompressor, Keepalive};
+use crate::server::cluster::{Announcement, ANNOUNCE_PERMISSION, READ_CLUSTER_PERMISSION};
+use crate::server::events::ServerEventType;
+use crate::server::rpc::{self, RpcError, RpcReply, RpcRequest, RpcResponse};
 use crate::server::{
-    config::ServerConfig,
+    apps::{AppInstanceInfo, READ_APPS_PERMISSION},
+    bench,
+    config::{AuthFailureBehavior, ServerConfig},
+    dispatch::{self, HandlerFuture, RpcContext},
     error::{Error, Result},
+    ratelimit::HandshakeSlot,
+    resume::{self, HeldSession},
+    tokens::{self, Identity, TokenError},
+    user::UserRole,
+    Server,
 };
+use bytes::Bytes;
+use chrono::{DateTime, TimeZone, Utc};
+use futures_util::future::BoxFuture;
 use log::{debug, error, info};
 use rcpcore::{ConnectionState, Frame};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::collections::HashMap;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
 use tokio::net::TcpStream;
+use tokio::sync::{oneshot, Semaphore};
+use tokio::time::Instant;
 use uuid::Uuid;
 
+/// How long a rejected client gets to send the request that is answered with the reason
+const AUTH_REJECT_READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Permission needed to kick other sessions
+pub const MANAGE_SESSIONS_PERMISSION: &str = "admin:sessions";
+
+/// Permission needed to list sessions
+pub const READ_SESSIONS_PERMISSION: &str = "read:sessions";
+
+/// Permission needed to read recent server events
+pub const READ_EVENTS_PERMISSION: &str = "read:events";
+
+/// Permission needed to change the log level
+pub const LOG_LEVEL_PERMISSION: &str = "admin:diag";
+
+/// Name of the pre-shared key method in `auth.allowed_methods`
+pub const PSK_METHOD: &str = "psk";
+
+/// Name of the issued token method in `auth.allowed_methods`
+pub const TOKEN_METHOD: &str = "token";
+
+/// IP address a client is reported and rate limited by
+pub fn client_ip(addr: &SocketAddr) -> IpAddr {
+    addr.ip().to_canonical()
+}
+
+/// An active session as listed by `sessions/list`
+///
+/// Timestamps are RFC 3339. Sessions that did not authenticate with a token
+/// are listed as the operator, with an empty `user_id` and `expires_at`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SessionSummary {
+    /// Session ID
+    pub id: Uuid,
+
+    /// ID of the token the session authenticated with
+    pub user_id: String,
+
+    /// User or client the session acts as
+    pub username: String,
+
+    /// IP address of the client
+    pub client_ip: IpAddr,
+
+    /// Source port of the client
+    pub client_port: u16,
+
+    /// When the connection was accepted
+    pub created_at: String,
+
+    /// When the session's token expires
+    pub expires_at: String,
+
+    /// When the session last sent a request
+    pub last_active: String,
+
+    /// Whether the session has authenticated
+    pub active: bool,
+}
+
+impl SessionSummary {
+    /// Summary of a session that has just connected
+    fn new(id: Uuid, peer_addr: &SocketAddr) -> Self {
+        let now = Utc::now().to_rfc3339();
+        Self {
+            id,
+            user_id: String::new(),
+            username: "operator".to_string(),
+            client_ip: client_ip(peer_addr),
+            client_port: peer_addr.port(),
+            created_at: now.clone(),
+            expires_at: String::new(),
+            last_active: now,
+            active: false,
+        }
+    }
+}
+
+/// A session as described by `sessions/info`
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionDetails {
+    /// What `sessions/list` reports about the session
+    #[serde(flatten)]
+    pub summary: SessionSummary,
+
+    /// Application instances running in the session, when asked for
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub apps: Option<Vec<AppInstanceInfo>>,
+}
+
 /// A client session on the server
 pub struct Session {
     /// Session ID
     pub id: Uuid,
 
-    /// Connection stream
-    stream: TcpStream,
+    /// Framed connection stream
+    framed: FramedStream<TcpStream>,
+
+    /// Frame compression negotiated during the handshake
+    compressor: FrameCompressor,
 
     /// Server configuration
     config: ServerConfig,
 
-    /// Peer address
-    #[allow(dead_code)]
-    peer_addr: String,
+    /// Address of the connected client
+    peer_addr: SocketAddr,
 
     /// Session state
     state: ConnectionState,
@@ -33,13 +149,90 @@ pub struct Session {
     /// Client name
     client_name: Option<String>,
 
+    /// Credential the client presented in its hello
+    credential: Option<String>,
+
+    /// Challenge issued in the handshake, which a pre-shared key is proven against
+    challenge: String,
+
+    /// Client's proof of the pre-shared key for `challenge`, if it sent one
+    proof: Option<String>,
+
     /// Session permissions
-    #[allow(dead_code)]
     permissions: Vec<String>,
 
+    /// ID of the token the session authenticated with, if any
+    token_id: Option<Uuid>,
+
+    /// Expiry of that token, in seconds since the Unix epoch
+    token_exp: Option<i64>,
+
+    /// When the connection was accepted, RFC 3339
+    created_at: String,
+
+    /// Resume token the client presented in its hello
+    resume: Option<String>,
+
+    /// Resume token issued to the session, when resumption is on
+    resume_token: Option<String>,
+
+    /// Whether the connection dropped in a way the client may resume from
+    resumable: bool,
+
     /// Active services
     #[allow(dead_code)]
     services: HashMap<String, Box<dyn ServiceTrait + Send>>,
+
+    /// Signalled when the server wants this session to close
+    goodbye_rx: oneshot::Receiver<Goodbye>,
+
+    /// Handshake slot held until the handshake finishes
+    handshake_slot: Option<HandshakeSlot>,
+
+    /// Slots for requests running at once, or `None` for no limit
+    inflight: Option<Arc<Semaphore>>,
+
+    /// When the session last finished a request, for the idle timeout
+    last_request: Instant,
+
+    /// Idle timeout in seconds for the session's role (0 for no limit)
+    idle_timeout: u64,
+
+    /// When the session reaches its maximum lifetime, if it has one
+    lifetime_deadline: Option<Instant>,
+
+    /// When a frame last crossed the connection either way, for keep-alives
+    last_traffic: Instant,
+
+    /// Keep-alive pings sent so far
+    pings_sent: u64,
+
+    /// When the client must have answered the outstanding keep-alive ping
+    ping_deadline: Option<Instant>,
+
+    /// Handle to the server owning this session
+    server: Server,
+}
+
+/// Where a request went
+enum Dispatched {
+    /// Handled by the session itself
+    Done(std::result::Result<Value, RpcError>),
+
+    /// Left to a handler registered with the server's dispatcher
+    Deferred(HandlerFuture),
+}
+
+/// What woke the request loop
+enum SessionInput {
+    /// A frame arrived from the client, or reading one failed
+    Frame(std::result::Result<Bytes, FrameError>),
+
+    /// The connection has been quiet for `keepalive_secs`
+    Keepalive,
+
+    /// The session should close, with a goodbye if one is given
+    Close(Option<Goodbye>),
 }
 
 // Define a service trait for our session
@@ -61,20 +254,62 @@ impl ServiceFactory {
 
 impl Session {
     /// Create a new session
-    pub fn new(id: Uuid, tcp_stream: TcpStream, config: ServerConfig, peer_addr: String) -> Self {
+    pub fn new(
+        id: Uuid,
+        tcp_stream: TcpStream,
+        config: ServerConfig,
+        peer_addr: SocketAddr,
+        server: Server,
+    ) -> Self {
+        let framed = codec::framed(tcp_stream, config.session.max_frame_size);
+        let compressor = FrameCompressor::disabled(config.session.max_frame_size);
+        let inflight = match config.session.max_inflight_rpcs {
+            0 => None,
+            max => Some(Arc::new(Semaphore::new(max))),
+        };
+        let idle_timeout = config.session.timeout;
+        let summary = SessionSummary::new(id, &peer_addr);
+        let created_at = summary.created_at.clone();
+        server.register_summary(summary);
+
         Self {
             id,
-            stream: tcp_stream,
+            framed,
+            compressor,
             config,
             peer_addr,
             state: ConnectionState::Connected,
             client_id: None,
             client_name: None,
+            credential: None,
+            challenge: handshake::new_challenge(),
+            proof: None,
             permissions: Vec::new(),
-            services: HashMap::new(),
+            token_id: None,
+            token_exp: None,
+            created_at,
+            resume: None,
+            resume_token: None,
+            resumable: false,
+            services: server.create_services(),
+            goodbye_rx: server.register_goodbye(id),
+            handshake_slot: None,
+            inflight,
+            last_request: Instant::now(),
+            idle_timeout,
+            lifetime_deadline: None,
+            last_traffic: Instant::now(),
+            pings_sent: 0,
+            ping_deadline: None,
+            server,
         }
     }
 
+    /// Hold `slot` until the handshake completes or fails
+    pub(crate) fn hold_handshake_slot(&mut self, slot: HandshakeSlot) {
+        self.handshake_slot = Some(slot);
+    }
+
     /// Get the session ID
     pub fn id(&self) -> Uuid {
         self.id
@@ -90,6 +325,24 @@ impl Session {
         self.client_name.as_deref()
     }
 
+    /// Get the address of the connected client
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.peer_addr
+    }
+
+    /// Get the IP address of the connected client
+    ///
+    /// IPv4 clients of a dual-stack listener are reported by their IPv4
+    /// address rather than the IPv4-mapped IPv6 one.
+    pub fn client_ip(&self) -> IpAddr {
+        client_ip(&self.peer_addr)
+    }
+
+    /// Get the source port of the connected client
+    pub fn client_port(&self) -> u16 {
+        self.peer_addr.port()
+    }
+
     /// Get the session state
     pub fn state(&self) -> ConnectionState {
         self.state
@@ -99,40 +352,101 @@ impl Session {
     pub async fn process(&mut self) -> Result<()> {
         debug!("Processing session: {}", self.id);
 
-        self.handle_handshake().await?;
-        self.authenticate().await?;
+        let handshake = self.handle_handshake().await;
+        self.handshake_slot = None;
+        handshake?;
+        let authenticated = match self.resume.take() {
+            Some(token) => self.resume_session(&token).await,
+            None => self.authenticate().await,
+        };
+        if let Err(e) = authenticated {
+            self.record_event(ServerEventType::AuthFailed);
+            return Err(e);
+        }
+        self.record_event(ServerEventType::Authenticated);
+        self.publish_identity();
+        self.apply_role_limits().await;
+        if self.config.session.resume_grace_secs > 0 {
+            self.resume_token = Some(resume::issue_token());
+        }
 
         // Main request handling loop
         self.state = ConnectionState::Authenticated; // We use Authenticated as the "ready" state
 
         // Simplified session handling for now
         info!("Session {} authenticated and ready", self.id);
+        self.last_request = Instant::now();
+        self.last_traffic = self.last_request;
 
-        // In a real implementation, we would have a frame processing loop here
-        // For now, we'll just keep the connection alive and simulate activity
-        let mut buffer = [0u8; 1024];
-
+        // Each frame carries a JSON-RPC request or batch, answered by exactly one response frame
         loop {
-            match self.stream.read(&mut buffer).await {
-                Ok(0) => {
-                    // Connection closed
-                    debug!("Connection closed by client");
+            let frame = match self.next_input().await {
+                SessionInput::Frame(frame) => frame,
+                SessionInput::Keepalive => {
+                    if let Err(e) = self.send_keepalive().await {
+                        debug!("Failed to send keep-alive to session {}: {}", self.id, e);
+                        self.resumable = true;
+                        break;
+                    }
+                    continue;
+                }
+                SessionInput::Close(goodbye) => {
+                    // An unanswered ping means a lost connection, not a closed session
+                    self.resumable = goodbye
+                        .as_ref()
+                        .is_some_and(|g| g.reason == DisconnectReason::KeepaliveTimeout);
+                    if let Some(goodbye) = goodbye {
+                        self.say_goodbye(goodbye).await;
+                    }
                     break;
                 }
-                Ok(_) => {
-                    // Process the request - simplified for now
-                    debug!("Received data from client");
+            };
 
-                    // Send back a simple response - just some bytes for now
-                    let response_data = vec![0, 1, 2, 3, 4];
-                    if let Err(e) = self.stream.write_all(&response_data).await {
+            match frame {
+                Ok(frame) => {
+                    // Any frame shows the client is alive
+                    self.last_traffic = Instant::now();
+                    self.ping_deadline = None;
+                    if let Some(pong) = Keepalive::pong_from_payload(&frame) {
+                        debug!("Session {} answered keep-alive {}", self.id, pong.seq);
+                        continue;
+                    }
+
+                    debug!("Received {} byte frame from client", frame.len());
+
+                    let response = self.handle_frame(&frame).await;
+                    let response_data = match serde_json::to_vec(&response) {
+                        Ok(data) => Bytes::from(data),
+                        Err(e) => {
+                            error!("Failed to serialize response: {}", e);
+                            break;
+                        }
+                    };
+                    if let Err(e) = self.write_message(response_data).await {
                         error!("Failed to send response: {}", e);
+                        self.resumable = true;
                         break;
                     }
+                    self.last_request = Instant::now();
+                    self.last_traffic = self.last_request;
+                }
+                Err(FrameError::Closed) => {
+                    // Connection closed
+                    debug!("Connection closed by client");
+                    self.resumable = true;
+                    break;
+                }
+                Err(FrameError::ReadTimeout(limit)) => {
+                    info!(
+                        "Session {} took longer than {:?} to send a frame, closing",
+                        self.id, limit
+                    );
+                    break;
                 }
                 Err(e) => {
                     error!("Error reading from client: {}", e);
-                    return Err(Error::Io(e));
+                    self.resumable = true;
+                    return Err(e.into());
                 }
             }
         }
@@ -140,34 +454,894 @@ impl Session {
         Ok(())
     }
 
+    /// Wait for the next frame, an idle timeout, a keep-alive or a request to close
+    ///
+    /// The idle timeout covers the time since the last request, and a
+    /// keep-alive is due once the connection has been quiet for
+    /// `keepalive_secs`. Once a frame's first bytes are in, it has
+    /// `max_frame_read_secs` to arrive in full. A session past its maximum
+    /// lifetime is closed before it reads another frame, however busy it is.
+    async fn next_input(&mut self) -> SessionInput {
+        if self
+            .lifetime_deadline
+            .is_some_and(|deadline| deadline <= Instant::now())
+        {
+            return self.lifetime_exceeded();
+        }
+        let lifetime_deadline = self.lifetime_deadline;
+        let lifetime = async move {
+            match lifetime_deadline {
+                Some(deadline) => tokio::time::sleep_until(deadline).await,
+                None => std::future::pending().await,
+            }
+        };
+
+        let idle_timeout = self.idle_timeout;
+        let idle_deadline = self.last_request + Duration::from_secs(idle_timeout);
+        let idle = async move {
+            match idle_timeout {
+                0 => std::future::pending().await,
+                _ => tokio::time::sleep_until(idle_deadline).await,
+            }
+        };
+
+        let (id, keepalive_secs) = (self.id, self.config.session.keepalive_secs);
+        let (last_traffic, ping_deadline) = (self.last_traffic, self.ping_deadline);
+        let keepalive = async move {
+            match (keepalive_secs, ping_deadline) {
+                (0, _) => std::future::pending().await,
+                (_, Some(deadline)) => {
+                    tokio::time::sleep_until(deadline).await;
+                    info!("Session {} did not answer a keep-alive ping, closing", id);
+                    SessionInput::Close(Some(Goodbye::new(DisconnectReason::KeepaliveTimeout)))
+                }
+                (secs, None) => {
+                    tokio::time::sleep_until(last_traffic + Duration::from_secs(secs)).await;
+                    SessionInput::Keepalive
+                }
+            }
+        };
+
+        // Bytes left over from the last read mean a frame has already started
+        if self.framed.read_buffer().is_empty() {
+            tokio::select! {
+                ready = self.framed.get_ref().readable() => {
+                    if let Err(e) = ready {
+                        return SessionInput::Frame(Err(FrameError::Io(e)));
+                    }
+                }
+                goodbye = &mut self.goodbye_rx => return SessionInput::Close(goodbye.ok()),
+                _ = idle => {
+                    info!("Session {} idle for {} seconds, closing", self.id, idle_timeout);
+                    return SessionInput::Close(Some(Goodbye::new(DisconnectReason::IdleTimeout)));
+                }
+                _ = lifetime => return self.lifetime_exceeded(),
+                input = keepalive => return input,
+            }
+        }
+
+        let framed = &mut self.framed;
+        let frame_read_secs = self.config.session.max_frame_read_secs;
+        let frame = async move {
+            match frame_read_secs {
+                0 => codec::read_frame(framed).await,
+                secs => {
+                    let limit = Duration::from_secs(secs);
+                    tokio::time::timeout(limit, codec::read_frame(framed))
+                        .await
+                        .unwrap_or(Err(FrameError::ReadTimeout(limit)))
+                }
+            }
+        };
+
+        tokio::select! {
+            frame = frame => {
+                SessionInput::Frame(frame.and_then(|frame| self.compressor.decode(frame)))
+            }
+            goodbye = &mut self.goodbye_rx => SessionInput::Close(goodbye.ok()),
+        }
+    }
+
+    /// Close the session for having reached its maximum lifetime
+    fn lifetime_exceeded(&self) -> SessionInput {
+        info!("Session {} reached its maximum lifetime, closing", self.id);
+        SessionInput::Close(Some(
+            Goodbye::new(DisconnectReason::LifetimeExceeded)
+                .with_message("session lifetime exceeded; please reconnect"),
+        ))
+    }
+
+    /// Take the idle timeout and maximum lifetime of the session's role
+    ///
+    /// The lifetime counts from the session's first connection, which a
+    /// resumed session keeps.
+    async fn apply_role_limits(&mut self) {
+        let role = self.role().await;
+        let session = &self.config.session;
+        self.idle_timeout = session.timeout_for(role.as_ref());
+        self.lifetime_deadline = match session.max_lifetime_for(role.as_ref()) {
+            0 => None,
+            secs => {
+                let age = DateTime::parse_from_rfc3339(&self.created_at)
+                    .ok()
+                    .and_then(|created| (Utc::now() - created.with_timezone(&Utc)).to_std().ok())
+                    .unwrap_or_default();
+                Some(Instant::now() + Duration::from_secs(secs).saturating_sub(age))
+            }
+        };
+    }
+
+    /// Role of the session's user, if known
+    ///
+    /// A session without a user name acts as the operator, an admin. Named
+    /// users are looked up with the configured provider, or among the
+    /// server's own users when it has none.
+    async fn role(&self) -> Option<UserRole> {
+        match (&self.client_name, self.server.auth_manager()) {
+            (None, _) => Some(UserRole::Admin),
+            (Some(name), Some(auth)) => auth
+                .get_user_by_username(name)
+                .await
+                .ok()
+                .flatten()
+                .map(|user| user.role),
+            (Some(name), None) => self
+                .server
+                .users()
+                .get_user_by_username(name)
+                .await
+                .map(|user| user.role),
+        }
+    }
+
+    /// Send the next keep-alive ping and start waiting for the answer
+    async fn send_keepalive(&mut self) -> std::result::Result<(), FrameError> {
+        self.pings_sent += 1;
+        let ping = Keepalive::new(self.pings_sent);
+        debug!("Sending keep-alive {} to session {}", ping.seq, self.id);
+        self.write_message(ping.ping_payload()).await?;
+
+        self.last_traffic = Instant::now();
+        self.ping_deadline = Some(
+            self.last_traffic + Duration::from_secs(self.config.session.keepalive_timeout_secs),
+        );
+        Ok(())
+    }
+
+    /// Tell the client why the server is closing the session
+    ///
+    /// Best effort: the session closes whether or not the frame is delivered.
+    async fn say_goodbye(&mut self, goodbye: Goodbye) {
+        info!("Session {} {}", self.id, goodbye);
+        if let Err(e) = self.write_message(goodbye.to_payload()).await {
+            debug!("Failed to send goodbye to session {}: {}", self.id, e);
+        }
+    }
+
+    /// Parse a request frame and dispatch it
+    ///
+    /// A batch is handled item by item, so a failing item only affects its own
+    /// response. Items served by the server's dispatcher run concurrently, at
+    /// most `max_inflight_rpcs` at a time; the rest wait for a slot.
+    async fn handle_frame(&mut self, frame: &[u8]) -> RpcReply {
+        let message: Value = match serde_json::from_slice(frame) {
+            Ok(message) => message,
+            Err(e) => {
+                return RpcReply::Single(RpcResponse::failure(
+                    Value::Null,
+                    RpcError::new(rpc::PARSE_ERROR, format!("Invalid request: {}", e)),
+                ))
+            }
+        };
+
+        match message {
+            Value::Array(items) if items.is_empty() => RpcReply::Single(RpcResponse::failure(
+                Value::Null,
+                RpcError::new(rpc::INVALID_REQUEST, "Empty batch"),
+            )),
+            Value::Array(items) => {
+                debug!("Session {} handling batch of {}", self.id, items.len());
+                let mut pending = Vec::with_capacity(items.len());
+                for item in items {
+                    pending.push(self.handle_request(item).await);
+                }
+                RpcReply::Batch(futures_util::future::join_all(pending).await)
+            }
+            message => RpcReply::Single(self.handle_request(message).await.await),
+        }
+    }
+
+    /// Dispatch a single request, returning a future of its response
+    ///
+    /// Methods bound to the session are handled before this returns. Methods
+    /// served by the dispatcher run when the returned future is polled, once
+    /// it has one of the session's in-flight slots.
+    async fn handle_request(&mut self, message: Value) -> BoxFuture<'static, RpcResponse> {
+        let id = message.get("id").cloned().unwrap_or(Value::Null);
+        let request: RpcRequest = match serde_json::from_value(message) {
+            Ok(request) => request,
+            Err(e) => {
+                let response = RpcResponse::failure(
+                    id,
+                    RpcError::new(rpc::INVALID_REQUEST, format!("Invalid request: {}", e)),
+                );
+                return Box::pin(std::future::ready(response));
+            }
+        };
+
+        debug!("Session {} handling {}", self.id, request.method);
+        self.server.update_summary(&self.id, |summary| {
+            summary.last_active = Utc::now().to_rfc3339();
+        });
+
+        let metrics = self.server.metrics().clone();
+        let handler = match self.dispatch(&request.method, request.params).await {
+            Dispatched::Done(result) => {
+                metrics.request_handled(result.is_err());
+                let response = RpcResponse::from_result(request.id, result);
+                return Box::pin(std::future::ready(response));
+            }
+            Dispatched::Deferred(handler) => handler,
+        };
+
+        let inflight = self.inflight.clone();
+        Box::pin(async move {
+            let _slot = match inflight {
+                Some(slots) => Some(slots.acquire_owned().await),
+                None => None,
+            };
+            let result = handler.await;
+            metrics.request_handled(result.is_err());
+            RpcResponse::from_result(request.id, result)
+        })
+    }
+
+    /// Route a request to its method handler
+    async fn dispatch(&mut self, method: &str, params: Value) -> Dispatched {
+        // A token revoked mid-session stops working at once, not on reconnect
+        if let Some(jti) = self
+            .token_id
+            .filter(|jti| self.server.tokens().is_revoked(jti))
+        {
+            debug!("Session {} used revoked token {}", self.id, jti);
+            return Dispatched::Done(Err(Error::from(TokenError::Revoked).into()));
+        }
+
+        // Deployments may disable methods or gate them behind a permission
+        if let Err(e) = dispatch::authorize(&self.server.config().rpc, method, &self.permissions) {
+            debug!("Session {} refused {}: {}", self.id, method, e.message);
+            return Dispatched::Done(Err(e));
+        }
+
+        let result = match method {
+            "session/info" => Ok(self.session_info()),
+            "apps/logs" => self.handle_app_logs(params).await,
+            "diag/set_log_level" => self.handle_set_log_level(params).await,
+            "events/recent" => self.handle_recent_events(params),
+            "sessions/kick" => self.handle_kick_session(params),
+            "cluster/announce" => self.handle_cluster_announce(params).await,
+            "cluster/peers" => self.handle_cluster_peers(),
+            "auth/whoami" => self.handle_whoami().await,
+            "auth/tokens/issue" => self.handle_issue_token(params),
+            "auth/tokens/list" => self.handle_list_tokens(),
+            "auth/tokens/revoke" => self.handle_revoke_token(params),
+            "diag/bench" => self.handle_bench(params).await,
+            _ => {
+                let handler = self
+                    .server
+                    .dispatcher()
+                    .call(method, params, self.rpc_context());
+                return Dispatched::Deferred(handler);
+            }
+        };
+        Dispatched::Done(result)
+    }
+
+    /// Handle `session/info`: the calling client's own session
+    fn session_info(&self) -> Value {
+        serde_json::json!({
+            "session_id": self.id,
+            "user": self.client_name,
+            "client_ip": self.client_ip(),
+            "client_port": self.client_port(),
+            "permissions": self.permissions,
+            "token_id": self.token_id,
+            "resume_token": self.resume_token,
+        })
+    }
+
+    /// Handle `cluster/announce`: note a peer daemon and return this one's health
+    async fn handle_cluster_announce(&self, params: Value) -> std::result::Result<Value, RpcError> {
+        self.require_permission(ANNOUNCE_PERMISSION)?;
+        let announcement: Announcement = rpc::parse_params(params)?;
+        debug!(
+            "Session {} announced cluster peer {}",
+            self.id, announcement.address
+        );
+        self.server.peers().record_announcement(announcement);
+
+        serde_json::to_value(self.server.health().await)
+            .map_err(|e| RpcError::new(rpc::INTERNAL_ERROR, e.to_string()))
+    }
+
+    /// Handle `cluster/peers`: what this daemon knows about its peers
+    fn handle_cluster_peers(&self) -> std::result::Result<Value, RpcError> {
+        self.require_permission(READ_CLUSTER_PERMISSION)?;
+        serde_json::to_value(self.server.peers().list())
+            .map_err(|e| RpcError::new(rpc::INTERNAL_ERROR, e.to_string()))
+    }
+
+    /// Handle `auth/whoami`: who the calling session is, and nobody else
+    async fn handle_whoami(&self) -> std::result::Result<Value, RpcError> {
+        // The token was checked at handshake, but may have expired since
+        let now = Utc::now().timestamp();
+        if self.token_exp.is_some_and(|exp| exp <= now) {
+            return Err(Error::from(TokenError::Expired).into());
+        }
+
+        let identity = Identity {
+            user: self.client_name.clone(),
+            role: self.role().await,
+            permissions: self.permissions.clone(),
+            token_id: self.token_id,
+            expires_at: self
+                .token_exp
+                .and_then(|exp| Utc.timestamp_opt(exp, 0).single()),
+        };
+
+        serde_json::to_value(identity)
+            .map_err(|e| RpcError::new(rpc::INTERNAL_ERROR, e.to_string()))
+    }
+
+    /// Handle `apps/logs`: recent output of a running application instance
+    async fn handle_app_logs(&mut self, params: Value) -> std::result::Result<Value, RpcError> {
+        #[derive(Deserialize)]
+        struct Params {
+            instance_id: Uuid,
+            #[serde(default = "default_log_lines")]
+            lines: usize,
+            #[serde(default)]
+            since: Option<u64>,
+        }
+
+        fn default_log_lines() -> usize {
+            100
+        }
+
+        let params: Params = rpc::parse_params(params)?;
+
+        // A session may always read the output of the instances it launched
+        if !tokens::permission_granted(&self.permissions, READ_APPS_PERMISSION) {
+            let launched = self.server.apps().list_in_session(&self.id).await;
+            let instance_id = params.instance_id.to_string();
+            if !launched.iter().any(|instance| instance.id == instance_id) {
+                self.require_permission(READ_APPS_PERMISSION)?;
+            }
+        }
+
+        let logs = self
+            .server
+            .apps()
+            .logs(&params.instance_id, params.lines, params.since)
+            .await?;
+
+        serde_json::to_value(logs).map_err(|e| RpcError::new(rpc::INTERNAL_ERROR, e.to_string()))
+    }
+
+    /// Handle `diag/set_log_level`: change the daemon log level at runtime
+    async fn handle_set_log_level(
+        &mut self,
+        params: Value,
+    ) -> std::result::Result<Value, RpcError> {
+        #[derive(Deserialize)]
+        struct Params {
+            level: String,
+        }
+
+        self.require_permission(LOG_LEVEL_PERMISSION)?;
+        let params: Params = rpc::parse_params(params)?;
+        let level = logging::parse_level(&params.level).ok_or_else(|| {
+            RpcError::invalid_params(format!(
+                "Invalid log level: {} (expected off, error, warn, info, debug or trace)",
+                params.level
+            ))
+        })?;
+
+        let previous = logging::set_level(level);
+        Ok(serde_json::json!({
+            "level": level.to_string().to_lowercase(),
+            "previous": previous.to_string().to_lowercase(),
+        }))
+    }
+
+    /// Handle `events/recent`: recent connection history, optionally filtered by type
+    fn handle_recent_events(&self, params: Value) -> std::result::Result<Value, RpcError> {
+        #[derive(Deserialize)]
+        struct Params {
+            #[serde(default = "default_event_count")]
+            count: usize,
+            #[serde(default, rename = "type")]
+            types: Vec<ServerEventType>,
+        }
+
+        fn default_event_count() -> usize {
+            50
+        }
+
+        self.require_permission(READ_EVENTS_PERMISSION)?;
+        let params: Params = rpc::parse_params(params)?;
+        let events = self.server.events().recent(params.count, &params.types);
+
+        serde_json::to_value(events).map_err(|e| RpcError::new(rpc::INTERNAL_ERROR, e.to_string()))
+    }
+
+    /// Handle `sessions/kick`: disconnect a session, telling it why
+    fn handle_kick_session(&self, params: Value) -> std::result::Result<Value, RpcError> {
+        #[derive(Deserialize)]
+        struct Params {
+            session_id: Uuid,
+            #[serde(default)]
+            reason: Option<String>,
+        }
+
+        self.require_permission(MANAGE_SESSIONS_PERMISSION)?;
+        let params: Params = rpc::parse_params(params)?;
+
+        let mut goodbye = Goodbye::new(DisconnectReason::Kicked);
+        if let Some(reason) = params.reason.filter(|r| !r.is_empty()) {
+            goodbye = goodbye.with_message(reason);
+        }
+        self.server.send_goodbye(&params.session_id, goodbye)?;
+        info!("Session {} kicked session {}", self.id, params.session_id);
+
+        Ok(serde_json::json!({ "session_id": params.session_id }))
+    }
+
+    /// Handle `auth/tokens/issue`: sign a new token for a user or client
+    ///
+    /// A session can only hand out permissions it holds itself, so an
+    /// `admin:tokens` token cannot mint one for `admin:*`.
+    fn handle_issue_token(&self, params: Value) -> std::result::Result<Value, RpcError> {
+        #[derive(Deserialize)]
+        struct Params {
+            subject: String,
+            #[serde(default)]
+            permissions: Vec<String>,
+            #[serde(default = "default_token_ttl")]
+            ttl_secs: u64,
+        }
+
+        fn default_token_ttl() -> u64 {
+            tokens::DEFAULT_TOKEN_TTL_SECS
+        }
+
+        self.require_permission(tokens::MANAGE_TOKENS_PERMISSION)?;
+        let params: Params = rpc::parse_params(params)?;
+        if params.subject.trim().is_empty() {
+            return Err(RpcError::invalid_params("subject must not be empty"));
+        }
+        if !(1..=tokens::MAX_TOKEN_TTL_SECS).contains(&params.ttl_secs) {
+            return Err(RpcError::invalid_params(format!(
+                "ttl_secs must be between 1 and {}",
+                tokens::MAX_TOKEN_TTL_SECS
+            )));
+        }
+        for permission in &params.permissions {
+            self.require_permission(permission)?;
+        }
+
+        let issued = self.server.tokens().issue_token(
+            &params.subject,
+            params.permissions,
+            Duration::from_secs(params.ttl_secs),
+        );
+        info!(
+            "Session {} issued token {} to {}",
+            self.id, issued.info.jti, issued.info.subject
+        );
+
+        serde_json::to_value(issued).map_err(|e| RpcError::new(rpc::INTERNAL_ERROR, e.to_string()))
+    }
+
+    /// Handle `auth/tokens/list`: issued tokens that have not yet expired
+    fn handle_list_tokens(&self) -> std::result::Result<Value, RpcError> {
+        self.require_permission(tokens::MANAGE_TOKENS_PERMISSION)?;
+        let tokens = self.server.tokens().list();
+
+        serde_json::to_value(tokens).map_err(|e| RpcError::new(rpc::INTERNAL_ERROR, e.to_string()))
+    }
+
+    /// Handle `auth/tokens/revoke`: reject a token from now on
+    fn handle_revoke_token(&self, params: Value) -> std::result::Result<Value, RpcError> {
+        #[derive(Deserialize)]
+        struct Params {
+            jti: Uuid,
+        }
+
+        self.require_permission(tokens::MANAGE_TOKENS_PERMISSION)?;
+        let params: Params = rpc::parse_params(params)?;
+        let token = self.server.tokens().revoke(&params.jti)?;
+        info!(
+            "Session {} revoked token {} of {}",
+            self.id, token.jti, token.subject
+        );
+
+        serde_json::to_value(token).map_err(|e| RpcError::new(rpc::INTERNAL_ERROR, e.to_string()))
+    }
+
+    /// Handle `diag/bench`: time auth validation against a provider, server-side
+    ///
+    /// With a `username` the configured provider is benchmarked with that
+    /// user's `password`; without one the in-process mock provider is.
+    async fn handle_bench(&self, params: Value) -> std::result::Result<Value, RpcError> {
+        #[derive(Deserialize)]
+        struct Params {
+            #[serde(default = "default_target")]
+            target: String,
+            #[serde(default = "default_iterations")]
+            iterations: usize,
+            #[serde(default)]
+            username: Option<String>,
+            #[serde(default)]
+            password: Option<String>,
+        }
+
+        fn default_target() -> String {
+            "auth".to_string()
+        }
+
+        fn default_iterations() -> usize {
+            bench::DEFAULT_BENCH_ITERATIONS
+        }
+
+        self.require_permission(bench::BENCH_PERMISSION)?;
+        let params: Params = rpc::parse_params(params)?;
+        if params.target != "auth" {
+            return Err(RpcError::invalid_params(format!(
+                "Unknown benchmark: {} (expected auth)",
+                params.target
+            )));
+        }
+        if params.iterations == 0 || params.iterations > bench::MAX_BENCH_ITERATIONS {
+            return Err(RpcError::invalid_params(format!(
+                "Iterations must be between 1 and {}",
+                bench::MAX_BENCH_ITERATIONS
+            )));
+        }
+
+        info!(
+            "Session {} running {} auth benchmark iterations",
+            self.id, params.iterations
+        );
+        let report = match params.username {
+            Some(username) => {
+                let manager = self.server.auth_manager().cloned().ok_or_else(|| {
+                    RpcError::invalid_params(
+                        "No auth provider is configured; leave out the user to benchmark the mock provider",
+                    )
+                })?;
+                let password = params.password.unwrap_or_default();
+                bench::bench_auth(&manager, &username, password.as_bytes(), params.iterations).await
+            }
+            None => bench::bench_mock_auth(params.iterations)
+                .await
+                .map_err(|e| RpcError::new(rpc::INTERNAL_ERROR, e.to_string()))?,
+        };
+
+        serde_json::to_value(report).map_err(|e| RpcError::new(rpc::INTERNAL_ERROR, e.to_string()))
+    }
+
+    /// The session as seen by handlers registered with the server's dispatcher
+    fn rpc_context(&self) -> RpcContext {
+        RpcContext {
+            server: self.server.clone(),
+            session_id: self.id,
+            client_name: self.client_name.clone(),
+            permissions: self.permissions.clone(),
+        }
+    }
+
+    /// Fail with a permission error unless the session holds `permission`
+    fn require_permission(&self, permission: &str) -> std::result::Result<(), RpcError> {
+        dispatch::require_permission(&self.permissions, permission)
+    }
+
+    /// Show who the session authenticated as in `sessions/list`
+    fn publish_identity(&self) {
+        let user = self.client_name.clone();
+        let token_id = self.token_id;
+        let expires_at = self
+            .token_exp
+            .and_then(|exp| Utc.timestamp_opt(exp, 0).single())
+            .map(|time| time.to_rfc3339());
+
+        self.server.update_summary(&self.id, |summary| {
+            if let Some(user) = user {
+                summary.username = user;
+            }
+            summary.user_id = token_id.map(|jti| jti.to_string()).unwrap_or_default();
+            summary.expires_at = expires_at.unwrap_or_default();
+            summary.active = true;
+        });
+    }
+
+    /// The resume token and identity to hold, if the connection dropped resumably
+    pub(crate) fn held_session(&self) -> Option<(String, HeldSession)> {
+        if !self.resumable {
+            return None;
+        }
+
+        let token = self.resume_token.clone()?;
+        let held = HeldSession {
+            session_id: self.id,
+            created_at: self.created_at.clone(),
+            client_name: self.client_name.clone(),
+            permissions: self.permissions.clone(),
+            token_id: self.token_id,
+            token_exp: self.token_exp,
+        };
+        Some((token, held))
+    }
+
+    /// Record a server event for this session
+    fn record_event(&self, event_type: ServerEventType) {
+        self.server.events().record(
+            event_type,
+            Some(self.id),
+            self.client_name.clone(),
+            Some(self.client_ip().to_string()),
+        );
+    }
+
     /// Handle initial protocol handshake
     async fn handle_handshake(&mut self) -> Result<()> {
         debug!("Handling handshake");
 
-        // Here would be the implementation of the protocol handshake
-        // For brevity, I'm providing a simplified version
+        // Non-RCP clients fail the magic check at once; silent ones time out
+        let handshake_timeout = Duration::from_millis(self.config.session.handshake_timeout_ms);
+        let (hello, compressor, proof) = match tokio::time::timeout(
+            handshake_timeout,
+            handshake::server_handshake_with_challenge(
+                &mut self.framed,
+                &self.config.compression,
+                self.config.compression_threshold,
+                &self.challenge,
+            ),
+        )
+        .await
+        {
+            Ok(Ok(result)) => result,
+            Ok(Err(FrameError::BadMagic)) => {
+                debug!(
+                    "Session {} from {} is not an RCP client, closing",
+                    self.id, self.peer_addr
+                );
+                return Err(FrameError::BadMagic.into());
+            }
+            Ok(Err(e)) => return Err(e.into()),
+            Err(_) => {
+                return Err(Error::Protocol(format!(
+                    "Handshake not completed within {} ms",
+                    self.config.session.handshake_timeout_ms
+                )))
+            }
+        };
+
+        match compressor.algorithm() {
+            Some(algorithm) => debug!("Session {} negotiated {} compression", self.id, algorithm),
+            None => debug!("Session {} using uncompressed frames", self.id),
+        }
+        self.compressor = compressor;
+        self.credential = hello.auth;
+        self.proof = proof;
+        self.resume = hello.resume;
 
         self.state = ConnectionState::Authenticated;
         Ok(())
     }
 
+    /// Read the next message, undoing any negotiated compression
+    async fn read_message(&mut self) -> std::result::Result<Bytes, FrameError> {
+        let frame = codec::read_frame(&mut self.framed).await?;
+        self.compressor.decode(frame)
+    }
+
+    /// Write a message, applying any negotiated compression
+    async fn write_message(&mut self, payload: Bytes) -> std::result::Result<(), FrameError> {
+        let frame = self.compressor.encode(payload)?;
+        codec::write_frame(&mut self.framed, frame).await
+    }
+
     /// Handle authentication
     async fn authenticate(&mut self) -> Result<()> {
         debug!("Authenticating client");
 
+        // Only a token's own claims, or the operator's key, grant anything
+        self.permissions = Vec::new();
+
         if !self.config.auth.required {
             debug!("Authentication not required");
+            self.permissions = vec![tokens::OPERATOR_PERMISSION.to_string()];
             self.state = ConnectionState::Authenticated;
             return Ok(());
         }
 
-        // Here would be the actual authentication implementation
-        // For brevity, I'm providing a simplified version
+        // Checked before the credentials, so a limited IP learns nothing from them
+        self.refuse_if_rate_limited().await?;
+        let peer_ip = self.client_ip();
+        let limiter = self.server.auth_rate_limiter().clone();
+
+        let psk = self.config.auth.psk.as_deref();
+        if psk.is_some_and(|psk| self.presented_psk(psk)) {
+            if !self.config.auth.allows_method(PSK_METHOD) {
+                limiter.record_failure(peer_ip);
+                return self
+                    .reject_unauthenticated("Authentication method 'psk' is not allowed")
+                    .await;
+            }
+            self.permissions = vec![tokens::OPERATOR_PERMISSION.to_string()];
+        } else {
+            // Anything that is not shaped like a token is checked as a key
+            let verified = match self.credential.as_deref() {
+                Some(credential) => self.server.tokens().verify_token(credential),
+                None => Err(TokenError::Malformed),
+            };
+
+            let reason = match verified {
+                Ok(_) if !self.config.auth.allows_method(TOKEN_METHOD) => {
+                    Some("Authentication method 'token' is not allowed".to_string())
+                }
+                Ok(claims) => {
+                    debug!(
+                        "Session {} authenticated with token {}",
+                        self.id, claims.jti
+                    );
+                    self.token_id = Some(claims.jti);
+                    self.token_exp = Some(claims.exp);
+                    self.client_name = Some(claims.sub);
+                    self.permissions = claims.permissions;
+                    None
+                }
+                Err(TokenError::Malformed) if psk.is_none() => {
+                    Some("Invalid or missing auth token".to_string())
+                }
+                Err(TokenError::Malformed)
+                    if self.config.auth.require_psk_proof && self.sent_plain_psk(psk) =>
+                {
+                    Some(
+                        "Pre-shared key must be proven against the handshake challenge".to_string(),
+                    )
+                }
+                Err(TokenError::Malformed) => Some("Invalid or missing pre-shared key".to_string()),
+                Err(e) => Some(e.to_string()),
+            };
+
+            if let Some(reason) = reason {
+                limiter.record_failure(peer_ip);
+                return self.reject_unauthenticated(&reason).await;
+            }
+        }
 
         self.state = ConnectionState::Authenticated;
         Ok(())
     }
 
+    /// Whether the client proved it holds `psk`, or sent it as-is where that is allowed
+    ///
+    /// A proof only counts for this connection's challenge, so one replayed
+    /// from an earlier connection fails.
+    fn presented_psk(&self, psk: &str) -> bool {
+        match &self.proof {
+            Some(proof) => handshake::verify_challenge_proof(psk, &self.challenge, proof),
+            None => !self.config.auth.require_psk_proof && self.sent_plain_psk(Some(psk)),
+        }
+    }
+
+    /// Whether the client sent `psk` itself as its credential
+    fn sent_plain_psk(&self, psk: Option<&str>) -> bool {
+        match (psk, self.credential.as_deref()) {
+            (Some(psk), Some(credential)) => handshake::verify_plain_key(psk, credential),
+            _ => false,
+        }
+    }
+
+    /// Reattach to the held session `token` resumes, taking over its ID and identity
+    async fn resume_session(&mut self, token: &str) -> Result<()> {
+        debug!("Session {} resuming a held session", self.id);
+
+        // A resume token is a credential, so guessing one is limited the same way
+        self.refuse_if_rate_limited().await?;
+        let peer_ip = self.client_ip();
+        let held = match self.server.resumption().take(token) {
+            Ok(held) => held,
+            Err(e) => {
+                self.server.auth_rate_limiter().record_failure(peer_ip);
+                return self.reject_unauthenticated(&e.to_string()).await;
+            }
+        };
+
+        // The session's token may have run out or been revoked while it was held
+        let now = Utc::now().timestamp();
+        if held.token_exp.is_some_and(|exp| exp <= now) {
+            return self
+                .reject_unauthenticated(&TokenError::Expired.to_string())
+                .await;
+        }
+        if held
+            .token_id
+            .is_some_and(|jti| self.server.tokens().is_revoked(&jti))
+        {
+            return self
+                .reject_unauthenticated(&TokenError::Revoked.to_string())
+                .await;
+        }
+
+        self.server
+            .adopt_session_id(&self.id, held.session_id, &held.created_at)
+            .await;
+        info!("Session {} resumed session {}", self.id, held.session_id);
+        self.id = held.session_id;
+        self.created_at = held.created_at;
+        self.client_name = held.client_name;
+        self.permissions = held.permissions;
+        self.token_id = held.token_id;
+        self.token_exp = held.token_exp;
+
+        self.state = ConnectionState::Authenticated;
+        Ok(())
+    }
+
+    /// Close the session if its IP has failed authentication too often
+    async fn refuse_if_rate_limited(&mut self) -> Result<()> {
+        let peer_ip = self.client_ip();
+        if !self.server.auth_rate_limiter().is_limited(peer_ip) {
+            return Ok(());
+        }
+
+        info!(
+            "Session {} closed: too many failed authentication attempts from {}",
+            self.id, peer_ip
+        );
+        tokio::time::sleep(Duration::from_millis(self.config.auth_tarpit_delay_ms)).await;
+        self.state = ConnectionState::Closed;
+        Err(Error::Authentication(
+            "Too many failed authentication attempts".to_string(),
+        ))
+    }
+
+    /// Answer a client that failed authentication according to `auth_failure_behavior`
+    ///
+    /// Always returns an authentication error so the session is closed afterwards.
+    async fn reject_unauthenticated(&mut self, reason: &str) -> Result<()> {
+        info!("Session {} failed authentication: {}", self.id, reason);
+
+        match self.config.auth_failure_behavior {
+            AuthFailureBehavior::RejectWithReason => {
+                // Answer the first request so the client sees why it was refused
+                if let Ok(Ok(frame)) =
+                    tokio::time::timeout(AUTH_REJECT_READ_TIMEOUT, self.read_message()).await
+                {
+                    let id = serde_json::from_slice::<Value>(&frame)
+                        .ok()
+                        .and_then(|message| message.get("id").cloned())
+                        .unwrap_or(Value::Null);
+                    let response =
+                        RpcResponse::failure(id, Error::Authentication(reason.to_string()).into());
+                    if let Ok(data) = serde_json::to_vec(&response) {
+                        let _ = self.write_message(Bytes::from(data)).await;
+                    }
+                }
+            }
+            AuthFailureBehavior::SilentClose => {}
+            AuthFailureBehavior::Tarpit => {
+                tokio::time::sleep(Duration::from_millis(self.config.auth_tarpit_delay_ms)).await;
+            }
+        }
+
+        self.state = ConnectionState::Closed;
+        Err(Error::Authentication(reason.to_string()))
+    }
+
     /// Disconnect the session
     pub async fn disconnect(&mut self) -> Result<()> {
         info!("Disconnecting session: {}", self.id);
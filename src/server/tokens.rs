@@ -0,0 +1,431 @@
+//! Issued auth tokens and their revocation
+//!
+//! A token is a self-contained credential, `<claims>.<signature>`, with both
+//! parts base64url-encoded and the claims signed by HMAC-SHA256 under
+//! `auth.token_secret`. The [`TokenRegistry`] keeps the metadata of every token
+//! issued or presented since startup, so admins can list them, and a
+//! revocation set that [`TokenRegistry::verify_token`] consults before
+//! accepting one.
+//!
+//! Revocations are written to `auth.revocation_file` when one is configured,
+//! so a revoked token stays rejected across restarts for as long as it would
+//! otherwise be valid. Entries are dropped once their token has expired, since
+//! expiry alone rejects it from then on.
+
+use crate::files::{self, PRIVATE_FILE_MODE};
+use crate::server::config::AuthConfig;
+use crate::server::error::{Error, Result};
+use crate::server::user::UserRole;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, TimeZone, Utc};
+use hmac::{Hmac, Mac};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use uuid::Uuid;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Permission needed to issue, list and revoke tokens
+pub const MANAGE_TOKENS_PERMISSION: &str = "admin:tokens";
+
+/// Lifetime of tokens issued over RPC when the caller does not ask for one
+pub const DEFAULT_TOKEN_TTL_SECS: u64 = 3600;
+
+/// Longest lifetime a token issued over RPC may have
+pub const MAX_TOKEN_TTL_SECS: u64 = 90 * 24 * 3600;
+
+/// Permission held by sessions that did not authenticate with a token
+///
+/// Those clients presented the pre-shared key, or the server needs none, so
+/// they act as the operator and may do anything.
+pub const OPERATOR_PERMISSION: &str = "admin:*";
+
+/// Permission of observers, such as monitoring integrations
+///
+/// It grants every `read:` permission: status, sessions, metrics, events,
+/// configuration and cluster peers, but none of the `admin:` ones that
+/// change anything.
+pub const OBSERVER_PERMISSION: &str = "read:*";
+
+/// Whether `granted` includes `required`, directly or through a `prefix:*` wildcard
+///
+/// Holding `admin:<area>` also grants `read:<area>`, so admins never need
+/// the observer permission on top.
+pub fn permission_granted(granted: &[String], required: &str) -> bool {
+    let grants = |required: &str| {
+        granted.iter().any(|permission| {
+            permission == required
+                || permission
+                    .strip_suffix(":*")
+                    .is_some_and(|prefix| required.starts_with(&format!("{}:", prefix)))
+        })
+    };
+
+    grants(required)
+        || required
+            .strip_prefix("read:")
+            .is_some_and(|area| grants(&format!("admin:{}", area)))
+}
+
+/// Whether `credential` is shaped like an issued token rather than a key
+///
+/// Only the shape is checked, not the signature, so a client can tell which
+/// credentials to send as-is and which to prove against the handshake challenge.
+pub fn looks_like_token(credential: &str) -> bool {
+    credential
+        .split_once('.')
+        .is_some_and(|(payload, signature)| {
+            URL_SAFE_NO_PAD.decode(signature).is_ok()
+                && URL_SAFE_NO_PAD
+                    .decode(payload)
+                    .ok()
+                    .and_then(|json| serde_json::from_slice::<TokenClaims>(&json).ok())
+                    .is_some()
+        })
+}
+
+/// Claims carried by a signed token
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenClaims {
+    /// Unique token ID, used to revoke it
+    pub jti: Uuid,
+
+    /// User or client the token was issued to
+    pub sub: String,
+
+    /// Permissions granted to sessions using the token
+    #[serde(default)]
+    pub permissions: Vec<String>,
+
+    /// Issue time, in seconds since the Unix epoch
+    pub iat: i64,
+
+    /// Expiry time, in seconds since the Unix epoch
+    pub exp: i64,
+}
+
+/// Metadata of a known token, as listed by `auth/tokens/list`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenInfo {
+    /// Unique token ID
+    pub jti: Uuid,
+
+    /// User or client the token was issued to
+    pub subject: String,
+
+    /// Permissions granted by the token
+    pub permissions: Vec<String>,
+
+    /// When the token was issued
+    pub issued_at: DateTime<Utc>,
+
+    /// When the token expires
+    pub expires_at: DateTime<Utc>,
+
+    /// Whether the token has been revoked
+    pub revoked: bool,
+}
+
+/// A newly issued token and its metadata, as returned by `auth/tokens/issue`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IssuedToken {
+    /// The token itself, to be handed to the client it was issued for
+    pub token: String,
+
+    /// Metadata of the token, as `auth/tokens/list` shows it
+    #[serde(flatten)]
+    pub info: TokenInfo,
+}
+
+/// Who a session is, as reported to that session by `auth/whoami`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Identity {
+    /// User or client the session acts as, `None` for the operator
+    pub user: Option<String>,
+
+    /// Role of the user, if it is known
+    pub role: Option<UserRole>,
+
+    /// Permissions held by the session
+    pub permissions: Vec<String>,
+
+    /// ID of the token the session authenticated with, if any
+    pub token_id: Option<Uuid>,
+
+    /// When that token expires
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Reason a presented token was not accepted
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum TokenError {
+    /// The credential is not shaped like a token at all
+    #[error("Malformed token")]
+    Malformed,
+
+    /// The signature does not match the claims
+    #[error("Invalid token signature")]
+    BadSignature,
+
+    /// The token is past its expiry time
+    #[error("Token has expired")]
+    Expired,
+
+    /// The token was revoked by an admin
+    #[error("Token has been revoked")]
+    Revoked,
+}
+
+impl From<TokenError> for Error {
+    fn from(error: TokenError) -> Self {
+        Error::Authentication(error.to_string())
+    }
+}
+
+#[derive(Debug, Default)]
+struct TokenState {
+    /// Known tokens by ID
+    known: HashMap<Uuid, TokenClaims>,
+
+    /// Revoked token IDs and the expiry of the token each one names
+    revoked: BTreeMap<Uuid, i64>,
+}
+
+impl TokenState {
+    /// Forget tokens and revocations that have expired
+    fn prune(&mut self, now: i64) {
+        self.known.retain(|_, claims| claims.exp > now);
+        self.revoked.retain(|_, &mut exp| exp > now);
+    }
+}
+
+/// Registry of issued tokens and the revocation set, shared by all sessions
+#[derive(Debug, Clone)]
+pub struct TokenRegistry {
+    key: Arc<Vec<u8>>,
+    revocation_file: Option<PathBuf>,
+    state: Arc<Mutex<TokenState>>,
+}
+
+impl TokenRegistry {
+    /// Create a registry from the server's auth configuration
+    ///
+    /// Without a `token_secret` a random key is used, so tokens only stay
+    /// valid until the server restarts. Revocations are loaded from the
+    /// `revocation_file`, if there is one.
+    pub fn new(config: &AuthConfig) -> Self {
+        let key = match &config.token_secret {
+            Some(secret) => secret.as_bytes().to_vec(),
+            None => [*Uuid::new_v4().as_bytes(), *Uuid::new_v4().as_bytes()].concat(),
+        };
+
+        let revocation_file = config.revocation_file.as_ref().map(PathBuf::from);
+        let mut state = TokenState::default();
+        if let Some(path) = &revocation_file {
+            state.revoked = load_revocations(path);
+            state.prune(Utc::now().timestamp());
+            debug!(
+                "Loaded {} token revocations from {}",
+                state.revoked.len(),
+                path.display()
+            );
+        }
+
+        Self {
+            key: Arc::new(key),
+            revocation_file,
+            state: Arc::new(Mutex::new(state)),
+        }
+    }
+
+    /// Issue a token for `subject`, valid for `ttl`
+    pub fn issue(&self, subject: &str, permissions: Vec<String>, ttl: Duration) -> String {
+        self.issue_token(subject, permissions, ttl).token
+    }
+
+    /// Issue a token like [`issue`](Self::issue), returning its metadata too
+    pub fn issue_token(
+        &self,
+        subject: &str,
+        permissions: Vec<String>,
+        ttl: Duration,
+    ) -> IssuedToken {
+        let iat = Utc::now().timestamp();
+        let claims = TokenClaims {
+            jti: Uuid::new_v4(),
+            sub: subject.to_string(),
+            permissions,
+            iat,
+            exp: iat.saturating_add(ttl.as_secs().try_into().unwrap_or(i64::MAX)),
+        };
+
+        // Claims are plain data and always serialize
+        let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&claims).unwrap_or_default());
+        let signature = URL_SAFE_NO_PAD.encode(self.sign(payload.as_bytes()));
+
+        let info = token_info(&claims, false);
+        self.lock().known.insert(claims.jti, claims);
+        IssuedToken {
+            token: format!("{}.{}", payload, signature),
+            info,
+        }
+    }
+
+    /// Check a presented token, returning its claims if it may be used
+    ///
+    /// A valid token issued before a restart is registered again here, so it
+    /// shows up in [`list`](Self::list) and can be revoked once it is used.
+    pub fn verify_token(&self, token: &str) -> std::result::Result<TokenClaims, TokenError> {
+        let (payload, signature) = token.split_once('.').ok_or(TokenError::Malformed)?;
+        let signature = URL_SAFE_NO_PAD
+            .decode(signature)
+            .map_err(|_| TokenError::Malformed)?;
+        let claims: TokenClaims = URL_SAFE_NO_PAD
+            .decode(payload)
+            .ok()
+            .and_then(|json| serde_json::from_slice(&json).ok())
+            .ok_or(TokenError::Malformed)?;
+
+        let mut mac = self.mac();
+        mac.update(payload.as_bytes());
+        mac.verify_slice(&signature)
+            .map_err(|_| TokenError::BadSignature)?;
+
+        let now = Utc::now().timestamp();
+        if claims.exp <= now {
+            return Err(TokenError::Expired);
+        }
+
+        let mut state = self.lock();
+        if state.revoked.contains_key(&claims.jti) {
+            return Err(TokenError::Revoked);
+        }
+        state
+            .known
+            .entry(claims.jti)
+            .or_insert_with(|| claims.clone());
+
+        Ok(claims)
+    }
+
+    /// Unexpired known tokens, oldest first
+    pub fn list(&self) -> Vec<TokenInfo> {
+        let mut state = self.lock();
+        state.prune(Utc::now().timestamp());
+
+        let mut tokens: Vec<TokenInfo> = state
+            .known
+            .values()
+            .map(|claims| token_info(claims, state.revoked.contains_key(&claims.jti)))
+            .collect();
+        tokens.sort_by_key(|token| (token.issued_at, token.jti));
+        tokens
+    }
+
+    /// Revoke a known token so it is rejected from now on
+    ///
+    /// The revocation set is saved before returning, so a failed write is
+    /// reported rather than lost on the next restart.
+    pub fn revoke(&self, jti: &Uuid) -> Result<TokenInfo> {
+        let mut state = self.lock();
+        state.prune(Utc::now().timestamp());
+
+        let claims = state
+            .known
+            .get(jti)
+            .cloned()
+            .ok_or_else(|| Error::NotFound(format!("Token {} not found", jti)))?;
+
+        if state.revoked.insert(claims.jti, claims.exp).is_none() {
+            if let Some(path) = &self.revocation_file {
+                if let Err(e) = save_revocations(path, &state.revoked) {
+                    state.revoked.remove(&claims.jti);
+                    return Err(Error::Internal(format!(
+                        "Failed to save token revocations to {}: {}",
+                        path.display(),
+                        e
+                    )));
+                }
+            }
+        }
+
+        Ok(token_info(&claims, true))
+    }
+
+    /// Whether a token ID is in the revocation set
+    pub fn is_revoked(&self, jti: &Uuid) -> bool {
+        self.lock().revoked.contains_key(jti)
+    }
+
+    fn mac(&self) -> HmacSha256 {
+        // HMAC accepts keys of any length
+        HmacSha256::new_from_slice(&self.key).expect("HMAC key of any length")
+    }
+
+    fn sign(&self, payload: &[u8]) -> Vec<u8> {
+        let mut mac = self.mac();
+        mac.update(payload);
+        mac.finalize().into_bytes().to_vec()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, TokenState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn token_info(claims: &TokenClaims, revoked: bool) -> TokenInfo {
+    let time = |secs: i64| Utc.timestamp_opt(secs, 0).single().unwrap_or_default();
+    TokenInfo {
+        jti: claims.jti,
+        subject: claims.sub.clone(),
+        permissions: claims.permissions.clone(),
+        issued_at: time(claims.iat),
+        expires_at: time(claims.exp),
+        revoked,
+    }
+}
+
+/// Read a revocation file, treating a missing or unreadable one as empty
+fn load_revocations(path: &Path) -> BTreeMap<Uuid, i64> {
+    match std::fs::read_to_string(path) {
+        Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+            warn!(
+                "Ignoring invalid token revocation file {}: {}",
+                path.display(),
+                e
+            );
+            BTreeMap::new()
+        }),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+        Err(e) => {
+            warn!(
+                "Failed to read token revocation file {}: {}",
+                path.display(),
+                e
+            );
+            BTreeMap::new()
+        }
+    }
+}
+
+/// Write the revocation set, replacing the file atomically
+fn save_revocations(path: &Path, revoked: &BTreeMap<Uuid, i64>) -> std::io::Result<()> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let data = serde_json::to_vec_pretty(revoked)?;
+    let tmp = path.with_extension("tmp");
+    let written = files::write_with_mode(&tmp, data, PRIVATE_FILE_MODE)
+        .and_then(|()| std::fs::rename(&tmp, path));
+    if written.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+    written
+}
@@ -1,20 +1,35 @@
+use crate::files::{self, PRIVATE_FILE_MODE};
+use crate::server::config::AuthConfig;
 use crate::server::error::{Error, Result};
+use log::{debug, warn};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use uuid::Uuid;
 
+/// Permission needed to export and import users
+pub const MANAGE_USERS_PERMISSION: &str = "admin:users";
+
+/// Version of the user export format written by this build
+pub const USER_EXPORT_VERSION: u32 = 1;
+
 /// User role types
+///
+/// Config files may spell roles in lowercase, as `FromStr` accepts them.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub enum UserRole {
     /// Administrator with full access
+    #[serde(alias = "admin")]
     Admin,
 
     /// Regular user with limited access
+    #[serde(alias = "user")]
     User,
 
     /// Guest user with restricted access
+    #[serde(alias = "guest")]
     Guest,
 }
 
@@ -58,8 +73,8 @@ pub struct User {
     /// User's email (optional)
     pub email: Option<String>,
 
-    /// Password hash
-    #[serde(skip_serializing)]
+    /// Password hash, never sent to clients
+    #[serde(skip_serializing, default)]
     pub password_hash: String,
 
     /// User role
@@ -72,9 +87,129 @@ pub struct User {
     pub updated_at: String,
 }
 
+/// Changes `users/update` makes to a user, leaving fields that are `None` alone
+///
+/// An empty full name or email clears it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UserUpdate {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub full_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<UserRole>,
+}
+
+impl UserUpdate {
+    /// Whether the update changes nothing
+    pub fn is_empty(&self) -> bool {
+        self.full_name.is_none() && self.email.is_none() && self.role.is_none()
+    }
+
+    /// Apply the update to `user`, bumping its `updated_at`
+    pub fn apply(self, user: &mut User) {
+        let cleared = |value: String| (!value.is_empty()).then_some(value);
+        if let Some(full_name) = self.full_name {
+            user.full_name = cleared(full_name);
+        }
+        if let Some(email) = self.email {
+            user.email = cleared(email);
+        }
+        if let Some(role) = self.role {
+            user.role = role;
+        }
+        user.updated_at = chrono::Utc::now().to_rfc3339();
+    }
+}
+
+/// A user as written to an export file, password hash included
+///
+/// Only the hash is kept, never a plaintext password, but the file should
+/// still be handled as a secret.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportedUser {
+    pub id: Uuid,
+    pub username: String,
+    #[serde(default)]
+    pub full_name: Option<String>,
+    #[serde(default)]
+    pub email: Option<String>,
+    pub password_hash: String,
+    pub role: UserRole,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl From<User> for ExportedUser {
+    fn from(user: User) -> Self {
+        Self {
+            id: user.id,
+            username: user.username,
+            full_name: user.full_name,
+            email: user.email,
+            password_hash: user.password_hash,
+            role: user.role,
+            created_at: user.created_at,
+            updated_at: user.updated_at,
+        }
+    }
+}
+
+impl From<ExportedUser> for User {
+    fn from(user: ExportedUser) -> Self {
+        Self {
+            id: user.id,
+            username: user.username,
+            full_name: user.full_name,
+            email: user.email,
+            password_hash: user.password_hash,
+            role: user.role,
+            created_at: user.created_at,
+            updated_at: user.updated_at,
+        }
+    }
+}
+
+/// Users exported by `users/export`, to be restored with `users/import`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UserExport {
+    /// Format version, [`USER_EXPORT_VERSION`] when written by this build
+    pub version: u32,
+
+    /// When the export was taken, RFC 3339
+    pub exported_at: String,
+
+    /// The exported users, sorted by username
+    pub users: Vec<ExportedUser>,
+}
+
+/// What an import does with a user whose username already exists
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportMode {
+    /// Keep the existing user and skip the imported one
+    #[default]
+    Merge,
+
+    /// Replace the existing user with the imported one
+    Replace,
+}
+
+/// Usernames an import added, replaced and skipped
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImportReport {
+    pub added: Vec<String>,
+    pub replaced: Vec<String>,
+    pub skipped: Vec<String>,
+}
+
 /// Manager for user operations
+///
+/// With a users file every change is saved to it before it takes effect, in
+/// the export format, so the internal provider's users survive a restart.
 pub struct UserManager {
     users: Arc<RwLock<HashMap<Uuid, User>>>,
+    file: Option<PathBuf>,
 }
 
 impl UserManager {
@@ -82,9 +217,48 @@ impl UserManager {
     pub fn new() -> Self {
         Self {
             users: Arc::new(RwLock::new(HashMap::new())),
+            file: None,
+        }
+    }
+
+    /// Create a user manager from the server's auth configuration
+    ///
+    /// Users are loaded from the `users_file`, if there is one; without it
+    /// they are kept in memory only.
+    pub fn from_config(config: &AuthConfig) -> Self {
+        let file = config.users_file.as_ref().map(PathBuf::from);
+        let mut users = HashMap::new();
+        if let Some(path) = &file {
+            users = load_users(path);
+            debug!("Loaded {} users from {}", users.len(), path.display());
+        }
+
+        Self {
+            users: Arc::new(RwLock::new(users)),
+            file,
         }
     }
 
+    /// Apply `change` to a copy of the users, saving it before it replaces them
+    ///
+    /// A failed write leaves the users as they were.
+    async fn modify<T>(
+        &self,
+        change: impl FnOnce(&mut HashMap<Uuid, User>) -> Result<T>,
+    ) -> Result<T> {
+        let mut users = self.users.write().await;
+        let mut updated = users.clone();
+        let result = change(&mut updated)?;
+
+        if let Some(path) = &self.file {
+            save_users(path, &updated).map_err(|e| {
+                Error::Internal(format!("Failed to save users to {}: {}", path.display(), e))
+            })?;
+        }
+        *users = updated;
+        Ok(result)
+    }
+
     /// Get a user by ID
     pub async fn get_user(&self, id: &Uuid) -> Option<User> {
         let users = self.users.read().await;
@@ -104,47 +278,50 @@ impl UserManager {
 
     /// Add a new user
     pub async fn add_user(&self, user: User) -> Result<()> {
-        let mut users = self.users.write().await;
-
-        // Check if username already exists
-        for existing in users.values() {
-            if existing.username == user.username {
-                return Err(Error::AlreadyExists(format!(
-                    "User with username '{}' already exists",
-                    user.username
-                )));
+        self.modify(|users| {
+            // Check if username already exists
+            for existing in users.values() {
+                if existing.username == user.username {
+                    return Err(Error::AlreadyExists(format!(
+                        "User with username '{}' already exists",
+                        user.username
+                    )));
+                }
             }
-        }
 
-        users.insert(user.id, user);
-        Ok(())
+            users.insert(user.id, user);
+            Ok(())
+        })
+        .await
     }
 
     /// Update a user
     pub async fn update_user(&self, user: User) -> Result<()> {
-        let mut users = self.users.write().await;
-
-        // Check if user exists
-        if !users.contains_key(&user.id) {
-            return Err(Error::NotFound(format!(
-                "User with ID '{}' not found",
-                user.id
-            )));
-        }
+        self.modify(|users| {
+            // Check if user exists
+            if !users.contains_key(&user.id) {
+                return Err(Error::NotFound(format!(
+                    "User with ID '{}' not found",
+                    user.id
+                )));
+            }
 
-        users.insert(user.id, user);
-        Ok(())
+            users.insert(user.id, user);
+            Ok(())
+        })
+        .await
     }
 
     /// Remove a user
     pub async fn remove_user(&self, id: &Uuid) -> Result<()> {
-        let mut users = self.users.write().await;
-
-        if users.remove(id).is_none() {
-            return Err(Error::NotFound(format!("User with ID '{}' not found", id)));
-        }
+        self.modify(|users| {
+            if users.remove(id).is_none() {
+                return Err(Error::NotFound(format!("User with ID '{}' not found", id)));
+            }
 
-        Ok(())
+            Ok(())
+        })
+        .await
     }
 
     /// List all users
@@ -152,6 +329,115 @@ impl UserManager {
         let users = self.users.read().await;
         users.values().cloned().collect()
     }
+
+    /// Export every user, password hashes and roles included
+    pub async fn export(&self) -> UserExport {
+        export_of(&*self.users.read().await)
+    }
+
+    /// Restore users from an export
+    ///
+    /// A user whose username or ID already exists is skipped with
+    /// [`ImportMode::Merge`] and takes the existing user's place with
+    /// [`ImportMode::Replace`]. Nothing is imported if the export is of an
+    /// unknown version or names a user twice.
+    pub async fn import(&self, export: UserExport, mode: ImportMode) -> Result<ImportReport> {
+        if export.version != USER_EXPORT_VERSION {
+            return Err(Error::InvalidArgument(format!(
+                "Unsupported user export version {} (expected {})",
+                export.version, USER_EXPORT_VERSION
+            )));
+        }
+
+        let (mut usernames, mut ids) = (HashSet::new(), HashSet::new());
+        for user in &export.users {
+            if !usernames.insert(user.username.as_str()) || !ids.insert(user.id) {
+                return Err(Error::InvalidArgument(format!(
+                    "User '{}' appears more than once in the export",
+                    user.username
+                )));
+            }
+        }
+
+        self.modify(|users| {
+            let mut report = ImportReport::default();
+            for user in export.users {
+                let existing: Vec<Uuid> = users
+                    .values()
+                    .filter(|existing| existing.username == user.username || existing.id == user.id)
+                    .map(|existing| existing.id)
+                    .collect();
+
+                if existing.is_empty() {
+                    report.added.push(user.username.clone());
+                } else if mode == ImportMode::Merge {
+                    report.skipped.push(user.username);
+                    continue;
+                } else {
+                    for id in existing {
+                        users.remove(&id);
+                    }
+                    report.replaced.push(user.username.clone());
+                }
+                users.insert(user.id, user.into());
+            }
+
+            Ok(report)
+        })
+        .await
+    }
+}
+
+/// Every user in `users`, sorted by username
+fn export_of(users: &HashMap<Uuid, User>) -> UserExport {
+    let mut users: Vec<ExportedUser> = users.values().cloned().map(ExportedUser::from).collect();
+    users.sort_by(|a, b| a.username.cmp(&b.username));
+
+    UserExport {
+        version: USER_EXPORT_VERSION,
+        exported_at: chrono::Utc::now().to_rfc3339(),
+        users,
+    }
+}
+
+/// Read the users file, starting with no users if it is missing or invalid
+fn load_users(path: &Path) -> HashMap<Uuid, User> {
+    let export: UserExport = match std::fs::read_to_string(path) {
+        Ok(contents) => match serde_json::from_str(&contents) {
+            Ok(export) => export,
+            Err(e) => {
+                warn!("Ignoring invalid users file {}: {}", path.display(), e);
+                return HashMap::new();
+            }
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return HashMap::new(),
+        Err(e) => {
+            warn!("Failed to read users file {}: {}", path.display(), e);
+            return HashMap::new();
+        }
+    };
+
+    export
+        .users
+        .into_iter()
+        .map(|user| (user.id, User::from(user)))
+        .collect()
+}
+
+/// Write the users file, replacing it atomically and readable by its owner only
+fn save_users(path: &Path, users: &HashMap<Uuid, User>) -> std::io::Result<()> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let data = serde_json::to_vec_pretty(&export_of(users))?;
+    let tmp = path.with_extension("tmp");
+    let written = files::write_with_mode(&tmp, data, PRIVATE_FILE_MODE)
+        .and_then(|()| std::fs::rename(&tmp, path));
+    if written.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+    written
 }
 
 impl Default for UserManager {
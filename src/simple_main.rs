@@ -92,16 +92,7 @@ async fn main() -> Result<()> {
 
     // Load configuration
     let config_file = &cli.config;
-    let config = match config::ServiceConfig::from_file(config_file) {
-        Ok(cfg) => {
-            info!("Configuration loaded from {}", config_file);
-            cfg
-        },
-        Err(e) => {
-            info!("Failed to load config from {}: {}. Using defaults.", config_file, e);
-            config::ServiceConfig::default()
-        }
-    };
+    let config = config::ServiceConfig::load_or_default(config_file, false)?;
 
     // Handle command or run daemon by default
     match cli.command {
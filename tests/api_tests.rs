@@ -0,0 +1,751 @@
+//! API tests for rcpdaemon
+
+use rcpdaemon::build_info::{self, BuildInfo};
+
+#[test]
+fn test_build_info_reports_crate_version() {
+    let info = BuildInfo::current();
+    assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+    assert_eq!(build_info::VERSION, info.version);
+}
+
+#[cfg(feature = "api")]
+mod api_tests {
+    use rcpdaemon::api::server::server_info;
+    use rcpdaemon::config::ServiceConfig;
+
+    #[test]
+    fn test_server_info_reports_capabilities() {
+        let mut config = ServiceConfig::default();
+        config.server.tls.enabled = true;
+        config.server.auth.provider = "native".to_string();
+
+        let info = server_info(&config, true, false);
+        assert_eq!(info["protocol_version"], "1.1");
+        assert_eq!(info["build"]["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(info["features"]["tls"], true);
+        assert_eq!(info["features"]["auth_provider"], "native");
+        assert_eq!(info["features"]["api"], true);
+        assert_eq!(info["server"]["running"], true);
+        assert_eq!(info["api"]["running"], false);
+    }
+
+    #[test]
+    fn test_server_info_leaks_no_secrets() {
+        let mut config = ServiceConfig::default();
+        config.server.auth.psk = Some("hunter2".to_string());
+        config.server.tls.key_path = "/etc/rcp/secret-key.pem".to_string();
+        if let Some(api) = config.api.as_mut() {
+            api.auth.jwt_secret = Some("jwt-hunter2".to_string());
+        }
+
+        let info = server_info(&config, false, false).to_string();
+        assert!(!info.contains("hunter2"));
+        assert!(!info.contains("secret-key.pem"));
+    }
+
+    #[test]
+    fn test_api_listen_from_toml() {
+        use rcpdaemon::api::{ApiConfig, ApiListen};
+
+        let config: ApiConfig = toml::from_str("port = 9090").unwrap();
+        assert_eq!(
+            config.listen_target(),
+            ApiListen::Tcp {
+                address: "127.0.0.1".to_string(),
+                port: 9090
+            }
+        );
+
+        let config: ApiConfig = toml::from_str(
+            r#"
+            [listen]
+            type = "unix_socket"
+            path = "/run/rcpdaemon/api.sock"
+            "#,
+        )
+        .unwrap();
+        let listen = config.listen_target();
+        assert_eq!(
+            listen,
+            ApiListen::UnixSocket {
+                path: "/run/rcpdaemon/api.sock".into()
+            }
+        );
+        assert_eq!(listen.to_string(), "unix:/run/rcpdaemon/api.sock");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_api_serves_over_unix_socket() {
+        use rcpdaemon::api::{ApiConfig, ApiListen, ApiServer};
+        use rcpdaemon::ServiceManager;
+        use std::sync::Arc;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::UnixStream;
+        use tokio::sync::{mpsc, Mutex};
+
+        let path = std::env::temp_dir().join(format!("rcpdaemon-api-{}.sock", std::process::id()));
+        let config = ApiConfig {
+            listen: Some(ApiListen::UnixSocket { path: path.clone() }),
+            ..Default::default()
+        };
+        let (shutdown_tx, _shutdown_rx) = mpsc::channel(1);
+        let manager =
+            ServiceManager::new(std::env::temp_dir(), ServiceConfig::default(), shutdown_tx);
+        let api = ApiServer::new(config, Arc::new(Mutex::new(manager)));
+        api.start().await.unwrap();
+        assert!(api.is_running().await);
+
+        let mut stream = UnixStream::connect(&path).await.unwrap();
+        stream
+            .write_all(b"GET /health HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert!(response.contains(r#""status":"ok""#));
+
+        api.stop().await.unwrap();
+        assert!(!path.exists());
+    }
+
+    /// Send `request` over the Unix socket at `path`, returning the response
+    /// headers and raw body
+    #[cfg(unix)]
+    async fn unix_request(path: &std::path::Path, request: &str) -> (String, Vec<u8>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let mut stream = tokio::net::UnixStream::connect(path).await.unwrap();
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await.unwrap();
+
+        let split = response
+            .windows(4)
+            .position(|w| w == b"\r\n\r\n")
+            .expect("response has a header section");
+        let headers = String::from_utf8(response[..split].to_vec()).unwrap();
+        (headers.to_ascii_lowercase(), response[split + 4..].to_vec())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_api_compresses_large_responses() {
+        use rcpdaemon::api::{ApiConfig, ApiListen, ApiServer};
+        use rcpdaemon::ServiceManager;
+        use std::sync::Arc;
+        use tokio::sync::{mpsc, Mutex};
+
+        let path =
+            std::env::temp_dir().join(format!("rcpdaemon-api-gzip-{}.sock", std::process::id()));
+        let config = ApiConfig {
+            listen: Some(ApiListen::UnixSocket { path: path.clone() }),
+            compression_min_bytes: 100,
+            ..Default::default()
+        };
+        assert!(config.compression_enabled);
+        let (shutdown_tx, _shutdown_rx) = mpsc::channel(1);
+        let manager =
+            ServiceManager::new(std::env::temp_dir(), ServiceConfig::default(), shutdown_tx);
+        let api = ApiServer::new(config, Arc::new(Mutex::new(manager)));
+        api.start().await.unwrap();
+
+        let get = |route: &str, gzip: bool| {
+            format!(
+                "GET {} HTTP/1.1\r\nHost: localhost\r\n{}Connection: close\r\n\r\n",
+                route,
+                if gzip {
+                    "Accept-Encoding: gzip\r\n"
+                } else {
+                    ""
+                }
+            )
+        };
+
+        // /v1/info is well over the threshold
+        let (headers, body) = unix_request(&path, &get("/v1/info", true)).await;
+        assert!(headers.starts_with("http/1.1 200"), "{}", headers);
+        assert!(headers.contains("content-encoding: gzip"), "{}", headers);
+        assert!(!body.windows(8).any(|w| w == b"features"));
+
+        // ...but only compressed for clients that ask
+        let (headers, body) = unix_request(&path, &get("/v1/info", false)).await;
+        assert!(!headers.contains("content-encoding"), "{}", headers);
+        assert!(body.windows(8).any(|w| w == b"features"));
+
+        // /health is under it
+        let (headers, body) = unix_request(&path, &get("/health", true)).await;
+        assert!(headers.starts_with("http/1.1 200"), "{}", headers);
+        assert!(!headers.contains("content-encoding"), "{}", headers);
+        assert!(String::from_utf8_lossy(&body).contains(r#""status":"ok""#));
+
+        api.stop().await.unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_api_serves_openapi_document() {
+        use rcpdaemon::api::{ApiConfig, ApiListen, ApiServer};
+        use rcpdaemon::ServiceManager;
+        use std::sync::Arc;
+        use tokio::sync::{mpsc, Mutex};
+
+        let path =
+            std::env::temp_dir().join(format!("rcpdaemon-api-openapi-{}.sock", std::process::id()));
+        let config = ApiConfig {
+            listen: Some(ApiListen::UnixSocket { path: path.clone() }),
+            docs_enabled: true,
+            ..Default::default()
+        };
+        let (shutdown_tx, _shutdown_rx) = mpsc::channel(1);
+        let manager =
+            ServiceManager::new(std::env::temp_dir(), ServiceConfig::default(), shutdown_tx);
+        let api = ApiServer::new(config, Arc::new(Mutex::new(manager)));
+        api.start().await.unwrap();
+
+        let get = |route: &str| {
+            format!(
+                "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+                route
+            )
+        };
+        let (headers, body) = unix_request(&path, &get("/openapi.json")).await;
+        assert!(headers.starts_with("http/1.1 200"), "{}", headers);
+        let document: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        assert!(document["openapi"].as_str().unwrap().starts_with("3."));
+        for route in [
+            "/health",
+            "/v1/info",
+            "/v1/status",
+            "/v1/config",
+            "/v1/server/start",
+            "/v1/server/stop",
+            "/v1/server/sessions",
+        ] {
+            assert!(document["paths"].get(route).is_some(), "{} missing", route);
+        }
+        assert!(document["paths"]["/v1/server/start"].get("post").is_some());
+        assert!(document["components"]["schemas"]
+            .get("StatusResponse")
+            .is_some());
+
+        // The UI is served alongside, if this build has it
+        let (headers, _) = unix_request(&path, &get("/docs/")).await;
+        #[cfg(feature = "swagger-ui")]
+        assert!(headers.starts_with("http/1.1 200"), "{}", headers);
+        #[cfg(not(feature = "swagger-ui"))]
+        assert!(headers.starts_with("http/1.1 404"), "{}", headers);
+
+        api.stop().await.unwrap();
+    }
+
+    /// Start an API server on a Unix socket named after `name`, with a
+    /// `/slow` route taking `slow_secs` to answer
+    #[cfg(unix)]
+    async fn start_slow_api(
+        name: &str,
+        slow_secs: u64,
+        shutdown_grace_secs: u64,
+    ) -> (rcpdaemon::api::ApiServer, std::path::PathBuf) {
+        use axum::routing::get;
+        use rcpdaemon::api::server::LimitedBody;
+        use rcpdaemon::api::{ApiConfig, ApiListen, ApiServer};
+        use rcpdaemon::ServiceManager;
+        use std::sync::Arc;
+        use tokio::sync::{mpsc, Mutex};
+
+        let path = std::env::temp_dir().join(format!(
+            "rcpdaemon-api-{}-{}.sock",
+            name,
+            std::process::id()
+        ));
+        let config = ApiConfig {
+            listen: Some(ApiListen::UnixSocket { path: path.clone() }),
+            shutdown_grace_secs,
+            ..Default::default()
+        };
+        let routes: axum::Router<(), LimitedBody> = axum::Router::new().route(
+            "/slow",
+            get(move || async move {
+                tokio::time::sleep(std::time::Duration::from_secs(slow_secs)).await;
+                "done"
+            }),
+        );
+        let (shutdown_tx, _shutdown_rx) = mpsc::channel(1);
+        let manager =
+            ServiceManager::new(std::env::temp_dir(), ServiceConfig::default(), shutdown_tx);
+        let api = ApiServer::new(config, Arc::new(Mutex::new(manager))).with_routes(routes);
+        api.start().await.unwrap();
+        (api, path)
+    }
+
+    /// Request `/slow` in the background, once `api` has it in flight
+    #[cfg(unix)]
+    async fn request_slow(
+        api: &rcpdaemon::api::ApiServer,
+        path: &std::path::Path,
+    ) -> tokio::task::JoinHandle<(String, Vec<u8>)> {
+        let request = "GET /slow HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n";
+        let path = path.to_path_buf();
+        let pending = tokio::spawn(async move { unix_request(&path, request).await });
+
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        while api.in_flight() == 0 {
+            assert!(
+                std::time::Instant::now() < deadline,
+                "request never arrived"
+            );
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        pending
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_api_stop_drains_requests_in_flight() {
+        let (api, path) = start_slow_api("drain", 1, 10).await;
+        let pending = request_slow(&api, &path).await;
+        assert_eq!(api.in_flight(), 1);
+
+        // Stopping waits for the request, which completes normally
+        api.stop().await.unwrap();
+        assert_eq!(api.in_flight(), 0);
+        let (headers, body) = pending.await.unwrap();
+        assert!(headers.starts_with("http/1.1 200"), "{}", headers);
+        assert!(String::from_utf8_lossy(&body).contains("done"));
+        assert!(!api.is_running().await);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_api_stop_cuts_off_requests_after_grace() {
+        let (api, path) = start_slow_api("cutoff", 30, 1).await;
+        let pending = request_slow(&api, &path).await;
+
+        let started = std::time::Instant::now();
+        api.stop().await.unwrap();
+        assert!(started.elapsed() < std::time::Duration::from_secs(5));
+        assert_eq!(api.in_flight(), 0);
+
+        let (headers, body) = pending.await.unwrap();
+        assert!(headers.starts_with("http/1.1 503"), "{}", headers);
+        assert!(String::from_utf8_lossy(&body).contains("shutting down"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_api_serves_again_after_restart() {
+        let (api, path) = start_slow_api("restart", 30, 1).await;
+        let pending = request_slow(&api, &path).await;
+        api.stop().await.unwrap();
+        let (headers, _) = pending.await.unwrap();
+        assert!(headers.starts_with("http/1.1 503"), "{}", headers);
+
+        // The cut-off of the last stop does not carry over
+        api.start().await.unwrap();
+        let request = "GET /health HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n";
+        let (headers, _) = unix_request(&path, request).await;
+        assert!(headers.starts_with("http/1.1 200"), "{}", headers);
+        api.stop().await.unwrap();
+    }
+
+    /// A router with `/slow` sleeping for ten seconds and `/echo` returning
+    /// the request body, behind the limits of `config`
+    fn limited_test_router(config: &rcpdaemon::api::ApiConfig) -> axum::Router {
+        use axum::routing::{get, post};
+        use rcpdaemon::api::server::{limit_requests, LimitedBody};
+
+        let router: axum::Router<(), LimitedBody> = axum::Router::new()
+            .route(
+                "/slow",
+                get(|| async {
+                    tokio::time::sleep(std::time::Duration::from_secs(10)).await;
+                    "done"
+                }),
+            )
+            .route("/echo", post(|body: String| async move { body }));
+        limit_requests(router, config)
+    }
+
+    #[tokio::test]
+    async fn test_api_refuses_oversized_bodies() {
+        use axum::body::Body;
+        use axum::http::{Request, StatusCode};
+        use tower::ServiceExt;
+
+        let config = rcpdaemon::api::ApiConfig {
+            max_body_bytes: 16,
+            ..Default::default()
+        };
+        let post = |body: &'static str| {
+            Request::post("/echo")
+                .header("content-length", body.len())
+                .body(Body::from(body))
+                .unwrap()
+        };
+
+        let response = limited_test_router(&config)
+            .oneshot(post("small enough"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = limited_test_router(&config)
+            .oneshot(post("far more than sixteen bytes"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn test_api_times_out_slow_requests() {
+        use axum::body::Body;
+        use axum::http::{Request, StatusCode};
+        use tower::ServiceExt;
+
+        let config = rcpdaemon::api::ApiConfig {
+            request_timeout_secs: 1,
+            ..Default::default()
+        };
+        let started = std::time::Instant::now();
+        let response = limited_test_router(&config)
+            .oneshot(Request::get("/slow").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::REQUEST_TIMEOUT);
+        assert!(started.elapsed() < std::time::Duration::from_secs(5));
+    }
+
+    /// Send a CORS preflight from `origin` through a router behind the CORS
+    /// settings of `config`
+    async fn preflight(config: &rcpdaemon::api::ApiConfig, origin: &str) -> axum::http::HeaderMap {
+        use axum::body::Body;
+        use axum::http::{Method, Request};
+        use axum::routing::get;
+        use rcpdaemon::api::server::cors_layer;
+        use tower::ServiceExt;
+
+        let router = axum::Router::new()
+            .route("/", get(|| async { "ok" }))
+            .layer(cors_layer(config).unwrap());
+        let request = Request::builder()
+            .method(Method::OPTIONS)
+            .uri("/")
+            .header("origin", origin)
+            .header("access-control-request-method", "GET")
+            .body(Body::empty())
+            .unwrap();
+        router.oneshot(request).await.unwrap().headers().clone()
+    }
+
+    #[tokio::test]
+    async fn test_restrictive_cors_config() {
+        use rcpdaemon::api::server::cors_layer;
+        use rcpdaemon::api::ApiConfig;
+
+        let config = ApiConfig {
+            cors_allowed_origins: vec!["https://dash.example".to_string()],
+            cors_allowed_methods: vec!["get".to_string()],
+            cors_allowed_headers: vec!["authorization".to_string()],
+            cors_allow_credentials: true,
+            cors_max_age: Some(600),
+            ..Default::default()
+        };
+        let headers = preflight(&config, "https://dash.example").await;
+        assert_eq!(
+            headers["access-control-allow-origin"],
+            "https://dash.example"
+        );
+        assert_eq!(headers["access-control-allow-methods"], "GET");
+        assert_eq!(headers["access-control-allow-headers"], "authorization");
+        assert_eq!(headers["access-control-allow-credentials"], "true");
+        assert_eq!(headers["access-control-max-age"], "600");
+
+        // Other origins are not allowed
+        let headers = preflight(&config, "https://evil.example").await;
+        assert!(headers.get("access-control-allow-origin").is_none());
+
+        // Nor is any origin by default when none are listed
+        let config = ApiConfig {
+            cors_allowed_origins: Vec::new(),
+            ..Default::default()
+        };
+        let headers = preflight(&config, "https://dash.example").await;
+        assert!(headers.get("access-control-allow-origin").is_none());
+
+        // Invalid settings are refused
+        let invalid = ApiConfig {
+            cors_allowed_methods: vec!["NOT A METHOD".to_string()],
+            ..Default::default()
+        };
+        assert!(cors_layer(&invalid).is_err());
+        let invalid = ApiConfig {
+            cors_allow_any: true,
+            cors_allow_credentials: true,
+            ..Default::default()
+        };
+        assert!(cors_layer(&invalid).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_permissive_cors_config() {
+        use rcpdaemon::api::ApiConfig;
+
+        let config = ApiConfig {
+            cors_allow_any: true,
+            ..Default::default()
+        };
+        for origin in ["http://localhost:3000", "https://anywhere.example"] {
+            let headers = preflight(&config, origin).await;
+            assert_eq!(headers["access-control-allow-origin"], "*");
+        }
+        let headers = preflight(&config, "https://anywhere.example").await;
+        assert_eq!(
+            headers["access-control-allow-methods"],
+            "GET,POST,PUT,DELETE"
+        );
+        assert!(headers.get("access-control-allow-credentials").is_none());
+    }
+
+    #[test]
+    fn test_auth_errors_answer_401_and_403() {
+        use axum::http::{header, StatusCode};
+        use axum::response::IntoResponse;
+        use rcpdaemon::api::server::error_status;
+        use rcpdaemon::error::ServiceError;
+        use rcpdaemon::server::error::{Error as ServerError, PermissionError};
+
+        let unauthorized =
+            ServiceError::from(ServerError::Authentication("Token has expired".into()));
+        assert_eq!(error_status(&unauthorized), StatusCode::UNAUTHORIZED);
+        let response = unauthorized.into_response();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert!(response.headers().contains_key(header::WWW_AUTHENTICATE));
+
+        let forbidden = ServiceError::from(ServerError::PermissionDenied(PermissionError::new(
+            "admin:config",
+            vec!["read:*".to_string()],
+        )));
+        assert!(
+            matches!(&forbidden, ServiceError::Forbidden(required) if required == "admin:config")
+        );
+        assert_eq!(error_status(&forbidden), StatusCode::FORBIDDEN);
+        assert_eq!(forbidden.into_response().status(), StatusCode::FORBIDDEN);
+
+        let other = ServiceError::from(ServerError::NotFound("app".into()));
+        assert_eq!(error_status(&other), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_api_endpoints_need_credentials_and_permissions() {
+        use rcpdaemon::api::{ApiConfig, ApiListen, ApiServer};
+        use rcpdaemon::server::tokens::TokenRegistry;
+        use rcpdaemon::ServiceManager;
+        use std::sync::Arc;
+        use std::time::Duration;
+        use tokio::sync::{mpsc, Mutex};
+
+        let path =
+            std::env::temp_dir().join(format!("rcpdaemon-api-auth-{}.sock", std::process::id()));
+        let config = ApiConfig {
+            listen: Some(ApiListen::UnixSocket { path: path.clone() }),
+            ..Default::default()
+        };
+        assert!(config.auth.required);
+        let mut service_config = ServiceConfig::default();
+        service_config.server.auth.psk = Some("operator-key".to_string());
+        service_config.server.auth.token_secret = Some("test-secret".to_string());
+        let tokens = TokenRegistry::new(&service_config.server.auth);
+        let (shutdown_tx, _shutdown_rx) = mpsc::channel(1);
+        let manager = ServiceManager::new(std::env::temp_dir(), service_config, shutdown_tx);
+        let api = ApiServer::new(config, Arc::new(Mutex::new(manager)));
+        api.start().await.unwrap();
+
+        let request = |method: &str, route: &str, credential: Option<&str>| {
+            let path = path.clone();
+            let request = format!(
+                "{} {} HTTP/1.1\r\nHost: localhost\r\nContent-Length: 0\r\n{}Connection: close\r\n\r\n",
+                method,
+                route,
+                credential
+                    .map(|c| format!("Authorization: Bearer {}\r\n", c))
+                    .unwrap_or_default()
+            );
+            async move { unix_request(&path, &request).await }
+        };
+        let hour = Duration::from_secs(3600);
+        let observer = tokens.issue("monitor", vec!["read:sessions".to_string()], hour);
+        let expired = tokens.issue("monitor", vec!["read:*".to_string()], Duration::ZERO);
+
+        // Capabilities stay public
+        let (headers, _) = request("GET", "/v1/info", None).await;
+        assert!(headers.starts_with("http/1.1 200"), "{}", headers);
+
+        // Missing, wrong and expired credentials are 401
+        for credential in [None, Some("wrong-key"), Some(expired.as_str())] {
+            let (headers, body) = request("GET", "/v1/status", credential).await;
+            assert!(headers.starts_with("http/1.1 401"), "{}", headers);
+            assert!(headers.contains("www-authenticate: bearer"), "{}", headers);
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert!(body["error"].as_str().unwrap().starts_with("Unauthorized"));
+        }
+
+        // A token gets what its permissions allow, and 403 for the rest
+        let (headers, _) = request("GET", "/v1/server/sessions", Some(observer.as_str())).await;
+        assert!(headers.starts_with("http/1.1 200"), "{}", headers);
+        let (headers, body) = request("GET", "/v1/config", Some(observer.as_str())).await;
+        assert!(headers.starts_with("http/1.1 403"), "{}", headers);
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["required"], "read:config");
+        let (headers, _) = request("POST", "/v1/server/stop", Some(observer.as_str())).await;
+        assert!(headers.starts_with("http/1.1 403"), "{}", headers);
+
+        // The pre-shared key acts as the operator
+        let (headers, _) = request("POST", "/v1/server/start", Some("operator-key")).await;
+        assert!(headers.starts_with("http/1.1 200"), "{}", headers);
+        let (headers, _) = request("GET", "/v1/config", Some("operator-key")).await;
+        assert!(headers.starts_with("http/1.1 200"), "{}", headers);
+
+        api.stop().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_api_auth_can_be_turned_off() {
+        use axum::body::Body;
+        use axum::http::{Request, StatusCode};
+        use axum::routing::get;
+        use rcpdaemon::api::auth::{require_auth, ApiAuth};
+        use rcpdaemon::api::config::ApiAuthConfig;
+        use rcpdaemon::api::handlers;
+        use rcpdaemon::server::config::AuthConfig;
+        use rcpdaemon::server::tokens::TokenRegistry;
+        use tower::ServiceExt;
+
+        let router = |required: bool| {
+            let config = ApiAuthConfig {
+                required,
+                ..Default::default()
+            };
+            let auth = AuthConfig::default();
+            let api_auth = ApiAuth::new(&config, &auth, TokenRegistry::new(&auth));
+            axum::Router::new()
+                .route("/v1/config", get(handlers::config))
+                .route_layer(axum::middleware::from_fn_with_state(api_auth, require_auth))
+        };
+        let get_config = || {
+            Request::builder()
+                .uri("/v1/config")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = router(true).oneshot(get_config()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        // Without auth every request acts as the operator
+        let response = router(false).oneshot(get_config()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    /// Wait until `limit` counts `count` open connections
+    async fn wait_for_open(limit: &rcpdaemon::server::ratelimit::ConnectionLimit, count: usize) {
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        while limit.open() != count {
+            assert!(
+                std::time::Instant::now() < deadline,
+                "{} connections open",
+                limit.open()
+            );
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+    }
+
+    /// Whether the peer closes `stream` without sending anything
+    async fn closed_at_once(mut stream: tokio::net::TcpStream) -> bool {
+        use tokio::io::AsyncReadExt;
+
+        let mut buf = [0u8; 64];
+        let read = tokio::time::timeout(std::time::Duration::from_secs(5), stream.read(&mut buf))
+            .await
+            .expect("connection should be closed");
+        matches!(read, Ok(0) | Err(_))
+    }
+
+    #[tokio::test]
+    async fn test_connection_limit_shared_by_server_and_api() {
+        use rcpdaemon::api::{ApiConfig, ApiServer};
+        use rcpdaemon::protocol::codec;
+        use rcpdaemon::protocol::handshake::{client_handshake, ClientHello};
+        use rcpdaemon::server::config::ServerConfig;
+        use rcpdaemon::server::ratelimit::ConnectionLimit;
+        use rcpdaemon::server::Server;
+        use rcpdaemon::ServiceManager;
+        use std::sync::Arc;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::{TcpListener, TcpStream};
+        use tokio::sync::{mpsc, Mutex};
+
+        let limit = ConnectionLimit::new(2);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server_addr = listener.local_addr().unwrap();
+        let mut server_config = ServerConfig::default();
+        server_config.auth.required = false;
+        let server = Server::builder()
+            .config(server_config)
+            .connection_limit(limit.clone())
+            .build();
+        tokio::spawn(server.serve(listener));
+
+        let api_port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let config = ApiConfig {
+            port: api_port,
+            ..Default::default()
+        };
+        let (shutdown_tx, _shutdown_rx) = mpsc::channel(1);
+        let manager =
+            ServiceManager::new(std::env::temp_dir(), ServiceConfig::default(), shutdown_tx);
+        let api = ApiServer::new(config, Arc::new(Mutex::new(manager)))
+            .with_connection_limit(limit.clone());
+        api.start().await.unwrap();
+        let api_addr = ("127.0.0.1", api_port);
+
+        // One connection to each component takes both slots
+        let stream = TcpStream::connect(server_addr).await.unwrap();
+        let mut session = codec::framed(stream, codec::DEFAULT_MAX_FRAME_LENGTH);
+        client_handshake(&mut session, &ClientHello::default())
+            .await
+            .unwrap();
+        let idle_api_client = TcpStream::connect(api_addr).await.unwrap();
+        wait_for_open(&limit, 2).await;
+
+        // Either component now turns new connections away
+        assert!(closed_at_once(TcpStream::connect(server_addr).await.unwrap()).await);
+        assert!(closed_at_once(TcpStream::connect(api_addr).await.unwrap()).await);
+        assert_eq!(limit.open(), 2);
+
+        // Closing the session frees a slot the API can use
+        drop(session);
+        wait_for_open(&limit, 1).await;
+        let mut stream = TcpStream::connect(api_addr).await.unwrap();
+        stream
+            .write_all(b"GET /health HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+
+        drop(idle_api_client);
+        api.stop().await.unwrap();
+    }
+}
@@ -0,0 +1,110 @@
+//! Group membership cache tests
+
+use anyhow::{anyhow, Result};
+use rcpdaemon::auth::cache::GroupCache;
+use rcpdaemon::auth::command::CommandTimeout;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// Fetcher returning fixed groups and counting how often it runs
+async fn fetch_groups(fetches: &AtomicUsize) -> Result<Vec<String>> {
+    fetches.fetch_add(1, Ordering::SeqCst);
+    Ok(vec!["alice".to_string(), "rcp-users".to_string()])
+}
+
+#[tokio::test]
+async fn test_one_fetch_serves_membership_and_permissions() {
+    let cache = GroupCache::new();
+    let fetches = AtomicUsize::new(0);
+
+    // Required group check during authentication...
+    assert!(cache
+        .is_member("alice", "rcp-users", || fetch_groups(&fetches))
+        .await
+        .unwrap());
+
+    // ...followed by permission mapping for the same user
+    let groups = cache
+        .get_or_fetch("alice", || fetch_groups(&fetches))
+        .await
+        .unwrap();
+    assert_eq!(groups, vec!["alice", "rcp-users"]);
+
+    assert!(!cache
+        .is_member("alice", "sudo", || fetch_groups(&fetches))
+        .await
+        .unwrap());
+
+    assert_eq!(fetches.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_failed_fetch_is_not_cached() {
+    let cache = GroupCache::new();
+    let fetches = AtomicUsize::new(0);
+
+    let result = cache
+        .get_or_fetch("bob", || async {
+            fetches.fetch_add(1, Ordering::SeqCst);
+            Err(anyhow!("no such user"))
+        })
+        .await;
+    assert!(result.is_err());
+    assert!(cache.get("bob").is_none());
+
+    cache
+        .get_or_fetch("bob", || fetch_groups(&fetches))
+        .await
+        .unwrap();
+    assert_eq!(fetches.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn test_unknown_user_is_not_a_member() {
+    let cache = GroupCache::new();
+
+    let member = cache
+        .is_member("mallory", "rcp-users", || async {
+            Err(anyhow!("no such user"))
+        })
+        .await
+        .unwrap();
+
+    assert!(!member);
+}
+
+#[tokio::test]
+async fn test_membership_lookup_timeout_is_reported() {
+    let cache = GroupCache::new();
+
+    let err = cache
+        .is_member("alice", "rcp-users", || async {
+            Err(CommandTimeout {
+                program: "groups".to_string(),
+                timeout: Duration::from_secs(1),
+            }
+            .into())
+        })
+        .await
+        .unwrap_err();
+
+    assert!(err.is::<CommandTimeout>());
+}
+
+#[tokio::test]
+async fn test_clear_forces_refetch() {
+    let cache = GroupCache::new();
+    let fetches = AtomicUsize::new(0);
+
+    cache
+        .get_or_fetch("alice", || fetch_groups(&fetches))
+        .await
+        .unwrap();
+    cache.clear();
+    cache
+        .get_or_fetch("alice", || fetch_groups(&fetches))
+        .await
+        .unwrap();
+
+    assert_eq!(fetches.load(Ordering::SeqCst), 2);
+}
@@ -0,0 +1,116 @@
+//! Native auth subprocess helper tests
+
+#![cfg(unix)]
+
+use rcpdaemon::auth::command::{output_with_timeout, CommandTimeout};
+use std::time::{Duration, Instant};
+use tokio::process::Command;
+
+#[tokio::test]
+async fn test_command_output_is_captured() {
+    let output = output_with_timeout(Command::new("echo").arg("hello"), Duration::from_secs(5))
+        .await
+        .unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "hello");
+}
+
+#[tokio::test]
+async fn test_slow_command_is_killed_on_timeout() {
+    let started = Instant::now();
+    let err = output_with_timeout(Command::new("sleep").arg("30"), Duration::from_millis(200))
+        .await
+        .unwrap_err();
+
+    // The caller gets a distinguishable timeout error well before the command would finish
+    let timeout = err
+        .downcast_ref::<CommandTimeout>()
+        .expect("expected a timeout error");
+    assert_eq!(timeout.program, "sleep");
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[tokio::test]
+async fn test_large_output_does_not_block() {
+    // More output than fits in a pipe buffer must not stall the child
+    let output = output_with_timeout(
+        Command::new("sh").args(["-c", "head -c 200000 /dev/zero"]),
+        Duration::from_secs(10),
+    )
+    .await
+    .unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(output.stdout.len(), 200000);
+}
+
+#[tokio::test]
+async fn test_slow_command_does_not_block_runtime() {
+    // A current-thread runtime would stall the timer if the wait blocked the thread
+    let slow = output_with_timeout(Command::new("sleep").arg("1"), Duration::from_secs(5));
+    let tick = tokio::time::sleep(Duration::from_millis(50));
+
+    tokio::select! {
+        _ = slow => panic!("sleep finished before the timer"),
+        _ = tick => {}
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux_parsing {
+    use rcpdaemon::auth::native_linux::{
+        parse_group_members, parse_groups_output, parse_passwd_usernames,
+    };
+
+    #[test]
+    fn test_parse_groups_output() {
+        assert_eq!(
+            parse_groups_output("alice : alice sudo rcp-users\n"),
+            vec!["alice", "sudo", "rcp-users"]
+        );
+        assert!(parse_groups_output("").is_empty());
+    }
+
+    #[test]
+    fn test_parse_group_members() {
+        assert_eq!(
+            parse_group_members("rcp-users:x:1001:alice,bob\n"),
+            vec!["alice", "bob"]
+        );
+        assert!(parse_group_members("empty:x:1002:\n").is_empty());
+    }
+
+    #[test]
+    fn test_parse_passwd_file_contents() {
+        let passwd = "\
+# /etc/passwd
+root:x:0:0:root:/root:/bin/sh
+daemon:x:1:1:daemon:/usr/sbin:/usr/sbin/nologin
+
+alice:x:1000:1000:Alice Example,,,:/home/alice:/bin/bash
+bob:x:1001:1001::/home/bob:/bin/sh
+broken-entry
+nobody:x:65534:65534:nobody:/nonexistent:/usr/sbin/nologin
+weird:x:notanumber:100::/:/bin/false
+";
+
+        assert_eq!(
+            parse_passwd_usernames(passwd, 1000),
+            vec!["alice", "bob", "nobody"]
+        );
+    }
+
+    #[test]
+    fn test_parse_passwd_custom_uid_threshold() {
+        // macOS-style numbering starts regular accounts at 500
+        let passwd = "\
+daemon:x:1:1::/:/usr/bin/false
+carol:x:501:20:Carol:/Users/carol:/bin/zsh
+alice:x:1000:1000::/home/alice:/bin/bash
+";
+
+        assert_eq!(parse_passwd_usernames(passwd, 500), vec!["carol", "alice"]);
+        assert_eq!(parse_passwd_usernames(passwd, 1000), vec!["alice"]);
+    }
+}
@@ -1,7 +1,12 @@
 use anyhow::Result;
-use rcpdaemon::auth::factory::{AuthConfig, AuthProviderType, NativeAuthConfig};
+use rcpdaemon::auth::factory::{
+    AuthConfig, AuthProviderFactory, AuthProviderType, NativeAuthConfig,
+};
 use rcpdaemon::auth::manager::AuthManager;
 use rcpdaemon::auth::mock_provider::MockAuthProvider;
+use rcpdaemon::auth::provider::{AuthProvider, NotInitialized};
+use rcpdaemon::server::error::{Error as ServerError, PermissionError};
+use rcpdaemon::server::rpc::{self, RpcError};
 use rcpdaemon::server::user::{User, UserRole};
 use std::collections::HashMap;
 use tokio::test;
@@ -76,6 +81,229 @@ async fn test_auth_manager_validate_credentials() -> Result<()> {
     Ok(())
 }
 
+#[test]
+async fn test_auth_manager_refuses_disallowed_method() -> Result<()> {
+    // The mock provider supports both methods
+    let provider = MockAuthProvider::new()
+        .with_user(create_test_user())
+        .with_credential("testuser", b"password123");
+    assert!(provider.supports_auth_method("psk"));
+
+    let mut auth_config = create_test_auth_config();
+    auth_config.provider = AuthProviderType::Mock;
+    auth_config.allowed_methods = vec!["password".to_string()];
+
+    let mut manager = AuthManager::new(auth_config).await?;
+    manager.provider = std::sync::Arc::new(tokio::sync::RwLock::new(Box::new(provider)));
+    manager.initialize().await?;
+
+    // Refused before the provider is asked, so fallback does not apply either
+    let err = manager
+        .validate_credentials("testuser", b"", "psk")
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("not allowed"), "{}", err);
+
+    assert!(
+        manager
+            .validate_credentials("testuser", b"password123", "password")
+            .await?
+    );
+
+    Ok(())
+}
+
+#[test]
+async fn test_provider_refuses_use_before_initialize() -> Result<()> {
+    let mut provider = MockAuthProvider::new()
+        .with_user(create_test_user())
+        .with_credential("testuser", b"password123")
+        .with_permission("testuser", "app:safari");
+    assert!(!provider.is_initialized());
+
+    let err = provider
+        .validate_credentials("testuser", b"password123", "password")
+        .await
+        .unwrap_err();
+    assert!(err.downcast_ref::<NotInitialized>().is_some(), "{}", err);
+    let err = provider
+        .has_permission(&create_test_user(), "app:safari")
+        .await
+        .unwrap_err();
+    assert!(
+        err.to_string().contains("before it was initialized"),
+        "{}",
+        err
+    );
+
+    // Initializing again is harmless
+    provider.initialize().await?;
+    provider.initialize().await?;
+    assert!(provider.is_initialized());
+    assert!(
+        provider
+            .validate_credentials("testuser", b"password123", "password")
+            .await?
+    );
+    assert!(
+        provider
+            .has_permission(&create_test_user(), "app:safari")
+            .await?
+    );
+
+    Ok(())
+}
+
+#[test]
+async fn test_auth_manager_initializes_provider_on_first_use() -> Result<()> {
+    let provider = MockAuthProvider::new()
+        .with_user(create_test_user())
+        .with_credential("testuser", b"password123");
+
+    let mut auth_config = create_test_auth_config();
+    auth_config.provider = AuthProviderType::Mock;
+    let mut manager = AuthManager::new(auth_config).await?;
+    manager.provider = std::sync::Arc::new(tokio::sync::RwLock::new(Box::new(provider)));
+
+    // Never initialized explicitly
+    assert!(
+        manager
+            .validate_credentials("testuser", b"password123", "password")
+            .await?
+    );
+    assert!(manager.provider.read().await.is_initialized());
+
+    Ok(())
+}
+
+/// A manager around a mock provider whose first `failures` initializations fail
+async fn flaky_manager(failures: u32, attempts: u32, strict: bool) -> Result<AuthManager> {
+    let provider = MockAuthProvider::new()
+        .with_user(create_test_user())
+        .with_credential("testuser", b"password123")
+        .with_init_failures(failures);
+
+    let mut auth_config = create_test_auth_config();
+    auth_config.provider = AuthProviderType::Mock;
+    auth_config.init_attempts = attempts;
+    auth_config.init_retry_delay_ms = 1;
+    auth_config.init_strict = strict;
+    let mut manager = AuthManager::new(auth_config).await?;
+    manager.provider = std::sync::Arc::new(tokio::sync::RwLock::new(Box::new(provider)));
+    Ok(manager)
+}
+
+#[test]
+async fn test_auth_manager_retries_initialization() -> Result<()> {
+    let mut manager = flaky_manager(2, 3, true).await?;
+    manager.initialize().await?;
+
+    assert!(manager.initialized);
+    assert!(!manager.is_degraded());
+    assert!(
+        manager
+            .validate_credentials("testuser", b"password123", "password")
+            .await?
+    );
+
+    Ok(())
+}
+
+#[test]
+async fn test_auth_manager_strict_initialization_gives_up() -> Result<()> {
+    let mut manager = flaky_manager(3, 2, true).await?;
+    let err = manager.initialize().await.unwrap_err();
+
+    assert!(err.to_string().contains("after 2 attempts"), "{}", err);
+    assert!(!manager.initialized);
+    assert!(!manager.is_degraded());
+
+    Ok(())
+}
+
+#[test]
+async fn test_auth_manager_lenient_initialization_fails_closed() -> Result<()> {
+    let mut manager = flaky_manager(3, 2, false).await?;
+    manager.initialize().await?;
+
+    assert!(!manager.initialized);
+    assert!(manager.is_degraded());
+
+    // The third failure is on first use, which refuses the credentials
+    assert!(manager
+        .validate_credentials("testuser", b"password123", "password")
+        .await
+        .is_err());
+    assert!(manager.is_degraded());
+
+    // Once the backend is back, authentication works again
+    assert!(
+        manager
+            .validate_credentials("testuser", b"password123", "password")
+            .await?
+    );
+    assert!(!manager.is_degraded());
+
+    Ok(())
+}
+
+#[test]
+async fn test_auth_manager_refuses_oversized_input() -> Result<()> {
+    let provider = MockAuthProvider::new()
+        .with_user(create_test_user())
+        .with_credential("testuser", b"password123");
+
+    let mut auth_config = create_test_auth_config();
+    auth_config.provider = AuthProviderType::Mock;
+    auth_config.max_username_len = "testuser".len();
+    auth_config.max_credential_len = "password123".len();
+
+    let mut manager = AuthManager::new(auth_config).await?;
+    manager.provider = std::sync::Arc::new(tokio::sync::RwLock::new(Box::new(provider)));
+    manager.initialize().await?;
+
+    // Input exactly at the limits reaches the provider
+    assert!(
+        manager
+            .validate_credentials("testuser", b"password123", "password")
+            .await?
+    );
+    assert!(
+        !manager
+            .validate_credentials("testuser", b"password12", "password")
+            .await?
+    );
+
+    // One byte over is refused outright
+    let err = manager
+        .validate_credentials("testusers", b"password123", "password")
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("Username is 9 bytes"), "{}", err);
+    let err = manager
+        .validate_credentials("testuser", b"password1234", "password")
+        .await
+        .unwrap_err();
+    assert!(
+        err.to_string().contains("Credential is 12 bytes"),
+        "{}",
+        err
+    );
+
+    // Zero turns a limit off
+    let mut unlimited = create_test_auth_config();
+    unlimited.max_username_len = 0;
+    unlimited.max_credential_len = 0;
+    assert!(unlimited
+        .check_lengths(&"u".repeat(10_000), &[0; 100_000])
+        .is_ok());
+    assert!(AuthConfig::default()
+        .check_lengths(&"u".repeat(10_000), b"")
+        .is_err());
+
+    Ok(())
+}
+
 #[test]
 async fn test_auth_manager_has_permission() -> Result<()> {
     // Create a mock provider with permissions
@@ -156,6 +384,55 @@ async fn test_auth_manager_get_permissions() -> Result<()> {
     Ok(())
 }
 
+#[test]
+async fn test_permission_error_explains_denial() -> Result<()> {
+    let permission = PermissionError::new(
+        "app:word",
+        vec!["app:office".to_string(), "connect:*".to_string()],
+    );
+    assert_eq!(
+        permission.to_string(),
+        "operation requires permission app:word (you have: app:office, connect:*)"
+    );
+
+    let rpc_error = RpcError::from(ServerError::PermissionDenied(permission));
+    assert_eq!(rpc_error.code, rpc::PERMISSION_DENIED);
+    assert_eq!(rpc_error.data.unwrap()["required"], "app:word");
+
+    // Without grants to list, only the requirement is shown
+    let permission = PermissionError::new("app:word", Vec::new());
+    assert_eq!(
+        permission.to_string(),
+        "operation requires permission app:word"
+    );
+
+    Ok(())
+}
+
+#[test]
+async fn test_unauthorized_and_forbidden_are_told_apart() -> Result<()> {
+    let unauthorized = RpcError::from(ServerError::Authentication("Token has expired".to_string()));
+    assert_eq!(unauthorized.code, rpc::UNAUTHENTICATED);
+    assert_eq!(
+        unauthorized.message,
+        "Authentication error: Token has expired"
+    );
+    assert!(unauthorized.data.is_none());
+
+    let forbidden = RpcError::from(ServerError::PermissionDenied(PermissionError::new(
+        "admin:sessions",
+        Vec::new(),
+    )));
+    assert_eq!(forbidden.code, rpc::PERMISSION_DENIED);
+    assert_eq!(forbidden.data.unwrap()["required"], "admin:sessions");
+
+    // Token problems are credential problems
+    let expired = ServerError::from(rcpdaemon::server::tokens::TokenError::Expired);
+    assert!(matches!(expired, ServerError::Authentication(_)));
+
+    Ok(())
+}
+
 /// Create a test user
 fn create_test_user() -> User {
     User {
@@ -170,6 +447,32 @@ fn create_test_user() -> User {
     }
 }
 
+#[test]
+async fn test_available_providers_on_this_platform() -> Result<()> {
+    let providers = AuthProviderFactory::available_providers();
+    let implemented = |provider| {
+        providers
+            .iter()
+            .find(|(p, _)| *p == provider)
+            .map(|(_, implemented)| *implemented)
+    };
+
+    assert_eq!(implemented(AuthProviderType::Native), Some(true));
+    assert_eq!(implemented(AuthProviderType::Mock), Some(true));
+    assert_eq!(implemented(AuthProviderType::Ldap), Some(false));
+
+    // Every type reported implemented can actually be created
+    for (provider, _) in providers.iter().filter(|(_, implemented)| *implemented) {
+        let config = AuthConfig {
+            provider: *provider,
+            ..Default::default()
+        };
+        assert!(AuthProviderFactory::create_provider(&config).is_ok());
+    }
+
+    Ok(())
+}
+
 /// Create test auth configuration
 fn create_test_auth_config() -> AuthConfig {
     AuthConfig {
@@ -183,8 +486,11 @@ fn create_test_auth_config() -> AuthConfig {
             permission_mapping: true,
             admin_groups: vec!["admin".to_string(), "wheel".to_string()],
             permission_mappings: HashMap::new(),
+            ..Default::default()
         },
         ldap: HashMap::new(),
         oauth: HashMap::new(),
+        allowed_methods: Vec::new(),
+        ..Default::default()
     }
 }
@@ -0,0 +1,86 @@
+//! Native auth subprocess concurrency limit tests
+//!
+//! The limiter is process-wide, so these tests live in their own binary.
+
+#![cfg(target_os = "linux")]
+
+use rcpdaemon::auth::command::{subprocess_limiter, SubprocessLimiter};
+use rcpdaemon::auth::factory::{
+    AuthConfig, AuthProviderFactory, AuthProviderType, NativeAuthConfig,
+};
+use rcpdaemon::server::user::{User, UserRole};
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+fn user(username: &str) -> User {
+    User {
+        id: Uuid::new_v4(),
+        username: username.to_string(),
+        full_name: None,
+        email: None,
+        password_hash: String::new(),
+        role: UserRole::User,
+        created_at: String::new(),
+        updated_at: String::new(),
+    }
+}
+
+#[tokio::test]
+async fn test_limiter_queues_beyond_capacity() {
+    let limiter = SubprocessLimiter::new(2);
+    let first = limiter.acquire().await;
+    let _second = limiter.acquire().await;
+    assert_eq!(limiter.running(), 2);
+
+    // A third command waits until a slot is released
+    let third = tokio::time::timeout(Duration::from_millis(100), limiter.acquire()).await;
+    assert!(third.is_err());
+
+    drop(first);
+    let _third = tokio::time::timeout(Duration::from_secs(5), limiter.acquire())
+        .await
+        .expect("slot not released");
+    assert_eq!(limiter.peak(), 2);
+
+    // Zero disables the limit
+    let unlimited = SubprocessLimiter::new(0);
+    let slots: Vec<_> = futures_util::future::join_all((0..10).map(|_| unlimited.acquire())).await;
+    assert_eq!(unlimited.running(), slots.len());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_group_lookups_respect_concurrency_bound() {
+    let config = AuthConfig {
+        provider: AuthProviderType::Native,
+        native: NativeAuthConfig {
+            max_concurrent_auth_subprocesses: 2,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let provider = Arc::new(AuthProviderFactory::create_provider(&config).unwrap());
+    assert_eq!(subprocess_limiter().capacity(), 2);
+
+    // Distinct users, so every call misses the group cache and runs `groups`
+    let lookups = (0..16).map(|i| {
+        let provider = provider.clone();
+        tokio::spawn(async move {
+            let _ = provider
+                .get_permissions(&user(&format!("rcp-limit-test-{}", i)))
+                .await;
+        })
+    });
+    for lookup in futures_util::future::join_all(lookups).await {
+        lookup.unwrap();
+    }
+
+    let limiter = subprocess_limiter();
+    assert!(limiter.peak() >= 1);
+    assert!(
+        limiter.peak() <= 2,
+        "peak of {} subprocesses",
+        limiter.peak()
+    );
+    assert_eq!(limiter.running(), 0);
+}
@@ -0,0 +1,149 @@
+//! Server-side auth benchmark tests
+
+mod common;
+
+use common::open_config;
+use rcpdaemon::auth::factory::{AuthConfig, AuthProviderType};
+use rcpdaemon::auth::manager::AuthManager;
+use rcpdaemon::auth::mock_provider::MockAuthProvider;
+use rcpdaemon::client::{Client, ClientError};
+use rcpdaemon::server::bench::{percentile, BENCH_PERMISSION};
+use rcpdaemon::server::config::ServerConfig;
+use rcpdaemon::server::rpc;
+use rcpdaemon::server::user::{User, UserRole};
+use rcpdaemon::server::Server;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use uuid::Uuid;
+
+const HOUR: Duration = Duration::from_secs(3600);
+
+/// Serve `server` on an ephemeral port and return a client for it
+async fn serve(server: &Server) -> Client {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(server.clone().serve(listener));
+
+    Client::new(addr.ip().to_string(), addr.port(), 5)
+}
+
+/// An auth manager whose mock provider knows `alice` with password "wonderland"
+async fn mock_manager() -> AuthManager {
+    let provider = MockAuthProvider::new()
+        .with_user(User {
+            id: Uuid::new_v4(),
+            username: "alice".to_string(),
+            full_name: None,
+            email: None,
+            password_hash: String::new(),
+            role: UserRole::User,
+            created_at: String::new(),
+            updated_at: String::new(),
+        })
+        .with_credential("alice", b"wonderland");
+
+    let mut manager = AuthManager::new(AuthConfig {
+        provider: AuthProviderType::Mock,
+        ..Default::default()
+    })
+    .await
+    .unwrap();
+    manager.provider = Arc::new(tokio::sync::RwLock::new(Box::new(provider)));
+    manager.initialize().await.unwrap();
+    manager
+}
+
+#[test]
+fn test_percentile_nearest_rank() {
+    let latencies: Vec<Duration> = (1..=100).map(Duration::from_millis).collect();
+    assert_eq!(percentile(&latencies, 50), Duration::from_millis(50));
+    assert_eq!(percentile(&latencies, 99), Duration::from_millis(99));
+    assert_eq!(percentile(&latencies, 100), Duration::from_millis(100));
+
+    assert_eq!(
+        percentile(&[Duration::from_millis(7)], 50),
+        Duration::from_millis(7)
+    );
+    assert_eq!(percentile(&[], 50), Duration::ZERO);
+}
+
+#[tokio::test]
+async fn test_bench_defaults_to_mock_provider() {
+    let client = serve(&Server::new(open_config())).await;
+
+    let report = client.bench_auth(20, None, None).await.unwrap();
+    assert_eq!(report.provider, "mock-provider");
+    assert_eq!(report.iterations, 20);
+    assert_eq!(report.failures, 0);
+    assert!(report.p50_us <= report.p90_us && report.p90_us <= report.p99_us);
+    assert!(report.p99_us <= report.max_us);
+    assert!(report.per_second > 0.0);
+
+    // Out-of-range iteration counts are refused before anything runs
+    match client.bench_auth(0, None, None).await.unwrap_err() {
+        ClientError::Rpc { code, .. } => assert_eq!(code, rpc::INVALID_PARAMS),
+        other => panic!("unexpected error: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_bench_runs_against_configured_provider() {
+    let server = Server::builder()
+        .config(open_config())
+        .auth_manager(Arc::new(mock_manager().await))
+        .build();
+    let client = serve(&server).await;
+
+    let report = client
+        .bench_auth(10, Some("alice"), Some("wonderland"))
+        .await
+        .unwrap();
+    assert_eq!(report.username, "alice");
+    assert_eq!(report.failures, 0);
+
+    // Wrong credentials are counted, not fatal
+    let report = client
+        .bench_auth(10, Some("alice"), Some("looking-glass"))
+        .await
+        .unwrap();
+    assert_eq!(report.failures, 10);
+
+    // Without a provider only the mock can be benchmarked
+    let bare = serve(&Server::new(open_config())).await;
+    assert!(bare.bench_auth(10, Some("alice"), None).await.is_err());
+}
+
+#[tokio::test]
+async fn test_bench_requires_admin() {
+    let mut config = ServerConfig::default();
+    config.auth.psk = Some("operator-key".to_string());
+    let server = Server::new(config);
+    let client = serve(&server).await;
+
+    let token = server
+        .tokens()
+        .issue("alice", vec!["app:*".to_string()], HOUR);
+    match client
+        .clone()
+        .with_auth(Some(token))
+        .bench_auth(10, None, None)
+        .await
+        .unwrap_err()
+    {
+        ClientError::PermissionDenied(permission) => {
+            assert_eq!(permission.required, BENCH_PERMISSION)
+        }
+        other => panic!("unexpected error: {:?}", other),
+    }
+
+    let admin = server
+        .tokens()
+        .issue("ops", vec![BENCH_PERMISSION.to_string()], HOUR);
+    let report = client
+        .with_auth(Some(admin))
+        .bench_auth(10, None, None)
+        .await
+        .unwrap();
+    assert_eq!(report.iterations, 10);
+}
@@ -0,0 +1,201 @@
+//! CLI configuration command tests
+//!
+//! These tests cover key validation and value handling for `config get/set/remove`.
+
+#[cfg(feature = "cli")]
+mod cli_config_tests {
+    use rcpdaemon::cli::commands::config::{
+        apply_config_value, apply_daemon_config_value, daemon_config_keys, is_daemon_config_key,
+        set_daemon_config_value, suggest_config_key, unknown_key_error,
+    };
+    use rcpdaemon::cli::config::CliConfig;
+    use rcpdaemon::config::ServiceConfig;
+    use rcpdaemon::server::config::AuthFailureBehavior;
+
+    #[test]
+    fn test_typo_suggests_closest_key() {
+        assert_eq!(suggest_config_key("porrt"), Some("port"));
+        assert_eq!(suggest_config_key("hostt"), Some("host"));
+        assert_eq!(suggest_config_key("verify_crt"), Some("verify_cert"));
+    }
+
+    #[test]
+    fn test_unrelated_key_has_no_suggestion() {
+        assert_eq!(suggest_config_key("database_url"), None);
+    }
+
+    #[test]
+    fn test_unknown_key_error_includes_suggestion() {
+        let message = unknown_key_error("porrt").to_string();
+        assert!(message.contains("porrt"));
+        assert!(message.contains("did you mean `port`?"));
+    }
+
+    #[test]
+    fn test_verify_cert_false_skips_verification() {
+        let mut config = CliConfig::default();
+
+        apply_config_value(&mut config, "verify_cert", "false").unwrap();
+        assert!(config.service.skip_verify);
+
+        apply_config_value(&mut config, "verify_cert", "true").unwrap();
+        assert!(!config.service.skip_verify);
+    }
+
+    #[test]
+    fn test_apply_unknown_key_is_rejected() {
+        let mut config = CliConfig::default();
+        let err = apply_config_value(&mut config, "porrt", "9000").unwrap_err();
+        assert!(err.to_string().contains("did you mean `port`?"));
+    }
+
+    #[test]
+    fn test_daemon_keys_are_told_apart_from_cli_keys() {
+        assert!(is_daemon_config_key("server.port"));
+        assert!(is_daemon_config_key("auth.required"));
+        assert!(!is_daemon_config_key("port"));
+
+        let keys = daemon_config_keys();
+        assert!(keys.contains(&"server.session.timeout".to_string()));
+        assert!(keys.contains(&"auth.native.require_group".to_string()));
+        assert!(!keys.iter().any(|key| key.starts_with("server.auth.")));
+    }
+
+    #[test]
+    fn test_set_nested_daemon_keys() {
+        let mut config = ServiceConfig::default();
+
+        apply_daemon_config_value(&mut config, "server.port", "9000").unwrap();
+        apply_daemon_config_value(&mut config, "server.tls.enabled", "true").unwrap();
+        apply_daemon_config_value(&mut config, "server.session.timeout", "120").unwrap();
+        apply_daemon_config_value(&mut config, "auth.required", "false").unwrap();
+        apply_daemon_config_value(&mut config, "auth.native.require_group", "rcp-users").unwrap();
+        apply_daemon_config_value(&mut config, "server.auth_failure_behavior", "tarpit").unwrap();
+        apply_daemon_config_value(&mut config, "auth.allowed_clients", "alpha, beta").unwrap();
+
+        assert_eq!(config.server.port, 9000);
+        assert!(config.server.tls.enabled);
+        assert_eq!(config.server.session.timeout, 120);
+        assert!(!config.server.auth.required);
+        assert_eq!(
+            config.server.auth.native.require_group.as_deref(),
+            Some("rcp-users")
+        );
+        assert_eq!(
+            config.server.auth_failure_behavior,
+            AuthFailureBehavior::Tarpit
+        );
+        assert_eq!(config.server.auth.allowed_clients, vec!["alpha", "beta"]);
+
+        // The long form of an auth key works too, and unset string options
+        // accept values that look like numbers
+        apply_daemon_config_value(&mut config, "server.auth.native.require_group", "1000").unwrap();
+        assert_eq!(
+            config.server.auth.native.require_group.as_deref(),
+            Some("1000")
+        );
+    }
+
+    #[test]
+    fn test_set_daemon_value_keeps_comments_and_hides_secrets() {
+        let path =
+            std::env::temp_dir().join(format!("rcpdaemon-config-set-{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"# Daemon settings
+address = "127.0.0.1"
+port = 8716 # the default
+
+[tls]
+enabled = false
+cert_path = "cert.pem"
+key_path = "key.pem"
+"#,
+        )
+        .unwrap();
+        let path_str = path.to_str().unwrap();
+
+        assert_eq!(
+            set_daemon_config_value("server.port", "9000", path_str).unwrap(),
+            "9000"
+        );
+        assert_eq!(
+            set_daemon_config_value("auth.psk", "hunter2", path_str).unwrap(),
+            rcpdaemon::server::config::REDACTED
+        );
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert!(text.starts_with("# Daemon settings\n"), "{}", text);
+        assert!(text.contains("port = 9000 # the default"), "{}", text);
+        let config = ServiceConfig::from_file(&path).unwrap();
+        assert_eq!(config.server.port, 9000);
+        assert_eq!(config.server.auth.psk.as_deref(), Some("hunter2"));
+
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_daemon_values_are_type_checked() {
+        let mut config = ServiceConfig::default();
+
+        for (key, value) in [
+            ("server.port", "70000"),
+            ("server.port", "high"),
+            ("server.tls.enabled", "yes"),
+            ("server.auth_failure_behavior", "explode"),
+        ] {
+            let err = apply_daemon_config_value(&mut config, key, value).unwrap_err();
+            assert!(
+                err.to_string()
+                    .contains(&format!("Invalid value for {}", key)),
+                "{}",
+                err
+            );
+        }
+
+        // A failed update leaves the config untouched
+        assert_eq!(config.server.port, ServiceConfig::default().server.port);
+        assert!(!config.server.tls.enabled);
+    }
+
+    #[test]
+    fn test_unknown_daemon_key_suggests_closest() {
+        let mut config = ServiceConfig::default();
+
+        let err = apply_daemon_config_value(&mut config, "server.prot", "9000").unwrap_err();
+        assert!(
+            err.to_string().contains("did you mean `server.port`?"),
+            "{}",
+            err
+        );
+
+        let err = apply_daemon_config_value(&mut config, "auth.requird", "true").unwrap_err();
+        assert!(
+            err.to_string().contains("did you mean `auth.required`?"),
+            "{}",
+            err
+        );
+
+        let err = apply_daemon_config_value(&mut config, "server.tls", "true").unwrap_err();
+        assert!(err.to_string().contains("is a section"));
+    }
+
+    #[test]
+    fn test_daemon_config_with_log_file_round_trips() {
+        let path =
+            std::env::temp_dir().join(format!("rcpdaemon-config-set-{}.toml", std::process::id()));
+
+        let mut config = ServiceConfig {
+            log_file: Some("/var/log/rcpdaemon.log".into()),
+            ..Default::default()
+        };
+        apply_daemon_config_value(&mut config, "server.port", "9001").unwrap();
+        config.to_file(&path).unwrap();
+
+        let loaded = ServiceConfig::from_file(&path).unwrap();
+        assert_eq!(loaded.server.port, 9001);
+        assert_eq!(loaded.log_file, config.log_file);
+
+        let _ = std::fs::remove_file(path);
+    }
+}
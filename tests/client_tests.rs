@@ -0,0 +1,480 @@
+//! Daemon client tests
+
+mod common;
+
+use bytes::Bytes;
+use common::open_config;
+use rcpdaemon::client::types::{CurrentSession, ServerInfo, SessionInfo};
+use rcpdaemon::client::{
+    parse_batch_response, parse_response, parse_result, Client, ClientError, MockTransport,
+};
+use rcpdaemon::protocol::goodbye::{DisconnectReason, Goodbye};
+use rcpdaemon::protocol::handshake::{
+    server_handshake, ProtocolVersion, ServerHello, PROTOCOL_MAGIC, PROTOCOL_VERSION,
+};
+use rcpdaemon::protocol::{codec, FrameError};
+use rcpdaemon::server::config::ServerConfig;
+use rcpdaemon::server::{rpc, Server};
+use std::net::{IpAddr, Ipv6Addr};
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpListener;
+use uuid::Uuid;
+
+#[test]
+fn test_parse_response_result() {
+    let result = parse_response(r#"{"jsonrpc":"2.0","id":"1","result":{"ok":true}}"#).unwrap();
+    assert_eq!(result["ok"], true);
+}
+
+#[test]
+fn test_parse_response_error() {
+    let err = parse_response(
+        r#"{"jsonrpc":"2.0","id":"1","error":{"code":-32601,"message":"Method not found: x"}}"#,
+    )
+    .unwrap_err();
+
+    match err {
+        ClientError::Rpc { code, message } => {
+            assert_eq!(code, -32601);
+            assert_eq!(message, "Method not found: x");
+        }
+        other => panic!("unexpected error: {:?}", other),
+    }
+}
+
+#[test]
+fn test_parse_response_permission_error() {
+    let err = parse_response(
+        r#"{"jsonrpc":"2.0","id":"1","error":{"code":-32003,"message":"Operation not permitted","data":{"required":"app:word","granted":["connect:*","app:office"]}}}"#,
+    )
+    .unwrap_err();
+
+    match err {
+        ClientError::PermissionDenied(permission) => {
+            assert_eq!(permission.required, "app:word");
+            assert_eq!(permission.granted, vec!["connect:*", "app:office"]);
+        }
+        other => panic!("unexpected error: {:?}", other),
+    }
+    assert_eq!(
+        parse_response(
+            r#"{"jsonrpc":"2.0","id":"1","error":{"code":-32003,"message":"x","data":{"required":"app:word"}}}"#
+        )
+        .unwrap_err()
+        .to_string(),
+        "operation requires permission app:word"
+    );
+
+    // Permission-denied errors without details stay plain RPC errors
+    let err = parse_response(
+        r#"{"jsonrpc":"2.0","id":"1","error":{"code":-32003,"message":"Operation not permitted: x"}}"#,
+    )
+    .unwrap_err();
+    assert!(matches!(err, ClientError::Rpc { code: -32003, .. }));
+}
+
+#[test]
+fn test_parse_response_without_result() {
+    let err = parse_response(r#"{"jsonrpc":"2.0","id":"1"}"#).unwrap_err();
+    assert!(matches!(err, ClientError::InvalidResponse(_)));
+}
+
+#[test]
+fn test_parse_result_names_method_and_field() {
+    let err = parse_result::<ServerInfo>(
+        "server/info",
+        serde_json::json!({
+            "version": "0.1.0",
+            "uptime": "1s",
+            "address": "127.0.0.1",
+            "port": 8716,
+            "active_sessions": 0,
+            "total_sessions": 0
+        }),
+    )
+    .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Failed to parse server/info response: missing field `tls_enabled`"
+    );
+
+    let err = parse_result::<Vec<ServerInfo>>(
+        "test/infos",
+        serde_json::json!([{
+            "version": "0.1.0",
+            "uptime": "1s",
+            "address": "127.0.0.1",
+            "port": "eighty",
+            "tls_enabled": false,
+            "active_sessions": 0,
+            "total_sessions": 0
+        }]),
+    )
+    .unwrap_err();
+    match err {
+        ClientError::UnexpectedResult { method, message } => {
+            assert_eq!(method, "test/infos");
+            assert!(message.starts_with("[0].port: invalid type"), "{}", message);
+        }
+        other => panic!("unexpected error: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_malformed_result_from_server_is_explained() {
+    // A daemon of another version answering with an older shape
+    let server = Server::builder()
+        .config(open_config())
+        .rpc_method("server/info", |_, _| async {
+            Ok(serde_json::json!({ "version": "0.0.1", "uptime": "1s" }))
+        })
+        .build();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(server.serve(listener));
+
+    let client = Client::new(addr.ip().to_string(), addr.port(), 5);
+    let err = client.get_server_info().await.unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Failed to parse server/info response: missing field `address`"
+    );
+}
+
+#[tokio::test]
+async fn test_client_round_trip_against_server() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(Server::new(open_config()).serve(listener));
+
+    let client = Client::new(addr.ip().to_string(), addr.port(), 5);
+
+    let err = client
+        .get_app_logs(&Uuid::new_v4().to_string(), 10, None)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, ClientError::Rpc { code, .. } if code == rpc::NOT_FOUND));
+
+    let err = client
+        .call_raw("does/not/exist", serde_json::Value::Null)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, ClientError::Rpc { code, .. } if code == rpc::METHOD_NOT_FOUND));
+}
+
+#[tokio::test]
+async fn test_client_reports_connection_failure() {
+    // Bind and drop a listener to get a port nothing is listening on
+    let port = {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        listener.local_addr().unwrap().port()
+    };
+
+    let client = Client::new("127.0.0.1".to_string(), port, 5);
+    let err = client.get_status().await.unwrap_err();
+    assert!(matches!(err, ClientError::ConnectionRefused(_)));
+}
+
+/// A client whose requests go to `transport`, never to the network
+fn mock_client(transport: &MockTransport, timeout_seconds: u64) -> Client {
+    Client::new("mock".to_string(), 0, timeout_seconds).with_transport(transport.clone())
+}
+
+#[tokio::test]
+async fn test_mock_transport_successful_call() {
+    let transport = MockTransport::new().with_result(serde_json::json!({
+        "running": true,
+        "pid": 42,
+        "uptime": "5s",
+        "version": "1.2.3"
+    }));
+    let client = mock_client(&transport, 5).with_auth(Some("secret".to_string()));
+
+    let status = client.get_status().await.unwrap();
+    assert!(status.running);
+    assert_eq!(status.pid, Some(42));
+    assert_eq!(status.version, "1.2.3");
+
+    // The request is well-formed JSON-RPC and carries the auth token
+    let requests = transport.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].auth.as_deref(), Some("secret"));
+    let request: serde_json::Value = serde_json::from_str(&requests[0].body).unwrap();
+    assert_eq!(request["jsonrpc"], "2.0");
+    assert_eq!(request["method"], "status");
+    assert_eq!(request["auth"], "secret");
+    assert!(request["id"].is_string());
+}
+
+#[tokio::test]
+async fn test_mock_transport_rpc_error() {
+    let transport = MockTransport::new().with_rpc_error(rpc::NOT_FOUND, "No such session");
+    let client = mock_client(&transport, 5);
+
+    match client.disconnect_session("abc").await.unwrap_err() {
+        ClientError::Rpc { code, message } => {
+            assert_eq!(code, rpc::NOT_FOUND);
+            assert_eq!(message, "No such session");
+        }
+        other => panic!("unexpected error: {:?}", other),
+    }
+    let request: serde_json::Value = serde_json::from_str(&transport.requests()[0].body).unwrap();
+    assert_eq!(request["params"]["session_id"], "abc");
+    assert!(request["auth"].is_null());
+
+    // Once the queue runs out, requests fail like an unreachable daemon
+    assert!(matches!(
+        client.ping().await.unwrap_err(),
+        ClientError::Connection(_)
+    ));
+}
+
+#[tokio::test]
+async fn test_mock_transport_timeout() {
+    let transport = MockTransport::new().with_hang();
+    let client = mock_client(&transport, 1);
+
+    let started = std::time::Instant::now();
+    let err = client.get_status().await.unwrap_err();
+    assert!(matches!(err, ClientError::Timeout(1)));
+    assert!(started.elapsed() < std::time::Duration::from_secs(3));
+}
+
+#[test]
+fn test_parse_batch_response_matches_ids() {
+    let ids = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+    let results = parse_batch_response(
+        r#"[
+            {"jsonrpc":"2.0","id":"b","error":{"code":-32601,"message":"Method not found: x"}},
+            {"jsonrpc":"2.0","id":"a","result":1}
+        ]"#,
+        &ids,
+    )
+    .unwrap();
+
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[0].as_ref().unwrap(), 1);
+    assert!(matches!(
+        results[1],
+        Err(ClientError::Rpc { code: -32601, .. })
+    ));
+    assert!(matches!(results[2], Err(ClientError::InvalidResponse(_))));
+}
+
+#[test]
+fn test_parse_batch_response_whole_batch_error() {
+    let err = parse_batch_response(
+        r#"{"jsonrpc":"2.0","id":null,"error":{"code":-32600,"message":"Empty batch"}}"#,
+        &[],
+    )
+    .unwrap_err();
+
+    assert!(matches!(err, ClientError::Rpc { code: -32600, .. }));
+}
+
+#[tokio::test]
+async fn test_batch_round_trip_against_server() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(Server::new(open_config()).serve(listener));
+
+    let client = Client::new(addr.ip().to_string(), addr.port(), 5);
+    let level = log::max_level().to_string().to_lowercase();
+
+    let results = client
+        .call_batch(vec![
+            ("diag/set_log_level", serde_json::json!({ "level": level })),
+            ("does/not/exist", serde_json::Value::Null),
+            (
+                "apps/logs",
+                serde_json::json!({ "instance_id": Uuid::new_v4() }),
+            ),
+        ])
+        .await
+        .unwrap();
+
+    // The failing items do not affect the successful one
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[0].as_ref().unwrap()["level"], level);
+    assert!(
+        matches!(results[1], Err(ClientError::Rpc { code, .. }) if code == rpc::METHOD_NOT_FOUND)
+    );
+    assert!(matches!(results[2], Err(ClientError::Rpc { code, .. }) if code == rpc::NOT_FOUND));
+}
+
+/// Accept one connection and answer its hello as a daemon speaking `version`
+async fn spawn_daemon_with_version(version: ProtocolVersion) -> std::net::SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut framed = codec::framed(stream, codec::DEFAULT_MAX_FRAME_LENGTH);
+        let mut magic = [0u8; PROTOCOL_MAGIC.len()];
+        framed.get_mut().read_exact(&mut magic).await.unwrap();
+        assert_eq!(magic, PROTOCOL_MAGIC);
+        codec::read_frame(&mut framed).await.unwrap();
+
+        let hello = ServerHello {
+            protocol_version: version,
+            compression: None,
+            compression_threshold: 0,
+            challenge: None,
+        };
+        let payload = serde_json::to_vec(&hello).unwrap();
+        codec::write_frame(&mut framed, Bytes::from(payload))
+            .await
+            .unwrap();
+    });
+
+    addr
+}
+
+#[tokio::test]
+async fn test_client_refuses_incompatible_daemon() {
+    let daemon = ProtocolVersion::new(PROTOCOL_VERSION.major + 1, 0);
+    let addr = spawn_daemon_with_version(daemon).await;
+    let client = Client::new(addr.ip().to_string(), addr.port(), 5);
+
+    let err = client.get_status().await.unwrap_err();
+    match &err {
+        ClientError::IncompatibleProtocol {
+            client,
+            daemon: reported,
+        } => {
+            assert_eq!(*client, PROTOCOL_VERSION);
+            assert_eq!(*reported, daemon);
+        }
+        other => panic!("expected a version mismatch, got {:?}", other),
+    }
+    assert!(err.to_string().contains("upgrade the CLI"));
+}
+
+#[tokio::test]
+async fn test_client_refuses_older_daemon() {
+    let addr = spawn_daemon_with_version(ProtocolVersion::new(PROTOCOL_VERSION.major - 1, 0)).await;
+    let client = Client::new(addr.ip().to_string(), addr.port(), 5);
+
+    let err = client.get_status().await.unwrap_err();
+    assert!(matches!(err, ClientError::IncompatibleProtocol { .. }));
+    assert!(err.to_string().contains("upgrade the daemon"));
+}
+
+#[tokio::test]
+async fn test_psk_client_refuses_daemon_without_challenge() {
+    // A 1.0 daemon issues no challenge, so there is nothing to prove the key against
+    let addr = spawn_daemon_with_version(ProtocolVersion::new(PROTOCOL_VERSION.major, 0)).await;
+    let client =
+        Client::new(addr.ip().to_string(), addr.port(), 5).with_auth(Some("secret".to_string()));
+
+    let err = client.get_status().await.unwrap_err();
+    assert!(
+        matches!(
+            err,
+            ClientError::Frame(FrameError::ChallengeUnsupported(version))
+                if version == ProtocolVersion::new(PROTOCOL_VERSION.major, 0)
+        ),
+        "{:?}",
+        err
+    );
+    assert!(err.to_string().contains("does not support PSK challenge"));
+}
+
+#[tokio::test]
+async fn test_client_accepts_same_version_daemon() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(Server::new(open_config()).serve(listener));
+
+    let client = Client::new(addr.ip().to_string(), addr.port(), 5);
+    let level = log::max_level().to_string().to_lowercase();
+    assert_eq!(client.set_log_level(&level).await.unwrap(), level);
+}
+
+#[tokio::test]
+async fn test_client_reports_server_goodbye() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    // A daemon that answers the request by saying goodbye and closing
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut framed = codec::framed(stream, codec::DEFAULT_MAX_FRAME_LENGTH);
+        let (_hello, compressor) = server_handshake(&mut framed, &[], 0).await.unwrap();
+        codec::read_frame(&mut framed).await.unwrap();
+
+        let goodbye = Goodbye::new(DisconnectReason::IdleTimeout);
+        codec::write_frame(
+            &mut framed,
+            compressor.encode(goodbye.to_payload()).unwrap(),
+        )
+        .await
+        .unwrap();
+    });
+
+    let client = Client::new(addr.ip().to_string(), addr.port(), 5);
+    let err = client.get_status().await.unwrap_err();
+    match &err {
+        ClientError::Disconnected(goodbye) => {
+            assert_eq!(goodbye.reason, DisconnectReason::IdleTimeout)
+        }
+        other => panic!("expected a goodbye, got {:?}", other),
+    }
+    assert_eq!(err.to_string(), "disconnected by server: idle timeout");
+}
+
+#[tokio::test]
+async fn test_running_config_has_secrets_redacted() {
+    let mut config = ServerConfig::default();
+    config.auth.psk = Some("hunter2".to_string());
+    config.session.max_sessions = 7;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(Server::new(config).serve(listener));
+
+    let client =
+        Client::new(addr.ip().to_string(), addr.port(), 5).with_auth(Some("hunter2".to_string()));
+    let running = client.get_running_config().await.unwrap();
+
+    assert_eq!(running["session"]["max_sessions"], 7);
+    assert_eq!(running["auth"]["psk"], rcpdaemon::server::config::REDACTED);
+    assert!(!running.to_string().contains("hunter2"));
+}
+
+#[tokio::test]
+async fn test_client_connects_over_ipv6() {
+    // Skip on hosts without an IPv6 loopback
+    let Ok(listener) = TcpListener::bind("[::1]:0").await else {
+        return;
+    };
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(Server::new(open_config()).serve(listener));
+
+    let client = Client::new("::1".to_string(), addr.port(), 5);
+    let session = client.session_info().await.unwrap();
+    assert_eq!(session.client_ip, Some(IpAddr::V6(Ipv6Addr::LOCALHOST)));
+
+    let client_addr = session.client_addr().unwrap();
+    assert!(client_addr.is_ipv6());
+    assert_ne!(client_addr.port(), 0);
+}
+
+#[test]
+fn test_session_types_carry_ipv6_addresses() {
+    let info: SessionInfo = serde_json::from_str(
+        r#"{"id":"s1","user_id":"u1","username":"alice","client_ip":"2001:db8::5","client_port":8716,
+            "created_at":"","expires_at":"","last_active":"","active":true}"#,
+    )
+    .unwrap();
+    assert_eq!(info.client_ip, "2001:db8::5".parse::<IpAddr>().unwrap());
+    assert_eq!(info.client_address(), "[2001:db8::5]:8716");
+
+    // Older daemons report no port
+    let current: CurrentSession = serde_json::from_str(
+        r#"{"session_id":"s1","user":null,"client_ip":"::1","permissions":[],"token_id":null}"#,
+    )
+    .unwrap();
+    assert_eq!(current.client_ip, Some(IpAddr::V6(Ipv6Addr::LOCALHOST)));
+    assert_eq!(current.client_addr(), None);
+}
@@ -0,0 +1,281 @@
+//! Cluster peer awareness tests
+
+use bytes::Bytes;
+use rcpdaemon::client::{Client, ClientError};
+use rcpdaemon::protocol::codec;
+use rcpdaemon::protocol::handshake::server_handshake;
+use rcpdaemon::server::cluster::{
+    parse_peer_address, Announcement, PeerHealth, PeerTable, ANNOUNCE_PERMISSION,
+    MAX_ANNOUNCED_PEERS,
+};
+use rcpdaemon::server::config::ServerConfig;
+use rcpdaemon::server::Server;
+use serde_json::Value;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::oneshot;
+
+/// Answer one `cluster/announce` with `health`, passing on what was announced
+async fn spawn_mock_peer(health: PeerHealth) -> (SocketAddr, oneshot::Receiver<Value>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (tx, rx) = oneshot::channel();
+
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut framed = codec::framed(stream, codec::DEFAULT_MAX_FRAME_LENGTH);
+        let (_hello, compressor) = server_handshake(&mut framed, &[], 0).await.unwrap();
+        let frame = codec::read_frame(&mut framed).await.unwrap();
+        let request: Value = serde_json::from_slice(&compressor.decode(frame).unwrap()).unwrap();
+        assert_eq!(request["method"], "cluster/announce");
+
+        let response = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": request["id"],
+            "result": health,
+        });
+        let _ = tx.send(request["params"].clone());
+        codec::write_frame(
+            &mut framed,
+            compressor
+                .encode(Bytes::from(response.to_string()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    });
+
+    (addr, rx)
+}
+
+/// An address nothing listens on
+async fn closed_address() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    listener.local_addr().unwrap()
+}
+
+#[test]
+fn test_parse_peer_address() {
+    assert_eq!(
+        parse_peer_address("10.0.0.5:8716"),
+        Some(("10.0.0.5".to_string(), 8716))
+    );
+    assert_eq!(
+        parse_peer_address("[::1]:8716"),
+        Some(("::1".to_string(), 8716))
+    );
+    assert_eq!(
+        parse_peer_address("daemon-2.internal:9000"),
+        Some(("daemon-2.internal".to_string(), 9000))
+    );
+    assert_eq!(parse_peer_address("daemon-2.internal"), None);
+    assert_eq!(parse_peer_address(":8716"), None);
+    assert_eq!(parse_peer_address("host:port"), None);
+}
+
+#[test]
+fn test_peer_table_tracks_probes_and_announcements() {
+    let table = PeerTable::new(&["10.0.0.5:8716".to_string()]);
+    assert_eq!(table.configured(), vec!["10.0.0.5:8716"]);
+    assert!(!table.list()[0].reachable);
+
+    let health = PeerHealth {
+        version: "1.0.0".to_string(),
+        active_sessions: 3,
+        uptime_secs: 60,
+    };
+    table.record_probe(
+        "10.0.0.5:8716",
+        Ok((health.clone(), Duration::from_millis(12))),
+    );
+    let peer = &table.list()[0];
+    assert!(peer.reachable);
+    assert_eq!(peer.latency_ms, Some(12));
+    assert_eq!(peer.health.as_ref().unwrap().active_sessions, 3);
+
+    // A failed probe keeps the last reported health but not the latency
+    table.record_probe("10.0.0.5:8716", Err("connection refused".to_string()));
+    let peer = &table.list()[0];
+    assert!(!peer.reachable);
+    assert_eq!(peer.latency_ms, None);
+    assert_eq!(peer.last_error.as_deref(), Some("connection refused"));
+    assert!(peer.health.is_some());
+
+    // Peers that announce themselves are listed but never probed
+    table.record_announcement(Announcement {
+        address: "10.0.0.6:8716".to_string(),
+        health,
+    });
+    let peers = table.list();
+    assert_eq!(peers.len(), 2);
+    assert!(!peers[1].configured && peers[1].reachable);
+    assert_eq!(table.configured(), vec!["10.0.0.5:8716"]);
+}
+
+#[test]
+fn test_announced_peers_are_capped() {
+    let table = PeerTable::new(&["10.0.0.5:8716".to_string()]);
+    let health = PeerHealth {
+        version: "1.2.3".to_string(),
+        active_sessions: 0,
+        uptime_secs: 1,
+    };
+    for i in 0..MAX_ANNOUNCED_PEERS + 5 {
+        table.record_announcement(Announcement {
+            address: format!("10.1.0.{}:8716", i),
+            health: health.clone(),
+        });
+    }
+
+    // Configured peers are never evicted to make room
+    let peers = table.list();
+    assert_eq!(peers.len(), MAX_ANNOUNCED_PEERS + 1);
+    assert_eq!(table.configured(), vec!["10.0.0.5:8716"]);
+    let last = format!("10.1.0.{}:8716", MAX_ANNOUNCED_PEERS + 4);
+    assert!(peers.iter().any(|peer| peer.address == last));
+}
+
+#[tokio::test]
+async fn test_announce_requires_admin_cluster() {
+    let server = Server::new(ServerConfig::default());
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(server.clone().serve(listener));
+
+    let client = |permission: &str| {
+        let token = server.tokens().issue(
+            "peer",
+            vec![permission.to_string()],
+            Duration::from_secs(60),
+        );
+        Client::new(addr.ip().to_string(), addr.port(), 5).with_auth(Some(token))
+    };
+    let announcement = serde_json::json!({
+        "address": "10.0.0.9:8716",
+        "health": { "version": "1.2.3", "active_sessions": 0, "uptime_secs": 1 },
+    });
+
+    match client("read:cluster")
+        .call_raw("cluster/announce", announcement.clone())
+        .await
+        .unwrap_err()
+    {
+        ClientError::PermissionDenied(permission) => {
+            assert_eq!(permission.required, ANNOUNCE_PERMISSION)
+        }
+        other => panic!("unexpected error: {:?}", other),
+    }
+    assert!(server.peers().list().is_empty());
+
+    client(ANNOUNCE_PERMISSION)
+        .call_raw("cluster/announce", announcement)
+        .await
+        .unwrap();
+    assert_eq!(server.peers().list()[0].address, "10.0.0.9:8716");
+}
+
+#[tokio::test]
+async fn test_probe_records_peer_health() {
+    let mocked = PeerHealth {
+        version: "9.9.9".to_string(),
+        active_sessions: 7,
+        uptime_secs: 3600,
+    };
+    let (mock_addr, announced) = spawn_mock_peer(mocked.clone()).await;
+    let down = closed_address().await;
+
+    let mut config = ServerConfig::default();
+    config.cluster.peers = vec![mock_addr.to_string(), down.to_string()];
+    config.cluster.advertise_address = Some("daemon-1.internal:8716".to_string());
+    config.cluster.probe_timeout_secs = 2;
+    let server = Server::new(config);
+    server.probe_peers().await;
+
+    // The peer is told who is asking
+    let announced: Announcement = serde_json::from_value(announced.await.unwrap()).unwrap();
+    assert_eq!(announced.address, "daemon-1.internal:8716");
+
+    let peers = server.peers().list();
+    let up = peers
+        .iter()
+        .find(|p| p.address == mock_addr.to_string())
+        .unwrap();
+    assert!(up.reachable);
+    assert_eq!(up.health.as_ref(), Some(&mocked));
+    assert!(up.latency_ms.is_some());
+    assert!(up.last_seen.is_some());
+
+    let unreachable = peers
+        .iter()
+        .find(|p| p.address == down.to_string())
+        .unwrap();
+    assert!(!unreachable.reachable);
+    assert!(unreachable.last_error.is_some());
+}
+
+#[tokio::test]
+async fn test_daemons_learn_about_each_other() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let peer_addr = listener.local_addr().unwrap();
+    let mut config = ServerConfig::default();
+    config.auth.required = false;
+    let peer = Server::new(config);
+    tokio::spawn(peer.clone().serve(listener));
+
+    let mut config = ServerConfig::default();
+    config.auth.required = false;
+    config.cluster.peers = vec![peer_addr.to_string()];
+    config.cluster.advertise_address = Some("daemon-1.internal:8716".to_string());
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = Server::new(config);
+    tokio::spawn(server.clone().serve(listener));
+
+    // Serving starts the periodic probe, whose first round runs at once
+    for _ in 0..100 {
+        if server.peers().list()[0].reachable {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    // The probing daemon reports the peer over cluster/peers
+    let peers = Client::new(addr.ip().to_string(), addr.port(), 5)
+        .cluster_peers()
+        .await
+        .unwrap();
+    assert_eq!(peers.len(), 1);
+    assert!(peers[0].configured && peers[0].reachable);
+    let health = peers[0].health.as_ref().unwrap();
+    assert_eq!(health.version, env!("CARGO_PKG_VERSION"));
+    assert_eq!(health.active_sessions, 1);
+
+    // The probed peer lists the announcing daemon without probing it
+    let announced = peer.peers().list();
+    assert_eq!(announced.len(), 1);
+    assert_eq!(announced[0].address, "daemon-1.internal:8716");
+    assert!(!announced[0].configured);
+}
+
+#[test]
+fn test_cluster_settings_from_toml() {
+    let config: ServerConfig = toml::from_str(
+        r#"
+        [cluster]
+        peers = ["10.0.0.5:8716", "10.0.0.6:8716"]
+        peer_token = "peer-secret"
+        "#,
+    )
+    .unwrap();
+    assert_eq!(config.cluster.peers.len(), 2);
+    assert_eq!(config.cluster.probe_interval_secs, 30);
+    assert_eq!(
+        config.to_redacted_json()["cluster"]["peer_token"],
+        "<redacted>"
+    );
+
+    // Without the section nothing is probed
+    let config: ServerConfig = toml::from_str("").unwrap();
+    assert!(config.cluster.peers.is_empty());
+}
@@ -0,0 +1,10 @@
+//! Helpers shared by the integration tests
+
+use rcpdaemon::server::config::ServerConfig;
+
+/// Default config letting clients in without a credential, as the operator
+pub fn open_config() -> ServerConfig {
+    let mut config = ServerConfig::default();
+    config.auth.required = false;
+    config
+}
@@ -29,9 +29,13 @@ fn test_custom_service_config() {
         address: "0.0.0.0".to_string(),
         port: 9999,
         tls: tls_config,
+        log_file: None,
+        pid_file: None,
+        max_total_connections: 0,
         server: server::config::ServerConfig::default(),
         #[cfg(feature = "api")]
         api: None,
+        loaded_from: None,
     };
 
     // Verify custom configuration values
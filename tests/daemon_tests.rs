@@ -0,0 +1,147 @@
+//! Daemon control tests
+
+use rcpdaemon::client::Client;
+use rcpdaemon::daemon::{wait_until_ready, DaemonStatus};
+use rcpdaemon::server::config::ServerConfig;
+use rcpdaemon::server::Server;
+use std::time::{Duration, Instant};
+use tokio::net::TcpListener;
+
+/// A port nothing listens on, for now
+async fn free_port() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    listener.local_addr().unwrap().port()
+}
+
+#[test]
+fn test_daemon_status_display() {
+    let running = DaemonStatus {
+        running: true,
+        pid: Some(4242),
+        stale_pid_file: false,
+    };
+    assert_eq!(running.to_string(), "Running (PID: 4242)");
+
+    let stale = DaemonStatus {
+        running: false,
+        pid: Some(4242),
+        stale_pid_file: true,
+    };
+    assert_eq!(stale.to_string(), "Not running (stale PID file)");
+
+    let stopped = DaemonStatus {
+        running: false,
+        pid: None,
+        stale_pid_file: false,
+    };
+    assert_eq!(stopped.to_string(), "Not running");
+}
+
+#[test]
+fn test_daemon_status_json() {
+    let status = DaemonStatus {
+        running: true,
+        pid: Some(4242),
+        stale_pid_file: false,
+    };
+
+    assert_eq!(
+        serde_json::to_value(&status).unwrap(),
+        serde_json::json!({ "running": true, "pid": 4242, "stale_pid_file": false })
+    );
+}
+
+#[tokio::test]
+async fn test_wait_until_ready_waits_for_slow_daemon() {
+    let port = free_port().await;
+
+    // A daemon that takes a while to bind its port
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        let listener = TcpListener::bind(("127.0.0.1", port)).await.unwrap();
+        let mut config = ServerConfig::default();
+        config.auth.required = false;
+        Server::new(config).serve(listener).await
+    });
+
+    let client = Client::new("127.0.0.1".to_string(), port, 5);
+    assert!(client.ping().await.is_err());
+    let elapsed = wait_until_ready(&client, Duration::from_secs(10))
+        .await
+        .unwrap();
+    assert!(elapsed >= Duration::from_millis(500));
+    client.ping().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_wait_until_ready_gives_up_after_timeout() {
+    let client = Client::new("127.0.0.1".to_string(), free_port().await, 5);
+
+    let started = Instant::now();
+    let err = wait_until_ready(&client, Duration::from_millis(300))
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("did not become ready"), "{}", err);
+    assert!(started.elapsed() < Duration::from_secs(2));
+}
+
+/// Arguments of the daemon control commands, which differ with the CLI
+#[cfg(feature = "cli")]
+const START: &[&str] = &["daemon", "start"];
+#[cfg(not(feature = "cli"))]
+const START: &[&str] = &["start"];
+#[cfg(feature = "cli")]
+const STOP: &[&str] = &["daemon", "stop"];
+#[cfg(not(feature = "cli"))]
+const STOP: &[&str] = &["stop"];
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_daemon_writes_pid_file_at_overridden_path() {
+    use rcpdaemon::config::ServiceConfig;
+    use rcpdaemon::daemon::{daemon_status, read_pid, wait_for_exit};
+    use std::process::Command;
+
+    let id = std::process::id();
+    let dir = std::env::temp_dir();
+    let config_path = dir.join(format!("rcpdaemon-pid-test-{}.toml", id));
+    let pid_file = dir.join(format!("rcpdaemon-pid-test-{}.pid", id));
+    let mut config = ServiceConfig {
+        port: free_port().await,
+        ..Default::default()
+    };
+    config.server.port = free_port().await;
+    #[cfg(feature = "api")]
+    {
+        config.api = None;
+    }
+    config.to_file(&config_path).unwrap();
+
+    let daemon = |command: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_rcpdaemon"))
+            .args(["--config", config_path.to_str().unwrap()])
+            .args(["--pid-file", pid_file.to_str().unwrap()])
+            .args(command)
+            .output()
+            .unwrap()
+    };
+
+    let output = daemon(START);
+    assert!(output.status.success(), "{:?}", output);
+    let pid = read_pid(&pid_file)
+        .unwrap()
+        .expect("no PID file at --pid-file");
+    assert!(
+        String::from_utf8_lossy(&output.stdout).contains(&format!("PID: {}", pid)),
+        "{:?}",
+        output
+    );
+    assert!(daemon_status(&pid_file).unwrap().running);
+
+    let output = daemon(STOP);
+    assert!(output.status.success(), "{:?}", output);
+    wait_for_exit(pid, Duration::from_secs(10)).await.unwrap();
+    assert!(!pid_file.exists());
+
+    let _ = std::fs::remove_file(&config_path);
+}
@@ -0,0 +1,355 @@
+//! RPC dispatcher tests
+
+mod common;
+
+use common::open_config;
+use rcpdaemon::client::{Client, ClientError};
+use rcpdaemon::server::config::ServerConfig;
+use rcpdaemon::server::dispatch::{format_uptime, RpcContext, RpcDispatcher};
+use rcpdaemon::server::rpc::{self, RpcError};
+use rcpdaemon::server::session::{MANAGE_SESSIONS_PERMISSION, READ_SESSIONS_PERMISSION};
+use rcpdaemon::server::Server;
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use uuid::Uuid;
+
+fn context(server: &Server, permissions: &[&str]) -> RpcContext {
+    RpcContext {
+        server: server.clone(),
+        session_id: Uuid::new_v4(),
+        client_name: None,
+        permissions: permissions.iter().map(|p| p.to_string()).collect(),
+    }
+}
+
+#[tokio::test]
+async fn test_core_handlers_are_registered() {
+    let dispatcher = RpcDispatcher::with_core_handlers();
+    assert_eq!(
+        dispatcher.methods(),
+        vec![
+            "metrics",
+            "ping",
+            "server/config",
+            "server/config/update",
+            "server/info",
+            "server/restart",
+            "sessions/info",
+            "sessions/list",
+            "status",
+            "users/export",
+            "users/import"
+        ]
+    );
+
+    let server = Server::new(ServerConfig::default());
+    let pong = dispatcher
+        .dispatch("ping", Value::Null, context(&server, &[]))
+        .await
+        .unwrap();
+    assert_eq!(pong, "pong");
+
+    let status = dispatcher
+        .dispatch("status", Value::Null, context(&server, &[]))
+        .await
+        .unwrap();
+    assert_eq!(status["pid"], std::process::id());
+    assert_eq!(status["version"], env!("CARGO_PKG_VERSION"));
+
+    // Handlers see the caller's permissions
+    let err = dispatcher
+        .dispatch("sessions/list", Value::Null, context(&server, &["app:*"]))
+        .await
+        .unwrap_err();
+    assert_eq!(err.code, rpc::PERMISSION_DENIED);
+    let sessions = dispatcher
+        .dispatch(
+            "sessions/list",
+            Value::Null,
+            context(&server, &[MANAGE_SESSIONS_PERMISSION]),
+        )
+        .await
+        .unwrap();
+    assert_eq!(sessions, json!([]));
+
+    let err = dispatcher
+        .dispatch("no/such/method", Value::Null, context(&server, &[]))
+        .await
+        .unwrap_err();
+    assert_eq!(err.code, rpc::METHOD_NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_registered_handler_replaces_earlier_one() {
+    let mut dispatcher = RpcDispatcher::with_core_handlers();
+    dispatcher.register("ping", |_, _| async { Ok(json!("custom pong")) });
+    dispatcher.register("echo", |params, _| async move { Ok(params) });
+    assert!(dispatcher.contains("echo"));
+
+    let server = Server::new(ServerConfig::default());
+    let reply = dispatcher
+        .dispatch("ping", Value::Null, context(&server, &[]))
+        .await
+        .unwrap();
+    assert_eq!(reply, "custom pong");
+    let reply = dispatcher
+        .dispatch("echo", json!({ "n": 1 }), context(&server, &[]))
+        .await
+        .unwrap();
+    assert_eq!(reply, json!({ "n": 1 }));
+}
+
+#[tokio::test]
+async fn test_fingerprinted_config_needs_admin_config() {
+    use rcpdaemon::server::config::REDACTED;
+    use rcpdaemon::server::live_config::{CONFIG_PERMISSION, READ_CONFIG_PERMISSION};
+
+    let dispatcher = RpcDispatcher::with_core_handlers();
+    let mut config = ServerConfig::default();
+    config.auth.psk = Some("hunter2".to_string());
+    let server = Server::new(config);
+    let salted = json!({ "secret_salt": "salt" });
+
+    let redacted = dispatcher
+        .dispatch(
+            "server/config",
+            Value::Null,
+            context(&server, &[READ_CONFIG_PERMISSION]),
+        )
+        .await
+        .unwrap();
+    assert_eq!(redacted["auth"]["psk"], REDACTED);
+
+    let err = dispatcher
+        .dispatch(
+            "server/config",
+            salted.clone(),
+            context(&server, &[READ_CONFIG_PERMISSION]),
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(err.code, rpc::PERMISSION_DENIED);
+
+    let fingerprinted = dispatcher
+        .dispatch(
+            "server/config",
+            salted,
+            context(&server, &[CONFIG_PERMISSION]),
+        )
+        .await
+        .unwrap();
+    assert_eq!(fingerprinted, server.config().to_fingerprinted_json("salt"));
+    assert!(!fingerprinted.to_string().contains("hunter2"));
+}
+
+#[tokio::test]
+async fn test_sessions_serve_registered_methods() {
+    let server = Server::builder()
+        .config(open_config())
+        .rpc_method("test/whoami", |_, ctx: RpcContext| async move {
+            Ok(json!({ "session_id": ctx.session_id }))
+        })
+        .rpc_method("test/fail", |_, _| async {
+            Err(RpcError::invalid_params("always fails"))
+        })
+        .build();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(server.clone().serve(listener));
+    let client = Client::new(addr.ip().to_string(), addr.port(), 5);
+
+    // The handler gets the calling session's context
+    let reply = client.call_raw("test/whoami", Value::Null).await.unwrap();
+    let session_id: Uuid = serde_json::from_value(reply["session_id"].clone()).unwrap();
+    assert_ne!(session_id, Uuid::nil());
+
+    match client.call_raw("test/fail", Value::Null).await.unwrap_err() {
+        ClientError::Rpc { code, message } => {
+            assert_eq!(code, rpc::INVALID_PARAMS);
+            assert_eq!(message, "always fails");
+        }
+        other => panic!("unexpected error: {:?}", other),
+    }
+
+    // Core methods are still served alongside
+    let info = client.get_server_info().await.unwrap();
+    assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+    assert!(info.total_sessions >= 2);
+    assert!(client.get_status().await.unwrap().running);
+    client.ping().await.unwrap();
+}
+
+/// Serve a `test/slow` method recording how many calls run at once
+async fn spawn_slow_server(max_inflight_rpcs: usize) -> (Client, Arc<AtomicUsize>) {
+    let running = Arc::new(AtomicUsize::new(0));
+    let peak = Arc::new(AtomicUsize::new(0));
+
+    let mut config = open_config();
+    config.session.max_inflight_rpcs = max_inflight_rpcs;
+    let server = Server::builder()
+        .config(config)
+        .rpc_method("test/slow", {
+            let peak = peak.clone();
+            move |params, _| {
+                let (running, peak) = (running.clone(), peak.clone());
+                async move {
+                    let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    running.fetch_sub(1, Ordering::SeqCst);
+                    Ok(params)
+                }
+            }
+        })
+        .build();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(server.serve(listener));
+
+    (Client::new(addr.ip().to_string(), addr.port(), 10), peak)
+}
+
+#[tokio::test]
+async fn test_batch_runs_with_bounded_concurrency() {
+    let (client, peak) = spawn_slow_server(4).await;
+    let calls = (0..32).map(|i| ("test/slow", json!({ "n": i }))).collect();
+
+    let started = std::time::Instant::now();
+    let results = client.call_batch(calls).await.unwrap();
+
+    // Every call is answered, in order, though no more than four ran at once
+    assert_eq!(results.len(), 32);
+    for (i, result) in results.into_iter().enumerate() {
+        assert_eq!(result.unwrap()["n"], i);
+    }
+    let peak = peak.load(Ordering::SeqCst);
+    assert!((2..=4).contains(&peak), "peak of {} calls", peak);
+    assert!(started.elapsed() >= Duration::from_millis(8 * 50));
+}
+
+#[tokio::test]
+async fn test_zero_inflight_limit_runs_whole_batch_at_once() {
+    let (client, peak) = spawn_slow_server(0).await;
+    let calls = (0..20).map(|i| ("test/slow", json!({ "n": i }))).collect();
+
+    client.call_batch(calls).await.unwrap();
+    assert_eq!(peak.load(Ordering::SeqCst), 20);
+}
+
+/// Config disabling `ping` and requiring `admin:sessions` for `sessions/list`
+fn restricted_rpc_config() -> ServerConfig {
+    let mut config = ServerConfig::default();
+    config.rpc.disabled_methods = vec!["ping".to_string()];
+    config.rpc.permissions.insert(
+        "sessions/list".to_string(),
+        MANAGE_SESSIONS_PERMISSION.to_string(),
+    );
+    config
+}
+
+#[tokio::test]
+async fn test_rpc_config_disables_and_gates_methods() {
+    let dispatcher = RpcDispatcher::with_core_handlers();
+    let server = Server::new(restricted_rpc_config());
+
+    let err = dispatcher
+        .dispatch("ping", Value::Null, context(&server, &["admin:*"]))
+        .await
+        .unwrap_err();
+    assert_eq!(err.code, rpc::METHOD_NOT_FOUND);
+
+    // read:sessions is enough for the handler, but not for the configured permission
+    let err = dispatcher
+        .dispatch(
+            "sessions/list",
+            Value::Null,
+            context(&server, &[READ_SESSIONS_PERMISSION]),
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(err.code, rpc::PERMISSION_DENIED);
+    let sessions = dispatcher
+        .dispatch(
+            "sessions/list",
+            Value::Null,
+            context(&server, &[MANAGE_SESSIONS_PERMISSION]),
+        )
+        .await
+        .unwrap();
+    assert_eq!(sessions, json!([]));
+
+    // Methods not mentioned are untouched
+    let status = dispatcher
+        .dispatch("status", Value::Null, context(&server, &[]))
+        .await
+        .unwrap();
+    assert_eq!(status["pid"], std::process::id());
+}
+
+#[tokio::test]
+async fn test_sessions_apply_rpc_config() {
+    let mut config = restricted_rpc_config();
+    config.rpc.disabled_methods.push("session/info".to_string());
+    config.auth.psk = Some("secret".to_string());
+    let server = Server::new(config);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(server.clone().serve(listener));
+
+    // Methods the session serves itself are covered as well as dispatched ones
+    let client =
+        Client::new(addr.ip().to_string(), addr.port(), 5).with_auth(Some("secret".to_string()));
+    for method in ["ping", "session/info"] {
+        match client.call_raw(method, Value::Null).await.unwrap_err() {
+            ClientError::Rpc { code, .. } => assert_eq!(code, rpc::METHOD_NOT_FOUND),
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    let token = server.tokens().issue(
+        "viewer",
+        vec![READ_SESSIONS_PERMISSION.to_string()],
+        Duration::from_secs(60),
+    );
+    let viewer = client.clone().with_auth(Some(token));
+    let err = viewer.list_sessions().await.unwrap_err();
+    assert!(matches!(err, ClientError::PermissionDenied(_)));
+    assert!(client.list_sessions().await.is_ok());
+}
+
+#[test]
+fn test_rpc_config_from_toml() {
+    let config: ServerConfig = toml::from_str(
+        r#"
+        [rpc]
+        disabled_methods = ["apps/launch"]
+
+        [rpc.permissions]
+        "sessions/list" = "admin:sessions"
+        "#,
+    )
+    .unwrap();
+    assert!(config.rpc.is_disabled("apps/launch"));
+    assert!(!config.rpc.is_disabled("apps/list"));
+    assert_eq!(
+        config.rpc.required_permission("sessions/list"),
+        Some("admin:sessions")
+    );
+    assert!(config.validate().is_empty());
+
+    // Nothing is restricted by default
+    let config = ServerConfig::default();
+    assert!(config.rpc.disabled_methods.is_empty());
+    assert!(config.rpc.permissions.is_empty());
+}
+
+#[test]
+fn test_format_uptime() {
+    assert_eq!(format_uptime(Duration::ZERO), "0s");
+    assert_eq!(format_uptime(Duration::from_secs(59)), "59s");
+    assert_eq!(format_uptime(Duration::from_secs(3_600)), "1h 0m 0s");
+    assert_eq!(format_uptime(Duration::from_secs(93_784)), "1d 2h 3m 4s");
+}
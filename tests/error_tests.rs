@@ -51,3 +51,16 @@ fn test_error_debug() {
     assert!(!debug_str.is_empty());
     assert!(debug_str.contains("Service"));
 }
+
+#[test]
+fn test_auth_error_creation() {
+    // Bad credentials and a missing permission read differently
+    let unauthorized = ServiceError::Unauthorized("Token has expired".to_string());
+    assert_eq!(unauthorized.to_string(), "Unauthorized: Token has expired");
+
+    let forbidden = ServiceError::Forbidden("admin:config".to_string());
+    assert_eq!(
+        forbidden.to_string(),
+        "Forbidden: operation requires permission admin:config"
+    );
+}
@@ -0,0 +1,156 @@
+//! Server event log tests
+
+use rcpdaemon::client::Client;
+use rcpdaemon::server::config::ServerConfig;
+use rcpdaemon::server::events::{EventLog, ServerEventType};
+use rcpdaemon::server::{ConnectDecision, Server};
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use uuid::Uuid;
+
+#[test]
+fn test_event_log_keeps_most_recent_events() {
+    let log = EventLog::new(3);
+    for _ in 0..5 {
+        log.record(ServerEventType::Connected, Some(Uuid::new_v4()), None, None);
+    }
+
+    let events = log.recent(10, &[]);
+    assert_eq!(log.len(), 3);
+    assert_eq!(
+        events.iter().map(|e| e.seq).collect::<Vec<_>>(),
+        vec![2, 3, 4]
+    );
+
+    let last = log.recent(2, &[]);
+    assert_eq!(last.iter().map(|e| e.seq).collect::<Vec<_>>(), vec![3, 4]);
+}
+
+#[test]
+fn test_event_log_filters_by_type() {
+    let log = EventLog::new(10);
+    log.record(
+        ServerEventType::Connected,
+        None,
+        None,
+        Some("10.0.0.1".into()),
+    );
+    log.record(
+        ServerEventType::Authenticated,
+        None,
+        Some("alice".into()),
+        None,
+    );
+    log.record(ServerEventType::Disconnected, None, None, None);
+    log.record(
+        ServerEventType::Connected,
+        None,
+        None,
+        Some("10.0.0.2".into()),
+    );
+
+    let connects = log.recent(10, &[ServerEventType::Connected]);
+    assert_eq!(connects.len(), 2);
+    assert_eq!(connects[1].client_ip.as_deref(), Some("10.0.0.2"));
+
+    // The count applies after filtering
+    let last = log.recent(
+        1,
+        &[
+            ServerEventType::Authenticated,
+            ServerEventType::Disconnected,
+        ],
+    );
+    assert_eq!(last.len(), 1);
+    assert_eq!(last[0].event_type, ServerEventType::Disconnected);
+}
+
+#[test]
+fn test_event_type_names() {
+    assert_eq!(
+        "auth_failed".parse::<ServerEventType>(),
+        Ok(ServerEventType::AuthFailed)
+    );
+    assert_eq!(
+        "Connected".parse::<ServerEventType>(),
+        Ok(ServerEventType::Connected)
+    );
+    assert!("bogus".parse::<ServerEventType>().is_err());
+
+    for event_type in ServerEventType::ALL {
+        let json = serde_json::to_value(event_type).unwrap();
+        assert_eq!(json, event_type.as_str());
+    }
+}
+
+#[test]
+fn test_event_log_size_from_config() {
+    let config: ServerConfig = toml::from_str("event_log_size = 2").unwrap();
+    assert_eq!(Server::new(config).events().capacity(), 2);
+    assert_eq!(ServerConfig::default().event_log_size, 1000);
+}
+
+#[tokio::test]
+async fn test_recent_events_over_rpc() {
+    let mut config = ServerConfig::default();
+    config.auth.required = false;
+    let server = Server::new(config);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(server.clone().serve(listener));
+
+    let client = Client::new(addr.ip().to_string(), addr.port(), 5);
+
+    // The first request's own connection is already in the log
+    let events = client.recent_events(10, &[]).await.unwrap();
+    let types: Vec<ServerEventType> = events.iter().map(|e| e.event_type).collect();
+    assert_eq!(
+        types,
+        vec![ServerEventType::Connected, ServerEventType::Authenticated]
+    );
+    assert_eq!(events[0].client_ip.as_deref(), Some("127.0.0.1"));
+    assert_eq!(events[0].session_id, events[1].session_id);
+
+    // Closing that connection is recorded once the session is cleaned up
+    let mut disconnects = Vec::new();
+    for _ in 0..50 {
+        disconnects = server.events().recent(10, &[ServerEventType::Disconnected]);
+        if !disconnects.is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(disconnects.len(), 1);
+    assert_eq!(disconnects[0].session_id, events[0].session_id);
+
+    let connects = client
+        .recent_events(1, &[ServerEventType::Connected])
+        .await
+        .unwrap();
+    assert_eq!(connects.len(), 1);
+    assert_ne!(connects[0].session_id, events[0].session_id);
+}
+
+#[tokio::test]
+async fn test_rejected_connections_are_recorded() {
+    let server =
+        Server::new(ServerConfig::default()).with_connect_hook(|_| ConnectDecision::Reject);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(server.clone().serve(listener));
+
+    let _stream = TcpStream::connect(addr).await.unwrap();
+
+    let mut rejected = Vec::new();
+    for _ in 0..50 {
+        rejected = server.events().recent(10, &[ServerEventType::Rejected]);
+        if !rejected.is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    assert_eq!(rejected.len(), 1);
+    assert_eq!(rejected[0].session_id, None);
+    assert_eq!(rejected[0].client_ip.as_deref(), Some("127.0.0.1"));
+}
@@ -0,0 +1,55 @@
+//! Permissions of files the daemon writes
+
+#![cfg(unix)]
+
+use rcpdaemon::files::{write_with_mode, PRIVATE_FILE_MODE, PUBLIC_FILE_MODE};
+use rcpdaemon::server::config::ServerConfig;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("rcpdaemon-{}-{}", name, Uuid::new_v4()))
+}
+
+fn mode(path: &Path) -> u32 {
+    std::fs::metadata(path).unwrap().permissions().mode() & 0o777
+}
+
+#[test]
+fn test_write_with_mode_sets_mode() {
+    let path = temp_path("unit");
+    write_with_mode(&path, "[Unit]\n", PUBLIC_FILE_MODE).unwrap();
+    assert_eq!(mode(&path), 0o644);
+
+    // Rewriting an existing file tightens its mode too
+    write_with_mode(&path, "secret", PRIVATE_FILE_MODE).unwrap();
+    assert_eq!(mode(&path), 0o600);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "secret");
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_server_config_is_private() {
+    let path = temp_path("config.toml");
+    std::fs::write(&path, "").unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+
+    let mut config = ServerConfig::default();
+    config.auth.psk = Some("secret".to_string());
+    config.to_file(&path).unwrap();
+    assert_eq!(mode(&path), 0o600);
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[cfg(feature = "cli")]
+#[test]
+fn test_cli_config_is_private() {
+    let path = temp_path("cli.toml");
+    rcpdaemon::cli::utils::save_config(&Default::default(), path.clone()).unwrap();
+    assert_eq!(mode(&path), 0o600);
+
+    let _ = std::fs::remove_file(&path);
+}
@@ -0,0 +1,198 @@
+//! Runtime server configuration changes
+
+use rcpdaemon::client::{Client, ClientError};
+use rcpdaemon::server::config::ServerConfig;
+use rcpdaemon::server::live_config::{apply_setting, ConfigFile, CONFIG_PERMISSION};
+use rcpdaemon::server::{rpc, Server};
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use uuid::Uuid;
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("rcpdaemon-{}-{}", name, Uuid::new_v4()))
+}
+
+/// Serve `server` on an ephemeral port and return a client for it
+async fn serve(server: &Server) -> Client {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(server.clone().serve(listener));
+
+    Client::new(addr.ip().to_string(), addr.port(), 5)
+}
+
+#[test]
+fn test_apply_setting_checks_key_and_value() {
+    let config = ServerConfig::default();
+
+    let updated = apply_setting(&config, "session.timeout", "90").unwrap();
+    assert_eq!(updated.session.timeout, 90);
+    let updated = apply_setting(&config, "auth.allowed_methods", "token, psk").unwrap();
+    assert_eq!(updated.auth.allowed_methods, vec!["token", "psk"]);
+    let updated = apply_setting(&config, "auth.psk", "1234").unwrap();
+    assert_eq!(updated.auth.psk.as_deref(), Some("1234"));
+
+    for (key, value) in [
+        ("session.timout", "90"),
+        ("session", "90"),
+        ("session.timeout", "soon"),
+    ] {
+        assert!(
+            apply_setting(&config, key, value).is_err(),
+            "{} = {} was accepted",
+            key,
+            value
+        );
+    }
+}
+
+#[test]
+fn test_update_saves_nested_table_and_applies() {
+    let dir = temp_path("config-dir");
+    std::fs::create_dir(&dir).unwrap();
+    let path = dir.join("rcpdaemon.toml");
+    std::fs::write(
+        &path,
+        "address = \"10.0.0.1\"\nport = 8716\n\n[server]\nport = 9000\n",
+    )
+    .unwrap();
+
+    let server = Server::builder()
+        .config(ServerConfig::default())
+        .config_file(ConfigFile::nested(&path, "server"))
+        .build();
+    server
+        .live_config()
+        .update("session.max_inflight_rpcs", "4")
+        .unwrap();
+    assert_eq!(server.config().session.max_inflight_rpcs, 4);
+
+    // The rest of the daemon config survives
+    let saved: toml::Value = toml::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(saved["address"].as_str(), Some("10.0.0.1"));
+    assert_eq!(
+        saved["server"]["session"]["max_inflight_rpcs"].as_integer(),
+        Some(4)
+    );
+
+    // The temporary file was renamed over the config, not left beside it
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_failed_write_leaves_running_config_unchanged() {
+    // The directory does not exist, so the temporary file cannot be created
+    let path = temp_path("missing-dir").join("rcpdaemon.toml");
+    let server = Server::builder()
+        .config(ServerConfig::default())
+        .config_file(ConfigFile::standalone(&path))
+        .build();
+    let before = server.config();
+
+    assert!(server
+        .live_config()
+        .update("session.timeout", "90")
+        .is_err());
+    assert_eq!(server.config().session.timeout, before.session.timeout);
+    assert_eq!(
+        serde_json::to_value(&*server.config()).unwrap(),
+        serde_json::to_value(&*before).unwrap()
+    );
+    assert!(!path.exists());
+}
+
+#[test]
+fn test_update_refuses_invalid_config() {
+    let path = temp_path("server.toml");
+    let server = Server::builder()
+        .config(ServerConfig::default())
+        .config_file(ConfigFile::standalone(&path))
+        .build();
+
+    // Parses as a number, but the server cannot run with it
+    let err = server
+        .live_config()
+        .update("session.max_sessions", "0")
+        .unwrap_err();
+    assert!(err.to_string().contains("session.max_sessions"), "{}", err);
+    assert_ne!(server.config().session.max_sessions, 0);
+    assert!(!path.exists());
+
+    // A problem the running config already has does not block other updates
+    let mut broken = ServerConfig::default();
+    broken.session.max_sessions = 0;
+    let server = Server::builder()
+        .config(broken)
+        .config_file(ConfigFile::standalone(&path))
+        .build();
+    server
+        .live_config()
+        .update("session.timeout", "90")
+        .unwrap();
+    assert_eq!(server.config().session.timeout, 90);
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn test_config_update_rpc() {
+    let path = temp_path("server.toml");
+    let mut config = ServerConfig::default();
+    config.auth.psk = Some("operator-key".to_string());
+    let server = Server::builder()
+        .config(config)
+        .config_file(ConfigFile::standalone(&path))
+        .build();
+    let client = serve(&server).await;
+    let hour = Duration::from_secs(3600);
+
+    // Only admins may change the configuration
+    let token = server
+        .tokens()
+        .issue("alice", vec!["app:*".to_string()], hour);
+    match client
+        .clone()
+        .with_auth(Some(token))
+        .update_server_config("session.timeout", "90")
+        .await
+        .unwrap_err()
+    {
+        ClientError::PermissionDenied(permission) => {
+            assert_eq!(permission.required, CONFIG_PERMISSION)
+        }
+        other => panic!("unexpected error: {:?}", other),
+    }
+
+    let admin = client.with_auth(Some(server.tokens().issue(
+        "ops",
+        vec![CONFIG_PERMISSION.to_string()],
+        hour,
+    )));
+    let updated = admin
+        .update_server_config("session.timeout", "90")
+        .await
+        .unwrap();
+    assert_eq!(updated["session"]["timeout"], 90);
+    assert_eq!(updated["auth"]["psk"], "<redacted>");
+    assert_eq!(server.config().session.timeout, 90);
+    let saved: ServerConfig = toml::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(saved.session.timeout, 90);
+    assert_eq!(
+        admin.get_running_config().await.unwrap()["session"]["timeout"],
+        90
+    );
+
+    match admin
+        .update_server_config("session.timeout", "soon")
+        .await
+        .unwrap_err()
+    {
+        ClientError::Rpc { code, .. } => assert_eq!(code, rpc::INVALID_PARAMS),
+        other => panic!("unexpected error: {:?}", other),
+    }
+
+    let _ = std::fs::remove_file(&path);
+}
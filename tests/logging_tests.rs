@@ -0,0 +1,116 @@
+//! Runtime log level tests
+//!
+//! The log level is process-global, so the level changes are exercised in a single test.
+
+use log::LevelFilter;
+use rcpdaemon::logging;
+
+#[test]
+fn test_parse_level() {
+    assert_eq!(logging::parse_level("debug"), Some(LevelFilter::Debug));
+    assert_eq!(logging::parse_level(" WARN "), Some(LevelFilter::Warn));
+    assert_eq!(logging::parse_level("off"), Some(LevelFilter::Off));
+    assert_eq!(logging::parse_level("loud"), None);
+}
+
+#[test]
+fn test_target_filter_levels() {
+    use log::Level;
+    use logging::LogFilter;
+
+    let filter =
+        LogFilter::parse("warn, rcpdaemon::auth=debug,rcpdaemon::server::session=off").unwrap();
+    assert_eq!(filter.default, Some(LevelFilter::Warn));
+    let enabled = |target: &str, level: Level| filter.enabled(target, level, LevelFilter::Warn);
+
+    // Auth is raised, submodules included, while the rest stays quiet
+    assert!(enabled("rcpdaemon::auth", Level::Debug));
+    assert!(enabled("rcpdaemon::auth::manager", Level::Debug));
+    assert!(!enabled("rcpdaemon::auth::manager", Level::Trace));
+    assert!(!enabled("rcpdaemon::server", Level::Info));
+    assert!(enabled("rcpdaemon::server", Level::Warn));
+
+    // The most specific directive wins
+    assert!(!enabled("rcpdaemon::server::session", Level::Error));
+
+    // Without a default level, unnamed targets keep the one given
+    let filter = LogFilter::parse("rcpdaemon::api=trace").unwrap();
+    assert_eq!(filter.default, None);
+    assert!(filter.enabled("rcpdaemon::daemon", Level::Info, LevelFilter::Info));
+    assert!(!filter.enabled("rcpdaemon::daemon", Level::Debug, LevelFilter::Info));
+    assert!(filter.enabled("rcpdaemon::api", Level::Trace, LevelFilter::Info));
+
+    assert!(LogFilter::parse("rcpdaemon::auth=loud").is_err());
+}
+
+#[test]
+fn test_startup_filter_sources() {
+    use logging::startup_filter;
+
+    // --log-filter wins over the environment, and must parse
+    let filter = startup_filter(Some("debug"), Some("error"), false)
+        .unwrap()
+        .unwrap();
+    assert_eq!(filter.default, Some(LevelFilter::Debug));
+    assert!(startup_filter(Some("rcpdaemon=loud"), None, false).is_err());
+
+    // A bad environment filter is only ignored
+    assert!(startup_filter(None, Some("rcpdaemon=loud"), false)
+        .unwrap()
+        .is_none());
+    assert!(startup_filter(None, None, true).unwrap().is_none());
+
+    // An explicit -v keeps its level over RUST_LOG's default, not its directives
+    let filter = startup_filter(None, Some("error,rcpdaemon::auth=trace"), true)
+        .unwrap()
+        .unwrap();
+    assert_eq!(filter.default, None);
+    assert!(filter.enabled("rcpdaemon::auth", log::Level::Trace, LevelFilter::Debug));
+    let filter = startup_filter(None, Some("error"), false).unwrap().unwrap();
+    assert_eq!(filter.default, Some(LevelFilter::Error));
+}
+
+#[test]
+fn test_runtime_level_changes() {
+    log::set_max_level(LevelFilter::Info);
+
+    assert_eq!(logging::set_level(LevelFilter::Debug), LevelFilter::Info);
+    assert_eq!(logging::level(), LevelFilter::Debug);
+
+    // Stepping is clamped at both ends
+    assert_eq!(logging::increase_verbosity(), LevelFilter::Trace);
+    assert_eq!(logging::increase_verbosity(), LevelFilter::Trace);
+
+    logging::set_level(LevelFilter::Warn);
+    assert_eq!(logging::decrease_verbosity(), LevelFilter::Error);
+    assert_eq!(logging::decrease_verbosity(), LevelFilter::Error);
+    assert_eq!(logging::level(), LevelFilter::Error);
+}
+
+#[test]
+fn test_log_file_tee() {
+    use std::io::Write;
+
+    let path = std::env::temp_dir().join(format!("rcpdaemon-tee-{}.log", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    logging::set_log_file(Some(&path)).unwrap();
+    assert_eq!(logging::log_file(), Some(path.canonicalize().unwrap()));
+
+    let mut writer = logging::LogWriter;
+    writer.write_all(b"first record\n").unwrap();
+
+    // Once stderr itself points at the file, records reach it only through stderr
+    logging::set_console_redirect(Some(&path));
+    writer.write_all(b"second record\n").unwrap();
+    logging::set_console_redirect(None);
+
+    logging::set_log_file(None).unwrap();
+    writer.write_all(b"third record\n").unwrap();
+    assert_eq!(logging::log_file(), None);
+
+    let contents = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(contents, "first record\n");
+
+    let _ = std::fs::remove_file(&path);
+}
@@ -88,3 +88,220 @@ async fn test_manager_work_dir() {
     // Assert that the work directory is correctly set
     assert_eq!(manager.get_work_dir(), &work_dir);
 }
+
+#[test]
+fn test_startup_summary_lists_settings_without_secrets() {
+    use rcpdaemon::manager::StartupSummary;
+
+    let mut config = ServiceConfig::default();
+    config.server.address = "0.0.0.0".to_string();
+    config.server.port = 8717;
+    config.server.tls.enabled = true;
+    config.server.tls.key_path = "/etc/rcp/secret-key.pem".to_string();
+    config.server.auth.psk = Some("hunter2".to_string());
+    config.server.auth.provider = "native".to_string();
+    config.loaded_from = Some(PathBuf::from("/etc/rcpdaemon/config.toml"));
+
+    let summary = StartupSummary::new(&config, Some("127.0.0.1:8080".to_string()), 4);
+    let line = summary.to_string();
+    for expected in [
+        "address=0.0.0.0:8717",
+        "tls=on",
+        "auth_provider=native",
+        "auth_required=true",
+        "api=127.0.0.1:8080",
+        "worker_threads=4",
+        "config_file=/etc/rcpdaemon/config.toml",
+    ] {
+        assert!(
+            line.contains(expected),
+            "{} missing from {}",
+            expected,
+            line
+        );
+    }
+
+    let json = serde_json::to_value(&summary).unwrap();
+    for key in [
+        "address",
+        "tls",
+        "auth_provider",
+        "auth_required",
+        "api",
+        "worker_threads",
+        "config_file",
+    ] {
+        assert!(json.get(key).is_some(), "{} missing from {}", key, json);
+    }
+
+    for output in [line, json.to_string()] {
+        assert!(!output.contains("hunter2"));
+        assert!(!output.contains("secret-key.pem"));
+    }
+
+    // A service without the API says so
+    let line = StartupSummary::new(&ServiceConfig::default(), None, 1).to_string();
+    assert!(line.contains("api=off"), "{}", line);
+    assert!(line.contains("config_file=none"), "{}", line);
+}
+
+/// Config serving on a free local port, with the operator key "operator-key"
+/// and users kept in `users_file`
+fn served_config(users_file: &std::path::Path) -> ServiceConfig {
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let mut config = ServiceConfig::default();
+    config.server.address = "127.0.0.1".to_string();
+    config.server.port = port;
+    config.server.auth.psk = Some("operator-key".to_string());
+    config.server.auth.users_file = Some(users_file.to_string_lossy().into_owned());
+    #[cfg(feature = "api")]
+    {
+        config.api = None;
+    }
+    config
+}
+
+/// Start a manager for `config`, returning it and an operator client once it answers
+async fn start_served(config: ServiceConfig) -> (ServiceManager, rcpdaemon::Client) {
+    let (tx, _rx) = mpsc::channel::<()>(1);
+    let client = rcpdaemon::Client::new(config.server.address.clone(), config.server.port, 5)
+        .with_auth(Some("operator-key".to_string()));
+    let mut manager = ServiceManager::new(std::env::temp_dir(), config, tx);
+    manager.start().await.unwrap();
+    rcpdaemon::daemon::wait_until_ready(&client, std::time::Duration::from_secs(10))
+        .await
+        .unwrap();
+    (manager, client)
+}
+
+#[tokio::test]
+async fn test_imported_users_survive_a_restart() {
+    use rcpdaemon::server::user::{ExportedUser, ImportMode, UserExport, USER_EXPORT_VERSION};
+
+    let users_file =
+        std::env::temp_dir().join(format!("rcpdaemon-users-{}.json", uuid::Uuid::new_v4()));
+    let export = UserExport {
+        version: USER_EXPORT_VERSION,
+        exported_at: "2024-01-03T00:00:00+00:00".to_string(),
+        users: ["alice", "bob"]
+            .into_iter()
+            .map(|username| ExportedUser {
+                id: uuid::Uuid::new_v4(),
+                username: username.to_string(),
+                full_name: None,
+                email: None,
+                password_hash: format!("$argon2id$v=19${}", username),
+                role: rcpdaemon::server::user::UserRole::User,
+                created_at: "2024-01-01T00:00:00+00:00".to_string(),
+                updated_at: "2024-01-02T00:00:00+00:00".to_string(),
+            })
+            .collect(),
+    };
+
+    // The daemon's own users/import reaches the configured users file
+    let (mut manager, client) = start_served(served_config(&users_file)).await;
+    let report = client
+        .import_users(&export, ImportMode::Merge)
+        .await
+        .unwrap();
+    assert_eq!(report.added, vec!["alice", "bob"]);
+    manager.stop().await.unwrap();
+    assert!(users_file.exists());
+
+    let (mut manager, client) = start_served(served_config(&users_file)).await;
+    let restored = client.export_users().await.unwrap();
+    manager.stop().await.unwrap();
+    let _ = std::fs::remove_file(&users_file);
+
+    assert_eq!(restored.users, export.users);
+}
+
+#[tokio::test]
+async fn test_updated_users_survive_a_restart() {
+    use rcpdaemon::server::user::{
+        ExportedUser, ImportMode, UserExport, UserRole, UserUpdate, USER_EXPORT_VERSION,
+    };
+
+    let users_file =
+        std::env::temp_dir().join(format!("rcpdaemon-users-{}.json", uuid::Uuid::new_v4()));
+    let export = UserExport {
+        version: USER_EXPORT_VERSION,
+        exported_at: "2024-01-03T00:00:00+00:00".to_string(),
+        users: vec![ExportedUser {
+            id: uuid::Uuid::new_v4(),
+            username: "carol".to_string(),
+            full_name: None,
+            email: None,
+            password_hash: "$argon2id$v=19$carol".to_string(),
+            role: UserRole::User,
+            created_at: "2024-01-01T00:00:00+00:00".to_string(),
+            updated_at: "2024-01-02T00:00:00+00:00".to_string(),
+        }],
+    };
+
+    // users/update changes the users the daemon itself serves
+    let (mut manager, client) = start_served(served_config(&users_file)).await;
+    client
+        .import_users(&export, ImportMode::Merge)
+        .await
+        .unwrap();
+    let update = UserUpdate {
+        email: Some("carol@example.com".to_string()),
+        role: Some(UserRole::Admin),
+        ..Default::default()
+    };
+    let updated = client.update_user("carol", &update).await.unwrap();
+    assert_eq!(updated.role, UserRole::Admin);
+    manager.stop().await.unwrap();
+
+    let (mut manager, client) = start_served(served_config(&users_file)).await;
+    let restored = client.export_users().await.unwrap();
+    manager.stop().await.unwrap();
+    let _ = std::fs::remove_file(&users_file);
+
+    assert_eq!(restored.users.len(), 1);
+    assert_eq!(restored.users[0].role, UserRole::Admin);
+    assert_eq!(
+        restored.users[0].email.as_deref(),
+        Some("carol@example.com")
+    );
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn test_started_service_resolves_roles_with_the_configured_provider() {
+    use rcpdaemon::server::tokens::TokenRegistry;
+    use rcpdaemon::server::user::UserRole;
+
+    let users_file =
+        std::env::temp_dir().join(format!("rcpdaemon-users-{}.json", uuid::Uuid::new_v4()));
+    let mut config = served_config(&users_file);
+    config.server.auth.provider = "native".to_string();
+    config.server.auth.token_secret = Some("test-secret".to_string());
+    config.server.auth.native.require_group = None;
+    config.server.auth.native.admin_groups = vec!["root".to_string()];
+    config.server.auth.native.default_role = UserRole::Guest;
+    let tokens = TokenRegistry::new(&config.server.auth);
+    let (address, port) = (config.server.address.clone(), config.server.port);
+
+    let (mut manager, _operator) = start_served(config).await;
+    let server = manager.get_server().clone().unwrap();
+    assert!(server.lock().await.auth_manager().is_some());
+
+    // Roles come from the provider's view of the account's groups
+    let role = |user: &str| {
+        let client = rcpdaemon::Client::new(address.clone(), port, 5).with_auth(Some(
+            tokens.issue(user, Vec::new(), std::time::Duration::from_secs(3600)),
+        ));
+        async move { client.whoami().await.unwrap().role }
+    };
+    assert_eq!(role("root").await, Some(UserRole::Admin));
+    assert_eq!(role("nobody").await, Some(UserRole::Guest));
+    assert_eq!(role("no-such-user").await, None);
+
+    manager.stop().await.unwrap();
+}
@@ -1,5 +1,12 @@
 use anyhow::Result;
-use rcpdaemon::auth::factory::{AuthConfig, AuthProviderType, NativeAuthConfig};
+use rcpdaemon::auth::command::is_valid_username;
+use rcpdaemon::auth::factory::{
+    AuthConfig, AuthProviderFactory, AuthProviderType, NativeAuthConfig,
+};
+use rcpdaemon::auth::improved_native::{
+    determine_role_common, get_linux_user_groups, get_macos_user_groups, get_unix_user_groups,
+    get_windows_user_groups,
+};
 use rcpdaemon::auth::manager::AuthManager;
 use rcpdaemon::server::user::UserRole;
 use std::collections::HashMap;
@@ -31,9 +38,12 @@ async fn test_macos_native_auth() -> Result<()> {
                 mappings.insert("admin".to_string(), vec!["admin:*".to_string()]);
                 mappings
             },
+            ..Default::default()
         },
         ldap: HashMap::new(),
         oauth: HashMap::new(),
+        allowed_methods: Vec::new(),
+        ..Default::default()
     };
 
     // Create the authentication manager
@@ -83,9 +93,12 @@ async fn test_unix_native_auth() -> Result<()> {
                 mappings.insert("wheel".to_string(), vec!["admin:*".to_string()]);
                 mappings
             },
+            ..Default::default()
         },
         ldap: HashMap::new(),
         oauth: HashMap::new(),
+        allowed_methods: Vec::new(),
+        ..Default::default()
     };
 
     // Create the authentication manager
@@ -98,3 +111,170 @@ async fn test_unix_native_auth() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+async fn test_valid_usernames() {
+    for username in ["alice", "j.doe", "svc_backup", "web-01", "HOST$"] {
+        assert!(is_valid_username(username), "{}", username);
+    }
+    for username in [
+        "",
+        "-rf",
+        "--help",
+        "john smith",
+        "a\tb",
+        "..",
+        "x/../y",
+        "élodie",
+    ] {
+        assert!(!is_valid_username(username), "{:?}", username);
+    }
+}
+
+#[test]
+async fn test_native_provider_rejects_unsafe_usernames() -> Result<()> {
+    let config = AuthConfig {
+        provider: AuthProviderType::Native,
+        ..Default::default()
+    };
+    let mut provider = AuthProviderFactory::create_provider(&config)?;
+    provider.initialize().await?;
+
+    // Refused before any lookup command runs
+    for username in ["-rf", "john smith"] {
+        let err = provider
+            .validate_credentials(username, b"secret", "password")
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("Invalid username"), "{}", err);
+        assert!(provider.get_user_by_username(username).await?.is_none());
+    }
+
+    Ok(())
+}
+
+#[test]
+async fn test_group_helpers_reject_unsafe_usernames() {
+    for username in ["-rf", "bob; whoami", "x' -Group Administrators"] {
+        let mut cache = HashMap::new();
+        let results = [
+            get_macos_user_groups(username, &mut cache).await,
+            get_linux_user_groups(username, &mut cache).await,
+            get_windows_user_groups(username, &mut cache).await,
+            get_unix_user_groups(username, &mut cache).await,
+        ];
+        for result in results {
+            let err = result.unwrap_err();
+            assert!(err.to_string().contains("Invalid username"), "{}", err);
+        }
+        assert!(cache.is_empty());
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn test_linux_groups_lookup_runs_on_the_runtime() {
+    let mut cache = HashMap::new();
+    let groups = get_linux_user_groups("root", &mut cache).await.unwrap();
+    assert!(groups.contains(&"root".to_string()), "{:?}", groups);
+    assert_eq!(cache.get("root"), Some(&groups));
+
+    // A second lookup is answered from the cache
+    assert_eq!(
+        get_linux_user_groups("root", &mut cache).await.unwrap(),
+        groups
+    );
+}
+
+#[tokio::test]
+async fn test_role_from_groups() {
+    let admin_groups = vec!["wheel".to_string()];
+    let user_groups = vec!["rcp-users".to_string()];
+    let groups = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+
+    // Admin membership wins over a user group
+    assert_eq!(
+        determine_role_common(
+            &groups(&["rcp-users", "wheel"]),
+            &admin_groups,
+            &user_groups,
+            &UserRole::Guest
+        ),
+        UserRole::Admin
+    );
+
+    // A user group lifts its members above the default role
+    assert_eq!(
+        determine_role_common(
+            &groups(&["rcp-users"]),
+            &admin_groups,
+            &user_groups,
+            &UserRole::Guest
+        ),
+        UserRole::User
+    );
+
+    // Everyone else gets the default role
+    assert_eq!(
+        determine_role_common(
+            &groups(&["staff"]),
+            &admin_groups,
+            &user_groups,
+            &UserRole::Guest
+        ),
+        UserRole::Guest
+    );
+    assert_eq!(
+        determine_role_common(&groups(&["staff"]), &admin_groups, &[], &UserRole::User),
+        UserRole::User
+    );
+}
+
+#[tokio::test]
+async fn test_default_role_from_toml() {
+    let config: NativeAuthConfig = toml::from_str(
+        r#"
+        default_role = "guest"
+        user_groups = ["rcp-users"]
+        "#,
+    )
+    .unwrap();
+    assert_eq!(config.default_role, UserRole::Guest);
+    assert_eq!(config.user_groups, vec!["rcp-users"]);
+
+    // Without the setting everyone keeps the regular user role
+    assert_eq!(NativeAuthConfig::default().default_role, UserRole::User);
+}
+
+#[tokio::test]
+async fn test_group_cache_refetches_after_ttl() {
+    use rcpdaemon::auth::cache::GroupCache;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    let cache = GroupCache::with_ttl(Duration::from_millis(50));
+    let fetches = AtomicUsize::new(0);
+    let fetch = || async {
+        fetches.fetch_add(1, Ordering::SeqCst);
+        Ok(vec!["wheel".to_string()])
+    };
+
+    assert!(cache.is_member("alice", "wheel", fetch).await?);
+    assert!(cache.is_member("alice", "wheel", fetch).await?);
+    assert_eq!(fetches.load(Ordering::SeqCst), 1);
+
+    // Past the TTL the groups are looked up again, so a removal shows
+    tokio::time::sleep(Duration::from_millis(80)).await;
+    assert_eq!(cache.get("alice"), None);
+    assert!(cache.is_member("alice", "wheel", fetch).await?);
+    assert_eq!(fetches.load(Ordering::SeqCst), 2);
+
+    // Fresh entries are evicted oldest first rather than growing the cache
+    let cache = GroupCache::new();
+    for n in 0..2000 {
+        cache.insert(&format!("user{}", n), Vec::new());
+    }
+    assert_eq!(cache.len(), 1024);
+    assert_eq!(cache.get("user1999"), Some(Vec::new()));
+    Ok(())
+}
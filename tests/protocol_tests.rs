@@ -0,0 +1,392 @@
+//! Wire protocol tests
+//!
+//! These tests exercise the shared framing used by the server and the client.
+
+use bytes::Bytes;
+use rcpdaemon::protocol::codec::{self, FrameError};
+use tokio::io::{duplex, AsyncWriteExt};
+
+#[tokio::test]
+async fn test_frame_round_trip() {
+    let (client, server) = duplex(1024);
+    let mut client = codec::framed(client, codec::DEFAULT_MAX_FRAME_LENGTH);
+    let mut server = codec::framed(server, codec::DEFAULT_MAX_FRAME_LENGTH);
+
+    codec::write_frame(&mut client, Bytes::from_static(b"{\"method\":\"status\"}"))
+        .await
+        .expect("write should succeed");
+
+    let received = codec::read_text_frame(&mut server)
+        .await
+        .expect("read should succeed");
+    assert_eq!(received, "{\"method\":\"status\"}");
+}
+
+#[tokio::test]
+async fn test_frame_uses_big_endian_length_prefix() {
+    let (mut raw, server) = duplex(1024);
+    let mut server = codec::framed(server, codec::DEFAULT_MAX_FRAME_LENGTH);
+
+    // Hand-written frame: 4-byte big-endian length followed by the payload
+    raw.write_all(&5u32.to_be_bytes()).await.unwrap();
+    raw.write_all(b"hello").await.unwrap();
+
+    let frame = codec::read_frame(&mut server).await.unwrap();
+    assert_eq!(&frame[..], b"hello");
+}
+
+#[tokio::test]
+async fn test_oversized_frame_is_rejected() {
+    let (mut raw, server) = duplex(1024);
+    let mut server = codec::framed(server, 16);
+
+    raw.write_all(&1024u32.to_be_bytes()).await.unwrap();
+
+    let err = codec::read_frame(&mut server).await.unwrap_err();
+    assert!(matches!(err, FrameError::TooLarge(16)));
+}
+
+#[tokio::test]
+async fn test_oversized_write_is_rejected() {
+    let (client, _server) = duplex(1024);
+    let mut client = codec::framed(client, 4);
+
+    let err = codec::write_frame(&mut client, Bytes::from_static(b"too long"))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, FrameError::TooLarge(4)));
+}
+
+#[tokio::test]
+async fn test_closed_stream_reports_closed() {
+    let (client, server) = duplex(1024);
+    let mut server = codec::framed(server, codec::DEFAULT_MAX_FRAME_LENGTH);
+    drop(client);
+
+    let err = codec::read_frame(&mut server).await.unwrap_err();
+    assert!(matches!(err, FrameError::Closed));
+}
+
+mod compression_tests {
+    use bytes::Bytes;
+    use rcpdaemon::protocol::codec;
+    use rcpdaemon::protocol::codec::FrameError;
+    use rcpdaemon::protocol::compression::{negotiate, CompressionAlgorithm, FrameCompressor};
+    use rcpdaemon::protocol::handshake::{
+        client_handshake, server_handshake, ClientHello, ProtocolVersion, ServerHello,
+        PROTOCOL_MAGIC, PROTOCOL_VERSION,
+    };
+    use tokio::io::{duplex, AsyncWriteExt};
+
+    fn large_payload() -> Bytes {
+        Bytes::from("rcp payload ".repeat(1000))
+    }
+
+    #[test]
+    fn test_round_trip_each_algorithm() {
+        for algorithm in [CompressionAlgorithm::Gzip, CompressionAlgorithm::Zstd] {
+            let compressor = FrameCompressor::new(Some(algorithm), 64, 1024 * 1024);
+            let payload = large_payload();
+
+            let encoded = compressor.encode(payload.clone()).unwrap();
+            assert!(encoded.len() < payload.len(), "{} should shrink", algorithm);
+
+            let decoded = compressor.decode(encoded).unwrap();
+            assert_eq!(decoded, payload);
+        }
+    }
+
+    #[test]
+    fn test_small_frames_stay_uncompressed() {
+        let compressor = FrameCompressor::new(Some(CompressionAlgorithm::Gzip), 1024, 1024 * 1024);
+        let payload = Bytes::from_static(b"{\"method\":\"status\"}");
+
+        let encoded = compressor.encode(payload.clone()).unwrap();
+        assert_eq!(encoded[0], 0);
+        assert_eq!(&encoded[1..], &payload[..]);
+        assert_eq!(compressor.decode(encoded).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_disabled_compressor_passes_frames_through() {
+        let compressor = FrameCompressor::disabled(1024);
+        let payload = large_payload();
+
+        assert_eq!(compressor.encode(payload.clone()).unwrap(), payload);
+        assert_eq!(compressor.decode(payload.clone()).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_decompression_respects_max_frame_length() {
+        let sender = FrameCompressor::new(Some(CompressionAlgorithm::Zstd), 0, 1024 * 1024);
+        let receiver = FrameCompressor::new(Some(CompressionAlgorithm::Zstd), 0, 128);
+
+        let encoded = sender.encode(large_payload()).unwrap();
+        assert!(receiver.decode(encoded).is_err());
+    }
+
+    #[test]
+    fn test_negotiation_prefers_server_order() {
+        let client = [CompressionAlgorithm::Zstd, CompressionAlgorithm::Gzip];
+        let server = [CompressionAlgorithm::Gzip, CompressionAlgorithm::Zstd];
+        assert_eq!(
+            negotiate(&client, &server),
+            Some(CompressionAlgorithm::Gzip)
+        );
+
+        assert_eq!(
+            negotiate(&[CompressionAlgorithm::Zstd], &[CompressionAlgorithm::Gzip]),
+            None
+        );
+        assert_eq!(negotiate(&client, &[]), None);
+    }
+
+    #[tokio::test]
+    async fn test_handshake_agrees_on_compression() {
+        let (client_io, server_io) = duplex(64 * 1024);
+        let mut client = codec::framed(client_io, codec::DEFAULT_MAX_FRAME_LENGTH);
+        let mut server = codec::framed(server_io, codec::DEFAULT_MAX_FRAME_LENGTH);
+
+        let server_task = tokio::spawn(async move {
+            let (_hello, compressor) =
+                server_handshake(&mut server, &[CompressionAlgorithm::Gzip], 16)
+                    .await
+                    .unwrap();
+
+            let frame = codec::read_frame(&mut server).await.unwrap();
+            let payload = compressor.decode(frame).unwrap();
+            codec::write_frame(&mut server, compressor.encode(payload).unwrap())
+                .await
+                .unwrap();
+            compressor.algorithm()
+        });
+
+        let compressor = client_handshake(&mut client, &ClientHello::default())
+            .await
+            .unwrap();
+        assert_eq!(compressor.algorithm(), Some(CompressionAlgorithm::Gzip));
+
+        let payload = large_payload();
+        codec::write_frame(&mut client, compressor.encode(payload.clone()).unwrap())
+            .await
+            .unwrap();
+        let echoed = compressor
+            .decode(codec::read_frame(&mut client).await.unwrap())
+            .unwrap();
+
+        assert_eq!(echoed, payload);
+        assert_eq!(server_task.await.unwrap(), Some(CompressionAlgorithm::Gzip));
+    }
+
+    #[tokio::test]
+    async fn test_handshake_without_overlap_disables_compression() {
+        let (client_io, server_io) = duplex(64 * 1024);
+        let mut client = codec::framed(client_io, codec::DEFAULT_MAX_FRAME_LENGTH);
+        let mut server = codec::framed(server_io, codec::DEFAULT_MAX_FRAME_LENGTH);
+
+        let server_task = tokio::spawn(async move {
+            server_handshake(&mut server, &[CompressionAlgorithm::Zstd], 16)
+                .await
+                .unwrap()
+                .1
+                .algorithm()
+        });
+
+        let hello = ClientHello {
+            compression: vec![CompressionAlgorithm::Gzip],
+            ..Default::default()
+        };
+        let compressor = client_handshake(&mut client, &hello).await.unwrap();
+
+        assert_eq!(compressor.algorithm(), None);
+        assert_eq!(server_task.await.unwrap(), None);
+    }
+
+    /// Run a handshake between a client speaking `version` and this build's server
+    async fn handshake_with_client_version(
+        version: ProtocolVersion,
+    ) -> (
+        Result<FrameCompressor, FrameError>,
+        Result<(ClientHello, FrameCompressor), FrameError>,
+    ) {
+        let (client_io, server_io) = duplex(64 * 1024);
+        let mut client = codec::framed(client_io, codec::DEFAULT_MAX_FRAME_LENGTH);
+        let mut server = codec::framed(server_io, codec::DEFAULT_MAX_FRAME_LENGTH);
+
+        let server_task = tokio::spawn(async move { server_handshake(&mut server, &[], 16).await });
+
+        let hello = ClientHello {
+            protocol_version: version,
+            ..Default::default()
+        };
+        let client_result = client_handshake(&mut client, &hello).await;
+
+        (client_result, server_task.await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_handshake_accepts_compatible_versions() {
+        let (client, server) = handshake_with_client_version(PROTOCOL_VERSION).await;
+        assert!(client.is_ok());
+        assert_eq!(server.unwrap().0.protocol_version, PROTOCOL_VERSION);
+
+        // A minor version difference is only warned about
+        let newer_minor = ProtocolVersion::new(PROTOCOL_VERSION.major, PROTOCOL_VERSION.minor + 1);
+        let (client, server) = handshake_with_client_version(newer_minor).await;
+        assert!(client.is_ok());
+        assert!(server.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_handshake_refuses_incompatible_versions() {
+        let newer_major = ProtocolVersion::new(PROTOCOL_VERSION.major + 1, 0);
+        let (client, server) = handshake_with_client_version(newer_major).await;
+
+        match client {
+            Err(FrameError::IncompatibleProtocol { local, remote }) => {
+                assert_eq!(local, newer_major);
+                assert_eq!(remote, PROTOCOL_VERSION);
+            }
+            other => panic!("expected a version mismatch, got {:?}", other.map(|_| ())),
+        }
+        assert!(matches!(
+            server,
+            Err(FrameError::IncompatibleProtocol { remote, .. }) if remote == newer_major
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_hellos_without_version_count_as_1_0() {
+        let hello: ClientHello = serde_json::from_str(r#"{"compression": []}"#).unwrap();
+        assert_eq!(hello.protocol_version, ProtocolVersion::new(1, 0));
+        let hello: ServerHello =
+            serde_json::from_str(r#"{"compression": null, "compression_threshold": 0}"#).unwrap();
+        assert_eq!(hello.protocol_version, ProtocolVersion::new(1, 0));
+
+        // Peers that predate versioning speak 1.0 and are still talked to
+        let (client, server) = handshake_with_client_version(ProtocolVersion::unversioned()).await;
+        assert!(client.is_ok());
+        assert!(server.is_ok());
+    }
+
+    #[test]
+    fn test_protocol_version_display_and_compatibility() {
+        let version = ProtocolVersion::new(1, 2);
+        assert_eq!(version.to_string(), "1.2");
+        assert!(version.is_compatible_with(&ProtocolVersion::new(1, 0)));
+        assert!(!version.is_compatible_with(&ProtocolVersion::new(2, 2)));
+    }
+
+    #[tokio::test]
+    async fn test_server_handshake_rejects_non_rcp_clients_at_once() {
+        let (client_io, server_io) = duplex(64 * 1024);
+        let mut server = codec::framed(server_io, codec::DEFAULT_MAX_FRAME_LENGTH);
+
+        let server_task = tokio::spawn(async move { server_handshake(&mut server, &[], 16).await });
+
+        // A browser pointed at the port; the stream is left open
+        let mut client_io = client_io;
+        client_io
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+
+        let result = tokio::time::timeout(std::time::Duration::from_secs(1), server_task)
+            .await
+            .expect("bad magic should be rejected without waiting")
+            .unwrap();
+        assert!(matches!(result, Err(FrameError::BadMagic)));
+    }
+
+    #[tokio::test]
+    async fn test_server_handshake_rejects_partial_bad_magic() {
+        let (mut client_io, server_io) = duplex(64 * 1024);
+        let mut server = codec::framed(server_io, codec::DEFAULT_MAX_FRAME_LENGTH);
+
+        let server_task = tokio::spawn(async move { server_handshake(&mut server, &[], 16).await });
+
+        // The first byte matches, the second one does not
+        client_io.write_all(&PROTOCOL_MAGIC[..1]).await.unwrap();
+        client_io.write_all(b"X").await.unwrap();
+
+        let result = tokio::time::timeout(std::time::Duration::from_secs(1), server_task)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(matches!(result, Err(FrameError::BadMagic)));
+    }
+}
+
+mod goodbye_tests {
+    use rcpdaemon::protocol::goodbye::{DisconnectReason, Goodbye, GOODBYE_METHOD};
+
+    #[test]
+    fn test_goodbye_round_trip() {
+        let goodbye = Goodbye::new(DisconnectReason::Kicked).with_message("maintenance");
+        let payload = goodbye.to_payload();
+
+        let message: serde_json::Value = serde_json::from_slice(&payload).unwrap();
+        assert_eq!(message["method"], GOODBYE_METHOD);
+        assert_eq!(message["params"]["reason"], "kicked");
+        assert!(message.get("id").is_none());
+
+        assert_eq!(Goodbye::from_payload(&payload), Some(goodbye.clone()));
+        assert_eq!(
+            goodbye.to_string(),
+            "disconnected by server: kicked by an administrator (maintenance)"
+        );
+    }
+
+    #[test]
+    fn test_responses_are_not_goodbyes() {
+        assert_eq!(
+            Goodbye::from_payload(br#"{"jsonrpc":"2.0","id":"1","result":{}}"#),
+            None
+        );
+        assert_eq!(Goodbye::from_payload(b"not json"), None);
+    }
+
+    #[test]
+    fn test_unknown_disconnect_reason_is_tolerated() {
+        let goodbye = Goodbye::from_payload(
+            br#"{"jsonrpc":"2.0","method":"session/goodbye","params":{"reason":"overloaded"}}"#,
+        )
+        .unwrap();
+        assert_eq!(goodbye.reason, DisconnectReason::Unknown);
+    }
+}
+
+mod keepalive_tests {
+    use rcpdaemon::protocol::keepalive::{Keepalive, PING_METHOD, PONG_METHOD};
+
+    #[test]
+    fn test_ping_and_pong_round_trip() {
+        let ping = Keepalive::new(3);
+        let payload = ping.ping_payload();
+
+        let message: serde_json::Value = serde_json::from_slice(&payload).unwrap();
+        assert_eq!(message["method"], PING_METHOD);
+        assert_eq!(message["params"]["seq"], 3);
+        assert!(message.get("id").is_none());
+        assert_eq!(Keepalive::ping_from_payload(&payload), Some(ping));
+        assert_eq!(Keepalive::pong_from_payload(&payload), None);
+
+        let pong = ping.pong_payload();
+        let message: serde_json::Value = serde_json::from_slice(&pong).unwrap();
+        assert_eq!(message["method"], PONG_METHOD);
+        assert_eq!(Keepalive::pong_from_payload(&pong), Some(ping));
+        assert_eq!(Keepalive::ping_from_payload(&pong), None);
+    }
+
+    #[test]
+    fn test_requests_are_not_pongs() {
+        // A request that happens to use the method name still gets answered
+        assert_eq!(
+            Keepalive::pong_from_payload(
+                br#"{"jsonrpc":"2.0","id":"1","method":"session/pong","params":{"seq":1}}"#
+            ),
+            None
+        );
+        assert_eq!(Keepalive::pong_from_payload(b"not json"), None);
+    }
+}
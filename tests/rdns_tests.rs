@@ -0,0 +1,152 @@
+//! Reverse DNS for connection logs
+
+use rcpdaemon::client::Client;
+use rcpdaemon::server::config::ServerConfig;
+use rcpdaemon::server::rdns::{describe_source, ReverseDns, ReverseResolver, MAX_CACHED_ADDRESSES};
+use rcpdaemon::server::Server;
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::TcpListener;
+
+/// Resolver answering from a fixed table, after `delay`, counting its lookups
+#[derive(Default)]
+struct FakeResolver {
+    names: HashMap<IpAddr, String>,
+    delay: Duration,
+    calls: Arc<AtomicUsize>,
+}
+
+impl FakeResolver {
+    fn with_name(mut self, ip: IpAddr, name: &str) -> Self {
+        self.names.insert(ip, name.to_string());
+        self
+    }
+}
+
+impl ReverseResolver for FakeResolver {
+    fn resolve(&self, ip: IpAddr) -> Option<String> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        std::thread::sleep(self.delay);
+        self.names.get(&ip).cloned()
+    }
+}
+
+const LOCALHOST: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+#[test]
+fn test_describe_source() {
+    assert_eq!(
+        describe_source(Some("db1.example.com"), LOCALHOST),
+        "db1.example.com (127.0.0.1)"
+    );
+    assert_eq!(describe_source(None, LOCALHOST), "127.0.0.1");
+    assert_eq!(
+        describe_source(None, IpAddr::V6(Ipv6Addr::LOCALHOST)),
+        "::1"
+    );
+}
+
+#[tokio::test]
+async fn test_lookups_are_cached() {
+    let unnamed = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+    let resolver = FakeResolver::default().with_name(LOCALHOST, "localhost");
+    let calls = resolver.calls.clone();
+    let dns = ReverseDns::new(Arc::new(resolver), Duration::from_secs(1));
+
+    assert_eq!(dns.describe(LOCALHOST).await, "localhost (127.0.0.1)");
+    assert_eq!(dns.describe(LOCALHOST).await, "localhost (127.0.0.1)");
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+    // Addresses without a name are remembered too
+    assert_eq!(dns.describe(unnamed).await, "192.0.2.1");
+    assert_eq!(dns.describe(unnamed).await, "192.0.2.1");
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn test_full_cache_evicts_the_oldest_address() {
+    let resolver = FakeResolver::default();
+    let calls = resolver.calls.clone();
+    let dns = ReverseDns::new(Arc::new(resolver), Duration::from_secs(1));
+    let address = |i: usize| IpAddr::V4(Ipv4Addr::from(0x0a00_0000 + i as u32));
+
+    // Every entry is fresh, so none can be dropped as expired
+    for i in 0..=MAX_CACHED_ADDRESSES {
+        dns.lookup(address(i)).await;
+    }
+    assert_eq!(dns.cached_addresses(), MAX_CACHED_ADDRESSES);
+    let looked_up = calls.load(Ordering::SeqCst);
+
+    // The newest address is still cached, the first one made room for it
+    dns.lookup(address(MAX_CACHED_ADDRESSES)).await;
+    assert_eq!(calls.load(Ordering::SeqCst), looked_up);
+    dns.lookup(address(0)).await;
+    assert_eq!(calls.load(Ordering::SeqCst), looked_up + 1);
+    assert_eq!(dns.cached_addresses(), MAX_CACHED_ADDRESSES);
+}
+
+#[tokio::test]
+async fn test_slow_lookup_falls_back_to_ip() {
+    let resolver = FakeResolver {
+        delay: Duration::from_millis(500),
+        ..Default::default()
+    }
+    .with_name(LOCALHOST, "localhost");
+    let calls = resolver.calls.clone();
+    let dns = ReverseDns::new(Arc::new(resolver), Duration::from_millis(50));
+
+    let started = Instant::now();
+    assert_eq!(dns.describe(LOCALHOST).await, "127.0.0.1");
+    assert!(started.elapsed() < Duration::from_millis(400));
+
+    // A timed out lookup is not cached, so the next connection tries again
+    dns.describe(LOCALHOST).await;
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn test_accept_loop_does_not_wait_for_lookup() {
+    let resolver = FakeResolver {
+        delay: Duration::from_secs(2),
+        ..Default::default()
+    };
+    let calls = resolver.calls.clone();
+
+    let mut config = ServerConfig::default();
+    config.auth.required = false;
+    config.log_reverse_dns = true;
+    config.reverse_dns_timeout_ms = 5_000;
+    let server = Server::builder()
+        .config(config)
+        .reverse_resolver(resolver)
+        .build();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(server.serve(listener));
+
+    let client = Client::new(addr.ip().to_string(), addr.port(), 5);
+    let started = Instant::now();
+    client.ping().await.unwrap();
+    client.ping().await.unwrap();
+    assert!(started.elapsed() < Duration::from_secs(1));
+    assert!(calls.load(Ordering::SeqCst) >= 1);
+}
+
+#[test]
+fn test_reverse_dns_is_off_by_default() {
+    let config = ServerConfig::default();
+    assert!(!config.log_reverse_dns);
+
+    let config: ServerConfig = toml::from_str(
+        r#"
+        log_reverse_dns = true
+        reverse_dns_timeout_ms = 250
+        "#,
+    )
+    .unwrap();
+    assert!(config.log_reverse_dns);
+    assert_eq!(config.reverse_dns_timeout_ms, 250);
+}
@@ -0,0 +1,2198 @@
+//! Integrated server tests
+//!
+//! These tests exercise server components without running the full daemon.
+
+mod common;
+
+use bytes::Bytes;
+use common::open_config;
+use rcpdaemon::auth::factory::{AuthConfig, AuthProviderType};
+use rcpdaemon::auth::manager::AuthManager;
+use rcpdaemon::auth::mock_provider::MockAuthProvider;
+use rcpdaemon::client::{Client, ClientError};
+use rcpdaemon::protocol::codec::{self, FrameError};
+use rcpdaemon::protocol::goodbye::{DisconnectReason, Goodbye};
+use rcpdaemon::protocol::handshake::{self, client_handshake, ClientHello};
+use rcpdaemon::protocol::keepalive::Keepalive;
+use rcpdaemon::server::apps::{
+    AppDefinition, AppLauncher, AppRegistry, OutputBuffer, OutputStream, MANAGE_APPS_PERMISSION,
+    READ_APPS_PERMISSION,
+};
+use rcpdaemon::server::config::{
+    ApplicationConfig, AuthFailureBehavior, BindRetryConfig, ServerConfig,
+};
+use rcpdaemon::server::error::Error;
+use rcpdaemon::server::events::{EventLog, ServerEventType};
+use rcpdaemon::server::listener::{bind_listener, retry_bind, SocketOptions};
+use rcpdaemon::server::metrics::ServerMetrics;
+use rcpdaemon::server::ratelimit::{source_key, AuthRateLimiter, MAX_TRACKED_SOURCES};
+use rcpdaemon::server::resume::{HeldSession, ResumeError, ResumeRegistry};
+use rcpdaemon::server::rpc;
+use rcpdaemon::server::server::RESTART_PERMISSION;
+use rcpdaemon::server::session::{self, MANAGE_SESSIONS_PERMISSION};
+use rcpdaemon::server::tokens::OBSERVER_PERMISSION;
+use rcpdaemon::server::user::{
+    ImportMode, User, UserExport, UserManager, UserRole, UserUpdate, MANAGE_USERS_PERMISSION,
+    USER_EXPORT_VERSION,
+};
+use rcpdaemon::server::{ConnectDecision, Server};
+use std::io;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use uuid::Uuid;
+
+#[tokio::test]
+async fn test_bind_retries_until_address_available() {
+    let retry = BindRetryConfig {
+        attempts: 5,
+        delay_ms: 10,
+    };
+    let calls = Arc::new(AtomicU32::new(0));
+
+    // Simulate an interface that only comes up on the third attempt
+    let counter = calls.clone();
+    let listener = retry_bind("127.0.0.1:0", &retry, move |addr| {
+        let counter = counter.clone();
+        async move {
+            if counter.fetch_add(1, Ordering::SeqCst) < 2 {
+                Err(io::Error::new(
+                    io::ErrorKind::AddrNotAvailable,
+                    "cannot assign requested address",
+                ))
+            } else {
+                TcpListener::bind(addr).await
+            }
+        }
+    })
+    .await
+    .expect("bind should eventually succeed");
+
+    assert_eq!(calls.load(Ordering::SeqCst), 3);
+    assert!(listener.local_addr().is_ok());
+}
+
+#[tokio::test]
+async fn test_bind_gives_up_after_configured_attempts() {
+    let retry = BindRetryConfig {
+        attempts: 2,
+        delay_ms: 1,
+    };
+    let calls = Arc::new(AtomicU32::new(0));
+
+    let counter = calls.clone();
+    let result: io::Result<()> = retry_bind("127.0.0.1:0", &retry, move |_addr| {
+        let counter = counter.clone();
+        async move {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Err(io::Error::new(io::ErrorKind::AddrNotAvailable, "not yet"))
+        }
+    })
+    .await;
+
+    assert!(result.is_err());
+    assert_eq!(calls.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn test_bind_does_not_retry_permanent_errors() {
+    let retry = BindRetryConfig {
+        attempts: 5,
+        delay_ms: 1,
+    };
+    let calls = Arc::new(AtomicU32::new(0));
+
+    let counter = calls.clone();
+    let result: io::Result<()> = retry_bind("127.0.0.1:0", &retry, move |_addr| {
+        let counter = counter.clone();
+        async move {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Err(io::Error::new(io::ErrorKind::PermissionDenied, "denied"))
+        }
+    })
+    .await;
+
+    assert!(result.is_err());
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_listener_rebinds_port_right_after_close() {
+    let options = SocketOptions::from_config(&ServerConfig::default());
+    assert!(options.reuse_addr);
+    assert!(!options.reuse_port);
+
+    let listener = bind_listener("127.0.0.1:0", options).await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    // Closing the accepted side first leaves the port's connection in TIME_WAIT
+    let client = TcpStream::connect(addr).await.unwrap();
+    let (accepted, _) = listener.accept().await.unwrap();
+    drop(accepted);
+    drop(listener);
+    drop(client);
+
+    let rebound = bind_listener(&addr.to_string(), options).await.unwrap();
+    assert_eq!(rebound.local_addr().unwrap(), addr);
+}
+
+#[test]
+fn test_reuse_addr_can_be_turned_off() {
+    let config: ServerConfig = toml::from_str("reuse_addr = false").unwrap();
+    assert_eq!(
+        SocketOptions::from_config(&config),
+        SocketOptions {
+            reuse_addr: false,
+            reuse_port: false,
+        }
+    );
+}
+
+#[test]
+fn test_output_buffer_is_bounded() {
+    let mut buffer = OutputBuffer::new(3);
+    for i in 0..5 {
+        buffer.push(OutputStream::Stdout, format!("line {}", i));
+    }
+
+    assert_eq!(buffer.len(), 3);
+    assert_eq!(buffer.next_seq(), 5);
+
+    let lines: Vec<String> = buffer.tail(10).into_iter().map(|l| l.line).collect();
+    assert_eq!(lines, vec!["line 2", "line 3", "line 4"]);
+}
+
+#[test]
+fn test_output_buffer_since_returns_newer_lines() {
+    let mut buffer = OutputBuffer::new(10);
+    buffer.push(OutputStream::Stdout, "first".to_string());
+    buffer.push(OutputStream::Stderr, "second".to_string());
+    buffer.push(OutputStream::Stdout, "third".to_string());
+
+    let newer = buffer.since(1, 10);
+    assert_eq!(newer.len(), 2);
+    assert_eq!(newer[0].line, "second");
+    assert_eq!(newer[0].stream, OutputStream::Stderr);
+
+    assert!(buffer.since(buffer.next_seq(), 10).is_empty());
+    assert_eq!(buffer.tail(1)[0].line, "third");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_launcher_captures_stdout_and_stderr() {
+    let launcher = AppLauncher::new(100);
+    let app = AppDefinition {
+        id: "echo".to_string(),
+        name: "Echo".to_string(),
+        executable_path: "sh".to_string(),
+        arguments: vec!["-c".to_string()],
+        working_dir: None,
+        ..Default::default()
+    };
+
+    let instance = launcher
+        .launch(
+            &app,
+            "tester",
+            &[],
+            &["echo out; echo err >&2; sleep 5".to_string()],
+        )
+        .await
+        .expect("launch should succeed");
+    let id: Uuid = instance.id.parse().unwrap();
+
+    // Output is captured asynchronously; wait for both lines to arrive
+    let mut logs = launcher.logs(&id, 10, None).await.unwrap();
+    for _ in 0..50 {
+        if logs.lines.len() == 2 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        logs = launcher.logs(&id, 10, None).await.unwrap();
+    }
+
+    assert_eq!(logs.lines.len(), 2);
+    assert!(logs
+        .lines
+        .iter()
+        .any(|l| l.stream == OutputStream::Stdout && l.line == "out"));
+    assert!(logs
+        .lines
+        .iter()
+        .any(|l| l.stream == OutputStream::Stderr && l.line == "err"));
+
+    launcher.stop(&id).await.unwrap();
+    assert!(launcher.list().await.is_empty());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_exited_instance_is_removed_from_registry() {
+    let events = EventLog::new(10);
+    let launcher = AppLauncher::default().with_events(events.clone());
+    let app = AppDefinition {
+        id: "short".to_string(),
+        name: "Short-lived".to_string(),
+        executable_path: "sh".to_string(),
+        arguments: vec!["-c".to_string(), "exit 3".to_string()],
+        working_dir: None,
+        ..Default::default()
+    };
+
+    let instance = launcher.launch(&app, "tester", &[], &[]).await.unwrap();
+    let id: Uuid = instance.id.parse().unwrap();
+
+    let mut exited = Vec::new();
+    for _ in 0..100 {
+        exited = events.recent(10, &[ServerEventType::AppExited]);
+        if !exited.is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    assert_eq!(exited.len(), 1);
+    assert_eq!(exited[0].instance_id, Some(id));
+    assert_eq!(exited[0].exit_code, Some(3));
+    assert_eq!(exited[0].user.as_deref(), Some("tester"));
+    assert!(launcher.list().await.is_empty());
+
+    // Already reaped, so there is nothing left to stop
+    assert!(matches!(launcher.stop(&id).await, Err(Error::NotFound(_))));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_stopped_instance_exits_once() {
+    let events = EventLog::new(10);
+    let launcher = AppLauncher::default().with_events(events.clone());
+    let app = AppDefinition {
+        id: "sleeper".to_string(),
+        name: "Sleeper".to_string(),
+        executable_path: "sleep".to_string(),
+        arguments: vec!["30".to_string()],
+        working_dir: None,
+        ..Default::default()
+    };
+
+    let instance = launcher.launch(&app, "tester", &[], &[]).await.unwrap();
+    let id: Uuid = instance.id.parse().unwrap();
+    assert_eq!(launcher.list().await.len(), 1);
+
+    launcher.stop(&id).await.unwrap();
+    assert!(launcher.list().await.is_empty());
+
+    // Killed by a signal, so there is no exit code
+    let exited = events.recent(10, &[ServerEventType::AppExited]);
+    assert_eq!(exited.len(), 1);
+    assert_eq!(exited[0].instance_id, Some(id));
+    assert_eq!(exited[0].exit_code, None);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_session_info_lists_apps_running_in_session() {
+    let server = Server::new(open_config());
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(server.clone().serve(listener));
+
+    // A session that stays connected while its apps run
+    let (mut framed, compressor) = connect_resuming(addr, None).await;
+    let info = request_session_info(&mut framed, &compressor).await;
+    let session_id: Uuid = serde_json::from_value(info["result"]["session_id"].clone()).unwrap();
+
+    let app = AppDefinition {
+        id: "sleeper".to_string(),
+        name: "Sleeper".to_string(),
+        executable_path: "sleep".to_string(),
+        arguments: vec!["30".to_string()],
+        working_dir: None,
+        ..Default::default()
+    };
+    let apps = server.apps();
+    let owned = apps
+        .launch_in_session(session_id, &app, "tester", &[], &[])
+        .await
+        .unwrap();
+    let unowned = apps.launch(&app, "tester", &[], &[]).await.unwrap();
+    assert_eq!(owned.session_id, Some(session_id));
+    assert_eq!(unowned.session_id, None);
+
+    let client = Client::new(addr.ip().to_string(), addr.port(), 5);
+    let info = client
+        .get_session(&session_id.to_string(), true)
+        .await
+        .unwrap();
+    assert_eq!(info.id, session_id.to_string());
+    let listed = info.apps.expect("apps were asked for");
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0].id, owned.id);
+    assert_eq!(listed[0].session_id, Some(session_id.to_string()));
+
+    // Only listed when asked for
+    let info = client
+        .get_session(&session_id.to_string(), false)
+        .await
+        .unwrap();
+    assert!(info.apps.is_none());
+
+    match client.get_session(&Uuid::new_v4().to_string(), true).await {
+        Err(ClientError::Rpc { code, .. }) => assert_eq!(code, rpc::NOT_FOUND),
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    // A stopped instance no longer counts
+    apps.stop(&owned.id.parse().unwrap()).await.unwrap();
+    assert!(apps.list_in_session(&session_id).await.is_empty());
+    apps.stop(&unowned.id.parse().unwrap()).await.unwrap();
+    drop(framed);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_app_logs_need_read_apps_unless_launched_in_session() {
+    let server = Server::new(ServerConfig::default());
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(server.clone().serve(listener));
+
+    let ttl = Duration::from_secs(60);
+    let token = server
+        .tokens()
+        .issue("alice", vec!["app:*".to_string()], ttl);
+    let stream = TcpStream::connect(addr).await.unwrap();
+    let mut framed = codec::framed(stream, codec::DEFAULT_MAX_FRAME_LENGTH);
+    let hello = ClientHello {
+        auth: Some(token),
+        ..Default::default()
+    };
+    let compressor = client_handshake(&mut framed, &hello).await.unwrap();
+    let info = request_session_info(&mut framed, &compressor).await;
+    let session_id: Uuid = serde_json::from_value(info["result"]["session_id"].clone()).unwrap();
+
+    let app = AppDefinition {
+        id: "sleeper".to_string(),
+        name: "Sleeper".to_string(),
+        executable_path: "sleep".to_string(),
+        arguments: vec!["30".to_string()],
+        ..Default::default()
+    };
+    let apps = server.apps();
+    let owned = apps
+        .launch_in_session(session_id, &app, "alice", &[], &[])
+        .await
+        .unwrap();
+    let other = apps.launch(&app, "bob", &[], &[]).await.unwrap();
+
+    let logs = |id: &str| serde_json::json!({ "instance_id": id });
+    let response = request_over(&mut framed, &compressor, "apps/logs", logs(&owned.id)).await;
+    assert_eq!(response["result"]["instance_id"], owned.id.as_str());
+    let response = request_over(&mut framed, &compressor, "apps/logs", logs(&other.id)).await;
+    assert_eq!(
+        response["error"]["code"],
+        rpc::PERMISSION_DENIED,
+        "{}",
+        response
+    );
+
+    // read:apps reaches every instance
+    let reader = Client::new(addr.ip().to_string(), addr.port(), 5).with_auth(Some(
+        server
+            .tokens()
+            .issue("ops", vec!["read:apps".to_string()], ttl),
+    ));
+    let read = reader.get_app_logs(&other.id, 10, None).await.unwrap();
+    assert_eq!(read.instance_id, other.id);
+
+    apps.stop(&owned.id.parse().unwrap()).await.unwrap();
+    apps.stop(&other.id.parse().unwrap()).await.unwrap();
+}
+
+#[test]
+fn test_app_definition_capabilities_round_trip() {
+    // A definition from before the capability fields existed
+    let old: AppDefinition = serde_json::from_value(serde_json::json!({
+        "id": "editor",
+        "name": "Editor",
+        "executable_path": "/usr/bin/editor",
+    }))
+    .unwrap();
+    assert_eq!(old.required_permission, None);
+    assert_eq!(old.category, None);
+    assert!(old.accepts_args);
+
+    let app = AppDefinition {
+        required_permission: Some("app:editor".to_string()),
+        category: Some("Office".to_string()),
+        accepts_args: false,
+        ..old
+    };
+    let json = serde_json::to_string(&app).unwrap();
+    let back: AppDefinition = serde_json::from_str(&json).unwrap();
+    assert_eq!(back.required_permission.as_deref(), Some("app:editor"));
+    assert_eq!(back.category.as_deref(), Some("Office"));
+    assert!(!back.accepts_args);
+
+    // Clients read the same fields, with the same defaults
+    let info: rcpdaemon::client::types::AppInfo = serde_json::from_str(&json).unwrap();
+    assert_eq!(info.required_permission.as_deref(), Some("app:editor"));
+    let info: rcpdaemon::client::types::AppInfo = serde_json::from_value(serde_json::json!({
+        "id": "editor",
+        "name": "Editor",
+        "executable_path": "/usr/bin/editor",
+    }))
+    .unwrap();
+    assert!(info.accepts_args);
+    assert_eq!(info.category, None);
+}
+
+/// An empty directory for one test's files
+fn scratch_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("rcpdaemon-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Application config serving the definitions in `dir`
+fn app_config(dir: &std::path::Path) -> ApplicationConfig {
+    ApplicationConfig {
+        enabled: true,
+        app_dir: dir.display().to_string(),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_app_registry_loads_definitions_from_app_dir() {
+    let dir = scratch_dir("app-registry");
+    let editor = AppDefinition {
+        id: "editor".to_string(),
+        name: "Editor".to_string(),
+        executable_path: "/usr/bin/editor".to_string(),
+        required_permission: Some("app:editor".to_string()),
+        category: Some("Office".to_string()),
+        accepts_args: false,
+        ..Default::default()
+    };
+    editor.save(&dir.join("editor.toml")).unwrap();
+    std::fs::write(
+        dir.join("legacy.json"),
+        r#"{ "id": "legacy", "name": "Legacy", "executable_path": "/usr/bin/legacy" }"#,
+    )
+    .unwrap();
+    std::fs::write(dir.join("broken.toml"), "id = ").unwrap();
+    std::fs::write(dir.join("readme.txt"), "not a definition").unwrap();
+
+    let registry = AppRegistry::from_config(&app_config(&dir));
+    let ids: Vec<String> = registry.list().into_iter().map(|app| app.id).collect();
+    assert_eq!(ids, ["editor", "legacy"]);
+    assert_eq!(registry.get("editor"), Some(editor));
+    let legacy = registry.get("legacy").unwrap();
+    assert_eq!(legacy.required_permission, None);
+    assert!(legacy.accepts_args);
+
+    // Nothing is served while application management is off
+    let disabled = ApplicationConfig {
+        enabled: false,
+        ..app_config(&dir)
+    };
+    assert!(AppRegistry::from_config(&disabled).list().is_empty());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Serve `config` with the "editor", "sleeper" and "short" applications
+///
+/// "editor" needs the `app:editor` permission and "short", which exits right
+/// away, takes no extra arguments.
+#[cfg(unix)]
+async fn spawn_app_server(
+    mut config: ServerConfig,
+    dir: &std::path::Path,
+) -> (Server, std::net::SocketAddr) {
+    let apps = [
+        AppDefinition {
+            id: "editor".to_string(),
+            name: "Editor".to_string(),
+            executable_path: "true".to_string(),
+            required_permission: Some("app:editor".to_string()),
+            category: Some("Office".to_string()),
+            accepts_args: false,
+            ..Default::default()
+        },
+        AppDefinition {
+            id: "sleeper".to_string(),
+            name: "Sleeper".to_string(),
+            executable_path: "sleep".to_string(),
+            arguments: vec!["30".to_string()],
+            ..Default::default()
+        },
+        AppDefinition {
+            id: "short".to_string(),
+            name: "Short".to_string(),
+            executable_path: "true".to_string(),
+            accepts_args: false,
+            ..Default::default()
+        },
+    ];
+    for app in &apps {
+        app.save(&dir.join(format!("{}.toml", app.id))).unwrap();
+    }
+    config.application = app_config(dir);
+
+    let server = Server::new(config);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(server.clone().serve(listener));
+    (server, addr)
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_apps_are_listed_launched_and_stopped_over_rpc() {
+    let dir = scratch_dir("apps-rpc");
+    let (server, addr) = spawn_app_server(open_config(), &dir).await;
+    let client = Client::new(addr.ip().to_string(), addr.port(), 5);
+
+    // The capability fields survive the trip through the registry and RPC
+    let listed = client.list_apps().await.unwrap();
+    let ids: Vec<&str> = listed.iter().map(|app| app.id.as_str()).collect();
+    assert_eq!(ids, ["editor", "short", "sleeper"]);
+    let editor = client.get_app_info("editor").await.unwrap();
+    assert_eq!(editor.required_permission.as_deref(), Some("app:editor"));
+    assert_eq!(editor.category.as_deref(), Some("Office"));
+    assert!(!editor.accepts_args);
+    assert!(client.get_app_info("sleeper").await.unwrap().accepts_args);
+    match client.get_app_info("missing").await {
+        Err(ClientError::Rpc { code, .. }) => assert_eq!(code, rpc::NOT_FOUND),
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    // Launched instances belong to the session that launched them
+    let (mut framed, compressor) = connect_resuming(addr, None).await;
+    let info = request_session_info(&mut framed, &compressor).await;
+    let session_id = info["result"]["session_id"].as_str().unwrap().to_string();
+    let params = serde_json::json!({ "app_id": "sleeper", "user_id": "tester" });
+    let response = request_over(&mut framed, &compressor, "apps/launch", params).await;
+    let instance_id = response["result"]["id"].as_str().unwrap().to_string();
+    assert_eq!(response["result"]["user_id"], "tester");
+    let session = client.get_session(&session_id, true).await.unwrap();
+    let apps = session.apps.expect("apps were asked for");
+    assert_eq!(apps.len(), 1);
+    assert_eq!(apps[0].id, instance_id);
+    assert!(apps[0].pid.is_some());
+
+    let instances = client.list_app_instances().await.unwrap();
+    assert!(instances.iter().any(|instance| instance.id == instance_id));
+    client.stop_app(&instance_id).await.unwrap();
+    assert!(client.list_app_instances().await.unwrap().is_empty());
+    match client.stop_app(&instance_id).await {
+        Err(ClientError::Rpc { code, .. }) => assert_eq!(code, rpc::NOT_FOUND),
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    // The definition's own limits still apply
+    let args = Some(vec!["file.txt".to_string()]);
+    match client.launch_app("short", None, args).await {
+        Err(ClientError::Rpc { code, .. }) => assert_eq!(code, rpc::INVALID_PARAMS),
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    // An instance that exits on its own is reaped from the list
+    let short = client.launch_app("short", None, None).await.unwrap();
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while server.apps().list().await.iter().any(|i| i.id == short.id) {
+        assert!(Instant::now() < deadline, "exited instance was not reaped");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    drop(framed);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_app_rpcs_need_the_apps_permissions() {
+    let dir = scratch_dir("apps-rpc-permissions");
+    let (server, addr) = spawn_app_server(ServerConfig::default(), &dir).await;
+    let ttl = Duration::from_secs(60);
+    let client_with = |permissions: &[&str]| {
+        let permissions = permissions.iter().map(|p| p.to_string()).collect();
+        let token = server.tokens().issue("alice", permissions, ttl);
+        Client::new(addr.ip().to_string(), addr.port(), 5).with_auth(Some(token))
+    };
+
+    let required = |result: Result<(), ClientError>| match result {
+        Err(ClientError::PermissionDenied(permission)) => permission.required,
+        other => panic!("unexpected result: {:?}", other),
+    };
+
+    let nobody = client_with(&[]);
+    let denied = nobody.list_apps().await.map(|_| ());
+    assert_eq!(required(denied), READ_APPS_PERMISSION);
+
+    // Reading does not allow launching or stopping
+    let reader = client_with(&[READ_APPS_PERMISSION]);
+    assert_eq!(reader.list_apps().await.unwrap().len(), 3);
+    assert!(reader.list_app_instances().await.unwrap().is_empty());
+    let denied = reader.launch_app("sleeper", None, None).await.map(|_| ());
+    assert_eq!(required(denied), MANAGE_APPS_PERMISSION);
+    let denied = reader.stop_app(&Uuid::new_v4().to_string()).await;
+    assert_eq!(required(denied), MANAGE_APPS_PERMISSION);
+
+    // Launching still needs the application's own permission
+    let admin = client_with(&[MANAGE_APPS_PERMISSION]);
+    let denied = admin.launch_app("editor", None, None).await.map(|_| ());
+    assert_eq!(required(denied), "app:editor");
+    let instance = admin.launch_app("sleeper", None, None).await.unwrap();
+    assert_eq!(instance.user_id, "alice");
+    admin.stop_app(&instance.id).await.unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_launcher_enforces_app_capabilities() {
+    let launcher = AppLauncher::default();
+    let app = AppDefinition {
+        id: "editor".to_string(),
+        executable_path: "/nonexistent/editor".to_string(),
+        required_permission: Some("app:editor".to_string()),
+        accepts_args: false,
+        ..Default::default()
+    };
+
+    let result = launcher.launch(&app, "tester", &[], &[]).await;
+    assert!(matches!(result, Err(Error::PermissionDenied(_))));
+    let result = launcher
+        .launch(&app, "tester", &["app:other".to_string()], &[])
+        .await;
+    assert!(matches!(result, Err(Error::PermissionDenied(_))));
+
+    // A wildcard grants it; extra arguments are still refused
+    let permissions = ["app:*".to_string()];
+    assert!(app.check_launch(&permissions, &[]).is_ok());
+    assert!(matches!(
+        app.check_launch(&permissions, &["file.txt".to_string()]),
+        Err(Error::InvalidArgument(_))
+    ));
+    assert!(launcher.list().await.is_empty());
+}
+
+#[tokio::test]
+async fn test_logs_for_unknown_instance_is_not_found() {
+    let launcher = AppLauncher::default();
+    let result = launcher.logs(&Uuid::new_v4(), 10, None).await;
+    assert!(matches!(result, Err(Error::NotFound(_))));
+}
+
+#[tokio::test]
+async fn test_rejecting_connect_hook_prevents_session() {
+    let calls = Arc::new(AtomicU32::new(0));
+    let counter = calls.clone();
+    let server = Server::new(ServerConfig::default()).with_connect_hook(move |_addr| {
+        counter.fetch_add(1, Ordering::SeqCst);
+        ConnectDecision::Reject
+    });
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(server.clone().serve(listener));
+
+    // A rejected connection is closed straight away
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let mut buf = [0u8; 1];
+    let read = tokio::time::timeout(Duration::from_secs(5), stream.read(&mut buf))
+        .await
+        .expect("rejected connection should be closed")
+        .unwrap_or(0);
+
+    assert_eq!(read, 0);
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+    assert!(server.get_sessions().await.is_empty());
+}
+
+#[tokio::test]
+async fn test_builder_shares_metrics_handle() {
+    let metrics = ServerMetrics::new();
+    let server = Server::builder()
+        .config(ServerConfig::default())
+        .metrics(metrics.clone())
+        .connect_hook(|_addr| ConnectDecision::Reject)
+        .build();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(server.serve(listener));
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let mut buf = [0u8; 1];
+    let _ = tokio::time::timeout(Duration::from_secs(5), stream.read(&mut buf)).await;
+
+    let snapshot = metrics.snapshot();
+    assert_eq!(snapshot.connections_rejected, 1);
+    assert_eq!(snapshot.connections_accepted, 0);
+    assert_eq!(snapshot.sessions_active, 0);
+}
+
+/// Serve `config` on an ephemeral port and return a client for it
+async fn spawn_server(config: ServerConfig) -> rcpdaemon::Client {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(Server::new(config).serve(listener));
+
+    rcpdaemon::Client::new(addr.ip().to_string(), addr.port(), 5)
+}
+
+/// Config requiring the pre-shared key "secret", failing clients as `behavior`
+fn psk_config(behavior: AuthFailureBehavior) -> ServerConfig {
+    let mut config = ServerConfig::default();
+    config.auth.psk = Some("secret".to_string());
+    config.auth_failure_behavior = behavior;
+    config.auth_tarpit_delay_ms = 300;
+    config
+}
+
+#[tokio::test]
+async fn test_anonymous_client_is_refused_when_auth_required() {
+    let client = spawn_server(ServerConfig::default()).await;
+
+    match client.whoami().await.unwrap_err() {
+        rcpdaemon::ClientError::Rpc { code, message } => {
+            assert_eq!(code, rpc::UNAUTHENTICATED);
+            assert!(message.contains("auth token"));
+        }
+        other => panic!("expected an authentication error, got {:?}", other),
+    }
+    assert!(matches!(
+        client.set_log_level("info").await,
+        Err(rcpdaemon::ClientError::Rpc { code, .. }) if code == rpc::UNAUTHENTICATED
+    ));
+}
+
+#[tokio::test]
+async fn test_auth_failure_rejects_with_reason() {
+    let client = spawn_server(psk_config(AuthFailureBehavior::RejectWithReason)).await;
+
+    match client.set_log_level("info").await.unwrap_err() {
+        rcpdaemon::ClientError::Rpc { code, message } => {
+            assert_eq!(code, rpc::UNAUTHENTICATED);
+            assert!(message.contains("pre-shared key"));
+        }
+        other => panic!("expected an authentication error, got {:?}", other),
+    }
+
+    let authenticated = client.with_auth(Some("secret".to_string()));
+    let level = log::max_level().to_string().to_lowercase();
+    assert_eq!(authenticated.set_log_level(&level).await.unwrap(), level);
+}
+
+/// Complete the handshake with `addr` as a client answering its challenge
+/// with the proof `prove` makes of it, returning the challenge
+async fn connect_with_proof(
+    addr: std::net::SocketAddr,
+    prove: impl FnOnce(&str) -> String,
+) -> (
+    codec::FramedStream<TcpStream>,
+    rcpdaemon::protocol::FrameCompressor,
+    String,
+) {
+    let stream = TcpStream::connect(addr).await.unwrap();
+    let mut framed = codec::framed(stream, codec::DEFAULT_MAX_FRAME_LENGTH);
+    framed
+        .get_mut()
+        .write_all(&handshake::PROTOCOL_MAGIC)
+        .await
+        .unwrap();
+    let hello = ClientHello {
+        challenge: true,
+        ..Default::default()
+    };
+    codec::write_frame(
+        &mut framed,
+        Bytes::from(serde_json::to_vec(&hello).unwrap()),
+    )
+    .await
+    .unwrap();
+
+    let frame = codec::read_frame(&mut framed).await.unwrap();
+    let server_hello: handshake::ServerHello = serde_json::from_slice(&frame).unwrap();
+    let challenge = server_hello
+        .challenge
+        .expect("server should issue a challenge");
+    let response = handshake::ChallengeResponse {
+        proof: prove(&challenge),
+    };
+    codec::write_frame(
+        &mut framed,
+        Bytes::from(serde_json::to_vec(&response).unwrap()),
+    )
+    .await
+    .unwrap();
+
+    let compressor = rcpdaemon::protocol::FrameCompressor::new(
+        server_hello.compression,
+        server_hello.compression_threshold,
+        codec::DEFAULT_MAX_FRAME_LENGTH,
+    );
+    (framed, compressor, challenge)
+}
+
+#[tokio::test]
+async fn test_psk_proof_replayed_on_a_new_connection_is_rejected() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(Server::new(psk_config(AuthFailureBehavior::RejectWithReason)).serve(listener));
+
+    let (mut framed, compressor, first) = connect_with_proof(addr, |challenge| {
+        handshake::challenge_proof("secret", challenge)
+    })
+    .await;
+    let response = request_session_info(&mut framed, &compressor).await;
+    assert!(response["result"]["session_id"].is_string(), "{}", response);
+
+    // A proof recorded on the first connection only held for its challenge
+    let recorded = handshake::challenge_proof("secret", &first);
+    let (mut framed, compressor, second) = connect_with_proof(addr, |_| recorded).await;
+    assert_ne!(first, second);
+    let response = request_session_info(&mut framed, &compressor).await;
+    assert_eq!(
+        response["error"]["code"],
+        rpc::UNAUTHENTICATED,
+        "{}",
+        response
+    );
+}
+
+#[test]
+fn test_plain_key_matches_only_itself() {
+    assert!(handshake::verify_plain_key("secret", "secret"));
+    assert!(!handshake::verify_plain_key("secret", "secreT"));
+    assert!(!handshake::verify_plain_key("secret", "secret2"));
+    assert!(!handshake::verify_plain_key("secret", ""));
+}
+
+#[tokio::test]
+async fn test_required_psk_proof_refuses_key_sent_as_is() {
+    let mut config = psk_config(AuthFailureBehavior::RejectWithReason);
+    config.auth.require_psk_proof = true;
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(Server::new(config).serve(listener));
+
+    let stream = TcpStream::connect(addr).await.unwrap();
+    let mut framed = codec::framed(stream, codec::DEFAULT_MAX_FRAME_LENGTH);
+    let hello = ClientHello {
+        auth: Some("secret".to_string()),
+        ..Default::default()
+    };
+    let compressor = client_handshake(&mut framed, &hello).await.unwrap();
+    let response = request_session_info(&mut framed, &compressor).await;
+    assert_eq!(
+        response["error"]["code"],
+        rpc::UNAUTHENTICATED,
+        "{}",
+        response
+    );
+    assert!(response["error"]["message"]
+        .as_str()
+        .unwrap()
+        .contains("handshake challenge"));
+
+    // The client proves its key rather than sending it
+    let client = rcpdaemon::Client::new(addr.ip().to_string(), addr.port(), 5)
+        .with_auth(Some("secret".to_string()));
+    let level = log::max_level().to_string().to_lowercase();
+    assert_eq!(client.set_log_level(&level).await.unwrap(), level);
+}
+
+#[tokio::test]
+async fn test_auth_failure_silent_close() {
+    let client = spawn_server(psk_config(AuthFailureBehavior::SilentClose))
+        .await
+        .with_auth(Some("wrong".to_string()));
+
+    let started = Instant::now();
+    let err = client.set_log_level("info").await.unwrap_err();
+
+    // No reason is given; the connection is simply closed
+    assert!(matches!(err, rcpdaemon::ClientError::Frame(_)), "{:?}", err);
+    assert!(started.elapsed() < Duration::from_millis(300));
+}
+
+#[tokio::test]
+async fn test_auth_failure_tarpit_delays_close() {
+    let client = spawn_server(psk_config(AuthFailureBehavior::Tarpit)).await;
+
+    let started = Instant::now();
+    let err = client.set_log_level("info").await.unwrap_err();
+
+    assert!(matches!(err, rcpdaemon::ClientError::Frame(_)), "{:?}", err);
+    assert!(started.elapsed() >= Duration::from_millis(300));
+}
+
+#[test]
+fn test_auth_failure_behavior_from_toml() {
+    let config: ServerConfig = toml::from_str(
+        r#"
+        auth_failure_behavior = "tarpit"
+        auth_tarpit_delay_ms = 1500
+        "#,
+    )
+    .unwrap();
+
+    assert_eq!(config.auth_failure_behavior, AuthFailureBehavior::Tarpit);
+    assert_eq!(config.auth_tarpit_delay_ms, 1500);
+    assert_eq!(
+        ServerConfig::default().auth_failure_behavior,
+        AuthFailureBehavior::RejectWithReason
+    );
+}
+
+#[tokio::test]
+async fn test_non_rcp_client_is_closed_without_waiting_for_timeout() {
+    let server = Server::new(ServerConfig::default());
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(server.clone().serve(listener));
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+
+    // Well within the default 10s handshake timeout
+    let mut buf = [0u8; 64];
+    let read = tokio::time::timeout(Duration::from_secs(1), stream.read(&mut buf))
+        .await
+        .expect("non-RCP client should be closed at once")
+        .unwrap_or(0);
+    assert_eq!(read, 0);
+}
+
+#[tokio::test]
+async fn test_silent_client_is_closed_after_handshake_timeout() {
+    let mut config = ServerConfig::default();
+    config.session.handshake_timeout_ms = 200;
+    let server = Server::new(config);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(server.clone().serve(listener));
+
+    let started = Instant::now();
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let mut buf = [0u8; 1];
+    let read = tokio::time::timeout(Duration::from_secs(5), stream.read(&mut buf))
+        .await
+        .expect("silent client should be closed after the handshake timeout")
+        .unwrap_or(0);
+
+    assert_eq!(read, 0);
+    assert!(started.elapsed() >= Duration::from_millis(200));
+}
+
+#[test]
+fn test_auth_rate_limiter_limits_one_ip() {
+    let limiter = AuthRateLimiter::new(Some(3));
+    let attacker: std::net::IpAddr = "203.0.113.7".parse().unwrap();
+    let other: std::net::IpAddr = "198.51.100.1".parse().unwrap();
+
+    for _ in 0..3 {
+        assert!(!limiter.is_limited(attacker));
+        limiter.record_failure(attacker);
+    }
+
+    assert!(limiter.is_limited(attacker));
+    assert!(!limiter.is_limited(other));
+    assert_eq!(limiter.tracked_ips(), 1);
+}
+
+#[test]
+fn test_auth_rate_limiter_window_expires() {
+    let limiter = AuthRateLimiter::with_window(Some(1), Duration::from_millis(50));
+    let ip: std::net::IpAddr = "203.0.113.7".parse().unwrap();
+
+    limiter.record_failure(ip);
+    assert!(limiter.is_limited(ip));
+
+    std::thread::sleep(Duration::from_millis(60));
+    assert!(!limiter.is_limited(ip));
+    assert_eq!(limiter.tracked_ips(), 0);
+}
+
+#[test]
+fn test_auth_rate_limiter_counts_ipv6_per_prefix() {
+    let limiter = AuthRateLimiter::new(Some(2));
+    let first: std::net::IpAddr = "2001:db8:1:2::1".parse().unwrap();
+    let rotated: std::net::IpAddr = "2001:db8:1:2:ffff::9".parse().unwrap();
+    let neighbour: std::net::IpAddr = "2001:db8:1:3::1".parse().unwrap();
+
+    limiter.record_failure(first);
+    limiter.record_failure(rotated);
+    assert!(limiter.is_limited(first));
+    assert!(limiter.is_limited(rotated));
+    assert!(!limiter.is_limited(neighbour));
+    assert_eq!(limiter.tracked_ips(), 1);
+
+    // An IPv4-mapped address counts as the IPv4 address
+    let mapped: std::net::IpAddr = "::ffff:203.0.113.7".parse().unwrap();
+    assert_eq!(
+        source_key(mapped),
+        "203.0.113.7".parse::<std::net::IpAddr>().unwrap()
+    );
+}
+
+#[test]
+fn test_auth_rate_limiter_evicts_the_oldest_source_when_full() {
+    let limiter = AuthRateLimiter::new(Some(1));
+    let source = |n: usize| std::net::IpAddr::from([10, (n >> 16) as u8, (n >> 8) as u8, n as u8]);
+
+    for n in 0..MAX_TRACKED_SOURCES {
+        limiter.record_failure(source(n));
+    }
+    assert_eq!(limiter.tracked_ips(), MAX_TRACKED_SOURCES);
+    assert!(limiter.is_limited(source(0)));
+
+    limiter.record_failure(source(MAX_TRACKED_SOURCES));
+    assert_eq!(limiter.tracked_ips(), MAX_TRACKED_SOURCES);
+    assert!(!limiter.is_limited(source(0)));
+    assert!(limiter.is_limited(source(1)));
+    assert!(limiter.is_limited(source(MAX_TRACKED_SOURCES)));
+}
+
+#[test]
+fn test_auth_rate_limiter_disabled() {
+    let ip: std::net::IpAddr = "203.0.113.7".parse().unwrap();
+    for limiter in [AuthRateLimiter::new(None), AuthRateLimiter::new(Some(0))] {
+        assert!(!limiter.is_enabled());
+        for _ in 0..10 {
+            limiter.record_failure(ip);
+        }
+        assert!(!limiter.is_limited(ip));
+    }
+}
+
+#[tokio::test]
+async fn test_repeated_auth_failures_from_one_ip_are_throttled() {
+    let mut config = psk_config(AuthFailureBehavior::RejectWithReason);
+    config.auth.max_auth_attempts_per_ip_per_min = Some(2);
+    let client = spawn_server(config).await;
+
+    // Failures below the limit still get the usual answer
+    for _ in 0..2 {
+        assert!(matches!(
+            client.set_log_level("info").await,
+            Err(rcpdaemon::ClientError::Rpc { .. })
+        ));
+    }
+
+    // Once limited the credentials are not even checked: the correct key is
+    // refused too, and the connection is closed after the tarpit delay
+    let authenticated = client.with_auth(Some("secret".to_string()));
+    let started = Instant::now();
+    let result = authenticated.set_log_level("info").await;
+    assert!(result.is_err());
+    assert!(!matches!(result, Err(rcpdaemon::ClientError::Rpc { .. })));
+    assert!(started.elapsed() >= Duration::from_millis(300));
+}
+
+#[tokio::test]
+async fn test_auth_rate_limit_ignores_successful_attempts() {
+    let mut config = psk_config(AuthFailureBehavior::RejectWithReason);
+    config.auth.max_auth_attempts_per_ip_per_min = Some(1);
+    let client = spawn_server(config)
+        .await
+        .with_auth(Some("secret".to_string()));
+
+    let level = log::max_level().to_string().to_lowercase();
+    for _ in 0..3 {
+        assert_eq!(client.set_log_level(&level).await.unwrap(), level);
+    }
+}
+
+/// Connect to `addr` and complete the handshake without sending a request
+async fn connect_idle_client(
+    addr: std::net::SocketAddr,
+) -> (
+    codec::FramedStream<TcpStream>,
+    rcpdaemon::protocol::FrameCompressor,
+) {
+    let stream = TcpStream::connect(addr).await.unwrap();
+    let mut framed = codec::framed(stream, codec::DEFAULT_MAX_FRAME_LENGTH);
+    let compressor = client_handshake(&mut framed, &ClientHello::default())
+        .await
+        .unwrap();
+    (framed, compressor)
+}
+
+/// Like [`connect_idle_client`], presenting the operator's key "secret"
+async fn connect_operator(
+    addr: std::net::SocketAddr,
+) -> (
+    codec::FramedStream<TcpStream>,
+    rcpdaemon::protocol::FrameCompressor,
+) {
+    let stream = TcpStream::connect(addr).await.unwrap();
+    let mut framed = codec::framed(stream, codec::DEFAULT_MAX_FRAME_LENGTH);
+    let hello = ClientHello {
+        auth: Some("secret".to_string()),
+        ..Default::default()
+    };
+    let compressor = client_handshake(&mut framed, &hello).await.unwrap();
+    (framed, compressor)
+}
+
+#[tokio::test]
+async fn test_idle_session_gets_goodbye_before_close() {
+    let mut config = open_config();
+    config.session.timeout = 1;
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(Server::new(config).serve(listener));
+
+    let (mut framed, compressor) = connect_idle_client(addr).await;
+    let frame = tokio::time::timeout(Duration::from_secs(5), codec::read_frame(&mut framed))
+        .await
+        .expect("idle session should be closed")
+        .unwrap();
+
+    let goodbye = Goodbye::from_payload(&compressor.decode(frame).unwrap()).unwrap();
+    assert_eq!(goodbye.reason, DisconnectReason::IdleTimeout);
+    assert!(matches!(
+        codec::read_frame(&mut framed).await,
+        Err(FrameError::Closed)
+    ));
+}
+
+/// Config sending a keep-alive after a second of quiet, answered within a second
+fn keepalive_config(idle_timeout: u64) -> ServerConfig {
+    let mut config = open_config();
+    config.session.timeout = idle_timeout;
+    config.session.keepalive_secs = 1;
+    config.session.keepalive_timeout_secs = 1;
+    config
+}
+
+#[tokio::test]
+async fn test_answered_keepalives_keep_session_until_idle_timeout() {
+    let server = Server::new(keepalive_config(4));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(server.clone().serve(listener));
+
+    let (mut framed, compressor) = connect_idle_client(addr).await;
+    let started = Instant::now();
+    let mut pongs = 0;
+    let goodbye = loop {
+        let frame = tokio::time::timeout(Duration::from_secs(5), codec::read_frame(&mut framed))
+            .await
+            .expect("server should ping or close")
+            .unwrap();
+        let payload = compressor.decode(frame).unwrap();
+        match Keepalive::ping_from_payload(&payload) {
+            Some(ping) => {
+                assert_eq!(ping.seq, pongs + 1);
+                let pong = compressor.encode(ping.pong_payload()).unwrap();
+                codec::write_frame(&mut framed, pong).await.unwrap();
+                pongs += 1;
+            }
+            None => break Goodbye::from_payload(&payload).unwrap(),
+        }
+    };
+
+    // Pongs kept the connection up, but are not activity for the idle timeout
+    assert!(pongs >= 2, "only {} keep-alives", pongs);
+    assert_eq!(goodbye.reason, DisconnectReason::IdleTimeout);
+    assert!(started.elapsed() >= Duration::from_secs(4));
+}
+
+#[tokio::test]
+async fn test_unanswered_keepalive_drops_session() {
+    let server = Server::new(keepalive_config(0));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(server.clone().serve(listener));
+
+    let (mut framed, compressor) = connect_idle_client(addr).await;
+    let frame = codec::read_frame(&mut framed).await.unwrap();
+    assert!(Keepalive::ping_from_payload(&compressor.decode(frame).unwrap()).is_some());
+
+    let frame = tokio::time::timeout(Duration::from_secs(5), codec::read_frame(&mut framed))
+        .await
+        .expect("silent client should be dropped")
+        .unwrap();
+    let goodbye = Goodbye::from_payload(&compressor.decode(frame).unwrap()).unwrap();
+    assert_eq!(goodbye.reason, DisconnectReason::KeepaliveTimeout);
+    assert!(matches!(
+        codec::read_frame(&mut framed).await,
+        Err(FrameError::Closed)
+    ));
+}
+
+/// Send a request every 200ms over a fresh connection until the server says goodbye
+///
+/// Returns the goodbye and how many requests were answered before it.
+async fn stay_busy_until_goodbye(addr: std::net::SocketAddr) -> (Goodbye, usize) {
+    let (mut framed, compressor) = connect_idle_client(addr).await;
+    let request = serde_json::json!({ "jsonrpc": "2.0", "id": 1, "method": "session/info" });
+    let mut answered = 0;
+    loop {
+        let frame = compressor.encode(Bytes::from(request.to_string())).unwrap();
+        codec::write_frame(&mut framed, frame).await.unwrap();
+        let frame = tokio::time::timeout(Duration::from_secs(5), codec::read_frame(&mut framed))
+            .await
+            .expect("server should answer or close")
+            .unwrap();
+        if let Some(goodbye) = Goodbye::from_payload(&compressor.decode(frame).unwrap()) {
+            return (goodbye, answered);
+        }
+        answered += 1;
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+}
+
+#[tokio::test]
+async fn test_active_session_closed_after_max_lifetime() {
+    let mut config = open_config();
+    config.session.timeout = 1;
+    config.session.max_lifetime_secs = 2;
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(Server::new(config).serve(listener));
+
+    let started = Instant::now();
+    let (goodbye, answered) = stay_busy_until_goodbye(addr).await;
+
+    // Never idle for the idle timeout, but closed once two seconds old
+    assert_eq!(goodbye.reason, DisconnectReason::LifetimeExceeded);
+    assert_eq!(
+        goodbye.message.as_deref(),
+        Some("session lifetime exceeded; please reconnect")
+    );
+    assert!(started.elapsed() >= Duration::from_secs(2));
+    assert!(answered >= 5, "only {} requests answered", answered);
+}
+
+#[tokio::test]
+async fn test_max_lifetime_overridden_for_role() {
+    use rcpdaemon::server::config::RoleSessionConfig;
+
+    let mut config = open_config();
+    config.session.roles.insert(
+        "admin".to_string(),
+        RoleSessionConfig {
+            max_lifetime_secs: Some(1),
+            ..Default::default()
+        },
+    );
+    assert_eq!(config.session.max_lifetime_for(Some(&UserRole::Admin)), 1);
+    assert_eq!(config.session.max_lifetime_for(Some(&UserRole::User)), 0);
+    assert_eq!(
+        config.session.timeout_for(Some(&UserRole::Admin)),
+        config.session.timeout
+    );
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(Server::new(config).serve(listener));
+
+    // Without a credential the session is the operator's, an admin
+    let (goodbye, _) = stay_busy_until_goodbye(addr).await;
+    assert_eq!(goodbye.reason, DisconnectReason::LifetimeExceeded);
+}
+
+#[tokio::test]
+async fn test_send_goodbye_closes_session() {
+    let server = Server::new(open_config());
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(server.clone().serve(listener));
+
+    let (mut framed, compressor) = connect_idle_client(addr).await;
+    let session_id = server.get_sessions().await[0];
+    server
+        .send_goodbye(&session_id, Goodbye::new(DisconnectReason::Shutdown))
+        .unwrap();
+
+    let frame = codec::read_frame(&mut framed).await.unwrap();
+    let goodbye = Goodbye::from_payload(&compressor.decode(frame).unwrap()).unwrap();
+    assert_eq!(goodbye.reason, DisconnectReason::Shutdown);
+
+    // A session can only be told once
+    assert!(matches!(
+        server.send_goodbye(&session_id, Goodbye::new(DisconnectReason::Shutdown)),
+        Err(Error::NotFound(_))
+    ));
+    assert!(matches!(
+        server.send_goodbye(&Uuid::new_v4(), Goodbye::new(DisconnectReason::Shutdown)),
+        Err(Error::NotFound(_))
+    ));
+}
+
+/// Connect to `addr`, resuming the session `resume` is a token for if given
+async fn connect_resuming(
+    addr: std::net::SocketAddr,
+    resume: Option<String>,
+) -> (
+    codec::FramedStream<TcpStream>,
+    rcpdaemon::protocol::FrameCompressor,
+) {
+    let stream = TcpStream::connect(addr).await.unwrap();
+    let mut framed = codec::framed(stream, codec::DEFAULT_MAX_FRAME_LENGTH);
+    let hello = ClientHello {
+        resume,
+        ..Default::default()
+    };
+    let compressor = client_handshake(&mut framed, &hello).await.unwrap();
+    (framed, compressor)
+}
+
+/// Ask for `session/info` over an open connection, returning the raw response
+async fn request_session_info(
+    framed: &mut codec::FramedStream<TcpStream>,
+    compressor: &rcpdaemon::protocol::FrameCompressor,
+) -> serde_json::Value {
+    request_over(framed, compressor, "session/info", serde_json::Value::Null).await
+}
+
+/// Call `method` over an open connection, returning the raw response
+async fn request_over(
+    framed: &mut codec::FramedStream<TcpStream>,
+    compressor: &rcpdaemon::protocol::FrameCompressor,
+    method: &str,
+    params: serde_json::Value,
+) -> serde_json::Value {
+    let request =
+        serde_json::json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+    let frame = compressor.encode(Bytes::from(request.to_string())).unwrap();
+    codec::write_frame(framed, frame).await.unwrap();
+    let frame = codec::read_frame(framed).await.unwrap();
+    serde_json::from_slice(&compressor.decode(frame).unwrap()).unwrap()
+}
+
+/// Wait until the server holds `count` dropped sessions for resumption
+async fn wait_for_held_sessions(server: &Server, count: usize) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while server.resumption().len() != count {
+        assert!(Instant::now() < deadline, "session was not held");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+#[tokio::test]
+async fn test_dropped_session_resumes_with_token() {
+    let mut config = open_config();
+    config.session.resume_grace_secs = 30;
+    let server = Server::new(config);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(server.clone().serve(listener));
+
+    let (mut framed, compressor) = connect_resuming(addr, None).await;
+    let info = request_session_info(&mut framed, &compressor).await["result"].clone();
+    let session_id = info["session_id"].clone();
+    let token = info["resume_token"].as_str().unwrap().to_string();
+    drop(framed);
+    wait_for_held_sessions(&server, 1).await;
+
+    // The new connection takes over the dropped session's ID and gets a new token
+    let (mut framed, compressor) = connect_resuming(addr, Some(token.clone())).await;
+    let info = request_session_info(&mut framed, &compressor).await["result"].clone();
+    assert_eq!(info["session_id"], session_id);
+    assert_ne!(info["resume_token"], token.as_str());
+    assert!(server.resumption().is_empty());
+    let listed: Vec<String> = server
+        .session_summaries()
+        .iter()
+        .map(|s| s.id.to_string())
+        .collect();
+    assert_eq!(listed, vec![session_id.as_str().unwrap().to_string()]);
+
+    // A token is good for one resume only
+    drop(framed);
+    wait_for_held_sessions(&server, 1).await;
+    let (mut framed, compressor) = connect_resuming(addr, Some(token)).await;
+    let response = request_session_info(&mut framed, &compressor).await;
+    assert_eq!(response["error"]["code"], rpc::UNAUTHENTICATED);
+}
+
+#[tokio::test]
+async fn test_expired_resume_token_is_refused() {
+    let mut config = open_config();
+    config.session.resume_grace_secs = 1;
+    let server = Server::new(config);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(server.clone().serve(listener));
+
+    let (mut framed, compressor) = connect_resuming(addr, None).await;
+    let info = request_session_info(&mut framed, &compressor).await["result"].clone();
+    let token = info["resume_token"].as_str().unwrap().to_string();
+    drop(framed);
+    wait_for_held_sessions(&server, 1).await;
+    tokio::time::sleep(Duration::from_millis(1_200)).await;
+
+    let (mut framed, compressor) = connect_resuming(addr, Some(token)).await;
+    let response = request_session_info(&mut framed, &compressor).await;
+    assert_eq!(response["error"]["code"], rpc::UNAUTHENTICATED);
+    assert!(response["error"]["message"]
+        .as_str()
+        .unwrap()
+        .contains("expired"));
+}
+
+#[test]
+fn test_resume_registry_reaps_after_grace() {
+    let registry = ResumeRegistry::new();
+    let held = HeldSession {
+        session_id: Uuid::new_v4(),
+        created_at: "2024-01-01T00:00:00+00:00".to_string(),
+        client_name: Some("alice".to_string()),
+        permissions: vec!["app:*".to_string()],
+        token_id: None,
+        token_exp: None,
+    };
+
+    registry.hold("live".to_string(), held.clone(), Duration::from_secs(60));
+    registry.hold("stale".to_string(), held.clone(), Duration::ZERO);
+    assert_eq!(registry.len(), 1);
+    assert_eq!(registry.take("live"), Ok(held.clone()));
+    assert_eq!(registry.take("live"), Err(ResumeError::Unknown));
+
+    registry.hold("brief".to_string(), held, Duration::from_millis(20));
+    std::thread::sleep(Duration::from_millis(40));
+    assert_eq!(registry.take("brief"), Err(ResumeError::Expired));
+
+    // Sessions are not held unless resumption is switched on
+    assert_eq!(ServerConfig::default().session.resume_grace_secs, 0);
+}
+
+#[tokio::test]
+async fn test_kicked_session_receives_reason_before_close() {
+    let server = Server::new(open_config());
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(server.clone().serve(listener));
+
+    let (mut framed, compressor) = connect_idle_client(addr).await;
+    let session_id = server.get_sessions().await[0].to_string();
+
+    let admin = Client::new(addr.ip().to_string(), addr.port(), 5);
+    admin
+        .kick_session(&session_id, Some("maintenance window"))
+        .await
+        .unwrap();
+
+    let frame = codec::read_frame(&mut framed).await.unwrap();
+    let goodbye = Goodbye::from_payload(&compressor.decode(frame).unwrap()).unwrap();
+    assert_eq!(goodbye.reason, DisconnectReason::Kicked);
+    assert_eq!(goodbye.message.as_deref(), Some("maintenance window"));
+    assert!(matches!(
+        codec::read_frame(&mut framed).await,
+        Err(FrameError::Closed)
+    ));
+
+    // The session is gone, so kicking it again fails
+    let err = admin.kick_session(&session_id, None).await.unwrap_err();
+    assert!(matches!(err, ClientError::Rpc { code, .. } if code == rpc::NOT_FOUND));
+}
+
+#[tokio::test]
+async fn test_kick_requires_admin_permission() {
+    let server = Server::new(ServerConfig::default());
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(server.clone().serve(listener));
+
+    let token = server
+        .tokens()
+        .issue("alice", vec!["app:*".to_string()], Duration::from_secs(60));
+    let client = Client::new(addr.ip().to_string(), addr.port(), 5).with_auth(Some(token));
+
+    match client
+        .kick_session(&Uuid::new_v4().to_string(), None)
+        .await
+        .unwrap_err()
+    {
+        ClientError::PermissionDenied(permission) => {
+            assert_eq!(permission.required, MANAGE_SESSIONS_PERMISSION)
+        }
+        other => panic!("unexpected error: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_restart_requires_admin_and_drains_sessions() {
+    let server = Server::new(psk_config(AuthFailureBehavior::RejectWithReason));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(server.clone().serve(listener));
+
+    let (mut framed, compressor) = connect_operator(addr).await;
+    let idle_id = server.get_sessions().await[0];
+
+    let token = server
+        .tokens()
+        .issue("alice", vec!["read:*".to_string()], Duration::from_secs(60));
+    let client = Client::new(addr.ip().to_string(), addr.port(), 15);
+    match client
+        .clone()
+        .with_auth(Some(token))
+        .restart_server()
+        .await
+        .unwrap_err()
+    {
+        ClientError::PermissionDenied(permission) => {
+            assert_eq!(permission.required, RESTART_PERMISSION)
+        }
+        other => panic!("unexpected error: {:?}", other),
+    }
+    assert!(server.get_sessions().await.contains(&idle_id));
+
+    // The operator's key makes a session an admin
+    let client = client.with_auth(Some("secret".to_string()));
+    let status = client.restart_server().await.unwrap();
+    assert!(status.running);
+    assert_eq!(status.uptime.as_deref(), Some("0s"));
+
+    // Other sessions were told and are gone before the reply
+    let frame = codec::read_frame(&mut framed).await.unwrap();
+    let goodbye = Goodbye::from_payload(&compressor.decode(frame).unwrap()).unwrap();
+    assert_eq!(goodbye.reason, DisconnectReason::Shutdown);
+    assert!(!server.get_sessions().await.contains(&idle_id));
+
+    // The same process keeps serving
+    client.ping().await.unwrap();
+    assert!(client.get_status().await.unwrap().running);
+}
+
+#[tokio::test]
+async fn test_restart_fails_when_not_serving() {
+    let server = Server::new(ServerConfig::default());
+    assert!(server.restart(None).await.is_err());
+}
+
+/// Serve a `test/slow` method taking `delay`, returning the server and a client
+async fn spawn_slow_method_server(delay: Duration) -> (Server, Client) {
+    let server = Server::builder()
+        .config(open_config())
+        .rpc_method("test/slow", move |params, _| async move {
+            tokio::time::sleep(delay).await;
+            Ok(params)
+        })
+        .build();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(server.clone().serve(listener));
+    (server, Client::new(addr.ip().to_string(), addr.port(), 15))
+}
+
+#[tokio::test]
+async fn test_stop_lets_in_flight_request_finish_within_grace() {
+    let (server, client) = spawn_slow_method_server(Duration::from_millis(500)).await;
+    let call = tokio::spawn({
+        let client = client.clone();
+        async move {
+            client
+                .call_raw("test/slow", serde_json::json!({ "n": 1 }))
+                .await
+        }
+    });
+    while server.get_sessions().await.is_empty() {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    let started = Instant::now();
+    server.stop_within(Duration::from_secs(5)).await.unwrap();
+    assert!(started.elapsed() >= Duration::from_millis(300));
+    assert!(started.elapsed() < Duration::from_secs(5));
+
+    // The request was answered in full, and the session is gone
+    assert_eq!(call.await.unwrap().unwrap()["n"], 1);
+    assert!(server.get_sessions().await.is_empty());
+    assert!(!server.is_running().await);
+
+    // No new connections are taken once stopping has begun
+    assert!(matches!(
+        client.ping().await.unwrap_err(),
+        ClientError::ConnectionRefused(_) | ClientError::Connection(_)
+    ));
+}
+
+#[tokio::test]
+async fn test_stop_disconnects_sessions_outlasting_grace() {
+    let (server, client) = spawn_slow_method_server(Duration::from_secs(30)).await;
+    let call =
+        tokio::spawn(async move { client.call_raw("test/slow", serde_json::Value::Null).await });
+    while server.get_sessions().await.is_empty() {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    let started = Instant::now();
+    server
+        .stop_within(Duration::from_millis(200))
+        .await
+        .unwrap();
+    assert!(started.elapsed() < Duration::from_secs(5));
+
+    let err = tokio::time::timeout(Duration::from_secs(5), call)
+        .await
+        .expect("the session should be closed")
+        .unwrap()
+        .unwrap_err();
+    assert!(matches!(err, ClientError::Frame(_)), "{:?}", err);
+}
+
+#[test]
+fn test_shutdown_grace_is_configurable() {
+    assert_eq!(ServerConfig::default().shutdown_grace_secs, 10);
+    let config: ServerConfig = toml::from_str("shutdown_grace_secs = 0").unwrap();
+    assert_eq!(config.shutdown_grace_secs, 0);
+}
+
+#[tokio::test]
+async fn test_observer_can_list_sessions_but_not_kick() {
+    // A monitoring user whose provider grants only the observer permission
+    let provider = MockAuthProvider::new()
+        .with_user(User {
+            id: Uuid::new_v4(),
+            username: "grafana".to_string(),
+            full_name: None,
+            email: None,
+            password_hash: String::new(),
+            role: UserRole::User,
+            created_at: String::new(),
+            updated_at: String::new(),
+        })
+        .with_permission("grafana", OBSERVER_PERMISSION);
+    let mut manager = AuthManager::new(AuthConfig {
+        provider: AuthProviderType::Mock,
+        ..Default::default()
+    })
+    .await
+    .unwrap();
+    manager.provider = Arc::new(tokio::sync::RwLock::new(Box::new(provider)));
+    manager.initialize().await.unwrap();
+    let user = manager
+        .get_user_by_username("grafana")
+        .await
+        .unwrap()
+        .unwrap();
+    let permissions = manager.get_permissions(&user).await.unwrap();
+
+    let server = Server::new(psk_config(AuthFailureBehavior::RejectWithReason));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(server.clone().serve(listener));
+    let _idle = connect_operator(addr).await;
+
+    let token = server
+        .tokens()
+        .issue("grafana", permissions, Duration::from_secs(60));
+    let observer = Client::new(addr.ip().to_string(), addr.port(), 5).with_auth(Some(token));
+
+    // Everything that only reads is allowed
+    let sessions = observer.list_sessions().await.unwrap();
+    assert!(sessions.len() >= 2);
+    assert!(observer.get_metrics().await.unwrap().connections_accepted >= 2);
+    observer.get_running_config().await.unwrap();
+    observer.recent_events(10, &[]).await.unwrap();
+    assert!(observer.get_status().await.unwrap().running);
+
+    // Anything that changes state is refused, naming the admin permission
+    let victim = sessions[0].id.clone();
+    match observer.kick_session(&victim, None).await.unwrap_err() {
+        ClientError::PermissionDenied(permission) => {
+            assert_eq!(permission.required, MANAGE_SESSIONS_PERMISSION)
+        }
+        other => panic!("unexpected error: {:?}", other),
+    }
+    assert!(matches!(
+        observer.set_log_level("info").await,
+        Err(ClientError::PermissionDenied(_))
+    ));
+    assert!(matches!(
+        observer.update_server_config("session.timeout", "90").await,
+        Err(ClientError::PermissionDenied(_))
+    ));
+    assert!(server
+        .session_summaries()
+        .iter()
+        .any(|summary| summary.id.to_string() == victim));
+}
+
+#[tokio::test]
+async fn test_list_sessions_reports_session_info() {
+    let server = Server::new(psk_config(AuthFailureBehavior::RejectWithReason));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(server.clone().serve(listener));
+
+    let _idle = connect_operator(addr).await;
+    let idle_id = server.get_sessions().await[0];
+
+    let client =
+        |token: String| Client::new(addr.ip().to_string(), addr.port(), 5).with_auth(Some(token));
+    let ttl = Duration::from_secs(60);
+    let user = server
+        .tokens()
+        .issue("alice", vec!["app:*".to_string()], ttl);
+    let admin = server
+        .tokens()
+        .issue("ops", vec![MANAGE_SESSIONS_PERMISSION.to_string()], ttl);
+
+    let err = client(user).list_sessions().await.unwrap_err();
+    assert!(matches!(err, ClientError::PermissionDenied(_)));
+
+    // Every entry has exactly the fields of the client's SessionInfo
+    let raw = client(admin.clone())
+        .call_raw("sessions/list", serde_json::Value::Null)
+        .await
+        .unwrap();
+    let entries = raw.as_array().unwrap();
+    assert!(entries.len() >= 2);
+    for entry in entries {
+        let mut keys: Vec<&str> = entry
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(|k| k.as_str())
+            .collect();
+        keys.sort();
+        assert_eq!(
+            keys,
+            vec![
+                "active",
+                "client_ip",
+                "client_port",
+                "created_at",
+                "expires_at",
+                "id",
+                "last_active",
+                "user_id",
+                "username"
+            ]
+        );
+    }
+
+    let sessions = client(admin.clone()).list_sessions().await.unwrap();
+    assert!(sessions.iter().any(|s| s.id == idle_id.to_string()));
+    let own = sessions.iter().find(|s| s.username == "ops").unwrap();
+    let jti = server.tokens().verify_token(&admin).unwrap().jti;
+    assert_eq!(own.user_id, jti.to_string());
+    assert!(own.active);
+    assert!(!own.expires_at.is_empty());
+    assert_eq!(own.client_ip, addr.ip());
+}
+
+#[tokio::test]
+async fn test_stalled_handshakes_do_not_crowd_out_clients() {
+    let mut config = open_config();
+    config.session.max_concurrent_handshakes = 4;
+    config.session.handshake_slot_wait_ms = 100;
+    config.session.handshake_timeout_ms = 1000;
+    let server = Server::new(config);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(server.clone().serve(listener));
+
+    // A session established before the flood holds no handshake slot
+    let (mut established, compressor) = connect_idle_client(addr).await;
+
+    // Connections that never start the handshake: four get a slot, the rest
+    // are closed once the slot wait runs out
+    let mut stalled = Vec::new();
+    for _ in 0..16 {
+        stalled.push(TcpStream::connect(addr).await.unwrap());
+    }
+    tokio::time::sleep(Duration::from_millis(400)).await;
+
+    let mut closed = 0;
+    for stream in &mut stalled {
+        let mut buf = [0u8; 1];
+        if let Ok(Ok(0)) =
+            tokio::time::timeout(Duration::from_millis(20), stream.read(&mut buf)).await
+        {
+            closed += 1;
+        }
+    }
+    assert_eq!(closed, 12);
+    assert_eq!(server.handshake_limiter().in_progress(), 4);
+    assert_eq!(
+        server
+            .events()
+            .recent(100, &[ServerEventType::Rejected])
+            .len(),
+        12
+    );
+
+    // The established session is still served during the flood
+    let request = br#"{"jsonrpc":"2.0","id":1,"method":"ping"}"#;
+    codec::write_frame(
+        &mut established,
+        compressor
+            .encode(bytes::Bytes::from_static(request))
+            .unwrap(),
+    )
+    .await
+    .unwrap();
+    let frame = codec::read_frame(&mut established).await.unwrap();
+    let response: serde_json::Value =
+        serde_json::from_slice(&compressor.decode(frame).unwrap()).unwrap();
+    assert_eq!(response["result"], "pong");
+
+    // Once the stalled handshakes time out, new clients get through
+    tokio::time::sleep(Duration::from_millis(800)).await;
+    assert_eq!(server.handshake_limiter().in_progress(), 0);
+    let client = Client::new(addr.ip().to_string(), addr.port(), 5);
+    client.ping().await.unwrap();
+}
+
+#[test]
+fn test_client_ip_unmaps_ipv4_on_dual_stack() {
+    let mapped: std::net::SocketAddr = "[::ffff:10.0.0.5]:50123".parse().unwrap();
+    assert_eq!(session::client_ip(&mapped).to_string(), "10.0.0.5");
+
+    let v6: std::net::SocketAddr = "[2001:db8::5]:50123".parse().unwrap();
+    assert_eq!(session::client_ip(&v6).to_string(), "2001:db8::5");
+}
+
+#[tokio::test]
+async fn test_ipv6_client_events_report_bare_ip() {
+    // Skip on hosts without an IPv6 loopback
+    let Ok(listener) = TcpListener::bind("[::1]:0").await else {
+        return;
+    };
+    let addr = listener.local_addr().unwrap();
+    let server = Server::new(open_config());
+    tokio::spawn(server.clone().serve(listener));
+
+    Client::new("::1".to_string(), addr.port(), 5)
+        .ping()
+        .await
+        .unwrap();
+
+    let connects = server.events().recent(10, &[ServerEventType::Connected]);
+    assert_eq!(connects[0].client_ip.as_deref(), Some("::1"));
+}
+
+#[tokio::test]
+async fn test_slow_frame_is_cut_off() {
+    let mut config = open_config();
+    config.session.max_frame_read_secs = 1;
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(Server::new(config).serve(listener));
+
+    // Announce a 100-byte frame, then dribble it out a byte at a time
+    let (framed, _compressor) = connect_idle_client(addr).await;
+    let mut stream = framed.into_inner();
+    stream.write_all(&100u32.to_be_bytes()).await.unwrap();
+    let started = Instant::now();
+
+    let mut buf = [0u8; 64];
+    let mut closed = false;
+    for _ in 0..25 {
+        if stream.write_all(b"x").await.is_err() {
+            closed = true;
+            break;
+        }
+        match tokio::time::timeout(Duration::from_millis(200), stream.read(&mut buf)).await {
+            Ok(Ok(0)) | Ok(Err(_)) => {
+                closed = true;
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    assert!(closed, "slow frame should close the session");
+    let elapsed = started.elapsed();
+    assert!(
+        elapsed >= Duration::from_millis(900),
+        "closed after {:?}",
+        elapsed
+    );
+    assert!(
+        elapsed < Duration::from_secs(5),
+        "closed after {:?}",
+        elapsed
+    );
+}
+
+#[tokio::test]
+async fn test_frame_timeout_does_not_apply_between_frames() {
+    let mut config = open_config();
+    config.session.max_frame_read_secs = 1;
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(Server::new(config).serve(listener));
+
+    // Waiting longer than the frame limit between requests is only idling
+    let (mut framed, compressor) = connect_idle_client(addr).await;
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+
+    let request = br#"{"jsonrpc":"2.0","id":1,"method":"ping"}"#;
+    codec::write_frame(
+        &mut framed,
+        compressor
+            .encode(bytes::Bytes::from_static(request))
+            .unwrap(),
+    )
+    .await
+    .unwrap();
+    let frame = codec::read_frame(&mut framed).await.unwrap();
+    let response: serde_json::Value =
+        serde_json::from_slice(&compressor.decode(frame).unwrap()).unwrap();
+    assert_eq!(response["result"], "pong");
+}
+
+#[test]
+fn test_frame_read_limit_from_toml() {
+    let config: ServerConfig = toml::from_str("").unwrap();
+    assert_eq!(config.session.max_frame_read_secs, 30);
+
+    let config: ServerConfig = toml::from_str(
+        r#"
+        [session]
+        max_frame_read_secs = 0
+        "#,
+    )
+    .unwrap();
+    assert_eq!(config.session.max_frame_read_secs, 0);
+}
+
+fn user(username: &str, role: UserRole) -> User {
+    User {
+        id: Uuid::new_v4(),
+        username: username.to_string(),
+        full_name: Some(format!("{} example", username)),
+        email: None,
+        password_hash: format!("$argon2id$v=19${}", username),
+        role,
+        created_at: "2024-01-01T00:00:00+00:00".to_string(),
+        updated_at: "2024-01-02T00:00:00+00:00".to_string(),
+    }
+}
+
+#[tokio::test]
+async fn test_users_round_trip_through_export_and_import() {
+    let source = Arc::new(UserManager::new());
+    for user in [
+        user("alice", UserRole::Admin),
+        user("bob", UserRole::User),
+        user("carol", UserRole::Guest),
+    ] {
+        source.add_user(user).await.unwrap();
+    }
+    let server = Server::builder().user_manager(source.clone()).build();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(server.clone().serve(listener));
+
+    let client =
+        |token: String| Client::new(addr.ip().to_string(), addr.port(), 5).with_auth(Some(token));
+    let ttl = Duration::from_secs(60);
+    let user_token = server.tokens().issue("bob", vec!["app:*".to_string()], ttl);
+    let admin = server
+        .tokens()
+        .issue("ops", vec![MANAGE_USERS_PERMISSION.to_string()], ttl);
+
+    let err = client(user_token).export_users().await.unwrap_err();
+    assert!(matches!(err, ClientError::PermissionDenied(_)));
+
+    // Hashes and roles survive the trip through the file format
+    let export = client(admin).export_users().await.unwrap();
+    assert_eq!(export.version, USER_EXPORT_VERSION);
+    let names: Vec<&str> = export.users.iter().map(|u| u.username.as_str()).collect();
+    assert_eq!(names, vec!["alice", "bob", "carol"]);
+    let file: UserExport = serde_json::from_str(&serde_json::to_string(&export).unwrap()).unwrap();
+
+    let target = UserManager::new();
+    let report = target.import(file, ImportMode::Merge).await.unwrap();
+    assert_eq!(report.added, vec!["alice", "bob", "carol"]);
+    for original in source.list_users().await {
+        let restored = target.get_user(&original.id).await.unwrap();
+        assert_eq!(restored.username, original.username);
+        assert_eq!(restored.password_hash, original.password_hash);
+        assert_eq!(restored.role, original.role);
+        assert_eq!(restored.created_at, original.created_at);
+    }
+}
+
+#[tokio::test]
+async fn test_user_update_changes_each_field_alone() {
+    let users = Arc::new(UserManager::new());
+    let alice = user("alice", UserRole::User);
+    users.add_user(alice.clone()).await.unwrap();
+    let server = Server::builder().user_manager(users.clone()).build();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(server.clone().serve(listener));
+
+    let client =
+        |token: String| Client::new(addr.ip().to_string(), addr.port(), 5).with_auth(Some(token));
+    let ttl = Duration::from_secs(60);
+    let admin = client(server.tokens().issue(
+        "ops",
+        vec![MANAGE_USERS_PERMISSION.to_string()],
+        ttl,
+    ));
+
+    let updated = admin
+        .update_user(
+            "alice",
+            &UserUpdate {
+                full_name: Some("Alice Liddell".to_string()),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(updated.full_name.as_deref(), Some("Alice Liddell"));
+    assert_eq!(updated.email, None);
+    assert_eq!(updated.role, UserRole::User);
+
+    let updated = admin
+        .update_user(
+            "alice",
+            &UserUpdate {
+                email: Some("alice@example.com".to_string()),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(updated.email.as_deref(), Some("alice@example.com"));
+    assert_eq!(updated.full_name.as_deref(), Some("Alice Liddell"));
+
+    let updated = admin
+        .update_user(
+            "alice",
+            &UserUpdate {
+                role: Some(UserRole::Guest),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(updated.role, UserRole::Guest);
+    assert_eq!(updated.email.as_deref(), Some("alice@example.com"));
+
+    // An empty value clears the field
+    admin
+        .update_user(
+            "alice",
+            &UserUpdate {
+                email: Some(String::new()),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+    // The manager holds the update, and the password hash is untouched
+    let stored = users.get_user(&alice.id).await.unwrap();
+    assert_eq!(stored.full_name.as_deref(), Some("Alice Liddell"));
+    assert_eq!(stored.email, None);
+    assert_eq!(stored.role, UserRole::Guest);
+    assert_eq!(stored.password_hash, alice.password_hash);
+    assert_ne!(stored.updated_at, alice.updated_at);
+
+    let raw = admin
+        .call_raw(
+            "users/update",
+            serde_json::json!({ "username": "alice", "role": "owner" }),
+        )
+        .await
+        .unwrap_err();
+    match raw {
+        ClientError::Rpc { code, message } => {
+            assert_eq!(code, rpc::INVALID_PARAMS);
+            assert!(message.contains("Invalid user role: owner"), "{}", message);
+        }
+        other => panic!("expected an invalid role error, got {:?}", other),
+    }
+    assert!(admin
+        .update_user(
+            "nobody",
+            &UserUpdate {
+                role: Some(UserRole::Admin),
+                ..Default::default()
+            }
+        )
+        .await
+        .is_err());
+
+    let user_token = server.tokens().issue("bob", vec!["app:*".to_string()], ttl);
+    let err = client(user_token)
+        .update_user(
+            "alice",
+            &UserUpdate {
+                role: Some(UserRole::Admin),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap_err();
+    assert!(matches!(err, ClientError::PermissionDenied(_)));
+}
+
+#[tokio::test]
+async fn test_user_import_merges_or_replaces_duplicates() {
+    let manager = UserManager::new();
+    let existing = user("alice", UserRole::User);
+    manager.add_user(existing.clone()).await.unwrap();
+
+    let mut incoming = user("alice", UserRole::Admin);
+    incoming.password_hash = "new-hash".to_string();
+    let export = UserExport {
+        version: USER_EXPORT_VERSION,
+        exported_at: String::new(),
+        users: vec![incoming.clone().into(), user("dave", UserRole::User).into()],
+    };
+
+    // Merging keeps the account already there
+    let report = manager
+        .import(export.clone(), ImportMode::Merge)
+        .await
+        .unwrap();
+    assert_eq!(report.added, vec!["dave"]);
+    assert_eq!(report.skipped, vec!["alice"]);
+    let alice = manager.get_user_by_username("alice").await.unwrap();
+    assert_eq!(alice.id, existing.id);
+    assert_eq!(alice.role, UserRole::User);
+
+    let report = manager.import(export, ImportMode::Replace).await.unwrap();
+    assert_eq!(report.replaced, vec!["alice", "dave"]);
+    let alice = manager.get_user_by_username("alice").await.unwrap();
+    assert_eq!(alice.id, incoming.id);
+    assert_eq!(alice.password_hash, "new-hash");
+    assert_eq!(manager.list_users().await.len(), 2);
+
+    // An export naming a user twice, or of another version, imports nothing
+    let twice = UserExport {
+        version: USER_EXPORT_VERSION,
+        exported_at: String::new(),
+        users: vec![
+            user("erin", UserRole::User).into(),
+            user("erin", UserRole::Guest).into(),
+        ],
+    };
+    assert!(matches!(
+        manager.import(twice, ImportMode::Replace).await,
+        Err(Error::InvalidArgument(_))
+    ));
+    let future = UserExport {
+        version: USER_EXPORT_VERSION + 1,
+        exported_at: String::new(),
+        users: vec![user("frank", UserRole::User).into()],
+    };
+    assert!(matches!(
+        manager.import(future, ImportMode::Merge).await,
+        Err(Error::InvalidArgument(_))
+    ));
+    assert_eq!(manager.list_users().await.len(), 2);
+}
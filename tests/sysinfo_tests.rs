@@ -0,0 +1,121 @@
+//! Host introspection tests
+
+use rcpdaemon::sysinfo;
+use std::time::Duration;
+
+#[test]
+fn test_hostname_is_never_empty() {
+    let hostname = sysinfo::hostname();
+    assert!(!hostname.is_empty());
+    assert_eq!(hostname, hostname.trim());
+    assert_ne!(hostname, "example-host.local");
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[test]
+fn test_parse_proc_uptime() {
+    assert_eq!(
+        sysinfo::parse_proc_uptime("12345.67 54321.00\n"),
+        Some(Duration::from_secs_f64(12345.67))
+    );
+    assert_eq!(sysinfo::parse_proc_uptime(""), None);
+    assert_eq!(sysinfo::parse_proc_uptime("not-a-number 1.0"), None);
+    assert_eq!(sysinfo::parse_proc_uptime("-5.0 1.0"), None);
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[test]
+fn test_parse_proc_meminfo() {
+    let meminfo = "MemTotal:        2048 kB\nMemFree:          512 kB\n\
+                   MemAvailable:    1024 kB\nSwapTotal:        100 kB\nSwapFree:          40 kB\n";
+    assert_eq!(
+        sysinfo::parse_proc_meminfo(meminfo),
+        Some(sysinfo::MemoryInfo {
+            total: 2048 * 1024,
+            available: 1024 * 1024,
+            swap_total: 100 * 1024,
+            swap_free: 40 * 1024,
+        })
+    );
+
+    // Older kernels have no MemAvailable
+    let old = "MemTotal: 2048 kB\nMemFree: 512 kB\n";
+    let memory = sysinfo::parse_proc_meminfo(old).unwrap();
+    assert_eq!(memory.available, 512 * 1024);
+    assert_eq!(memory.swap_total, 0);
+
+    assert_eq!(sysinfo::parse_proc_meminfo("MemFree: 512 kB\n"), None);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_memory_is_reported() {
+    let memory = sysinfo::memory().expect("/proc/meminfo should be readable");
+    assert!(memory.total > 0);
+    assert!(memory.available <= memory.total);
+    assert!(memory.swap_free <= memory.swap_total);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_unix_disk_space_is_reported() {
+    let disk = sysinfo::disk_space(std::path::Path::new("/")).expect("statvfs should answer");
+    assert!(disk.total > 0);
+    assert!(disk.available <= disk.total);
+    assert_eq!(
+        sysinfo::disk_space(std::path::Path::new("/no/such/dir")),
+        None
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_unix_interfaces_include_loopback() {
+    let addresses = sysinfo::interface_addresses().expect("getifaddrs should answer");
+    let loopback = addresses
+        .iter()
+        .find(|address| address.address == std::net::IpAddr::from([127, 0, 0, 1]))
+        .expect("loopback should have an address");
+    assert!(!loopback.interface.is_empty());
+    assert_eq!(loopback.prefix_len, 8);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_unix_kernel_version_matches_uname() {
+    let version = sysinfo::kernel_version().expect("uname should report a release");
+
+    if let Ok(output) = std::process::Command::new("uname").arg("-r").output() {
+        assert_eq!(version, String::from_utf8_lossy(&output.stdout).trim());
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_unix_hostname_matches_hostname_command() {
+    if let Ok(output) = std::process::Command::new("hostname").output() {
+        if output.status.success() {
+            assert_eq!(
+                sysinfo::hostname(),
+                String::from_utf8_lossy(&output.stdout).trim()
+            );
+        }
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos", windows))]
+#[test]
+fn test_boot_uptime_is_reported() {
+    let first = sysinfo::boot_uptime().expect("boot uptime should be available");
+    assert!(first > Duration::ZERO);
+
+    let second = sysinfo::boot_uptime().unwrap();
+    assert!(second + Duration::from_secs(1) >= first);
+}
+
+#[cfg(windows)]
+#[test]
+fn test_windows_kernel_version_looks_like_a_version() {
+    let version = sysinfo::kernel_version().expect("ver should report a version");
+    assert!(version.split('.').all(|part| part.parse::<u32>().is_ok()));
+}
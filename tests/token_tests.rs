@@ -0,0 +1,360 @@
+//! Auth token registry and revocation tests
+
+use rcpdaemon::client::{Client, ClientError};
+use rcpdaemon::server::config::{AuthConfig, ServerConfig};
+use rcpdaemon::server::error::Error;
+use rcpdaemon::server::rpc;
+use rcpdaemon::server::tokens::{
+    permission_granted, TokenError, TokenRegistry, MANAGE_TOKENS_PERMISSION, OBSERVER_PERMISSION,
+};
+use rcpdaemon::server::Server;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use uuid::Uuid;
+
+const HOUR: Duration = Duration::from_secs(3600);
+
+fn auth_config(revocation_file: Option<&PathBuf>) -> AuthConfig {
+    AuthConfig {
+        token_secret: Some("test-secret".to_string()),
+        revocation_file: revocation_file.map(|p| p.to_string_lossy().into_owned()),
+        ..Default::default()
+    }
+}
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("rcpdaemon-{}-{}.json", name, Uuid::new_v4()))
+}
+
+#[test]
+fn test_issued_token_verifies() {
+    let registry = TokenRegistry::new(&auth_config(None));
+    let token = registry.issue("alice", vec!["app:*".to_string()], HOUR);
+
+    let claims = registry.verify_token(&token).unwrap();
+    assert_eq!(claims.sub, "alice");
+    assert_eq!(claims.permissions, vec!["app:*"]);
+    assert_eq!(claims.exp - claims.iat, 3600);
+
+    let listed = registry.list();
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0].jti, claims.jti);
+    assert!(!listed[0].revoked);
+}
+
+#[test]
+fn test_invalid_tokens_are_rejected() {
+    let registry = TokenRegistry::new(&auth_config(None));
+    let token = registry.issue("alice", Vec::new(), HOUR);
+
+    assert_eq!(
+        registry.verify_token("not-a-token"),
+        Err(TokenError::Malformed)
+    );
+
+    // A token signed under another key does not verify
+    let other = TokenRegistry::new(&AuthConfig::default());
+    assert_eq!(other.verify_token(&token), Err(TokenError::BadSignature));
+
+    let expired = registry.issue("alice", Vec::new(), Duration::ZERO);
+    assert_eq!(registry.verify_token(&expired), Err(TokenError::Expired));
+}
+
+#[test]
+fn test_revoked_token_is_rejected() {
+    let registry = TokenRegistry::new(&auth_config(None));
+    let token = registry.issue("alice", Vec::new(), HOUR);
+    let jti = registry.verify_token(&token).unwrap().jti;
+
+    let revoked = registry.revoke(&jti).unwrap();
+    assert_eq!(revoked.subject, "alice");
+    assert!(revoked.revoked);
+    assert_eq!(registry.verify_token(&token), Err(TokenError::Revoked));
+    assert!(registry.list()[0].revoked);
+
+    assert!(matches!(
+        registry.revoke(&Uuid::new_v4()),
+        Err(Error::NotFound(_))
+    ));
+}
+
+#[test]
+fn test_revocations_survive_restart() {
+    let path = temp_path("revoked");
+    let registry = TokenRegistry::new(&auth_config(Some(&path)));
+    let token = registry.issue("alice", Vec::new(), HOUR);
+    let kept = registry.issue("bob", Vec::new(), HOUR);
+    let jti = registry.verify_token(&token).unwrap().jti;
+    registry.revoke(&jti).unwrap();
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode, rcpdaemon::files::PRIVATE_FILE_MODE);
+    }
+
+    // A new registry with the same key and file still rejects the revoked token
+    let restarted = TokenRegistry::new(&auth_config(Some(&path)));
+    assert!(restarted.is_revoked(&jti));
+    assert_eq!(restarted.verify_token(&token), Err(TokenError::Revoked));
+
+    // Tokens from before the restart are listed again once they are used
+    assert!(restarted.list().is_empty());
+    let bob = restarted.verify_token(&kept).unwrap();
+    assert_eq!(
+        restarted.list().iter().map(|t| t.jti).collect::<Vec<_>>(),
+        vec![bob.jti]
+    );
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_permission_wildcards() {
+    let granted = vec!["admin:*".to_string()];
+    assert!(permission_granted(&granted, MANAGE_TOKENS_PERMISSION));
+    assert!(!permission_granted(&granted, "administrator:tokens"));
+
+    let granted = vec![MANAGE_TOKENS_PERMISSION.to_string()];
+    assert!(permission_granted(&granted, MANAGE_TOKENS_PERMISSION));
+    assert!(!permission_granted(
+        &["app:*".to_string()],
+        MANAGE_TOKENS_PERMISSION
+    ));
+}
+
+#[test]
+fn test_observer_reads_but_does_not_administer() {
+    let observer = vec![OBSERVER_PERMISSION.to_string()];
+    assert!(permission_granted(&observer, "read:sessions"));
+    assert!(!permission_granted(&observer, "admin:sessions"));
+
+    // Admin permissions include reading the same area, and only that area
+    let admin = vec!["admin:sessions".to_string()];
+    assert!(permission_granted(&admin, "read:sessions"));
+    assert!(!permission_granted(&admin, "read:events"));
+    assert!(permission_granted(&["admin:*".to_string()], "read:events"));
+}
+
+#[test]
+fn test_token_settings_from_toml() {
+    let config: ServerConfig = toml::from_str(
+        r#"
+        [auth]
+        token_secret = "s3cret"
+        revocation_file = "/var/lib/rcpdaemon/revoked.json"
+        "#,
+    )
+    .unwrap();
+
+    assert_eq!(config.auth.token_secret.as_deref(), Some("s3cret"));
+    assert_eq!(
+        config.auth.revocation_file.as_deref(),
+        Some("/var/lib/rcpdaemon/revoked.json")
+    );
+    assert_eq!(
+        config.to_redacted_json()["auth"]["token_secret"],
+        "<redacted>"
+    );
+}
+
+#[tokio::test]
+async fn test_token_rpcs_require_admin() {
+    let mut config = ServerConfig::default();
+    config.auth.psk = Some("operator-key".to_string());
+    let server = Server::new(config);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(server.clone().serve(listener));
+
+    let client = |auth: &str| {
+        Client::new(addr.ip().to_string(), addr.port(), 5).with_auth(Some(auth.to_string()))
+    };
+    let user_token = server
+        .tokens()
+        .issue("alice", vec!["app:*".to_string()], HOUR);
+    let admin_token =
+        server
+            .tokens()
+            .issue("ops", vec![MANAGE_TOKENS_PERMISSION.to_string()], HOUR);
+
+    // A token without admin permission is told what it lacks
+    match client(&user_token).list_tokens().await.unwrap_err() {
+        ClientError::PermissionDenied(permission) => {
+            assert_eq!(permission.required, MANAGE_TOKENS_PERMISSION);
+            assert_eq!(permission.granted, vec!["app:*"]);
+        }
+        other => panic!("unexpected error: {:?}", other),
+    }
+
+    // The operator key and admin tokens may list and revoke
+    let tokens = client("operator-key").list_tokens().await.unwrap();
+    let mut subjects: Vec<&str> = tokens.iter().map(|t| t.subject.as_str()).collect();
+    subjects.sort();
+    assert_eq!(subjects, vec!["alice", "ops"]);
+
+    let alice = tokens
+        .iter()
+        .find(|t| t.subject == "alice")
+        .unwrap()
+        .jti
+        .to_string();
+    let revoked = client(&admin_token).revoke_token(&alice).await.unwrap();
+    assert!(revoked.revoked);
+
+    // The revoked token can no longer authenticate
+    assert!(client(&user_token).list_tokens().await.is_err());
+    assert!(client(&admin_token)
+        .revoke_token(&Uuid::new_v4().to_string())
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_admins_issue_tokens_over_rpc() {
+    let mut config = ServerConfig::default();
+    config.auth.psk = Some("operator-key".to_string());
+    let server = Server::new(config);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(server.clone().serve(listener));
+
+    let client = |auth: &str| {
+        Client::new(addr.ip().to_string(), addr.port(), 5).with_auth(Some(auth.to_string()))
+    };
+
+    // The operator hands out a token that then authenticates as its subject
+    let issued = client("operator-key")
+        .issue_token("monitor", vec![OBSERVER_PERMISSION.to_string()], HOUR)
+        .await
+        .unwrap();
+    assert_eq!(issued.info.subject, "monitor");
+    assert_eq!(issued.info.permissions, vec![OBSERVER_PERMISSION]);
+    assert_eq!(
+        (issued.info.expires_at - issued.info.issued_at).num_seconds(),
+        3600
+    );
+    let identity = client(&issued.token).whoami().await.unwrap();
+    assert_eq!(identity.user.as_deref(), Some("monitor"));
+    assert_eq!(identity.token_id, Some(issued.info.jti));
+    let listed = client("operator-key").list_tokens().await.unwrap();
+    assert!(listed.iter().any(|t| t.jti == issued.info.jti));
+
+    // Without admin:tokens a session may not issue any
+    match client(&issued.token)
+        .issue_token("other", Vec::new(), HOUR)
+        .await
+        .unwrap_err()
+    {
+        ClientError::PermissionDenied(permission) => {
+            assert_eq!(permission.required, MANAGE_TOKENS_PERMISSION)
+        }
+        other => panic!("unexpected error: {:?}", other),
+    }
+
+    // A token manager cannot grant more than it holds
+    let manager = server
+        .tokens()
+        .issue("ops", vec![MANAGE_TOKENS_PERMISSION.to_string()], HOUR);
+    match client(&manager)
+        .issue_token("root", vec!["admin:*".to_string()], HOUR)
+        .await
+        .unwrap_err()
+    {
+        ClientError::PermissionDenied(permission) => assert_eq!(permission.required, "admin:*"),
+        other => panic!("unexpected error: {:?}", other),
+    }
+    assert!(client(&manager)
+        .issue_token("ci", vec![MANAGE_TOKENS_PERMISSION.to_string()], HOUR)
+        .await
+        .is_ok());
+
+    // Subjects and lifetimes are checked
+    for (subject, ttl) in [("", HOUR), ("ci", Duration::ZERO)] {
+        match client("operator-key")
+            .issue_token(subject, Vec::new(), ttl)
+            .await
+            .unwrap_err()
+        {
+            ClientError::Rpc { code, .. } => assert_eq!(code, rpc::INVALID_PARAMS),
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_whoami_reports_own_token() {
+    let mut config = ServerConfig::default();
+    config.auth.psk = Some("operator-key".to_string());
+    let server = Server::new(config);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(server.clone().serve(listener));
+
+    let client = |auth: &str| {
+        Client::new(addr.ip().to_string(), addr.port(), 5).with_auth(Some(auth.to_string()))
+    };
+
+    // A valid token reports its own subject, permissions and expiry
+    let token = server
+        .tokens()
+        .issue("alice", vec!["app:*".to_string()], HOUR);
+    server
+        .tokens()
+        .issue("bob", vec!["admin:*".to_string()], HOUR);
+    let identity = client(&token).whoami().await.unwrap();
+    let claims = server.tokens().verify_token(&token).unwrap();
+    assert_eq!(identity.user.as_deref(), Some("alice"));
+    assert_eq!(identity.permissions, vec!["app:*"]);
+    assert_eq!(identity.token_id, Some(claims.jti));
+    assert_eq!(identity.expires_at.unwrap().timestamp(), claims.exp);
+    assert_eq!(identity.role, None);
+
+    // An expired token is refused with the reason
+    let expired = server.tokens().issue("alice", Vec::new(), Duration::ZERO);
+    match client(&expired).whoami().await.unwrap_err() {
+        ClientError::Rpc { code, message } => {
+            assert_eq!(code, rpc::UNAUTHENTICATED);
+            assert!(message.contains("expired"), "{}", message);
+        }
+        other => panic!("unexpected error: {:?}", other),
+    }
+
+    // The operator key has no token behind it
+    let identity = client("operator-key").whoami().await.unwrap();
+    assert_eq!(identity.user, None);
+    assert_eq!(identity.token_id, None);
+    assert_eq!(identity.expires_at, None);
+}
+
+#[tokio::test]
+async fn test_disallowed_auth_method_is_refused() {
+    let mut config = ServerConfig::default();
+    config.auth.psk = Some("operator-key".to_string());
+    config.auth.allowed_methods = vec!["token".to_string()];
+    let server = Server::new(config);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(server.clone().serve(listener));
+
+    let client = |auth: &str| {
+        Client::new(addr.ip().to_string(), addr.port(), 5).with_auth(Some(auth.to_string()))
+    };
+
+    // The correct key is refused once the method is left out
+    match client("operator-key").whoami().await.unwrap_err() {
+        ClientError::Rpc { code, message } => {
+            assert_eq!(code, rpc::UNAUTHENTICATED);
+            assert!(message.contains("'psk' is not allowed"), "{}", message);
+        }
+        other => panic!("unexpected error: {:?}", other),
+    }
+
+    let token = server
+        .tokens()
+        .issue("alice", vec!["app:*".to_string()], HOUR);
+    let identity = client(&token).whoami().await.unwrap();
+    assert_eq!(identity.user.as_deref(), Some("alice"));
+}
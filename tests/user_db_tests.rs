@@ -0,0 +1,185 @@
+//! passwd/group database snapshot tests
+
+use rcpdaemon::auth::user_db::{parse_group, parse_passwd, UserDatabase};
+use std::time::{Duration, Instant};
+
+const PASSWD: &str = "\
+# /etc/passwd
+root:x:0:0:root:/root:/bin/sh
+alice:x:1000:1000:Alice Example,,,:/home/alice:/bin/bash
+bob:x:1001:1001::/home/bob:/bin/sh
+broken-entry
+";
+
+const GROUP: &str = "\
+root:x:0:
+alice:x:1000:
+bob:x:1001:
+sudo:x:27:alice
+rcp-users:x:1500:alice,bob
+";
+
+/// Synthetic databases with `users` accounts spread across `groups` groups
+fn synthetic_databases(users: u32, groups: u32) -> (String, String) {
+    let mut passwd = String::new();
+    for i in 0..users {
+        passwd.push_str(&format!(
+            "user{i}:x:{uid}:{uid}:User {i},,,:/home/user{i}:/bin/sh\n",
+            uid = 1000 + i
+        ));
+    }
+
+    let mut group = String::new();
+    for i in 0..users {
+        group.push_str(&format!("user{i}:x:{}:\n", 1000 + i));
+    }
+    for g in 0..groups {
+        let members: Vec<String> = (0..users)
+            .filter(|i| i % groups == g)
+            .map(|i| format!("user{i}"))
+            .collect();
+        group.push_str(&format!("team{g}:x:{}:{}\n", 50000 + g, members.join(",")));
+    }
+
+    (passwd, group)
+}
+
+#[test]
+fn test_parse_passwd_skips_malformed_entries() {
+    let entries = parse_passwd(PASSWD);
+
+    assert_eq!(entries.len(), 3);
+    assert_eq!(entries[1].username, "alice");
+    assert_eq!(entries[1].uid, 1000);
+    assert_eq!(entries[1].display_name(), "Alice Example");
+    assert_eq!(entries[2].display_name(), "bob");
+}
+
+#[test]
+fn test_parse_group_members() {
+    let groups = parse_group(GROUP);
+
+    let rcp_users = groups.iter().find(|g| g.name == "rcp-users").unwrap();
+    assert_eq!(rcp_users.gid, 1500);
+    assert_eq!(rcp_users.members, vec!["alice", "bob"]);
+    assert!(groups
+        .iter()
+        .find(|g| g.name == "bob")
+        .unwrap()
+        .members
+        .is_empty());
+}
+
+#[test]
+fn test_database_resolves_primary_and_supplementary_groups() {
+    let database = UserDatabase::new(PASSWD, GROUP);
+
+    assert_eq!(database.groups("alice"), ["alice", "sudo", "rcp-users"]);
+    assert_eq!(database.groups("bob"), ["bob", "rcp-users"]);
+    assert!(database.groups("mallory").is_empty());
+
+    let regular: Vec<&str> = database
+        .regular_users(1000)
+        .map(|entry| entry.username.as_str())
+        .collect();
+    assert_eq!(regular, vec!["alice", "bob"]);
+}
+
+#[test]
+fn test_database_scales_to_many_entries() {
+    let (passwd, group) = synthetic_databases(20_000, 500);
+
+    let started = Instant::now();
+    let database = UserDatabase::new(&passwd, &group);
+    let elapsed = started.elapsed();
+
+    assert_eq!(database.regular_users(1000).count(), 20_000);
+    assert_eq!(database.groups("user0"), ["user0", "team0"]);
+    assert_eq!(database.groups("user12345"), ["user12345", "team345"]);
+
+    // A single pass over both databases; per-user subprocesses would take minutes
+    assert!(
+        elapsed < Duration::from_secs(5),
+        "building the snapshot took {:?}",
+        elapsed
+    );
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::{synthetic_databases, GROUP, PASSWD};
+    use rcpdaemon::auth::native_linux::{users_from_database, LinuxAuthConfig};
+    use rcpdaemon::server::user::UserRole;
+
+    #[test]
+    fn test_users_from_database_maps_roles() {
+        let users = users_from_database(&LinuxAuthConfig::default(), PASSWD, GROUP);
+
+        assert_eq!(users.len(), 2);
+        assert_eq!(users[0].username, "alice");
+        assert_eq!(users[0].full_name.as_deref(), Some("Alice Example"));
+        assert_eq!(users[0].role, UserRole::Admin);
+        assert_eq!(users[1].username, "bob");
+        assert_eq!(users[1].role, UserRole::User);
+    }
+
+    #[test]
+    fn test_users_from_database_many_entries() {
+        let (passwd, group) = synthetic_databases(5_000, 50);
+        let config = LinuxAuthConfig {
+            admin_groups: vec!["team0".to_string()],
+            ..Default::default()
+        };
+
+        let users = users_from_database(&config, &passwd, &group);
+
+        assert_eq!(users.len(), 5_000);
+        let admins = users.iter().filter(|u| u.role == UserRole::Admin).count();
+        assert_eq!(admins, 100);
+    }
+
+    #[test]
+    fn test_users_from_database_default_role() {
+        let config = LinuxAuthConfig {
+            default_role: UserRole::Guest,
+            ..Default::default()
+        };
+        let users = users_from_database(&config, PASSWD, GROUP);
+        assert_eq!(users[0].role, UserRole::Admin);
+        assert_eq!(users[1].role, UserRole::Guest);
+
+        let config = LinuxAuthConfig {
+            user_groups: vec!["rcp-users".to_string()],
+            ..config
+        };
+        let users = users_from_database(&config, PASSWD, GROUP);
+        assert_eq!(users[0].role, UserRole::Admin);
+        assert_eq!(users[1].role, UserRole::User);
+    }
+
+    #[test]
+    fn test_user_ids_are_derived_from_usernames() {
+        let first = users_from_database(&LinuxAuthConfig::default(), PASSWD, GROUP);
+        let second = users_from_database(&LinuxAuthConfig::default(), PASSWD, GROUP);
+
+        assert_eq!(first[0].id, second[0].id);
+        assert_ne!(first[0].id, first[1].id);
+        assert_eq!(
+            first[0].id,
+            uuid::Uuid::new_v5(&uuid::Uuid::NAMESPACE_DNS, b"alice")
+        );
+    }
+
+    #[test]
+    fn test_users_from_database_custom_uid_threshold() {
+        let config = LinuxAuthConfig {
+            min_user_uid: 1001,
+            ..Default::default()
+        };
+
+        let users = users_from_database(&config, PASSWD, GROUP);
+
+        assert_eq!(users.len(), 1);
+        assert_eq!(users[0].username, "bob");
+    }
+}